@@ -3,6 +3,7 @@ use tx2_pack::{
     format::{PackedSnapshot, ComponentArchetype, ComponentData, StructOfArraysData, FieldType, FieldArray},
     storage::{SnapshotWriter, SnapshotReader},
     compression::CompressionCodec,
+    ArchetypeBuilder,
 };
 
 #[cfg(feature = "encryption")]
@@ -11,44 +12,20 @@ use tx2_pack::encryption::EncryptionKey;
 fn create_test_snapshot(entity_count: usize, fields_per_entity: usize) -> PackedSnapshot {
     let mut snapshot = PackedSnapshot::new();
 
-    let mut archetype = ComponentArchetype {
-        component_id: "Position".to_string(),
-        entity_ids: Vec::with_capacity(entity_count),
-        data: ComponentData::StructOfArrays(StructOfArraysData {
-            field_names: vec![
-                "x".to_string(),
-                "y".to_string(),
-                "z".to_string(),
-            ],
-            field_types: vec![
-                FieldType::F32,
-                FieldType::F32,
-                FieldType::F32,
-            ],
-            field_data: vec![
-                FieldArray::F32(Vec::with_capacity(entity_count)),
-                FieldArray::F32(Vec::with_capacity(entity_count)),
-                FieldArray::F32(Vec::with_capacity(entity_count)),
-            ],
-        }),
-    };
-
-    for i in 0..entity_count {
-        let entity_id = i as u32;
-        archetype.entity_ids.push(entity_id);
-
-        if let ComponentData::StructOfArrays(ref mut soa) = archetype.data {
-            if let FieldArray::F32(ref mut x_values) = soa.field_data[0] {
-                x_values.push(i as f32 * 1.5);
-            }
-            if let FieldArray::F32(ref mut y_values) = soa.field_data[1] {
-                y_values.push(i as f32 * 2.5);
-            }
-            if let FieldArray::F32(ref mut z_values) = soa.field_data[2] {
-                z_values.push(i as f32 * 3.5);
-            }
-        }
-    }
+    let mut archetype = ArchetypeBuilder::with_capacity("Position", entity_count)
+        .with_field("x", FieldType::F32)
+        .with_field("y", FieldType::F32)
+        .with_field("z", FieldType::F32);
+
+    let entity_ids: Vec<u32> = (0..entity_count as u32).collect();
+    let xs: Vec<f32> = (0..entity_count).map(|i| i as f32 * 1.5).collect();
+    let ys: Vec<f32> = (0..entity_count).map(|i| i as f32 * 2.5).collect();
+    let zs: Vec<f32> = (0..entity_count).map(|i| i as f32 * 3.5).collect();
+    archetype.extend_entities(&entity_ids);
+    archetype.extend_column("x", &xs).unwrap();
+    archetype.extend_column("y", &ys).unwrap();
+    archetype.extend_column("z", &zs).unwrap();
+    let archetype = archetype.build().unwrap();
 
     snapshot.archetypes.push(archetype);
     snapshot.header.entity_count = entity_count as u64;
@@ -71,9 +48,9 @@ fn create_test_snapshot(entity_count: usize, fields_per_entity: usize) -> Packed
                     FieldType::F32,
                 ],
                 field_data: vec![
-                    FieldArray::F32(Vec::with_capacity(entity_count)),
-                    FieldArray::F32(Vec::with_capacity(entity_count)),
-                    FieldArray::F32(Vec::with_capacity(entity_count)),
+                    FieldArray::F32(Vec::with_capacity(entity_count).into()),
+                    FieldArray::F32(Vec::with_capacity(entity_count).into()),
+                    FieldArray::F32(Vec::with_capacity(entity_count).into()),
                 ],
             }),
         };
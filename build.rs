@@ -0,0 +1,17 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto/tx2pack.proto");
+
+    if env::var_os("CARGO_FEATURE_PROTOBUF").is_none() {
+        return;
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    let proto = out_dir.join("tx2pack.proto");
+    fs::copy("proto/tx2pack.proto", &proto).expect("failed to publish proto/tx2pack.proto");
+
+    println!("cargo:proto={}", proto.display());
+}
@@ -0,0 +1,316 @@
+use crate::capture::CaptureOptions;
+use crate::changeset::ChangeSet;
+use crate::error::Result;
+use crate::format::{ComponentArchetype, EntityMetadata, PackedSnapshot};
+use crate::layout::{NativeLayout, TableView};
+use crate::registry::ComponentRegistry;
+use crate::remap::EntityIdMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tx2_link::{ComponentId, EntityId};
+
+pub trait WorldCapture {
+    fn component_ids(&self) -> Result<Vec<ComponentId>>;
+
+    fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>>;
+
+    fn capture_entity_metadata(&self) -> Result<HashMap<EntityId, EntityMetadata>> {
+        Ok(HashMap::new())
+    }
+
+    // Singletons that replay depends on but no entity owns, such as RNG
+    // streams and schedule tick counters, encoded however the world likes.
+    fn capture_resources(&self) -> Result<BTreeMap<String, Vec<u8>>> {
+        Ok(BTreeMap::new())
+    }
+
+    // Worlds that store components in archetype tables can capture each
+    // component's rows table by table and describe the tables here, so
+    // restore can copy whole columns back instead of inserting entities.
+    fn capture_layout(&self) -> Result<Option<NativeLayout>> {
+        Ok(None)
+    }
+
+    fn capture(&self) -> Result<PackedSnapshot> {
+        self.capture_with(&CaptureOptions::default())
+    }
+
+    // Captures into a buffer left over from an earlier capture, which worlds
+    // can refill to reuse its column allocations. The default replaces it.
+    fn capture_archetype_into(&self, component_id: &ComponentId, buffer: &mut Option<ComponentArchetype>) -> Result<()> {
+        *buffer = self.capture_archetype(component_id)?;
+        Ok(())
+    }
+
+    fn capture_with(&self, options: &CaptureOptions) -> Result<PackedSnapshot> {
+        let mut snapshot = PackedSnapshot::new();
+
+        for component_id in self.component_ids()? {
+            if !options.component_matches(&component_id) {
+                continue;
+            }
+            if let Some(archetype) = self.capture_archetype(&component_id)? {
+                snapshot.archetypes.push(archetype);
+            }
+        }
+        snapshot.entity_metadata = self.capture_entity_metadata()?;
+        snapshot.resources = self.capture_resources()?;
+        finish_capture(self, &mut snapshot, options)?;
+
+        Ok(snapshot)
+    }
+}
+
+// Everything after the archetypes, metadata and resources have been taken.
+pub(crate) fn finish_capture<W: WorldCapture + ?Sized>(
+    world: &W,
+    snapshot: &mut PackedSnapshot,
+    options: &CaptureOptions,
+) -> Result<()> {
+    let entities: BTreeSet<EntityId> = snapshot
+        .archetypes
+        .iter()
+        .flat_map(|a| a.entity_ids.iter().copied())
+        .collect();
+    snapshot.header.entity_count = entities.len() as u64;
+    snapshot.header.component_count = snapshot.archetypes.len() as u64;
+    snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+    snapshot.rebuild_entity_index();
+    if options.is_unfiltered() {
+        if let Some(layout) = world.capture_layout()? {
+            layout.tables(snapshot)?;
+            snapshot.layout = Some(layout);
+        }
+    }
+    options.apply(snapshot);
+
+    Ok(())
+}
+
+// Bridges a world's change detection (tx2-link's, or the ECS's own) to
+// incremental capture. changes_since reports every component added, changed
+// or removed after the given tick; capture_rows should only touch the named
+// entities, and the default falls back to filtering a full capture.
+pub trait ChangeTracking: WorldCapture {
+    fn current_tick(&self) -> u64;
+
+    fn changes_since(&self, tick: u64) -> Result<ChangeSet>;
+
+    fn capture_rows(&self, component_id: &ComponentId, entity_ids: &BTreeSet<EntityId>) -> Result<Option<ComponentArchetype>> {
+        Ok(self.capture_archetype(component_id)?.map(|mut archetype| {
+            archetype.retain_entities(|id| entity_ids.contains(&id));
+            archetype
+        }))
+    }
+}
+
+pub trait WorldRestore {
+    // Picks the id a snapshot entity gets in this world. Worlds that restore
+    // into live state, where the snapshot's ids may already be taken, spawn a
+    // fresh entity here; the default keeps the id.
+    fn allocate_entity(&mut self, old_id: EntityId) -> Result<EntityId> {
+        Ok(old_id)
+    }
+
+    fn restore_archetype(&mut self, archetype: &ComponentArchetype) -> Result<()>;
+
+    fn restore_entity_metadata(&mut self, _entity_id: EntityId, _metadata: &EntityMetadata) -> Result<()> {
+        Ok(())
+    }
+
+    fn restore_resource(&mut self, _name: &str, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    // Offered every table of a snapshot captured in native layout; returning
+    // false restores it component by component instead.
+    fn restore_tables(&mut self, _tables: &[TableView<'_>]) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn restore(&mut self, snapshot: &PackedSnapshot) -> Result<()> {
+        let bulk = match snapshot.native_tables() {
+            Some(tables) => self.restore_tables(&tables)?,
+            None => false,
+        };
+        if !bulk {
+            for archetype in &snapshot.archetypes {
+                self.restore_archetype(archetype)?;
+            }
+        }
+
+        let mut entity_ids: Vec<&EntityId> = snapshot.entity_metadata.keys().collect();
+        entity_ids.sort();
+        for entity_id in entity_ids {
+            self.restore_entity_metadata(*entity_id, &snapshot.entity_metadata[entity_id])?;
+        }
+
+        for (name, data) in &snapshot.resources {
+            self.restore_resource(name, data)?;
+        }
+
+        Ok(())
+    }
+
+    // Allocates every entity through allocate_entity, rewrites the snapshot's
+    // ids and registered entity fields to match, restores it, and returns the
+    // map so callers can fix references held outside the world.
+    fn restore_remapped(&mut self, snapshot: &PackedSnapshot, registry: &ComponentRegistry) -> Result<EntityIdMap> {
+        let map = allocate_ids(snapshot, |old_id| self.allocate_entity(old_id))?;
+        let mut remapped = snapshot.clone();
+        remapped.remap_entities(&map, registry)?;
+        self.restore(&remapped)?;
+        Ok(map)
+    }
+}
+
+pub(crate) fn allocate_ids<F>(snapshot: &PackedSnapshot, mut allocate: F) -> Result<EntityIdMap>
+where
+    F: FnMut(EntityId) -> Result<EntityId>,
+{
+    let entity_ids: BTreeSet<EntityId> = snapshot
+        .archetypes
+        .iter()
+        .flat_map(|a| a.entity_ids.iter().copied())
+        .chain(snapshot.entity_metadata.keys().copied())
+        .collect();
+    entity_ids.into_iter().map(|old_id| Ok((old_id, allocate(old_id)?))).collect()
+}
+
+impl WorldCapture for PackedSnapshot {
+    fn component_ids(&self) -> Result<Vec<ComponentId>> {
+        Ok(self.archetypes.iter().map(|a| a.component_id.clone()).collect())
+    }
+
+    fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+        Ok(self.archetypes.iter().find(|a| a.component_id == *component_id).cloned())
+    }
+
+    fn capture_entity_metadata(&self) -> Result<HashMap<EntityId, EntityMetadata>> {
+        Ok(self.entity_metadata.clone())
+    }
+
+    fn capture_resources(&self) -> Result<BTreeMap<String, Vec<u8>>> {
+        Ok(self.resources.clone())
+    }
+
+    fn capture_layout(&self) -> Result<Option<NativeLayout>> {
+        Ok(self.layout.clone())
+    }
+
+    fn capture(&self) -> Result<PackedSnapshot> {
+        Ok(self.clone())
+    }
+}
+
+impl WorldRestore for PackedSnapshot {
+    fn restore_archetype(&mut self, archetype: &ComponentArchetype) -> Result<()> {
+        match self.archetypes.iter_mut().find(|a| a.component_id == archetype.component_id) {
+            Some(existing) => *existing = archetype.clone(),
+            None => self.archetypes.push(archetype.clone()),
+        }
+        self.rebuild_entity_index();
+        Ok(())
+    }
+
+    fn restore_entity_metadata(&mut self, entity_id: EntityId, metadata: &EntityMetadata) -> Result<()> {
+        self.entity_metadata.insert(entity_id, metadata.clone());
+        Ok(())
+    }
+
+    fn restore_resource(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.resources.insert(name.to_string(), data.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PackError;
+    use crate::format::{ComponentData, FieldArray, FieldType, FieldValue, StructOfArraysData};
+    use std::collections::BTreeMap;
+
+    #[derive(Default)]
+    struct ToyWorld {
+        positions: BTreeMap<EntityId, (f32, f32)>,
+        names: BTreeMap<EntityId, String>,
+    }
+
+    impl WorldCapture for ToyWorld {
+        fn component_ids(&self) -> Result<Vec<ComponentId>> {
+            Ok(vec!["Position".to_string(), "Name".to_string()])
+        }
+
+        fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+            let (entity_ids, soa) = match component_id.as_str() {
+                "Position" => (
+                    self.positions.keys().copied().collect(),
+                    StructOfArraysData {
+                        field_names: vec!["x".to_string(), "y".to_string()],
+                        field_types: vec![FieldType::F32, FieldType::F32],
+                        field_data: vec![
+                            FieldArray::F32(self.positions.values().map(|p| p.0).collect()),
+                            FieldArray::F32(self.positions.values().map(|p| p.1).collect()),
+                        ],
+                    },
+                ),
+                "Name" => (
+                    self.names.keys().copied().collect(),
+                    StructOfArraysData {
+                        field_names: vec!["value".to_string()],
+                        field_types: vec![FieldType::String],
+                        field_data: vec![FieldArray::String(self.names.values().cloned().collect())],
+                    },
+                ),
+                _ => return Ok(None),
+            };
+
+            Ok(Some(ComponentArchetype {
+                component_id: component_id.clone(),
+                entity_ids,
+                data: ComponentData::StructOfArrays(soa),
+            }))
+        }
+    }
+
+    impl WorldRestore for ToyWorld {
+        fn restore_archetype(&mut self, archetype: &ComponentArchetype) -> Result<()> {
+            for (row, entity_id) in archetype.entity_ids.iter().enumerate() {
+                let values = archetype.row_values(row).unwrap_or_default();
+                match (archetype.component_id.as_str(), values.as_slice()) {
+                    ("Position", [(_, FieldValue::F32(x)), (_, FieldValue::F32(y))]) => {
+                        self.positions.insert(*entity_id, (*x, *y));
+                    }
+                    ("Name", [(_, FieldValue::String(name))]) => {
+                        self.names.insert(*entity_id, name.clone());
+                    }
+                    (component, _) => {
+                        return Err(PackError::InvalidFormat(format!("Unexpected component {}", component)));
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_world_capture_restore_roundtrip() {
+        let mut world = ToyWorld::default();
+        world.positions.insert(1, (0.0, 1.0));
+        world.positions.insert(4, (2.5, -3.0));
+        world.names.insert(4, "scout".to_string());
+
+        let snapshot = world.capture().unwrap();
+        assert_eq!(snapshot.header.entity_count, 2);
+        assert_eq!(snapshot.locate(4).len(), 2);
+
+        let mut restored = ToyWorld::default();
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.positions, world.positions);
+        assert_eq!(restored.names, world.names);
+
+        let mut copy = PackedSnapshot::new();
+        copy.restore(&snapshot).unwrap();
+        assert_eq!(copy.capture().unwrap().content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::bounds;
+use crate::error::{PackError, Result};
+use crate::format::{CompressionType, PackFormat, PackedSnapshot, SnapshotHeader, MAGIC_NUMBER};
+use memmap2::Mmap;
+use rkyv::rancor;
+use rkyv::util::AlignedVec;
+use std::fs::File;
+use std::path::Path;
+
+pub use crate::format::{ArchivedComponentArchetype, ArchivedComponentData, ArchivedFieldArray, ArchivedPackedSnapshot};
+
+pub(crate) fn encode_snapshot(snapshot: &PackedSnapshot) -> Result<Vec<u8>> {
+    // Resources would change the archived layout zero-copy readers rely on.
+    if !snapshot.resources.is_empty() {
+        return Err(PackError::Serialization("Rkyv format does not support resources".to_string()));
+    }
+
+    rkyv::to_bytes::<rancor::Error>(snapshot)
+        .map(|bytes| bytes.to_vec())
+        .map_err(|e| PackError::Serialization(e.to_string()))
+}
+
+pub(crate) fn decode_snapshot(data: &[u8]) -> Result<PackedSnapshot> {
+    let mut aligned = AlignedVec::<16>::with_capacity(data.len());
+    aligned.extend_from_slice(data);
+
+    rkyv::from_bytes::<PackedSnapshot, rancor::Error>(&aligned).map_err(|e| PackError::Deserialization(e.to_string()))
+}
+
+pub fn access_snapshot(bytes: &[u8]) -> Result<&ArchivedPackedSnapshot> {
+    let (_, data) = archived_data(bytes)?;
+    rkyv::access::<ArchivedPackedSnapshot, rancor::Error>(data).map_err(|e| PackError::Deserialization(e.to_string()))
+}
+
+pub struct MappedSnapshot {
+    mmap: Mmap,
+    header: SnapshotHeader,
+}
+
+impl MappedSnapshot {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        let (header, _) = archived_data(&mmap)?;
+
+        Ok(Self { mmap, header })
+    }
+
+    pub fn header(&self) -> &SnapshotHeader {
+        &self.header
+    }
+
+    pub fn archived(&self) -> Result<&ArchivedPackedSnapshot> {
+        access_snapshot(&self.mmap)
+    }
+
+    pub fn to_snapshot(&self) -> Result<PackedSnapshot> {
+        rkyv::deserialize::<PackedSnapshot, rancor::Error>(self.archived()?)
+            .map_err(|e| PackError::Deserialization(e.to_string()))
+    }
+}
+
+fn archived_data(bytes: &[u8]) -> Result<(SnapshotHeader, &[u8])> {
+    let header = SnapshotHeader::decode(bytes)?;
+    header.validate_with_magic(MAGIC_NUMBER)?;
+
+    if header.format != PackFormat::Rkyv {
+        return Err(PackError::InvalidFormat(format!("Expected Rkyv format, found {:?}", header.format)));
+    }
+    if header.compression != CompressionType::None || header.encrypted {
+        return Err(PackError::InvalidFormat(
+            "Zero-copy access requires an uncompressed, unencrypted snapshot".to_string(),
+        ));
+    }
+
+    let data = bounds::slice(bytes, header.data_offset, header.data_size, "Data")?;
+
+    header.checksum_algorithm.verify(data, &header.checksum)?;
+
+    Ok((header, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionCodec;
+    use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, StructOfArraysData};
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rkyv_zero_copy_access() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Rkyv;
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: vec![1, 2, 3],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string()],
+                field_types: vec![FieldType::F32],
+                field_data: vec![FieldArray::F32(vec![1.0, 2.0, 3.0].into())],
+            }),
+        });
+        snapshot.rebuild_entity_index();
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("world.tx2pack");
+        SnapshotWriter::new()
+            .with_compression(CompressionCodec::none())
+            .write_to_file(&snapshot, &path)
+            .unwrap();
+
+        let mapped = MappedSnapshot::open(&path).unwrap();
+        let archived = mapped.archived().unwrap();
+        assert_eq!(archived.archetypes[0].component_id.as_str(), "Position");
+        match &archived.archetypes[0].data {
+            ArchivedComponentData::StructOfArrays(soa) => match &soa.field_data[0] {
+                ArchivedFieldArray::F32(values) => assert_eq!(values[2].to_native(), 3.0),
+                _ => panic!("expected f32 column"),
+            },
+            _ => panic!("expected columnar data"),
+        }
+        assert_eq!(mapped.to_snapshot().unwrap().content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+
+        let compressed = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+        assert!(access_snapshot(&compressed).is_err());
+        let loaded = SnapshotReader::new().read_from_bytes(&compressed).unwrap();
+        assert_eq!(loaded.header.format, PackFormat::Rkyv);
+        assert_eq!(loaded.archetypes[0].entity_ids, vec![1, 2, 3]);
+    }
+}
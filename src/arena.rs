@@ -0,0 +1,612 @@
+use crate::error::{PackError, Result};
+use crate::format::{
+    check_columns, ComponentArchetype, ComponentData, EntityLocation, EntityMetadata, FieldArray, FieldType, FieldValue,
+    PackFormat, PackedSnapshot, SnapshotHeader, StructOfArraysData, FLAG_DELTA_ENTITY_IDS, FORMAT_VERSION_MAJOR,
+};
+use crate::layout::NativeLayout;
+use crate::limits::ParseLimits;
+use crate::sectioned;
+use bincode::Options;
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+use serde::de::{self, DeserializeSeed, EnumAccess, SeqAccess, VariantAccess, Visitor};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::marker::PhantomData;
+use tx2_link::EntityId;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArenaColumn<'a> {
+    Bool(&'a [bool]),
+    I8(&'a [i8]),
+    I16(&'a [i16]),
+    I32(&'a [i32]),
+    I64(&'a [i64]),
+    U8(&'a [u8]),
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+    U64(&'a [u64]),
+    F32(&'a [f32]),
+    F64(&'a [f64]),
+    String(&'a [&'a str]),
+    Bytes(&'a [&'a [u8]]),
+}
+
+impl ArenaColumn<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            ArenaColumn::Bool(v) => v.len(),
+            ArenaColumn::I8(v) => v.len(),
+            ArenaColumn::I16(v) => v.len(),
+            ArenaColumn::I32(v) => v.len(),
+            ArenaColumn::I64(v) => v.len(),
+            ArenaColumn::U8(v) => v.len(),
+            ArenaColumn::U16(v) => v.len(),
+            ArenaColumn::U32(v) => v.len(),
+            ArenaColumn::U64(v) => v.len(),
+            ArenaColumn::F32(v) => v.len(),
+            ArenaColumn::F64(v) => v.len(),
+            ArenaColumn::String(v) => v.len(),
+            ArenaColumn::Bytes(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            ArenaColumn::Bool(_) => FieldType::Bool,
+            ArenaColumn::I8(_) => FieldType::I8,
+            ArenaColumn::I16(_) => FieldType::I16,
+            ArenaColumn::I32(_) => FieldType::I32,
+            ArenaColumn::I64(_) => FieldType::I64,
+            ArenaColumn::U8(_) => FieldType::U8,
+            ArenaColumn::U16(_) => FieldType::U16,
+            ArenaColumn::U32(_) => FieldType::U32,
+            ArenaColumn::U64(_) => FieldType::U64,
+            ArenaColumn::F32(_) => FieldType::F32,
+            ArenaColumn::F64(_) => FieldType::F64,
+            ArenaColumn::String(_) => FieldType::String,
+            ArenaColumn::Bytes(_) => FieldType::Bytes,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<FieldValue> {
+        match self {
+            ArenaColumn::Bool(v) => v.get(index).map(|x| FieldValue::Bool(*x)),
+            ArenaColumn::I8(v) => v.get(index).map(|x| FieldValue::I8(*x)),
+            ArenaColumn::I16(v) => v.get(index).map(|x| FieldValue::I16(*x)),
+            ArenaColumn::I32(v) => v.get(index).map(|x| FieldValue::I32(*x)),
+            ArenaColumn::I64(v) => v.get(index).map(|x| FieldValue::I64(*x)),
+            ArenaColumn::U8(v) => v.get(index).map(|x| FieldValue::U8(*x)),
+            ArenaColumn::U16(v) => v.get(index).map(|x| FieldValue::U16(*x)),
+            ArenaColumn::U32(v) => v.get(index).map(|x| FieldValue::U32(*x)),
+            ArenaColumn::U64(v) => v.get(index).map(|x| FieldValue::U64(*x)),
+            ArenaColumn::F32(v) => v.get(index).map(|x| FieldValue::F32(*x)),
+            ArenaColumn::F64(v) => v.get(index).map(|x| FieldValue::F64(*x)),
+            ArenaColumn::String(v) => v.get(index).map(|x| FieldValue::String(x.to_string())),
+            ArenaColumn::Bytes(v) => v.get(index).map(|x| FieldValue::Bytes(x.to_vec())),
+        }
+    }
+
+    pub fn to_field_array(&self) -> FieldArray {
+        match self {
+            ArenaColumn::Bool(v) => FieldArray::Bool(v.to_vec().into()),
+            ArenaColumn::I8(v) => FieldArray::I8(v.to_vec().into()),
+            ArenaColumn::I16(v) => FieldArray::I16(v.to_vec().into()),
+            ArenaColumn::I32(v) => FieldArray::I32(v.to_vec().into()),
+            ArenaColumn::I64(v) => FieldArray::I64(v.to_vec().into()),
+            ArenaColumn::U8(v) => FieldArray::U8(v.to_vec().into()),
+            ArenaColumn::U16(v) => FieldArray::U16(v.to_vec().into()),
+            ArenaColumn::U32(v) => FieldArray::U32(v.to_vec().into()),
+            ArenaColumn::U64(v) => FieldArray::U64(v.to_vec().into()),
+            ArenaColumn::F32(v) => FieldArray::F32(v.to_vec().into()),
+            ArenaColumn::F64(v) => FieldArray::F64(v.to_vec().into()),
+            ArenaColumn::String(v) => FieldArray::String(v.iter().map(|s| s.to_string()).collect()),
+            ArenaColumn::Bytes(v) => FieldArray::Bytes(v.iter().map(|b| b.to_vec()).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArenaComponentData<'a> {
+    StructOfArrays {
+        field_names: &'a [&'a str],
+        field_types: &'a [FieldType],
+        field_data: &'a [ArenaColumn<'a>],
+    },
+    Blob(&'a [u8]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArenaArchetype<'a> {
+    pub component_id: &'a str,
+    pub entity_ids: &'a [EntityId],
+    pub data: ArenaComponentData<'a>,
+}
+
+impl ArenaArchetype<'_> {
+    pub fn column(&self, name: &str) -> Option<&ArenaColumn<'_>> {
+        match &self.data {
+            ArenaComponentData::StructOfArrays { field_names, field_data, .. } => field_names
+                .iter()
+                .position(|field| *field == name)
+                .map(|index| &field_data[index]),
+            ArenaComponentData::Blob(_) => None,
+        }
+    }
+
+    pub fn check_consistency(&self) -> Result<()> {
+        match &self.data {
+            ArenaComponentData::StructOfArrays { field_names, field_types, field_data } => check_columns(
+                self.component_id,
+                self.entity_ids.len(),
+                field_names,
+                field_types,
+                field_data.iter().map(|column| (column.field_type(), column.len())),
+            ),
+            ArenaComponentData::Blob(_) => Ok(()),
+        }
+    }
+
+    pub fn to_archetype(&self) -> ComponentArchetype {
+        let data = match &self.data {
+            ArenaComponentData::StructOfArrays { field_names, field_types, field_data } => {
+                ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: field_names.iter().map(|name| name.to_string()).collect(),
+                    field_types: field_types.to_vec(),
+                    field_data: field_data.iter().map(ArenaColumn::to_field_array).collect(),
+                })
+            }
+            ArenaComponentData::Blob(payload) => ComponentData::Blob(payload.to_vec()),
+        };
+
+        ComponentArchetype {
+            component_id: self.component_id.to_string(),
+            entity_ids: self.entity_ids.to_vec(),
+            data,
+        }
+    }
+}
+
+pub struct ArenaSnapshot<'a> {
+    pub header: SnapshotHeader,
+    pub archetypes: &'a [ArenaArchetype<'a>],
+    pub entity_metadata: HashMap<EntityId, EntityMetadata>,
+    pub resources: BTreeMap<String, Vec<u8>>,
+    pub layout: Option<NativeLayout>,
+}
+
+impl<'a> ArenaSnapshot<'a> {
+    pub fn archetype(&self, component_id: &str) -> Option<&ArenaArchetype<'a>> {
+        self.archetypes.iter().find(|archetype| archetype.component_id == component_id)
+    }
+
+    pub fn to_snapshot(&self) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header = self.header.clone();
+        snapshot.archetypes = self.archetypes.iter().map(ArenaArchetype::to_archetype).collect();
+        snapshot.entity_metadata = self.entity_metadata.clone();
+        snapshot.resources = self.resources.clone();
+        snapshot.layout = self.layout.clone();
+        snapshot.rebuild_entity_index();
+        snapshot
+    }
+}
+
+pub(crate) fn decode_snapshot<'a, F>(
+    header: SnapshotHeader,
+    data: &[u8],
+    limits: &ParseLimits,
+    open: F,
+    arena: &'a Bump,
+) -> Result<ArenaSnapshot<'a>>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    if header.is_legacy() {
+        return Err(PackError::VersionMismatch {
+            expected: format!("{}.x", FORMAT_VERSION_MAJOR),
+            actual: format!("{}.{}", header.version_major(), header.version_minor()),
+        });
+    }
+
+    let snapshot = match header.format {
+        PackFormat::Custom => {
+            let toc = sectioned::read_toc(data, &open)?;
+            limits.check_toc(&toc)?;
+
+            let mut archetypes = BumpVec::with_capacity_in(toc.archetypes.len(), arena);
+            for entry in &toc.archetypes {
+                let raw = sectioned::open_chunked(sectioned::section_bytes(data, &entry.section)?, &open)?;
+                archetypes.push(deserialize_seed(ArchetypeSeed(arena, header.flags & FLAG_DELTA_ENTITY_IDS != 0), &raw)?);
+            }
+
+            ArenaSnapshot {
+                header,
+                archetypes: archetypes.into_bump_slice(),
+                entity_metadata: sectioned::decode_section(data, &toc.entity_metadata, &open)?,
+                resources: sectioned::decode_resources(data, &toc, &open)?,
+                layout: toc.layout.clone(),
+            }
+        }
+        PackFormat::Bincode => {
+            let seed = SnapshotSeed(arena, header.version_minor());
+            let (archetypes, entity_metadata, resources, layout) = deserialize_seed(seed, &open(data)?)?;
+            ArenaSnapshot {
+                header,
+                archetypes,
+                entity_metadata,
+                resources,
+                layout,
+            }
+        }
+        format => {
+            return Err(PackError::Deserialization(format!(
+                "Arena decoding supports Custom and Bincode snapshots, not {:?}", format
+            )));
+        }
+    };
+
+    check_limits(&snapshot, limits)?;
+    Ok(snapshot)
+}
+
+fn check_limits(snapshot: &ArenaSnapshot<'_>, limits: &ParseLimits) -> Result<()> {
+    limits.check_entity_metadata(&snapshot.entity_metadata)?;
+
+    let mut entities = 0u64;
+    for archetype in snapshot.archetypes {
+        archetype.check_consistency()?;
+        limits.check_string(archetype.component_id)?;
+        entities += archetype.entity_ids.len() as u64;
+
+        if let ArenaComponentData::StructOfArrays { field_names, field_data, .. } = &archetype.data {
+            for name in *field_names {
+                limits.check_string(name)?;
+            }
+            for column in *field_data {
+                if let ArenaColumn::String(values) = column {
+                    for value in *values {
+                        limits.check_string(value)?;
+                    }
+                }
+            }
+        }
+    }
+
+    limits.check_counts(snapshot.archetypes.len() as u64, entities)
+}
+
+fn deserialize_seed<'de, S: DeserializeSeed<'de>>(seed: S, bytes: &'de [u8]) -> Result<S::Value> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .deserialize_seed(seed, bytes)
+        .map_err(|e| PackError::Deserialization(e.to_string()))
+}
+
+struct Plain<T>(PhantomData<T>);
+
+impl<T> Clone for Plain<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Plain<T> {}
+
+impl<'de, T: Deserialize<'de>> DeserializeSeed<'de> for Plain<T> {
+    type Value = T;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<T, D::Error> {
+        T::deserialize(deserializer)
+    }
+}
+
+fn plain<T>() -> Plain<T> {
+    Plain(PhantomData)
+}
+
+#[derive(Clone, Copy)]
+struct SliceSeed<'a, S>(&'a Bump, S);
+
+impl<'de, 'a, S> DeserializeSeed<'de> for SliceSeed<'a, S>
+where
+    S: DeserializeSeed<'de> + Copy,
+    S::Value: 'a,
+{
+    type Value = &'a [S::Value];
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a, S> Visitor<'de> for SliceSeed<'a, S>
+where
+    S: DeserializeSeed<'de> + Copy,
+    S::Value: 'a,
+{
+    type Value = &'a [S::Value];
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut values = BumpVec::with_capacity_in(seq.size_hint().unwrap_or(0), self.0);
+        while let Some(value) = seq.next_element_seed(self.1)? {
+            values.push(value);
+        }
+        Ok(values.into_bump_slice())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct StrSeed<'a>(&'a Bump);
+
+impl<'de, 'a> DeserializeSeed<'de> for StrSeed<'a> {
+    type Value = &'a str;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<&'a str, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'a> Visitor<'_> for StrSeed<'a> {
+    type Value = &'a str;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> std::result::Result<&'a str, E> {
+        Ok(self.0.alloc_str(value))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ArchetypeSeed<'a>(&'a Bump, bool);
+
+impl<'de, 'a> DeserializeSeed<'de> for ArchetypeSeed<'a> {
+    type Value = ArenaArchetype<'a>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("ComponentArchetype", &["component_id", "entity_ids", "data"], self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for ArchetypeSeed<'a> {
+    type Value = ArenaArchetype<'a>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a component archetype")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        Ok(ArenaArchetype {
+            component_id: next(&mut seq, StrSeed(self.0), 0)?,
+            entity_ids: if self.1 {
+                let encoded = next(&mut seq, SliceSeed(self.0, plain::<u8>()), 1)?;
+                let ids = sectioned::decode_entity_ids(encoded).map_err(de::Error::custom)?;
+                self.0.alloc_slice_copy(&ids)
+            } else {
+                next(&mut seq, SliceSeed(self.0, plain::<EntityId>()), 1)?
+            },
+            data: next(&mut seq, ComponentDataSeed(self.0), 2)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ComponentDataSeed<'a>(&'a Bump);
+
+impl<'de, 'a> DeserializeSeed<'de> for ComponentDataSeed<'a> {
+    type Value = ArenaComponentData<'a>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_enum("ComponentData", &["StructOfArrays", "Blob"], self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for ComponentDataSeed<'a> {
+    type Value = ArenaComponentData<'a>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("component data")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> std::result::Result<Self::Value, A::Error> {
+        let (index, variant) = data.variant::<u32>()?;
+        match index {
+            0 => variant.newtype_variant_seed(SoaSeed(self.0)),
+            1 => variant
+                .newtype_variant_seed(SliceSeed(self.0, plain::<u8>()))
+                .map(ArenaComponentData::Blob),
+            _ => Err(de::Error::unknown_variant(&index.to_string(), &["StructOfArrays", "Blob"])),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SoaSeed<'a>(&'a Bump);
+
+impl<'de, 'a> DeserializeSeed<'de> for SoaSeed<'a> {
+    type Value = ArenaComponentData<'a>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("StructOfArraysData", &["field_names", "field_types", "field_data"], self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for SoaSeed<'a> {
+    type Value = ArenaComponentData<'a>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct-of-arrays data")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        Ok(ArenaComponentData::StructOfArrays {
+            field_names: next(&mut seq, SliceSeed(self.0, StrSeed(self.0)), 0)?,
+            field_types: next(&mut seq, SliceSeed(self.0, plain::<FieldType>()), 1)?,
+            field_data: next(&mut seq, SliceSeed(self.0, ColumnSeed(self.0)), 2)?,
+        })
+    }
+}
+
+const FIELD_ARRAY_VARIANTS: &[&str] = &[
+    "Bool", "I8", "I16", "I32", "I64", "U8", "U16", "U32", "U64", "F32", "F64", "String", "Bytes",
+];
+
+#[derive(Clone, Copy)]
+struct ColumnSeed<'a>(&'a Bump);
+
+impl<'de, 'a> DeserializeSeed<'de> for ColumnSeed<'a> {
+    type Value = ArenaColumn<'a>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_enum("FieldArray", FIELD_ARRAY_VARIANTS, self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for ColumnSeed<'a> {
+    type Value = ArenaColumn<'a>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a field array")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> std::result::Result<Self::Value, A::Error> {
+        let arena = self.0;
+        let (index, variant) = data.variant::<u32>()?;
+        match index {
+            0 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::Bool),
+            1 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::I8),
+            2 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::I16),
+            3 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::I32),
+            4 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::I64),
+            5 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::U8),
+            6 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::U16),
+            7 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::U32),
+            8 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::U64),
+            9 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::F32),
+            10 => variant.newtype_variant_seed(SliceSeed(arena, plain())).map(ArenaColumn::F64),
+            11 => variant
+                .newtype_variant_seed(SliceSeed(arena, StrSeed(arena)))
+                .map(ArenaColumn::String),
+            12 => variant
+                .newtype_variant_seed(SliceSeed(arena, SliceSeed(arena, plain::<u8>())))
+                .map(ArenaColumn::Bytes),
+            _ => Err(de::Error::unknown_variant(&index.to_string(), FIELD_ARRAY_VARIANTS)),
+        }
+    }
+}
+
+type SnapshotParts<'a> = (
+    &'a [ArenaArchetype<'a>],
+    HashMap<EntityId, EntityMetadata>,
+    BTreeMap<String, Vec<u8>>,
+    Option<NativeLayout>,
+);
+
+// Carries the payload's minor version: 2.1 added the entity index and
+// resources, 2.2 the layout.
+#[derive(Clone, Copy)]
+struct SnapshotSeed<'a>(&'a Bump, u16);
+
+impl<'de, 'a> DeserializeSeed<'de> for SnapshotSeed<'a> {
+    type Value = SnapshotParts<'a>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct(
+            "PackedSnapshot",
+            &["header", "archetypes", "entity_metadata", "entity_index", "resources", "layout"],
+            self,
+        )
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for SnapshotSeed<'a> {
+    type Value = SnapshotParts<'a>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a packed snapshot")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        next(&mut seq, plain::<SnapshotHeader>(), 0)?;
+        let archetypes = next(&mut seq, SliceSeed(self.0, ArchetypeSeed(self.0, false)), 1)?;
+        let entity_metadata = next(&mut seq, plain(), 2)?;
+        let mut resources = BTreeMap::new();
+        let mut layout = None;
+        if self.1 > 0 {
+            next(&mut seq, plain::<HashMap<EntityId, Vec<EntityLocation>>>(), 3)?;
+            resources = next(&mut seq, plain(), 4)?;
+        }
+        if self.1 > 1 {
+            layout = next(&mut seq, plain(), 5)?;
+        }
+        Ok((archetypes, entity_metadata, resources, layout))
+    }
+}
+
+fn next<'de, A, S>(seq: &mut A, seed: S, index: usize) -> std::result::Result<S::Value, A::Error>
+where
+    A: SeqAccess<'de>,
+    S: DeserializeSeed<'de>,
+{
+    seq.next_element_seed(seed)?
+        .ok_or_else(|| de::Error::invalid_length(index, &"more fields"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+
+    #[test]
+    fn test_arena_decode_matches_owned() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Tag".to_string(),
+            entity_ids: vec![3, 1],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["name".to_string(), "raw".to_string(), "weight".to_string()],
+                field_types: vec![FieldType::String, FieldType::Bytes, FieldType::F64],
+                field_data: vec![
+                    FieldArray::String(vec!["alpha".to_string(), "beta".to_string()].into()),
+                    FieldArray::Bytes(vec![vec![1, 2], vec![]].into()),
+                    FieldArray::F64(vec![0.5, 1.5].into()),
+                ],
+            }),
+        });
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Opaque".to_string(),
+            entity_ids: vec![1],
+            data: ComponentData::Blob(vec![9, 8, 7]),
+        });
+        snapshot.entity_metadata.insert(1, EntityMetadata::new());
+
+        for format in [PackFormat::Custom, PackFormat::Bincode] {
+            snapshot.header.format = format;
+            let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+
+            let arena = Bump::new();
+            let decoded = SnapshotReader::new().read_arena_from_bytes(&bytes, &arena).unwrap();
+            let tag = decoded.archetype("Tag").unwrap();
+            assert_eq!(tag.column("name"), Some(&ArenaColumn::String(&["alpha", "beta"])));
+            assert_eq!(tag.column("weight").unwrap().get(1), Some(FieldValue::F64(1.5)));
+            assert_eq!(decoded.archetype("Opaque").unwrap().data, ArenaComponentData::Blob(&[9, 8, 7]));
+            assert_eq!(decoded.to_snapshot().content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+        }
+
+        snapshot.header.format = PackFormat::Cbor;
+        let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+        assert!(SnapshotReader::new().read_arena_from_bytes(&bytes, &Bump::new()).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, PackedSnapshot, StructOfArraysData};
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array,
+    Int8Array, RecordBatch, StringArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tx2_link::EntityId;
+
+pub const ENTITY_ID_COLUMN: &str = "entity_id";
+pub const COMPONENT_ID_KEY: &str = "tx2.component_id";
+
+impl ComponentArchetype {
+    pub fn to_arrow(&self) -> Result<RecordBatch> {
+        let soa = match &self.data {
+            ComponentData::StructOfArrays(soa) => soa,
+            ComponentData::Blob(_) => {
+                return Err(PackError::Serialization(format!(
+                    "Component {} is stored as a blob and has no columnar form", self.component_id
+                )));
+            }
+        };
+
+        let mut fields = vec![Field::new(ENTITY_ID_COLUMN, DataType::UInt64, false)];
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(UInt64Array::from_iter_values(
+            self.entity_ids.iter().map(|id| u64::from(*id)),
+        ))];
+
+        for ((name, field_type), data) in soa.field_names.iter().zip(&soa.field_types).zip(&soa.field_data) {
+            fields.push(Field::new(name, data_type(*field_type), false));
+            columns.push(to_array(data));
+        }
+
+        let metadata = HashMap::from([(COMPONENT_ID_KEY.to_string(), self.component_id.clone())]);
+        let schema = Schema::new(fields).with_metadata(metadata);
+
+        RecordBatch::try_new(Arc::new(schema), columns).map_err(|e| PackError::Serialization(e.to_string()))
+    }
+
+    pub fn from_arrow(batch: &RecordBatch) -> Result<Self> {
+        let schema = batch.schema();
+        let component_id = schema
+            .metadata()
+            .get(COMPONENT_ID_KEY)
+            .cloned()
+            .ok_or_else(|| PackError::Deserialization(format!("Record batch is missing {} metadata", COMPONENT_ID_KEY)))?;
+
+        let entity_column = batch
+            .column_by_name(ENTITY_ID_COLUMN)
+            .and_then(|column| column.as_any().downcast_ref::<UInt64Array>())
+            .ok_or_else(|| PackError::Deserialization(format!("Record batch is missing a UInt64 {} column", ENTITY_ID_COLUMN)))?;
+        if entity_column.null_count() > 0 {
+            return Err(PackError::Deserialization(format!("Column {} contains nulls", ENTITY_ID_COLUMN)));
+        }
+
+        let entity_ids = entity_column
+            .values()
+            .iter()
+            .map(|id| {
+                EntityId::try_from(*id)
+                    .map_err(|_| PackError::Deserialization(format!("Entity id {} is out of range", id)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut soa = StructOfArraysData {
+            field_names: Vec::new(),
+            field_types: Vec::new(),
+            field_data: Vec::new(),
+        };
+
+        for (field, column) in schema.fields().iter().zip(batch.columns()) {
+            if field.name() == ENTITY_ID_COLUMN {
+                continue;
+            }
+            if column.null_count() > 0 {
+                return Err(PackError::Deserialization(format!("Column {} contains nulls", field.name())));
+            }
+
+            let data = from_array(column.as_ref()).ok_or_else(|| {
+                PackError::Deserialization(format!(
+                    "Column {} has unsupported type {}", field.name(), field.data_type()
+                ))
+            })?;
+
+            soa.field_names.push(field.name().clone());
+            soa.field_types.push(data.field_type());
+            soa.field_data.push(data);
+        }
+
+        Ok(ComponentArchetype {
+            component_id,
+            entity_ids,
+            data: ComponentData::StructOfArrays(soa),
+        })
+    }
+}
+
+impl PackedSnapshot {
+    pub fn to_arrow(&self) -> Result<Vec<RecordBatch>> {
+        self.archetypes
+            .iter()
+            .filter(|archetype| matches!(archetype.data, ComponentData::StructOfArrays(_)))
+            .map(ComponentArchetype::to_arrow)
+            .collect()
+    }
+
+    pub fn from_arrow(batches: &[RecordBatch]) -> Result<Self> {
+        let mut snapshot = PackedSnapshot::new();
+
+        for batch in batches {
+            snapshot.archetypes.push(ComponentArchetype::from_arrow(batch)?);
+        }
+
+        snapshot.rebuild_entity_index();
+        snapshot.header.entity_count = snapshot.entity_index.len() as u64;
+        snapshot.header.component_count = snapshot.archetypes.len() as u64;
+        snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+
+        Ok(snapshot)
+    }
+}
+
+pub(crate) fn data_type(field_type: FieldType) -> DataType {
+    match field_type {
+        FieldType::Bool => DataType::Boolean,
+        FieldType::I8 => DataType::Int8,
+        FieldType::I16 => DataType::Int16,
+        FieldType::I32 => DataType::Int32,
+        FieldType::I64 => DataType::Int64,
+        FieldType::U8 => DataType::UInt8,
+        FieldType::U16 => DataType::UInt16,
+        FieldType::U32 => DataType::UInt32,
+        FieldType::U64 => DataType::UInt64,
+        FieldType::F32 => DataType::Float32,
+        FieldType::F64 => DataType::Float64,
+        FieldType::String => DataType::Utf8,
+        FieldType::Bytes => DataType::Binary,
+    }
+}
+
+pub(crate) fn to_array(data: &FieldArray) -> ArrayRef {
+    match data {
+        FieldArray::Bool(v) => Arc::new(BooleanArray::from(v.to_vec())),
+        FieldArray::I8(v) => Arc::new(Int8Array::from(v.to_vec())),
+        FieldArray::I16(v) => Arc::new(Int16Array::from(v.to_vec())),
+        FieldArray::I32(v) => Arc::new(Int32Array::from(v.to_vec())),
+        FieldArray::I64(v) => Arc::new(Int64Array::from(v.to_vec())),
+        FieldArray::U8(v) => Arc::new(UInt8Array::from(v.to_vec())),
+        FieldArray::U16(v) => Arc::new(UInt16Array::from(v.to_vec())),
+        FieldArray::U32(v) => Arc::new(UInt32Array::from(v.to_vec())),
+        FieldArray::U64(v) => Arc::new(UInt64Array::from(v.to_vec())),
+        FieldArray::F32(v) => Arc::new(Float32Array::from(v.to_vec())),
+        FieldArray::F64(v) => Arc::new(Float64Array::from(v.to_vec())),
+        FieldArray::String(v) => Arc::new(StringArray::from_iter_values(v)),
+        FieldArray::Bytes(v) => Arc::new(BinaryArray::from_iter_values(v)),
+    }
+}
+
+fn from_array(array: &dyn Array) -> Option<FieldArray> {
+    let any = array.as_any();
+
+    let data = match array.data_type() {
+        DataType::Boolean => FieldArray::Bool(any.downcast_ref::<BooleanArray>()?.iter().map(Option::unwrap_or_default).collect()),
+        DataType::Int8 => FieldArray::I8(any.downcast_ref::<Int8Array>()?.values().to_vec().into()),
+        DataType::Int16 => FieldArray::I16(any.downcast_ref::<Int16Array>()?.values().to_vec().into()),
+        DataType::Int32 => FieldArray::I32(any.downcast_ref::<Int32Array>()?.values().to_vec().into()),
+        DataType::Int64 => FieldArray::I64(any.downcast_ref::<Int64Array>()?.values().to_vec().into()),
+        DataType::UInt8 => FieldArray::U8(any.downcast_ref::<UInt8Array>()?.values().to_vec().into()),
+        DataType::UInt16 => FieldArray::U16(any.downcast_ref::<UInt16Array>()?.values().to_vec().into()),
+        DataType::UInt32 => FieldArray::U32(any.downcast_ref::<UInt32Array>()?.values().to_vec().into()),
+        DataType::UInt64 => FieldArray::U64(any.downcast_ref::<UInt64Array>()?.values().to_vec().into()),
+        DataType::Float32 => FieldArray::F32(any.downcast_ref::<Float32Array>()?.values().to_vec().into()),
+        DataType::Float64 => FieldArray::F64(any.downcast_ref::<Float64Array>()?.values().to_vec().into()),
+        DataType::Utf8 => FieldArray::String(
+            any.downcast_ref::<StringArray>()?.iter().map(|s| s.unwrap_or_default().to_string()).collect(),
+        ),
+        DataType::Binary => FieldArray::Bytes(
+            any.downcast_ref::<BinaryArray>()?.iter().map(|b| b.unwrap_or_default().to_vec()).collect(),
+        ),
+        _ => return None,
+    };
+
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arrow_roundtrip() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Unit".to_string(),
+            entity_ids: vec![3, 7],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["hp".to_string(), "name".to_string(), "alive".to_string()],
+                field_types: vec![FieldType::F32, FieldType::String, FieldType::Bool],
+                field_data: vec![
+                    FieldArray::F32(vec![10.0, 2.5].into()),
+                    FieldArray::String(vec!["knight".to_string(), "archer".to_string()].into()),
+                    FieldArray::Bool(vec![true, false].into()),
+                ],
+            }),
+        });
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Script".to_string(),
+            entity_ids: vec![3],
+            data: ComponentData::Blob(vec![1, 2, 3]),
+        });
+
+        let batches = snapshot.to_arrow().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 2);
+        assert_eq!(batches[0].schema().field(1).data_type(), &DataType::Float32);
+        assert!(snapshot.archetypes[1].to_arrow().is_err());
+
+        let restored = PackedSnapshot::from_arrow(&batches).unwrap();
+        let unit = &restored.archetypes[0];
+        assert_eq!(unit.component_id, "Unit");
+        assert_eq!(unit.entity_ids, vec![3, 7]);
+        match (&unit.data, &snapshot.archetypes[0].data) {
+            (ComponentData::StructOfArrays(a), ComponentData::StructOfArrays(b)) => {
+                assert_eq!(a.field_names, b.field_names);
+                assert_eq!(a.field_types, b.field_types);
+                assert_eq!(a.field_data, b.field_data);
+            }
+            _ => panic!("expected columnar data"),
+        }
+        assert_eq!(restored.header.entity_count, 2);
+    }
+}
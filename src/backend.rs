@@ -0,0 +1,44 @@
+use crate::error::Result;
+use crate::format::PackedSnapshot;
+use crate::metadata::SnapshotMetadata;
+use crate::storage::{SnapshotReader, SnapshotStore, SnapshotWriter};
+
+pub trait StorageBackend {
+    fn save(&self, snapshot: &PackedSnapshot, metadata: &SnapshotMetadata, writer: &SnapshotWriter) -> Result<()>;
+
+    fn load(&self, id: &str, reader: &SnapshotReader) -> Result<(PackedSnapshot, SnapshotMetadata)>;
+
+    fn load_metadata(&self, id: &str) -> Result<SnapshotMetadata>;
+
+    fn exists(&self, id: &str) -> bool;
+
+    fn delete(&self, id: &str) -> Result<()>;
+
+    fn list(&self) -> Result<Vec<String>>;
+}
+
+impl StorageBackend for SnapshotStore {
+    fn save(&self, snapshot: &PackedSnapshot, metadata: &SnapshotMetadata, writer: &SnapshotWriter) -> Result<()> {
+        SnapshotStore::save(self, snapshot, metadata, writer).map(|_| ())
+    }
+
+    fn load(&self, id: &str, reader: &SnapshotReader) -> Result<(PackedSnapshot, SnapshotMetadata)> {
+        SnapshotStore::load(self, id, reader)
+    }
+
+    fn load_metadata(&self, id: &str) -> Result<SnapshotMetadata> {
+        SnapshotStore::load_metadata(self, id)
+    }
+
+    fn exists(&self, id: &str) -> bool {
+        SnapshotStore::exists(self, id)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        SnapshotStore::delete(self, id)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        SnapshotStore::list(self)
+    }
+}
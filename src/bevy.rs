@@ -0,0 +1,389 @@
+use crate::capture::CaptureOptions;
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, FieldValue, PackedSnapshot, StructOfArraysData};
+use bevy_ecs::entity::Entity;
+use bevy_ecs::reflect::ReflectComponent;
+use bevy_ecs::world::World;
+use bevy_reflect::{DynamicStruct, DynamicTupleStruct, PartialReflect, ReflectRef, TypeInfo, TypeRegistry};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tx2_link::EntityId;
+
+const VALUE_FIELD: &str = "value";
+
+#[derive(Debug, Clone, Default)]
+pub struct BevyCapture {
+    components: Option<BTreeSet<String>>,
+    options: CaptureOptions,
+}
+
+impl BevyCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_component<T: bevy_reflect::TypePath>(self) -> Self {
+        self.with_type_path(T::type_path())
+    }
+
+    pub fn with_type_path(mut self, type_path: &str) -> Self {
+        self.components
+            .get_or_insert_with(BTreeSet::new)
+            .insert(type_path.to_string());
+        self
+    }
+
+    pub fn with_options(mut self, options: CaptureOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn capture(&self, world: &World, registry: &TypeRegistry) -> Result<PackedSnapshot> {
+        let mut entities: Vec<Entity> = world
+            .archetypes()
+            .iter()
+            .flat_map(|archetype| archetype.entities().iter().map(|e| e.id()))
+            .collect();
+        entities.sort_by_key(|entity| entity.index_u32());
+        entities.retain(|entity| self.options.entity_matches(snapshot_id(*entity), None));
+
+        let mut snapshot = PackedSnapshot::new();
+        let mut captured: BTreeSet<EntityId> = BTreeSet::new();
+
+        for registration in registry.iter() {
+            let type_path = registration.type_info().type_path();
+            if self.components.as_ref().is_some_and(|set| !set.contains(type_path))
+                || !self.options.component_matches(&type_path.to_string())
+            {
+                continue;
+            }
+            let Some(reflect_component) = registration.data::<ReflectComponent>() else {
+                continue;
+            };
+
+            let mut entity_ids = Vec::new();
+            let mut columns: BTreeMap<String, FieldArray> = BTreeMap::new();
+            let mut field_names: Vec<String> = Vec::new();
+
+            for entity in &entities {
+                let Some(component) = reflect_component.reflect(world.entity(*entity)) else {
+                    continue;
+                };
+
+                let mut row = Vec::new();
+                flatten(component.as_partial_reflect(), "", &mut row).map_err(|field| {
+                    PackError::Serialization(format!("Cannot pack field '{}' of {}", field, type_path))
+                })?;
+
+                if entity_ids.is_empty() {
+                    field_names = row.iter().map(|(name, _)| name.clone()).collect();
+                }
+                for (name, value) in row {
+                    columns
+                        .entry(name)
+                        .or_insert_with(|| FieldArray::with_type(value.field_type()))
+                        .push(value)?;
+                }
+
+                let entity_id = snapshot_id(*entity);
+                entity_ids.push(entity_id);
+                captured.insert(entity_id);
+            }
+
+            if entity_ids.is_empty() {
+                continue;
+            }
+
+            let field_data: Vec<FieldArray> = field_names
+                .iter()
+                .map(|name| columns.remove(name).unwrap_or_else(|| FieldArray::with_type(FieldType::Bool)))
+                .collect();
+
+            snapshot.archetypes.push(ComponentArchetype {
+                component_id: type_path.to_string(),
+                entity_ids,
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_types: field_data.iter().map(FieldArray::field_type).collect(),
+                    field_names,
+                    field_data,
+                }),
+            });
+        }
+
+        snapshot.archetypes.sort_by(|a, b| a.component_id.cmp(&b.component_id));
+        snapshot.header.entity_count = captured.len() as u64;
+        snapshot.header.component_count = snapshot.archetypes.len() as u64;
+        snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+        snapshot.rebuild_entity_index();
+
+        Ok(snapshot)
+    }
+}
+
+pub fn capture_world(world: &World, registry: &TypeRegistry) -> Result<PackedSnapshot> {
+    BevyCapture::new().capture(world, registry)
+}
+
+pub fn restore_world(
+    world: &mut World,
+    registry: &TypeRegistry,
+    snapshot: &PackedSnapshot,
+) -> Result<HashMap<EntityId, Entity>> {
+    let mut plan = Vec::with_capacity(snapshot.archetypes.len());
+    for archetype in &snapshot.archetypes {
+        let registration = registry.get_with_type_path(&archetype.component_id).ok_or_else(|| {
+            PackError::InvalidFormat(format!("Component {} is not registered", archetype.component_id))
+        })?;
+        let reflect_component = registration.data::<ReflectComponent>().ok_or_else(|| {
+            PackError::InvalidFormat(format!("{} is not reflected as a component", archetype.component_id))
+        })?;
+        if matches!(archetype.data, ComponentData::Blob(_)) {
+            return Err(PackError::InvalidFormat(format!(
+                "Component {} is stored as a blob and cannot be restored through reflection",
+                archetype.component_id
+            )));
+        }
+        plan.push((archetype, registration.type_info(), reflect_component));
+    }
+
+    let entity_ids: BTreeSet<EntityId> = snapshot
+        .archetypes
+        .iter()
+        .flat_map(|a| a.entity_ids.iter().copied())
+        .collect();
+    let entity_map: HashMap<EntityId, Entity> = entity_ids
+        .into_iter()
+        .map(|id| (id, world.spawn_empty().id()))
+        .collect();
+
+    for (archetype, type_info, reflect_component) in plan {
+        for (row, entity_id) in archetype.entity_ids.iter().enumerate() {
+            let values: HashMap<String, FieldValue> = archetype.row_values(row).unwrap_or_default().into_iter().collect();
+            let component = rebuild(type_info, "", &values, &entity_map).map_err(|field| {
+                PackError::Deserialization(format!(
+                    "Cannot restore field '{}' of {} for entity {}",
+                    field, archetype.component_id, entity_id
+                ))
+            })?;
+
+            reflect_component.insert(&mut world.entity_mut(entity_map[entity_id]), component.as_ref(), registry);
+        }
+    }
+
+    Ok(entity_map)
+}
+
+fn snapshot_id(entity: Entity) -> EntityId {
+    EntityId::from(entity.index_u32())
+}
+
+fn field_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+fn flatten(value: &dyn PartialReflect, prefix: &str, row: &mut Vec<(String, FieldValue)>) -> std::result::Result<(), String> {
+    if let Some(entity) = value.try_downcast_ref::<Entity>() {
+        row.push((leaf_name(prefix), FieldValue::U64(u64::from(snapshot_id(*entity)))));
+        return Ok(());
+    }
+    if let Some(leaf) = leaf_value(value) {
+        row.push((leaf_name(prefix), leaf));
+        return Ok(());
+    }
+
+    match value.reflect_ref() {
+        ReflectRef::Struct(s) => {
+            for index in 0..s.field_len() {
+                let name = s.name_at(index).unwrap_or_default();
+                flatten(s.field_at(index).unwrap(), &field_path(prefix, name), row)?;
+            }
+            Ok(())
+        }
+        ReflectRef::TupleStruct(t) => {
+            for index in 0..t.field_len() {
+                flatten(t.field(index).unwrap(), &field_path(prefix, &index.to_string()), row)?;
+            }
+            Ok(())
+        }
+        _ => Err(leaf_name(prefix)),
+    }
+}
+
+fn leaf_name(prefix: &str) -> String {
+    if prefix.is_empty() {
+        VALUE_FIELD.to_string()
+    } else {
+        prefix.to_string()
+    }
+}
+
+fn rebuild(
+    info: &'static TypeInfo,
+    prefix: &str,
+    values: &HashMap<String, FieldValue>,
+    entity_map: &HashMap<EntityId, Entity>,
+) -> std::result::Result<Box<dyn PartialReflect>, String> {
+    if info.is::<Entity>() {
+        let entity = match values.get(&leaf_name(prefix)) {
+            Some(FieldValue::U64(id)) => EntityId::try_from(*id)
+                .ok()
+                .and_then(|id| entity_map.get(&id).copied())
+                .unwrap_or(Entity::PLACEHOLDER),
+            _ => return Err(leaf_name(prefix)),
+        };
+        return Ok(Box::new(entity));
+    }
+    if let Some(field_type) = leaf_type(info) {
+        return match values.get(&leaf_name(prefix)) {
+            Some(value) if value.field_type() == field_type => Ok(boxed_value(value.clone())),
+            _ => Err(leaf_name(prefix)),
+        };
+    }
+
+    match info {
+        TypeInfo::Struct(struct_info) => {
+            let mut dynamic = DynamicStruct::default();
+            dynamic.set_represented_type(Some(info));
+            for field in struct_info.iter() {
+                let field_info = field.type_info().ok_or_else(|| field_path(prefix, field.name()))?;
+                dynamic.insert_boxed(field.name(), rebuild(field_info, &field_path(prefix, field.name()), values, entity_map)?);
+            }
+            Ok(Box::new(dynamic))
+        }
+        TypeInfo::TupleStruct(tuple_info) => {
+            let mut dynamic = DynamicTupleStruct::default();
+            dynamic.set_represented_type(Some(info));
+            for field in tuple_info.iter() {
+                let path = field_path(prefix, &field.index().to_string());
+                let field_info = field.type_info().ok_or_else(|| path.clone())?;
+                dynamic.insert_boxed(rebuild(field_info, &path, values, entity_map)?);
+            }
+            Ok(Box::new(dynamic))
+        }
+        _ => Err(leaf_name(prefix)),
+    }
+}
+
+macro_rules! leaf_types {
+    ($($variant:ident => $ty:ty),* $(,)?) => {
+        fn leaf_value(value: &dyn PartialReflect) -> Option<FieldValue> {
+            $(
+                if let Some(v) = value.try_downcast_ref::<$ty>() {
+                    return Some(FieldValue::$variant(v.clone()));
+                }
+            )*
+            None
+        }
+
+        fn leaf_type(info: &TypeInfo) -> Option<FieldType> {
+            $(
+                if info.is::<$ty>() {
+                    return Some(FieldType::$variant);
+                }
+            )*
+            None
+        }
+
+        fn boxed_value(value: FieldValue) -> Box<dyn PartialReflect> {
+            match value {
+                $(FieldValue::$variant(v) => Box::new(v),)*
+            }
+        }
+    };
+}
+
+leaf_types! {
+    Bool => bool,
+    I8 => i8,
+    I16 => i16,
+    I32 => i32,
+    I64 => i64,
+    U8 => u8,
+    U16 => u16,
+    U32 => u32,
+    U64 => u64,
+    F32 => f32,
+    F64 => f64,
+    String => String,
+    Bytes => Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_ecs::component::Component;
+    use bevy_reflect::Reflect;
+
+    #[derive(Component, Reflect, Debug, Clone, PartialEq)]
+    #[reflect(Component)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Reflect, Debug, Clone, PartialEq)]
+    struct Stats {
+        hp: i32,
+        name: String,
+    }
+
+    #[derive(Component, Reflect, Debug, Clone, PartialEq)]
+    #[reflect(Component)]
+    struct Unit {
+        stats: Stats,
+        alive: bool,
+    }
+
+    #[derive(Component, Reflect, Debug, Clone, PartialEq)]
+    #[reflect(Component)]
+    struct Target(Entity);
+
+    fn registry() -> TypeRegistry {
+        let mut registry = TypeRegistry::default();
+        registry.register::<Position>();
+        registry.register::<Unit>();
+        registry.register::<Target>();
+        registry
+    }
+
+    #[test]
+    fn test_bevy_capture_and_restore() {
+        let registry = registry();
+        let mut world = World::new();
+        world.spawn_empty();
+        let hero = world
+            .spawn((
+                Position { x: 1.0, y: 2.0 },
+                Unit { stats: Stats { hp: 30, name: "hero".to_string() }, alive: true },
+            ))
+            .id();
+        let archer = world.spawn((Position { x: 5.0, y: -1.0 }, Target(hero))).id();
+
+        let snapshot = capture_world(&world, &registry).unwrap();
+        assert_eq!(snapshot.header.entity_count, 2);
+        assert_eq!(snapshot.archetypes.len(), 3);
+        let unit = snapshot.archetypes.iter().find(|a| a.component_id.ends_with("Unit")).unwrap();
+        let row = unit.row_values(0).unwrap();
+        assert_eq!(row[0], ("stats.hp".to_string(), FieldValue::I32(30)));
+
+        let positions_only = BevyCapture::new().with_component::<Position>().capture(&world, &registry).unwrap();
+        assert_eq!(positions_only.archetypes.len(), 1);
+
+        let mut restored = World::new();
+        restored.spawn_empty();
+        restored.spawn_empty();
+        let map = restore_world(&mut restored, &registry, &snapshot).unwrap();
+
+        let new_hero = map[&EntityId::from(hero.index_u32())];
+        let new_archer = map[&EntityId::from(archer.index_u32())];
+        assert_ne!(new_hero, hero);
+        assert_eq!(restored.get::<Position>(new_hero), Some(&Position { x: 1.0, y: 2.0 }));
+        assert_eq!(restored.get::<Unit>(new_hero).unwrap().stats.name, "hero");
+        assert_eq!(restored.get::<Target>(new_archer), Some(&Target(new_hero)));
+
+        assert!(restore_world(&mut World::new(), &TypeRegistry::default(), &snapshot).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::error::{PackError, Result};
+
+// Offsets and sizes come from headers and tables of contents that may be
+// hostile. Every sum is checked and every conversion to usize is fallible,
+// so a crafted file cannot wrap an index on a 32-bit target.
+
+pub(crate) fn end(offset: u64, size: u64, label: &str) -> Result<u64> {
+    offset
+        .checked_add(size)
+        .ok_or_else(|| PackError::InvalidFormat(format!("{} {}+{} overflows", label, offset, size)))
+}
+
+pub(crate) fn end_within(offset: u64, size: u64, limit: u64, label: &str) -> Result<u64> {
+    let end = end(offset, size, label)?;
+    if end > limit {
+        return Err(PackError::InvalidFormat(format!(
+            "{} {}+{} exceeds length {}", label, offset, size, limit
+        )));
+    }
+    Ok(end)
+}
+
+pub(crate) fn to_usize(value: u64, label: &str) -> Result<usize> {
+    usize::try_from(value)
+        .map_err(|_| PackError::InvalidFormat(format!("{} {} does not fit in memory on this platform", label, value)))
+}
+
+pub(crate) fn slice<'a>(bytes: &'a [u8], offset: u64, size: u64, label: &str) -> Result<&'a [u8]> {
+    let end = end_within(offset, size, bytes.len() as u64, label)?;
+    // Both ends are at most bytes.len(), so they fit in usize.
+    Ok(&bytes[offset as usize..end as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{PackFormat, PackedSnapshot, SnapshotHeader, HEADER_SIZE};
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+
+    #[test]
+    fn test_ranges_never_wrap() {
+        let bytes = [0u8; 16];
+        assert_eq!(slice(&bytes, 4, 8, "Data").unwrap().len(), 8);
+        assert!(slice(&bytes, 16, 0, "Data").unwrap().is_empty());
+        assert!(slice(&bytes, 8, 9, "Data").is_err());
+        assert!(slice(&bytes, u64::MAX, 2, "Data").is_err());
+        assert!(slice(&bytes, 2, u64::MAX, "Data").is_err());
+        assert!(end(u64::MAX - 1, 1, "Data").is_ok());
+        assert!(end_within(u64::MAX, 1, u64::MAX, "Data").is_err());
+    }
+
+    // A small deterministic generator stands in for a fuzzer: every header
+    // field that feeds offset math is replaced with extreme and random values,
+    // and the reader must return an error rather than panic.
+    #[test]
+    fn test_hostile_headers_are_rejected_without_panicking() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("hostile.tx2pack");
+        let reader = SnapshotReader::new();
+
+        let mut seed = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for format in [PackFormat::Custom, PackFormat::Bincode] {
+            let mut snapshot = PackedSnapshot::new();
+            snapshot.header.format = format;
+            let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+            let original = SnapshotHeader::decode(&bytes).unwrap();
+
+            let len = bytes.len() as u64;
+            let extremes = [0, 1, HEADER_SIZE, len, len + 1, u32::MAX as u64, u64::MAX / 2, u64::MAX - 1, u64::MAX];
+            for round in 0..400 {
+                let mut header = original.clone();
+                let mut pick = || match next() % 3 {
+                    0 => extremes[(next() % extremes.len() as u64) as usize],
+                    1 => next() % (2 * len),
+                    _ => next(),
+                };
+                match round % 4 {
+                    0 => header.data_offset = pick(),
+                    1 => header.data_size = pick(),
+                    2 => (header.data_offset, header.data_size) = (pick(), pick()),
+                    _ => (header.archetype_count, header.entity_count) = (pick(), pick()),
+                }
+
+                let mut hostile = bytes.clone();
+                let mut encoded = Vec::new();
+                header.encode_into(&mut encoded).unwrap();
+                hostile[..HEADER_SIZE as usize].copy_from_slice(&encoded);
+                let trailer = hostile.len() - HEADER_SIZE as usize;
+                hostile[trailer..].copy_from_slice(&encoded);
+
+                let changed = header.data_offset != original.data_offset || header.data_size != original.data_size;
+                let read = reader.read_from_bytes(&hostile);
+                assert!(!changed || read.is_err(), "{:?}", header);
+                let _ = reader.read_lazy_from_bytes(&hostile);
+                let _ = reader.recover_from_bytes(&hostile);
+
+                std::fs::write(&path, &hostile).unwrap();
+                let _ = reader.read_from_file(&path);
+                let _ = reader.read_lazy_from_file(&path).and_then(|lazy| lazy.into_snapshot());
+                let _ = crate::validate::validate(&path);
+            }
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use crate::delta::DeltaSnapshot;
+use crate::error::{PackError, Result};
+use crate::format::PackedSnapshot;
+use crate::replay::TimeTravel;
+use crate::storage::{SnapshotReader, SnapshotWriter};
+use crate::transfer::{read_encoded, write_encoded};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tungstenite::handshake::{HandshakeError, HandshakeRole};
+use tungstenite::http::Uri;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+
+pub const DEFAULT_KEYFRAME_INTERVAL: u64 = 30;
+pub const DEFAULT_MAX_BROADCAST_FRAME: usize = 1 << 30;
+
+const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BroadcastFrame {
+    Keyframe { sequence: u64, time: f64, snapshot: Vec<u8> },
+    /// Changes since frame `sequence - 1`.
+    Delta { sequence: u64, time: f64, delta: Vec<u8> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastTransport {
+    Tcp,
+    WebSocket,
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    WebSocket(Box<WebSocket<TcpStream>>),
+}
+
+impl Connection {
+    fn send(&mut self, encoded: &[u8]) -> Result<()> {
+        match self {
+            Connection::Tcp(stream) => write_encoded(stream, encoded),
+            Connection::WebSocket(socket) => Ok(socket.send(Message::Binary(encoded.to_vec()))?),
+        }
+    }
+
+    fn receive(&mut self, max_len: usize) -> Result<BroadcastFrame> {
+        let encoded = match self {
+            Connection::Tcp(stream) => read_encoded(stream, max_len)?,
+            Connection::WebSocket(socket) => loop {
+                match socket.read()? {
+                    Message::Binary(encoded) => break encoded,
+                    Message::Close(_) => return Err(std::io::Error::from(ErrorKind::ConnectionAborted).into()),
+                    _ => continue,
+                }
+            },
+        };
+        Ok(bincode::deserialize(&encoded)?)
+    }
+}
+
+fn websocket_config(max_frame: usize) -> WebSocketConfig {
+    WebSocketConfig {
+        max_message_size: Some(max_frame),
+        max_frame_size: Some(max_frame),
+        ..WebSocketConfig::default()
+    }
+}
+
+fn handshake_error<R: HandshakeRole>(error: HandshakeError<R>) -> PackError {
+    match error {
+        HandshakeError::Failure(error) => error.into(),
+        HandshakeError::Interrupted(_) => std::io::Error::from(ErrorKind::WouldBlock).into(),
+    }
+}
+
+/// Subscribers that miss the write timeout are dropped rather than stalling
+/// the rest.
+pub struct Publisher {
+    listener: TcpListener,
+    transport: BroadcastTransport,
+    writer: SnapshotWriter,
+    keyframe_interval: u64,
+    write_timeout: Duration,
+    subscribers: Vec<Connection>,
+    last: Option<PackedSnapshot>,
+    sequence: u64,
+}
+
+impl Publisher {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Self::bind_with(addr, BroadcastTransport::Tcp)
+    }
+
+    pub fn bind_websocket<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Self::bind_with(addr, BroadcastTransport::WebSocket)
+    }
+
+    pub fn bind_with<A: ToSocketAddrs>(addr: A, transport: BroadcastTransport) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            transport,
+            writer: SnapshotWriter::new(),
+            keyframe_interval: DEFAULT_KEYFRAME_INTERVAL,
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            subscribers: Vec::new(),
+            last: None,
+            sequence: 0,
+        })
+    }
+
+    pub fn with_writer(mut self, writer: SnapshotWriter) -> Self {
+        self.writer = writer;
+        self
+    }
+
+    pub fn with_keyframe_interval(mut self, frames: u64) -> Self {
+        self.keyframe_interval = frames.max(1);
+        self
+    }
+
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Returns how many subscribers received the frame.
+    pub fn publish(&mut self, time: f64, snapshot: &PackedSnapshot) -> Result<usize> {
+        let joined = self.subscribers.len();
+        self.accept_pending()?;
+
+        let sequence = self.sequence + 1;
+        let delta = match &self.last {
+            Some(last) if joined > 0 && !(sequence - 1).is_multiple_of(self.keyframe_interval) => {
+                let delta = self.writer.write_delta_to_bytes(&DeltaSnapshot::from_snapshots(last, snapshot)?)?;
+                Some(bincode::serialize(&BroadcastFrame::Delta { sequence, time, delta })?)
+            }
+            _ => None,
+        };
+        let keyframe = if delta.is_none() || self.subscribers.len() > joined {
+            let snapshot = self.writer.write_to_bytes(snapshot)?;
+            Some(bincode::serialize(&BroadcastFrame::Keyframe { sequence, time, snapshot })?)
+        } else {
+            None
+        };
+
+        let mut index = 0;
+        self.subscribers.retain_mut(|connection| {
+            let frame = if index < joined { delta.as_ref().or(keyframe.as_ref()) } else { keyframe.as_ref() };
+            index += 1;
+            frame.is_some_and(|frame| connection.send(frame).is_ok())
+        });
+
+        self.last = Some(snapshot.clone());
+        self.sequence = sequence;
+        Ok(self.subscribers.len())
+    }
+
+    fn accept_pending(&mut self) -> Result<()> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            stream.set_nonblocking(false)?;
+            stream.set_nodelay(true)?;
+            stream.set_write_timeout(Some(self.write_timeout))?;
+
+            let connection = match self.transport {
+                BroadcastTransport::Tcp => Connection::Tcp(stream),
+                BroadcastTransport::WebSocket => {
+                    stream.set_read_timeout(Some(self.write_timeout))?;
+                    // A client that fails the handshake is simply not added.
+                    match tungstenite::accept_with_config(stream, Some(websocket_config(DEFAULT_MAX_BROADCAST_FRAME))) {
+                        Ok(socket) => Connection::WebSocket(Box::new(socket)),
+                        Err(_) => continue,
+                    }
+                }
+            };
+            self.subscribers.push(connection);
+        }
+    }
+}
+
+pub struct Subscriber {
+    connection: Connection,
+    reader: SnapshotReader,
+    max_frame: usize,
+    history: Option<usize>,
+    timeline: TimeTravel,
+    latest: Option<PackedSnapshot>,
+    sequence: Option<u64>,
+}
+
+impl Subscriber {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(Connection::Tcp(stream)))
+    }
+
+    pub fn connect_websocket(url: &str) -> Result<Self> {
+        let uri: Uri = url.parse().map_err(|_| PackError::InvalidFormat(format!("Invalid WebSocket url {}", url)))?;
+        let host = uri.host().ok_or_else(|| PackError::InvalidFormat(format!("WebSocket url {} has no host", url)))?;
+        let stream = TcpStream::connect((host, uri.port_u16().unwrap_or(80)))?;
+        stream.set_nodelay(true)?;
+
+        let config = websocket_config(DEFAULT_MAX_BROADCAST_FRAME);
+        let (socket, _) = tungstenite::client::client_with_config(url, stream, Some(config)).map_err(handshake_error)?;
+        Ok(Self::new(Connection::WebSocket(Box::new(socket))))
+    }
+
+    fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            reader: SnapshotReader::new(),
+            max_frame: DEFAULT_MAX_BROADCAST_FRAME,
+            history: None,
+            timeline: TimeTravel::new(),
+            latest: None,
+            sequence: None,
+        }
+    }
+
+    pub fn with_reader(mut self, reader: SnapshotReader) -> Self {
+        self.reader = reader;
+        self
+    }
+
+    pub fn with_max_frame(mut self, bytes: usize) -> Self {
+        self.max_frame = bytes;
+        self
+    }
+
+    pub fn with_history(mut self, frames: usize) -> Self {
+        self.history = Some(frames.max(1));
+        self
+    }
+
+    pub fn timeline(&self) -> &TimeTravel {
+        &self.timeline
+    }
+
+    pub fn latest(&self) -> Option<&PackedSnapshot> {
+        self.latest.as_ref()
+    }
+
+    pub fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
+    pub fn receive(&mut self) -> Result<f64> {
+        let (sequence, time, snapshot) = match self.connection.receive(self.max_frame)? {
+            BroadcastFrame::Keyframe { sequence, time, snapshot } => {
+                (sequence, time, self.reader.read_from_bytes(&snapshot)?)
+            }
+            BroadcastFrame::Delta { sequence, time, delta } => {
+                let base = self
+                    .latest
+                    .as_ref()
+                    .filter(|_| self.sequence.is_some_and(|last| last + 1 == sequence))
+                    .ok_or_else(|| PackError::Delta(format!("Broadcast frame {} arrived without its base", sequence)))?;
+                (sequence, time, self.reader.read_delta_from_bytes(&delta)?.apply(base)?)
+            }
+        };
+
+        self.timeline.record(time, snapshot.clone());
+        if let Some(limit) = self.history {
+            let excess = self.timeline.len().saturating_sub(limit);
+            let cutoff = self.timeline.iter().nth(excess).filter(|_| excess > 0).map(|(time, _)| time);
+            if let Some(cutoff) = cutoff {
+                self.timeline.prune_before(cutoff);
+            }
+        }
+        self.latest = Some(snapshot);
+        self.sequence = Some(sequence);
+        Ok(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData};
+
+    fn frame(tick: u8) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Score".to_string(),
+            entity_ids: vec![1, 2],
+            data: ComponentData::Blob(vec![tick; 32]),
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_subscribers_rebuild_the_published_timeline() {
+        let mut publisher = Publisher::bind("127.0.0.1:0").unwrap().with_keyframe_interval(3);
+        let mut early = Subscriber::connect(publisher.local_addr().unwrap()).unwrap();
+
+        for tick in 0..4 {
+            assert_eq!(publisher.publish(tick as f64, &frame(tick)).unwrap(), 1);
+            assert_eq!(early.receive().unwrap(), tick as f64);
+        }
+        let mut late = Subscriber::connect(publisher.local_addr().unwrap()).unwrap().with_history(2);
+        for tick in 4..6 {
+            assert_eq!(publisher.publish(tick as f64, &frame(tick)).unwrap(), 2);
+            early.receive().unwrap();
+            late.receive().unwrap();
+        }
+
+        assert_eq!(early.timeline().len(), 6);
+        assert_eq!(early.sequence(), Some(6));
+        let at_two = early.timeline().get_snapshot_at_time(2.0).unwrap();
+        assert_eq!(at_two.content_checksum().unwrap(), frame(2).content_checksum().unwrap());
+        assert_eq!(late.timeline().iter().map(|(time, _)| time).collect::<Vec<_>>(), vec![4.0, 5.0]);
+        assert_eq!(late.latest().unwrap().content_checksum().unwrap(), frame(5).content_checksum().unwrap());
+
+        drop(early);
+        publisher.publish(6.0, &frame(6)).unwrap();
+        assert_eq!(publisher.publish(7.0, &frame(7)).unwrap(), 1);
+
+        let mut publisher = Publisher::bind_websocket("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/", publisher.local_addr().unwrap());
+        let dashboard = std::thread::spawn(move || {
+            let mut subscriber = Subscriber::connect_websocket(&url).unwrap();
+            (subscriber.receive().unwrap(), subscriber.receive().unwrap())
+        });
+        let mut tick = 0;
+        while publisher.subscriber_count() == 0 {
+            publisher.publish(tick as f64, &frame(tick)).unwrap();
+            tick += 1;
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        publisher.publish(tick as f64, &frame(tick)).unwrap();
+        assert_eq!(dashboard.join().unwrap(), ((tick - 1) as f64, tick as f64));
+    }
+}
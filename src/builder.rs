@@ -0,0 +1,219 @@
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, FieldValue, StructOfArraysData};
+use crate::packable::PackField;
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone)]
+pub struct ColumnBuilder<T: PackField> {
+    values: Vec<T>,
+}
+
+impl<T: PackField> ColumnBuilder<T> {
+    pub fn new() -> Self {
+        Self { values: Vec::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.values.reserve(additional);
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.values.push(value);
+    }
+
+    pub fn extend_from_slice(&mut self, values: &[T]) {
+        self.values.extend_from_slice(values);
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn finish(self) -> FieldArray {
+        T::column(self.values)
+    }
+}
+
+impl<T: PackField> Default for ColumnBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PackField> Extend<T> for ColumnBuilder<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.values.extend(iter);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchetypeBuilder {
+    component_id: ComponentId,
+    entity_ids: Vec<EntityId>,
+    field_names: Vec<String>,
+    columns: Vec<FieldArray>,
+}
+
+impl ArchetypeBuilder {
+    pub fn new(component_id: impl Into<ComponentId>) -> Self {
+        Self::with_capacity(component_id, 0)
+    }
+
+    pub fn with_capacity(component_id: impl Into<ComponentId>, rows: usize) -> Self {
+        Self {
+            component_id: component_id.into(),
+            entity_ids: Vec::with_capacity(rows),
+            field_names: Vec::new(),
+            columns: Vec::new(),
+        }
+    }
+
+    pub fn with_field(mut self, name: &str, field_type: FieldType) -> Self {
+        let mut column = FieldArray::with_type(field_type);
+        column.reserve(self.entity_ids.capacity());
+        self.field_names.push(name.to_string());
+        self.columns.push(column);
+        self
+    }
+
+    pub fn with_column<T: PackField>(mut self, name: &str, column: ColumnBuilder<T>) -> Self {
+        self.field_names.push(name.to_string());
+        self.columns.push(column.finish());
+        self
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.entity_ids.reserve(additional);
+        for column in &mut self.columns {
+            column.reserve(additional);
+        }
+    }
+
+    pub fn push_row<I>(&mut self, entity_id: EntityId, values: I) -> Result<()>
+    where
+        I: IntoIterator<Item = FieldValue>,
+    {
+        let values: Vec<FieldValue> = values.into_iter().collect();
+        if values.len() != self.columns.len() {
+            return Err(PackError::InvalidFormat(format!(
+                "Row for {} has {} values, expected {}", self.component_id, values.len(), self.columns.len()
+            )));
+        }
+
+        for ((name, column), value) in self.field_names.iter().zip(&self.columns).zip(&values) {
+            if value.field_type() != column.field_type() {
+                return Err(PackError::InvalidFormat(format!(
+                    "Field {} of {} is {:?}, got {:?}", name, self.component_id, column.field_type(), value.field_type()
+                )));
+            }
+        }
+
+        for (column, value) in self.columns.iter_mut().zip(values) {
+            column.push(value)?;
+        }
+        self.entity_ids.push(entity_id);
+
+        Ok(())
+    }
+
+    pub fn extend_entities(&mut self, entity_ids: &[EntityId]) {
+        self.entity_ids.extend_from_slice(entity_ids);
+    }
+
+    pub fn extend_column<T: PackField>(&mut self, name: &str, values: &[T]) -> Result<()> {
+        let index = self
+            .field_names
+            .iter()
+            .position(|field| field == name)
+            .ok_or_else(|| PackError::InvalidFormat(format!("{} has no field {}", self.component_id, name)))?;
+
+        let column = &mut self.columns[index];
+        let found = column.field_type();
+        T::values_mut(column)
+            .ok_or_else(|| PackError::InvalidFormat(format!(
+                "Field {} of {} is {:?}, got {:?}", name, self.component_id, found, T::FIELD_TYPE
+            )))?
+            .extend_from_slice(values);
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entity_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entity_ids.is_empty()
+    }
+
+    pub fn build(self) -> Result<ComponentArchetype> {
+        for (name, column) in self.field_names.iter().zip(&self.columns) {
+            if column.len() != self.entity_ids.len() {
+                return Err(PackError::InvalidFormat(format!(
+                    "Field {} of {} has {} values for {} entities",
+                    name, self.component_id, column.len(), self.entity_ids.len()
+                )));
+            }
+        }
+
+        Ok(ComponentArchetype {
+            component_id: self.component_id,
+            entity_ids: self.entity_ids,
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: self.field_names,
+                field_types: self.columns.iter().map(FieldArray::field_type).collect(),
+                field_data: self.columns,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_and_row_construction() {
+        let mut xs = ColumnBuilder::with_capacity(3);
+        xs.extend_from_slice(&[1.0f32, 2.0]);
+        xs.push(3.0);
+
+        let mut builder = ArchetypeBuilder::with_capacity("Position", 4)
+            .with_column("x", xs)
+            .with_field("label", FieldType::String);
+        builder.extend_entities(&[10, 11, 12]);
+        builder
+            .extend_column("label", &["a".to_string(), "b".to_string(), "c".to_string()])
+            .unwrap();
+        builder
+            .push_row(13, [FieldValue::F32(4.0), FieldValue::String("d".to_string())])
+            .unwrap();
+
+        assert!(builder.push_row(14, [FieldValue::F32(5.0)]).is_err());
+        assert!(builder.push_row(14, [FieldValue::I32(5), FieldValue::String("e".to_string())]).is_err());
+        assert!(builder.extend_column("x", &[1u8]).is_err());
+        assert_eq!(builder.len(), 4);
+
+        let archetype = builder.build().unwrap();
+        assert_eq!(archetype.entity_ids, vec![10, 11, 12, 13]);
+        assert_eq!(
+            archetype.row_values(3).unwrap(),
+            vec![("x".to_string(), FieldValue::F32(4.0)), ("label".to_string(), FieldValue::String("d".to_string()))]
+        );
+
+        let mut ragged = ArchetypeBuilder::new("Velocity").with_field("dx", FieldType::F32);
+        ragged.extend_entities(&[1, 2]);
+        ragged.extend_column("dx", &[0.5f32]).unwrap();
+        assert!(ragged.build().is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::checksum::ChecksumAlgorithm;
+use crate::error::Result;
+use crate::format::{PackedSnapshot, SnapshotHeader, MAGIC_NUMBER};
+use crate::storage::SnapshotReader;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+type CacheKey = (ChecksumAlgorithm, [u8; 32]);
+
+struct CacheEntry {
+    key: CacheKey,
+    snapshot: Arc<PackedSnapshot>,
+    size: usize,
+}
+
+#[derive(Default)]
+struct Entries {
+    lru: VecDeque<CacheEntry>,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+pub struct CachingReader {
+    reader: SnapshotReader,
+    capacity: usize,
+    max_bytes: Option<usize>,
+    entries: Mutex<Entries>,
+}
+
+impl CachingReader {
+    pub fn new(reader: SnapshotReader, capacity: usize) -> Self {
+        Self {
+            reader,
+            capacity,
+            max_bytes: None,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn read_from_bytes(&self, bytes: &[u8]) -> Result<Arc<PackedSnapshot>> {
+        let header = SnapshotHeader::decode(bytes)?;
+        header.validate_with_magic(MAGIC_NUMBER)?;
+
+        self.get_or_load(&header, || self.reader.read_from_bytes(bytes))
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(&self, path: P) -> Result<Arc<PackedSnapshot>> {
+        let header = self.reader.read_header_from_file(path.as_ref())?;
+
+        self.get_or_load(&header, || self.reader.read_from_file(path.as_ref()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().lru.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn cached_bytes(&self) -> usize {
+        self.lock().bytes
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.lock().hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.lock().misses
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.lock();
+        entries.lru.clear();
+        entries.bytes = 0;
+    }
+
+    fn get_or_load<F>(&self, header: &SnapshotHeader, load: F) -> Result<Arc<PackedSnapshot>>
+    where
+        F: FnOnce() -> Result<PackedSnapshot>,
+    {
+        let key = (header.checksum_algorithm, header.checksum);
+
+        {
+            let mut entries = self.lock();
+            if let Some(index) = entries.lru.iter().position(|entry| entry.key == key) {
+                let entry = entries.lru.remove(index).expect("index from position");
+                let snapshot = entry.snapshot.clone();
+                entries.lru.push_back(entry);
+                entries.hits += 1;
+                return Ok(snapshot);
+            }
+            entries.misses += 1;
+        }
+
+        let snapshot = Arc::new(load()?);
+        let size = snapshot.estimated_heap_size();
+
+        let mut entries = self.lock();
+        if let Some(index) = entries.lru.iter().position(|entry| entry.key == key) {
+            return Ok(entries.lru[index].snapshot.clone());
+        }
+
+        if self.capacity == 0 || self.max_bytes.is_some_and(|max| size > max) {
+            return Ok(snapshot);
+        }
+
+        entries.bytes += size;
+        entries.lru.push_back(CacheEntry {
+            key,
+            snapshot: snapshot.clone(),
+            size,
+        });
+
+        while entries.lru.len() > self.capacity || self.max_bytes.is_some_and(|max| entries.bytes > max) {
+            match entries.lru.pop_front() {
+                Some(evicted) => entries.bytes -= evicted.size,
+                None => break,
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SnapshotWriter;
+
+    fn snapshot_bytes(timestamp: i64) -> Vec<u8> {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.timestamp = timestamp;
+        SnapshotWriter::new().write_to_bytes(&snapshot).unwrap()
+    }
+
+    #[test]
+    fn test_repeated_reads_share_snapshot() {
+        let cache = CachingReader::new(SnapshotReader::new(), 2);
+        let (a, b, c) = (snapshot_bytes(1), snapshot_bytes(2), snapshot_bytes(3));
+
+        let first = cache.read_from_bytes(&a).unwrap();
+        let again = cache.read_from_bytes(&a).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        cache.read_from_bytes(&b).unwrap();
+        cache.read_from_bytes(&a).unwrap();
+        cache.read_from_bytes(&c).unwrap();
+        assert_eq!(cache.len(), 2);
+
+        // b was least recently used, so it was evicted and a survived.
+        assert!(Arc::ptr_eq(&first, &cache.read_from_bytes(&a).unwrap()));
+        let misses = cache.misses();
+        cache.read_from_bytes(&b).unwrap();
+        assert_eq!(cache.misses(), misses + 1);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("a.tx2pack");
+        std::fs::write(&path, &a).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.read_from_file(&path).unwrap()));
+    }
+}
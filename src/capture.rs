@@ -0,0 +1,232 @@
+use crate::format::{ComponentData, EntityMetadata, PackedSnapshot};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Arc;
+use tx2_link::{ComponentId, EntityId};
+
+type EntityPredicate = dyn Fn(EntityId, Option<&EntityMetadata>) -> bool + Send + Sync;
+
+// Decides which components and entities a capture keeps. Component filters
+// are checked before an archetype is captured at all; entity filters see the
+// entity's metadata, so tag filters only match entities that have some.
+#[derive(Clone, Default)]
+pub struct CaptureOptions {
+    include_components: Option<BTreeSet<ComponentId>>,
+    exclude_components: BTreeSet<ComponentId>,
+    include_tags: Option<BTreeSet<String>>,
+    exclude_tags: BTreeSet<String>,
+    predicate: Option<Arc<EntityPredicate>>,
+}
+
+impl CaptureOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_components<I, S>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ComponentId>,
+    {
+        self.include_components
+            .get_or_insert_with(BTreeSet::new)
+            .extend(components.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn without_components<I, S>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ComponentId>,
+    {
+        self.exclude_components.extend(components.into_iter().map(Into::into));
+        self
+    }
+
+    // Keeps only entities carrying at least one of these tags.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include_tags.get_or_insert_with(BTreeSet::new).extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn without_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude_tags.extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(EntityId, Option<&EntityMetadata>) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    pub fn is_unfiltered(&self) -> bool {
+        self.include_components.is_none()
+            && self.exclude_components.is_empty()
+            && !self.filters_entities()
+    }
+
+    pub fn filters_entities(&self) -> bool {
+        self.include_tags.is_some() || !self.exclude_tags.is_empty() || self.predicate.is_some()
+    }
+
+    pub fn component_matches(&self, component_id: &ComponentId) -> bool {
+        self.include_components.as_ref().is_none_or(|components| components.contains(component_id))
+            && !self.exclude_components.contains(component_id)
+    }
+
+    pub fn entity_matches(&self, entity_id: EntityId, metadata: Option<&EntityMetadata>) -> bool {
+        let tags = metadata.map_or(&[][..], |metadata| metadata.tags.as_slice());
+
+        self.include_tags.as_ref().is_none_or(|include| tags.iter().any(|tag| include.contains(tag)))
+            && !tags.iter().any(|tag| self.exclude_tags.contains(tag))
+            && self.predicate.as_ref().is_none_or(|predicate| predicate(entity_id, metadata))
+    }
+
+    // Filters an existing capture in place. Blob archetypes holding world
+    // components are filtered row by row; other blobs are opaque, so they are
+    // kept whole as long as any of their entities match.
+    pub fn apply(&self, snapshot: &mut PackedSnapshot) {
+        if self.is_unfiltered() {
+            return;
+        }
+
+        snapshot.archetypes.retain(|archetype| self.component_matches(&archetype.component_id));
+
+        if self.filters_entities() {
+            let metadata = &snapshot.entity_metadata;
+            let keep = |id: EntityId| self.entity_matches(id, metadata.get(&id));
+
+            for archetype in &mut snapshot.archetypes {
+                match &archetype.data {
+                    ComponentData::StructOfArrays(_) => archetype.retain_entities(keep),
+                    ComponentData::Blob(_) => match archetype.world_components() {
+                        Ok(components) if components.len() == archetype.entity_ids.len() => {
+                            let (entity_ids, components): (Vec<_>, Vec<_>) = archetype
+                                .entity_ids
+                                .iter()
+                                .copied()
+                                .zip(components)
+                                .filter(|(id, _)| keep(*id))
+                                .unzip();
+                            archetype.data = ComponentData::Blob(
+                                bincode::serialize(&components).expect("world components serialize into an in-memory buffer"),
+                            );
+                            archetype.entity_ids = entity_ids;
+                        }
+                        _ => {
+                            if !archetype.entity_ids.iter().any(|id| keep(*id)) {
+                                archetype.entity_ids.clear();
+                                archetype.data = ComponentData::Blob(Vec::new());
+                            }
+                        }
+                    },
+                }
+            }
+            snapshot.archetypes.retain(|archetype| !archetype.is_empty());
+        }
+
+        let entities: BTreeSet<EntityId> =
+            snapshot.archetypes.iter().flat_map(|a| a.entity_ids.iter().copied()).collect();
+        snapshot.entity_metadata.retain(|id, _| entities.contains(id));
+        snapshot.header.entity_count = entities.len() as u64;
+        snapshot.header.component_count = snapshot.archetypes.len() as u64;
+        snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+        snapshot.rebuild_entity_index();
+    }
+}
+
+impl fmt::Debug for CaptureOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureOptions")
+            .field("include_components", &self.include_components)
+            .field("exclude_components", &self.exclude_components)
+            .field("include_tags", &self.include_tags)
+            .field("exclude_tags", &self.exclude_tags)
+            .field("predicate", &self.predicate.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::WorldCapture;
+    use crate::error::Result;
+    use crate::format::{ComponentArchetype, FieldArray, FieldType, StructOfArraysData};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    struct World {
+        captured: Mutex<Vec<ComponentId>>,
+    }
+
+    impl WorldCapture for World {
+        fn component_ids(&self) -> Result<Vec<ComponentId>> {
+            Ok(["Position", "RenderCache", "Particle"].map(String::from).to_vec())
+        }
+
+        fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+            self.captured.lock().unwrap().push(component_id.clone());
+            Ok(Some(ComponentArchetype {
+                component_id: component_id.clone(),
+                entity_ids: vec![1, 2, 3],
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["x".to_string()],
+                    field_types: vec![FieldType::F32],
+                    field_data: vec![FieldArray::F32(vec![1.0, 2.0, 3.0].into())],
+                }),
+            }))
+        }
+
+        fn capture_entity_metadata(&self) -> Result<HashMap<EntityId, EntityMetadata>> {
+            let tagged = |tag: &str| EntityMetadata { tags: vec![tag.to_string()], ..EntityMetadata::new() };
+            Ok(HashMap::from([(1, tagged("gameplay")), (2, tagged("transient"))]))
+        }
+    }
+
+    #[test]
+    fn test_capture_skips_excluded_components_and_entities() {
+        let world = World { captured: Mutex::new(Vec::new()) };
+
+        let options = CaptureOptions::new().without_components(["RenderCache"]).without_tags(["transient"]);
+        let snapshot = world.capture_with(&options).unwrap();
+        assert_eq!(*world.captured.lock().unwrap(), vec!["Position".to_string(), "Particle".to_string()]);
+        assert_eq!(snapshot.archetypes.len(), 2);
+        assert_eq!(snapshot.archetypes[0].entity_ids, vec![1, 3]);
+        assert_eq!(snapshot.header.entity_count, 2);
+        assert_eq!(snapshot.entity_metadata.keys().copied().collect::<Vec<_>>(), vec![1]);
+
+        let options = CaptureOptions::new()
+            .with_components(["Position", "Particle"])
+            .with_tags(["gameplay"])
+            .with_predicate(|id, _| id != 3);
+        let snapshot = world.capture_with(&options).unwrap();
+        assert_eq!(snapshot.archetypes.iter().map(|a| a.entity_ids.clone()).collect::<Vec<_>>(), vec![vec![1], vec![1]]);
+
+        let options = CaptureOptions::new().with_components(["Particle"]).with_predicate(|id, _| id == 2);
+        let mut snapshot = PackedSnapshot::from_world_snapshot(tx2_link::WorldSnapshot {
+            entities: (1..=3)
+                .map(|id| tx2_link::SerializedEntity {
+                    id,
+                    components: vec![tx2_link::SerializedComponent { id: "Particle".to_string(), data: vec![id as u8] }],
+                })
+                .collect(),
+            timestamp: 0.0,
+            version: 0,
+        });
+        options.apply(&mut snapshot);
+        let components = snapshot.archetypes[0].world_components().unwrap();
+        assert_eq!((snapshot.archetypes[0].entity_ids.clone(), components[0].data.clone()), (vec![2], vec![2]));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::error::Result;
+use crate::metadata::SnapshotMetadata;
+use crate::search::SearchIndex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const CATALOG_FILE: &str = "catalog.json";
+/// Changes appended since catalog.json and the search index were last written.
+pub const CATALOG_LOG_FILE: &str = "catalog.log";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub metadata: SnapshotMetadata,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Catalog {
+    entries: BTreeMap<String, CatalogEntry>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_json_atomic(path.as_ref(), self)
+    }
+
+    pub fn insert(&mut self, entry: CatalogEntry) {
+        self.entries.insert(entry.metadata.id.clone(), entry);
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<CatalogEntry> {
+        self.entries.remove(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CatalogEntry> {
+        self.entries.get(id)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &CatalogEntry> {
+        self.entries.values()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|id| id.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum CatalogChange {
+    Put(Box<CatalogEntry>),
+    Remove(String),
+}
+
+impl CatalogChange {
+    pub(crate) fn apply(&self, catalog: &mut Catalog, index: &mut SearchIndex) {
+        match self {
+            CatalogChange::Put(entry) => {
+                index.index(&entry.metadata);
+                catalog.insert(entry.as_ref().clone());
+            }
+            CatalogChange::Remove(id) => {
+                catalog.remove(id);
+                index.remove(id);
+            }
+        }
+    }
+}
+
+// A line cut short by a crash mid-append never made it into the catalog and
+// is skipped; the next append starts on a fresh line after it.
+pub(crate) fn read_catalog_log(path: &Path) -> Result<Vec<CatalogChange>> {
+    let log = match std::fs::read_to_string(path) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(log.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+// Returns the size of the log after the append.
+pub(crate) fn append_catalog_log(path: &Path, change: &CatalogChange) -> Result<u64> {
+    let mut log = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+    let len = log.metadata()?.len();
+    let mut line = Vec::new();
+    if len > 0 {
+        let mut last = [0u8];
+        log.seek(SeekFrom::Start(len - 1))?;
+        log.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            line.push(b'\n');
+        }
+    }
+    serde_json::to_writer(&mut line, change)?;
+    line.push(b'\n');
+    log.write_all(&line)?;
+    Ok(len + line.len() as u64)
+}
+
+pub(crate) fn write_json_atomic<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let json = serde_json::to_vec(value)?;
+    let tmp_path = path.with_extension("json.tmp");
+
+    std::fs::write(&tmp_path, json)?;
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
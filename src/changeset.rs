@@ -0,0 +1,251 @@
+use crate::diff::DiffOptions;
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, PackedSnapshot};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentChanges {
+    pub added: BTreeSet<EntityId>,
+    pub removed: BTreeSet<EntityId>,
+    pub modified: BTreeSet<EntityId>,
+    pub blob_changed: bool,
+}
+
+impl ComponentChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty() && !self.blob_changed
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub spawned: BTreeSet<EntityId>,
+    pub despawned: BTreeSet<EntityId>,
+    // Entities that died in the window, despawned or not: a world that hands
+    // a dead entity's id to a new one lists it here and reports the new
+    // entity's components as added. Comparing snapshots cannot see that, so
+    // compute only ever finds the despawned ones.
+    #[serde(default)]
+    pub tombstones: BTreeSet<EntityId>,
+    pub components: BTreeMap<ComponentId, ComponentChanges>,
+}
+
+impl ChangeSet {
+    pub fn compute(old: &PackedSnapshot, new: &PackedSnapshot) -> Self {
+        Self::compute_with_options(old, new, &DiffOptions::default())
+    }
+
+    pub fn compute_with_options(old: &PackedSnapshot, new: &PackedSnapshot, options: &DiffOptions) -> Self {
+        let mut changes = ChangeSet::default();
+
+        let old_archetypes = by_component(old);
+        let new_archetypes = by_component(new);
+        let component_ids: BTreeSet<&ComponentId> = old_archetypes.keys().chain(new_archetypes.keys()).copied().collect();
+
+        for component_id in component_ids {
+            let component = match (old_archetypes.get(component_id), new_archetypes.get(component_id)) {
+                (Some(a), Some(b)) => compare_archetypes(a, b, options),
+                (Some(a), None) => ComponentChanges {
+                    removed: a.entity_ids.iter().copied().collect(),
+                    blob_changed: has_blob_data(a),
+                    ..ComponentChanges::default()
+                },
+                (None, Some(b)) => ComponentChanges {
+                    added: b.entity_ids.iter().copied().collect(),
+                    blob_changed: has_blob_data(b),
+                    ..ComponentChanges::default()
+                },
+                (None, None) => continue,
+            };
+
+            if !component.is_empty() {
+                changes.components.insert(component_id.clone(), component);
+            }
+        }
+
+        let old_entities = entity_set(old);
+        let new_entities = entity_set(new);
+        changes.spawned = new_entities.difference(&old_entities).copied().collect();
+        changes.despawned = old_entities.difference(&new_entities).copied().collect();
+        changes.tombstones = changes.despawned.clone();
+
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spawned.is_empty() && self.despawned.is_empty() && self.tombstones.is_empty() && self.components.is_empty()
+    }
+
+    pub fn component(&self, component_id: &str) -> Option<&ComponentChanges> {
+        self.components.get(component_id)
+    }
+
+    pub fn changed_components(&self) -> impl Iterator<Item = &ComponentId> {
+        self.components.keys()
+    }
+
+    pub fn modified_entities(&self) -> BTreeSet<EntityId> {
+        self.components
+            .values()
+            .flat_map(|c| c.added.iter().chain(&c.removed).chain(&c.modified))
+            .copied()
+            .collect()
+    }
+}
+
+fn compare_archetypes(old: &ComponentArchetype, new: &ComponentArchetype, options: &DiffOptions) -> ComponentChanges {
+    let mut changes = ComponentChanges::default();
+
+    match (&old.data, &new.data) {
+        (ComponentData::StructOfArrays(a), ComponentData::StructOfArrays(b))
+            if a.field_names == b.field_names && a.field_types == b.field_types =>
+        {
+            if old.entity_ids == new.entity_ids {
+                let changed_columns: Vec<usize> = (0..a.field_data.len())
+                    .filter(|&i| column_hash(&a.field_data[i]) != column_hash(&b.field_data[i]))
+                    .collect();
+
+                for row in 0..old.entity_ids.len() {
+                    let modified = changed_columns.iter().any(|&i| {
+                        match (a.field_data[i].get(row), b.field_data[i].get(row)) {
+                            (Some(x), Some(y)) => !options.values_equal(&old.component_id, &a.field_names[i], &x, &y),
+                            _ => true,
+                        }
+                    });
+                    if modified {
+                        changes.modified.insert(old.entity_ids[row]);
+                    }
+                }
+
+                return changes;
+            }
+
+            let new_rows: HashMap<EntityId, usize> = new.entity_ids.iter().enumerate().map(|(row, id)| (*id, row)).collect();
+            for (old_row, entity_id) in old.entity_ids.iter().enumerate() {
+                match new_rows.get(entity_id) {
+                    Some(&new_row) => {
+                        let modified = a.field_names.iter().zip(a.field_data.iter().zip(&b.field_data)).any(|(field, (x, y))| {
+                            match (x.get(old_row), y.get(new_row)) {
+                                (Some(x), Some(y)) => !options.values_equal(&old.component_id, field, &x, &y),
+                                _ => true,
+                            }
+                        });
+                        if modified {
+                            changes.modified.insert(*entity_id);
+                        }
+                    }
+                    None => {
+                        changes.removed.insert(*entity_id);
+                    }
+                }
+            }
+        }
+        (ComponentData::Blob(a), ComponentData::Blob(b)) => {
+            changes.blob_changed = a != b;
+            let old_ids: BTreeSet<EntityId> = old.entity_ids.iter().copied().collect();
+            let new_ids: BTreeSet<EntityId> = new.entity_ids.iter().copied().collect();
+            changes.removed = old_ids.difference(&new_ids).copied().collect();
+            changes.added = new_ids.difference(&old_ids).copied().collect();
+            return changes;
+        }
+        _ => {
+            changes.modified = old.entity_ids.iter().filter(|id| new.entity_ids.contains(id)).copied().collect();
+            changes.removed = old.entity_ids.iter().filter(|id| !new.entity_ids.contains(id)).copied().collect();
+            changes.blob_changed = has_blob_data(old) || has_blob_data(new);
+        }
+    }
+
+    let old_ids: BTreeSet<EntityId> = old.entity_ids.iter().copied().collect();
+    changes.added = new.entity_ids.iter().filter(|id| !old_ids.contains(id)).copied().collect();
+
+    changes
+}
+
+fn column_hash(column: &FieldArray) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    match column {
+        FieldArray::Bool(v) => v.hash(&mut hasher),
+        FieldArray::I8(v) => v.hash(&mut hasher),
+        FieldArray::I16(v) => v.hash(&mut hasher),
+        FieldArray::I32(v) => v.hash(&mut hasher),
+        FieldArray::I64(v) => v.hash(&mut hasher),
+        FieldArray::U8(v) => v.hash(&mut hasher),
+        FieldArray::U16(v) => v.hash(&mut hasher),
+        FieldArray::U32(v) => v.hash(&mut hasher),
+        FieldArray::U64(v) => v.hash(&mut hasher),
+        FieldArray::F32(v) => v.iter().for_each(|x| x.to_bits().hash(&mut hasher)),
+        FieldArray::F64(v) => v.iter().for_each(|x| x.to_bits().hash(&mut hasher)),
+        FieldArray::String(v) => v.hash(&mut hasher),
+        FieldArray::Bytes(v) => v.hash(&mut hasher),
+    }
+
+    hasher.finish()
+}
+
+fn has_blob_data(archetype: &ComponentArchetype) -> bool {
+    matches!(&archetype.data, ComponentData::Blob(data) if !data.is_empty())
+}
+
+fn by_component(snapshot: &PackedSnapshot) -> HashMap<&ComponentId, &ComponentArchetype> {
+    let mut map = HashMap::new();
+    for archetype in &snapshot.archetypes {
+        map.entry(&archetype.component_id).or_insert(archetype);
+    }
+    map
+}
+
+fn entity_set(snapshot: &PackedSnapshot) -> BTreeSet<EntityId> {
+    snapshot
+        .archetypes
+        .iter()
+        .flat_map(|a| a.entity_ids.iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::diff;
+    use crate::format::{FieldType, StructOfArraysData};
+
+    fn archetype(component: &str, rows: &[(EntityId, f64)]) -> ComponentArchetype {
+        ComponentArchetype {
+            component_id: component.to_string(),
+            entity_ids: rows.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["v".to_string()],
+                field_types: vec![FieldType::F64],
+                field_data: vec![FieldArray::F64(rows.iter().map(|(_, v)| *v).collect())],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_change_set_matches_diff() {
+        let mut old = PackedSnapshot::new();
+        old.archetypes.push(archetype("Position", &[(1, 0.0), (2, 1.0), (3, 2.0)]));
+        old.archetypes.push(archetype("Health", &[(1, 100.0), (2, 100.0)]));
+
+        let mut new = PackedSnapshot::new();
+        new.archetypes.push(archetype("Position", &[(1, 0.0), (2, 1.5), (3, 2.0)]));
+        new.archetypes.push(archetype("Health", &[(2, 100.0), (4, 50.0)]));
+
+        let changes = ChangeSet::compute(&old, &new);
+
+        assert_eq!(changes.spawned, BTreeSet::from([4]));
+        assert!(changes.despawned.is_empty());
+        assert_eq!(changes.component("Position").unwrap().modified, BTreeSet::from([2]));
+
+        let health = changes.component("Health").unwrap();
+        assert_eq!(health.added, BTreeSet::from([4]));
+        assert_eq!(health.removed, BTreeSet::from([1]));
+        assert!(health.modified.is_empty());
+
+        assert_eq!(changes.modified_entities(), diff(&old, &new).changed_entities());
+        assert!(ChangeSet::compute(&new, &new).is_empty());
+    }
+}
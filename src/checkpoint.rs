@@ -1,7 +1,9 @@
+use crate::delta::DeltaSnapshot;
 use crate::error::Result;
 use crate::format::PackedSnapshot;
-use crate::metadata::SnapshotMetadata;
+use crate::metadata::{Provenance, SnapshotMetadata};
 use crate::storage::{SnapshotWriter, SnapshotReader, SnapshotStore};
+use std::collections::HashSet;
 use std::path::Path;
 use ahash::AHashMap;
 
@@ -26,11 +28,13 @@ impl Checkpoint {
     }
 
     pub fn with_parent(mut self, parent_id: String) -> Self {
+        self.metadata.provenance.parent_id = Some(parent_id.clone());
         self.parent_id = Some(parent_id);
         self
     }
 
     pub fn with_metadata(mut self, metadata: SnapshotMetadata) -> Self {
+        self.parent_id = metadata.provenance.parent_id.clone();
         self.metadata = metadata;
         self
     }
@@ -42,6 +46,9 @@ pub struct CheckpointManager {
     reader: SnapshotReader,
     checkpoints: AHashMap<String, Checkpoint>,
     checkpoint_chain: Vec<String>,
+    provenance: Provenance,
+    keyframe_interval: usize,
+    since_keyframe: usize,
 }
 
 impl CheckpointManager {
@@ -56,9 +63,36 @@ impl CheckpointManager {
             reader,
             checkpoints: AHashMap::new(),
             checkpoint_chain: Vec::new(),
+            provenance: Provenance::default(),
+            keyframe_interval: 1,
+            since_keyframe: 0,
         })
     }
 
+    // Opens a store another manager wrote to, such as a standby after a
+    // failover, and carries on the chain that ends at its newest checkpoint.
+    // Checkpoints pruned before the store was opened are left out.
+    pub fn resume<P: AsRef<Path>>(root_dir: P) -> Result<Self> {
+        let mut manager = Self::new(root_dir)?;
+        let catalog = manager.store.catalog()?;
+        let parents: HashSet<&str> =
+            catalog.entries().filter_map(|entry| entry.metadata.provenance.parent_id.as_deref()).collect();
+        let latest = catalog
+            .entries()
+            .filter(|entry| !parents.contains(entry.metadata.id.as_str()))
+            .max_by(|a, b| (a.metadata.created_at, &a.metadata.id).cmp(&(b.metadata.created_at, &b.metadata.id)));
+
+        if let Some(latest) = latest {
+            let mut chain = manager.store.lineage(&latest.metadata.id)?;
+            chain.retain(|id| manager.store.exists(id));
+            chain.reverse();
+            manager.since_keyframe = chain.iter().rev().take_while(|id| manager.store.is_delta(id)).count();
+            manager.checkpoint_chain = chain;
+        }
+
+        Ok(manager)
+    }
+
     pub fn with_writer(mut self, writer: SnapshotWriter) -> Self {
         self.writer = writer;
         self
@@ -69,6 +103,17 @@ impl CheckpointManager {
         self
     }
 
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    pub fn with_keyframe_interval(mut self, interval: usize) -> Self {
+        self.keyframe_interval = interval.max(1);
+        self
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(id = %id)))]
     pub fn create_checkpoint(
         &mut self,
         id: String,
@@ -76,12 +121,31 @@ impl CheckpointManager {
     ) -> Result<()> {
         let parent_id = self.checkpoint_chain.last().cloned();
 
-        let mut checkpoint = Checkpoint::new(id.clone(), snapshot);
+        let mut provenance = self.provenance.clone();
+        if provenance.hostname.is_none() {
+            provenance.hostname = Provenance::current_hostname();
+        }
+
+        let metadata = SnapshotMetadata::new(id.clone()).with_provenance(provenance);
+        let mut checkpoint = Checkpoint::new(id.clone(), snapshot).with_metadata(metadata);
         if let Some(parent) = parent_id {
             checkpoint = checkpoint.with_parent(parent);
         }
 
-        self.store.save(&checkpoint.snapshot, &checkpoint.metadata, &self.writer)?;
+        let base = match &checkpoint.parent_id {
+            Some(parent) if self.since_keyframe + 1 < self.keyframe_interval => Some(parent.clone()),
+            _ => None,
+        };
+
+        if let Some(base_id) = base {
+            let base_snapshot = self.load_checkpoint(&base_id)?.snapshot;
+            let delta = DeltaSnapshot::from_snapshots(&base_snapshot, &checkpoint.snapshot)?.with_base_id(base_id);
+            self.store.save_delta(&delta, &checkpoint.metadata, &self.writer)?;
+            self.since_keyframe += 1;
+        } else {
+            self.store.save(&checkpoint.snapshot, &checkpoint.metadata, &self.writer)?;
+            self.since_keyframe = 0;
+        }
 
         self.checkpoint_chain.push(id.clone());
         self.checkpoints.insert(id, checkpoint);
@@ -89,6 +153,7 @@ impl CheckpointManager {
         Ok(())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(id)))]
     pub fn load_checkpoint(&mut self, id: &str) -> Result<Checkpoint> {
         if let Some(checkpoint) = self.checkpoints.get(id) {
             return Ok(checkpoint.clone());
@@ -99,8 +164,8 @@ impl CheckpointManager {
         let checkpoint = Checkpoint {
             id: id.to_string(),
             snapshot,
+            parent_id: metadata.provenance.parent_id.clone(),
             metadata,
-            parent_id: None,
         };
 
         self.checkpoints.insert(id.to_string(), checkpoint.clone());
@@ -109,12 +174,20 @@ impl CheckpointManager {
     }
 
     pub fn delete_checkpoint(&mut self, id: &str) -> Result<()> {
+        for dependent in self.store.delta_dependents(id)? {
+            self.store.materialize(&dependent, &self.reader, &self.writer)?;
+        }
+
         self.store.delete(id)?;
         self.checkpoints.remove(id);
         self.checkpoint_chain.retain(|cid| cid != id);
         Ok(())
     }
 
+    pub fn store(&self) -> &SnapshotStore {
+        &self.store
+    }
+
     pub fn list_checkpoints(&self) -> Result<Vec<String>> {
         self.store.list()
     }
@@ -127,6 +200,16 @@ impl CheckpointManager {
         self.checkpoint_chain.last().map(|s| s.as_str())
     }
 
+    pub fn lineage(&self, id: &str) -> Result<Vec<String>> {
+        self.store.lineage(id)
+    }
+
+    pub fn origin(&self, id: &str) -> Result<String> {
+        let lineage = self.lineage(id)?;
+        Ok(lineage.last().cloned().unwrap_or_else(|| id.to_string()))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn prune_old_checkpoints(&mut self, keep_count: usize) -> Result<()> {
         let chain_len = self.checkpoint_chain.len();
 
@@ -181,6 +264,74 @@ mod tests {
         assert_eq!(manager.get_latest_checkpoint(), Some("cp2"));
     }
 
+    #[test]
+    fn test_checkpoint_provenance() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut manager = CheckpointManager::new(temp_dir.path()).unwrap().with_provenance(
+            Provenance::new()
+                .with_build("1.4.2".to_string())
+                .with_commit("abc123".to_string())
+                .with_seed(42),
+        );
+
+        for i in 0..3 {
+            manager.create_checkpoint(format!("cp{}", i), PackedSnapshot::new()).unwrap();
+        }
+
+        let mut reopened = CheckpointManager::new(temp_dir.path()).unwrap();
+        let loaded = reopened.load_checkpoint("cp2").unwrap();
+        assert_eq!(loaded.parent_id.as_deref(), Some("cp1"));
+        assert_eq!(loaded.metadata.provenance.build.as_deref(), Some("1.4.2"));
+        assert_eq!(loaded.metadata.provenance.commit.as_deref(), Some("abc123"));
+        assert_eq!(loaded.metadata.provenance.seed, Some(42));
+
+        assert_eq!(reopened.lineage("cp2").unwrap(), vec!["cp2", "cp1", "cp0"]);
+        assert_eq!(reopened.origin("cp2").unwrap(), "cp0");
+        assert_eq!(reopened.store.children("cp0").unwrap(), vec!["cp1"]);
+    }
+
+    #[test]
+    fn test_checkpoint_delta_storage() {
+        use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, StructOfArraysData};
+
+        fn frame(step: u32) -> PackedSnapshot {
+            let mut snapshot = PackedSnapshot::new();
+            snapshot.archetypes.push(ComponentArchetype {
+                component_id: "Counter".to_string(),
+                entity_ids: (0..100).collect(),
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["value".to_string()],
+                    field_types: vec![FieldType::U32],
+                    field_data: vec![FieldArray::U32((0..100).map(|i| i + step).collect())],
+                }),
+            });
+            snapshot.rebuild_entity_index();
+            snapshot
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut manager = CheckpointManager::new(temp_dir.path()).unwrap().with_keyframe_interval(3);
+
+        for step in 0..5 {
+            manager.create_checkpoint(format!("cp{}", step), frame(step)).unwrap();
+        }
+
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let deltas: Vec<bool> = (0..5).map(|i| store.is_delta(&format!("cp{}", i))).collect();
+        assert_eq!(deltas, vec![false, true, true, false, true]);
+
+        let mut reopened = CheckpointManager::new(temp_dir.path()).unwrap();
+        let loaded = reopened.load_checkpoint("cp2").unwrap();
+        assert_eq!(loaded.snapshot.content_checksum().unwrap(), frame(2).content_checksum().unwrap());
+        assert_eq!(reopened.list_checkpoints().unwrap().len(), 5);
+
+        manager.prune_old_checkpoints(3).unwrap();
+        assert!(!store.is_delta("cp2"));
+        let (snapshot, metadata) = store.load("cp2", &SnapshotReader::new()).unwrap();
+        assert_eq!(snapshot.content_checksum().unwrap(), frame(2).content_checksum().unwrap());
+        assert_eq!(metadata.delta_base, None);
+    }
+
     #[test]
     fn test_checkpoint_clear() {
         let temp_dir = TempDir::new().unwrap();
@@ -0,0 +1,128 @@
+use crate::error::{PackError, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+    Xxh3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn digest(self, data: &[u8]) -> [u8; 32] {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    pub fn hasher(self) -> ChecksumHasher {
+        match self {
+            ChecksumAlgorithm::Sha256 => ChecksumHasher::Sha256(Box::default()),
+            ChecksumAlgorithm::Blake3 => ChecksumHasher::Blake3(Box::default()),
+            ChecksumAlgorithm::Xxh3 => ChecksumHasher::Xxh3(Box::default()),
+        }
+    }
+
+    pub fn verify(self, data: &[u8], expected: &[u8; 32]) -> Result<()> {
+        if self.digest(data) != *expected {
+            return Err(PackError::ChecksumMismatch);
+        }
+
+        Ok(())
+    }
+}
+
+pub enum ChecksumHasher {
+    Sha256(Box<Sha256>),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl ChecksumHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumHasher::Sha256(hasher) => hasher.update(data),
+            ChecksumHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            ChecksumHasher::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        match self {
+            ChecksumHasher::Sha256(hasher) => hasher.finalize().into(),
+            ChecksumHasher::Blake3(hasher) => hasher.finalize().into(),
+            ChecksumHasher::Xxh3(hasher) => {
+                let mut digest = [0u8; 32];
+                digest[..16].copy_from_slice(&hasher.digest128().to_le_bytes());
+                digest
+            }
+        }
+    }
+}
+
+pub(crate) const CRC32C_SIZE: usize = 4;
+
+pub(crate) fn append_crc32c(sealed: &mut Vec<u8>) {
+    let crc = crc32c::crc32c(sealed);
+    sealed.extend_from_slice(&crc.to_le_bytes());
+}
+
+pub(crate) fn strip_crc32c(sealed: &[u8]) -> Result<&[u8]> {
+    let (body, crc) = split_crc32c(sealed)?;
+
+    if crc32c::crc32c(body) != u32::from_le_bytes(*crc) {
+        return Err(PackError::ChunkChecksumMismatch { chunk: 0 });
+    }
+
+    Ok(body)
+}
+
+pub(crate) fn skip_crc32c(sealed: &[u8]) -> Result<&[u8]> {
+    Ok(split_crc32c(sealed)?.0)
+}
+
+fn split_crc32c(sealed: &[u8]) -> Result<(&[u8], &[u8; CRC32C_SIZE])> {
+    sealed
+        .split_last_chunk::<CRC32C_SIZE>()
+        .ok_or_else(|| PackError::InvalidFormat("Truncated chunk checksum".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_algorithms_detect_corruption() {
+        let data = b"tx2pack checksum payload".to_vec();
+        let mut corrupt = data.clone();
+        corrupt[3] ^= 1;
+
+        for algorithm in [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Xxh3] {
+            let digest = algorithm.digest(&data);
+            assert!(algorithm.verify(&data, &digest).is_ok());
+            assert!(matches!(algorithm.verify(&corrupt, &digest), Err(PackError::ChecksumMismatch)));
+        }
+
+        assert_ne!(ChecksumAlgorithm::Sha256.digest(&data), ChecksumAlgorithm::Blake3.digest(&data));
+        assert_eq!(ChecksumAlgorithm::Xxh3.digest(&data)[..16], xxhash_rust::xxh3::xxh3_128(&data).to_le_bytes());
+    }
+
+    #[test]
+    fn test_writer_records_algorithm() {
+        use crate::format::PackedSnapshot;
+        use crate::storage::{SnapshotReader, SnapshotWriter};
+
+        let writer = SnapshotWriter::new().with_checksum(ChecksumAlgorithm::Xxh3);
+        let mut bytes = writer.write_to_bytes(&PackedSnapshot::new()).unwrap();
+        assert!(SnapshotReader::new().read_from_bytes(&bytes).is_ok());
+
+        let last = bytes.len() - crate::format::HEADER_SIZE as usize - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(SnapshotReader::new().read_from_bytes(&bytes).map_err(PackError::into_root), Err(PackError::ChecksumMismatch)));
+    }
+}
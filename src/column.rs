@@ -0,0 +1,174 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+pub struct Column<T>(Arc<Vec<T>>);
+
+impl<T> Column<T> {
+    pub fn new() -> Self {
+        Self(Arc::new(Vec::new()))
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.0
+    }
+
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.0) > 1
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T: Clone> Column<T> {
+    pub fn make_mut(&mut self) -> &mut Vec<T> {
+        Arc::make_mut(&mut self.0)
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| (*shared).clone())
+    }
+}
+
+impl<T> Clone for Column<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> Default for Column<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Deref for Column<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T: Clone> DerefMut for Column<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        self.make_mut()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Column<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Column<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || self.0 == other.0
+    }
+}
+
+impl<T> From<Vec<T>> for Column<T> {
+    fn from(values: Vec<T>) -> Self {
+        Self(Arc::new(values))
+    }
+}
+
+impl<T: Clone> From<Column<T>> for Vec<T> {
+    fn from(column: Column<T>) -> Self {
+        column.into_vec()
+    }
+}
+
+impl<T> FromIterator<T> for Column<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<Vec<T>>())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Column<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<T: Serialize> Serialize for Column<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Column<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(feature = "rkyv")]
+mod archived {
+    use super::Column;
+    use rkyv::rancor::{Fallible, Source};
+    use rkyv::ser::{Allocator, Writer};
+    use rkyv::vec::{ArchivedVec, VecResolver};
+    use rkyv::{Archive, Deserialize, Place, Serialize};
+
+    impl<T: Archive> Archive for Column<T> {
+        type Archived = ArchivedVec<T::Archived>;
+        type Resolver = VecResolver;
+
+        fn resolve(&self, resolver: Self::Resolver, out: Place<Self::Archived>) {
+            ArchivedVec::resolve_from_slice(self.as_slice(), resolver, out);
+        }
+    }
+
+    impl<T, S> Serialize<S> for Column<T>
+    where
+        T: Serialize<S>,
+        S: Fallible + Allocator + Writer + ?Sized,
+    {
+        fn serialize(&self, serializer: &mut S) -> Result<Self::Resolver, S::Error> {
+            ArchivedVec::serialize_from_slice(self.as_slice(), serializer)
+        }
+    }
+
+    impl<T, D> Deserialize<Column<T>, D> for ArchivedVec<T::Archived>
+    where
+        T: Archive,
+        ArchivedVec<T::Archived>: Deserialize<Vec<T>, D>,
+        D: Fallible + ?Sized,
+        D::Error: Source,
+    {
+        fn deserialize(&self, deserializer: &mut D) -> Result<Column<T>, D::Error> {
+            Deserialize::<Vec<T>, D>::deserialize(self, deserializer).map(Column::from)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_until_written() {
+        let original: Column<f32> = vec![1.0, 2.0, 3.0].into();
+        let mut fork = original.clone();
+        assert!(fork.ptr_eq(&original));
+        assert!(original.is_shared());
+
+        fork.push(4.0);
+        assert!(!fork.ptr_eq(&original));
+        assert_eq!(original.as_slice(), [1.0, 2.0, 3.0]);
+        assert_eq!(fork.as_slice(), [1.0, 2.0, 3.0, 4.0]);
+        assert!(!original.is_shared());
+
+        let bytes = bincode::serialize(&fork).unwrap();
+        assert_eq!(bytes, bincode::serialize(&vec![1.0f32, 2.0, 3.0, 4.0]).unwrap());
+        assert_eq!(bincode::deserialize::<Column<f32>>(&bytes).unwrap(), fork);
+    }
+}
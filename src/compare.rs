@@ -0,0 +1,213 @@
+use crate::error::Result;
+use crate::format::PackedSnapshot;
+use crate::metadata::SnapshotMetadata;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentSizeDelta {
+    pub component_id: String,
+    pub entities_a: u64,
+    pub entities_b: u64,
+    pub bytes_a: u64,
+    pub bytes_b: u64,
+}
+
+impl ComponentSizeDelta {
+    pub fn entity_delta(&self) -> i64 {
+        self.entities_b as i64 - self.entities_a as i64
+    }
+
+    pub fn byte_delta(&self) -> i64 {
+        self.bytes_b as i64 - self.bytes_a as i64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataChange {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonReport {
+    pub id_a: String,
+    pub id_b: String,
+    pub entity_count_a: u64,
+    pub entity_count_b: u64,
+    pub file_size_a: u64,
+    pub file_size_b: u64,
+    pub components: Vec<ComponentSizeDelta>,
+    pub metadata_changes: Vec<MetadataChange>,
+}
+
+impl ComparisonReport {
+    pub fn compare(
+        (snapshot_a, metadata_a): (&PackedSnapshot, &SnapshotMetadata),
+        (snapshot_b, metadata_b): (&PackedSnapshot, &SnapshotMetadata),
+    ) -> Result<Self> {
+        let sizes_a = component_sizes(snapshot_a)?;
+        let sizes_b = component_sizes(snapshot_b)?;
+
+        let component_ids: BTreeSet<&String> = sizes_a.keys().chain(sizes_b.keys()).collect();
+        let components = component_ids
+            .into_iter()
+            .map(|id| {
+                let (entities_a, bytes_a) = sizes_a.get(id).copied().unwrap_or_default();
+                let (entities_b, bytes_b) = sizes_b.get(id).copied().unwrap_or_default();
+                ComponentSizeDelta {
+                    component_id: id.clone(),
+                    entities_a,
+                    entities_b,
+                    bytes_a,
+                    bytes_b,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            id_a: metadata_a.id.clone(),
+            id_b: metadata_b.id.clone(),
+            entity_count_a: entity_count(snapshot_a),
+            entity_count_b: entity_count(snapshot_b),
+            file_size_a: 0,
+            file_size_b: 0,
+            components,
+            metadata_changes: metadata_changes(metadata_a, metadata_b),
+        })
+    }
+
+    pub fn entity_delta(&self) -> i64 {
+        self.entity_count_b as i64 - self.entity_count_a as i64
+    }
+
+    pub fn file_size_delta(&self) -> i64 {
+        self.file_size_b as i64 - self.file_size_a as i64
+    }
+
+    pub fn file_size_growth(&self) -> f64 {
+        if self.file_size_a == 0 {
+            return if self.file_size_b == 0 { 0.0 } else { f64::INFINITY };
+        }
+
+        self.file_size_delta() as f64 / self.file_size_a as f64
+    }
+
+    pub fn exceeds_growth(&self, max_growth: f64) -> bool {
+        self.file_size_growth() > max_growth
+    }
+
+    pub fn changed_components(&self) -> impl Iterator<Item = &ComponentSizeDelta> {
+        self.components
+            .iter()
+            .filter(|c| c.entity_delta() != 0 || c.byte_delta() != 0)
+    }
+}
+
+fn entity_count(snapshot: &PackedSnapshot) -> u64 {
+    snapshot
+        .archetypes
+        .iter()
+        .flat_map(|a| a.entity_ids.iter())
+        .collect::<HashSet<_>>()
+        .len() as u64
+}
+
+fn component_sizes(snapshot: &PackedSnapshot) -> Result<BTreeMap<String, (u64, u64)>> {
+    let mut sizes = BTreeMap::new();
+
+    for archetype in &snapshot.archetypes {
+        let bytes = bincode::serialized_size(archetype)?;
+        let entry: &mut (u64, u64) = sizes.entry(archetype.component_id.clone()).or_default();
+        entry.0 += archetype.entity_ids.len() as u64;
+        entry.1 += bytes;
+    }
+
+    Ok(sizes)
+}
+
+fn metadata_changes(a: &SnapshotMetadata, b: &SnapshotMetadata) -> Vec<MetadataChange> {
+    let mut changes = Vec::new();
+
+    let mut check = |field: String, before: Option<String>, after: Option<String>| {
+        if before != after {
+            changes.push(MetadataChange { field, before, after });
+        }
+    };
+
+    check("name".to_string(), a.name.clone(), b.name.clone());
+    check("description".to_string(), a.description.clone(), b.description.clone());
+    check("world_time".to_string(), Some(a.world_time.to_string()), Some(b.world_time.to_string()));
+    check(
+        "schema_version".to_string(),
+        Some(a.schema_version.to_string()),
+        Some(b.schema_version.to_string()),
+    );
+    check("tags".to_string(), Some(a.tags.join(",")), Some(b.tags.join(",")));
+
+    let keys: BTreeSet<&String> = a.custom_fields.keys().chain(b.custom_fields.keys()).collect();
+    for key in keys {
+        check(
+            format!("custom_fields.{}", key),
+            a.custom_fields.get(key).cloned(),
+            b.custom_fields.get(key).cloned(),
+        );
+    }
+
+    let keys: BTreeSet<&String> = a.labels.keys().chain(b.labels.keys()).collect();
+    for key in keys {
+        check(format!("labels.{}", key), a.labels.get(key).cloned(), b.labels.get(key).cloned());
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData};
+    use crate::storage::{SnapshotReader, SnapshotStore, SnapshotWriter};
+
+    fn snapshot_with_blob(component: &str, entities: u32, blob_len: usize) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: component.to_string(),
+            entity_ids: (0..entities).collect(),
+            data: ComponentData::Blob(vec![7u8; blob_len]),
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_compare_snapshots_in_store() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let writer = SnapshotWriter::new();
+
+        let meta_a = SnapshotMetadata::new("build-1".to_string())
+            .with_custom_field("build".to_string(), "1".to_string());
+        let meta_b = SnapshotMetadata::new("build-2".to_string())
+            .with_custom_field("build".to_string(), "2".to_string());
+
+        store.save(&snapshot_with_blob("Inventory", 10, 100), &meta_a, &writer).unwrap();
+        store.save(&snapshot_with_blob("Inventory", 15, 400), &meta_b, &writer).unwrap();
+
+        let report = store.compare("build-1", "build-2", &SnapshotReader::new()).unwrap();
+
+        assert_eq!(report.entity_delta(), 5);
+        assert_eq!(report.components.len(), 1);
+        assert_eq!(report.components[0].entity_delta(), 5);
+        assert!(report.components[0].byte_delta() > 300);
+        assert!(report.file_size_a > 0 && report.file_size_b > 0);
+        assert_eq!(
+            report.metadata_changes,
+            vec![MetadataChange {
+                field: "custom_fields.build".to_string(),
+                before: Some("1".to_string()),
+                after: Some("2".to_string()),
+            }]
+        );
+        assert!(!report.exceeds_growth(10.0));
+    }
+}
@@ -0,0 +1,216 @@
+use crate::delta::push_entry;
+use crate::diff::{ComponentEntry, DiffFilter};
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, PackedSnapshot, StructOfArraysData};
+use crate::remap::EntityIdMap;
+use std::collections::{BTreeSet, HashMap};
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RemapStrategy {
+    Preserve,
+    #[default]
+    OnCollision,
+    Sequential(EntityId),
+}
+
+#[derive(Debug, Clone)]
+pub struct Composition {
+    pub snapshot: PackedSnapshot,
+    pub id_maps: Vec<EntityIdMap>,
+}
+
+pub fn compose(sources: &[(&PackedSnapshot, DiffFilter)]) -> Result<Composition> {
+    compose_with(sources, RemapStrategy::default())
+}
+
+pub fn compose_with(sources: &[(&PackedSnapshot, DiffFilter)], strategy: RemapStrategy) -> Result<Composition> {
+    let mut snapshot = PackedSnapshot::new();
+    let mut used: BTreeSet<EntityId> = BTreeSet::new();
+    let mut blob_owners: HashMap<ComponentId, usize> = HashMap::new();
+    let mut next_sequential = match strategy {
+        RemapStrategy::Sequential(start) => start,
+        _ => 0,
+    };
+    let mut id_maps = Vec::with_capacity(sources.len());
+
+    for (source_index, (source, filter)) in sources.iter().enumerate() {
+        let archetypes: Vec<&ComponentArchetype> = source
+            .archetypes
+            .iter()
+            .filter(|a| filter.component_matches(&a.component_id))
+            .collect();
+
+        let selected: BTreeSet<EntityId> = archetypes
+            .iter()
+            .flat_map(|a| a.entity_ids.iter().copied())
+            .filter(|id| filter.entity_matches(*id))
+            .collect();
+
+        let mut id_map = EntityIdMap::new();
+        for entity_id in selected {
+            let new_id = match strategy {
+                RemapStrategy::Preserve if used.contains(&entity_id) => {
+                    return Err(PackError::InvalidFormat(format!(
+                        "Entity {} from source {} collides with an earlier source", entity_id, source_index
+                    )));
+                }
+                RemapStrategy::Preserve => entity_id,
+                RemapStrategy::OnCollision if !used.contains(&entity_id) => entity_id,
+                RemapStrategy::OnCollision => used.iter().next_back().map_or(0, |max| max + 1),
+                RemapStrategy::Sequential(_) => {
+                    while used.contains(&next_sequential) {
+                        next_sequential += 1;
+                    }
+                    next_sequential
+                }
+            };
+
+            used.insert(new_id);
+            id_map.insert(entity_id, new_id);
+        }
+
+        for archetype in archetypes {
+            let index = match snapshot.archetypes.iter().position(|a| a.component_id == archetype.component_id) {
+                Some(index) => index,
+                None => {
+                    snapshot.archetypes.push(ComponentArchetype {
+                        component_id: archetype.component_id.clone(),
+                        entity_ids: Vec::new(),
+                        data: empty_like(&archetype.data),
+                    });
+                    snapshot.archetypes.len() - 1
+                }
+            };
+            let target = &mut snapshot.archetypes[index];
+
+            if let ComponentData::Blob(data) = &archetype.data {
+                match blob_owners.get(&archetype.component_id) {
+                    Some(owner) if *owner != source_index => {
+                        return Err(PackError::InvalidFormat(format!(
+                            "Blob component {} cannot be combined from multiple sources", archetype.component_id
+                        )));
+                    }
+                    Some(_) => {}
+                    None => {
+                        blob_owners.insert(archetype.component_id.clone(), source_index);
+                        if let ComponentData::Blob(target_data) = &mut target.data {
+                            target_data.clone_from(data);
+                        }
+                    }
+                }
+            }
+
+            for (row, entity_id) in archetype.entity_ids.iter().enumerate() {
+                if !filter.entity_matches(*entity_id) {
+                    continue;
+                }
+
+                let entry = ComponentEntry {
+                    entity_id: id_map.map(*entity_id),
+                    component_id: archetype.component_id.clone(),
+                    values: archetype.row_values(row),
+                };
+                push_entry(target, &entry).map_err(|_| {
+                    PackError::InvalidFormat(format!(
+                        "Component {} has incompatible layouts across sources", archetype.component_id
+                    ))
+                })?;
+            }
+        }
+
+        for (entity_id, metadata) in &source.entity_metadata {
+            if let Some(new_id) = id_map.get(*entity_id) {
+                snapshot.entity_metadata.insert(new_id, metadata.clone());
+            }
+        }
+
+        id_maps.push(id_map);
+    }
+
+    snapshot.archetypes.retain(|archetype| !archetype.is_empty());
+    snapshot.rebuild_entity_index();
+    snapshot.header.entity_count = used.len() as u64;
+    snapshot.header.component_count = snapshot.archetypes.len() as u64;
+    snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+
+    Ok(Composition { snapshot, id_maps })
+}
+
+fn empty_like(data: &ComponentData) -> ComponentData {
+    match data {
+        ComponentData::StructOfArrays(soa) => ComponentData::StructOfArrays(StructOfArraysData {
+            field_names: soa.field_names.clone(),
+            field_types: soa.field_types.clone(),
+            field_data: soa.field_types.iter().map(|t| FieldArray::with_type(*t)).collect(),
+        }),
+        ComponentData::Blob(_) => ComponentData::Blob(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{FieldType, FieldValue};
+
+    fn world(component: &str, rows: &[(EntityId, i32)]) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: component.to_string(),
+            entity_ids: rows.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["v".to_string()],
+                field_types: vec![FieldType::I32],
+                field_data: vec![FieldArray::I32(rows.iter().map(|(_, v)| *v).collect())],
+            }),
+        });
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Debug".to_string(),
+            entity_ids: rows.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::Blob(vec![0xde, 0xad]),
+        });
+        snapshot.rebuild_entity_index();
+        snapshot
+    }
+
+    #[test]
+    fn test_compose_remaps_collisions() {
+        let production = world("Enemy", &[(1, 10), (2, 20), (3, 30)]);
+        let mut level = world("Enemy", &[(1, 99), (7, 70)]);
+        level.annotate(1, "boss", FieldValue::Bool(true));
+
+        let composition = compose(&[
+            (&production, DiffFilter::new().with_components(["Enemy"]).with_entities([2, 3])),
+            (&level, DiffFilter::new().with_components(["Enemy"])),
+        ])
+        .unwrap();
+
+        let enemies = &composition.snapshot.archetypes[0];
+        assert_eq!(enemies.entity_ids, vec![2, 3, 1, 7]);
+        assert_eq!(composition.snapshot.archetypes.len(), 1);
+        assert!(composition.id_maps[1].is_identity());
+
+        let clash = compose(&[
+            (&production, DiffFilter::new().with_components(["Enemy"])),
+            (&level, DiffFilter::new().with_components(["Enemy"])),
+        ])
+        .unwrap();
+        let remapped = clash.id_maps[1].map(1);
+        assert_eq!(remapped, 4);
+        assert_eq!(clash.snapshot.annotation(remapped, "boss"), Some(&FieldValue::Bool(true)));
+        assert_eq!(clash.snapshot.entity_components(remapped).len(), 1);
+
+        let preserve = compose_with(
+            &[(&production, DiffFilter::new()), (&level, DiffFilter::new().with_components(["Enemy"]))],
+            RemapStrategy::Preserve,
+        );
+        assert!(preserve.is_err());
+
+        let blobs = compose(&[(&production, DiffFilter::new()), (&level, DiffFilter::new())]);
+        assert!(blobs.is_err());
+
+        let sequential = compose_with(&[(&level, DiffFilter::new())], RemapStrategy::Sequential(1000)).unwrap();
+        assert_eq!(sequential.snapshot.archetypes[0].entity_ids, vec![1000, 1001]);
+        assert!(matches!(&sequential.snapshot.archetypes[1].data, ComponentData::Blob(data) if *data == [0xde, 0xad]));
+    }
+}
@@ -1,5 +1,13 @@
 use crate::error::{PackError, Result};
 use crate::format::CompressionType;
+use std::io::{self, Read, Write};
+
+const ZSTD_BLOCK_SIZE: usize = 128 * 1024;
+const LZ4_BLOCK_SIZE: usize = 64 * 1024;
+const LZ4_FRAME_OVERHEAD: usize = 19 + 4 + 4;
+
+/// Largest output `decompress` will produce.
+pub const DEFAULT_MAX_DECOMPRESSED: u64 = 100 * 1024 * 1024;
 
 #[derive(Debug, Clone, Copy)]
 pub enum CompressionCodec {
@@ -30,6 +38,16 @@ impl CompressionCodec {
     }
 }
 
+impl Default for CompressionCodec {
+    fn default() -> Self {
+        if cfg!(feature = "compression") {
+            CompressionCodec::zstd_default()
+        } else {
+            CompressionCodec::None
+        }
+    }
+}
+
 impl From<CompressionCodec> for CompressionType {
     fn from(codec: CompressionCodec) -> Self {
         match codec {
@@ -43,48 +61,274 @@ impl From<CompressionCodec> for CompressionType {
 pub fn compress(data: &[u8], codec: CompressionCodec) -> Result<Vec<u8>> {
     match codec {
         CompressionCodec::None => Ok(data.to_vec()),
+        CompressionCodec::Zstd(level) => zstd_compress(data, level),
+        CompressionCodec::Lz4 => lz4_compress(data),
+    }
+}
 
-        CompressionCodec::Zstd(level) => {
-            zstd::bulk::compress(data, level)
-                .map_err(|e| PackError::Compression(e.to_string()))
-        }
+/// Fails with `PackError::LimitExceeded` past `DEFAULT_MAX_DECOMPRESSED`;
+/// `decompress_limited` takes another cap.
+pub fn decompress(data: &[u8], compression_type: CompressionType) -> Result<Vec<u8>> {
+    match compression_type {
+        CompressionType::None => Ok(data.to_vec()),
+        _ => decompress_limited(data, compression_type, DEFAULT_MAX_DECOMPRESSED),
+    }
+}
 
-        CompressionCodec::Lz4 => {
-            let mut encoder = lz4::EncoderBuilder::new()
-                .level(4)
-                .build(Vec::new())
-                .map_err(|e| PackError::Compression(e.to_string()))?;
+/// Decompresses with no cap on the output, so only use it on trusted input.
+pub fn decompress_unbounded(data: &[u8], compression_type: CompressionType) -> Result<Vec<u8>> {
+    match compression_type {
+        CompressionType::None => Ok(data.to_vec()),
+        CompressionType::Zstd => zstd_decompress(data),
+        CompressionType::Lz4 => lz4_decompress(data),
+    }
+}
 
-            std::io::copy(&mut &data[..], &mut encoder)
-                .map_err(|e| PackError::Compression(e.to_string()))?;
+pub fn compress_bound(len: usize, codec: CompressionCodec) -> usize {
+    match codec {
+        CompressionCodec::None => len,
+        CompressionCodec::Zstd(_) => {
+            len + (len >> 8) + ZSTD_BLOCK_SIZE.saturating_sub(len) / 2048
+        }
+        CompressionCodec::Lz4 => {
+            // Mirrors LZ4F_compressFrameBound: the encoder may hold back up to
+            // one block of input before emitting it.
+            let max_src = len + LZ4_BLOCK_SIZE - 1;
+            max_src + 4 * max_src.div_ceil(LZ4_BLOCK_SIZE) + LZ4_FRAME_OVERHEAD
+        }
+    }
+}
 
-            let (compressed, result) = encoder.finish();
-            result.map_err(|e| PackError::Compression(e.to_string()))?;
+pub fn compress_into(data: &[u8], codec: CompressionCodec, out: &mut [u8]) -> Result<usize> {
+    let capacity = out.len();
+    let mut cursor = io::Cursor::new(out);
 
-            Ok(compressed)
+    match encode_to(data, codec, &mut cursor) {
+        Ok(()) => Ok(cursor.position() as usize),
+        Err(e) if e.kind() == io::ErrorKind::WriteZero => {
+            let mut counter = ByteCounter(0);
+            encode_to(data, codec, &mut counter).map_err(|e| PackError::Compression(e.to_string()))?;
+            Err(PackError::BufferTooSmall { required: counter.0, capacity })
         }
+        Err(e) => Err(PackError::Compression(e.to_string())),
     }
 }
 
-pub fn decompress(data: &[u8], compression_type: CompressionType) -> Result<Vec<u8>> {
-    match compression_type {
-        CompressionType::None => Ok(data.to_vec()),
+pub fn decompress_limited(data: &[u8], compression_type: CompressionType, max: u64) -> Result<Vec<u8>> {
+    let decoder = decoder(data, compression_type).map_err(|e| PackError::Decompression(e.to_string()))?;
+
+    let mut decompressed = Vec::new();
+    decoder
+        .take(max.saturating_add(1))
+        .read_to_end(&mut decompressed)
+        .map_err(|e| PackError::Decompression(e.to_string()))?;
+
+    if decompressed.len() as u64 > max {
+        return Err(PackError::LimitExceeded {
+            limit: "decompressed bytes",
+            max,
+        });
+    }
+
+    Ok(decompressed)
+}
+
+pub fn decompress_into(data: &[u8], compression_type: CompressionType, out: &mut [u8]) -> Result<usize> {
+    let mut decoder = decoder(data, compression_type).map_err(|e| PackError::Decompression(e.to_string()))?;
+    let mut filled = 0;
 
-        CompressionType::Zstd => {
-            zstd::bulk::decompress(data, 100 * 1024 * 1024)
-                .map_err(|e| PackError::Decompression(e.to_string()))
+    while filled < out.len() {
+        match decoder.read(&mut out[filled..]) {
+            Ok(0) => return Ok(filled),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(PackError::Decompression(e.to_string())),
         }
+    }
+
+    let remaining = io::copy(&mut decoder, &mut io::sink())
+        .map_err(|e| PackError::Decompression(e.to_string()))? as usize;
 
-        CompressionType::Lz4 => {
-            let mut decoder = lz4::Decoder::new(data)
-                .map_err(|e| PackError::Decompression(e.to_string()))?;
+    if remaining == 0 {
+        Ok(filled)
+    } else {
+        Err(PackError::BufferTooSmall {
+            required: filled + remaining,
+            capacity: out.len(),
+        })
+    }
+}
 
-            let mut decompressed = Vec::new();
-            std::io::copy(&mut decoder, &mut decompressed)
-                .map_err(|e| PackError::Decompression(e.to_string()))?;
+struct ByteCounter(usize);
 
-            Ok(decompressed)
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn encode_to<W: Write>(data: &[u8], codec: CompressionCodec, mut out: W) -> io::Result<()> {
+    match codec {
+        CompressionCodec::None => out.write_all(data),
+        #[cfg(feature = "compression")]
+        CompressionCodec::Zstd(level) => zstd::stream::copy_encode(data, out, level),
+        #[cfg(feature = "compression")]
+        CompressionCodec::Lz4 => {
+            let mut encoder = lz4::EncoderBuilder::new().level(4).build(out)?;
+            encoder.write_all(data)?;
+            encoder.finish().1
         }
+        #[cfg(all(not(feature = "compression"), feature = "pure-decompression"))]
+        CompressionCodec::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(out);
+            encoder.write_all(data)?;
+            encoder.finish().map(|_| ()).map_err(io::Error::other)
+        }
+        #[cfg(not(feature = "compression"))]
+        codec => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{:?} compression requires the compression feature", codec),
+        )),
+    }
+}
+
+fn decoder(data: &[u8], compression_type: CompressionType) -> io::Result<Box<dyn Read + '_>> {
+    match compression_type {
+        CompressionType::None => Ok(Box::new(data)),
+        #[cfg(feature = "compression")]
+        CompressionType::Zstd => Ok(Box::new(zstd::stream::Decoder::new(data)?)),
+        #[cfg(feature = "compression")]
+        CompressionType::Lz4 => Ok(Box::new(lz4::Decoder::new(data)?)),
+        #[cfg(all(not(feature = "compression"), feature = "pure-decompression"))]
+        CompressionType::Zstd => ruzstd::decoding::StreamingDecoder::new(data)
+            .map(|decoder| Box::new(decoder) as Box<dyn Read>)
+            .map_err(io::Error::other),
+        #[cfg(all(not(feature = "compression"), feature = "pure-decompression"))]
+        CompressionType::Lz4 => Ok(Box::new(lz4_flex::frame::FrameDecoder::new(data))),
+        #[cfg(not(any(feature = "compression", feature = "pure-decompression")))]
+        compression_type => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Snapshot is {:?} compressed but no decompression feature is enabled", compression_type),
+        )),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn zstd_compress(data: &[u8], level: i32) -> Result<Vec<u8>> {
+    zstd::stream::encode_all(data, level)
+        .map_err(|e| PackError::Compression(e.to_string()))
+}
+
+#[cfg(not(feature = "compression"))]
+fn zstd_compress(_data: &[u8], _level: i32) -> Result<Vec<u8>> {
+    Err(PackError::Compression("Zstd compression requires the compression feature".to_string()))
+}
+
+#[cfg(feature = "compression")]
+fn zstd_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    zstd::stream::decode_all(data)
+        .map_err(|e| PackError::Decompression(e.to_string()))
+}
+
+#[cfg(all(not(feature = "compression"), feature = "pure-decompression"))]
+fn zstd_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    pure::zstd_decompress(data)
+}
+
+#[cfg(not(any(feature = "compression", feature = "pure-decompression")))]
+fn zstd_decompress(_data: &[u8]) -> Result<Vec<u8>> {
+    Err(PackError::Decompression(
+        "Snapshot is Zstd compressed but no decompression feature is enabled".to_string()
+    ))
+}
+
+#[cfg(feature = "compression")]
+fn lz4_compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = lz4::EncoderBuilder::new()
+        .level(4)
+        .build(Vec::new())
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+
+    std::io::copy(&mut &data[..], &mut encoder)
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+
+    let (compressed, result) = encoder.finish();
+    result.map_err(|e| PackError::Compression(e.to_string()))?;
+
+    Ok(compressed)
+}
+
+#[cfg(all(not(feature = "compression"), feature = "pure-decompression"))]
+fn lz4_compress(data: &[u8]) -> Result<Vec<u8>> {
+    pure::lz4_compress(data)
+}
+
+#[cfg(not(any(feature = "compression", feature = "pure-decompression")))]
+fn lz4_compress(_data: &[u8]) -> Result<Vec<u8>> {
+    Err(PackError::Compression("LZ4 compression requires the compression feature".to_string()))
+}
+
+#[cfg(feature = "compression")]
+fn lz4_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = lz4::Decoder::new(data)
+        .map_err(|e| PackError::Decompression(e.to_string()))?;
+
+    let mut decompressed = Vec::new();
+    std::io::copy(&mut decoder, &mut decompressed)
+        .map_err(|e| PackError::Decompression(e.to_string()))?;
+
+    Ok(decompressed)
+}
+
+#[cfg(all(not(feature = "compression"), feature = "pure-decompression"))]
+fn lz4_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    pure::lz4_decompress(data)
+}
+
+#[cfg(not(any(feature = "compression", feature = "pure-decompression")))]
+fn lz4_decompress(_data: &[u8]) -> Result<Vec<u8>> {
+    Err(PackError::Decompression(
+        "Snapshot is LZ4 compressed but no decompression feature is enabled".to_string()
+    ))
+}
+
+#[cfg(feature = "pure-decompression")]
+#[cfg_attr(feature = "compression", allow(dead_code))]
+mod pure {
+    use crate::error::{PackError, Result};
+    use std::io::{Read, Write};
+
+    pub(super) fn zstd_decompress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut decoder = ruzstd::decoding::StreamingDecoder::new(data)
+            .map_err(|e| PackError::Decompression(e.to_string()))?;
+
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed)
+            .map_err(|e| PackError::Decompression(e.to_string()))?;
+
+        Ok(decompressed)
+    }
+
+    pub(super) fn lz4_compress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        encoder.write_all(data)
+            .map_err(|e| PackError::Compression(e.to_string()))?;
+
+        encoder.finish()
+            .map_err(|e| PackError::Compression(e.to_string()))
+    }
+
+    pub(super) fn lz4_decompress(data: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        lz4_flex::frame::FrameDecoder::new(data).read_to_end(&mut decompressed)
+            .map_err(|e| PackError::Decompression(e.to_string()))?;
+
+        Ok(decompressed)
     }
 }
 
@@ -92,6 +336,7 @@ pub fn decompress(data: &[u8], compression_type: CompressionType) -> Result<Vec<
 mod tests {
     use super::*;
 
+    #[cfg(feature = "compression")]
     #[test]
     fn test_zstd_compression() {
         let data = b"Hello, World! This is a test of ZSTD compression.".repeat(100);
@@ -103,6 +348,7 @@ mod tests {
         assert_eq!(data, decompressed);
     }
 
+    #[cfg(feature = "compression")]
     #[test]
     fn test_lz4_compression() {
         let data = b"Hello, World! This is a test of LZ4 compression.".repeat(100);
@@ -114,6 +360,42 @@ mod tests {
         assert_eq!(data, decompressed);
     }
 
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compress_into_caller_buffer() {
+        let mut state = 0x2545_f491u32;
+        let noise: Vec<u8> = (0..200_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+
+        for codec in [CompressionCodec::None, CompressionCodec::zstd_default(), CompressionCodec::Lz4] {
+            let mut compressed = vec![0u8; compress_bound(noise.len(), codec)];
+            let written = compress_into(&noise, codec, &mut compressed).unwrap();
+
+            let mut decompressed = vec![0u8; noise.len()];
+            let read = decompress_into(&compressed[..written], codec.into(), &mut decompressed).unwrap();
+            assert_eq!(read, noise.len());
+            assert_eq!(decompressed, noise);
+
+            match decompress_into(&compressed[..written], codec.into(), &mut decompressed[..100]) {
+                Err(PackError::BufferTooSmall { required, capacity }) => {
+                    assert_eq!((required, capacity), (noise.len(), 100));
+                }
+                other => panic!("expected BufferTooSmall, got {:?}", other),
+            }
+
+            match compress_into(&noise, codec, &mut compressed[..16]) {
+                Err(PackError::BufferTooSmall { required, .. }) => assert_eq!(required, written),
+                other => panic!("expected BufferTooSmall, got {:?}", other),
+            }
+        }
+    }
+
     #[test]
     fn test_no_compression() {
         let data = b"Hello, World!";
@@ -124,4 +406,32 @@ mod tests {
         let decompressed = decompress(&compressed, CompressionType::None).unwrap();
         assert_eq!(data, decompressed.as_slice());
     }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_decompress_is_capped_by_default() {
+        let data = vec![0u8; DEFAULT_MAX_DECOMPRESSED as usize + 1];
+
+        for codec in [CompressionCodec::zstd_fast(), CompressionCodec::Lz4] {
+            let compressed = compress(&data, codec).unwrap();
+            assert!(matches!(
+                decompress(&compressed, codec.into()),
+                Err(PackError::LimitExceeded { max: DEFAULT_MAX_DECOMPRESSED, .. })
+            ));
+            assert_eq!(decompress_unbounded(&compressed, codec.into()).unwrap().len(), data.len());
+        }
+    }
+
+    #[cfg(all(feature = "compression", feature = "pure-decompression"))]
+    #[test]
+    fn test_pure_decompression_reads_native_output() {
+        let data = b"Snapshots written natively must open in the browser.".repeat(100);
+
+        let zstd = compress(&data, CompressionCodec::zstd_default()).unwrap();
+        assert_eq!(pure::zstd_decompress(&zstd).unwrap(), data);
+
+        let lz4 = compress(&data, CompressionCodec::Lz4).unwrap();
+        assert_eq!(pure::lz4_decompress(&lz4).unwrap(), data);
+        assert_eq!(decompress(&pure::lz4_compress(&data).unwrap(), CompressionType::Lz4).unwrap(), data);
+    }
 }
@@ -0,0 +1,438 @@
+use crate::arrow::{data_type, to_array, ENTITY_ID_COLUMN};
+use crate::error::{PackError, Result};
+use crate::format::ComponentData;
+use crate::storage::{SnapshotReader, SnapshotStore};
+use ::datafusion::catalog::Session;
+use ::datafusion::datasource::{TableProvider, TableType};
+use ::datafusion::error::{DataFusionError, Result as DataFusionResult};
+use ::datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use ::datafusion::logical_expr::expr::InList;
+use ::datafusion::logical_expr::{BinaryExpr, Expr, Operator, TableProviderFilterPushDown};
+use ::datafusion::physical_plan::empty::EmptyExec;
+use ::datafusion::physical_plan::stream::RecordBatchReceiverStream;
+use ::datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use ::datafusion::physical_plan::ExecutionPlan;
+use ::datafusion::prelude::SessionContext;
+use ::datafusion::sql::TableReference;
+use ::datafusion::scalar::ScalarValue;
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use async_trait::async_trait;
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use tx2_link::ComponentId;
+
+pub const SNAPSHOT_ID_COLUMN: &str = "snapshot_id";
+
+pub struct ComponentTable {
+    store: Arc<SnapshotStore>,
+    reader: Arc<SnapshotReader>,
+    component_id: ComponentId,
+    snapshot_ids: Vec<String>,
+    schema: SchemaRef,
+}
+
+impl ComponentTable {
+    pub fn component_id(&self) -> &ComponentId {
+        &self.component_id
+    }
+
+    pub fn snapshot_ids(&self) -> &[String] {
+        &self.snapshot_ids
+    }
+
+    fn selected_ids(&self, filters: &[Expr]) -> Vec<&String> {
+        let filters: Vec<SnapshotFilter> = filters.iter().filter_map(SnapshotFilter::from_expr).collect();
+
+        self.snapshot_ids
+            .iter()
+            .filter(|id| filters.iter().all(|filter| filter.matches(id)))
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for ComponentTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentTable")
+            .field("component_id", &self.component_id)
+            .field("snapshots", &self.snapshot_ids.len())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl TableProvider for ComponentTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn supports_filters_pushdown(&self, filters: &[&Expr]) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|filter| match SnapshotFilter::from_expr(filter) {
+                Some(_) => TableProviderFilterPushDown::Exact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(projection) => Arc::new(self.schema.project(projection)?),
+            None => self.schema.clone(),
+        };
+
+        let partitions: Vec<Arc<dyn PartitionStream>> = self
+            .selected_ids(filters)
+            .into_iter()
+            .map(|snapshot_id| {
+                Arc::new(ComponentPartition {
+                    store: self.store.clone(),
+                    reader: self.reader.clone(),
+                    component_id: self.component_id.clone(),
+                    snapshot_id: snapshot_id.clone(),
+                    schema: schema.clone(),
+                }) as Arc<dyn PartitionStream>
+            })
+            .collect();
+
+        if partitions.is_empty() {
+            return Ok(Arc::new(EmptyExec::new(schema)));
+        }
+        Ok(Arc::new(StreamingTableExec::try_new(schema, partitions, None, Vec::new(), false, limit)?))
+    }
+}
+
+// One snapshot's rows of a component, holding only the projected columns.
+#[derive(Clone)]
+struct ComponentPartition {
+    store: Arc<SnapshotStore>,
+    reader: Arc<SnapshotReader>,
+    component_id: ComponentId,
+    snapshot_id: String,
+    schema: SchemaRef,
+}
+
+impl ComponentPartition {
+    fn read_batch(&self) -> Result<Option<RecordBatch>> {
+        let snapshot = self.store.load_lazy(&self.snapshot_id, &self.reader)?;
+        let archetype = match snapshot.archetype(&self.component_id)? {
+            Some(archetype) => archetype,
+            None => return Ok(None),
+        };
+        let soa = match &archetype.data {
+            ComponentData::StructOfArrays(soa) => soa,
+            ComponentData::Blob(_) => return Err(self.mismatch("is stored as a blob")),
+        };
+
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| match field.name().as_str() {
+                SNAPSHOT_ID_COLUMN => {
+                    Ok(Arc::new(StringArray::from(vec![self.snapshot_id.as_str(); archetype.entity_ids.len()])) as ArrayRef)
+                }
+                ENTITY_ID_COLUMN => Ok(Arc::new(UInt64Array::from_iter_values(
+                    archetype.entity_ids.iter().map(|id| u64::from(*id)),
+                )) as ArrayRef),
+                name => soa
+                    .field_names
+                    .iter()
+                    .position(|n| n == name)
+                    .filter(|&index| data_type(soa.field_types[index]) == *field.data_type())
+                    .map(|index| to_array(&soa.field_data[index]))
+                    .ok_or_else(|| self.mismatch(&format!("has no {} field of type {}", name, field.data_type()))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let options = RecordBatchOptions::new().with_row_count(Some(archetype.entity_ids.len()));
+        RecordBatch::try_new_with_options(self.schema.clone(), columns, &options)
+            .map(Some)
+            .map_err(|e| PackError::Query(e.to_string()))
+    }
+
+    fn mismatch(&self, problem: &str) -> PackError {
+        PackError::Query(format!(
+            "Component {} in snapshot {} {}", self.component_id, self.snapshot_id, problem
+        ))
+    }
+}
+
+impl std::fmt::Debug for ComponentPartition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentPartition")
+            .field("component_id", &self.component_id)
+            .field("snapshot_id", &self.snapshot_id)
+            .finish()
+    }
+}
+
+impl PartitionStream for ComponentPartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let mut builder = RecordBatchReceiverStream::builder(self.schema.clone(), 1);
+        let tx = builder.tx();
+        let partition = self.clone();
+
+        // Store reads block, so they stay off the runtime's worker threads.
+        builder.spawn_blocking(move || {
+            if let Some(batch) = partition.read_batch().map_err(to_datafusion)? {
+                // The receiver is gone once a limit has been reached.
+                let _ = tx.blocking_send(Ok(batch));
+            }
+            Ok(())
+        });
+        builder.build()
+    }
+}
+
+pub fn component_tables(store: Arc<SnapshotStore>, reader: SnapshotReader) -> Result<Vec<ComponentTable>> {
+    let reader = Arc::new(reader);
+    let mut ids = store.list()?;
+    ids.sort();
+
+    // None marks blob components, which have no columns to query.
+    let mut components: BTreeMap<ComponentId, Option<(SchemaRef, Vec<String>)>> = BTreeMap::new();
+    for id in ids {
+        let snapshot = store.load_lazy(&id, &reader)?;
+        for component_id in snapshot.component_ids() {
+            if let Some(table) = components.get_mut(component_id) {
+                if let Some((_, snapshot_ids)) = table {
+                    snapshot_ids.push(id.clone());
+                }
+                continue;
+            }
+
+            // The table of contents has no field types, so the first snapshot
+            // holding a component decodes that one section for its schema.
+            let schema = match snapshot.archetype(component_id)?.map(|archetype| &archetype.data) {
+                Some(ComponentData::StructOfArrays(soa)) => {
+                    let mut fields = vec![
+                        Field::new(SNAPSHOT_ID_COLUMN, DataType::Utf8, false),
+                        Field::new(ENTITY_ID_COLUMN, DataType::UInt64, false),
+                    ];
+                    fields.extend(
+                        soa.field_names
+                            .iter()
+                            .zip(&soa.field_types)
+                            .map(|(name, field_type)| Field::new(name, data_type(*field_type), false)),
+                    );
+                    Some((Arc::new(Schema::new(fields)), vec![id.clone()]))
+                }
+                _ => None,
+            };
+            components.insert(component_id.clone(), schema);
+        }
+    }
+
+    Ok(components
+        .into_iter()
+        .filter_map(|(component_id, table)| table.map(|table| (component_id, table)))
+        .map(|(component_id, (schema, snapshot_ids))| ComponentTable {
+            store: store.clone(),
+            reader: reader.clone(),
+            component_id,
+            snapshot_ids,
+            schema,
+        })
+        .collect())
+}
+
+pub fn register_store(ctx: &SessionContext, store: Arc<SnapshotStore>, reader: SnapshotReader) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+
+    for table in component_tables(store, reader)? {
+        let name = table.component_id.clone();
+        // Bare, so component ids are not parsed or lowercased as SQL names.
+        ctx.register_table(TableReference::bare(name.as_str()), Arc::new(table)).map_err(to_pack)?;
+        names.push(name);
+    }
+
+    Ok(names)
+}
+
+// Filters on snapshot_id decide which snapshots are opened at all.
+#[derive(Debug, PartialEq)]
+enum SnapshotFilter {
+    In(BTreeSet<String>),
+    Compare(Operator, String),
+}
+
+impl SnapshotFilter {
+    fn from_expr(expr: &Expr) -> Option<Self> {
+        match expr {
+            Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+                let (op, value) = match (left.as_ref(), right.as_ref()) {
+                    (Expr::Column(column), Expr::Literal(value)) if column.name == SNAPSHOT_ID_COLUMN => (*op, value),
+                    (Expr::Literal(value), Expr::Column(column)) if column.name == SNAPSHOT_ID_COLUMN => (op.swap()?, value),
+                    _ => return None,
+                };
+                let id = literal_string(value)?;
+                match op {
+                    Operator::Eq => Some(Self::In(BTreeSet::from([id]))),
+                    Operator::NotEq | Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq => {
+                        Some(Self::Compare(op, id))
+                    }
+                    _ => None,
+                }
+            }
+            Expr::InList(InList { expr, list, negated: false }) => match expr.as_ref() {
+                Expr::Column(column) if column.name == SNAPSHOT_ID_COLUMN => list
+                    .iter()
+                    .map(|item| match item {
+                        Expr::Literal(value) => literal_string(value),
+                        _ => None,
+                    })
+                    .collect::<Option<_>>()
+                    .map(Self::In),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn matches(&self, id: &str) -> bool {
+        match self {
+            Self::In(ids) => ids.contains(id),
+            Self::Compare(op, value) => match op {
+                Operator::NotEq => id != value,
+                Operator::Lt => id < value.as_str(),
+                Operator::LtEq => id <= value.as_str(),
+                Operator::Gt => id > value.as_str(),
+                _ => id >= value.as_str(),
+            },
+        }
+    }
+}
+
+fn literal_string(value: &ScalarValue) -> Option<String> {
+    match value {
+        ScalarValue::Utf8(Some(s)) | ScalarValue::LargeUtf8(Some(s)) | ScalarValue::Utf8View(Some(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn to_datafusion(error: PackError) -> DataFusionError {
+    DataFusionError::External(Box::new(error))
+}
+
+fn to_pack(error: DataFusionError) -> PackError {
+    PackError::Query(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, FieldArray, FieldType, PackFormat, PackedSnapshot, StructOfArraysData};
+    use crate::metadata::SnapshotMetadata;
+    use crate::storage::SnapshotWriter;
+    use arrow_array::{Array, Float32Array, Int64Array};
+    use tempfile::TempDir;
+
+    fn snapshot_with_positions(xs: Vec<f32>) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: (0..xs.len() as u32).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string()],
+                field_types: vec![FieldType::F32],
+                field_data: vec![FieldArray::F32(xs.into())],
+            }),
+        });
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Sprite".to_string(),
+            entity_ids: vec![0],
+            data: ComponentData::Blob(vec![1, 2, 3]),
+        });
+        snapshot.rebuild_entity_index();
+        snapshot
+    }
+
+    fn store_with_frames(temp_dir: &TempDir) -> Arc<SnapshotStore> {
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let writer = SnapshotWriter::new();
+        store.save(&snapshot_with_positions(vec![1.0, 2.0]), &SnapshotMetadata::new("frame_1".to_string()), &writer).unwrap();
+        store.save(&snapshot_with_positions(vec![10.0]), &SnapshotMetadata::new("frame_2".to_string()), &writer).unwrap();
+        store.save(&snapshot_with_positions(vec![20.0, 30.0]), &SnapshotMetadata::new("frame_3".to_string()), &writer).unwrap();
+        Arc::new(store)
+    }
+
+    async fn query(ctx: &SessionContext, sql: &str) -> Vec<RecordBatch> {
+        ctx.sql(sql).await.unwrap().collect().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sql_over_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let ctx = SessionContext::new();
+        let tables = register_store(&ctx, store_with_frames(&temp_dir), SnapshotReader::new()).unwrap();
+        assert_eq!(tables, vec!["Position"]);
+
+        let batches = query(&ctx, "SELECT SUM(x) AS total FROM \"Position\" WHERE snapshot_id = 'frame_1'").await;
+        let total = batches[0].column(0).as_any().downcast_ref::<arrow_array::Float64Array>().unwrap();
+        assert_eq!(total.value(0), 3.0);
+
+        let batches = query(&ctx, "SELECT x FROM \"Position\" WHERE x > 5 AND x < 15").await;
+        let xs: Vec<f32> = batches
+            .iter()
+            .flat_map(|batch| batch.column(0).as_any().downcast_ref::<Float32Array>().unwrap().values().to_vec())
+            .collect();
+        assert_eq!(xs, vec![10.0]);
+
+        let batches = query(&ctx, "SELECT COUNT(*) FROM \"Position\" WHERE snapshot_id >= 'frame_2'").await;
+        let count = batches[0].column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(count.value(0), 3);
+
+        let batches = query(&ctx, "SELECT entity_id FROM \"Position\" WHERE snapshot_id IN ('frame_3')").await;
+        assert_eq!(batches[0].num_columns(), 1);
+        assert_eq!(batches[0].column(0).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_scan_opens_only_the_selected_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        let tables = component_tables(store_with_frames(&temp_dir), SnapshotReader::new()).unwrap();
+        let table = &tables[0];
+        assert_eq!(table.snapshot_ids(), ["frame_1", "frame_2", "frame_3"]);
+        let names: Vec<String> = table.schema().fields().iter().map(|f| f.name().clone()).collect();
+        assert_eq!(names, [SNAPSHOT_ID_COLUMN, ENTITY_ID_COLUMN, "x"]);
+
+        let ctx = SessionContext::new();
+        let state = ctx.state();
+        let filters = [
+            Expr::Column(SNAPSHOT_ID_COLUMN.into()).gt(Expr::Literal(ScalarValue::from("frame_1"))),
+            Expr::Literal(ScalarValue::from("frame_3")).gt(Expr::Column(SNAPSHOT_ID_COLUMN.into())),
+        ];
+        let plan = table.scan(&state, Some(&vec![2]), &filters, None).await.unwrap();
+        assert_eq!(plan.properties().output_partitioning().partition_count(), 1);
+        assert_eq!(plan.schema().fields().len(), 1);
+
+        let none = [Expr::Column(SNAPSHOT_ID_COLUMN.into()).eq(Expr::Literal(ScalarValue::from("frame_9")))];
+        let plan = table.scan(&state, None, &none, None).await.unwrap();
+        assert!(::datafusion::physical_plan::collect(plan, ctx.task_ctx()).await.unwrap().is_empty());
+
+        let other = Expr::Column("x".into()).gt(Expr::Literal(ScalarValue::from(1.0f32)));
+        assert_eq!(SnapshotFilter::from_expr(&other), None);
+    }
+}
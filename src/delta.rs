@@ -0,0 +1,884 @@
+use crate::diff::{diff, values_equal, ComponentEntry, DiffBody, SnapshotDiff, SnapshotDiffV2_0};
+use crate::error::{PackError, Result};
+use crate::format::{
+    ComponentArchetype, ComponentData, EntityMetadata, FieldArray, PackedSnapshot, SnapshotHeader,
+    StructOfArraysData,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityMetadataChange {
+    pub entity_id: EntityId,
+    pub old: Option<EntityMetadata>,
+    pub new: Option<EntityMetadata>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceChange {
+    pub name: String,
+    pub old: Option<Vec<u8>>,
+    pub new: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColumnEncoding {
+    Xor,
+    Subtract,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnDelta {
+    pub component_id: ComponentId,
+    pub field: String,
+    pub encoding: ColumnEncoding,
+    pub data: Vec<u8>,
+}
+
+// Stands in for a checksum that was never computed, e.g. by
+// IncrementalCapture between verified deltas. apply and revert skip the
+// check on that side.
+pub const UNVERIFIED_CHECKSUM: [u8; 32] = [0; 32];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "DeltaSnapshotLayout")]
+pub struct DeltaSnapshot {
+    pub base_id: Option<String>,
+    pub base_checksum: [u8; 32],
+    pub target_checksum: [u8; 32],
+    pub base_header: SnapshotHeader,
+    pub target_header: SnapshotHeader,
+    pub changes: SnapshotDiff,
+    pub column_deltas: Vec<ColumnDelta>,
+    pub entity_metadata_changes: Vec<EntityMetadataChange>,
+    pub resource_changes: Vec<ResourceChange>,
+}
+
+// On disk, fields added since 2.0 go last, so older readers stop before
+// them: resource_changes came in 2.1 and the diff's tombstones in 2.2.
+impl Serialize for DeltaSnapshot {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut delta = serializer.serialize_struct("DeltaSnapshot", 10)?;
+        delta.serialize_field("base_id", &self.base_id)?;
+        delta.serialize_field("base_checksum", &self.base_checksum)?;
+        delta.serialize_field("target_checksum", &self.target_checksum)?;
+        delta.serialize_field("base_header", &self.base_header)?;
+        delta.serialize_field("target_header", &self.target_header)?;
+        delta.serialize_field("changes", &DiffBody(&self.changes))?;
+        delta.serialize_field("column_deltas", &self.column_deltas)?;
+        delta.serialize_field("entity_metadata_changes", &self.entity_metadata_changes)?;
+        delta.serialize_field("resource_changes", &self.resource_changes)?;
+        delta.serialize_field("tombstones", &self.changes.tombstones)?;
+        delta.end()
+    }
+}
+
+#[derive(Deserialize)]
+struct DeltaSnapshotLayout {
+    #[serde(default)]
+    base_id: Option<String>,
+    base_checksum: [u8; 32],
+    target_checksum: [u8; 32],
+    base_header: SnapshotHeader,
+    target_header: SnapshotHeader,
+    changes: SnapshotDiffV2_0,
+    column_deltas: Vec<ColumnDelta>,
+    entity_metadata_changes: Vec<EntityMetadataChange>,
+    #[serde(default)]
+    resource_changes: Vec<ResourceChange>,
+    #[serde(default)]
+    tombstones: Vec<EntityId>,
+}
+
+impl From<DeltaSnapshotLayout> for DeltaSnapshot {
+    fn from(delta: DeltaSnapshotLayout) -> Self {
+        Self {
+            base_id: delta.base_id,
+            base_checksum: delta.base_checksum,
+            target_checksum: delta.target_checksum,
+            base_header: delta.base_header,
+            target_header: delta.target_header,
+            changes: delta.changes.with_tombstones(delta.tombstones),
+            column_deltas: delta.column_deltas,
+            entity_metadata_changes: delta.entity_metadata_changes,
+            resource_changes: delta.resource_changes,
+        }
+    }
+}
+
+// See PackedSnapshotV2_0.
+#[derive(Deserialize)]
+pub(crate) struct DeltaSnapshotV2_0 {
+    base_id: Option<String>,
+    base_checksum: [u8; 32],
+    target_checksum: [u8; 32],
+    base_header: SnapshotHeader,
+    target_header: SnapshotHeader,
+    changes: SnapshotDiffV2_0,
+    column_deltas: Vec<ColumnDelta>,
+    entity_metadata_changes: Vec<EntityMetadataChange>,
+}
+
+impl From<DeltaSnapshotV2_0> for DeltaSnapshot {
+    fn from(delta: DeltaSnapshotV2_0) -> Self {
+        Self {
+            base_id: delta.base_id,
+            base_checksum: delta.base_checksum,
+            target_checksum: delta.target_checksum,
+            base_header: delta.base_header,
+            target_header: delta.target_header,
+            changes: delta.changes.into(),
+            column_deltas: delta.column_deltas,
+            entity_metadata_changes: delta.entity_metadata_changes,
+            resource_changes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DeltaSnapshotV2_1 {
+    base_id: Option<String>,
+    base_checksum: [u8; 32],
+    target_checksum: [u8; 32],
+    base_header: SnapshotHeader,
+    target_header: SnapshotHeader,
+    changes: SnapshotDiffV2_0,
+    column_deltas: Vec<ColumnDelta>,
+    entity_metadata_changes: Vec<EntityMetadataChange>,
+    resource_changes: Vec<ResourceChange>,
+}
+
+impl From<DeltaSnapshotV2_1> for DeltaSnapshot {
+    fn from(delta: DeltaSnapshotV2_1) -> Self {
+        Self {
+            base_id: delta.base_id,
+            base_checksum: delta.base_checksum,
+            target_checksum: delta.target_checksum,
+            base_header: delta.base_header,
+            target_header: delta.target_header,
+            changes: delta.changes.into(),
+            column_deltas: delta.column_deltas,
+            entity_metadata_changes: delta.entity_metadata_changes,
+            resource_changes: delta.resource_changes,
+        }
+    }
+}
+
+impl DeltaSnapshot {
+    pub fn from_snapshots(base: &PackedSnapshot, target: &PackedSnapshot) -> Result<Self> {
+        let entity_ids: BTreeSet<&EntityId> = base.entity_metadata.keys().chain(target.entity_metadata.keys()).collect();
+
+        let entity_metadata_changes = entity_ids
+            .into_iter()
+            .filter_map(|entity_id| {
+                let old = base.entity_metadata.get(entity_id);
+                let new = target.entity_metadata.get(entity_id);
+                (old != new).then(|| EntityMetadataChange {
+                    entity_id: *entity_id,
+                    old: old.cloned(),
+                    new: new.cloned(),
+                })
+            })
+            .collect();
+
+        let column_deltas = column_deltas(base, target);
+        let mut changes = diff(base, target);
+        changes.value_changes.retain(|change| {
+            !column_deltas
+                .iter()
+                .any(|delta| delta.component_id == change.component_id && delta.field == change.field)
+        });
+        // Deltas keep them in resource_changes, where 2.1 put them on disk.
+        let resource_changes = std::mem::take(&mut changes.resource_changes);
+
+        Ok(Self {
+            base_id: None,
+            base_checksum: base.content_checksum()?,
+            target_checksum: target.content_checksum()?,
+            base_header: base.header.clone(),
+            target_header: target.header.clone(),
+            changes,
+            column_deltas,
+            entity_metadata_changes,
+            resource_changes,
+        })
+    }
+
+    pub fn with_base_id(mut self, base_id: String) -> Self {
+        self.base_id = Some(base_id);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+            && self.column_deltas.is_empty()
+            && self.entity_metadata_changes.is_empty()
+            && self.resource_changes.is_empty()
+    }
+
+    pub fn apply(&self, base: &PackedSnapshot) -> Result<PackedSnapshot> {
+        if !matches_checksum(base, &self.base_checksum)? {
+            return Err(PackError::Delta("Base snapshot does not match delta base checksum".to_string()));
+        }
+
+        let mut target = apply_diff(base, &self.changes)?;
+        for delta in &self.column_deltas {
+            apply_column_delta(&mut target, delta, true)?;
+        }
+        for change in &self.entity_metadata_changes {
+            set_entity_metadata(&mut target, change.entity_id, change.new.clone());
+        }
+        for change in &self.resource_changes {
+            set_resource(&mut target, &change.name, change.new.clone());
+        }
+        target.header = self.target_header.clone();
+
+        if !matches_checksum(&target, &self.target_checksum)? {
+            return Err(PackError::Delta("Applied delta does not match target checksum".to_string()));
+        }
+
+        Ok(target)
+    }
+
+    pub fn revert(&self, target: &PackedSnapshot) -> Result<PackedSnapshot> {
+        if !matches_checksum(target, &self.target_checksum)? {
+            return Err(PackError::Delta("Target snapshot does not match delta target checksum".to_string()));
+        }
+
+        let mut base = apply_diff(target, &self.changes.invert())?;
+        for delta in &self.column_deltas {
+            apply_column_delta(&mut base, delta, false)?;
+        }
+        for change in &self.entity_metadata_changes {
+            set_entity_metadata(&mut base, change.entity_id, change.old.clone());
+        }
+        for change in &self.resource_changes {
+            set_resource(&mut base, &change.name, change.old.clone());
+        }
+        base.header = self.base_header.clone();
+
+        if !matches_checksum(&base, &self.base_checksum)? {
+            return Err(PackError::Delta("Reverted delta does not match base checksum".to_string()));
+        }
+
+        Ok(base)
+    }
+}
+
+fn matches_checksum(snapshot: &PackedSnapshot, checksum: &[u8; 32]) -> Result<bool> {
+    Ok(*checksum == UNVERIFIED_CHECKSUM || snapshot.content_checksum()? == *checksum)
+}
+
+#[derive(Debug, Clone)]
+pub struct DeltaChain {
+    keyframe: PackedSnapshot,
+    deltas: Vec<DeltaSnapshot>,
+}
+
+impl DeltaChain {
+    pub fn new(keyframe: PackedSnapshot) -> Self {
+        Self {
+            keyframe,
+            deltas: Vec::new(),
+        }
+    }
+
+    pub fn with_deltas<I: IntoIterator<Item = DeltaSnapshot>>(mut self, deltas: I) -> Self {
+        self.deltas.extend(deltas);
+        self
+    }
+
+    pub fn push(&mut self, delta: DeltaSnapshot) {
+        self.deltas.push(delta);
+    }
+
+    pub fn keyframe(&self) -> &PackedSnapshot {
+        &self.keyframe
+    }
+
+    pub fn deltas(&self) -> &[DeltaSnapshot] {
+        &self.deltas
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn materialize(&self, count: usize) -> Result<PackedSnapshot> {
+        if count > self.deltas.len() {
+            return Err(PackError::Delta(format!(
+                "Chain has {} deltas, cannot materialize {}", self.deltas.len(), count
+            )));
+        }
+
+        let mut snapshot = self.keyframe.clone();
+        for delta in &self.deltas[..count] {
+            snapshot = delta.apply(&snapshot)?;
+        }
+
+        Ok(snapshot)
+    }
+
+    pub fn consolidate(&self) -> Result<PackedSnapshot> {
+        self.materialize(self.deltas.len())
+    }
+
+    pub fn squash(&self) -> Result<DeltaSnapshot> {
+        self.rebase(&self.keyframe)
+    }
+
+    pub fn squash_range(&self, from: usize, to: usize) -> Result<DeltaSnapshot> {
+        if from > to || to > self.deltas.len() {
+            return Err(PackError::Delta(format!(
+                "Invalid delta range {}..{} for chain of {} deltas", from, to, self.deltas.len()
+            )));
+        }
+
+        let start = self.materialize(from)?;
+        let mut end = start.clone();
+        for delta in &self.deltas[from..to] {
+            end = delta.apply(&end)?;
+        }
+
+        DeltaSnapshot::from_snapshots(&start, &end)
+    }
+
+    pub fn rebase(&self, base: &PackedSnapshot) -> Result<DeltaSnapshot> {
+        DeltaSnapshot::from_snapshots(base, &self.consolidate()?)
+    }
+}
+
+pub fn apply_diff(base: &PackedSnapshot, changes: &SnapshotDiff) -> Result<PackedSnapshot> {
+    let mut snapshot = base.clone();
+
+    let mut removals: HashMap<&ComponentId, HashSet<EntityId>> = HashMap::new();
+    for entry in &changes.components_removed {
+        removals.entry(&entry.component_id).or_default().insert(entry.entity_id);
+    }
+
+    for (component_id, entity_ids) in removals {
+        let archetype = archetype_mut(&mut snapshot, component_id)?;
+        let before = archetype.entity_ids.len();
+        archetype.retain_entities(|id| !entity_ids.contains(&id));
+
+        if before - archetype.entity_ids.len() != entity_ids.len() {
+            return Err(PackError::Delta(format!(
+                "Cannot remove missing {} components", component_id
+            )));
+        }
+    }
+
+    for change in &changes.blob_changes {
+        let index = match find_archetype(&snapshot, &change.component_id) {
+            Some(index) => index,
+            None => {
+                snapshot.archetypes.push(ComponentArchetype {
+                    component_id: change.component_id.clone(),
+                    entity_ids: Vec::new(),
+                    data: ComponentData::Blob(Vec::new()),
+                });
+                snapshot.archetypes.len() - 1
+            }
+        };
+
+        let archetype = &mut snapshot.archetypes[index];
+        match &mut archetype.data {
+            ComponentData::Blob(data) if *data == change.old => data.clone_from(&change.new),
+            ComponentData::StructOfArrays(_) if archetype.entity_ids.is_empty() && change.old.is_empty() => {
+                archetype.data = ComponentData::Blob(change.new.clone());
+            }
+            _ => {
+                return Err(PackError::Delta(format!(
+                    "Blob data for {} does not match delta", change.component_id
+                )));
+            }
+        }
+    }
+
+    for entry in &changes.components_added {
+        let index = match find_archetype(&snapshot, &entry.component_id) {
+            Some(index) => index,
+            None => {
+                snapshot.archetypes.push(ComponentArchetype {
+                    component_id: entry.component_id.clone(),
+                    entity_ids: Vec::new(),
+                    data: empty_data(entry),
+                });
+                snapshot.archetypes.len() - 1
+            }
+        };
+
+        push_entry(&mut snapshot.archetypes[index], entry)?;
+    }
+
+    for change in &changes.value_changes {
+        let archetype = archetype_mut(&mut snapshot, &change.component_id)?;
+        let row = archetype.row_of(change.entity_id).ok_or_else(|| {
+            PackError::Delta(format!("Entity {} has no {} component", change.entity_id, change.component_id))
+        })?;
+
+        let column = match &mut archetype.data {
+            ComponentData::StructOfArrays(soa) => soa
+                .field_names
+                .iter()
+                .position(|name| *name == change.field)
+                .map(|index| &mut soa.field_data[index]),
+            ComponentData::Blob(_) => None,
+        }
+        .ok_or_else(|| PackError::Delta(format!("Unknown field {}.{}", change.component_id, change.field)))?;
+
+        match column.get(row) {
+            Some(current) if values_equal(&current, &change.old) => column.set(row, change.new.clone())?,
+            _ => {
+                return Err(PackError::Delta(format!(
+                    "Value of {}.{} for entity {} does not match delta",
+                    change.component_id, change.field, change.entity_id
+                )));
+            }
+        }
+    }
+
+    snapshot.archetypes.retain(|archetype| !archetype.is_empty());
+    snapshot.rebuild_entity_index();
+    if changes.moves_rows() {
+        snapshot.layout = None;
+    }
+
+    // A despawned entity may only come out of the diff with components it
+    // was given again, never with ones left over from before it died. Blobs
+    // are replaced whole, so they already hold whatever the world had.
+    let readded: HashSet<(EntityId, &ComponentId)> = if changes.tombstones.is_empty() {
+        HashSet::new()
+    } else {
+        changes.components_added.iter().map(|entry| (entry.entity_id, &entry.component_id)).collect()
+    };
+    for &entity_id in &changes.tombstones {
+        for (archetype, _) in snapshot.entity_components(entity_id) {
+            if matches!(archetype.data, ComponentData::StructOfArrays(_))
+                && !readded.contains(&(entity_id, &archetype.component_id)) {
+                return Err(PackError::Delta(format!(
+                    "Despawned entity {} would keep its {} component", entity_id, archetype.component_id
+                )));
+            }
+        }
+    }
+
+    for change in &changes.resource_changes {
+        set_resource(&mut snapshot, &change.name, change.new.clone());
+    }
+
+    snapshot.header.entity_count = (snapshot.header.entity_count + changes.entities_added.len() as u64)
+        .saturating_sub(changes.entities_removed.len() as u64);
+    snapshot.header.component_count = snapshot.archetypes.len() as u64;
+    snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+
+    Ok(snapshot)
+}
+
+fn column_deltas(base: &PackedSnapshot, target: &PackedSnapshot) -> Vec<ColumnDelta> {
+    let mut deltas = Vec::new();
+
+    for old in &base.archetypes {
+        let new = match target.archetypes.iter().find(|a| a.component_id == old.component_id) {
+            Some(new) if new.entity_ids == old.entity_ids => new,
+            _ => continue,
+        };
+
+        let (old_soa, new_soa) = match (&old.data, &new.data) {
+            (ComponentData::StructOfArrays(a), ComponentData::StructOfArrays(b))
+                if a.field_names == b.field_names && a.field_types == b.field_types => (a, b),
+            _ => continue,
+        };
+
+        for ((field, old_column), new_column) in old_soa.field_names.iter().zip(&old_soa.field_data).zip(&new_soa.field_data) {
+            if old_column == new_column {
+                continue;
+            }
+
+            if let Some((encoding, data)) = encode_column(old_column, new_column) {
+                deltas.push(ColumnDelta {
+                    component_id: old.component_id.clone(),
+                    field: field.clone(),
+                    encoding,
+                    data,
+                });
+            }
+        }
+    }
+
+    deltas
+}
+
+macro_rules! subtract_residuals {
+    ($base:expr, $target:expr) => {
+        $base.iter().zip($target.iter()).flat_map(|(a, b)| b.wrapping_sub(*a).to_le_bytes()).collect()
+    };
+}
+
+macro_rules! xor_residuals {
+    ($base:expr, $target:expr) => {
+        $base.iter().zip($target.iter()).flat_map(|(a, b)| (a.to_bits() ^ b.to_bits()).to_le_bytes()).collect()
+    };
+}
+
+fn encode_column(base: &FieldArray, target: &FieldArray) -> Option<(ColumnEncoding, Vec<u8>)> {
+    let encoded = match (base, target) {
+        (FieldArray::Bool(a), FieldArray::Bool(b)) => {
+            (ColumnEncoding::Xor, a.iter().zip(b).map(|(a, b)| (a ^ b) as u8).collect())
+        }
+        (FieldArray::I8(a), FieldArray::I8(b)) => (ColumnEncoding::Subtract, subtract_residuals!(a, b)),
+        (FieldArray::I16(a), FieldArray::I16(b)) => (ColumnEncoding::Subtract, subtract_residuals!(a, b)),
+        (FieldArray::I32(a), FieldArray::I32(b)) => (ColumnEncoding::Subtract, subtract_residuals!(a, b)),
+        (FieldArray::I64(a), FieldArray::I64(b)) => (ColumnEncoding::Subtract, subtract_residuals!(a, b)),
+        (FieldArray::U8(a), FieldArray::U8(b)) => (ColumnEncoding::Subtract, subtract_residuals!(a, b)),
+        (FieldArray::U16(a), FieldArray::U16(b)) => (ColumnEncoding::Subtract, subtract_residuals!(a, b)),
+        (FieldArray::U32(a), FieldArray::U32(b)) => (ColumnEncoding::Subtract, subtract_residuals!(a, b)),
+        (FieldArray::U64(a), FieldArray::U64(b)) => (ColumnEncoding::Subtract, subtract_residuals!(a, b)),
+        (FieldArray::F32(a), FieldArray::F32(b)) => (ColumnEncoding::Xor, xor_residuals!(a, b)),
+        (FieldArray::F64(a), FieldArray::F64(b)) => (ColumnEncoding::Xor, xor_residuals!(a, b)),
+        _ => return None,
+    };
+
+    Some(encoded)
+}
+
+macro_rules! apply_residuals {
+    ($values:expr, $data:expr, $ty:ty, |$value:ident, $residual:ident| $apply:expr) => {{
+        let width = std::mem::size_of::<$ty>();
+        if $data.len() != $values.len() * width {
+            return Err(PackError::Delta("Column delta length does not match column".to_string()));
+        }
+
+        for ($value, chunk) in $values.iter_mut().zip($data.chunks_exact(width)) {
+            let mut bytes = [0u8; std::mem::size_of::<$ty>()];
+            bytes.copy_from_slice(chunk);
+            let $residual = <$ty>::from_le_bytes(bytes);
+            *$value = $apply;
+        }
+    }};
+}
+
+macro_rules! apply_subtract {
+    ($values:expr, $data:expr, $ty:ty, $forward:expr) => {
+        apply_residuals!($values, $data, $ty, |value, residual| if $forward {
+            value.wrapping_add(residual)
+        } else {
+            value.wrapping_sub(residual)
+        })
+    };
+}
+
+fn apply_column_delta(snapshot: &mut PackedSnapshot, delta: &ColumnDelta, forward: bool) -> Result<()> {
+    let archetype = archetype_mut(snapshot, &delta.component_id)?;
+
+    let column = match &mut archetype.data {
+        ComponentData::StructOfArrays(soa) => soa
+            .field_names
+            .iter()
+            .position(|name| *name == delta.field)
+            .map(|index| &mut soa.field_data[index]),
+        ComponentData::Blob(_) => None,
+    }
+    .ok_or_else(|| PackError::Delta(format!("Unknown field {}.{}", delta.component_id, delta.field)))?;
+
+    let data = &delta.data;
+    match (column, delta.encoding) {
+        (FieldArray::Bool(values), ColumnEncoding::Xor) => {
+            apply_residuals!(values, data, u8, |value, residual| *value ^ (residual != 0))
+        }
+        (FieldArray::I8(values), ColumnEncoding::Subtract) => apply_subtract!(values, data, i8, forward),
+        (FieldArray::I16(values), ColumnEncoding::Subtract) => apply_subtract!(values, data, i16, forward),
+        (FieldArray::I32(values), ColumnEncoding::Subtract) => apply_subtract!(values, data, i32, forward),
+        (FieldArray::I64(values), ColumnEncoding::Subtract) => apply_subtract!(values, data, i64, forward),
+        (FieldArray::U8(values), ColumnEncoding::Subtract) => apply_subtract!(values, data, u8, forward),
+        (FieldArray::U16(values), ColumnEncoding::Subtract) => apply_subtract!(values, data, u16, forward),
+        (FieldArray::U32(values), ColumnEncoding::Subtract) => apply_subtract!(values, data, u32, forward),
+        (FieldArray::U64(values), ColumnEncoding::Subtract) => apply_subtract!(values, data, u64, forward),
+        (FieldArray::F32(values), ColumnEncoding::Xor) => {
+            apply_residuals!(values, data, u32, |value, residual| f32::from_bits(value.to_bits() ^ residual))
+        }
+        (FieldArray::F64(values), ColumnEncoding::Xor) => {
+            apply_residuals!(values, data, u64, |value, residual| f64::from_bits(value.to_bits() ^ residual))
+        }
+        _ => {
+            return Err(PackError::Delta(format!(
+                "Unsupported {:?} encoding for {}.{}", delta.encoding, delta.component_id, delta.field
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn set_entity_metadata(snapshot: &mut PackedSnapshot, entity_id: EntityId, metadata: Option<EntityMetadata>) {
+    match metadata {
+        Some(metadata) => {
+            snapshot.entity_metadata.insert(entity_id, metadata);
+        }
+        None => {
+            snapshot.entity_metadata.remove(&entity_id);
+        }
+    }
+}
+
+pub(crate) fn resource_changes(base: &BTreeMap<String, Vec<u8>>, target: &BTreeMap<String, Vec<u8>>) -> Vec<ResourceChange> {
+    let names: BTreeSet<&String> = base.keys().chain(target.keys()).collect();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let old = base.get(name);
+            let new = target.get(name);
+            (old != new).then(|| ResourceChange { name: name.clone(), old: old.cloned(), new: new.cloned() })
+        })
+        .collect()
+}
+
+fn set_resource(snapshot: &mut PackedSnapshot, name: &str, data: Option<Vec<u8>>) {
+    match data {
+        Some(data) => {
+            snapshot.resources.insert(name.to_string(), data);
+        }
+        None => {
+            snapshot.resources.remove(name);
+        }
+    }
+}
+
+fn find_archetype(snapshot: &PackedSnapshot, component_id: &ComponentId) -> Option<usize> {
+    snapshot.archetypes.iter().position(|a| a.component_id == *component_id)
+}
+
+fn archetype_mut<'a>(snapshot: &'a mut PackedSnapshot, component_id: &ComponentId) -> Result<&'a mut ComponentArchetype> {
+    snapshot
+        .archetypes
+        .iter_mut()
+        .find(|a| a.component_id == *component_id)
+        .ok_or_else(|| PackError::Delta(format!("Missing archetype {}", component_id)))
+}
+
+pub(crate) fn empty_data(entry: &ComponentEntry) -> ComponentData {
+    match &entry.values {
+        Some(values) => ComponentData::StructOfArrays(StructOfArraysData {
+            field_names: values.iter().map(|(name, _)| name.clone()).collect(),
+            field_types: values.iter().map(|(_, value)| value.field_type()).collect(),
+            field_data: values.iter().map(|(_, value)| FieldArray::with_type(value.field_type())).collect(),
+        }),
+        None => ComponentData::Blob(Vec::new()),
+    }
+}
+
+fn matches_layout(data: &ComponentData, entry: &ComponentEntry) -> bool {
+    match (data, &entry.values) {
+        (ComponentData::StructOfArrays(soa), Some(values)) => {
+            soa.field_names.len() == values.len()
+                && soa
+                    .field_names
+                    .iter()
+                    .zip(&soa.field_types)
+                    .zip(values)
+                    .all(|((name, field_type), (value_name, value))| {
+                        name == value_name && *field_type == value.field_type()
+                    })
+        }
+        (ComponentData::Blob(_), None) => true,
+        _ => false,
+    }
+}
+
+pub(crate) fn push_entry(archetype: &mut ComponentArchetype, entry: &ComponentEntry) -> Result<()> {
+    if archetype.entity_ids.contains(&entry.entity_id) {
+        return Err(PackError::Delta(format!(
+            "Entity {} already has a {} component", entry.entity_id, entry.component_id
+        )));
+    }
+
+    if !matches_layout(&archetype.data, entry) {
+        let reusable = match &archetype.data {
+            ComponentData::StructOfArrays(_) => archetype.entity_ids.is_empty(),
+            ComponentData::Blob(data) => archetype.entity_ids.is_empty() && data.is_empty(),
+        };
+
+        if !reusable {
+            return Err(PackError::Delta(format!(
+                "Layout of {} component does not match delta", entry.component_id
+            )));
+        }
+
+        archetype.data = empty_data(entry);
+    }
+
+    if let (ComponentData::StructOfArrays(soa), Some(values)) = (&mut archetype.data, &entry.values) {
+        for (column, (_, value)) in soa.field_data.iter_mut().zip(values) {
+            column.push(value.clone())?;
+        }
+    }
+
+    archetype.entity_ids.push(entry.entity_id);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{FieldType, FieldValue};
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+
+    fn snapshot(positions: &[(EntityId, f32, f32)], blob: Option<(&[EntityId], Vec<u8>)>) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: positions.iter().map(|(id, _, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string(), "y".to_string()],
+                field_types: vec![FieldType::F32, FieldType::F32],
+                field_data: vec![
+                    FieldArray::F32(positions.iter().map(|(_, x, _)| *x).collect()),
+                    FieldArray::F32(positions.iter().map(|(_, _, y)| *y).collect()),
+                ],
+            }),
+        });
+
+        if let Some((entity_ids, data)) = blob {
+            snapshot.archetypes.push(ComponentArchetype {
+                component_id: "Script".to_string(),
+                entity_ids: entity_ids.to_vec(),
+                data: ComponentData::Blob(data),
+            });
+        }
+
+        snapshot.header.entity_count = positions.len() as u64;
+        snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+        snapshot.header.component_count = snapshot.archetypes.len() as u64;
+        snapshot.rebuild_entity_index();
+        snapshot
+    }
+
+    #[test]
+    fn test_delta_apply_and_revert() {
+        let base = snapshot(&[(1, 0.0, 0.0), (2, 1.0, 1.0), (3, 2.0, 2.0)], None);
+        let mut target = snapshot(&[(3, 2.0, 2.0), (1, 0.5, 0.0), (4, 7.0, 7.0)], Some((&[1], vec![9, 9])));
+        target.annotate(4, "team", FieldValue::String("red".to_string()));
+        target.header.timestamp = 42;
+
+        let delta = DeltaSnapshot::from_snapshots(&base, &target).unwrap();
+        assert!(!delta.is_empty());
+
+        let applied = delta.apply(&base).unwrap();
+        assert_eq!(applied.content_checksum().unwrap(), target.content_checksum().unwrap());
+        assert_eq!(applied.header.timestamp, 42);
+        assert_eq!(
+            applied.annotation(4, "team"),
+            Some(&FieldValue::String("red".to_string()))
+        );
+
+        let reverted = delta.revert(&applied).unwrap();
+        assert_eq!(reverted.content_checksum().unwrap(), base.content_checksum().unwrap());
+        assert!(!reverted.contains_entity(4));
+        assert!(reverted.archetypes.iter().all(|a| a.component_id != "Script"));
+
+        assert!(matches!(delta.apply(&target), Err(PackError::Delta(_))));
+    }
+
+    #[test]
+    fn test_delta_chain_consolidate_and_rebase() {
+        let frames = [
+            snapshot(&[(1, 0.0, 0.0)], None),
+            snapshot(&[(1, 1.0, 0.0), (2, 5.0, 5.0)], None),
+            snapshot(&[(1, 2.0, 0.0), (2, 5.0, 6.0)], Some((&[2], vec![4]))),
+            snapshot(&[(2, 5.0, 7.0)], Some((&[2], vec![4, 4]))),
+        ];
+
+        let mut chain = DeltaChain::new(frames[0].clone());
+        for pair in frames.windows(2) {
+            chain.push(DeltaSnapshot::from_snapshots(&pair[0], &pair[1]).unwrap());
+        }
+        assert_eq!(chain.len(), 3);
+
+        let checksum = |s: &PackedSnapshot| s.content_checksum().unwrap();
+        assert_eq!(checksum(&chain.consolidate().unwrap()), checksum(&frames[3]));
+        assert_eq!(checksum(&chain.materialize(2).unwrap()), checksum(&frames[2]));
+
+        let squashed = chain.squash().unwrap();
+        assert_eq!(checksum(&squashed.apply(&frames[0]).unwrap()), checksum(&frames[3]));
+
+        let clip = chain.squash_range(1, 3).unwrap();
+        assert_eq!(checksum(&clip.apply(&frames[1]).unwrap()), checksum(&frames[3]));
+        assert!(chain.squash_range(2, 4).is_err());
+
+        let other_base = snapshot(&[(9, 1.0, 1.0)], None);
+        let rebased = chain.rebase(&other_base).unwrap();
+        assert_eq!(checksum(&rebased.apply(&other_base).unwrap()), checksum(&frames[3]));
+    }
+
+    #[test]
+    fn test_delta_encodes_drifting_columns() {
+        let rows: Vec<(EntityId, f32, f32)> = (0..2000).map(|i| (i, i as f32, 10.0)).collect();
+        let drifted: Vec<(EntityId, f32, f32)> = rows.iter().map(|(id, x, y)| (*id, x + 0.01, *y)).collect();
+        let base = snapshot(&rows, None);
+        let target = snapshot(&drifted, None);
+
+        let delta = DeltaSnapshot::from_snapshots(&base, &target).unwrap();
+        assert!(delta.changes.value_changes.is_empty());
+        assert_eq!(delta.column_deltas.len(), 1);
+        assert_eq!(delta.column_deltas[0].field, "x");
+        assert_eq!(delta.column_deltas[0].encoding, ColumnEncoding::Xor);
+
+        let delta_bytes = SnapshotWriter::new().write_delta_to_bytes(&delta).unwrap();
+        let target_bytes = SnapshotWriter::new().write_to_bytes(&target).unwrap();
+        assert!(delta_bytes.len() < target_bytes.len());
+
+        let applied = delta.apply(&base).unwrap();
+        assert_eq!(applied.content_checksum().unwrap(), target.content_checksum().unwrap());
+        let reverted = delta.revert(&applied).unwrap();
+        assert_eq!(reverted.content_checksum().unwrap(), base.content_checksum().unwrap());
+    }
+
+    #[test]
+    fn test_delta_storage_roundtrip() {
+        let base = snapshot(&[(1, 0.0, 0.0), (2, 1.0, 1.0)], Some((&[2], vec![1])));
+        let target = snapshot(&[(1, 3.0, 0.0)], Some((&[1, 2], vec![1, 2])));
+        let delta = DeltaSnapshot::from_snapshots(&base, &target).unwrap();
+
+        let bytes = SnapshotWriter::new().write_delta_to_bytes(&delta).unwrap();
+        assert!(SnapshotReader::new().read_from_bytes(&bytes).is_err());
+
+        let loaded = SnapshotReader::new().read_delta_from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.changes, delta.changes);
+
+        let applied = loaded.apply(&base).unwrap();
+        assert_eq!(applied.content_checksum().unwrap(), target.content_checksum().unwrap());
+    }
+
+    #[test]
+    fn test_older_readers_skip_trailing_tombstones() {
+        let base = snapshot(&[(1, 0.0, 0.0), (2, 1.0, 1.0)], None);
+        let target = snapshot(&[(1, 0.0, 0.0)], Some((&[1], vec![1])));
+        let mut delta = DeltaSnapshot::from_snapshots(&base, &target).unwrap();
+        delta.changes.tombstones = vec![1, 2];
+
+        let bytes = SnapshotWriter::new().write_delta_to_bytes(&delta).unwrap();
+        let (header, payload) = SnapshotReader::new().decode(&bytes, crate::format::DELTA_MAGIC_NUMBER).unwrap();
+        assert_eq!(header.version_minor(), crate::format::FORMAT_VERSION_MINOR);
+
+        // A 2.0 reader stops before resource_changes and the tombstones.
+        let old = DeltaSnapshot::from(bincode::deserialize::<DeltaSnapshotV2_0>(&payload).unwrap());
+        assert_eq!(old.changes.entities_removed, vec![2]);
+        assert_eq!(old.changes.tombstones, vec![2]);
+        assert_eq!(old.changes.components_added, delta.changes.components_added);
+        assert_eq!(old.entity_metadata_changes, delta.entity_metadata_changes);
+        let old = DeltaSnapshot::from(bincode::deserialize::<DeltaSnapshotV2_1>(&payload).unwrap());
+        assert_eq!(old.changes.tombstones, vec![2]);
+
+        let loaded = SnapshotReader::new().read_delta_from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.changes, delta.changes);
+    }
+}
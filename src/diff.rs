@@ -0,0 +1,637 @@
+use crate::delta::{resource_changes, ResourceChange};
+use crate::format::{ComponentArchetype, ComponentData, FieldValue, PackedSnapshot};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentEntry {
+    pub entity_id: EntityId,
+    pub component_id: ComponentId,
+    pub values: Option<Vec<(String, FieldValue)>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueChange {
+    pub entity_id: EntityId,
+    pub component_id: ComponentId,
+    pub field: String,
+    pub old: FieldValue,
+    pub new: FieldValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlobChange {
+    pub component_id: ComponentId,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotDiff {
+    pub entities_added: Vec<EntityId>,
+    pub entities_removed: Vec<EntityId>,
+    pub components_added: Vec<ComponentEntry>,
+    pub components_removed: Vec<ComponentEntry>,
+    pub value_changes: Vec<ValueChange>,
+    pub blob_changes: Vec<BlobChange>,
+    // Every entity despawned within the diff: those in entities_removed, and
+    // those whose id was spawned again afterwards. The components such an
+    // entity had before must all be listed as removed, so applying the diff
+    // never carries them over to its successor.
+    #[serde(default)]
+    pub tombstones: Vec<EntityId>,
+    #[serde(default)]
+    pub resource_changes: Vec<ResourceChange>,
+}
+
+// Deltas and patches keep the diff in its 2.0 layout and append the
+// tombstones after everything else, so older readers can skip them; this
+// writes that body and SnapshotDiffV2_0 reads it back.
+pub(crate) struct DiffBody<'a>(pub &'a SnapshotDiff);
+
+impl Serialize for DiffBody<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut body = serializer.serialize_struct("SnapshotDiff", 6)?;
+        body.serialize_field("entities_added", &self.0.entities_added)?;
+        body.serialize_field("entities_removed", &self.0.entities_removed)?;
+        body.serialize_field("components_added", &self.0.components_added)?;
+        body.serialize_field("components_removed", &self.0.components_removed)?;
+        body.serialize_field("value_changes", &self.0.value_changes)?;
+        body.serialize_field("blob_changes", &self.0.blob_changes)?;
+        body.end()
+    }
+}
+
+// The 2.0 layout, from before tombstones. See PackedSnapshotV2_0.
+#[derive(Deserialize)]
+pub(crate) struct SnapshotDiffV2_0 {
+    entities_added: Vec<EntityId>,
+    entities_removed: Vec<EntityId>,
+    components_added: Vec<ComponentEntry>,
+    components_removed: Vec<ComponentEntry>,
+    value_changes: Vec<ValueChange>,
+    blob_changes: Vec<BlobChange>,
+}
+
+impl From<SnapshotDiffV2_0> for SnapshotDiff {
+    fn from(diff: SnapshotDiffV2_0) -> Self {
+        Self {
+            tombstones: diff.entities_removed.clone(),
+            entities_added: diff.entities_added,
+            entities_removed: diff.entities_removed,
+            components_added: diff.components_added,
+            components_removed: diff.components_removed,
+            value_changes: diff.value_changes,
+            blob_changes: diff.blob_changes,
+            resource_changes: Vec::new(),
+        }
+    }
+}
+
+impl SnapshotDiffV2_0 {
+    // Tombstones include every removal, so an empty list is either a diff
+    // without any or one written before they existed.
+    pub(crate) fn with_tombstones(self, tombstones: Vec<EntityId>) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::from(self);
+        if !tombstones.is_empty() {
+            diff.tombstones = tombstones;
+        }
+        diff
+    }
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entities_added.is_empty()
+            && self.entities_removed.is_empty()
+            && self.components_added.is_empty()
+            && self.components_removed.is_empty()
+            && self.value_changes.is_empty()
+            && self.blob_changes.is_empty()
+            && self.tombstones.is_empty()
+            && self.resource_changes.is_empty()
+    }
+
+    pub fn change_count(&self) -> usize {
+        self.components_added.len()
+            + self.components_removed.len()
+            + self.value_changes.len()
+            + self.blob_changes.len()
+            + self.resource_changes.len()
+    }
+
+    // Whether applying the diff adds, drops or rewrites rows, which leaves
+    // them out of the order a native layout describes.
+    pub fn moves_rows(&self) -> bool {
+        !self.components_added.is_empty() || !self.components_removed.is_empty() || !self.blob_changes.is_empty()
+    }
+
+    // Ids that died and were spawned again within the diff.
+    pub fn respawned(&self) -> BTreeSet<EntityId> {
+        let removed: BTreeSet<&EntityId> = self.entities_removed.iter().collect();
+        self.tombstones.iter().filter(|id| !removed.contains(id)).copied().collect()
+    }
+
+    pub fn invert(&self) -> SnapshotDiff {
+        // Played backwards, spawned entities die and respawned ones die again.
+        let mut tombstones = self.entities_added.clone();
+        tombstones.extend(self.respawned());
+
+        SnapshotDiff {
+            tombstones,
+            entities_added: self.entities_removed.clone(),
+            entities_removed: self.entities_added.clone(),
+            components_added: self.components_removed.clone(),
+            components_removed: self.components_added.clone(),
+            value_changes: self
+                .value_changes
+                .iter()
+                .map(|change| ValueChange {
+                    old: change.new.clone(),
+                    new: change.old.clone(),
+                    ..change.clone()
+                })
+                .collect(),
+            blob_changes: self
+                .blob_changes
+                .iter()
+                .map(|change| BlobChange {
+                    component_id: change.component_id.clone(),
+                    old: change.new.clone(),
+                    new: change.old.clone(),
+                })
+                .collect(),
+            resource_changes: self
+                .resource_changes
+                .iter()
+                .map(|change| ResourceChange {
+                    name: change.name.clone(),
+                    old: change.new.clone(),
+                    new: change.old.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn changed_entities(&self) -> BTreeSet<EntityId> {
+        self.components_added
+            .iter()
+            .chain(&self.components_removed)
+            .map(|entry| entry.entity_id)
+            .chain(self.value_changes.iter().map(|change| change.entity_id))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffFilter {
+    entities: Option<BTreeSet<EntityId>>,
+    components: Option<BTreeSet<ComponentId>>,
+}
+
+impl DiffFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_entities<I: IntoIterator<Item = EntityId>>(mut self, entities: I) -> Self {
+        self.entities.get_or_insert_with(BTreeSet::new).extend(entities);
+        self
+    }
+
+    pub fn with_components<I, S>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ComponentId>,
+    {
+        self.components
+            .get_or_insert_with(BTreeSet::new)
+            .extend(components.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn entity_matches(&self, entity_id: EntityId) -> bool {
+        self.entities.as_ref().is_none_or(|entities| entities.contains(&entity_id))
+    }
+
+    pub fn component_matches(&self, component_id: &ComponentId) -> bool {
+        self.components.as_ref().is_none_or(|components| components.contains(component_id))
+    }
+
+    pub fn apply(&self, diff: &SnapshotDiff) -> SnapshotDiff {
+        let mut filtered = SnapshotDiff {
+            components_added: diff
+                .components_added
+                .iter()
+                .filter(|e| self.entity_matches(e.entity_id) && self.component_matches(&e.component_id))
+                .cloned()
+                .collect(),
+            components_removed: diff
+                .components_removed
+                .iter()
+                .filter(|e| self.entity_matches(e.entity_id) && self.component_matches(&e.component_id))
+                .cloned()
+                .collect(),
+            value_changes: diff
+                .value_changes
+                .iter()
+                .filter(|c| self.entity_matches(c.entity_id) && self.component_matches(&c.component_id))
+                .cloned()
+                .collect(),
+            blob_changes: if self.entities.is_none() {
+                diff.blob_changes
+                    .iter()
+                    .filter(|c| self.component_matches(&c.component_id))
+                    .cloned()
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            // Resources belong to no entity or component.
+            resource_changes: if self.entities.is_none() && self.components.is_none() {
+                diff.resource_changes.clone()
+            } else {
+                Vec::new()
+            },
+            ..SnapshotDiff::default()
+        };
+
+        let touched = filtered.changed_entities();
+        let keep = |entity_id: &EntityId| {
+            self.entity_matches(*entity_id) && (self.components.is_none() || touched.contains(entity_id))
+        };
+        filtered.entities_added = diff.entities_added.iter().copied().filter(keep).collect();
+        filtered.entities_removed = diff.entities_removed.iter().copied().filter(keep).collect();
+        filtered.tombstones = diff.tombstones.iter().copied().filter(keep).collect();
+
+        filtered
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FloatTolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl FloatTolerance {
+    pub fn absolute(epsilon: f64) -> Self {
+        Self {
+            absolute: epsilon,
+            relative: 0.0,
+        }
+    }
+
+    pub fn relative(epsilon: f64) -> Self {
+        Self {
+            absolute: 0.0,
+            relative: epsilon,
+        }
+    }
+
+    pub fn with_absolute(mut self, epsilon: f64) -> Self {
+        self.absolute = epsilon;
+        self
+    }
+
+    pub fn with_relative(mut self, epsilon: f64) -> Self {
+        self.relative = epsilon;
+        self
+    }
+
+    pub fn within(&self, a: f64, b: f64) -> bool {
+        if a.to_bits() == b.to_bits() || a == b {
+            return true;
+        }
+
+        let difference = (a - b).abs();
+        difference <= self.absolute || difference <= self.relative * a.abs().max(b.abs())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    default_tolerance: Option<FloatTolerance>,
+    field_tolerances: HashMap<(ComponentId, String), FloatTolerance>,
+}
+
+impl DiffOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tolerance(mut self, tolerance: FloatTolerance) -> Self {
+        self.default_tolerance = Some(tolerance);
+        self
+    }
+
+    pub fn with_field_tolerance(mut self, component_id: &str, field: &str, tolerance: FloatTolerance) -> Self {
+        self.field_tolerances.insert((component_id.to_string(), field.to_string()), tolerance);
+        self
+    }
+
+    pub fn tolerance_for(&self, component_id: &str, field: &str) -> Option<FloatTolerance> {
+        self.field_tolerances
+            .get(&(component_id.to_string(), field.to_string()))
+            .copied()
+            .or(self.default_tolerance)
+    }
+
+    pub fn values_equal(&self, component_id: &str, field: &str, a: &FieldValue, b: &FieldValue) -> bool {
+        match (a, b, self.tolerance_for(component_id, field)) {
+            (FieldValue::F32(x), FieldValue::F32(y), Some(tolerance)) => tolerance.within(*x as f64, *y as f64),
+            (FieldValue::F64(x), FieldValue::F64(y), Some(tolerance)) => tolerance.within(*x, *y),
+            _ => values_equal(a, b),
+        }
+    }
+}
+
+pub fn diff(old: &PackedSnapshot, new: &PackedSnapshot) -> SnapshotDiff {
+    diff_with_options(old, new, &DiffOptions::default())
+}
+
+pub fn diverges(a: &PackedSnapshot, b: &PackedSnapshot, options: &DiffOptions) -> bool {
+    !diff_with_options(a, b, options).is_empty()
+}
+
+pub fn diff_with_options(old: &PackedSnapshot, new: &PackedSnapshot, options: &DiffOptions) -> SnapshotDiff {
+    let mut result = SnapshotDiff::default();
+
+    let old_entities = entity_set(old);
+    let new_entities = entity_set(new);
+    result.entities_added = new_entities.difference(&old_entities).copied().collect();
+    result.entities_removed = old_entities.difference(&new_entities).copied().collect();
+    result.tombstones = result.entities_removed.clone();
+    result.resource_changes = resource_changes(&old.resources, &new.resources);
+
+    let old_archetypes = archetypes_by_component(old);
+    let new_archetypes = archetypes_by_component(new);
+    let component_ids: BTreeSet<&ComponentId> = old_archetypes.keys().chain(new_archetypes.keys()).copied().collect();
+
+    for component_id in component_ids {
+        diff_component(
+            old_archetypes.get(component_id).copied(),
+            new_archetypes.get(component_id).copied(),
+            options,
+            &mut result,
+        );
+    }
+
+    result
+}
+
+pub(crate) fn diff_component(
+    old: Option<&ComponentArchetype>,
+    new: Option<&ComponentArchetype>,
+    options: &DiffOptions,
+    result: &mut SnapshotDiff,
+) {
+    match (old, new) {
+        (Some(a), Some(b)) => diff_archetype(a, b, options, result),
+        (Some(a), None) => {
+            push_blob_change(&a.component_id, blob_bytes(a), &[], result);
+            result.components_removed.extend(entries(a));
+        }
+        (None, Some(b)) => {
+            push_blob_change(&b.component_id, &[], blob_bytes(b), result);
+            result.components_added.extend(entries(b));
+        }
+        (None, None) => {}
+    }
+}
+
+pub(crate) fn values_equal(a: &FieldValue, b: &FieldValue) -> bool {
+    match (a, b) {
+        (FieldValue::F32(x), FieldValue::F32(y)) => x.to_bits() == y.to_bits() || x == y,
+        (FieldValue::F64(x), FieldValue::F64(y)) => x.to_bits() == y.to_bits() || x == y,
+        _ => a == b,
+    }
+}
+
+fn diff_archetype(old: &ComponentArchetype, new: &ComponentArchetype, options: &DiffOptions, result: &mut SnapshotDiff) {
+    let old_rows = row_map(old);
+    let new_rows = row_map(new);
+
+    push_blob_change(&old.component_id, blob_bytes(old), blob_bytes(new), result);
+
+    let same_layout = same_layout(old, new);
+
+    for (entity_id, old_row) in &old_rows {
+        match new_rows.get(entity_id) {
+            Some(new_row) if same_layout => {
+                if let (Some(a), Some(b)) = (old.row_values(*old_row), new.row_values(*new_row)) {
+                    for ((field, old_value), (_, new_value)) in a.into_iter().zip(b) {
+                        if !options.values_equal(&old.component_id, &field, &old_value, &new_value) {
+                            result.value_changes.push(ValueChange {
+                                entity_id: *entity_id,
+                                component_id: old.component_id.clone(),
+                                field,
+                                old: old_value,
+                                new: new_value,
+                            });
+                        }
+                    }
+                }
+            }
+            Some(new_row) => {
+                result.components_removed.push(entry(old, *entity_id, *old_row));
+                result.components_added.push(entry(new, *entity_id, *new_row));
+            }
+            None => result.components_removed.push(entry(old, *entity_id, *old_row)),
+        }
+    }
+
+    for (entity_id, new_row) in &new_rows {
+        if !old_rows.contains_key(entity_id) {
+            result.components_added.push(entry(new, *entity_id, *new_row));
+        }
+    }
+}
+
+pub(crate) fn blob_bytes(archetype: &ComponentArchetype) -> &[u8] {
+    match &archetype.data {
+        ComponentData::Blob(data) => data,
+        ComponentData::StructOfArrays(_) => &[],
+    }
+}
+
+fn push_blob_change(component_id: &ComponentId, old: &[u8], new: &[u8], result: &mut SnapshotDiff) {
+    if old != new {
+        result.blob_changes.push(BlobChange {
+            component_id: component_id.clone(),
+            old: old.to_vec(),
+            new: new.to_vec(),
+        });
+    }
+}
+
+fn same_layout(a: &ComponentArchetype, b: &ComponentArchetype) -> bool {
+    match (&a.data, &b.data) {
+        (ComponentData::StructOfArrays(x), ComponentData::StructOfArrays(y)) => {
+            x.field_names == y.field_names && x.field_types == y.field_types
+        }
+        (ComponentData::Blob(_), ComponentData::Blob(_)) => true,
+        _ => false,
+    }
+}
+
+fn entry(archetype: &ComponentArchetype, entity_id: EntityId, row: usize) -> ComponentEntry {
+    ComponentEntry {
+        entity_id,
+        component_id: archetype.component_id.clone(),
+        values: archetype.row_values(row),
+    }
+}
+
+pub(crate) fn entries(archetype: &ComponentArchetype) -> Vec<ComponentEntry> {
+    row_map(archetype)
+        .into_iter()
+        .map(|(entity_id, row)| entry(archetype, entity_id, row))
+        .collect()
+}
+
+fn row_map(archetype: &ComponentArchetype) -> BTreeMap<EntityId, usize> {
+    archetype
+        .entity_ids
+        .iter()
+        .enumerate()
+        .map(|(row, id)| (*id, row))
+        .collect()
+}
+
+fn entity_set(snapshot: &PackedSnapshot) -> BTreeSet<EntityId> {
+    snapshot
+        .archetypes
+        .iter()
+        .flat_map(|a| a.entity_ids.iter().copied())
+        .collect()
+}
+
+fn archetypes_by_component(snapshot: &PackedSnapshot) -> HashMap<&ComponentId, &ComponentArchetype> {
+    let mut map = HashMap::new();
+    for archetype in &snapshot.archetypes {
+        map.entry(&archetype.component_id).or_insert(archetype);
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{FieldArray, FieldType, StructOfArraysData};
+
+    fn soa_archetype(component: &str, rows: &[(EntityId, f32)]) -> ComponentArchetype {
+        ComponentArchetype {
+            component_id: component.to_string(),
+            entity_ids: rows.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string()],
+                field_types: vec![FieldType::F32],
+                field_data: vec![FieldArray::F32(rows.iter().map(|(_, x)| *x).collect())],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_diff_detects_changes() {
+        let mut old = PackedSnapshot::new();
+        old.archetypes.push(soa_archetype("Position", &[(1, 0.0), (2, 5.0), (3, 1.0)]));
+
+        let mut new = PackedSnapshot::new();
+        new.archetypes.push(soa_archetype("Position", &[(1, 0.0), (2, 6.5), (4, 9.0)]));
+        new.archetypes.push(ComponentArchetype {
+            component_id: "Burning".to_string(),
+            entity_ids: vec![2],
+            data: ComponentData::Blob(vec![1, 2, 3]),
+        });
+
+        let result = diff(&old, &new);
+
+        assert_eq!(result.entities_added, vec![4]);
+        assert_eq!(result.entities_removed, vec![3]);
+        assert_eq!(
+            result.value_changes,
+            vec![ValueChange {
+                entity_id: 2,
+                component_id: "Position".to_string(),
+                field: "x".to_string(),
+                old: FieldValue::F32(5.0),
+                new: FieldValue::F32(6.5),
+            }]
+        );
+
+        let added: Vec<_> = result
+            .components_added
+            .iter()
+            .map(|e| (e.entity_id, e.component_id.as_str()))
+            .collect();
+        assert_eq!(added, vec![(2, "Burning"), (4, "Position")]);
+        assert_eq!(result.components_removed.len(), 1);
+        assert_eq!(result.components_removed[0].values, Some(vec![("x".to_string(), FieldValue::F32(1.0))]));
+
+        assert_eq!(result.changed_entities().into_iter().collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_diff_invert() {
+        let mut old = PackedSnapshot::new();
+        old.archetypes.push(soa_archetype("Position", &[(1, 1.0)]));
+        let mut new = PackedSnapshot::new();
+        new.archetypes.push(soa_archetype("Position", &[(1, 2.0), (2, 3.0)]));
+
+        let forward = diff(&old, &new);
+        let backward = diff(&new, &old);
+
+        assert_eq!(forward.invert(), backward);
+    }
+
+    #[test]
+    fn test_diff_with_tolerance() {
+        let mut old = PackedSnapshot::new();
+        old.archetypes.push(soa_archetype("Position", &[(1, 100.0), (2, 5.0)]));
+        let mut new = PackedSnapshot::new();
+        new.archetypes.push(soa_archetype("Position", &[(1, 100.00001), (2, 5.5)]));
+
+        assert_eq!(diff(&old, &new).value_changes.len(), 2);
+
+        let options = DiffOptions::new().with_tolerance(FloatTolerance::relative(1e-6));
+        let result = diff_with_options(&old, &new, &options);
+        assert_eq!(result.value_changes.len(), 1);
+        assert_eq!(result.value_changes[0].entity_id, 2);
+
+        let loose = options.with_field_tolerance("Position", "x", FloatTolerance::absolute(1.0));
+        assert!(!diverges(&old, &new, &loose));
+        assert!(diverges(&old, &new, &DiffOptions::new()));
+    }
+
+    #[test]
+    fn test_diff_resources() {
+        let mut old = PackedSnapshot::new();
+        old.resources.insert("clock".to_string(), vec![1]);
+        old.resources.insert("rng".to_string(), vec![7]);
+        let mut new = PackedSnapshot::new();
+        new.resources.insert("clock".to_string(), vec![2]);
+        new.resources.insert("weather".to_string(), vec![3]);
+
+        let result = diff(&old, &new);
+        let change = |name: &str, old: Option<u8>, new: Option<u8>| ResourceChange {
+            name: name.to_string(),
+            old: old.map(|b| vec![b]),
+            new: new.map(|b| vec![b]),
+        };
+        assert_eq!(
+            result.resource_changes,
+            vec![change("clock", Some(1), Some(2)), change("rng", Some(7), None), change("weather", None, Some(3))]
+        );
+        assert_eq!(result.change_count(), 3);
+        assert_eq!(result.invert(), diff(&new, &old));
+        assert_eq!(crate::delta::apply_diff(&old, &result).unwrap().resources, new.resources);
+
+        assert_eq!(DiffFilter::new().apply(&result).resource_changes.len(), 3);
+        assert!(DiffFilter::new().with_components(["Position"]).apply(&result).is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::diff::{DiffFilter, SnapshotDiff};
+use crate::error::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffFormatter {
+    filter: DiffFilter,
+}
+
+impl DiffFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_entities<I: IntoIterator<Item = EntityId>>(mut self, entities: I) -> Self {
+        self.filter = self.filter.with_entities(entities);
+        self
+    }
+
+    pub fn with_components<I, S>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ComponentId>,
+    {
+        self.filter = self.filter.with_components(components);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: DiffFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn filter(&self, diff: &SnapshotDiff) -> SnapshotDiff {
+        self.filter.apply(diff)
+    }
+
+    pub fn render_text(&self, diff: &SnapshotDiff) -> String {
+        let diff = self.filter(diff);
+
+        let mut lines: BTreeMap<EntityId, Vec<String>> = BTreeMap::new();
+
+        for entry in &diff.components_removed {
+            lines
+                .entry(entry.entity_id)
+                .or_default()
+                .push(format!("-{} component", entry.component_id));
+        }
+        for entry in &diff.components_added {
+            lines
+                .entry(entry.entity_id)
+                .or_default()
+                .push(format!("+{} component", entry.component_id));
+        }
+        for change in &diff.value_changes {
+            lines.entry(change.entity_id).or_default().push(format!(
+                "{}.{} {} -> {}",
+                change.component_id, change.field, change.old, change.new
+            ));
+        }
+        for entity_id in diff.entities_added.iter().chain(&diff.entities_removed).chain(&diff.tombstones) {
+            lines.entry(*entity_id).or_default();
+        }
+
+        let spawned: BTreeSet<&EntityId> = diff.entities_added.iter().collect();
+        let despawned: BTreeSet<&EntityId> = diff.entities_removed.iter().collect();
+        let respawned = diff.respawned();
+
+        let mut output = String::new();
+        for (entity_id, changes) in &lines {
+            let status = if spawned.contains(entity_id) {
+                " (spawned)"
+            } else if despawned.contains(entity_id) {
+                " (despawned)"
+            } else if respawned.contains(entity_id) {
+                " (respawned)"
+            } else {
+                ""
+            };
+
+            if changes.is_empty() {
+                output.push_str(&format!("Entity {}{}\n", entity_id, status));
+            } else {
+                output.push_str(&format!("Entity {}{}: {}\n", entity_id, status, changes.join("; ")));
+            }
+        }
+
+        for change in &diff.blob_changes {
+            output.push_str(&format!(
+                "Component {}: blob {} bytes -> {} bytes\n",
+                change.component_id,
+                change.old.len(),
+                change.new.len()
+            ));
+        }
+
+        for change in &diff.resource_changes {
+            let line = match (&change.old, &change.new) {
+                (Some(old), Some(new)) => format!("Resource {}: {} bytes -> {} bytes\n", change.name, old.len(), new.len()),
+                (None, Some(new)) => format!("Resource {}: added, {} bytes\n", change.name, new.len()),
+                (Some(_), None) => format!("Resource {}: removed\n", change.name),
+                (None, None) => continue,
+            };
+            output.push_str(&line);
+        }
+
+        output
+    }
+
+    pub fn render_json(&self, diff: &SnapshotDiff) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.filter(diff))?)
+    }
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&DiffFormatter::new().render_text(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{ComponentEntry, ValueChange};
+    use crate::format::FieldValue;
+
+    fn sample_diff() -> SnapshotDiff {
+        SnapshotDiff {
+            entities_added: vec![9],
+            components_added: vec![
+                ComponentEntry {
+                    entity_id: 4021,
+                    component_id: "Burning".to_string(),
+                    values: None,
+                },
+                ComponentEntry {
+                    entity_id: 9,
+                    component_id: "Position".to_string(),
+                    values: Some(vec![("x".to_string(), FieldValue::F32(0.0))]),
+                },
+            ],
+            value_changes: vec![ValueChange {
+                entity_id: 4021,
+                component_id: "Position".to_string(),
+                field: "x".to_string(),
+                old: FieldValue::F32(10.5),
+                new: FieldValue::F32(12.0),
+            }],
+            ..SnapshotDiff::default()
+        }
+    }
+
+    #[test]
+    fn test_render_text() {
+        let text = sample_diff().to_string();
+        assert_eq!(
+            text,
+            "Entity 9 (spawned): +Position component\nEntity 4021: +Burning component; Position.x 10.5 -> 12.0\n"
+        );
+
+        let filtered = DiffFormatter::new().with_components(["Burning"]).render_text(&sample_diff());
+        assert_eq!(filtered, "Entity 4021: +Burning component\n");
+
+        let by_entity = DiffFormatter::new().with_entities([9]).filter(&sample_diff());
+        assert_eq!(by_entity.entities_added, vec![9]);
+        assert!(by_entity.value_changes.is_empty());
+    }
+
+    #[test]
+    fn test_render_json() {
+        let json = DiffFormatter::new().with_entities([4021]).render_json(&sample_diff()).unwrap();
+        let parsed: SnapshotDiff = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.value_changes.len(), 1);
+        assert_eq!(parsed.components_added.len(), 1);
+        assert!(parsed.entities_added.is_empty());
+    }
+}
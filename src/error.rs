@@ -1,4 +1,8 @@
+use crate::metadata::SnapshotMetadata;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
+use tx2_link::ComponentId;
 
 #[derive(Error, Debug)]
 pub enum PackError {
@@ -32,12 +36,45 @@ pub enum PackError {
     #[error("Checksum mismatch")]
     ChecksumMismatch,
 
+    #[error("Checksum mismatch in chunk {chunk}")]
+    ChunkChecksumMismatch { chunk: usize },
+
+    #[error("Inconsistent archetype {component_id}, column {column}: {message}")]
+    InconsistentArchetype { component_id: String, column: String, message: String },
+
     #[error("Snapshot not found: {0}")]
     SnapshotNotFound(String),
 
+    #[error("Invalid snapshot id: {0:?}")]
+    InvalidSnapshotId(String),
+
     #[error("Invalid checkpoint: {0}")]
     InvalidCheckpoint(String),
 
+    #[error("Invalid metadata: {0}")]
+    InvalidMetadata(String),
+
+    #[error("Invalid label selector: {0}")]
+    InvalidSelector(String),
+
+    #[error("Query error: {0}")]
+    Query(String),
+
+    #[error("Delta error: {0}")]
+    Delta(String),
+
+    #[error("Conflict saving snapshot {}: stored version changed since it was loaded", ours.id)]
+    Conflict {
+        ours: Box<SnapshotMetadata>,
+        theirs: Box<SnapshotMetadata>,
+    },
+
+    #[error("Buffer too small: {required} bytes required, {capacity} available")]
+    BufferTooSmall { required: usize, capacity: usize },
+
+    #[error("Parse limit exceeded: {limit} is over {max}")]
+    LimitExceeded { limit: &'static str, max: u64 },
+
     #[error("Bincode error: {0}")]
     Bincode(#[from] bincode::Error),
 
@@ -50,8 +87,167 @@ pub enum PackError {
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[cfg(feature = "broadcast")]
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+
     #[error("Unknown error: {0}")]
     Unknown(String),
+
+    #[error("{source} ({context})")]
+    Context {
+        context: Box<ErrorContext>,
+        source: Box<PackError>,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, PackError>;
+
+// Boxed, as tungstenite's error would otherwise double the size of every Result.
+#[cfg(feature = "broadcast")]
+impl From<tungstenite::Error> for PackError {
+    fn from(error: tungstenite::Error) -> Self {
+        PackError::WebSocket(Box::new(error))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineStage {
+    Encode,
+    Compress,
+    Encrypt,
+    Write,
+    Read,
+    Verify,
+    Decrypt,
+    Decompress,
+    Decode,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    pub path: Option<PathBuf>,
+    pub snapshot_id: Option<String>,
+    pub component_id: Option<ComponentId>,
+    pub stage: Option<PipelineStage>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(path) = &self.path {
+            parts.push(format!("path {}", path.display()));
+        }
+        if let Some(snapshot_id) = &self.snapshot_id {
+            parts.push(format!("snapshot {}", snapshot_id));
+        }
+        if let Some(component_id) = &self.component_id {
+            parts.push(format!("component {}", component_id));
+        }
+        if let Some(stage) = &self.stage {
+            parts.push(format!("stage {:?}", stage).to_lowercase());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl PackError {
+    pub fn root(&self) -> &PackError {
+        match self {
+            PackError::Context { source, .. } => source,
+            error => error,
+        }
+    }
+
+    pub(crate) fn root_mut(&mut self) -> &mut PackError {
+        match self {
+            PackError::Context { source, .. } => source,
+            error => error,
+        }
+    }
+
+    pub fn into_root(self) -> PackError {
+        match self {
+            PackError::Context { source, .. } => *source,
+            error => error,
+        }
+    }
+
+    // Errors that mean the bytes themselves are bad, as opposed to a missing
+    // key, a limit or an I/O failure that may go away on retry.
+    pub fn is_corruption(&self) -> bool {
+        match self.root() {
+            PackError::Io(e) => matches!(e.kind(), std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::InvalidData),
+            PackError::Deserialization(_)
+            | PackError::Decompression(_)
+            | PackError::InvalidFormat(_)
+            | PackError::ChecksumMismatch
+            | PackError::ChunkChecksumMismatch { .. }
+            | PackError::InconsistentArchetype { .. }
+            | PackError::Bincode(_)
+            | PackError::MsgPackDecode(_)
+            | PackError::Json(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            PackError::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    // Context is kept flat: the innermost value of each field wins, since it
+    // was attached closest to where the error happened.
+    fn with_context(self, add: impl FnOnce(&mut ErrorContext)) -> PackError {
+        match self {
+            PackError::Context { mut context, source } => {
+                let mut outer = ErrorContext::default();
+                add(&mut outer);
+                context.path = context.path.take().or(outer.path);
+                context.snapshot_id = context.snapshot_id.take().or(outer.snapshot_id);
+                context.component_id = context.component_id.take().or(outer.component_id);
+                context.stage = context.stage.or(outer.stage);
+                PackError::Context { context, source }
+            }
+            error => {
+                let mut context = ErrorContext::default();
+                add(&mut context);
+                PackError::Context {
+                    context: Box::new(context),
+                    source: Box::new(error),
+                }
+            }
+        }
+    }
+}
+
+pub trait ResultExt<T> {
+    fn with_path(self, path: &Path) -> Result<T>;
+    fn with_snapshot_id(self, snapshot_id: &str) -> Result<T>;
+    fn with_component(self, component_id: &ComponentId) -> Result<T>;
+    fn in_stage(self, stage: PipelineStage) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn with_path(self, path: &Path) -> Result<T> {
+        self.map_err(|e| e.with_context(|context| context.path = Some(path.to_path_buf())))
+    }
+
+    fn with_snapshot_id(self, snapshot_id: &str) -> Result<T> {
+        self.map_err(|e| e.with_context(|context| context.snapshot_id = Some(snapshot_id.to_string())))
+    }
+
+    fn with_component(self, component_id: &ComponentId) -> Result<T> {
+        self.map_err(|e| e.with_context(|context| context.component_id = Some(component_id.clone())))
+    }
+
+    fn in_stage(self, stage: PipelineStage) -> Result<T> {
+        self.map_err(|e| e.with_context(|context| context.stage = Some(stage)))
+    }
+}
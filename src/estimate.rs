@@ -0,0 +1,183 @@
+use crate::error::Result;
+use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot, SnapshotHeader, StructOfArraysData};
+use crate::storage::SnapshotWriter;
+
+// Snapshots up to this many bincode bytes are encoded in full, so small saves
+// get an exact answer; larger ones are scaled up from a sample of this size.
+const SAMPLE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeEstimate {
+    pub serialized_bytes: u64,
+    pub compressed_bytes: u64,
+    pub file_bytes: u64,
+    pub sampled_bytes: u64,
+    pub exact: bool,
+}
+
+impl SnapshotWriter {
+    // Runs the snapshot (or a row sample of it) through the real write
+    // pipeline in memory, so format, compression, encryption and framing are
+    // all accounted for, and nothing touches the disk.
+    pub fn estimate(&self, snapshot: &PackedSnapshot) -> Result<SizeEstimate> {
+        snapshot.check_consistency()?;
+
+        let archetype_weights = snapshot
+            .archetypes
+            .iter()
+            .map(bincode::serialized_size)
+            .collect::<bincode::Result<Vec<_>>>()?;
+        let metadata_weight = bincode::serialized_size(&snapshot.entity_metadata)?;
+        let total_weight = archetype_weights.iter().sum::<u64>() + metadata_weight;
+
+        let mut out = Vec::new();
+        if total_weight <= SAMPLE_BYTES {
+            let serialized = self.encode_measured(snapshot, &mut out)? as u64;
+            let compressed = SnapshotHeader::decode(&out)?.data_size;
+            return Ok(SizeEstimate {
+                serialized_bytes: serialized,
+                compressed_bytes: compressed,
+                file_bytes: out.len() as u64,
+                sampled_bytes: total_weight,
+                exact: true,
+            });
+        }
+
+        // An empty snapshot gives the fixed cost (headers, table of contents
+        // framing) that should not be scaled with the sample.
+        let mut empty = PackedSnapshot::new();
+        empty.header = snapshot.header.clone();
+        let base_serialized = self.encode_measured(&empty, &mut out)? as u64;
+        let base_compressed = SnapshotHeader::decode(&out)?.data_size;
+        let framing = out.len() as u64 - base_compressed;
+
+        let fraction = SAMPLE_BYTES as f64 / total_weight as f64;
+        let sample = sample_snapshot(snapshot, fraction);
+        let sample_weight = sample
+            .archetypes
+            .iter()
+            .map(bincode::serialized_size)
+            .sum::<bincode::Result<u64>>()?
+            + bincode::serialized_size(&sample.entity_metadata)?;
+        let sample_serialized = self.encode_measured(&sample, &mut out)? as u64;
+        let sample_compressed = SnapshotHeader::decode(&out)?.data_size;
+
+        let scale = |sampled: u64, base: u64| {
+            let variable = sampled.saturating_sub(base) as f64 * total_weight as f64 / sample_weight.max(1) as f64;
+            base + variable.ceil() as u64
+        };
+        let compressed = scale(sample_compressed, base_compressed);
+
+        Ok(SizeEstimate {
+            serialized_bytes: scale(sample_serialized, base_serialized),
+            compressed_bytes: compressed,
+            file_bytes: compressed + framing,
+            sampled_bytes: sample_weight,
+            exact: false,
+        })
+    }
+}
+
+// Every archetype keeps a contiguous run of rows from its middle, so sorted
+// entity ids and neighbouring values compress the way the full column would.
+fn sample_snapshot(snapshot: &PackedSnapshot, fraction: f64) -> PackedSnapshot {
+    let keep = |len: usize| ((len as f64 * fraction).ceil() as usize).clamp(len.min(1), len);
+
+    let mut sample = PackedSnapshot::new();
+    sample.header = snapshot.header.clone();
+    sample.archetypes = snapshot
+        .archetypes
+        .iter()
+        .map(|archetype| {
+            let rows = archetype.entity_ids.len();
+            let start = (rows - keep(rows)) / 2;
+            let indices: Vec<usize> = (start..start + keep(rows)).collect();
+
+            let data = match &archetype.data {
+                ComponentData::StructOfArrays(soa) => ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: soa.field_names.clone(),
+                    field_types: soa.field_types.clone(),
+                    field_data: soa.field_data.iter().map(|column| column.gather(&indices)).collect(),
+                }),
+                ComponentData::Blob(payload) => ComponentData::Blob(payload[..keep(payload.len())].to_vec()),
+            };
+
+            ComponentArchetype {
+                component_id: archetype.component_id.clone(),
+                entity_ids: indices.iter().map(|&i| archetype.entity_ids[i]).collect(),
+                data,
+            }
+        })
+        .collect();
+    sample.entity_metadata = snapshot
+        .entity_metadata
+        .iter()
+        .take(keep(snapshot.entity_metadata.len()))
+        .map(|(id, metadata)| (*id, metadata.clone()))
+        .collect();
+    sample.header.entity_count = sample.archetypes.iter().map(|a| a.entity_ids.len() as u64).sum();
+    sample.rebuild_entity_index();
+
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionCodec;
+    use crate::format::{FieldArray, FieldType, PackFormat};
+
+    // Noisy enough that the sample compresses like the whole column would.
+    fn snapshot(rows: usize) -> PackedSnapshot {
+        let noise = |i: usize| (i as u64).wrapping_mul(6364136223846793005).rotate_left(17) % 1000;
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: (0..rows as u32).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string(), "name".to_string()],
+                field_types: vec![FieldType::F32, FieldType::String],
+                field_data: vec![
+                    FieldArray::F32((0..rows).map(|i| noise(i) as f32).collect::<Vec<_>>().into()),
+                    FieldArray::String((0..rows).map(|i| format!("entity-{}", noise(i) % 13)).collect::<Vec<_>>().into()),
+                ],
+            }),
+        });
+        snapshot.header.archetype_count = 1;
+        snapshot.header.entity_count = rows as u64;
+        snapshot
+    }
+
+    #[test]
+    fn test_small_snapshots_are_estimated_exactly() {
+        let writer = SnapshotWriter::new();
+        let snapshot = snapshot(100);
+
+        let estimate = writer.estimate(&snapshot).unwrap();
+        assert!(estimate.exact);
+        assert_eq!(estimate.file_bytes, writer.write_to_bytes(&snapshot).unwrap().len() as u64);
+    }
+
+    #[test]
+    fn test_large_snapshots_are_estimated_from_a_sample() {
+        let snapshot = snapshot(200_000);
+        for codec in [CompressionCodec::none(), CompressionCodec::zstd_default()] {
+            let writer = SnapshotWriter::new().with_compression(codec);
+            let estimate = writer.estimate(&snapshot).unwrap();
+            let actual = writer.write_to_bytes(&snapshot).unwrap().len() as f64;
+
+            assert!(!estimate.exact);
+            assert!(estimate.sampled_bytes <= 2 * SAMPLE_BYTES);
+            let error = (estimate.file_bytes as f64 - actual).abs() / actual;
+            assert!(error < 0.25, "{:?} estimated {} for {}", codec, estimate.file_bytes, actual);
+        }
+    }
+
+    #[test]
+    fn test_estimate_rejects_inconsistent_snapshots() {
+        let mut snapshot = snapshot(10);
+        snapshot.archetypes[0].entity_ids.pop();
+        assert!(SnapshotWriter::new().estimate(&snapshot).is_err());
+    }
+}
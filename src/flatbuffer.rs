@@ -0,0 +1,628 @@
+use crate::error::{PackError, Result};
+use crate::format::{
+    ComponentArchetype, ComponentData, EntityMetadata, FieldArray, FieldType, PackedSnapshot, StructOfArraysData,
+};
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
+use std::collections::HashMap;
+use tx2_link::EntityId;
+
+pub const FLATBUFFERS_IDENTIFIER: &str = "TX2F";
+pub const FLATBUFFERS_SCHEMA: &str = include_str!("../schema/tx2pack.fbs");
+
+const FIELD_TYPES: [FieldType; 13] = [
+    FieldType::Bool,
+    FieldType::I8,
+    FieldType::I16,
+    FieldType::I32,
+    FieldType::I64,
+    FieldType::U8,
+    FieldType::U16,
+    FieldType::U32,
+    FieldType::U64,
+    FieldType::F32,
+    FieldType::F64,
+    FieldType::String,
+    FieldType::Bytes,
+];
+
+pub(crate) fn encode_snapshot(snapshot: &PackedSnapshot) -> Vec<u8> {
+    let mut fbb = FlatBufferBuilder::new();
+
+    let archetypes: Vec<_> = snapshot.archetypes.iter().map(|a| write_archetype(&mut fbb, a)).collect();
+    let archetypes = fbb.create_vector(&archetypes);
+
+    let mut entity_ids: Vec<&EntityId> = snapshot.entity_metadata.keys().collect();
+    entity_ids.sort();
+    let entity_metadata: Vec<_> = entity_ids
+        .into_iter()
+        .map(|id| write_entity_meta(&mut fbb, *id, &snapshot.entity_metadata[id]))
+        .collect();
+    let entity_metadata = fbb.create_vector(&entity_metadata);
+
+    let resources: Vec<_> = snapshot
+        .resources
+        .iter()
+        .map(|(name, data)| {
+            let name = fbb.create_string(name);
+            let data = fbb.create_vector(data);
+            let start = fbb.start_table();
+            fbb.push_slot_always(schema::Resource::VT_NAME, name);
+            fbb.push_slot_always(schema::Resource::VT_DATA, data);
+            WIPOffset::<schema::Resource>::new(fbb.end_table(start).value())
+        })
+        .collect();
+    let resources = (!resources.is_empty()).then(|| fbb.create_vector(&resources));
+
+    let start = fbb.start_table();
+    fbb.push_slot::<i64>(schema::Snapshot::VT_TIMESTAMP, snapshot.header.timestamp, 0);
+    fbb.push_slot_always(schema::Snapshot::VT_ARCHETYPES, archetypes);
+    fbb.push_slot_always(schema::Snapshot::VT_ENTITY_METADATA, entity_metadata);
+    if let Some(resources) = resources {
+        fbb.push_slot_always(schema::Snapshot::VT_RESOURCES, resources);
+    }
+    let root = fbb.end_table(start);
+
+    fbb.finish(root, Some(FLATBUFFERS_IDENTIFIER));
+    fbb.finished_data().to_vec()
+}
+
+pub(crate) fn decode_snapshot(bytes: &[u8]) -> Result<PackedSnapshot> {
+    if !flatbuffers::buffer_has_identifier(bytes, FLATBUFFERS_IDENTIFIER, false) {
+        return Err(PackError::Deserialization("Missing FlatBuffers file identifier".to_string()));
+    }
+
+    let root = flatbuffers::root::<schema::Snapshot>(bytes).map_err(|e| PackError::Deserialization(e.to_string()))?;
+
+    let mut snapshot = PackedSnapshot::new();
+    snapshot.header.timestamp = root.timestamp();
+
+    for archetype in root.archetypes().iter().flatten() {
+        snapshot.archetypes.push(read_archetype(&archetype)?);
+    }
+
+    for meta in root.entity_metadata().iter().flatten() {
+        let mut annotations = HashMap::new();
+        for column in meta.annotations().iter().flatten() {
+            let value = read_column(&column)?
+                .get(0)
+                .ok_or_else(|| PackError::Deserialization("Empty annotation column".to_string()))?;
+            annotations.insert(column.name().unwrap_or_default().to_string(), value);
+        }
+
+        snapshot.entity_metadata.insert(
+            entity_id(meta.entity_id())?,
+            EntityMetadata {
+                created_at: meta.created_at(),
+                modified_at: meta.modified_at(),
+                tags: meta.tags().iter().flatten().map(str::to_string).collect(),
+                annotations,
+            },
+        );
+    }
+
+    for resource in root.resources().iter().flatten() {
+        let name = resource
+            .name()
+            .ok_or_else(|| PackError::Deserialization("Resource is missing a name".to_string()))?;
+        let data = resource.data().map(|data| data.bytes().to_vec()).unwrap_or_default();
+        snapshot.resources.insert(name.to_string(), data);
+    }
+
+    snapshot.rebuild_entity_index();
+    snapshot.header.entity_count = snapshot.entity_index.len() as u64;
+    snapshot.header.component_count = snapshot.archetypes.len() as u64;
+    snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+
+    Ok(snapshot)
+}
+
+fn write_archetype<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    archetype: &ComponentArchetype,
+) -> WIPOffset<schema::Archetype<'a>> {
+    let component_id = fbb.create_string(&archetype.component_id);
+    let entity_ids: Vec<u64> = archetype.entity_ids.iter().map(|id| u64::from(*id)).collect();
+    let entity_ids = fbb.create_vector(&entity_ids);
+
+    let (columns, blob) = match &archetype.data {
+        ComponentData::StructOfArrays(soa) => {
+            let columns: Vec<_> = soa
+                .field_names
+                .iter()
+                .zip(&soa.field_data)
+                .map(|(name, data)| write_column(fbb, name, data))
+                .collect();
+            (Some(fbb.create_vector(&columns)), None)
+        }
+        ComponentData::Blob(data) => (None, Some(fbb.create_vector(data))),
+    };
+
+    let start = fbb.start_table();
+    fbb.push_slot_always(schema::Archetype::VT_COMPONENT_ID, component_id);
+    fbb.push_slot_always(schema::Archetype::VT_ENTITY_IDS, entity_ids);
+    if let Some(columns) = columns {
+        fbb.push_slot_always(schema::Archetype::VT_COLUMNS, columns);
+    }
+    if let Some(blob) = blob {
+        fbb.push_slot::<bool>(schema::Archetype::VT_IS_BLOB, true, false);
+        fbb.push_slot_always(schema::Archetype::VT_BLOB, blob);
+    }
+    WIPOffset::new(fbb.end_table(start).value())
+}
+
+fn write_entity_meta<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    entity_id: EntityId,
+    metadata: &EntityMetadata,
+) -> WIPOffset<schema::EntityMeta<'a>> {
+    let tags: Vec<_> = metadata.tags.iter().map(|tag| fbb.create_string(tag)).collect();
+    let tags = fbb.create_vector(&tags);
+
+    let mut keys: Vec<&String> = metadata.annotations.keys().collect();
+    keys.sort();
+    let annotations: Vec<_> = keys
+        .into_iter()
+        .map(|key| {
+            let value = &metadata.annotations[key];
+            let mut column = FieldArray::with_type(value.field_type());
+            column.push(value.clone()).expect("column matches value type");
+            write_column(fbb, key, &column)
+        })
+        .collect();
+    let annotations = fbb.create_vector(&annotations);
+
+    let start = fbb.start_table();
+    fbb.push_slot::<u64>(schema::EntityMeta::VT_ENTITY_ID, u64::from(entity_id), 0);
+    fbb.push_slot::<i64>(schema::EntityMeta::VT_CREATED_AT, metadata.created_at, 0);
+    fbb.push_slot::<i64>(schema::EntityMeta::VT_MODIFIED_AT, metadata.modified_at, 0);
+    fbb.push_slot_always(schema::EntityMeta::VT_TAGS, tags);
+    fbb.push_slot_always(schema::EntityMeta::VT_ANNOTATIONS, annotations);
+    WIPOffset::new(fbb.end_table(start).value())
+}
+
+fn write_column<'a>(fbb: &mut FlatBufferBuilder<'a>, name: &str, data: &FieldArray) -> WIPOffset<schema::Column<'a>> {
+    let name = fbb.create_string(name);
+    let kind = FIELD_TYPES.iter().position(|t| *t == data.field_type()).unwrap_or_default() as u8;
+
+    let mut strings = None;
+    let mut blobs = None;
+    let mut packed = Vec::new();
+
+    macro_rules! pack {
+        ($values:expr) => {
+            for value in $values {
+                packed.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+    }
+
+    match data {
+        FieldArray::Bool(v) => packed.extend(v.iter().map(|b| u8::from(*b))),
+        FieldArray::I8(v) => pack!(v),
+        FieldArray::I16(v) => pack!(v),
+        FieldArray::I32(v) => pack!(v),
+        FieldArray::I64(v) => pack!(v),
+        FieldArray::U8(v) => packed.extend_from_slice(v),
+        FieldArray::U16(v) => pack!(v),
+        FieldArray::U32(v) => pack!(v),
+        FieldArray::U64(v) => pack!(v),
+        FieldArray::F32(v) => pack!(v),
+        FieldArray::F64(v) => pack!(v),
+        FieldArray::String(v) => {
+            let offsets: Vec<_> = v.iter().map(|s| fbb.create_string(s)).collect();
+            strings = Some(fbb.create_vector(&offsets));
+        }
+        FieldArray::Bytes(v) => {
+            let offsets: Vec<_> = v
+                .iter()
+                .map(|bytes| {
+                    let data = fbb.create_vector(bytes);
+                    let start = fbb.start_table();
+                    fbb.push_slot_always(schema::Bytes::VT_DATA, data);
+                    WIPOffset::<schema::Bytes>::new(fbb.end_table(start).value())
+                })
+                .collect();
+            blobs = Some(fbb.create_vector(&offsets));
+        }
+    }
+
+    let packed = match data {
+        FieldArray::String(_) | FieldArray::Bytes(_) => None,
+        _ => Some(fbb.create_vector(&packed)),
+    };
+
+    let start = fbb.start_table();
+    fbb.push_slot_always(schema::Column::VT_NAME, name);
+    fbb.push_slot::<u8>(schema::Column::VT_KIND, kind, 0);
+    if let Some(packed) = packed {
+        fbb.push_slot_always(schema::Column::VT_DATA, packed);
+    }
+    if let Some(strings) = strings {
+        fbb.push_slot_always(schema::Column::VT_STRINGS, strings);
+    }
+    if let Some(blobs) = blobs {
+        fbb.push_slot_always(schema::Column::VT_BLOBS, blobs);
+    }
+    WIPOffset::new(fbb.end_table(start).value())
+}
+
+fn read_archetype(archetype: &schema::Archetype) -> Result<ComponentArchetype> {
+    let component_id = archetype
+        .component_id()
+        .ok_or_else(|| PackError::Deserialization("Archetype is missing a component id".to_string()))?
+        .to_string();
+
+    let entity_ids = archetype
+        .entity_ids()
+        .iter()
+        .flat_map(|ids| ids.iter())
+        .map(entity_id)
+        .collect::<Result<Vec<_>>>()?;
+
+    let data = if archetype.is_blob() {
+        ComponentData::Blob(archetype.blob().map(|blob| blob.bytes().to_vec()).unwrap_or_default())
+    } else {
+        let mut soa = StructOfArraysData {
+            field_names: Vec::new(),
+            field_types: Vec::new(),
+            field_data: Vec::new(),
+        };
+
+        for column in archetype.columns().iter().flatten() {
+            let data = read_column(&column)?;
+            if data.len() != entity_ids.len() {
+                return Err(PackError::Deserialization(format!(
+                    "Column {}.{} has {} rows, expected {}",
+                    component_id,
+                    column.name().unwrap_or_default(),
+                    data.len(),
+                    entity_ids.len()
+                )));
+            }
+
+            soa.field_names.push(column.name().unwrap_or_default().to_string());
+            soa.field_types.push(data.field_type());
+            soa.field_data.push(data);
+        }
+
+        ComponentData::StructOfArrays(soa)
+    };
+
+    Ok(ComponentArchetype { component_id, entity_ids, data })
+}
+
+fn read_column(column: &schema::Column) -> Result<FieldArray> {
+    let field_type = *FIELD_TYPES
+        .get(column.kind() as usize)
+        .ok_or_else(|| PackError::Deserialization(format!("Unknown column kind {}", column.kind())))?;
+    let packed = column.data().map(|data| data.bytes()).unwrap_or_default();
+
+    macro_rules! unpack {
+        ($variant:ident, $ty:ty) => {{
+            const WIDTH: usize = std::mem::size_of::<$ty>();
+            if !packed.len().is_multiple_of(WIDTH) {
+                return Err(PackError::Deserialization(format!(
+                    "Column {} data is not a multiple of {} bytes", column.name().unwrap_or_default(), WIDTH
+                )));
+            }
+            FieldArray::$variant(
+                packed
+                    .chunks_exact(WIDTH)
+                    .map(|chunk| <$ty>::from_le_bytes(chunk.try_into().expect("chunk has exact width")))
+                    .collect(),
+            )
+        }};
+    }
+
+    Ok(match field_type {
+        FieldType::Bool => FieldArray::Bool(packed.iter().map(|b| *b != 0).collect()),
+        FieldType::I8 => FieldArray::I8(packed.iter().map(|b| *b as i8).collect()),
+        FieldType::I16 => unpack!(I16, i16),
+        FieldType::I32 => unpack!(I32, i32),
+        FieldType::I64 => unpack!(I64, i64),
+        FieldType::U8 => FieldArray::U8(packed.to_vec().into()),
+        FieldType::U16 => unpack!(U16, u16),
+        FieldType::U32 => unpack!(U32, u32),
+        FieldType::U64 => unpack!(U64, u64),
+        FieldType::F32 => unpack!(F32, f32),
+        FieldType::F64 => unpack!(F64, f64),
+        FieldType::String => FieldArray::String(column.strings().iter().flatten().map(str::to_string).collect()),
+        FieldType::Bytes => FieldArray::Bytes(
+            column
+                .blobs()
+                .iter()
+                .flatten()
+                .map(|blob| blob.data().map(|data| data.bytes().to_vec()).unwrap_or_default())
+                .collect(),
+        ),
+    })
+}
+
+fn entity_id(id: u64) -> Result<EntityId> {
+    EntityId::try_from(id).map_err(|_| PackError::Deserialization(format!("Entity id {} is out of range", id)))
+}
+
+// Accessors for schema/tx2pack.fbs, laid out as flatc would generate them.
+mod schema {
+    use flatbuffers::{
+        Follow, ForwardsUOffset, InvalidFlatbuffer, Table, VOffsetT, Verifiable, Vector, Verifier,
+    };
+
+    macro_rules! table {
+        ($name:ident) => {
+            #[derive(Clone, Copy)]
+            pub struct $name<'a> {
+                table: Table<'a>,
+            }
+
+            impl<'a> Follow<'a> for $name<'a> {
+                type Inner = $name<'a>;
+
+                unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+                    Self { table: Table::new(buf, loc) }
+                }
+            }
+        };
+    }
+
+    table!(Column);
+    table!(Bytes);
+    table!(Archetype);
+    table!(EntityMeta);
+    table!(Resource);
+    table!(Snapshot);
+
+    type Strings<'a> = Vector<'a, ForwardsUOffset<&'a str>>;
+    type Tables<'a, T> = Vector<'a, ForwardsUOffset<T>>;
+
+    impl<'a> Column<'a> {
+        pub const VT_NAME: VOffsetT = 4;
+        pub const VT_KIND: VOffsetT = 6;
+        pub const VT_DATA: VOffsetT = 8;
+        pub const VT_STRINGS: VOffsetT = 10;
+        pub const VT_BLOBS: VOffsetT = 12;
+
+        pub fn name(&self) -> Option<&'a str> {
+            unsafe { self.table.get::<ForwardsUOffset<&str>>(Self::VT_NAME, None) }
+        }
+
+        pub fn kind(&self) -> u8 {
+            unsafe { self.table.get::<u8>(Self::VT_KIND, Some(0)).unwrap_or_default() }
+        }
+
+        pub fn data(&self) -> Option<Vector<'a, u8>> {
+            unsafe { self.table.get::<ForwardsUOffset<Vector<'a, u8>>>(Self::VT_DATA, None) }
+        }
+
+        pub fn strings(&self) -> Option<Strings<'a>> {
+            unsafe { self.table.get::<ForwardsUOffset<Strings<'a>>>(Self::VT_STRINGS, None) }
+        }
+
+        pub fn blobs(&self) -> Option<Tables<'a, Bytes<'a>>> {
+            unsafe { self.table.get::<ForwardsUOffset<Tables<'a, Bytes<'a>>>>(Self::VT_BLOBS, None) }
+        }
+    }
+
+    impl Verifiable for Column<'_> {
+        fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+            v.visit_table(pos)?
+                .visit_field::<ForwardsUOffset<&str>>("name", Self::VT_NAME, false)?
+                .visit_field::<u8>("kind", Self::VT_KIND, false)?
+                .visit_field::<ForwardsUOffset<Vector<'_, u8>>>("data", Self::VT_DATA, false)?
+                .visit_field::<ForwardsUOffset<Strings<'_>>>("strings", Self::VT_STRINGS, false)?
+                .visit_field::<ForwardsUOffset<Tables<'_, Bytes>>>("blobs", Self::VT_BLOBS, false)?
+                .finish();
+            Ok(())
+        }
+    }
+
+    impl<'a> Bytes<'a> {
+        pub const VT_DATA: VOffsetT = 4;
+
+        pub fn data(&self) -> Option<Vector<'a, u8>> {
+            unsafe { self.table.get::<ForwardsUOffset<Vector<'a, u8>>>(Self::VT_DATA, None) }
+        }
+    }
+
+    impl Verifiable for Bytes<'_> {
+        fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+            v.visit_table(pos)?
+                .visit_field::<ForwardsUOffset<Vector<'_, u8>>>("data", Self::VT_DATA, false)?
+                .finish();
+            Ok(())
+        }
+    }
+
+    impl<'a> Archetype<'a> {
+        pub const VT_COMPONENT_ID: VOffsetT = 4;
+        pub const VT_ENTITY_IDS: VOffsetT = 6;
+        pub const VT_COLUMNS: VOffsetT = 8;
+        pub const VT_IS_BLOB: VOffsetT = 10;
+        pub const VT_BLOB: VOffsetT = 12;
+
+        pub fn component_id(&self) -> Option<&'a str> {
+            unsafe { self.table.get::<ForwardsUOffset<&str>>(Self::VT_COMPONENT_ID, None) }
+        }
+
+        pub fn entity_ids(&self) -> Option<Vector<'a, u64>> {
+            unsafe { self.table.get::<ForwardsUOffset<Vector<'a, u64>>>(Self::VT_ENTITY_IDS, None) }
+        }
+
+        pub fn columns(&self) -> Option<Tables<'a, Column<'a>>> {
+            unsafe { self.table.get::<ForwardsUOffset<Tables<'a, Column<'a>>>>(Self::VT_COLUMNS, None) }
+        }
+
+        pub fn is_blob(&self) -> bool {
+            unsafe { self.table.get::<bool>(Self::VT_IS_BLOB, Some(false)).unwrap_or_default() }
+        }
+
+        pub fn blob(&self) -> Option<Vector<'a, u8>> {
+            unsafe { self.table.get::<ForwardsUOffset<Vector<'a, u8>>>(Self::VT_BLOB, None) }
+        }
+    }
+
+    impl Verifiable for Archetype<'_> {
+        fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+            v.visit_table(pos)?
+                .visit_field::<ForwardsUOffset<&str>>("component_id", Self::VT_COMPONENT_ID, false)?
+                .visit_field::<ForwardsUOffset<Vector<'_, u64>>>("entity_ids", Self::VT_ENTITY_IDS, false)?
+                .visit_field::<ForwardsUOffset<Tables<'_, Column>>>("columns", Self::VT_COLUMNS, false)?
+                .visit_field::<bool>("is_blob", Self::VT_IS_BLOB, false)?
+                .visit_field::<ForwardsUOffset<Vector<'_, u8>>>("blob", Self::VT_BLOB, false)?
+                .finish();
+            Ok(())
+        }
+    }
+
+    impl<'a> EntityMeta<'a> {
+        pub const VT_ENTITY_ID: VOffsetT = 4;
+        pub const VT_CREATED_AT: VOffsetT = 6;
+        pub const VT_MODIFIED_AT: VOffsetT = 8;
+        pub const VT_TAGS: VOffsetT = 10;
+        pub const VT_ANNOTATIONS: VOffsetT = 12;
+
+        pub fn entity_id(&self) -> u64 {
+            unsafe { self.table.get::<u64>(Self::VT_ENTITY_ID, Some(0)).unwrap_or_default() }
+        }
+
+        pub fn created_at(&self) -> i64 {
+            unsafe { self.table.get::<i64>(Self::VT_CREATED_AT, Some(0)).unwrap_or_default() }
+        }
+
+        pub fn modified_at(&self) -> i64 {
+            unsafe { self.table.get::<i64>(Self::VT_MODIFIED_AT, Some(0)).unwrap_or_default() }
+        }
+
+        pub fn tags(&self) -> Option<Strings<'a>> {
+            unsafe { self.table.get::<ForwardsUOffset<Strings<'a>>>(Self::VT_TAGS, None) }
+        }
+
+        pub fn annotations(&self) -> Option<Tables<'a, Column<'a>>> {
+            unsafe { self.table.get::<ForwardsUOffset<Tables<'a, Column<'a>>>>(Self::VT_ANNOTATIONS, None) }
+        }
+    }
+
+    impl Verifiable for EntityMeta<'_> {
+        fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+            v.visit_table(pos)?
+                .visit_field::<u64>("entity_id", Self::VT_ENTITY_ID, false)?
+                .visit_field::<i64>("created_at", Self::VT_CREATED_AT, false)?
+                .visit_field::<i64>("modified_at", Self::VT_MODIFIED_AT, false)?
+                .visit_field::<ForwardsUOffset<Strings<'_>>>("tags", Self::VT_TAGS, false)?
+                .visit_field::<ForwardsUOffset<Tables<'_, Column>>>("annotations", Self::VT_ANNOTATIONS, false)?
+                .finish();
+            Ok(())
+        }
+    }
+
+    impl<'a> Resource<'a> {
+        pub const VT_NAME: VOffsetT = 4;
+        pub const VT_DATA: VOffsetT = 6;
+
+        pub fn name(&self) -> Option<&'a str> {
+            unsafe { self.table.get::<ForwardsUOffset<&str>>(Self::VT_NAME, None) }
+        }
+
+        pub fn data(&self) -> Option<Vector<'a, u8>> {
+            unsafe { self.table.get::<ForwardsUOffset<Vector<'a, u8>>>(Self::VT_DATA, None) }
+        }
+    }
+
+    impl Verifiable for Resource<'_> {
+        fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+            v.visit_table(pos)?
+                .visit_field::<ForwardsUOffset<&str>>("name", Self::VT_NAME, false)?
+                .visit_field::<ForwardsUOffset<Vector<'_, u8>>>("data", Self::VT_DATA, false)?
+                .finish();
+            Ok(())
+        }
+    }
+
+    impl<'a> Snapshot<'a> {
+        pub const VT_TIMESTAMP: VOffsetT = 4;
+        pub const VT_ARCHETYPES: VOffsetT = 6;
+        pub const VT_ENTITY_METADATA: VOffsetT = 8;
+        pub const VT_RESOURCES: VOffsetT = 10;
+
+        pub fn timestamp(&self) -> i64 {
+            unsafe { self.table.get::<i64>(Self::VT_TIMESTAMP, Some(0)).unwrap_or_default() }
+        }
+
+        pub fn archetypes(&self) -> Option<Tables<'a, Archetype<'a>>> {
+            unsafe { self.table.get::<ForwardsUOffset<Tables<'a, Archetype<'a>>>>(Self::VT_ARCHETYPES, None) }
+        }
+
+        pub fn entity_metadata(&self) -> Option<Tables<'a, EntityMeta<'a>>> {
+            unsafe { self.table.get::<ForwardsUOffset<Tables<'a, EntityMeta<'a>>>>(Self::VT_ENTITY_METADATA, None) }
+        }
+
+        pub fn resources(&self) -> Option<Tables<'a, Resource<'a>>> {
+            unsafe { self.table.get::<ForwardsUOffset<Tables<'a, Resource<'a>>>>(Self::VT_RESOURCES, None) }
+        }
+    }
+
+    impl Verifiable for Snapshot<'_> {
+        fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+            v.visit_table(pos)?
+                .visit_field::<i64>("timestamp", Self::VT_TIMESTAMP, false)?
+                .visit_field::<ForwardsUOffset<Tables<'_, Archetype>>>("archetypes", Self::VT_ARCHETYPES, false)?
+                .visit_field::<ForwardsUOffset<Tables<'_, EntityMeta>>>("entity_metadata", Self::VT_ENTITY_METADATA, false)?
+                .visit_field::<ForwardsUOffset<Tables<'_, Resource>>>("resources", Self::VT_RESOURCES, false)?
+                .finish();
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionCodec;
+    use crate::format::{FieldValue, PackFormat};
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+
+    #[test]
+    fn test_flatbuffers_roundtrip() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::FlatBuffers;
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Unit".to_string(),
+            entity_ids: vec![4, 9],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["hp".to_string(), "name".to_string(), "alive".to_string(), "raw".to_string()],
+                field_types: vec![FieldType::F64, FieldType::String, FieldType::Bool, FieldType::Bytes],
+                field_data: vec![
+                    FieldArray::F64(vec![1.5, -2.0].into()),
+                    FieldArray::String(vec!["a".to_string(), "b".to_string()].into()),
+                    FieldArray::Bool(vec![false, true].into()),
+                    FieldArray::Bytes(vec![vec![1, 2], vec![]].into()),
+                ],
+            }),
+        });
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Script".to_string(),
+            entity_ids: vec![9],
+            data: ComponentData::Blob(vec![7, 7, 7]),
+        });
+        snapshot.annotate(4, "kills", FieldValue::U16(12));
+        snapshot.rebuild_entity_index();
+
+        let payload = encode_snapshot(&snapshot);
+        assert!(flatbuffers::buffer_has_identifier(&payload, FLATBUFFERS_IDENTIFIER, false));
+
+        let bytes = SnapshotWriter::new()
+            .with_compression(CompressionCodec::none())
+            .write_to_bytes(&snapshot)
+            .unwrap();
+        let loaded = SnapshotReader::new().read_from_bytes(&bytes).unwrap();
+
+        assert_eq!(loaded.header.format, PackFormat::FlatBuffers);
+        assert_eq!(loaded.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+        assert_eq!(loaded.annotation(4, "kills"), Some(&FieldValue::U16(12)));
+
+        let mut corrupt = payload.clone();
+        let len = corrupt.len();
+        corrupt.truncate(len / 2);
+        assert!(decode_snapshot(&corrupt).is_err());
+    }
+}
@@ -1,25 +1,55 @@
+use crate::checksum::ChecksumAlgorithm;
+use crate::column::Column;
+use crate::layout::NativeLayout;
 use serde::{Deserialize, Serialize};
 use tx2_link::{EntityId, ComponentId};
-use ahash::AHashMap;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 
 pub const MAGIC_NUMBER: &[u8; 8] = b"TX2PACK\0";
-pub const FORMAT_VERSION: u32 = 1;
+pub const DELTA_MAGIC_NUMBER: &[u8; 8] = b"TX2DELTA";
+pub const PATCH_MAGIC_NUMBER: &[u8; 8] = b"TX2PATCH";
+// The major version sits in the low 16 bits and the minor in the high 16, so
+// 1.x packs, written before the split, read back as major 1. A minor bump may
+// only add what older readers can skip: unlisted sections, trailing table of
+// contents or payload fields, or bytes between the header and data_offset.
+// 2.1 added resources, 2.2 native layouts and delta and patch tombstones.
+pub const FORMAT_VERSION_MAJOR: u16 = 2;
+pub const FORMAT_VERSION_MINOR: u16 = 2;
+pub const FORMAT_VERSION: u32 = format_version(FORMAT_VERSION_MAJOR, FORMAT_VERSION_MINOR);
+pub const HEADER_SIZE: u64 = 125;
+// Snapshots from 1.x are still read, through crate::legacy; see there.
+pub const LEGACY_VERSION_MAJOR: u16 = 1;
+
+pub const FLAG_DELTA_ENTITY_IDS: u32 = 1 << 0;
+pub const FLAG_CHUNK_CRC32C: u32 = 1 << 1;
+pub const KNOWN_FLAGS: u32 = FLAG_DELTA_ENTITY_IDS | FLAG_CHUNK_CRC32C;
+
+pub const fn format_version(major: u16, minor: u16) -> u32 {
+    major as u32 | (minor as u32) << 16
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub enum PackFormat {
     Bincode,
     MessagePack,
     Custom,
+    FlatBuffers,
+    Cbor,
+    Rkyv,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct SnapshotHeader {
     pub magic: [u8; 8],
     pub version: u32,
     pub format: PackFormat,
     pub compression: CompressionType,
     pub encrypted: bool,
+    pub flags: u32,
+    pub checksum_algorithm: ChecksumAlgorithm,
     pub checksum: [u8; 32],
     pub timestamp: i64,
     pub entity_count: u64,
@@ -39,6 +69,8 @@ impl SnapshotHeader {
             format: PackFormat::Bincode,
             compression: CompressionType::Zstd,
             encrypted: false,
+            flags: 0,
+            checksum_algorithm: ChecksumAlgorithm::Sha256,
             checksum: [0u8; 32],
             timestamp: chrono::Utc::now().timestamp(),
             entity_count: 0,
@@ -51,17 +83,71 @@ impl SnapshotHeader {
         }
     }
 
+    pub fn encode_into(&self, out: &mut Vec<u8>) -> crate::Result<()> {
+        let start = out.len();
+        bincode::serialize_into(&mut *out, self)?;
+
+        let written = (out.len() - start) as u64;
+        if written != HEADER_SIZE {
+            return Err(crate::PackError::InvalidFormat(
+                format!("Header encoded to {} bytes, expected {}", written, HEADER_SIZE)
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn decode(bytes: &[u8]) -> crate::Result<Self> {
+        if crate::legacy::is_legacy(bytes) {
+            return crate::legacy::decode_header(bytes);
+        }
+        let encoded = bytes.get(..HEADER_SIZE as usize).ok_or_else(|| {
+            crate::PackError::InvalidFormat(format!("Buffer of {} bytes is shorter than the header", bytes.len()))
+        })?;
+        let header: Self = bincode::deserialize(encoded)?;
+
+        if header.data_offset < HEADER_SIZE {
+            return Err(crate::PackError::InvalidFormat(
+                format!("Data offset {} overlaps the {}-byte header", header.data_offset, HEADER_SIZE)
+            ));
+        }
+
+        Ok(header)
+    }
+
+    pub fn version_major(&self) -> u16 {
+        self.version as u16
+    }
+
+    pub fn version_minor(&self) -> u16 {
+        (self.version >> 16) as u16
+    }
+
+    // 1.x headers are shorter and have no copy after the data.
+    pub fn is_legacy(&self) -> bool {
+        self.version_major() == LEGACY_VERSION_MAJOR
+    }
+
+    pub fn is_newer_minor(&self) -> bool {
+        self.version_major() == FORMAT_VERSION_MAJOR && self.version_minor() > FORMAT_VERSION_MINOR
+    }
+
     pub fn validate(&self) -> crate::Result<()> {
-        if self.magic != *MAGIC_NUMBER {
+        self.validate_with_magic(MAGIC_NUMBER)
+    }
+
+    pub fn validate_with_magic(&self, magic: &[u8; 8]) -> crate::Result<()> {
+        if self.magic != *magic {
             return Err(crate::PackError::InvalidFormat(
                 "Invalid magic number".to_string()
             ));
         }
 
-        if self.version != FORMAT_VERSION {
+        let legacy_snapshot = self.is_legacy() && magic == MAGIC_NUMBER;
+        if self.version_major() != FORMAT_VERSION_MAJOR && !legacy_snapshot {
             return Err(crate::PackError::VersionMismatch {
-                expected: FORMAT_VERSION.to_string(),
-                actual: self.version.to_string(),
+                expected: format!("{}.x", FORMAT_VERSION_MAJOR),
+                actual: format!("{}.{}", self.version_major(), self.version_minor()),
             });
         }
 
@@ -76,6 +162,7 @@ impl Default for SnapshotHeader {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub enum CompressionType {
     None,
     Zstd,
@@ -83,19 +170,122 @@ pub enum CompressionType {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct ComponentArchetype {
     pub component_id: ComponentId,
     pub entity_ids: Vec<EntityId>,
     pub data: ComponentData,
 }
 
+impl ComponentArchetype {
+    pub fn is_empty(&self) -> bool {
+        self.entity_ids.is_empty()
+            && match &self.data {
+                ComponentData::StructOfArrays(_) => true,
+                ComponentData::Blob(data) => data.is_empty(),
+            }
+    }
+
+    pub fn retain_entities<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(EntityId) -> bool,
+    {
+        let mask: Vec<bool> = self.entity_ids.iter().map(|id| keep(*id)).collect();
+
+        retain_by_mask(&mut self.entity_ids, &mask);
+
+        if let ComponentData::StructOfArrays(soa) = &mut self.data {
+            for column in &mut soa.field_data {
+                column.retain_rows(&mask);
+            }
+        }
+    }
+
+    pub fn sort_by_entity_id(&mut self) -> bool {
+        if self.entity_ids.is_sorted() {
+            return true;
+        }
+
+        let soa = match &mut self.data {
+            ComponentData::StructOfArrays(soa) => soa,
+            ComponentData::Blob(_) => return false,
+        };
+
+        let mut order: Vec<usize> = (0..self.entity_ids.len()).collect();
+        order.sort_by_key(|&row| self.entity_ids[row]);
+
+        self.entity_ids = order.iter().map(|&row| self.entity_ids[row]).collect();
+        for column in &mut soa.field_data {
+            *column = column.gather(&order);
+        }
+
+        true
+    }
+
+    pub fn check_consistency(&self) -> crate::Result<()> {
+        match &self.data {
+            ComponentData::StructOfArrays(soa) => check_columns(
+                &self.component_id,
+                self.entity_ids.len(),
+                &soa.field_names,
+                &soa.field_types,
+                soa.field_data.iter().map(|column| (column.field_type(), column.len())),
+            ),
+            ComponentData::Blob(_) => Ok(()),
+        }
+    }
+
+    pub fn world_components(&self) -> crate::Result<Vec<tx2_link::SerializedComponent>> {
+        match &self.data {
+            ComponentData::Blob(payload) => bincode::deserialize(payload)
+                .map_err(|e| crate::PackError::Deserialization(e.to_string())),
+            ComponentData::StructOfArrays(_) => Err(crate::PackError::InvalidFormat(
+                format!("{} is stored as columns, not world components", self.component_id)
+            )),
+        }
+    }
+
+    pub fn estimated_heap_size(&self) -> usize {
+        let data = match &self.data {
+            ComponentData::StructOfArrays(soa) => {
+                vec_heap_size(&soa.field_names)
+                    + soa.field_names.iter().map(String::capacity).sum::<usize>()
+                    + vec_heap_size(&soa.field_types)
+                    + vec_heap_size(&soa.field_data)
+                    + soa.field_data.iter().map(FieldArray::estimated_heap_size).sum::<usize>()
+            }
+            ComponentData::Blob(payload) => payload.capacity(),
+        };
+
+        self.component_id.capacity() + vec_heap_size(&self.entity_ids) + data
+    }
+
+    pub fn row_of(&self, entity_id: EntityId) -> Option<usize> {
+        self.entity_ids.iter().position(|id| *id == entity_id)
+    }
+
+    pub fn row_values(&self, row: usize) -> Option<Vec<(String, FieldValue)>> {
+        match &self.data {
+            ComponentData::StructOfArrays(soa) => soa
+                .field_names
+                .iter()
+                .zip(&soa.field_data)
+                .map(|(name, column)| column.get(row).map(|value| (name.clone(), value)))
+                .collect(),
+            ComponentData::Blob(_) => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub enum ComponentData {
     StructOfArrays(StructOfArraysData),
     Blob(Vec<u8>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct StructOfArraysData {
     pub field_names: Vec<String>,
     pub field_types: Vec<FieldType>,
@@ -103,6 +293,7 @@ pub struct StructOfArraysData {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub enum FieldType {
     Bool,
     I8,
@@ -119,21 +310,22 @@ pub enum FieldType {
     Bytes,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub enum FieldArray {
-    Bool(Vec<bool>),
-    I8(Vec<i8>),
-    I16(Vec<i16>),
-    I32(Vec<i32>),
-    I64(Vec<i64>),
-    U8(Vec<u8>),
-    U16(Vec<u16>),
-    U32(Vec<u32>),
-    U64(Vec<u64>),
-    F32(Vec<f32>),
-    F64(Vec<f64>),
-    String(Vec<String>),
-    Bytes(Vec<Vec<u8>>),
+    Bool(Column<bool>),
+    I8(Column<i8>),
+    I16(Column<i16>),
+    I32(Column<i32>),
+    I64(Column<i64>),
+    U8(Column<u8>),
+    U16(Column<u16>),
+    U32(Column<u32>),
+    U64(Column<u64>),
+    F32(Column<f32>),
+    F64(Column<f64>),
+    String(Column<String>),
+    Bytes(Column<Vec<u8>>),
 }
 
 impl FieldArray {
@@ -158,20 +350,463 @@ impl FieldArray {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            FieldArray::Bool(_) => FieldType::Bool,
+            FieldArray::I8(_) => FieldType::I8,
+            FieldArray::I16(_) => FieldType::I16,
+            FieldArray::I32(_) => FieldType::I32,
+            FieldArray::I64(_) => FieldType::I64,
+            FieldArray::U8(_) => FieldType::U8,
+            FieldArray::U16(_) => FieldType::U16,
+            FieldArray::U32(_) => FieldType::U32,
+            FieldArray::U64(_) => FieldType::U64,
+            FieldArray::F32(_) => FieldType::F32,
+            FieldArray::F64(_) => FieldType::F64,
+            FieldArray::String(_) => FieldType::String,
+            FieldArray::Bytes(_) => FieldType::Bytes,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<FieldValue> {
+        match self {
+            FieldArray::Bool(v) => v.get(index).map(|x| FieldValue::Bool(*x)),
+            FieldArray::I8(v) => v.get(index).map(|x| FieldValue::I8(*x)),
+            FieldArray::I16(v) => v.get(index).map(|x| FieldValue::I16(*x)),
+            FieldArray::I32(v) => v.get(index).map(|x| FieldValue::I32(*x)),
+            FieldArray::I64(v) => v.get(index).map(|x| FieldValue::I64(*x)),
+            FieldArray::U8(v) => v.get(index).map(|x| FieldValue::U8(*x)),
+            FieldArray::U16(v) => v.get(index).map(|x| FieldValue::U16(*x)),
+            FieldArray::U32(v) => v.get(index).map(|x| FieldValue::U32(*x)),
+            FieldArray::U64(v) => v.get(index).map(|x| FieldValue::U64(*x)),
+            FieldArray::F32(v) => v.get(index).map(|x| FieldValue::F32(*x)),
+            FieldArray::F64(v) => v.get(index).map(|x| FieldValue::F64(*x)),
+            FieldArray::String(v) => v.get(index).map(|x| FieldValue::String(x.clone())),
+            FieldArray::Bytes(v) => v.get(index).map(|x| FieldValue::Bytes(x.clone())),
+        }
+    }
+
+    pub fn with_type(field_type: FieldType) -> Self {
+        match field_type {
+            FieldType::Bool => FieldArray::Bool(Column::new()),
+            FieldType::I8 => FieldArray::I8(Column::new()),
+            FieldType::I16 => FieldArray::I16(Column::new()),
+            FieldType::I32 => FieldArray::I32(Column::new()),
+            FieldType::I64 => FieldArray::I64(Column::new()),
+            FieldType::U8 => FieldArray::U8(Column::new()),
+            FieldType::U16 => FieldArray::U16(Column::new()),
+            FieldType::U32 => FieldArray::U32(Column::new()),
+            FieldType::U64 => FieldArray::U64(Column::new()),
+            FieldType::F32 => FieldArray::F32(Column::new()),
+            FieldType::F64 => FieldArray::F64(Column::new()),
+            FieldType::String => FieldArray::String(Column::new()),
+            FieldType::Bytes => FieldArray::Bytes(Column::new()),
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        match self {
+            FieldArray::Bool(column) => column.reserve(additional),
+            FieldArray::I8(column) => column.reserve(additional),
+            FieldArray::I16(column) => column.reserve(additional),
+            FieldArray::I32(column) => column.reserve(additional),
+            FieldArray::I64(column) => column.reserve(additional),
+            FieldArray::U8(column) => column.reserve(additional),
+            FieldArray::U16(column) => column.reserve(additional),
+            FieldArray::U32(column) => column.reserve(additional),
+            FieldArray::U64(column) => column.reserve(additional),
+            FieldArray::F32(column) => column.reserve(additional),
+            FieldArray::F64(column) => column.reserve(additional),
+            FieldArray::String(column) => column.reserve(additional),
+            FieldArray::Bytes(column) => column.reserve(additional),
+        }
+    }
+
+    pub fn push(&mut self, value: FieldValue) -> crate::Result<()> {
+        match (self, value) {
+            (FieldArray::Bool(column), FieldValue::Bool(value)) => column.push(value),
+            (FieldArray::I8(column), FieldValue::I8(value)) => column.push(value),
+            (FieldArray::I16(column), FieldValue::I16(value)) => column.push(value),
+            (FieldArray::I32(column), FieldValue::I32(value)) => column.push(value),
+            (FieldArray::I64(column), FieldValue::I64(value)) => column.push(value),
+            (FieldArray::U8(column), FieldValue::U8(value)) => column.push(value),
+            (FieldArray::U16(column), FieldValue::U16(value)) => column.push(value),
+            (FieldArray::U32(column), FieldValue::U32(value)) => column.push(value),
+            (FieldArray::U64(column), FieldValue::U64(value)) => column.push(value),
+            (FieldArray::F32(column), FieldValue::F32(value)) => column.push(value),
+            (FieldArray::F64(column), FieldValue::F64(value)) => column.push(value),
+            (FieldArray::String(column), FieldValue::String(value)) => column.push(value),
+            (FieldArray::Bytes(column), FieldValue::Bytes(value)) => column.push(value),
+            (column, value) => return Err(type_mismatch(column.field_type(), &value)),
+        }
+
+        Ok(())
+    }
+
+    pub fn set(&mut self, index: usize, value: FieldValue) -> crate::Result<()> {
+        if index >= self.len() {
+            return Err(crate::PackError::InvalidFormat(
+                format!("Row {} out of bounds for column of length {}", index, self.len())
+            ));
+        }
+
+        match (self, value) {
+            (FieldArray::Bool(column), FieldValue::Bool(value)) => column[index] = value,
+            (FieldArray::I8(column), FieldValue::I8(value)) => column[index] = value,
+            (FieldArray::I16(column), FieldValue::I16(value)) => column[index] = value,
+            (FieldArray::I32(column), FieldValue::I32(value)) => column[index] = value,
+            (FieldArray::I64(column), FieldValue::I64(value)) => column[index] = value,
+            (FieldArray::U8(column), FieldValue::U8(value)) => column[index] = value,
+            (FieldArray::U16(column), FieldValue::U16(value)) => column[index] = value,
+            (FieldArray::U32(column), FieldValue::U32(value)) => column[index] = value,
+            (FieldArray::U64(column), FieldValue::U64(value)) => column[index] = value,
+            (FieldArray::F32(column), FieldValue::F32(value)) => column[index] = value,
+            (FieldArray::F64(column), FieldValue::F64(value)) => column[index] = value,
+            (FieldArray::String(column), FieldValue::String(value)) => column[index] = value,
+            (FieldArray::Bytes(column), FieldValue::Bytes(value)) => column[index] = value,
+            (column, value) => return Err(type_mismatch(column.field_type(), &value)),
+        }
+
+        Ok(())
+    }
+
+    pub fn retain_rows(&mut self, keep: &[bool]) {
+        match self {
+            FieldArray::Bool(column) => retain_by_mask(column, keep),
+            FieldArray::I8(column) => retain_by_mask(column, keep),
+            FieldArray::I16(column) => retain_by_mask(column, keep),
+            FieldArray::I32(column) => retain_by_mask(column, keep),
+            FieldArray::I64(column) => retain_by_mask(column, keep),
+            FieldArray::U8(column) => retain_by_mask(column, keep),
+            FieldArray::U16(column) => retain_by_mask(column, keep),
+            FieldArray::U32(column) => retain_by_mask(column, keep),
+            FieldArray::U64(column) => retain_by_mask(column, keep),
+            FieldArray::F32(column) => retain_by_mask(column, keep),
+            FieldArray::F64(column) => retain_by_mask(column, keep),
+            FieldArray::String(column) => retain_by_mask(column, keep),
+            FieldArray::Bytes(column) => retain_by_mask(column, keep),
+        }
+    }
+
+    pub fn gather(&self, indices: &[usize]) -> FieldArray {
+        fn pick<T: Clone>(values: &[T], indices: &[usize]) -> Vec<T> {
+            indices.iter().map(|&i| values[i].clone()).collect()
+        }
+
+        match self {
+            FieldArray::Bool(v) => FieldArray::Bool(pick(v, indices).into()),
+            FieldArray::I8(v) => FieldArray::I8(pick(v, indices).into()),
+            FieldArray::I16(v) => FieldArray::I16(pick(v, indices).into()),
+            FieldArray::I32(v) => FieldArray::I32(pick(v, indices).into()),
+            FieldArray::I64(v) => FieldArray::I64(pick(v, indices).into()),
+            FieldArray::U8(v) => FieldArray::U8(pick(v, indices).into()),
+            FieldArray::U16(v) => FieldArray::U16(pick(v, indices).into()),
+            FieldArray::U32(v) => FieldArray::U32(pick(v, indices).into()),
+            FieldArray::U64(v) => FieldArray::U64(pick(v, indices).into()),
+            FieldArray::F32(v) => FieldArray::F32(pick(v, indices).into()),
+            FieldArray::F64(v) => FieldArray::F64(pick(v, indices).into()),
+            FieldArray::String(v) => FieldArray::String(pick(v, indices).into()),
+            FieldArray::Bytes(v) => FieldArray::Bytes(pick(v, indices).into()),
+        }
+    }
+
+    pub fn estimated_heap_size(&self) -> usize {
+        match self {
+            FieldArray::Bool(v) => vec_heap_size(v),
+            FieldArray::I8(v) => vec_heap_size(v),
+            FieldArray::I16(v) => vec_heap_size(v),
+            FieldArray::I32(v) => vec_heap_size(v),
+            FieldArray::I64(v) => vec_heap_size(v),
+            FieldArray::U8(v) => vec_heap_size(v),
+            FieldArray::U16(v) => vec_heap_size(v),
+            FieldArray::U32(v) => vec_heap_size(v),
+            FieldArray::U64(v) => vec_heap_size(v),
+            FieldArray::F32(v) => vec_heap_size(v),
+            FieldArray::F64(v) => vec_heap_size(v),
+            FieldArray::String(v) => vec_heap_size(v) + v.iter().map(String::capacity).sum::<usize>(),
+            FieldArray::Bytes(v) => vec_heap_size(v) + v.iter().map(Vec::capacity).sum::<usize>(),
+        }
+    }
+}
+
+fn vec_heap_size<T>(values: &Vec<T>) -> usize {
+    values.capacity() * std::mem::size_of::<T>()
+}
+
+fn hash_map_heap_size<K, V>(map: &HashMap<K, V>) -> usize {
+    map.capacity() * (std::mem::size_of::<(K, V)>() + 1)
+}
+
+fn type_mismatch(column: FieldType, value: &FieldValue) -> crate::PackError {
+    crate::PackError::InvalidFormat(
+        format!("Cannot store {:?} value in {:?} column", value.field_type(), column)
+    )
+}
+
+fn retain_by_mask<T>(values: &mut Vec<T>, keep: &[bool]) {
+    let mut row = 0;
+    values.retain(|_| {
+        let kept = keep.get(row).copied().unwrap_or(true);
+        row += 1;
+        kept
+    });
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub enum FieldValue {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Bool(v) => write!(f, "{}", v),
+            FieldValue::I8(v) => write!(f, "{}", v),
+            FieldValue::I16(v) => write!(f, "{}", v),
+            FieldValue::I32(v) => write!(f, "{}", v),
+            FieldValue::I64(v) => write!(f, "{}", v),
+            FieldValue::U8(v) => write!(f, "{}", v),
+            FieldValue::U16(v) => write!(f, "{}", v),
+            FieldValue::U32(v) => write!(f, "{}", v),
+            FieldValue::U64(v) => write!(f, "{}", v),
+            FieldValue::F32(v) => write!(f, "{:?}", v),
+            FieldValue::F64(v) => write!(f, "{:?}", v),
+            FieldValue::String(v) => write!(f, "{:?}", v),
+            FieldValue::Bytes(v) => write!(f, "<{} bytes>", v.len()),
+        }
+    }
+}
+
+impl FieldValue {
+    pub fn estimated_heap_size(&self) -> usize {
+        match self {
+            FieldValue::String(v) => v.capacity(),
+            FieldValue::Bytes(v) => v.capacity(),
+            _ => 0,
+        }
+    }
+
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            FieldValue::Bool(_) => FieldType::Bool,
+            FieldValue::I8(_) => FieldType::I8,
+            FieldValue::I16(_) => FieldType::I16,
+            FieldValue::I32(_) => FieldType::I32,
+            FieldValue::I64(_) => FieldType::I64,
+            FieldValue::U8(_) => FieldType::U8,
+            FieldValue::U16(_) => FieldType::U16,
+            FieldValue::U32(_) => FieldType::U32,
+            FieldValue::U64(_) => FieldType::U64,
+            FieldValue::F32(_) => FieldType::F32,
+            FieldValue::F64(_) => FieldType::F64,
+            FieldValue::String(_) => FieldType::String,
+            FieldValue::Bytes(_) => FieldType::Bytes,
+        }
+    }
+
+    pub fn as_i128(&self) -> Option<i128> {
+        match self {
+            FieldValue::I8(v) => Some(*v as i128),
+            FieldValue::I16(v) => Some(*v as i128),
+            FieldValue::I32(v) => Some(*v as i128),
+            FieldValue::I64(v) => Some(*v as i128),
+            FieldValue::U8(v) => Some(*v as i128),
+            FieldValue::U16(v) => Some(*v as i128),
+            FieldValue::U32(v) => Some(*v as i128),
+            FieldValue::U64(v) => Some(*v as i128),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            FieldValue::F32(v) => Some(*v as f64),
+            FieldValue::F64(v) => Some(*v),
+            other => other.as_i128().map(|v| v as f64),
+        }
+    }
+
+    pub fn compare(&self, other: &FieldValue) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (FieldValue::Bool(a), FieldValue::Bool(b)) => Some(a.cmp(b)),
+            (FieldValue::String(a), FieldValue::String(b)) => Some(a.cmp(b)),
+            (FieldValue::Bytes(a), FieldValue::Bytes(b)) => Some(a.cmp(b)),
+            (a, b) => match (a.as_i128(), b.as_i128()) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct PackedSnapshot {
     pub header: SnapshotHeader,
     pub archetypes: Vec<ComponentArchetype>,
     pub entity_metadata: HashMap<EntityId, EntityMetadata>,
+    #[serde(default)]
+    pub entity_index: HashMap<EntityId, Vec<EntityLocation>>,
+    // World-level state that belongs to no entity (RNG streams, schedule
+    // tick counters), keyed by name and opaque to the pack. Rkyv archives
+    // keep their 2.0 layout, so that format refuses snapshots carrying any.
+    #[serde(default)]
+    #[cfg_attr(feature = "rkyv", rkyv(with = rkyv::with::Skip))]
+    pub resources: BTreeMap<String, Vec<u8>>,
+    // The world's own table layout, when it captured in it. Only a hint for
+    // restore: it is left out of checksums, and formats that cannot carry it
+    // (rkyv, FlatBuffers) drop it, which falls back to per-component restore.
+    #[serde(default)]
+    #[cfg_attr(feature = "rkyv", rkyv(with = rkyv::with::Skip))]
+    pub layout: Option<NativeLayout>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Bincode cannot tell a missing trailing field from a truncated payload, so
+// snapshots written before resources existed are decoded through this.
+#[derive(Deserialize)]
+pub(crate) struct PackedSnapshotV2_0 {
+    header: SnapshotHeader,
+    archetypes: Vec<ComponentArchetype>,
+    entity_metadata: HashMap<EntityId, EntityMetadata>,
+    entity_index: HashMap<EntityId, Vec<EntityLocation>>,
+}
+
+impl From<PackedSnapshotV2_0> for PackedSnapshot {
+    fn from(snapshot: PackedSnapshotV2_0) -> Self {
+        Self {
+            header: snapshot.header,
+            archetypes: snapshot.archetypes,
+            entity_metadata: snapshot.entity_metadata,
+            entity_index: snapshot.entity_index,
+            resources: BTreeMap::new(),
+            layout: None,
+        }
+    }
+}
+
+// 2.1 added resources; the layout came in 2.2.
+#[derive(Deserialize)]
+pub(crate) struct PackedSnapshotV2_1 {
+    header: SnapshotHeader,
+    archetypes: Vec<ComponentArchetype>,
+    entity_metadata: HashMap<EntityId, EntityMetadata>,
+    entity_index: HashMap<EntityId, Vec<EntityLocation>>,
+    resources: BTreeMap<String, Vec<u8>>,
+}
+
+impl From<PackedSnapshotV2_1> for PackedSnapshot {
+    fn from(snapshot: PackedSnapshotV2_1) -> Self {
+        Self {
+            header: snapshot.header,
+            archetypes: snapshot.archetypes,
+            entity_metadata: snapshot.entity_metadata,
+            entity_index: snapshot.entity_index,
+            resources: snapshot.resources,
+            layout: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct EntityLocation {
+    pub archetype: usize,
+    pub row: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct EntityMetadata {
     pub created_at: i64,
     pub modified_at: i64,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub annotations: HashMap<String, FieldValue>,
+}
+
+impl EntityMetadata {
+    pub fn new() -> Self {
+        let now = chrono::Utc::now().timestamp();
+
+        Self {
+            created_at: now,
+            modified_at: now,
+            tags: Vec::new(),
+            annotations: HashMap::new(),
+        }
+    }
+}
+
+impl EntityMetadata {
+    pub fn estimated_heap_size(&self) -> usize {
+        vec_heap_size(&self.tags)
+            + self.tags.iter().map(String::capacity).sum::<usize>()
+            + hash_map_heap_size(&self.annotations)
+            + self
+                .annotations
+                .iter()
+                .map(|(key, value)| key.capacity() + value.estimated_heap_size())
+                .sum::<usize>()
+    }
+}
+
+impl Default for EntityMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Shared with arena archetypes, whose columns are borrowed rather than owned.
+pub(crate) fn check_columns<N: AsRef<str>>(
+    component_id: &str,
+    rows: usize,
+    field_names: &[N],
+    field_types: &[FieldType],
+    columns: impl ExactSizeIterator<Item = (FieldType, usize)>,
+) -> crate::Result<()> {
+    let inconsistent = |index: usize, message: String| crate::PackError::InconsistentArchetype {
+        component_id: component_id.to_string(),
+        column: field_names
+            .get(index)
+            .map_or_else(|| format!("#{}", index), |name| name.as_ref().to_string()),
+        message,
+    };
+
+    let column_count = columns.len();
+    if field_names.len() != field_types.len() || field_names.len() != column_count {
+        let index = field_names.len().min(field_types.len()).min(column_count);
+        return Err(inconsistent(index, format!(
+            "{} field names, {} field types and {} columns",
+            field_names.len(),
+            field_types.len(),
+            column_count
+        )));
+    }
+
+    for (index, (field_type, (actual, len))) in field_types.iter().zip(columns).enumerate() {
+        if actual != *field_type {
+            return Err(inconsistent(index, format!("declared {:?} but holds {:?}", field_type, actual)));
+        }
+        if len != rows {
+            return Err(inconsistent(index, format!("{} values for {} entities", len, rows)));
+        }
+    }
+
+    Ok(())
 }
 
 impl PackedSnapshot {
@@ -180,37 +815,191 @@ impl PackedSnapshot {
             header: SnapshotHeader::new(),
             archetypes: Vec::new(),
             entity_metadata: HashMap::new(),
+            entity_index: HashMap::new(),
+            resources: BTreeMap::new(),
+            layout: None,
         }
     }
 
+    pub fn check_consistency(&self) -> crate::Result<()> {
+        self.archetypes.iter().try_for_each(ComponentArchetype::check_consistency)
+    }
+
     pub fn from_world_snapshot(snapshot: tx2_link::WorldSnapshot) -> Self {
-        let mut packed = Self::new();
-        packed.header.timestamp = snapshot.timestamp as i64;
+        Self::from_world_snapshot_with(&snapshot, &crate::registry::ComponentRegistry::new())
+            .expect("world components serialize into an in-memory buffer")
+    }
 
-        let entity_count = snapshot.entities.len() as u64;
+    pub fn estimated_heap_size(&self) -> usize {
+        vec_heap_size(&self.archetypes)
+            + self.archetypes.iter().map(ComponentArchetype::estimated_heap_size).sum::<usize>()
+            + hash_map_heap_size(&self.entity_metadata)
+            + self.entity_metadata.values().map(EntityMetadata::estimated_heap_size).sum::<usize>()
+            + hash_map_heap_size(&self.entity_index)
+            + self.entity_index.values().map(vec_heap_size).sum::<usize>()
+            + self.resources.iter().map(|(name, data)| name.capacity() + data.capacity()).sum::<usize>()
+    }
 
-        let mut component_map: AHashMap<ComponentId, ComponentArchetype> = AHashMap::new();
+    pub fn archetype_heap_sizes(&self) -> Vec<(&ComponentId, usize)> {
+        self.archetypes
+            .iter()
+            .map(|archetype| (&archetype.component_id, archetype.estimated_heap_size()))
+            .collect()
+    }
 
-        for entity in &snapshot.entities {
-            for component in &entity.components {
-                let archetype = component_map
-                    .entry(component.id.clone())
-                    .or_insert_with(|| ComponentArchetype {
-                        component_id: component.id.clone(),
-                        entity_ids: Vec::new(),
-                        data: ComponentData::Blob(Vec::new()),
-                    });
+    pub fn rebuild_entity_index(&mut self) {
+        self.entity_index.clear();
 
-                archetype.entity_ids.push(entity.id);
+        for (archetype_index, archetype) in self.archetypes.iter().enumerate() {
+            for (row, entity_id) in archetype.entity_ids.iter().enumerate() {
+                self.entity_index
+                    .entry(*entity_id)
+                    .or_default()
+                    .push(EntityLocation { archetype: archetype_index, row });
             }
         }
+    }
+
+    pub fn sort_entity_ids(&mut self) {
+        let mut reordered = false;
+        for archetype in &mut self.archetypes {
+            reordered |= !archetype.entity_ids.is_sorted() && archetype.sort_by_entity_id();
+        }
 
-        packed.archetypes = component_map.into_values().collect();
-        packed.header.entity_count = entity_count;
-        packed.header.component_count = packed.archetypes.len() as u64;
-        packed.header.archetype_count = packed.archetypes.len() as u64;
+        if reordered && !self.entity_index.is_empty() {
+            self.rebuild_entity_index();
+        }
+    }
 
-        packed
+    pub fn ensure_entity_index(&mut self) {
+        if self.entity_index.is_empty() && self.archetypes.iter().any(|a| !a.entity_ids.is_empty()) {
+            self.rebuild_entity_index();
+        }
+    }
+
+    pub fn locate(&self, entity_id: EntityId) -> Vec<EntityLocation> {
+        if let Some(locations) = self.entity_index.get(&entity_id) {
+            let valid = locations.iter().all(|location| {
+                self.archetypes
+                    .get(location.archetype)
+                    .and_then(|a| a.entity_ids.get(location.row))
+                    == Some(&entity_id)
+            });
+
+            if valid {
+                return locations.clone();
+            }
+        }
+
+        self.archetypes
+            .iter()
+            .enumerate()
+            .filter_map(|(archetype, a)| a.row_of(entity_id).map(|row| EntityLocation { archetype, row }))
+            .collect()
+    }
+
+    pub fn entity_components(&self, entity_id: EntityId) -> Vec<(&ComponentArchetype, usize)> {
+        self.locate(entity_id)
+            .into_iter()
+            .map(|location| (&self.archetypes[location.archetype], location.row))
+            .collect()
+    }
+
+    pub fn contains_entity(&self, entity_id: EntityId) -> bool {
+        !self.locate(entity_id).is_empty()
+    }
+
+    pub fn content_checksum(&self) -> crate::Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+
+        let mut archetypes: Vec<&ComponentArchetype> = self.archetypes.iter().filter(|a| !a.is_empty()).collect();
+        archetypes.sort_by(|a, b| a.component_id.cmp(&b.component_id));
+
+        for archetype in archetypes {
+            let mut rows: Vec<usize> = (0..archetype.entity_ids.len()).collect();
+            rows.sort_by_key(|&row| archetype.entity_ids[row]);
+            let entity_ids: Vec<EntityId> = rows.iter().map(|&row| archetype.entity_ids[row]).collect();
+
+            hasher.update(bincode::serialize(&archetype.component_id)?);
+            hasher.update(bincode::serialize(&entity_ids)?);
+
+            match &archetype.data {
+                ComponentData::StructOfArrays(soa) => {
+                    hasher.update(bincode::serialize(&(&soa.field_names, &soa.field_types))?);
+                    for column in &soa.field_data {
+                        hasher.update(bincode::serialize(&column.gather(&rows))?);
+                    }
+                }
+                ComponentData::Blob(data) => hasher.update(data),
+            }
+        }
+
+        let mut entity_ids: Vec<&EntityId> = self.entity_metadata.keys().collect();
+        entity_ids.sort();
+
+        for entity_id in entity_ids {
+            let metadata = &self.entity_metadata[entity_id];
+            let annotations: BTreeMap<&String, &FieldValue> = metadata.annotations.iter().collect();
+            hasher.update(bincode::serialize(&(
+                entity_id,
+                metadata.created_at,
+                metadata.modified_at,
+                &metadata.tags,
+                annotations,
+            ))?);
+        }
+
+        // Skipped when empty so checksums recorded before resources existed
+        // still match.
+        if !self.resources.is_empty() {
+            hasher.update(bincode::serialize(&self.resources)?);
+        }
+
+        Ok(hasher.finalize().into())
+    }
+
+    pub fn annotate(&mut self, entity_id: EntityId, key: &str, value: FieldValue) {
+        let metadata = self.entity_metadata.entry(entity_id).or_default();
+        metadata.annotations.insert(key.to_string(), value);
+        metadata.modified_at = chrono::Utc::now().timestamp();
+    }
+
+    pub fn annotate_many<I>(&mut self, entity_ids: I, key: &str, value: FieldValue)
+    where
+        I: IntoIterator<Item = EntityId>,
+    {
+        for entity_id in entity_ids {
+            self.annotate(entity_id, key, value.clone());
+        }
+    }
+
+    pub fn annotation(&self, entity_id: EntityId, key: &str) -> Option<&FieldValue> {
+        self.entity_metadata
+            .get(&entity_id)
+            .and_then(|metadata| metadata.annotations.get(key))
+    }
+
+    pub fn annotations(&self, key: &str) -> Vec<(EntityId, &FieldValue)> {
+        let mut annotated: Vec<_> = self
+            .entity_metadata
+            .iter()
+            .filter_map(|(id, metadata)| metadata.annotations.get(key).map(|value| (*id, value)))
+            .collect();
+
+        annotated.sort_by_key(|(id, _)| *id);
+        annotated
+    }
+
+    pub fn remove_annotation(&mut self, entity_id: EntityId, key: &str) -> Option<FieldValue> {
+        self.entity_metadata
+            .get_mut(&entity_id)
+            .and_then(|metadata| metadata.annotations.remove(key))
+    }
+
+    pub fn clear_annotations(&mut self, key: &str) {
+        for metadata in self.entity_metadata.values_mut() {
+            metadata.annotations.remove(key);
+        }
     }
 }
 
@@ -219,3 +1008,70 @@ impl Default for PackedSnapshot {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_entity_ids_keeps_rows_aligned() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Health".to_string(),
+            entity_ids: vec![9, 2, 5],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["hp".to_string()],
+                field_types: vec![FieldType::U32],
+                field_data: vec![FieldArray::U32(vec![90, 20, 50].into())],
+            }),
+        });
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Script".to_string(),
+            entity_ids: vec![4, 1],
+            data: ComponentData::Blob(vec![1, 2]),
+        });
+        snapshot.rebuild_entity_index();
+
+        snapshot.sort_entity_ids();
+
+        let health = &snapshot.archetypes[0];
+        assert_eq!(health.entity_ids, vec![2, 5, 9]);
+        assert_eq!(health.row_values(0).unwrap()[0].1, FieldValue::U32(20));
+        assert_eq!(snapshot.locate(9)[0], EntityLocation { archetype: 0, row: 2 });
+        assert_eq!(snapshot.archetypes[1].entity_ids, vec![4, 1]);
+    }
+
+    #[test]
+    fn test_estimated_heap_size_tracks_columns() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: vec![1, 2],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string()],
+                field_types: vec![FieldType::F64],
+                field_data: vec![FieldArray::F64(vec![1.0, 2.0].into())],
+            }),
+        });
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Name".to_string(),
+            entity_ids: vec![1],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["name".to_string()],
+                field_types: vec![FieldType::String],
+                field_data: vec![FieldArray::String(vec!["x".repeat(1000)].into())],
+            }),
+        });
+
+        let sizes = snapshot.archetype_heap_sizes();
+        assert_eq!(sizes[0].0, "Position");
+        assert!(sizes[0].1 >= 2 * 8 + 2 * 4);
+        assert!(sizes[1].1 >= 1000);
+
+        let before = snapshot.estimated_heap_size();
+        assert!(before >= sizes.iter().map(|(_, size)| size).sum::<usize>());
+
+        snapshot.annotate(1, "note", FieldValue::Bytes(vec![0; 4096]));
+        assert!(snapshot.estimated_heap_size() >= before + 4096);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::checkpoint::CheckpointManager;
+use crate::error::Result;
+use crate::format::{FieldValue, PackedSnapshot};
+use crate::replay::{ReplayEngine, TimeTravel};
+use std::collections::BTreeMap;
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentState {
+    Fields(Vec<(String, FieldValue)>),
+    Blob,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityFrame {
+    pub time: f64,
+    pub components: BTreeMap<ComponentId, ComponentState>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityHistory {
+    pub entity_id: EntityId,
+    pub frames: Vec<EntityFrame>,
+    pub lifetimes: Vec<(f64, Option<f64>)>,
+}
+
+impl EntityHistory {
+    pub fn for_entity<'a, I>(entity_id: EntityId, frames: I) -> Self
+    where
+        I: IntoIterator<Item = (f64, &'a PackedSnapshot)>,
+    {
+        let mut history = Self {
+            entity_id,
+            frames: Vec::new(),
+            lifetimes: Vec::new(),
+        };
+
+        for (time, snapshot) in frames {
+            history.push_frame(time, snapshot);
+        }
+
+        history
+    }
+
+    pub fn from_time_travel(entity_id: EntityId, time_travel: &TimeTravel) -> Self {
+        Self::for_entity(entity_id, time_travel.iter())
+    }
+
+    pub fn from_replay(entity_id: EntityId, engine: &ReplayEngine) -> Self {
+        Self::for_entity(
+            entity_id,
+            engine.checkpoints().map(|cp| (cp.metadata.world_time, &cp.snapshot)),
+        )
+    }
+
+    pub fn from_checkpoints(entity_id: EntityId, manager: &mut CheckpointManager) -> Result<Self> {
+        let mut history = Self::for_entity(entity_id, std::iter::empty());
+
+        for id in manager.get_checkpoint_chain().to_vec() {
+            let checkpoint = manager.load_checkpoint(&id)?;
+            history.push_frame(checkpoint.metadata.world_time, &checkpoint.snapshot);
+        }
+
+        Ok(history)
+    }
+
+    pub fn spawned_at(&self) -> Option<f64> {
+        self.lifetimes.first().map(|(spawn, _)| *spawn)
+    }
+
+    pub fn despawned_at(&self) -> Option<f64> {
+        self.lifetimes.last().and_then(|(_, despawn)| *despawn)
+    }
+
+    pub fn is_alive_at(&self, time: f64) -> bool {
+        self.lifetimes
+            .iter()
+            .any(|(spawn, despawn)| time >= *spawn && despawn.is_none_or(|d| time < d))
+    }
+
+    pub fn component_at(&self, component_id: &str, time: f64) -> Option<&ComponentState> {
+        self.frames
+            .iter()
+            .rev()
+            .find(|frame| frame.time <= time)
+            .filter(|frame| self.is_alive_at(frame.time) && self.is_alive_at(time))
+            .and_then(|frame| frame.components.get(component_id))
+    }
+
+    fn push_frame(&mut self, time: f64, snapshot: &PackedSnapshot) {
+        let mut components = BTreeMap::new();
+
+        for (archetype, row) in snapshot.entity_components(self.entity_id) {
+            let state = archetype
+                .row_values(row)
+                .map(ComponentState::Fields)
+                .unwrap_or(ComponentState::Blob);
+            components.insert(archetype.component_id.clone(), state);
+        }
+
+        let alive = self.lifetimes.last().is_some_and(|(_, despawn)| despawn.is_none());
+
+        if components.is_empty() {
+            if alive {
+                if let Some((_, despawn)) = self.lifetimes.last_mut() {
+                    *despawn = Some(time);
+                }
+            }
+            return;
+        }
+
+        if !alive {
+            self.lifetimes.push((time, None));
+        }
+
+        self.frames.push(EntityFrame { time, components });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, StructOfArraysData};
+
+    fn snapshot_with(entities: &[(EntityId, f32)]) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: entities.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string()],
+                field_types: vec![FieldType::F32],
+                field_data: vec![FieldArray::F32(entities.iter().map(|(_, x)| *x).collect())],
+            }),
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_entity_history_spawn_and_despawn() {
+        let mut tt = TimeTravel::new();
+        tt.record(0.0, snapshot_with(&[(1, 0.0)]));
+        tt.record(1.0, snapshot_with(&[(1, 1.0), (42, 5.0)]));
+        tt.record(2.0, snapshot_with(&[(1, 2.0), (42, 6.0)]));
+        tt.record(3.0, snapshot_with(&[(1, 3.0)]));
+
+        let history = EntityHistory::from_time_travel(42, &tt);
+        assert_eq!(history.frames.len(), 2);
+        assert_eq!(history.spawned_at(), Some(1.0));
+        assert_eq!(history.despawned_at(), Some(3.0));
+        assert!(history.is_alive_at(2.5));
+        assert!(!history.is_alive_at(3.0));
+
+        assert_eq!(
+            history.component_at("Position", 2.5),
+            Some(&ComponentState::Fields(vec![("x".to_string(), FieldValue::F32(6.0))]))
+        );
+        assert_eq!(history.component_at("Position", 3.5), None);
+
+        let survivor = EntityHistory::from_time_travel(1, &tt);
+        assert_eq!(survivor.spawned_at(), Some(0.0));
+        assert_eq!(survivor.despawned_at(), None);
+    }
+
+    #[test]
+    fn test_entity_history_respawn() {
+        let frames = [
+            snapshot_with(&[(9, 1.0)]),
+            snapshot_with(&[]),
+            snapshot_with(&[(9, 2.0)]),
+        ];
+
+        let history = EntityHistory::for_entity(9, frames.iter().enumerate().map(|(i, s)| (i as f64, s)));
+        assert_eq!(history.lifetimes, vec![(0.0, Some(1.0)), (2.0, None)]);
+    }
+}
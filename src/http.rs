@@ -0,0 +1,507 @@
+use crate::error::{PackError, Result};
+use crate::storage::{validate_snapshot_id, SnapshotStore};
+use crate::store_query::{ListOptions, SortKey, SortOrder};
+use crate::transfer::DEFAULT_TRANSFER_CHUNK_SIZE;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::ReaderStream;
+
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+pub fn router(store: Arc<SnapshotStore>) -> Router {
+    Router::new()
+        .route("/snapshots", get(list_snapshots))
+        .route("/snapshots/query", get(query_snapshots))
+        .route("/snapshots/{id}", get(snapshot_metadata))
+        .route("/snapshots/{id}/header", get(snapshot_header))
+        .route("/snapshots/{id}/data", get(snapshot_data))
+        .route("/snapshots/{id}/transfer", get(transfer_manifest))
+        .route("/snapshots/{id}/transfer/{index}", get(transfer_chunk))
+        .with_state(store)
+}
+
+pub struct ApiError(PackError);
+
+impl From<PackError> for ApiError {
+    fn from(error: PackError) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0.root() {
+            PackError::SnapshotNotFound(_) => StatusCode::NOT_FOUND,
+            PackError::InvalidSelector(_) | PackError::Query(_) | PackError::InvalidSnapshotId(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            PackError::Conflict { .. } => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, Json(serde_json::json!({ "error": self.0.to_string() }))).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+#[derive(Debug, Default, Deserialize)]
+struct ListParams {
+    sort: Option<SortKey>,
+    order: Option<SortOrder>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    after: Option<String>,
+}
+
+async fn list_snapshots(State(store): State<Arc<SnapshotStore>>, Query(params): Query<ListParams>) -> ApiResult<Response> {
+    let mut options = ListOptions::new()
+        .sort_by(params.sort.unwrap_or_default(), params.order.unwrap_or_default())
+        .offset(params.offset.unwrap_or(0));
+    if let Some(limit) = params.limit {
+        options = options.limit(limit);
+    }
+    if let Some(cursor) = params.after {
+        options = options.after(cursor);
+    }
+
+    let page = blocking(&store, move |store| store.list_page(&options)).await?;
+    Ok(Json(page).into_response())
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct QueryParams {
+    tag: Option<String>,
+    name: Option<String>,
+    selector: Option<String>,
+    created_after: Option<i64>,
+    created_before: Option<i64>,
+    limit: Option<usize>,
+}
+
+async fn query_snapshots(State(store): State<Arc<SnapshotStore>>, Query(params): Query<QueryParams>) -> ApiResult<Response> {
+    let results = blocking(&store, move |store| {
+        let mut query = store.query();
+        if let Some(tag) = &params.tag {
+            query = query.with_tag(tag);
+        }
+        if let Some(name) = &params.name {
+            query = query.with_name_containing(name);
+        }
+        if let Some(selector) = &params.selector {
+            query = query.with_label_selector(selector)?;
+        }
+        if let Some(timestamp) = params.created_after {
+            query = query.created_after(timestamp);
+        }
+        if let Some(timestamp) = params.created_before {
+            query = query.created_before(timestamp);
+        }
+        if let Some(limit) = params.limit {
+            query = query.limit(limit);
+        }
+        query.execute()
+    })
+    .await?;
+
+    Ok(Json(results).into_response())
+}
+
+// Path segments arrive percent-decoded, so an id can carry separators.
+async fn snapshot_metadata(State(store): State<Arc<SnapshotStore>>, Path(id): Path<String>) -> ApiResult<Response> {
+    validate_snapshot_id(&id)?;
+    let metadata = blocking(&store, move |store| {
+        if !store.exists(&id) {
+            return Err(PackError::SnapshotNotFound(id));
+        }
+        store.load_metadata(&id)
+    })
+    .await?;
+
+    Ok(Json(metadata).into_response())
+}
+
+async fn snapshot_header(State(store): State<Arc<SnapshotStore>>, Path(id): Path<String>) -> ApiResult<Response> {
+    validate_snapshot_id(&id)?;
+    let header = blocking(&store, move |store| store.read_header(&id)).await?;
+    Ok(Json(header).into_response())
+}
+
+async fn snapshot_data(
+    State(store): State<Arc<SnapshotStore>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    validate_snapshot_id(&id)?;
+    let (total, etag) = {
+        let id = id.clone();
+        blocking(&store, move |store| Ok((store.file_size(&id)?, store.etag(&id)?))).await?
+    };
+    if etag_matches(&headers, header::IF_NONE_MATCH, &etag) {
+        return Ok(not_modified(etag));
+    }
+    let range = headers.get(header::RANGE).and_then(|value| value.to_str().ok());
+
+    let (start, end) = match range.map(|range| parse_range(range, total)) {
+        None => (0, total),
+        Some(Some(bounds)) => bounds,
+        Some(None) => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", total))],
+            )
+                .into_response());
+        }
+    };
+
+    // Streamed from the file, so a large snapshot is never held in memory.
+    let file = blocking(&store, move |store| {
+        let path = store.data_path(&id).ok_or(PackError::SnapshotNotFound(id))?;
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(start))?;
+        Ok(file)
+    })
+    .await?;
+    let body = ReaderStream::with_capacity(tokio::fs::File::from_std(file).take(end - start), STREAM_BUFFER_SIZE);
+
+    let mut response = Body::from_stream(body).into_response();
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(end - start));
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    response_headers.insert(header::ETAG, header_value(&etag)?);
+
+    if range.is_some() {
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+        let content_range = format!("bytes {}-{}/{}", start, end - 1, total);
+        response.headers_mut().insert(header::CONTENT_RANGE, header_value(&content_range)?);
+    }
+
+    Ok(response)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TransferParams {
+    chunk_size: Option<NonZeroU32>,
+}
+
+impl TransferParams {
+    fn chunk_size(&self) -> u32 {
+        self.chunk_size.map_or(DEFAULT_TRANSFER_CHUNK_SIZE, NonZeroU32::get)
+    }
+}
+
+async fn transfer_manifest(
+    State(store): State<Arc<SnapshotStore>>,
+    Path(id): Path<String>,
+    Query(params): Query<TransferParams>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    validate_snapshot_id(&id)?;
+    // Checked before hashing the file, so validating a cached copy is cheap.
+    let etag = {
+        let id = id.clone();
+        blocking(&store, move |store| store.etag(&id)).await?
+    };
+    if etag_matches(&headers, header::IF_NONE_MATCH, &etag) {
+        return Ok(not_modified(etag));
+    }
+
+    let manifest = blocking(&store, move |store| store.transfer_manifest(&id, params.chunk_size())).await?;
+    let etag = header_value(&manifest.etag())?;
+    Ok(([(header::ETAG, etag)], Json(manifest)).into_response())
+}
+
+// Chunks are byte ranges of the stored file, already compressed by the
+// writer that saved it, so they go out as they are without being decoded or
+// hashed here. The receiving store checks them against the manifest.
+async fn transfer_chunk(
+    State(store): State<Arc<SnapshotStore>>,
+    Path((id, index)): Path<(String, u32)>,
+    Query(params): Query<TransferParams>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    validate_snapshot_id(&id)?;
+    let (total, etag) = {
+        let id = id.clone();
+        blocking(&store, move |store| Ok((store.file_size(&id)?, store.etag(&id)?))).await?
+    };
+    // Clients send the manifest's etag, so a snapshot rewritten halfway
+    // through a download is refused instead of mixing versions.
+    if headers.contains_key(header::IF_MATCH) && !etag_matches(&headers, header::IF_MATCH, &etag) {
+        return Ok((StatusCode::PRECONDITION_FAILED, [(header::ETAG, etag)]).into_response());
+    }
+    let chunk_size = params.chunk_size() as u64;
+    let start = index as u64 * chunk_size;
+    if start >= total {
+        return Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", total))],
+        )
+            .into_response());
+    }
+
+    let bytes = blocking(&store, move |store| store.read_range(&id, start, chunk_size.min(total - start))).await?;
+    let mut response = bytes.into_response();
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+    // Compressing them again on the way out would only cost CPU.
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-transform"));
+    response_headers.insert(header::ETAG, header_value(&etag)?);
+
+    Ok(response)
+}
+
+// The store reads and hashes files with std::fs, which would stall the
+// runtime's workers, so its calls go to the blocking pool.
+async fn blocking<T, F>(store: &Arc<SnapshotStore>, f: F) -> ApiResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&SnapshotStore) -> Result<T> + Send + 'static,
+{
+    let store = Arc::clone(store);
+    tokio::task::spawn_blocking(move || f(&store))
+        .await
+        .map_err(|e| PackError::Unknown(format!("Store task failed: {}", e)))?
+        .map_err(ApiError)
+}
+
+// If-None-Match compares weakly, If-Match strongly.
+fn etag_matches(headers: &HeaderMap, name: HeaderName, etag: &str) -> bool {
+    let weak = name == header::IF_NONE_MATCH;
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag || (weak && tag.strip_prefix("W/") == Some(etag)))
+}
+
+// Etags come from stored metadata, which a synced store may have taken from
+// elsewhere; a value that cannot go in a header is a server error.
+fn header_value(value: &str) -> ApiResult<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|_| ApiError(PackError::InvalidFormat(format!("Cannot send {:?} as a header value", value))))
+}
+
+fn not_modified(etag: String) -> Response {
+    (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response()
+}
+
+fn parse_range(range: &str, total: u64) -> Option<(u64, u64)> {
+    let spec = range.strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (total.saturating_sub(suffix), total)
+        }
+        (start, "") => (start.parse().ok()?, total),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.saturating_add(1).min(total)),
+    };
+
+    (start < end).then_some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot};
+    use crate::metadata::SnapshotMetadata;
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+    use crate::store_sync::SYNC_SOURCE_CHECKSUM;
+    use crate::transfer::TransferManifest;
+    use axum::body::to_bytes;
+    use axum::http::Request;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    async fn get(app: &Router, uri: &str, range: Option<&str>) -> (StatusCode, HeaderMap, Vec<u8>) {
+        get_with(app, uri, range.map(|range| (header::RANGE, range))).await
+    }
+
+    async fn get_with(
+        app: &Router,
+        uri: &str,
+        header: Option<(HeaderName, &str)>,
+    ) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let mut request = Request::builder().uri(uri);
+        if let Some((name, value)) = header {
+            request = request.header(name, value);
+        }
+
+        let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, headers, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_rest_facade() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let writer = SnapshotWriter::new();
+        for (id, tag) in [("run_a", "boss"), ("run_b", "menu")] {
+            let metadata = SnapshotMetadata::new(id.to_string()).with_tag(tag.to_string());
+            store.save(&PackedSnapshot::new(), &metadata, &writer).unwrap();
+        }
+        let file_len = store.file_size("run_a").unwrap();
+        let app = router(Arc::new(store));
+
+        let (status, _, body) = get(&app, "/snapshots?sort=id&limit=1", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page["total"], 2);
+        assert_eq!(page["entries"][0]["metadata"]["id"], "run_a");
+        let cursor = page["next_cursor"].as_str().unwrap();
+        let (_, _, body) = get(&app, &format!("/snapshots?sort=id&limit=1&after={}", cursor), None).await;
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(page["entries"][0]["metadata"]["id"], "run_b");
+        assert!(page["next_cursor"].is_null());
+
+        let (_, _, body) = get(&app, "/snapshots/query?tag=boss", None).await;
+        let results: Vec<SnapshotMetadata> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "run_a");
+
+        let (status, _, body) = get(&app, "/snapshots/run_b/header", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let header: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(header["entity_count"], 0);
+
+        let (status, headers, full) = get(&app, "/snapshots/run_a/data", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_LENGTH], file_len.to_string().as_str());
+        assert_eq!(full, std::fs::read(temp_dir.path().join("run_a.tx2pack")).unwrap());
+
+        let (status, headers, part) = get(&app, "/snapshots/run_a/data", Some("bytes=0-7")).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(part, full[..8]);
+        assert_eq!(headers[header::CONTENT_RANGE], format!("bytes 0-7/{}", file_len).as_str());
+
+        let (status, _, _) = get(&app, "/snapshots/run_a/data", Some(&format!("bytes={}-", file_len))).await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+
+        let (status, _, _) = get(&app, "/snapshots/missing", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _, _) = get(&app, "/snapshots/query?selector=%21%21", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ids_cannot_escape_the_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let outside = SnapshotStore::new(temp_dir.path()).unwrap();
+        let metadata = SnapshotMetadata::new("secret".to_string());
+        outside.save(&PackedSnapshot::new(), &metadata, &SnapshotWriter::new()).unwrap();
+        let store = SnapshotStore::new(temp_dir.path().join("store")).unwrap();
+        assert!(!store.exists("../secret"));
+        assert!(matches!(store.load_metadata("../secret"), Err(PackError::InvalidSnapshotId(_))));
+        let app = router(Arc::new(store));
+
+        for route in ["", "/header", "/data", "/transfer", "/transfer/0"] {
+            let (status, _, _) = get(&app, &format!("/snapshots/..%2Fsecret{}", route), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", route);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfer_routes_serve_stored_chunks_verbatim() {
+        let (source_dir, target_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let source = SnapshotStore::new(source_dir.path()).unwrap();
+        let target = SnapshotStore::new(target_dir.path()).unwrap();
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Health".to_string(),
+            entity_ids: (0..64).collect(),
+            data: ComponentData::Blob((0..256u32).flat_map(|v| v.to_le_bytes()).collect()),
+        });
+        source.save(&snapshot, &SnapshotMetadata::new("raid".to_string()), &SnapshotWriter::new()).unwrap();
+        let stored = std::fs::read(source_dir.path().join("raid.tx2pack")).unwrap();
+        let app = router(Arc::new(source));
+
+        let (status, _, body) = get(&app, "/snapshots/raid/transfer?chunk_size=128", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let manifest: TransferManifest = serde_json::from_slice(&body).unwrap();
+        let mut receiver = target.receive_transfer(manifest).unwrap();
+        for index in receiver.missing_chunks() {
+            let uri = format!("/snapshots/raid/transfer/{}?chunk_size=128", index);
+            let (status, headers, chunk) = get(&app, &uri, None).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(headers[header::CACHE_CONTROL], "no-transform");
+            assert_eq!(chunk, stored[index as usize * 128..][..chunk.len()]);
+            receiver.write_chunk(index, &chunk).unwrap();
+        }
+        receiver.finish().unwrap();
+        let (loaded, _) = target.load("raid", &SnapshotReader::new()).unwrap();
+        assert_eq!(loaded.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+
+        let past_end = format!("/snapshots/raid/transfer/{}?chunk_size=128", stored.len().div_ceil(128));
+        assert_eq!(get(&app, &past_end, None).await.0, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(get(&app, "/snapshots/raid/transfer/0?chunk_size=0", None).await.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_conditional_fetch_validates_cached_copies() {
+        let (server_dir, cache_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let server = Arc::new(SnapshotStore::new(server_dir.path()).unwrap());
+        let cache = SnapshotStore::new(cache_dir.path()).unwrap();
+        let mut snapshot = PackedSnapshot::new();
+        let writer = SnapshotWriter::new();
+        server.save(&snapshot, &SnapshotMetadata::new("lobby".to_string()), &writer).unwrap();
+        cache.sync_from(&server).unwrap();
+        let cached = cache.etag("lobby").unwrap();
+        let app = router(server.clone());
+
+        let if_none_match = format!("\"stale\", W/{}", cached);
+        for uri in ["/snapshots/lobby/data", "/snapshots/lobby/transfer"] {
+            let (status, headers, body) = get_with(&app, uri, Some((header::IF_NONE_MATCH, &if_none_match))).await;
+            assert_eq!(status, StatusCode::NOT_MODIFIED);
+            assert_eq!(headers[header::ETAG], cached.as_str());
+            assert!(body.is_empty());
+        }
+        let chunk = get_with(&app, "/snapshots/lobby/transfer/0", Some((header::IF_MATCH, &cached))).await;
+        assert_eq!(chunk.0, StatusCode::OK);
+
+        // Once the server's copy changes, the cached etag no longer matches.
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Seat".to_string(),
+            entity_ids: vec![1, 2],
+            data: ComponentData::Blob(vec![1; 16]),
+        });
+        server.save(&snapshot, &SnapshotMetadata::new("lobby".to_string()), &writer).unwrap();
+        let revalidate = Some((header::IF_NONE_MATCH, cached.as_str()));
+        let (status, headers, body) = get_with(&app, "/snapshots/lobby/transfer", revalidate).await;
+        assert_eq!(status, StatusCode::OK);
+        let manifest: TransferManifest = serde_json::from_slice(&body).unwrap();
+        assert_eq!(headers[header::ETAG], manifest.etag().as_str());
+        assert_eq!(manifest.etag(), server.etag("lobby").unwrap());
+        assert_ne!(manifest.etag(), cached);
+        let chunk = get_with(&app, "/snapshots/lobby/transfer/0", Some((header::IF_MATCH, &cached))).await;
+        assert_eq!(chunk.0, StatusCode::PRECONDITION_FAILED);
+
+        // An etag that cannot be sent is an error response, not a panic.
+        let metadata = SnapshotMetadata::new("odd".to_string())
+            .with_custom_field(SYNC_SOURCE_CHECKSUM.to_string(), "line\nbreak".to_string());
+        server.save(&snapshot, &metadata, &writer).unwrap();
+        for uri in ["/snapshots/odd/data", "/snapshots/odd/transfer", "/snapshots/odd/transfer/0"] {
+            assert_eq!(get(&app, uri, None).await.0, StatusCode::INTERNAL_SERVER_ERROR, "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use crate::adapter::ChangeTracking;
+use crate::delta::{empty_data, resource_changes, DeltaSnapshot, EntityMetadataChange, UNVERIFIED_CHECKSUM};
+use crate::diff::{diff, values_equal, ComponentEntry, SnapshotDiff, ValueChange};
+use crate::error::{PackError, Result, ResultExt};
+use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot};
+use std::collections::{BTreeSet, HashMap};
+use tx2_link::{ComponentId, EntityId};
+
+// Keeps a mirror of the last capture and, on each tick, asks the world only
+// for the rows its change detection reports as dirty. Capturing and diffing
+// cost grows with the number of changes rather than the size of the world.
+// Checksums take a full pass, so deltas carry UNVERIFIED_CHECKSUM unless
+// they are verified, every few deltas or on demand.
+pub struct IncrementalCapture {
+    snapshot: PackedSnapshot,
+    checksum: Option<[u8; 32]>,
+    checksum_interval: Option<u64>,
+    unverified: u64,
+    tick: u64,
+    rows: HashMap<ComponentId, HashMap<EntityId, usize>>,
+}
+
+impl IncrementalCapture {
+    pub fn new<W: ChangeTracking + ?Sized>(world: &W) -> Result<Self> {
+        let tick = world.current_tick();
+        let snapshot = world.capture()?;
+        Ok(Self {
+            snapshot,
+            checksum: None,
+            checksum_interval: None,
+            unverified: 0,
+            tick,
+            rows: HashMap::new(),
+        })
+    }
+
+    // Every deltas-th delta from capture_delta carries real checksums.
+    pub fn with_checksum_interval(mut self, deltas: u64) -> Self {
+        self.checksum_interval = Some(deltas.max(1));
+        self
+    }
+
+    // The state every delta so far has been applied to, i.e. what the world
+    // looked like at the last capture.
+    pub fn snapshot(&self) -> &PackedSnapshot {
+        &self.snapshot
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    // The content checksum of snapshot(), computed once per capture.
+    pub fn checksum(&mut self) -> Result<[u8; 32]> {
+        match self.checksum {
+            Some(checksum) => Ok(checksum),
+            None => {
+                let checksum = self.snapshot.content_checksum()?;
+                self.checksum = Some(checksum);
+                Ok(checksum)
+            }
+        }
+    }
+
+    pub fn capture_delta<W: ChangeTracking + ?Sized>(&mut self, world: &W) -> Result<DeltaSnapshot> {
+        let verify = self.checksum_interval.is_some_and(|interval| self.unverified + 1 >= interval);
+        self.capture(world, verify)
+    }
+
+    // A delta with both checksums, whatever the interval.
+    pub fn capture_verified_delta<W: ChangeTracking + ?Sized>(&mut self, world: &W) -> Result<DeltaSnapshot> {
+        self.capture(world, true)
+    }
+
+    fn capture<W: ChangeTracking + ?Sized>(&mut self, world: &W, verify: bool) -> Result<DeltaSnapshot> {
+        let base_checksum = if verify { self.checksum()? } else { self.checksum.unwrap_or(UNVERIFIED_CHECKSUM) };
+        let tick = world.current_tick();
+        let changes = world.changes_since(self.tick)?;
+        let base_header = self.snapshot.header.clone();
+
+        let mut changed = SnapshotDiff {
+            entities_added: changes.spawned.iter().copied().collect(),
+            entities_removed: changes.despawned.iter().copied().collect(),
+            ..SnapshotDiff::default()
+        };
+
+        // Dead entities lose every row first, so an id the world has already
+        // handed to a new entity comes back only with what it was given since.
+        let dead: BTreeSet<EntityId> = changes.despawned.union(&changes.tombstones).copied().collect();
+        let entity_metadata_changes = if dead.is_empty() {
+            Vec::new()
+        } else {
+            self.remove_dead(world, &dead, &mut changed)?
+        };
+
+        for (component_id, component) in &changes.components {
+            let dirty: BTreeSet<EntityId> = component
+                .added
+                .iter()
+                .chain(&component.modified)
+                .filter(|id| !component.removed.contains(id))
+                .copied()
+                .collect();
+            self.update_component(world, component_id, &component.removed, &dirty, &mut changed)
+                .with_component(component_id)?;
+        }
+
+        // Resources have no change detection of their own and are few, so
+        // they are recaptured whole every tick.
+        let resources = world.capture_resources()?;
+        let resource_changes = resource_changes(&self.snapshot.resources, &resources);
+        self.snapshot.resources = resources;
+
+        self.snapshot.archetypes.retain(|archetype| !archetype.is_empty());
+        self.snapshot.rebuild_entity_index();
+        if changed.moves_rows() {
+            self.snapshot.layout = None;
+        }
+        let header = &mut self.snapshot.header;
+        header.entity_count = (header.entity_count + changed.entities_added.len() as u64)
+            .saturating_sub(changed.entities_removed.len() as u64);
+        header.component_count = self.snapshot.archetypes.len() as u64;
+        header.archetype_count = self.snapshot.archetypes.len() as u64;
+
+        self.checksum = None;
+        let target_checksum = if verify { self.checksum()? } else { UNVERIFIED_CHECKSUM };
+        self.unverified = if verify { 0 } else { self.unverified + 1 };
+        let delta = DeltaSnapshot {
+            base_id: None,
+            base_checksum,
+            target_checksum,
+            base_header,
+            target_header: self.snapshot.header.clone(),
+            changes: changed,
+            column_deltas: Vec::new(),
+            entity_metadata_changes,
+            resource_changes,
+        };
+        self.tick = tick;
+
+        Ok(delta)
+    }
+
+    fn remove_dead<W: ChangeTracking + ?Sized>(
+        &mut self,
+        world: &W,
+        dead: &BTreeSet<EntityId>,
+        changed: &mut SnapshotDiff,
+    ) -> Result<Vec<EntityMetadataChange>> {
+        let mut blobs = Vec::new();
+        for archetype in &mut self.snapshot.archetypes {
+            if !archetype.entity_ids.iter().any(|id| dead.contains(id)) {
+                continue;
+            }
+            if matches!(archetype.data, ComponentData::Blob(_)) {
+                blobs.push(archetype.component_id.clone());
+                continue;
+            }
+
+            for (row, entity_id) in archetype.entity_ids.iter().enumerate() {
+                if dead.contains(entity_id) {
+                    changed.tombstones.push(*entity_id);
+                    changed.components_removed.push(ComponentEntry {
+                        entity_id: *entity_id,
+                        component_id: archetype.component_id.clone(),
+                        values: archetype.row_values(row),
+                    });
+                }
+            }
+            archetype.retain_entities(|id| !dead.contains(&id));
+            self.rows.remove(&archetype.component_id);
+        }
+
+        for component_id in blobs {
+            changed.tombstones.extend(
+                self.snapshot
+                    .archetypes
+                    .iter()
+                    .filter(|a| a.component_id == component_id)
+                    .flat_map(|a| a.entity_ids.iter().copied().filter(|id| dead.contains(id))),
+            );
+            let index = self.snapshot.archetypes.iter().position(|a| a.component_id == component_id);
+            self.recapture_component(world, &component_id, index, changed).with_component(&component_id)?;
+        }
+        changed.tombstones.sort_unstable();
+        changed.tombstones.dedup();
+
+        Ok(dead
+            .iter()
+            .filter_map(|id| {
+                self.snapshot.entity_metadata.remove(id).map(|old| EntityMetadataChange {
+                    entity_id: *id,
+                    old: Some(old),
+                    new: None,
+                })
+            })
+            .collect())
+    }
+
+    fn update_component<W: ChangeTracking + ?Sized>(
+        &mut self,
+        world: &W,
+        component_id: &ComponentId,
+        removed: &BTreeSet<EntityId>,
+        dirty: &BTreeSet<EntityId>,
+        changed: &mut SnapshotDiff,
+    ) -> Result<()> {
+        let captured = if dirty.is_empty() { None } else { world.capture_rows(component_id, dirty)? };
+        let index = self.snapshot.archetypes.iter().position(|a| a.component_id == *component_id);
+
+        let is_blob = |archetype: &ComponentArchetype| matches!(archetype.data, ComponentData::Blob(_));
+        let blob = index.is_some_and(|i| is_blob(&self.snapshot.archetypes[i])) || captured.as_ref().is_some_and(is_blob);
+        if blob {
+            return self.recapture_component(world, component_id, index, changed);
+        }
+
+        let index = match index {
+            Some(index) => index,
+            None => match &captured {
+                Some(captured) => {
+                    self.snapshot.archetypes.push(ComponentArchetype {
+                        component_id: component_id.clone(),
+                        entity_ids: Vec::new(),
+                        data: empty_data(&ComponentEntry {
+                            entity_id: 0,
+                            component_id: component_id.clone(),
+                            values: Some(captured.row_values(0).unwrap_or_default()),
+                        }),
+                    });
+                    self.snapshot.archetypes.len() - 1
+                }
+                None => return Ok(()),
+            },
+        };
+
+        let archetype = &mut self.snapshot.archetypes[index];
+        let rows = self
+            .rows
+            .entry(component_id.clone())
+            .or_insert_with(|| archetype.entity_ids.iter().enumerate().map(|(row, id)| (*id, row)).collect());
+
+        let mut removals = 0;
+        for entity_id in removed {
+            if let Some(&row) = rows.get(entity_id) {
+                changed.components_removed.push(ComponentEntry {
+                    entity_id: *entity_id,
+                    component_id: component_id.clone(),
+                    values: archetype.row_values(row),
+                });
+                removals += 1;
+            }
+        }
+        if removals > 0 {
+            archetype.retain_entities(|id| !removed.contains(&id));
+            *rows = archetype.entity_ids.iter().enumerate().map(|(row, id)| (*id, row)).collect();
+        }
+
+        let captured = match captured {
+            Some(captured) => captured,
+            None => return Ok(()),
+        };
+        let soa = match &mut archetype.data {
+            ComponentData::StructOfArrays(soa) => soa,
+            ComponentData::Blob(_) => unreachable!("blob archetypes are recaptured whole"),
+        };
+        for (captured_row, entity_id) in captured.entity_ids.iter().enumerate() {
+            let values = captured.row_values(captured_row).unwrap_or_default();
+            if values.len() != soa.field_names.len()
+                || values.iter().zip(&soa.field_names).any(|((name, _), field)| name != field)
+            {
+                return Err(PackError::InvalidFormat(format!(
+                    "Captured rows of {} do not match the captured layout",
+                    component_id
+                )));
+            }
+
+            match rows.get(entity_id) {
+                Some(&row) => {
+                    for ((field, new), column) in values.into_iter().zip(&mut soa.field_data) {
+                        let old = column.get(row).expect("mirror rows match their columns");
+                        if !values_equal(&old, &new) {
+                            column.set(row, new.clone())?;
+                            changed.value_changes.push(ValueChange {
+                                entity_id: *entity_id,
+                                component_id: component_id.clone(),
+                                field,
+                                old,
+                                new,
+                            });
+                        }
+                    }
+                }
+                None => {
+                    for ((_, value), column) in values.iter().zip(&mut soa.field_data) {
+                        column.push(value.clone())?;
+                    }
+                    rows.insert(*entity_id, archetype.entity_ids.len());
+                    archetype.entity_ids.push(*entity_id);
+                    changed.components_added.push(ComponentEntry {
+                        entity_id: *entity_id,
+                        component_id: component_id.clone(),
+                        values: Some(values),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Blobs cannot be patched row by row, so the whole archetype is captured
+    // again and diffed against the mirror on its own.
+    fn recapture_component<W: ChangeTracking + ?Sized>(
+        &mut self,
+        world: &W,
+        component_id: &ComponentId,
+        index: Option<usize>,
+        changed: &mut SnapshotDiff,
+    ) -> Result<()> {
+        let single = |archetype: Option<ComponentArchetype>| {
+            let mut snapshot = PackedSnapshot::new();
+            snapshot.archetypes.extend(archetype);
+            snapshot
+        };
+        let old = index.map(|i| self.snapshot.archetypes[i].clone());
+        let new = world.capture_archetype(component_id)?;
+        let component_diff = diff(&single(old), &single(new.clone()));
+
+        changed.components_added.extend(component_diff.components_added);
+        changed.components_removed.extend(component_diff.components_removed);
+        changed.value_changes.extend(component_diff.value_changes);
+        changed.blob_changes.extend(component_diff.blob_changes);
+
+        self.rows.remove(component_id);
+        match (index, new) {
+            (Some(index), Some(new)) => self.snapshot.archetypes[index] = new,
+            (Some(index), None) => {
+                self.snapshot.archetypes.remove(index);
+            }
+            (None, Some(new)) => self.snapshot.archetypes.push(new),
+            (None, None) => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::WorldCapture;
+    use crate::changeset::{ChangeSet, ComponentChanges};
+    use crate::format::{FieldArray, FieldType, StructOfArraysData};
+    use std::collections::BTreeMap;
+
+    #[derive(Default)]
+    struct World {
+        tick: u64,
+        hp: BTreeMap<EntityId, u32>,
+        tags: BTreeMap<EntityId, Vec<u8>>,
+        log: Vec<(u64, &'static str, EntityId, char)>,
+        deaths: Vec<(u64, EntityId)>,
+    }
+
+    impl World {
+        fn set_hp(&mut self, id: EntityId, hp: Option<u32>) {
+            self.tick += 1;
+            let kind = match (hp, self.hp.contains_key(&id)) {
+                (Some(hp), present) => {
+                    self.hp.insert(id, hp);
+                    if present { 'm' } else { 'a' }
+                }
+                (None, _) => {
+                    self.hp.remove(&id);
+                    'r'
+                }
+            };
+            self.log.push((self.tick, "Health", id, kind));
+        }
+
+        fn set_tag(&mut self, id: EntityId, tag: Vec<u8>) {
+            self.tick += 1;
+            self.tags.insert(id, tag);
+            self.log.push((self.tick, "Tag", id, 'm'));
+        }
+
+        fn despawn(&mut self, id: EntityId) {
+            self.tick += 1;
+            self.hp.remove(&id);
+            self.tags.remove(&id);
+            self.deaths.push((self.tick, id));
+        }
+    }
+
+    impl WorldCapture for World {
+        fn component_ids(&self) -> Result<Vec<ComponentId>> {
+            Ok(vec!["Health".to_string(), "Tag".to_string()])
+        }
+
+        fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+            Ok(match component_id.as_str() {
+                "Health" if !self.hp.is_empty() => Some(ComponentArchetype {
+                    component_id: component_id.clone(),
+                    entity_ids: self.hp.keys().copied().collect(),
+                    data: ComponentData::StructOfArrays(StructOfArraysData {
+                        field_names: vec!["hp".to_string()],
+                        field_types: vec![FieldType::U32],
+                        field_data: vec![FieldArray::U32(self.hp.values().copied().collect::<Vec<_>>().into())],
+                    }),
+                }),
+                "Tag" if !self.tags.is_empty() => Some(ComponentArchetype {
+                    component_id: component_id.clone(),
+                    entity_ids: self.tags.keys().copied().collect(),
+                    data: ComponentData::Blob(bincode::serialize(&self.tags).unwrap()),
+                }),
+                _ => None,
+            })
+        }
+    }
+
+    impl ChangeTracking for World {
+        fn current_tick(&self) -> u64 {
+            self.tick
+        }
+
+        fn changes_since(&self, tick: u64) -> Result<ChangeSet> {
+            let mut changes = ChangeSet::default();
+            for (_, component_id, entity_id, kind) in self.log.iter().filter(|(t, ..)| *t > tick) {
+                let component: &mut ComponentChanges = changes.components.entry(component_id.to_string()).or_default();
+                match kind {
+                    'a' => component.added.insert(*entity_id),
+                    'r' => component.removed.insert(*entity_id),
+                    _ => component.modified.insert(*entity_id),
+                };
+            }
+            for (_, entity_id) in self.deaths.iter().filter(|(t, _)| *t > tick) {
+                changes.tombstones.insert(*entity_id);
+                if !self.hp.contains_key(entity_id) && !self.tags.contains_key(entity_id) {
+                    changes.despawned.insert(*entity_id);
+                }
+            }
+            Ok(changes)
+        }
+    }
+
+    #[test]
+    fn test_deltas_carry_only_dirty_rows_and_replay_to_the_world() {
+        let mut world = World::default();
+        for id in 0..100 {
+            world.set_hp(id, Some(id * 10));
+        }
+        world.set_tag(1, vec![1]);
+
+        let mut capture = IncrementalCapture::new(&world).unwrap();
+        let mut replayed = capture.snapshot().clone();
+
+        world.set_hp(5, Some(7));
+        world.set_hp(6, None);
+        world.set_hp(500, Some(1));
+        world.set_hp(5, Some(8));
+        let delta = capture.capture_delta(&world).unwrap();
+        assert_eq!(delta.changes.value_changes.len(), 1);
+        assert_eq!(delta.changes.components_added.len(), 1);
+        assert_eq!(delta.changes.components_removed.len(), 1);
+        replayed = delta.apply(&replayed).unwrap();
+        assert_eq!(replayed.content_checksum().unwrap(), world.capture().unwrap().content_checksum().unwrap());
+
+        world.set_tag(2, vec![2]);
+        world.set_hp(6, Some(60));
+        let delta = capture.capture_delta(&world).unwrap();
+        assert_eq!(delta.changes.blob_changes.len(), 1);
+        replayed = delta.apply(&replayed).unwrap();
+        assert_eq!(replayed.content_checksum().unwrap(), world.capture().unwrap().content_checksum().unwrap());
+
+        let delta = capture.capture_delta(&world).unwrap();
+        assert!(delta.is_empty());
+        assert_eq!(capture.tick(), world.tick);
+        assert_eq!(delta.target_checksum, UNVERIFIED_CHECKSUM);
+    }
+
+    #[test]
+    fn test_checksums_are_taken_every_interval_or_on_demand() {
+        let mut world = World::default();
+        world.set_hp(0, Some(1));
+        let mut capture = IncrementalCapture::new(&world).unwrap().with_checksum_interval(2);
+        let base = capture.snapshot().clone();
+
+        world.set_hp(0, Some(2));
+        let first = capture.capture_delta(&world).unwrap();
+        assert_eq!((first.base_checksum, first.target_checksum), (UNVERIFIED_CHECKSUM, UNVERIFIED_CHECKSUM));
+        let middle = first.apply(&base).unwrap();
+
+        world.set_hp(0, Some(3));
+        let second = capture.capture_delta(&world).unwrap();
+        assert_eq!(second.target_checksum, capture.checksum().unwrap());
+        assert!(second.apply(&base).is_err());
+        second.apply(&middle).unwrap();
+
+        world.set_hp(0, Some(4));
+        let third = capture.capture_verified_delta(&world).unwrap();
+        assert_eq!(third.base_checksum, second.target_checksum);
+        assert_ne!(third.target_checksum, UNVERIFIED_CHECKSUM);
+    }
+
+    #[test]
+    fn test_respawned_ids_do_not_inherit_components_of_the_dead_entity() {
+        let mut world = World::default();
+        for id in 0..3 {
+            world.set_hp(id, Some(id * 10));
+        }
+        world.set_tag(1, vec![1]);
+
+        let mut capture = IncrementalCapture::new(&world).unwrap();
+        let base = capture.snapshot().clone();
+
+        world.despawn(1);
+        world.set_hp(1, Some(99));
+        world.despawn(2);
+        let delta = capture.capture_delta(&world).unwrap();
+        assert_eq!(delta.changes.tombstones, vec![1, 2]);
+        assert_eq!(delta.changes.entities_removed, vec![2]);
+        assert_eq!(delta.changes.respawned(), BTreeSet::from([1]));
+        assert_eq!(delta.changes.components_added.len(), 1);
+
+        let replayed = delta.apply(&base).unwrap();
+        assert_eq!(replayed.content_checksum().unwrap(), world.capture().unwrap().content_checksum().unwrap());
+        assert_eq!(replayed.entity_components(1).len(), 1);
+
+        let stale = SnapshotDiff { tombstones: vec![0], ..SnapshotDiff::default() };
+        assert!(crate::delta::apply_diff(&replayed, &stale).is_err());
+    }
+}
@@ -0,0 +1,452 @@
+use crate::error::{PackError, Result};
+use crate::format::{
+    ComponentArchetype, ComponentData, EntityMetadata, FieldArray, FieldType, FieldValue, PackedSnapshot,
+    StructOfArraysData,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JsonHeader {
+    #[serde(default)]
+    timestamp: i64,
+    #[serde(default)]
+    schema: BTreeMap<ComponentId, Vec<JsonField>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    blobs: BTreeMap<ComponentId, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JsonField {
+    name: String,
+    #[serde(rename = "type")]
+    field_type: FieldType,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonEntity {
+    id: EntityId,
+    #[serde(default)]
+    components: BTreeMap<ComponentId, Option<Map<String, Value>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<JsonMetadata>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JsonMetadata {
+    #[serde(default)]
+    created_at: i64,
+    #[serde(default)]
+    modified_at: i64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: BTreeMap<String, Value>,
+}
+
+type JsonRow = (EntityId, Option<Map<String, Value>>);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonDocument {
+    #[serde(flatten)]
+    header: JsonHeader,
+    #[serde(default)]
+    entities: Vec<JsonEntity>,
+}
+
+impl PackedSnapshot {
+    pub fn to_json(&self) -> Result<String> {
+        let (header, entities) = export(self);
+        serde_json::to_string_pretty(&JsonDocument { header, entities })
+            .map_err(|e| PackError::Serialization(e.to_string()))
+    }
+
+    pub fn to_jsonl(&self) -> Result<String> {
+        let (header, entities) = export(self);
+
+        let mut output = serde_json::to_string(&header).map_err(|e| PackError::Serialization(e.to_string()))?;
+        output.push('\n');
+        for entity in &entities {
+            output.push_str(&serde_json::to_string(entity).map_err(|e| PackError::Serialization(e.to_string()))?);
+            output.push('\n');
+        }
+
+        Ok(output)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let document: JsonDocument =
+            serde_json::from_str(json).map_err(|e| PackError::Deserialization(e.to_string()))?;
+        import(document.header, document.entities)
+    }
+
+    pub fn from_jsonl(jsonl: &str) -> Result<Self> {
+        let mut header = JsonHeader::default();
+        let mut entities = Vec::new();
+
+        for (index, line) in jsonl.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let value: Value = serde_json::from_str(line)
+                .map_err(|e| PackError::Deserialization(format!("line {}: {}", index + 1, e)))?;
+
+            if value.get("id").is_some() {
+                entities.push(
+                    serde_json::from_value(value)
+                        .map_err(|e| PackError::Deserialization(format!("line {}: {}", index + 1, e)))?,
+                );
+            } else {
+                header = serde_json::from_value(value)
+                    .map_err(|e| PackError::Deserialization(format!("line {}: {}", index + 1, e)))?;
+            }
+        }
+
+        import(header, entities)
+    }
+}
+
+fn export(snapshot: &PackedSnapshot) -> (JsonHeader, Vec<JsonEntity>) {
+    let mut header = JsonHeader {
+        timestamp: snapshot.header.timestamp,
+        ..JsonHeader::default()
+    };
+    let mut entities: BTreeMap<EntityId, JsonEntity> = BTreeMap::new();
+
+    for archetype in &snapshot.archetypes {
+        match &archetype.data {
+            ComponentData::StructOfArrays(soa) => {
+                header.schema.insert(
+                    archetype.component_id.clone(),
+                    soa.field_names
+                        .iter()
+                        .zip(&soa.field_types)
+                        .map(|(name, field_type)| JsonField { name: name.clone(), field_type: *field_type })
+                        .collect(),
+                );
+            }
+            ComponentData::Blob(data) if !data.is_empty() => {
+                header.blobs.insert(archetype.component_id.clone(), to_hex(data));
+            }
+            ComponentData::Blob(_) => {}
+        }
+
+        for (row, entity_id) in archetype.entity_ids.iter().enumerate() {
+            let values = archetype.row_values(row).map(|values| {
+                values.into_iter().map(|(name, value)| (name, value_to_json(&value))).collect()
+            });
+
+            entities
+                .entry(*entity_id)
+                .or_insert_with(|| JsonEntity { id: *entity_id, components: BTreeMap::new(), metadata: None })
+                .components
+                .insert(archetype.component_id.clone(), values);
+        }
+    }
+
+    for (entity_id, metadata) in &snapshot.entity_metadata {
+        entities
+            .entry(*entity_id)
+            .or_insert_with(|| JsonEntity { id: *entity_id, components: BTreeMap::new(), metadata: None })
+            .metadata = Some(JsonMetadata {
+            created_at: metadata.created_at,
+            modified_at: metadata.modified_at,
+            tags: metadata.tags.clone(),
+            annotations: metadata
+                .annotations
+                .iter()
+                .map(|(key, value)| (key.clone(), value_to_json(value)))
+                .collect(),
+        });
+    }
+
+    (header, entities.into_values().collect())
+}
+
+fn import(header: JsonHeader, entities: Vec<JsonEntity>) -> Result<PackedSnapshot> {
+    let mut snapshot = PackedSnapshot::new();
+    snapshot.header.timestamp = header.timestamp;
+
+    let mut rows: BTreeMap<ComponentId, Vec<JsonRow>> = BTreeMap::new();
+    for entity in entities {
+        for (component_id, values) in entity.components {
+            rows.entry(component_id).or_default().push((entity.id, values));
+        }
+
+        if let Some(metadata) = entity.metadata {
+            let mut annotations = HashMap::new();
+            for (key, value) in metadata.annotations {
+                let field_type = infer_type(&value).ok_or_else(|| {
+                    PackError::Deserialization(format!("Entity {} annotation '{}' has an unsupported value", entity.id, key))
+                })?;
+                annotations.insert(key, json_to_value(&value, field_type).expect("inferred type accepts value"));
+            }
+
+            snapshot.entity_metadata.insert(
+                entity.id,
+                EntityMetadata {
+                    created_at: metadata.created_at,
+                    modified_at: metadata.modified_at,
+                    tags: metadata.tags,
+                    annotations,
+                },
+            );
+        }
+    }
+
+    for (component_id, rows) in rows {
+        let archetype = if rows.iter().all(|(_, values)| values.is_none()) {
+            let data = match header.blobs.get(&component_id) {
+                Some(hex) => from_hex(hex).ok_or_else(|| {
+                    PackError::Deserialization(format!("Blob data for {} is not valid hex", component_id))
+                })?,
+                None => Vec::new(),
+            };
+
+            ComponentArchetype {
+                component_id,
+                entity_ids: rows.into_iter().map(|(entity_id, _)| entity_id).collect(),
+                data: ComponentData::Blob(data),
+            }
+        } else {
+            let layout = match header.schema.get(&component_id) {
+                Some(fields) => fields.clone(),
+                None => infer_layout(&component_id, &rows)?,
+            };
+            import_columns(component_id, layout, rows)?
+        };
+
+        snapshot.archetypes.push(archetype);
+    }
+
+    snapshot.rebuild_entity_index();
+    snapshot.header.entity_count = snapshot.entity_index.len() as u64;
+    snapshot.header.component_count = snapshot.archetypes.len() as u64;
+    snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+
+    Ok(snapshot)
+}
+
+fn import_columns(
+    component_id: ComponentId,
+    layout: Vec<JsonField>,
+    rows: Vec<JsonRow>,
+) -> Result<ComponentArchetype> {
+    let mut soa = StructOfArraysData {
+        field_names: layout.iter().map(|field| field.name.clone()).collect(),
+        field_types: layout.iter().map(|field| field.field_type).collect(),
+        field_data: layout.iter().map(|field| FieldArray::with_type(field.field_type)).collect(),
+    };
+    let mut entity_ids = Vec::with_capacity(rows.len());
+
+    for (entity_id, values) in rows {
+        let values = values.ok_or_else(|| {
+            PackError::Deserialization(format!("Entity {} has no field values for {}", entity_id, component_id))
+        })?;
+
+        for (field, column) in layout.iter().zip(&mut soa.field_data) {
+            let value = values
+                .get(&field.name)
+                .and_then(|value| json_to_value(value, field.field_type))
+                .ok_or_else(|| {
+                    PackError::Deserialization(format!(
+                        "Entity {} has a missing or invalid {}.{} ({:?})",
+                        entity_id, component_id, field.name, field.field_type
+                    ))
+                })?;
+            column.push(value)?;
+        }
+
+        entity_ids.push(entity_id);
+    }
+
+    Ok(ComponentArchetype {
+        component_id,
+        entity_ids,
+        data: ComponentData::StructOfArrays(soa),
+    })
+}
+
+fn infer_layout(component_id: &str, rows: &[JsonRow]) -> Result<Vec<JsonField>> {
+    let mut types: BTreeMap<&String, FieldType> = BTreeMap::new();
+
+    for (_, values) in rows {
+        for (name, value) in values.iter().flatten() {
+            let inferred = infer_type(value).ok_or_else(|| {
+                PackError::Deserialization(format!("Cannot infer the type of {}.{}", component_id, name))
+            })?;
+
+            let merged = match types.get(name) {
+                None => inferred,
+                Some(existing) if *existing == inferred => inferred,
+                Some(FieldType::I64 | FieldType::U64 | FieldType::F64)
+                    if matches!(inferred, FieldType::I64 | FieldType::U64 | FieldType::F64) =>
+                {
+                    FieldType::F64
+                }
+                Some(existing) => {
+                    return Err(PackError::Deserialization(format!(
+                        "{}.{} mixes {:?} and {:?} values", component_id, name, existing, inferred
+                    )));
+                }
+            };
+            types.insert(name, merged);
+        }
+    }
+
+    Ok(types
+        .into_iter()
+        .map(|(name, field_type)| JsonField { name: name.clone(), field_type })
+        .collect())
+}
+
+fn infer_type(value: &Value) -> Option<FieldType> {
+    match value {
+        Value::Bool(_) => Some(FieldType::Bool),
+        Value::Number(n) if n.is_i64() => Some(FieldType::I64),
+        Value::Number(n) if n.is_u64() => Some(FieldType::U64),
+        Value::Number(_) | Value::Null => Some(FieldType::F64),
+        Value::String(_) => Some(FieldType::String),
+        Value::Array(items) if items.iter().all(|item| item.as_u64().is_some_and(|b| b <= 255)) => Some(FieldType::Bytes),
+        _ => None,
+    }
+}
+
+fn value_to_json(value: &FieldValue) -> Value {
+    match value {
+        FieldValue::Bool(v) => Value::from(*v),
+        FieldValue::I8(v) => Value::from(*v),
+        FieldValue::I16(v) => Value::from(*v),
+        FieldValue::I32(v) => Value::from(*v),
+        FieldValue::I64(v) => Value::from(*v),
+        FieldValue::U8(v) => Value::from(*v),
+        FieldValue::U16(v) => Value::from(*v),
+        FieldValue::U32(v) => Value::from(*v),
+        FieldValue::U64(v) => Value::from(*v),
+        FieldValue::F32(v) => v.to_string().parse::<f64>().map_or(Value::Null, Value::from),
+        FieldValue::F64(v) => Value::from(*v),
+        FieldValue::String(v) => Value::from(v.as_str()),
+        FieldValue::Bytes(v) => Value::from(to_hex(v)),
+    }
+}
+
+fn json_to_value(value: &Value, field_type: FieldType) -> Option<FieldValue> {
+    let float = || match value {
+        Value::Null => Some(f64::NAN),
+        _ => value.as_f64(),
+    };
+
+    Some(match field_type {
+        FieldType::Bool => FieldValue::Bool(value.as_bool()?),
+        FieldType::I8 => FieldValue::I8(value.as_i64()?.try_into().ok()?),
+        FieldType::I16 => FieldValue::I16(value.as_i64()?.try_into().ok()?),
+        FieldType::I32 => FieldValue::I32(value.as_i64()?.try_into().ok()?),
+        FieldType::I64 => FieldValue::I64(value.as_i64()?),
+        FieldType::U8 => FieldValue::U8(value.as_u64()?.try_into().ok()?),
+        FieldType::U16 => FieldValue::U16(value.as_u64()?.try_into().ok()?),
+        FieldType::U32 => FieldValue::U32(value.as_u64()?.try_into().ok()?),
+        FieldType::U64 => FieldValue::U64(value.as_u64()?),
+        FieldType::F32 => FieldValue::F32(float()? as f32),
+        FieldType::F64 => FieldValue::F64(float()?),
+        FieldType::String => FieldValue::String(value.as_str()?.to_string()),
+        FieldType::Bytes => FieldValue::Bytes(match value {
+            Value::String(hex) => from_hex(hex)?,
+            Value::Array(items) => items
+                .iter()
+                .map(|item| item.as_u64().and_then(|b| u8::try_from(b).ok()))
+                .collect::<Option<Vec<u8>>>()?,
+            _ => return None,
+        }),
+    })
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: vec![1, 2],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["y".to_string(), "x".to_string(), "label".to_string()],
+                field_types: vec![FieldType::F32, FieldType::I16, FieldType::Bytes],
+                field_data: vec![
+                    FieldArray::F32(vec![0.1, 2.5].into()),
+                    FieldArray::I16(vec![-3, 4].into()),
+                    FieldArray::Bytes(vec![vec![0xab], vec![]].into()),
+                ],
+            }),
+        });
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Script".to_string(),
+            entity_ids: vec![2],
+            data: ComponentData::Blob(vec![1, 2, 255]),
+        });
+        snapshot.annotate(2, "note", FieldValue::String("spawned by test".to_string()));
+        snapshot.rebuild_entity_index();
+        snapshot
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let snapshot = sample();
+
+        for restored in [
+            PackedSnapshot::from_json(&snapshot.to_json().unwrap()).unwrap(),
+            PackedSnapshot::from_jsonl(&snapshot.to_jsonl().unwrap()).unwrap(),
+        ] {
+            assert_eq!(restored.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+            assert_eq!(restored.header.entity_count, 2);
+        }
+
+        let jsonl = snapshot.to_jsonl().unwrap();
+        assert_eq!(jsonl.lines().count(), 3);
+        assert!(jsonl.contains("\"y\":0.1"));
+    }
+
+    #[test]
+    fn test_json_import_infers_types() {
+        let snapshot = PackedSnapshot::from_jsonl(
+            r#"
+            {"id": 5, "components": {"Health": {"hp": 10, "alive": true}}}
+            {"id": 6, "components": {"Health": {"hp": 7.5, "alive": false}, "Tag": null}}
+            "#,
+        )
+        .unwrap();
+
+        let health = snapshot.archetypes.iter().find(|a| a.component_id == "Health").unwrap();
+        assert_eq!(
+            health.row_values(0).unwrap(),
+            vec![
+                ("alive".to_string(), FieldValue::Bool(true)),
+                ("hp".to_string(), FieldValue::F64(10.0)),
+            ]
+        );
+        assert_eq!(snapshot.entity_components(6).len(), 2);
+
+        let invalid = PackedSnapshot::from_jsonl(r#"{"id": 1, "components": {"Health": {"hp": "full"}}}
+{"id": 2, "components": {"Health": {"hp": 3}}}"#);
+        assert!(invalid.is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, PackedSnapshot};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use tx2_link::{ComponentId, EntityId};
+
+// One of the world's own archetype tables: the components its entities
+// share, in the world's column order, and how many rows each chunk holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NativeTable {
+    pub components: Vec<ComponentId>,
+    pub chunk_rows: Vec<u32>,
+}
+
+impl NativeTable {
+    pub fn new<I, S>(components: I, chunk_rows: Vec<u32>) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ComponentId>,
+    {
+        Self {
+            components: components.into_iter().map(Into::into).collect(),
+            chunk_rows,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunk_rows.iter().map(|rows| *rows as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// How the world laid out the rows it captured. Each component's archetype
+// holds the rows of every table with that component, table after table, and
+// a table's components list its entities in the same order, so each table
+// column is one contiguous row range the ECS can copy in bulk.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NativeLayout {
+    pub tables: Vec<NativeTable>,
+}
+
+// A table resolved against a snapshot. Columns follow the table's column
+// order; ranges index the rows of each component archetype.
+#[derive(Debug, Clone)]
+pub struct TableView<'a> {
+    pub table: &'a NativeTable,
+    pub entity_ids: &'a [EntityId],
+    pub columns: Vec<(&'a ComponentArchetype, Range<usize>)>,
+}
+
+impl TableView<'_> {
+    // Row ranges of each chunk, relative to the start of the table.
+    pub fn chunks(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.table.chunk_rows.iter().scan(0, |start, rows| {
+            let range = *start..*start + *rows as usize;
+            *start = range.end;
+            Some(range)
+        })
+    }
+}
+
+impl NativeLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_table(mut self, table: NativeTable) -> Self {
+        self.tables.push(table);
+        self
+    }
+
+    // Fails if the snapshot no longer has the rows the layout describes,
+    // e.g. after a filter or a delta changed it since the capture.
+    pub fn tables<'a>(&'a self, snapshot: &'a PackedSnapshot) -> Result<Vec<TableView<'a>>> {
+        let mismatch = |message: String| PackError::InvalidFormat(format!("Native layout mismatch: {}", message));
+        let archetypes: HashMap<&ComponentId, &ComponentArchetype> =
+            snapshot.archetypes.iter().map(|archetype| (&archetype.component_id, archetype)).collect();
+        let mut offsets: HashMap<&ComponentId, usize> = HashMap::new();
+
+        let mut views = Vec::with_capacity(self.tables.len());
+        for (index, table) in self.tables.iter().enumerate() {
+            let mut entity_ids: Option<&[EntityId]> = None;
+            let mut columns = Vec::with_capacity(table.components.len());
+            for component_id in &table.components {
+                let archetype = archetypes
+                    .get(component_id)
+                    .ok_or_else(|| mismatch(format!("table {} lists missing component {}", index, component_id)))?;
+                let offset = offsets.entry(component_id).or_default();
+                let range = *offset..*offset + table.len();
+                *offset = range.end;
+
+                let ids = archetype.entity_ids.get(range.clone()).ok_or_else(|| {
+                    let rows = archetype.entity_ids.len();
+                    mismatch(format!("{} has {} rows, table {} needs {}", component_id, rows, index, range.end))
+                })?;
+                if entity_ids.is_some_and(|first| first != ids) {
+                    return Err(mismatch(format!("table {} columns list different entities", index)));
+                }
+                entity_ids = Some(ids);
+                columns.push((*archetype, range));
+            }
+
+            views.push(TableView {
+                table,
+                entity_ids: entity_ids.unwrap_or_default(),
+                columns,
+            });
+        }
+
+        for archetype in &snapshot.archetypes {
+            let covered = offsets.get(&archetype.component_id).copied().unwrap_or(0);
+            if covered != archetype.entity_ids.len() {
+                return Err(mismatch(format!(
+                    "tables cover {} of {} rows of {}",
+                    covered,
+                    archetype.entity_ids.len(),
+                    archetype.component_id
+                )));
+            }
+        }
+
+        Ok(views)
+    }
+}
+
+impl PackedSnapshot {
+    // The captured tables, if the snapshot still matches its layout.
+    pub fn native_tables(&self) -> Option<Vec<TableView<'_>>> {
+        self.layout.as_ref().and_then(|layout| layout.tables(self).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::{WorldCapture, WorldRestore};
+    use crate::format::{ComponentData, FieldArray, FieldType, PackFormat, StructOfArraysData};
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+
+    // Two tables, (Position) and (Position, Velocity), stored as chunks of
+    // plain f32 columns the way an archetype ECS would keep them.
+    type Table = (Vec<EntityId>, Vec<(ComponentId, Vec<f32>)>);
+
+    #[derive(Default)]
+    struct World {
+        tables: Vec<Table>,
+        inserted: usize,
+    }
+
+    impl WorldCapture for World {
+        fn component_ids(&self) -> Result<Vec<ComponentId>> {
+            Ok(vec!["Position".to_string(), "Velocity".to_string()])
+        }
+
+        fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+            let mut entity_ids = Vec::new();
+            let mut values = Vec::new();
+            for (ids, columns) in &self.tables {
+                if let Some((_, column)) = columns.iter().find(|(id, _)| id == component_id) {
+                    entity_ids.extend_from_slice(ids);
+                    values.extend_from_slice(column);
+                }
+            }
+            Ok(Some(ComponentArchetype {
+                component_id: component_id.clone(),
+                entity_ids,
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["x".to_string()],
+                    field_types: vec![FieldType::F32],
+                    field_data: vec![FieldArray::F32(values.into())],
+                }),
+            }))
+        }
+
+        fn capture_layout(&self) -> Result<Option<NativeLayout>> {
+            Ok(Some(NativeLayout {
+                tables: self
+                    .tables
+                    .iter()
+                    .map(|(ids, columns)| NativeTable::new(columns.iter().map(|(id, _)| id.clone()), vec![ids.len() as u32]))
+                    .collect(),
+            }))
+        }
+    }
+
+    impl WorldRestore for World {
+        fn restore_archetype(&mut self, archetype: &ComponentArchetype) -> Result<()> {
+            self.inserted += archetype.entity_ids.len();
+            Ok(())
+        }
+
+        fn restore_tables(&mut self, tables: &[TableView<'_>]) -> Result<bool> {
+            for view in tables {
+                let columns = view
+                    .columns
+                    .iter()
+                    .map(|(archetype, range)| match &archetype.data {
+                        ComponentData::StructOfArrays(soa) => match &soa.field_data[0] {
+                            FieldArray::F32(column) => (archetype.component_id.clone(), column[range.clone()].to_vec()),
+                            _ => unreachable!(),
+                        },
+                        ComponentData::Blob(_) => unreachable!(),
+                    })
+                    .collect();
+                self.tables.push((view.entity_ids.to_vec(), columns));
+            }
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_tables_restore_in_bulk_and_fall_back_when_stale() {
+        let world = World {
+            tables: vec![
+                (vec![7, 3], vec![("Position".to_string(), vec![7.0, 3.0])]),
+                (
+                    vec![1, 9, 4],
+                    vec![("Velocity".to_string(), vec![0.1, 0.9, 0.4]), ("Position".to_string(), vec![1.0, 9.0, 4.0])],
+                ),
+            ],
+            inserted: 0,
+        };
+        let snapshot = world.capture().unwrap();
+        for format in [PackFormat::Custom, PackFormat::Bincode] {
+            let mut packed = snapshot.clone();
+            packed.header.format = format;
+            let bytes = SnapshotWriter::new().write_to_bytes(&packed).unwrap();
+            assert_eq!(SnapshotReader::new().read_from_bytes(&bytes).unwrap().layout, snapshot.layout, "{:?}", format);
+        }
+
+        let tables = snapshot.native_tables().unwrap();
+        assert_eq!(tables[1].entity_ids, &[1, 9, 4]);
+        assert_eq!(tables[1].columns[1].1, 2..5);
+        assert_eq!(tables[1].chunks().collect::<Vec<_>>(), vec![0..3]);
+
+        let mut restored = World::default();
+        restored.restore(&snapshot).unwrap();
+        assert_eq!(restored.tables, world.tables);
+        assert_eq!(restored.inserted, 0);
+
+        let mut filtered = snapshot.clone();
+        filtered.archetypes[0].retain_entities(|id| id != 3);
+        assert!(filtered.native_tables().is_none());
+        let mut restored = World::default();
+        restored.restore(&filtered).unwrap();
+        assert_eq!(restored.inserted, 7);
+    }
+}
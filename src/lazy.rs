@@ -0,0 +1,368 @@
+use crate::adapter::WorldCapture;
+use crate::bounds;
+use crate::error::{PackError, PipelineStage, Result, ResultExt};
+use crate::format::{ComponentArchetype, EntityMetadata, PackedSnapshot, SnapshotHeader};
+use crate::layout::NativeLayout;
+use crate::sectioned::{self, ArchetypeEntry, Section, TableOfContents};
+use crate::storage::{read_at, SnapshotReader};
+use serde::de::DeserializeOwned;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::sync::Mutex;
+use tx2_link::{ComponentId, EntityId};
+
+enum Source {
+    Memory(Vec<u8>),
+    File(Mutex<File>),
+}
+
+struct Sections {
+    toc: TableOfContents,
+    source: Source,
+    reader: SnapshotReader,
+}
+
+impl Sections {
+    fn decode<T: DeserializeOwned>(&self, header: &SnapshotHeader, section: &Section) -> Result<T> {
+        bincode::deserialize(&self.open(header, section)?)
+            .map_err(|e| PackError::Deserialization(e.to_string()))
+            .in_stage(PipelineStage::Decode)
+    }
+
+    fn decode_archetype(&self, header: &SnapshotHeader, entry: &ArchetypeEntry) -> Result<ComponentArchetype> {
+        let decode = || {
+            let raw = self.open(header, &entry.section)?;
+            let archetype = sectioned::decode_archetype(&raw, header.flags).in_stage(PipelineStage::Decode)?;
+            self.reader.limits().check_archetype(&archetype)?;
+            archetype.check_consistency()?;
+            self.reader.interpret(archetype).in_stage(PipelineStage::Decode)
+        };
+        decode().with_component(&entry.component_id)
+    }
+
+    fn open(&self, header: &SnapshotHeader, section: &Section) -> Result<Vec<u8>> {
+        let open = |sealed: &[u8]| self.reader.open(header, sealed);
+
+        match &self.source {
+            Source::Memory(data) => sectioned::open_chunked(sectioned::section_bytes(data, section)?, open),
+            Source::File(file) => {
+                bounds::end_within(section.offset, section.size, header.data_size, "Section")?;
+                let offset = bounds::end(header.data_offset, section.offset, "Section")?;
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                let sealed = read_at(&mut file, offset, section.size).in_stage(PipelineStage::Read)?;
+                sectioned::open_chunked(&sealed, open)
+            }
+        }
+    }
+}
+
+pub struct LazySnapshot {
+    header: SnapshotHeader,
+    component_ids: Vec<ComponentId>,
+    entity_counts: Vec<u64>,
+    sections: Option<Sections>,
+    archetypes: Vec<OnceCell<ComponentArchetype>>,
+    entity_metadata: OnceCell<HashMap<EntityId, EntityMetadata>>,
+    resources: OnceCell<BTreeMap<String, Vec<u8>>>,
+    layout: Option<NativeLayout>,
+}
+
+impl LazySnapshot {
+    pub(crate) fn from_sections(header: SnapshotHeader, data: Vec<u8>, reader: SnapshotReader) -> Result<Self> {
+        let toc = sectioned::read_toc(&data, |sealed| reader.open(&header, sealed))?;
+        reader.limits().check_toc(&toc)?;
+        Ok(Self::with_sections(header, toc, Source::Memory(data), reader))
+    }
+
+    pub(crate) fn from_file(header: SnapshotHeader, mut file: File, reader: SnapshotReader) -> Result<Self> {
+        let (toc, _) = sectioned::read_file_toc(&mut file, &header, |sealed| reader.open(&header, sealed))?;
+        reader.limits().check_toc(&toc)?;
+        Ok(Self::with_sections(header, toc, Source::File(Mutex::new(file)), reader))
+    }
+
+    fn with_sections(header: SnapshotHeader, toc: TableOfContents, source: Source, reader: SnapshotReader) -> Self {
+        Self {
+            header,
+            component_ids: toc.archetypes.iter().map(|e| e.component_id.clone()).collect(),
+            entity_counts: toc.archetypes.iter().map(|e| e.entity_count).collect(),
+            archetypes: toc.archetypes.iter().map(|_| OnceCell::new()).collect(),
+            entity_metadata: OnceCell::new(),
+            resources: OnceCell::new(),
+            layout: toc.layout.clone(),
+            sections: Some(Sections {
+                toc,
+                source,
+                reader,
+            }),
+        }
+    }
+
+    pub(crate) fn from_snapshot(snapshot: PackedSnapshot) -> Self {
+        Self {
+            header: snapshot.header,
+            component_ids: snapshot.archetypes.iter().map(|a| a.component_id.clone()).collect(),
+            entity_counts: snapshot.archetypes.iter().map(|a| a.entity_ids.len() as u64).collect(),
+            archetypes: snapshot.archetypes.into_iter().map(OnceCell::from).collect(),
+            entity_metadata: OnceCell::from(snapshot.entity_metadata),
+            resources: OnceCell::from(snapshot.resources),
+            layout: snapshot.layout,
+            sections: None,
+        }
+    }
+
+    pub fn header(&self) -> &SnapshotHeader {
+        &self.header
+    }
+
+    pub fn component_ids(&self) -> &[ComponentId] {
+        &self.component_ids
+    }
+
+    pub fn entity_count(&self, component_id: &ComponentId) -> Option<u64> {
+        self.position(component_id).map(|index| self.entity_counts[index])
+    }
+
+    pub fn is_loaded(&self, component_id: &ComponentId) -> bool {
+        self.position(component_id)
+            .is_some_and(|index| self.archetypes[index].get().is_some())
+    }
+
+    pub fn archetype(&self, component_id: &ComponentId) -> Result<Option<&ComponentArchetype>> {
+        match self.position(component_id) {
+            Some(index) => self.archetype_at(index).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn entity_metadata(&self) -> Result<&HashMap<EntityId, EntityMetadata>> {
+        if let Some(metadata) = self.entity_metadata.get() {
+            return Ok(metadata);
+        }
+
+        let sections = self.sections()?;
+        let metadata = sections.decode(&self.header, &sections.toc.entity_metadata)?;
+        sections.reader.limits().check_entity_metadata(&metadata)?;
+        Ok(self.entity_metadata.get_or_init(|| metadata))
+    }
+
+    pub fn resources(&self) -> Result<&BTreeMap<String, Vec<u8>>> {
+        if let Some(resources) = self.resources.get() {
+            return Ok(resources);
+        }
+
+        let sections = self.sections()?;
+        let resources = if sections.toc.resources.size == 0 {
+            BTreeMap::new()
+        } else {
+            sections.decode(&self.header, &sections.toc.resources)?
+        };
+        Ok(self.resources.get_or_init(|| resources))
+    }
+
+    pub fn layout(&self) -> Option<&NativeLayout> {
+        self.layout.as_ref()
+    }
+
+    pub fn into_snapshot(self) -> Result<PackedSnapshot> {
+        self.entity_metadata()?;
+        self.resources()?;
+
+        let mut archetypes: Vec<Option<ComponentArchetype>> =
+            self.archetypes.into_iter().map(OnceCell::into_inner).collect();
+        let missing: Vec<usize> = (0..archetypes.len()).filter(|&i| archetypes[i].is_none()).collect();
+
+        if let Some(sections) = &self.sections {
+            let header = &self.header;
+            let decoded = sectioned::map_sections(&missing, |&index| {
+                sections.decode_archetype(header, &sections.toc.archetypes[index])
+            })?;
+            for (index, archetype) in missing.into_iter().zip(decoded) {
+                archetypes[index] = Some(archetype);
+            }
+        }
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header = self.header;
+        snapshot.archetypes = archetypes.into_iter().flatten().collect();
+        snapshot.entity_metadata = self.entity_metadata.into_inner().unwrap_or_default();
+        snapshot.resources = self.resources.into_inner().unwrap_or_default();
+        snapshot.layout = self.layout;
+        snapshot.rebuild_entity_index();
+
+        Ok(snapshot)
+    }
+
+    fn position(&self, component_id: &ComponentId) -> Option<usize> {
+        self.component_ids.iter().position(|id| id == component_id)
+    }
+
+    fn archetype_at(&self, index: usize) -> Result<&ComponentArchetype> {
+        if let Some(archetype) = self.archetypes[index].get() {
+            return Ok(archetype);
+        }
+
+        let sections = self.sections()?;
+        let archetype = sections.decode_archetype(&self.header, &sections.toc.archetypes[index])?;
+        Ok(self.archetypes[index].get_or_init(|| archetype))
+    }
+
+    fn sections(&self) -> Result<&Sections> {
+        self.sections
+            .as_ref()
+            .ok_or_else(|| PackError::InvalidFormat("Snapshot has no sections to load".to_string()))
+    }
+}
+
+impl WorldCapture for LazySnapshot {
+    fn component_ids(&self) -> Result<Vec<ComponentId>> {
+        Ok(self.component_ids.clone())
+    }
+
+    fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+        Ok(self.archetype(component_id)?.cloned())
+    }
+
+    fn capture_entity_metadata(&self) -> Result<HashMap<EntityId, EntityMetadata>> {
+        Ok(self.entity_metadata()?.clone())
+    }
+
+    fn capture_resources(&self) -> Result<BTreeMap<String, Vec<u8>>> {
+        Ok(self.resources()?.clone())
+    }
+
+    fn capture_layout(&self) -> Result<Option<NativeLayout>> {
+        Ok(self.layout.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentData, FieldArray, FieldType, PackFormat, StructOfArraysData};
+    use crate::storage::SnapshotWriter;
+
+    fn archetype(component_id: &str, values: Vec<f32>) -> ComponentArchetype {
+        ComponentArchetype {
+            component_id: component_id.to_string(),
+            entity_ids: (0..values.len() as u32).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["value".to_string()],
+                field_types: vec![FieldType::F32],
+                field_data: vec![FieldArray::F32(values.into())],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_lazy_archetype_loading() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype("Position", vec![1.0, 2.0, 3.0]));
+        snapshot.archetypes.push(archetype("Health", vec![100.0]));
+        snapshot.entity_metadata.insert(0, EntityMetadata::new());
+        snapshot.rebuild_entity_index();
+
+        let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+        let lazy = SnapshotReader::new().read_lazy_from_bytes(&bytes).unwrap();
+
+        let health = "Health".to_string();
+        let position = "Position".to_string();
+        assert_eq!(lazy.component_ids(), [position.clone(), health.clone()]);
+        assert_eq!(lazy.entity_count(&position), Some(3));
+        assert!(!lazy.is_loaded(&health));
+
+        assert_eq!(lazy.archetype(&health).unwrap().unwrap().entity_ids, vec![0]);
+        assert!(lazy.is_loaded(&health));
+        assert!(!lazy.is_loaded(&position));
+        assert!(lazy.archetype(&"Velocity".to_string()).unwrap().is_none());
+
+        let restored = lazy.into_snapshot().unwrap();
+        assert_eq!(restored.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+
+        let eager = SnapshotReader::new().read_from_bytes(&bytes).unwrap();
+        assert_eq!(eager.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+        assert_eq!(eager.header.format, PackFormat::Custom);
+    }
+
+    #[test]
+    fn test_lazy_file_reads_sections_on_demand() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("world.tx2pack");
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype("Position", vec![1.0, 2.0]));
+        snapshot.archetypes.push(archetype("Health", vec![50.0]));
+        SnapshotWriter::new().write_to_file(&snapshot, &path).unwrap();
+
+        let reader = SnapshotReader::new();
+        assert_eq!(reader.read_header_from_file(&path).unwrap().format, PackFormat::Custom);
+
+        let lazy = reader.read_lazy_from_file(&path).unwrap();
+        assert_eq!(lazy.archetype(&"Position".to_string()).unwrap().unwrap().entity_ids, vec![0, 1]);
+        assert!(!lazy.is_loaded(&"Health".to_string()));
+        assert_eq!(lazy.into_snapshot().unwrap().content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+
+        // Without chunk checksums the whole file is hashed up front.
+        SnapshotWriter::new().with_chunk_checksums(false).write_to_file(&snapshot, &path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - crate::format::HEADER_SIZE as usize - 1;
+        bytes[last] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(reader.read_lazy_from_file(&path).map_err(PackError::into_root), Err(PackError::ChecksumMismatch)));
+    }
+
+    #[test]
+    fn test_lazy_file_pinpoints_corrupt_chunk() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("world.tx2pack");
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype("Position", (0..1000).map(|v| v as f32).collect()));
+        snapshot.archetypes.push(archetype("Health", vec![50.0]));
+        let writer = SnapshotWriter::new().with_section_chunk_size(1024);
+        writer.write_to_file(&snapshot, &path).unwrap();
+
+        let reader = SnapshotReader::new();
+        let lazy = reader.read_lazy_from_file(&path).unwrap();
+        let section = lazy.sections.as_ref().unwrap().toc.archetypes[0].section;
+
+        // Flip a byte inside the third chunk of the Position section.
+        let mut bytes = std::fs::read(&path).unwrap();
+        let mut offset = (lazy.header.data_offset + section.offset) as usize;
+        for _ in 0..2 {
+            let length = u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize;
+            offset += 8 + length;
+        }
+        bytes[offset + 8] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let lazy = reader.read_lazy_from_file(&path).unwrap();
+        assert!(lazy.archetype(&"Health".to_string()).unwrap().is_some());
+        assert!(matches!(
+            lazy.archetype(&"Position".to_string()).map_err(PackError::into_root),
+            Err(PackError::ChunkChecksumMismatch { chunk: 2 })
+        ));
+        assert!(matches!(reader.read_from_bytes(&bytes).map_err(PackError::into_root), Err(PackError::ChecksumMismatch)));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_lazy_encrypted_sections() {
+        use crate::encryption::EncryptionKey;
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype("Position", vec![4.0]));
+
+        let key = EncryptionKey::generate();
+        let bytes = SnapshotWriter::new().with_encryption(key.clone()).write_to_bytes(&snapshot).unwrap();
+
+        assert!(SnapshotReader::new().read_lazy_from_bytes(&bytes).is_err());
+
+        let lazy = SnapshotReader::new().with_encryption(key).read_lazy_from_bytes(&bytes).unwrap();
+        assert!(lazy.archetype(&"Position".to_string()).unwrap().is_some());
+    }
+}
@@ -0,0 +1,201 @@
+// Reads snapshots written by 1.x. Their header is 117 bytes, with no flags
+// or checksum algorithm (the checksum is always SHA-256), and no copy of it
+// follows the data. The payload is the 1.x PackedSnapshot, whose entity
+// metadata has no annotations; archetypes are unchanged. 1.x had no deltas,
+// patches or sectioned packs. Writing a snapshot read this way back stamps
+// the current version, which is how a store is upgraded.
+use crate::checksum::ChecksumAlgorithm;
+use crate::error::{PackError, Result};
+use crate::format::{
+    ComponentArchetype, CompressionType, EntityMetadata, PackFormat, PackedSnapshot, SnapshotHeader, LEGACY_VERSION_MAJOR,
+};
+use crate::storage::deserialize_value;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tx2_link::EntityId;
+
+pub const LEGACY_HEADER_SIZE: u64 = 117;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SnapshotHeaderV1 {
+    magic: [u8; 8],
+    version: u32,
+    format: PackFormat,
+    compression: CompressionType,
+    encrypted: bool,
+    checksum: [u8; 32],
+    timestamp: i64,
+    entity_count: u64,
+    component_count: u64,
+    archetype_count: u64,
+    data_offset: u64,
+    data_size: u64,
+    metadata_offset: u64,
+    metadata_size: u64,
+}
+
+impl From<SnapshotHeaderV1> for SnapshotHeader {
+    fn from(header: SnapshotHeaderV1) -> Self {
+        Self {
+            magic: header.magic,
+            version: header.version,
+            format: header.format,
+            compression: header.compression,
+            encrypted: header.encrypted,
+            flags: 0,
+            checksum_algorithm: ChecksumAlgorithm::Sha256,
+            checksum: header.checksum,
+            timestamp: header.timestamp,
+            entity_count: header.entity_count,
+            component_count: header.component_count,
+            archetype_count: header.archetype_count,
+            data_offset: header.data_offset,
+            data_size: header.data_size,
+            metadata_offset: header.metadata_offset,
+            metadata_size: header.metadata_size,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntityMetadataV1 {
+    created_at: i64,
+    modified_at: i64,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PackedSnapshotV1 {
+    header: SnapshotHeaderV1,
+    archetypes: Vec<ComponentArchetype>,
+    entity_metadata: HashMap<EntityId, EntityMetadataV1>,
+}
+
+// The version sits right after the magic in every layout.
+pub(crate) fn is_legacy(bytes: &[u8]) -> bool {
+    bytes.get(8..12) == Some(&(LEGACY_VERSION_MAJOR as u32).to_le_bytes()[..])
+}
+
+pub(crate) fn decode_header(bytes: &[u8]) -> Result<SnapshotHeader> {
+    let encoded = bytes.get(..LEGACY_HEADER_SIZE as usize).ok_or_else(|| {
+        PackError::InvalidFormat(format!("Buffer of {} bytes is shorter than the 1.x header", bytes.len()))
+    })?;
+    let header: SnapshotHeaderV1 = bincode::deserialize(encoded)?;
+
+    if header.data_offset < LEGACY_HEADER_SIZE {
+        return Err(PackError::InvalidFormat(format!(
+            "Data offset {} overlaps the {}-byte 1.x header",
+            header.data_offset, LEGACY_HEADER_SIZE
+        )));
+    }
+
+    Ok(header.into())
+}
+
+pub(crate) fn decode_snapshot(data: &[u8], header: &SnapshotHeader) -> Result<PackedSnapshot> {
+    let legacy: PackedSnapshotV1 = deserialize_value(data, header.format)?;
+
+    let mut snapshot = PackedSnapshot::new();
+    snapshot.header = header.clone();
+    snapshot.archetypes = legacy.archetypes;
+    snapshot.entity_metadata = legacy
+        .entity_metadata
+        .into_iter()
+        .map(|(id, metadata)| {
+            let metadata = EntityMetadata {
+                created_at: metadata.created_at,
+                modified_at: metadata.modified_at,
+                tags: metadata.tags,
+                annotations: HashMap::new(),
+            };
+            (id, metadata)
+        })
+        .collect();
+
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{compress, CompressionCodec};
+    use crate::format::{ComponentData, FieldArray, FieldType, StructOfArraysData, FORMAT_VERSION, MAGIC_NUMBER};
+    use crate::storage::{SnapshotReader, SnapshotStore, SnapshotWriter};
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+
+    // What a 1.x SnapshotWriter put on disk.
+    fn write_v1(archetypes: Vec<ComponentArchetype>) -> Vec<u8> {
+        let mut header = SnapshotHeaderV1 {
+            magic: *MAGIC_NUMBER,
+            version: 1,
+            format: PackFormat::Bincode,
+            compression: CompressionType::Zstd,
+            encrypted: false,
+            checksum: [0; 32],
+            timestamp: 1_700_000_000,
+            entity_count: 2,
+            component_count: 1,
+            archetype_count: 1,
+            data_offset: 0,
+            data_size: 0,
+            metadata_offset: 0,
+            metadata_size: 0,
+        };
+        let entity_metadata = [(7, EntityMetadataV1 { created_at: 1, modified_at: 2, tags: vec!["boss".into()] })];
+        let payload = PackedSnapshotV1 {
+            header: header.clone(),
+            archetypes,
+            entity_metadata: entity_metadata.into_iter().collect(),
+        };
+        let data = compress(&bincode::serialize(&payload).unwrap(), CompressionCodec::zstd_default()).unwrap();
+
+        header.checksum = Sha256::digest(&data).into();
+        header.data_size = data.len() as u64;
+        header.data_offset = bincode::serialized_size(&header).unwrap();
+        let mut bytes = bincode::serialize(&header).unwrap();
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+
+    #[test]
+    fn test_stores_read_and_upgrade_1x_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        let archetype = ComponentArchetype {
+            component_id: "Health".to_string(),
+            entity_ids: vec![7, 9],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["hp".to_string()],
+                field_types: vec![FieldType::U32],
+                field_data: vec![FieldArray::U32(vec![100, 40].into())],
+            }),
+        };
+        let bytes = write_v1(vec![archetype]);
+        assert_eq!(SnapshotHeader::decode(&bytes).unwrap().data_offset, LEGACY_HEADER_SIZE);
+        std::fs::write(temp_dir.path().join("old.tx2pack"), &bytes).unwrap();
+
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        assert_eq!(store.list().unwrap(), vec!["old"]);
+        assert!(store.quarantined().unwrap().is_empty());
+        assert!(crate::validate(temp_dir.path().join("old.tx2pack")).unwrap().is_valid());
+
+        let reader = SnapshotReader::new();
+        let (snapshot, metadata) = store.load("old", &reader).unwrap();
+        assert!(snapshot.header.is_legacy());
+        assert_eq!(snapshot.locate(9).len(), 1);
+        assert_eq!(snapshot.entity_metadata[&7].tags, vec!["boss"]);
+        assert_eq!(reader.read_from_bytes(&bytes).unwrap().archetypes.len(), 1);
+
+        // Saving it back is the upgrade.
+        store.save(&snapshot, &metadata, &SnapshotWriter::new()).unwrap();
+        let upgraded = store.read_header("old").unwrap();
+        assert_eq!(upgraded.version, FORMAT_VERSION);
+        let (reloaded, _) = store.load("old", &reader).unwrap();
+        assert_eq!(reloaded.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+
+        // A 1.x header claiming a delta is not one 1.x could have written.
+        let mut delta = bytes.clone();
+        delta[..8].copy_from_slice(crate::format::DELTA_MAGIC_NUMBER);
+        assert!(reader.read_delta_from_bytes(&delta).is_err());
+    }
+}
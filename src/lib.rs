@@ -1,19 +1,172 @@
 pub mod format;
+pub mod legacy;
+pub mod column;
+pub mod flatbuffer;
+pub mod sectioned;
+pub mod lazy;
+pub mod cache;
+pub mod validate;
+pub mod recover;
+pub mod limits;
+pub mod read_options;
 pub mod storage;
+pub mod stream_write;
+pub mod estimate;
 pub mod compression;
+pub mod checksum;
 pub mod encryption;
 pub mod checkpoint;
 pub mod replay;
 pub mod error;
 pub mod metadata;
+pub mod store_query;
+pub mod store_sync;
+pub mod shipping;
+pub mod catalog;
+pub mod search;
+pub mod quarantine;
+pub mod selector;
+pub mod query;
+pub mod timeseries;
+pub mod history;
+pub mod compare;
+pub mod diff;
+pub mod diff_format;
+pub mod stream_diff;
+pub mod changeset;
+pub mod delta;
+pub mod merge;
+pub mod patch;
+pub mod remap;
+pub mod compose;
+pub mod prefab;
+pub mod json;
+pub mod capture;
+pub mod adapter;
+pub mod incremental;
+pub mod recorder;
+pub mod parallel;
+pub mod registry;
+pub mod layout;
+pub mod world;
+pub mod packable;
+pub mod builder;
+pub mod backend;
+pub mod transfer;
+pub mod replication;
+pub mod upload;
+#[cfg(feature = "broadcast")]
+pub mod broadcast;
+#[cfg(feature = "quic")]
+pub mod quic;
+mod bounds;
+mod telemetry;
+mod throttle;
 
-pub use format::{PackFormat, SnapshotHeader, ComponentArchetype};
-pub use storage::{SnapshotWriter, SnapshotReader, SnapshotStore};
-pub use compression::{CompressionCodec, compress, decompress};
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
+#[cfg(feature = "rkyv")]
+pub mod archive;
+
+#[cfg(feature = "protobuf")]
+pub mod proto;
+
+#[cfg(feature = "arena")]
+pub mod arena;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+
+#[cfg(feature = "bevy")]
+pub mod bevy;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "datafusion")]
+pub mod datafusion;
+
+#[cfg(feature = "http")]
+pub mod http;
+
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
+pub use format::{PackFormat, SnapshotHeader, ComponentArchetype, FieldValue};
+pub use column::Column;
+pub use storage::{validate_snapshot_id, DurabilityLevel, SnapshotWriter, SnapshotReader, SnapshotStore, VerifyMode};
+pub use estimate::SizeEstimate;
+pub use lazy::LazySnapshot;
+pub use cache::CachingReader;
+pub use validate::{validate, ValidationIssue, ValidationReport};
+pub use recover::{repair_header, DamageReport, DamagedSection, HeaderRepair, RecoveredSnapshot};
+pub use limits::ParseLimits;
+pub use read_options::{ReadMode, ReadOptions, ReadResult, ReadWarning};
+pub use stream_write::StreamingWriter;
+pub use compression::{CompressionCodec, compress, decompress, decompress_unbounded};
+pub use checksum::ChecksumAlgorithm;
 pub use checkpoint::{Checkpoint, CheckpointManager};
-pub use replay::{ReplayEngine, TimeTravel};
-pub use error::{PackError, Result};
-pub use metadata::SnapshotMetadata;
+pub use replay::{ReplayEngine, TimeTravel, SeekMode};
+pub use error::{ErrorContext, PackError, PipelineStage, Result, ResultExt};
+pub use metadata::{SnapshotMetadata, Provenance, MetadataSchema, MetadataValueType};
+pub use store_query::{StoreQuery, SortKey, SortOrder, ListOptions, ListPage};
+pub use store_sync::{SyncOptions, SyncReport};
+pub use shipping::{CheckpointShipper, StandbyLag, StandbyReport};
+pub use catalog::{Catalog, CatalogEntry};
+pub use search::SearchIndex;
+pub use quarantine::QuarantineEntry;
+pub use selector::{LabelSelector, SelectorOp};
+pub use query::{ColumnQuery, Predicate, QueryResult};
+pub use timeseries::{SeriesQuery, TimeSeries};
+pub use history::{EntityHistory, EntityFrame, ComponentState};
+pub use compare::ComparisonReport;
+pub use diff::{diff, diff_with_options, diverges, DiffOptions, FloatTolerance, SnapshotDiff, DiffFilter, ComponentEntry, ValueChange, BlobChange};
+pub use diff_format::DiffFormatter;
+pub use stream_diff::{ArchetypeSource, DiffChunk, StreamingDiff};
+pub use changeset::{ChangeSet, ComponentChanges};
+pub use delta::{DeltaSnapshot, DeltaChain, UNVERIFIED_CHECKSUM, EntityMetadataChange, ResourceChange, ColumnDelta, ColumnEncoding, apply_diff};
+pub use merge::{merge, MergeConflict, MergeResult, ConflictResolver, Resolution};
+pub use patch::{Patch, PatchWriter, PatchReader, PATCH_EXTENSION, select_patch, apply_selected};
+pub use remap::EntityIdMap;
+pub use prefab::{extract_prefab, instantiate_into_snapshot, instantiate_prefab};
+pub use capture::CaptureOptions;
+pub use adapter::{ChangeTracking, WorldCapture, WorldRestore};
+pub use incremental::IncrementalCapture;
+pub use recorder::{ChangeRecorder, Recorded};
+pub use parallel::ParallelCapture;
+pub use registry::{ComponentRegistry, ComponentSchema};
+pub use layout::{NativeLayout, NativeTable, TableView};
+pub use world::{RestoreReport, SkippedArchetype, WorldSnapshotTarget};
+pub use packable::{Packable, PackField};
+pub use builder::{ArchetypeBuilder, ColumnBuilder};
+pub use backend::StorageBackend;
+pub use transfer::{receive_snapshot, send_snapshot, TransferFrame, DEFAULT_TRANSFER_CHUNK_SIZE, TransferManifest, TransferReceiver};
+pub use compose::{compose, compose_with, Composition, RemapStrategy};
+
+pub use replication::{ClientId, DeltaApplier, DeltaStream, ReplicationFeedback, ReplicationMessage};
+pub use upload::{UploadOptions, UploadQueue, UPLOAD_QUEUE_FILE};
+
+#[cfg(feature = "derive")]
+pub use tx2_pack_derive::Packable;
+
+#[cfg(feature = "broadcast")]
+pub use broadcast::{BroadcastFrame, BroadcastTransport, Publisher, Subscriber};
+
+#[cfg(feature = "quic")]
+pub use quic::{receive_snapshot_quic, send_snapshot_quic};
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::{SqliteStore, SqliteQuery};
+
+#[cfg(feature = "bevy")]
+pub use bevy::{BevyCapture, capture_world, restore_world};
 
 #[cfg(feature = "encryption")]
 pub use encryption::{EncryptionKey, encrypt_snapshot, decrypt_snapshot};
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub use uring::{UringIo, UringWrite};
+
+#[cfg(feature = "arena")]
+pub use arena::{ArenaArchetype, ArenaColumn, ArenaSnapshot};
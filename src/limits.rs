@@ -0,0 +1,251 @@
+use crate::compression::{decompress_limited, decompress_unbounded};
+use crate::error::{PackError, Result};
+use crate::format::{
+    ComponentArchetype, ComponentData, CompressionType, EntityMetadata, FieldArray, FieldValue, PackedSnapshot,
+    SnapshotHeader,
+};
+use crate::sectioned::TableOfContents;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tx2_link::EntityId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseLimits {
+    pub max_archetypes: u64,
+    pub max_entities: u64,
+    pub max_decompressed_bytes: u64,
+    pub max_string_length: usize,
+}
+
+impl ParseLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_archetypes: u64::MAX,
+            max_entities: u64::MAX,
+            max_decompressed_bytes: u64::MAX,
+            max_string_length: usize::MAX,
+        }
+    }
+
+    // Generous for real saves, small enough that a hostile file cannot push
+    // a server into swapping before it is rejected.
+    pub fn hardened() -> Self {
+        Self {
+            max_archetypes: 4096,
+            max_entities: 16 * 1024 * 1024,
+            max_decompressed_bytes: 1024 * 1024 * 1024,
+            max_string_length: 64 * 1024,
+        }
+    }
+
+    pub fn with_max_archetypes(mut self, count: u64) -> Self {
+        self.max_archetypes = count;
+        self
+    }
+
+    pub fn with_max_entities(mut self, count: u64) -> Self {
+        self.max_entities = count;
+        self
+    }
+
+    pub fn with_max_decompressed_bytes(mut self, bytes: u64) -> Self {
+        self.max_decompressed_bytes = bytes;
+        self
+    }
+
+    pub fn with_max_string_length(mut self, bytes: usize) -> Self {
+        self.max_string_length = bytes;
+        self
+    }
+
+    pub(crate) fn budget(&self) -> DecompressionBudget {
+        DecompressionBudget {
+            unlimited: self.max_decompressed_bytes == u64::MAX,
+            max: self.max_decompressed_bytes,
+            remaining: AtomicU64::new(self.max_decompressed_bytes),
+        }
+    }
+
+    pub(crate) fn check_header(&self, header: &SnapshotHeader) -> Result<()> {
+        self.check_counts(header.archetype_count, header.entity_count)
+    }
+
+    pub(crate) fn check_toc(&self, toc: &TableOfContents) -> Result<()> {
+        check("archetype count", toc.archetypes.len() as u64, self.max_archetypes)?;
+
+        let mut entities = 0u64;
+        for entry in &toc.archetypes {
+            self.check_string(&entry.component_id)?;
+            entities = entities.saturating_add(entry.entity_count);
+            check("entity count", entities, self.max_entities)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn check_snapshot(&self, snapshot: &PackedSnapshot) -> Result<()> {
+        let mut entities = 0u64;
+        for archetype in &snapshot.archetypes {
+            self.check_archetype(archetype)?;
+            entities += archetype.entity_ids.len() as u64;
+        }
+
+        self.check_counts(snapshot.archetypes.len() as u64, entities)?;
+        self.check_entity_metadata(&snapshot.entity_metadata)
+    }
+
+    pub(crate) fn check_counts(&self, archetypes: u64, entities: u64) -> Result<()> {
+        check("archetype count", archetypes, self.max_archetypes)?;
+        check("entity count", entities, self.max_entities)
+    }
+
+    pub(crate) fn check_archetype(&self, archetype: &ComponentArchetype) -> Result<()> {
+        check("entity count", archetype.entity_ids.len() as u64, self.max_entities)?;
+        self.check_string(&archetype.component_id)?;
+
+        if let ComponentData::StructOfArrays(soa) = &archetype.data {
+            for name in &soa.field_names {
+                self.check_string(name)?;
+            }
+            for column in &soa.field_data {
+                if let FieldArray::String(values) = column {
+                    for value in values {
+                        self.check_string(value)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn check_entity_metadata(&self, metadata: &HashMap<EntityId, EntityMetadata>) -> Result<()> {
+        check("entity count", metadata.len() as u64, self.max_entities)?;
+
+        for entry in metadata.values() {
+            for tag in &entry.tags {
+                self.check_string(tag)?;
+            }
+            for (key, value) in &entry.annotations {
+                self.check_string(key)?;
+                if let FieldValue::String(value) = value {
+                    self.check_string(value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn check_string(&self, value: &str) -> Result<()> {
+        check("string length", value.len() as u64, self.max_string_length as u64)
+    }
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+// Shared by every payload of one read, so a pack cannot get around the limit
+// by splitting a decompression bomb across many small chunks.
+pub(crate) struct DecompressionBudget {
+    unlimited: bool,
+    max: u64,
+    remaining: AtomicU64,
+}
+
+impl DecompressionBudget {
+    pub(crate) fn decompress(&self, data: &[u8], compression_type: CompressionType) -> Result<Vec<u8>> {
+        if self.unlimited {
+            return decompress_unbounded(data, compression_type);
+        }
+
+        let exceeded = || PackError::LimitExceeded {
+            limit: "decompressed bytes",
+            max: self.max,
+        };
+
+        let remaining = self.remaining.load(Ordering::Relaxed);
+        let decompressed = match decompress_limited(data, compression_type, remaining) {
+            Err(PackError::LimitExceeded { .. }) => return Err(exceeded()),
+            result => result?,
+        };
+        self.remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| left.checked_sub(decompressed.len() as u64))
+            .map_err(|_| exceeded())?;
+
+        Ok(decompressed)
+    }
+}
+
+fn check(limit: &'static str, actual: u64, max: u64) -> Result<()> {
+    if actual > max {
+        return Err(PackError::LimitExceeded { limit, max });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionCodec;
+    use crate::format::{FieldType, PackFormat, StructOfArraysData};
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+
+    fn snapshot(format: PackFormat, rows: u32, name: &str) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = format;
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Name".to_string(),
+            entity_ids: (0..rows).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["name".to_string()],
+                field_types: vec![FieldType::String],
+                field_data: vec![FieldArray::String((0..rows).map(|_| name.to_string()).collect())],
+            }),
+        });
+        snapshot.header.entity_count = rows as u64;
+        snapshot.header.archetype_count = 1;
+        snapshot
+    }
+
+    fn limit_of(result: Result<PackedSnapshot>) -> &'static str {
+        match result.map_err(PackError::into_root) {
+            Err(PackError::LimitExceeded { limit, .. }) => limit,
+            other => panic!("expected a limit error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_hardened_reader_rejects_oversized_input() {
+        let writer = SnapshotWriter::new().with_compression(CompressionCodec::zstd_default());
+        let hardened = SnapshotReader::new().with_limits(ParseLimits::hardened());
+
+        for format in [PackFormat::Custom, PackFormat::Bincode] {
+            let bytes = writer.write_to_bytes(&snapshot(format, 100, "knight")).unwrap();
+            assert_eq!(hardened.read_from_bytes(&bytes).unwrap().archetypes[0].entity_ids.len(), 100);
+
+            let reader = SnapshotReader::new().with_limits(ParseLimits::hardened().with_max_entities(10));
+            assert_eq!(limit_of(reader.read_from_bytes(&bytes)), "entity count");
+
+            let reader = SnapshotReader::new().with_limits(ParseLimits::hardened().with_max_string_length(4));
+            assert_eq!(limit_of(reader.read_from_bytes(&bytes)), "string length");
+
+            // A megabyte of one repeated string compresses to almost nothing.
+            let bomb = writer.write_to_bytes(&snapshot(format, 1, &"a".repeat(1 << 20))).unwrap();
+            assert!(bomb.len() < 4096);
+            let reader = SnapshotReader::new().with_limits(ParseLimits::hardened().with_max_decompressed_bytes(64 * 1024));
+            assert_eq!(limit_of(reader.read_from_bytes(&bomb)), "decompressed bytes");
+            assert!(SnapshotReader::new().read_from_bytes(&bomb).is_ok());
+        }
+
+        // Counts declared in the header are rejected before any payload is read.
+        let mut lying = snapshot(PackFormat::Custom, 1, "knight");
+        lying.header.archetype_count = u64::MAX;
+        let bytes = writer.write_to_bytes(&lying).unwrap();
+        assert_eq!(limit_of(hardened.read_from_bytes(&bytes)), "archetype count");
+    }
+}
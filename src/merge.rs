@@ -0,0 +1,486 @@
+use crate::delta::{empty_data, push_entry};
+use crate::diff::{blob_bytes, entries, values_equal, ComponentEntry};
+use crate::error::Result;
+use crate::format::{ComponentArchetype, ComponentData, EntityMetadata, FieldValue, PackedSnapshot};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeConflict {
+    Value {
+        entity_id: EntityId,
+        component_id: ComponentId,
+        field: String,
+        base: FieldValue,
+        ours: FieldValue,
+        theirs: FieldValue,
+    },
+    Component {
+        entity_id: EntityId,
+        component_id: ComponentId,
+        base: Option<ComponentEntry>,
+        ours: Option<ComponentEntry>,
+        theirs: Option<ComponentEntry>,
+    },
+    Blob {
+        component_id: ComponentId,
+        base: Vec<u8>,
+        ours: Vec<u8>,
+        theirs: Vec<u8>,
+    },
+    EntityMetadata {
+        entity_id: EntityId,
+        base: Option<EntityMetadata>,
+        ours: Option<EntityMetadata>,
+        theirs: Option<EntityMetadata>,
+    },
+    Resource {
+        name: String,
+        base: Option<Vec<u8>>,
+        ours: Option<Vec<u8>>,
+        theirs: Option<Vec<u8>>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Base,
+    Ours,
+    Theirs,
+}
+
+pub trait ConflictResolver {
+    fn resolve(&mut self, conflict: &MergeConflict) -> Resolution;
+}
+
+impl<F> ConflictResolver for F
+where
+    F: FnMut(&MergeConflict) -> Resolution,
+{
+    fn resolve(&mut self, conflict: &MergeConflict) -> Resolution {
+        self(conflict)
+    }
+}
+
+impl ConflictResolver for Resolution {
+    fn resolve(&mut self, _conflict: &MergeConflict) -> Resolution {
+        *self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeResult {
+    pub snapshot: PackedSnapshot,
+    pub conflicts: Vec<(MergeConflict, Resolution)>,
+}
+
+impl MergeResult {
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+
+pub fn merge<R: ConflictResolver>(
+    base: &PackedSnapshot,
+    ours: &PackedSnapshot,
+    theirs: &PackedSnapshot,
+    resolver: &mut R,
+) -> Result<MergeResult> {
+    let mut merger = Merger {
+        resolver,
+        conflicts: Vec::new(),
+    };
+
+    let mut snapshot = PackedSnapshot::new();
+    snapshot.header = ours.header.clone();
+
+    for component_id in component_order(ours, theirs, base) {
+        let base_entries = component_entries(base, &component_id);
+        let ours_entries = component_entries(ours, &component_id);
+        let theirs_entries = component_entries(theirs, &component_id);
+
+        let entity_ids: BTreeSet<EntityId> = base_entries
+            .keys()
+            .chain(ours_entries.keys())
+            .chain(theirs_entries.keys())
+            .copied()
+            .collect();
+
+        let mut archetype = ComponentArchetype {
+            component_id: component_id.clone(),
+            entity_ids: Vec::new(),
+            data: ComponentData::Blob(Vec::new()),
+        };
+
+        for entity_id in entity_ids {
+            let merged = merger.merge_entry(
+                entity_id,
+                &component_id,
+                base_entries.get(&entity_id),
+                ours_entries.get(&entity_id),
+                theirs_entries.get(&entity_id),
+            );
+
+            if let Some(entry) = merged {
+                if archetype.entity_ids.is_empty() {
+                    archetype.data = empty_data(&entry);
+                }
+                push_entry(&mut archetype, &entry)?;
+            }
+        }
+
+        let blob = merger.merge_blob(
+            &component_id,
+            archetype_blob(base, &component_id),
+            archetype_blob(ours, &component_id),
+            archetype_blob(theirs, &component_id),
+        );
+        if let ComponentData::Blob(data) = &mut archetype.data {
+            *data = blob;
+        }
+
+        if !archetype.is_empty() {
+            snapshot.archetypes.push(archetype);
+        }
+    }
+
+    let entity_ids: BTreeSet<EntityId> = base
+        .entity_metadata
+        .keys()
+        .chain(ours.entity_metadata.keys())
+        .chain(theirs.entity_metadata.keys())
+        .copied()
+        .collect();
+
+    for entity_id in entity_ids {
+        let merged = merger.merge_metadata(
+            entity_id,
+            base.entity_metadata.get(&entity_id),
+            ours.entity_metadata.get(&entity_id),
+            theirs.entity_metadata.get(&entity_id),
+        );
+
+        if let Some(metadata) = merged {
+            snapshot.entity_metadata.insert(entity_id, metadata);
+        }
+    }
+
+    let names: BTreeSet<&String> = base
+        .resources
+        .keys()
+        .chain(ours.resources.keys())
+        .chain(theirs.resources.keys())
+        .collect();
+
+    for name in names {
+        let merged = merger.merge_resource(
+            name,
+            base.resources.get(name),
+            ours.resources.get(name),
+            theirs.resources.get(name),
+        );
+
+        if let Some(data) = merged {
+            snapshot.resources.insert(name.clone(), data);
+        }
+    }
+
+    snapshot.rebuild_entity_index();
+    snapshot.header.entity_count = snapshot.entity_index.len() as u64;
+    snapshot.header.component_count = snapshot.archetypes.len() as u64;
+    snapshot.header.archetype_count = snapshot.archetypes.len() as u64;
+
+    Ok(MergeResult {
+        snapshot,
+        conflicts: merger.conflicts,
+    })
+}
+
+struct Merger<'a, R> {
+    resolver: &'a mut R,
+    conflicts: Vec<(MergeConflict, Resolution)>,
+}
+
+impl<R: ConflictResolver> Merger<'_, R> {
+    fn resolve(&mut self, conflict: MergeConflict) -> Resolution {
+        let resolution = self.resolver.resolve(&conflict);
+        self.conflicts.push((conflict, resolution));
+        resolution
+    }
+
+    fn merge_entry(
+        &mut self,
+        entity_id: EntityId,
+        component_id: &ComponentId,
+        base: Option<&ComponentEntry>,
+        ours: Option<&ComponentEntry>,
+        theirs: Option<&ComponentEntry>,
+    ) -> Option<ComponentEntry> {
+        if entries_equal(ours, theirs) || entries_equal(base, theirs) {
+            return ours.cloned();
+        }
+        if entries_equal(base, ours) {
+            return theirs.cloned();
+        }
+
+        if let (Some(b), Some(o), Some(t)) = (base, ours, theirs) {
+            if let (Some(bv), Some(ov), Some(tv)) = (&b.values, &o.values, &t.values) {
+                if same_fields(bv, ov) && same_fields(bv, tv) {
+                    let mut values = Vec::with_capacity(bv.len());
+                    for (((field, b), (_, o)), (_, t)) in bv.iter().zip(ov).zip(tv) {
+                        let value = if values_equal(o, t) || values_equal(b, t) {
+                            o.clone()
+                        } else if values_equal(b, o) {
+                            t.clone()
+                        } else {
+                            let resolution = self.resolve(MergeConflict::Value {
+                                entity_id,
+                                component_id: component_id.clone(),
+                                field: field.clone(),
+                                base: b.clone(),
+                                ours: o.clone(),
+                                theirs: t.clone(),
+                            });
+                            pick(resolution, b, o, t).clone()
+                        };
+                        values.push((field.clone(), value));
+                    }
+
+                    return Some(ComponentEntry {
+                        values: Some(values),
+                        ..o.clone()
+                    });
+                }
+            }
+        }
+
+        let resolution = self.resolve(MergeConflict::Component {
+            entity_id,
+            component_id: component_id.clone(),
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        });
+        pick(resolution, base, ours, theirs).cloned()
+    }
+
+    fn merge_blob(&mut self, component_id: &ComponentId, base: &[u8], ours: &[u8], theirs: &[u8]) -> Vec<u8> {
+        if ours == theirs || base == theirs {
+            return ours.to_vec();
+        }
+        if base == ours {
+            return theirs.to_vec();
+        }
+
+        let resolution = self.resolve(MergeConflict::Blob {
+            component_id: component_id.clone(),
+            base: base.to_vec(),
+            ours: ours.to_vec(),
+            theirs: theirs.to_vec(),
+        });
+        pick(resolution, base, ours, theirs).to_vec()
+    }
+
+    fn merge_metadata(
+        &mut self,
+        entity_id: EntityId,
+        base: Option<&EntityMetadata>,
+        ours: Option<&EntityMetadata>,
+        theirs: Option<&EntityMetadata>,
+    ) -> Option<EntityMetadata> {
+        if ours == theirs || base == theirs {
+            return ours.cloned();
+        }
+        if base == ours {
+            return theirs.cloned();
+        }
+
+        let resolution = self.resolve(MergeConflict::EntityMetadata {
+            entity_id,
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        });
+        pick(resolution, base, ours, theirs).cloned()
+    }
+
+    fn merge_resource(
+        &mut self,
+        name: &str,
+        base: Option<&Vec<u8>>,
+        ours: Option<&Vec<u8>>,
+        theirs: Option<&Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        if ours == theirs || base == theirs {
+            return ours.cloned();
+        }
+        if base == ours {
+            return theirs.cloned();
+        }
+
+        let resolution = self.resolve(MergeConflict::Resource {
+            name: name.to_string(),
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        });
+        pick(resolution, base, ours, theirs).cloned()
+    }
+}
+
+fn pick<T>(resolution: Resolution, base: T, ours: T, theirs: T) -> T {
+    match resolution {
+        Resolution::Base => base,
+        Resolution::Ours => ours,
+        Resolution::Theirs => theirs,
+    }
+}
+
+fn entries_equal(a: Option<&ComponentEntry>, b: Option<&ComponentEntry>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => match (&a.values, &b.values) {
+            (Some(x), Some(y)) => {
+                same_fields(x, y) && x.iter().zip(y).all(|((_, x), (_, y))| values_equal(x, y))
+            }
+            (None, None) => true,
+            _ => false,
+        },
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn same_fields(a: &[(String, FieldValue)], b: &[(String, FieldValue)]) -> bool {
+    a.len() == b.len()
+        && a
+            .iter()
+            .zip(b)
+            .all(|((x, xv), (y, yv))| x == y && xv.field_type() == yv.field_type())
+}
+
+fn component_order(ours: &PackedSnapshot, theirs: &PackedSnapshot, base: &PackedSnapshot) -> Vec<ComponentId> {
+    let mut seen = HashSet::new();
+    ours.archetypes
+        .iter()
+        .chain(&theirs.archetypes)
+        .chain(&base.archetypes)
+        .filter(|archetype| seen.insert(archetype.component_id.clone()))
+        .map(|archetype| archetype.component_id.clone())
+        .collect()
+}
+
+fn component_entries(snapshot: &PackedSnapshot, component_id: &ComponentId) -> BTreeMap<EntityId, ComponentEntry> {
+    snapshot
+        .archetypes
+        .iter()
+        .filter(|archetype| archetype.component_id == *component_id)
+        .flat_map(entries)
+        .map(|entry| (entry.entity_id, entry))
+        .collect()
+}
+
+fn archetype_blob<'a>(snapshot: &'a PackedSnapshot, component_id: &ComponentId) -> &'a [u8] {
+    snapshot
+        .archetypes
+        .iter()
+        .find(|archetype| archetype.component_id == *component_id)
+        .map(blob_bytes)
+        .unwrap_or(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{FieldArray, FieldType, StructOfArraysData};
+
+    fn snapshot(rows: &[(EntityId, f32, f32)]) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: rows.iter().map(|(id, _, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string(), "y".to_string()],
+                field_types: vec![FieldType::F32, FieldType::F32],
+                field_data: vec![
+                    FieldArray::F32(rows.iter().map(|(_, x, _)| *x).collect()),
+                    FieldArray::F32(rows.iter().map(|(_, _, y)| *y).collect()),
+                ],
+            }),
+        });
+        snapshot.rebuild_entity_index();
+        snapshot
+    }
+
+    fn position(snapshot: &PackedSnapshot, entity_id: EntityId) -> Option<Vec<(String, FieldValue)>> {
+        let archetype = &snapshot.archetypes[0];
+        archetype.row_of(entity_id).and_then(|row| archetype.row_values(row))
+    }
+
+    #[test]
+    fn test_merge_non_conflicting() {
+        let base = snapshot(&[(1, 0.0, 0.0), (2, 0.0, 0.0), (3, 0.0, 0.0)]);
+        let ours = snapshot(&[(1, 5.0, 0.0), (2, 0.0, 0.0), (3, 0.0, 0.0), (4, 1.0, 1.0)]);
+        let theirs = snapshot(&[(1, 0.0, 7.0), (2, 0.0, 0.0)]);
+
+        let result = merge(&base, &ours, &theirs, &mut Resolution::Base).unwrap();
+
+        assert!(!result.has_conflicts());
+        assert_eq!(
+            position(&result.snapshot, 1),
+            Some(vec![("x".to_string(), FieldValue::F32(5.0)), ("y".to_string(), FieldValue::F32(7.0))])
+        );
+        assert!(result.snapshot.contains_entity(2));
+        assert!(!result.snapshot.contains_entity(3));
+        assert!(result.snapshot.contains_entity(4));
+    }
+
+    #[test]
+    fn test_merge_conflicts_use_resolver() {
+        let base = snapshot(&[(1, 0.0, 0.0), (2, 0.0, 0.0)]);
+        let ours = snapshot(&[(1, 1.0, 0.0), (2, 3.0, 0.0)]);
+        let theirs = snapshot(&[(1, 2.0, 0.0)]);
+
+        let mut seen = Vec::new();
+        let result = merge(&base, &ours, &theirs, &mut |conflict: &MergeConflict| {
+            seen.push(conflict.clone());
+            Resolution::Theirs
+        })
+        .unwrap();
+
+        assert_eq!(seen.len(), 2);
+        assert!(matches!(seen[0], MergeConflict::Value { entity_id: 1, .. }));
+        assert!(matches!(seen[1], MergeConflict::Component { entity_id: 2, .. }));
+        assert_eq!(result.conflicts.len(), 2);
+
+        assert_eq!(position(&result.snapshot, 1).unwrap()[0].1, FieldValue::F32(2.0));
+        assert!(!result.snapshot.contains_entity(2));
+    }
+
+    #[test]
+    fn test_merge_resources() {
+        let with = |resources: &[(&str, &[u8])]| {
+            let mut snapshot = snapshot(&[(1, 0.0, 0.0)]);
+            snapshot.resources = resources.iter().map(|(name, data)| (name.to_string(), data.to_vec())).collect();
+            snapshot
+        };
+        let base = with(&[("clock", &[1]), ("rng", &[7]), ("score", &[0])]);
+        let ours = with(&[("clock", &[1]), ("rng", &[8]), ("score", &[0]), ("weather", &[3])]);
+        let theirs = with(&[("clock", &[2]), ("rng", &[9])]);
+
+        let result = merge(&base, &ours, &theirs, &mut Resolution::Ours).unwrap();
+
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(
+            result.conflicts[0].0,
+            MergeConflict::Resource { name: "rng".to_string(), base: Some(vec![7]), ours: Some(vec![8]), theirs: Some(vec![9]) }
+        );
+        assert_eq!(
+            result.snapshot.resources,
+            BTreeMap::from([
+                ("clock".to_string(), vec![2]),
+                ("rng".to_string(), vec![8]),
+                ("weather".to_string(), vec![3]),
+            ])
+        );
+    }
+}
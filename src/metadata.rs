@@ -1,5 +1,6 @@
+use crate::error::{PackError, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnapshotMetadata {
@@ -11,6 +12,14 @@ pub struct SnapshotMetadata {
     pub schema_version: u32,
     pub custom_fields: HashMap<String, String>,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub provenance: Provenance,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub delta_base: Option<String>,
+    #[serde(default)]
+    pub checksum: Option<String>,
 }
 
 impl SnapshotMetadata {
@@ -24,6 +33,10 @@ impl SnapshotMetadata {
             schema_version: 1,
             custom_fields: HashMap::new(),
             tags: Vec::new(),
+            provenance: Provenance::default(),
+            labels: BTreeMap::new(),
+            delta_base: None,
+            checksum: None,
         }
     }
 
@@ -46,4 +59,201 @@ impl SnapshotMetadata {
         self.custom_fields.insert(key, value);
         self
     }
+
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    pub fn with_label(mut self, key: String, value: String) -> Self {
+        self.labels.insert(key, value);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub parent_id: Option<String>,
+    pub build: Option<String>,
+    pub commit: Option<String>,
+    pub hostname: Option<String>,
+    pub seed: Option<u64>,
+}
+
+impl Provenance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_parent(mut self, parent_id: String) -> Self {
+        self.parent_id = Some(parent_id);
+        self
+    }
+
+    pub fn with_build(mut self, build: String) -> Self {
+        self.build = Some(build);
+        self
+    }
+
+    pub fn with_commit(mut self, commit: String) -> Self {
+        self.commit = Some(commit);
+        self
+    }
+
+    pub fn with_hostname(mut self, hostname: String) -> Self {
+        self.hostname = Some(hostname);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn current_hostname() -> Option<String> {
+        std::env::var("HOSTNAME")
+            .or_else(|_| std::env::var("COMPUTERNAME"))
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataValueType {
+    String,
+    Integer,
+    Float,
+    Bool,
+}
+
+impl MetadataValueType {
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            MetadataValueType::String => true,
+            MetadataValueType::Integer => value.parse::<i64>().is_ok(),
+            MetadataValueType::Float => value.parse::<f64>().is_ok(),
+            MetadataValueType::Bool => value.parse::<bool>().is_ok(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MetadataSchema {
+    fields: HashMap<String, (MetadataValueType, bool)>,
+    allowed_tags: Option<HashSet<String>>,
+    required_tags: Vec<String>,
+    require_name: bool,
+}
+
+impl MetadataSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_required_field(mut self, key: &str, value_type: MetadataValueType) -> Self {
+        self.fields.insert(key.to_string(), (value_type, true));
+        self
+    }
+
+    pub fn with_optional_field(mut self, key: &str, value_type: MetadataValueType) -> Self {
+        self.fields.insert(key.to_string(), (value_type, false));
+        self
+    }
+
+    pub fn with_allowed_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_tags
+            .get_or_insert_with(HashSet::new)
+            .extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_required_tag(mut self, tag: &str) -> Self {
+        self.required_tags.push(tag.to_string());
+        self
+    }
+
+    pub fn with_required_name(mut self) -> Self {
+        self.require_name = true;
+        self
+    }
+
+    pub fn validate(&self, metadata: &SnapshotMetadata) -> Result<()> {
+        let mut violations = Vec::new();
+
+        if self.require_name && metadata.name.as_deref().is_none_or(str::is_empty) {
+            violations.push("missing name".to_string());
+        }
+
+        let mut keys: Vec<_> = self.fields.keys().collect();
+        keys.sort();
+
+        for key in keys {
+            let (value_type, required) = &self.fields[key];
+            match metadata.custom_fields.get(key) {
+                Some(value) if !value_type.accepts(value) => violations.push(
+                    format!("field '{}' value '{}' is not a valid {:?}", key, value, value_type)
+                ),
+                None if *required => violations.push(format!("missing required field '{}'", key)),
+                _ => {}
+            }
+        }
+
+        for tag in &self.required_tags {
+            if !metadata.tags.contains(tag) {
+                violations.push(format!("missing required tag '{}'", tag));
+            }
+        }
+
+        if let Some(allowed) = &self.allowed_tags {
+            for tag in &metadata.tags {
+                if !allowed.contains(tag) {
+                    violations.push(format!("tag '{}' is not in the vocabulary", tag));
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(PackError::InvalidMetadata(
+                format!("{}: {}", metadata.id, violations.join("; "))
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_schema() {
+        let schema = MetadataSchema::new()
+            .with_required_field("level", MetadataValueType::Integer)
+            .with_optional_field("difficulty_scale", MetadataValueType::Float)
+            .with_allowed_tags(["boss", "milestone"])
+            .with_required_name();
+
+        let valid = SnapshotMetadata::new("ok".to_string())
+            .with_name("Before boss".to_string())
+            .with_tag("boss".to_string())
+            .with_custom_field("level".to_string(), "5".to_string());
+        assert!(schema.validate(&valid).is_ok());
+
+        let invalid = SnapshotMetadata::new("bad".to_string())
+            .with_tag("misc".to_string())
+            .with_custom_field("difficulty_scale".to_string(), "hard".to_string());
+
+        let message = schema.validate(&invalid).unwrap_err().to_string();
+        assert!(message.contains("missing name"));
+        assert!(message.contains("missing required field 'level'"));
+        assert!(message.contains("'difficulty_scale'"));
+        assert!(message.contains("tag 'misc'"));
+    }
 }
@@ -0,0 +1,117 @@
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, StructOfArraysData};
+use tx2_link::{ComponentId, EntityId};
+
+pub trait PackField: Clone {
+    const FIELD_TYPE: FieldType;
+
+    fn column(values: Vec<Self>) -> FieldArray;
+
+    fn values(column: &FieldArray) -> Option<&[Self]>;
+
+    fn values_mut(column: &mut FieldArray) -> Option<&mut Vec<Self>>;
+}
+
+macro_rules! pack_field {
+    ($($variant:ident => $ty:ty),* $(,)?) => {
+        $(
+            impl PackField for $ty {
+                const FIELD_TYPE: FieldType = FieldType::$variant;
+
+                fn column(values: Vec<Self>) -> FieldArray {
+                    FieldArray::$variant(values.into())
+                }
+
+                fn values(column: &FieldArray) -> Option<&[Self]> {
+                    match column {
+                        FieldArray::$variant(values) => Some(values),
+                        _ => None,
+                    }
+                }
+
+                fn values_mut(column: &mut FieldArray) -> Option<&mut Vec<Self>> {
+                    match column {
+                        FieldArray::$variant(values) => Some(values),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+pack_field! {
+    Bool => bool,
+    I8 => i8,
+    I16 => i16,
+    I32 => i32,
+    I64 => i64,
+    U8 => u8,
+    U16 => u16,
+    U32 => u32,
+    U64 => u64,
+    F32 => f32,
+    F64 => f64,
+    String => String,
+    Bytes => Vec<u8>,
+}
+
+pub trait Packable: Sized {
+    const COMPONENT_ID: &'static str;
+
+    fn schema() -> Vec<(&'static str, FieldType)>;
+
+    fn pack_columns(rows: &[&Self]) -> Vec<FieldArray>;
+
+    fn unpack_columns(columns: &[&FieldArray], len: usize) -> Option<Vec<Self>>;
+
+    fn pack<'a, I>(rows: I) -> ComponentArchetype
+    where
+        Self: 'a,
+        I: IntoIterator<Item = (EntityId, &'a Self)>,
+    {
+        let (entity_ids, rows): (Vec<EntityId>, Vec<&Self>) = rows.into_iter().unzip();
+        let schema = Self::schema();
+
+        ComponentArchetype {
+            component_id: ComponentId::from(Self::COMPONENT_ID),
+            entity_ids,
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: schema.iter().map(|(name, _)| name.to_string()).collect(),
+                field_types: schema.iter().map(|(_, field_type)| *field_type).collect(),
+                field_data: Self::pack_columns(&rows),
+            }),
+        }
+    }
+
+    fn unpack(archetype: &ComponentArchetype) -> Result<Vec<(EntityId, Self)>> {
+        let soa = match &archetype.data {
+            ComponentData::StructOfArrays(soa) => soa,
+            ComponentData::Blob(_) => {
+                return Err(PackError::InvalidFormat(format!(
+                    "Component {} is stored as a blob", archetype.component_id
+                )));
+            }
+        };
+
+        let mut columns = Vec::new();
+        for (name, field_type) in Self::schema() {
+            let index = soa.field_names.iter().position(|n| n == name).ok_or_else(|| {
+                PackError::InvalidFormat(format!("Component {} has no field '{}'", archetype.component_id, name))
+            })?;
+            if soa.field_types[index] != field_type {
+                return Err(PackError::InvalidFormat(format!(
+                    "Field {}.{} is {:?}, expected {:?}",
+                    archetype.component_id, name, soa.field_types[index], field_type
+                )));
+            }
+            columns.push(&soa.field_data[index]);
+        }
+
+        let values = Self::unpack_columns(&columns, archetype.entity_ids.len()).ok_or_else(|| {
+            PackError::InvalidFormat(format!("Component {} has ragged columns", archetype.component_id))
+        })?;
+
+        Ok(archetype.entity_ids.iter().copied().zip(values).collect())
+    }
+}
@@ -0,0 +1,189 @@
+use crate::adapter::{finish_capture, WorldCapture};
+use crate::capture::CaptureOptions;
+use crate::error::{Result, ResultExt};
+use crate::format::{ComponentArchetype, PackedSnapshot, SnapshotHeader};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tx2_link::ComponentId;
+
+type Slot = (ComponentId, Option<ComponentArchetype>);
+
+// Captures a world's archetypes side by side and double-buffers the result.
+// Only capture needs the world frozen; the published snapshot is shared, so
+// it can be encoded and written while the world runs on. Once nobody holds
+// it any more, its column allocations are reused by the capture after next.
+pub struct ParallelCapture {
+    options: CaptureOptions,
+    front: Option<Arc<PackedSnapshot>>,
+    back: Option<PackedSnapshot>,
+    last_pause: Duration,
+}
+
+impl ParallelCapture {
+    pub fn new() -> Self {
+        Self {
+            options: CaptureOptions::default(),
+            front: None,
+            back: None,
+            last_pause: Duration::ZERO,
+        }
+    }
+
+    pub fn with_options(mut self, options: CaptureOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // The last published capture.
+    pub fn front(&self) -> Option<Arc<PackedSnapshot>> {
+        self.front.clone()
+    }
+
+    // How long the last capture had the world borrowed.
+    pub fn last_pause(&self) -> Duration {
+        self.last_pause
+    }
+
+    pub fn capture<W: WorldCapture + Sync + ?Sized>(&mut self, world: &W) -> Result<Arc<PackedSnapshot>> {
+        let started = Instant::now();
+        let mut snapshot = self.back.take().unwrap_or_default();
+
+        let mut buffers: HashMap<ComponentId, ComponentArchetype> = snapshot
+            .archetypes
+            .drain(..)
+            .map(|archetype| (archetype.component_id.clone(), archetype))
+            .collect();
+        let mut slots: Vec<Slot> = world
+            .component_ids()?
+            .into_iter()
+            .filter(|component_id| self.options.component_matches(component_id))
+            .map(|component_id| {
+                let buffer = buffers.remove(&component_id);
+                (component_id, buffer)
+            })
+            .collect();
+        capture_slots(world, &mut slots)?;
+
+        snapshot.header = SnapshotHeader::new();
+        snapshot.archetypes = slots.into_iter().filter_map(|(_, archetype)| archetype).collect();
+        snapshot.entity_metadata = world.capture_entity_metadata()?;
+        snapshot.resources = world.capture_resources()?;
+        snapshot.layout = None;
+        finish_capture(world, &mut snapshot, &self.options)?;
+        self.last_pause = started.elapsed();
+
+        let published = Arc::new(snapshot);
+        if let Some(previous) = self.front.replace(published.clone()) {
+            self.back = Arc::try_unwrap(previous).ok();
+        }
+        Ok(published)
+    }
+}
+
+impl Default for ParallelCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "rayon")]
+fn capture_slots<W: WorldCapture + Sync + ?Sized>(world: &W, slots: &mut [Slot]) -> Result<()> {
+    use rayon::prelude::*;
+
+    slots.par_iter_mut().try_for_each(|(component_id, buffer)| {
+        world.capture_archetype_into(component_id, buffer).with_component(component_id)
+    })
+}
+
+#[cfg(not(feature = "rayon"))]
+fn capture_slots<W: WorldCapture + Sync + ?Sized>(world: &W, slots: &mut [Slot]) -> Result<()> {
+    slots.iter_mut().try_for_each(|(component_id, buffer)| {
+        world.capture_archetype_into(component_id, buffer).with_component(component_id)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentData, FieldArray, FieldType, StructOfArraysData};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct World {
+        columns: Vec<(ComponentId, Vec<u64>)>,
+        reused: AtomicUsize,
+    }
+
+    impl WorldCapture for World {
+        fn component_ids(&self) -> Result<Vec<ComponentId>> {
+            Ok(self.columns.iter().map(|(id, _)| id.clone()).collect())
+        }
+
+        fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+            let mut buffer = None;
+            self.capture_archetype_into(component_id, &mut buffer)?;
+            Ok(buffer)
+        }
+
+        fn capture_archetype_into(&self, component_id: &ComponentId, buffer: &mut Option<ComponentArchetype>) -> Result<()> {
+            let (_, values) = self.columns.iter().find(|(id, _)| id == component_id).unwrap();
+            if let Some(ComponentArchetype {
+                entity_ids,
+                data: ComponentData::StructOfArrays(soa),
+                ..
+            }) = buffer
+            {
+                if let FieldArray::U64(column) = &mut soa.field_data[0] {
+                    self.reused.fetch_add(1, Ordering::Relaxed);
+                    entity_ids.clear();
+                    entity_ids.extend(0..values.len() as u32);
+                    let column = column.make_mut();
+                    column.clear();
+                    column.extend_from_slice(values);
+                    return Ok(());
+                }
+            }
+
+            *buffer = Some(ComponentArchetype {
+                component_id: component_id.clone(),
+                entity_ids: (0..values.len() as u32).collect(),
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["value".to_string()],
+                    field_types: vec![FieldType::U64],
+                    field_data: vec![FieldArray::U64(values.clone().into())],
+                }),
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parallel_capture_matches_serial_and_reuses_released_buffers() {
+        let mut world = World {
+            columns: (0..16).map(|i| (format!("Component{}", i), (0..1000).map(|v| v * i).collect())).collect(),
+            reused: AtomicUsize::new(0),
+        };
+        let options = CaptureOptions::new().without_components(["Component3"]);
+        let mut capture = ParallelCapture::new().with_options(options.clone());
+
+        let first = capture.capture(&world).unwrap();
+        let serial = world.capture_with(&options).unwrap();
+        assert_eq!(first.content_checksum().unwrap(), serial.content_checksum().unwrap());
+        assert_eq!(first.archetypes.len(), 15);
+
+        // The first capture is still held, so the second one cannot reuse it.
+        world.columns[0].1[0] = 42;
+        let second = capture.capture(&world).unwrap();
+        assert_eq!(world.reused.load(Ordering::Relaxed), 0);
+        assert_eq!(first.archetypes[0].row_values(0).unwrap()[0].1, crate::FieldValue::U64(0));
+        assert_eq!(second.archetypes[0].row_values(0).unwrap()[0].1, crate::FieldValue::U64(42));
+
+        drop(first);
+        drop(second);
+        capture.capture(&world).unwrap();
+        capture.capture(&world).unwrap();
+        assert_eq!(world.reused.load(Ordering::Relaxed), 15);
+        let serial = world.capture_with(&options).unwrap();
+        assert_eq!(capture.front().unwrap().content_checksum().unwrap(), serial.content_checksum().unwrap());
+    }
+}
@@ -0,0 +1,439 @@
+use crate::compression::CompressionCodec;
+use crate::delta::{apply_diff, ResourceChange};
+use crate::diff::{DiffBody, DiffFilter, SnapshotDiff, SnapshotDiffV2_0};
+use crate::error::{PackError, Result};
+use crate::format::{PackFormat, PackedSnapshot, SnapshotHeader, PATCH_MAGIC_NUMBER};
+use crate::storage::{
+    deserialize_versioned, read_file, serialize_value, SnapshotReader, SnapshotWriter,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use tx2_link::{ComponentId, EntityId};
+
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionKey;
+
+pub const PATCH_EXTENSION: &str = "tx2patch";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "PatchLayout")]
+pub struct Patch {
+    pub forward: SnapshotDiff,
+    pub reverse: Option<SnapshotDiff>,
+}
+
+// Tombstones and resource changes trail both diffs, as in DeltaSnapshot.
+impl Serialize for Patch {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut patch = serializer.serialize_struct("Patch", 6)?;
+        patch.serialize_field("forward", &DiffBody(&self.forward))?;
+        patch.serialize_field("reverse", &self.reverse.as_ref().map(DiffBody))?;
+        patch.serialize_field("forward_tombstones", &self.forward.tombstones)?;
+        patch.serialize_field("reverse_tombstones", &self.reverse.as_ref().map(|reverse| &reverse.tombstones))?;
+        patch.serialize_field("forward_resource_changes", &self.forward.resource_changes)?;
+        patch.serialize_field(
+            "reverse_resource_changes",
+            &self.reverse.as_ref().map(|reverse| &reverse.resource_changes),
+        )?;
+        patch.end()
+    }
+}
+
+#[derive(Deserialize)]
+struct PatchLayout {
+    forward: SnapshotDiffV2_0,
+    #[serde(default)]
+    reverse: Option<SnapshotDiffV2_0>,
+    #[serde(default)]
+    forward_tombstones: Vec<EntityId>,
+    #[serde(default)]
+    reverse_tombstones: Option<Vec<EntityId>>,
+    #[serde(default)]
+    forward_resource_changes: Vec<ResourceChange>,
+    #[serde(default)]
+    reverse_resource_changes: Option<Vec<ResourceChange>>,
+}
+
+impl From<PatchLayout> for Patch {
+    fn from(patch: PatchLayout) -> Self {
+        let reverse_tombstones = patch.reverse_tombstones.unwrap_or_default();
+        let reverse_resource_changes = patch.reverse_resource_changes.unwrap_or_default();
+        let mut forward = patch.forward.with_tombstones(patch.forward_tombstones);
+        forward.resource_changes = patch.forward_resource_changes;
+
+        Self {
+            forward,
+            reverse: patch.reverse.map(|reverse| SnapshotDiff {
+                resource_changes: reverse_resource_changes,
+                ..reverse.with_tombstones(reverse_tombstones)
+            }),
+        }
+    }
+}
+
+// See PackedSnapshotV2_0. Patches did not change in 2.1.
+#[derive(Deserialize)]
+struct PatchV2_0 {
+    forward: SnapshotDiffV2_0,
+    reverse: Option<SnapshotDiffV2_0>,
+}
+
+impl From<PatchV2_0> for Patch {
+    fn from(patch: PatchV2_0) -> Self {
+        Self {
+            forward: patch.forward.into(),
+            reverse: patch.reverse.map(Into::into),
+        }
+    }
+}
+
+impl Patch {
+    pub fn new(forward: SnapshotDiff) -> Self {
+        Self {
+            forward,
+            reverse: None,
+        }
+    }
+
+    pub fn with_reverse(mut self) -> Self {
+        self.reverse = Some(self.forward.invert());
+        self
+    }
+
+    pub fn has_reverse(&self) -> bool {
+        self.reverse.is_some()
+    }
+
+    pub fn apply(&self, base: &PackedSnapshot) -> Result<PackedSnapshot> {
+        apply_diff(base, &self.forward)
+    }
+
+    pub fn rollback(&self, target: &PackedSnapshot) -> Result<PackedSnapshot> {
+        let reverse = self.reverse.as_ref().ok_or_else(|| {
+            PackError::Delta("Patch was written without a reverse patch".to_string())
+        })?;
+        apply_diff(target, reverse)
+    }
+}
+
+pub struct PatchWriter {
+    writer: SnapshotWriter,
+    format: PackFormat,
+    include_reverse: bool,
+}
+
+impl PatchWriter {
+    pub fn new() -> Self {
+        Self {
+            writer: SnapshotWriter::new(),
+            format: PackFormat::Bincode,
+            include_reverse: false,
+        }
+    }
+
+    pub fn with_reverse(mut self, include_reverse: bool) -> Self {
+        self.include_reverse = include_reverse;
+        self
+    }
+
+    pub fn with_compression(mut self, codec: CompressionCodec) -> Self {
+        self.writer = self.writer.with_compression(codec);
+        self
+    }
+
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, key: EncryptionKey) -> Self {
+        self.writer = self.writer.with_encryption(key);
+        self
+    }
+
+    pub fn with_format(mut self, format: PackFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, patch: &SnapshotDiff, path: P) -> Result<()> {
+        let bytes = self.write_to_bytes(patch)?;
+        self.writer.write_file(path.as_ref(), &bytes, self.writer.durability())
+    }
+
+    pub fn write_to_bytes(&self, patch: &SnapshotDiff) -> Result<Vec<u8>> {
+        let mut patch = Patch::new(patch.clone());
+        if self.include_reverse {
+            patch = patch.with_reverse();
+        }
+
+        self.write_patch_to_bytes(&patch)
+    }
+
+    pub fn write_patch_to_file<P: AsRef<Path>>(&self, patch: &Patch, path: P) -> Result<()> {
+        let bytes = self.write_patch_to_bytes(patch)?;
+        self.writer.write_file(path.as_ref(), &bytes, self.writer.durability())
+    }
+
+    pub fn write_patch_to_bytes(&self, patch: &Patch) -> Result<Vec<u8>> {
+        let mut header = SnapshotHeader::new();
+        header.magic = *PATCH_MAGIC_NUMBER;
+        header.format = self.format;
+        header.entity_count = patch.forward.changed_entities().len() as u64;
+        header.component_count = patch.forward.change_count() as u64;
+
+        let serialized = serialize_value(patch, self.format)?;
+        self.writer.encode(header, &serialized)
+    }
+}
+
+impl Default for PatchWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct PatchReader {
+    reader: SnapshotReader,
+}
+
+impl PatchReader {
+    pub fn new() -> Self {
+        Self {
+            reader: SnapshotReader::new(),
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, key: EncryptionKey) -> Self {
+        self.reader = self.reader.with_encryption(key);
+        self
+    }
+
+    pub fn read_from_file<P: AsRef<Path>>(&self, path: P) -> Result<SnapshotDiff> {
+        self.read_from_bytes(&read_file(path.as_ref())?)
+    }
+
+    pub fn read_from_bytes(&self, bytes: &[u8]) -> Result<SnapshotDiff> {
+        Ok(self.read_patch_from_bytes(bytes)?.forward)
+    }
+
+    pub fn read_patch_from_file<P: AsRef<Path>>(&self, path: P) -> Result<Patch> {
+        self.read_patch_from_bytes(&read_file(path.as_ref())?)
+    }
+
+    pub fn read_patch_from_bytes(&self, bytes: &[u8]) -> Result<Patch> {
+        let (header, decompressed) = self.reader.decode(bytes, PATCH_MAGIC_NUMBER)?;
+        deserialize_versioned::<_, PatchV2_0, PatchV2_0>(&decompressed, &header)
+    }
+}
+
+impl Default for PatchReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn select_patch(patch: &SnapshotDiff, filter: &DiffFilter) -> Result<SnapshotDiff> {
+    let selected = filter.apply(patch);
+    check_dependencies(patch, &selected)?;
+    Ok(selected)
+}
+
+pub fn apply_selected(base: &PackedSnapshot, patch: &SnapshotDiff, filter: &DiffFilter) -> Result<PackedSnapshot> {
+    apply_diff(base, &select_patch(patch, filter)?)
+}
+
+fn check_dependencies(patch: &SnapshotDiff, selected: &SnapshotDiff) -> Result<()> {
+    type Key<'a> = (EntityId, &'a ComponentId);
+
+    let selected_added: HashSet<Key> = selected.components_added.iter().map(|e| (e.entity_id, &e.component_id)).collect();
+    let selected_removed: HashSet<Key> = selected.components_removed.iter().map(|e| (e.entity_id, &e.component_id)).collect();
+    let selected_blobs: HashSet<&ComponentId> = selected.blob_changes.iter().map(|c| &c.component_id).collect();
+    let patch_added: HashSet<Key> = patch.components_added.iter().map(|e| (e.entity_id, &e.component_id)).collect();
+    let patch_removed: HashSet<Key> = patch.components_removed.iter().map(|e| (e.entity_id, &e.component_id)).collect();
+    let patch_blobs: HashSet<&ComponentId> = patch.blob_changes.iter().map(|c| &c.component_id).collect();
+
+    let unmet = |what: String, dependency: &str| {
+        Err(PackError::Delta(format!("{} depends on unselected {}", what, dependency)))
+    };
+
+    for change in &selected.value_changes {
+        let key = (change.entity_id, &change.component_id);
+        if patch_added.contains(&key) && !selected_added.contains(&key) {
+            return unmet(
+                format!("Change to {}.{} on entity {}", change.component_id, change.field, change.entity_id),
+                "component addition",
+            );
+        }
+    }
+
+    for entry in &selected.components_added {
+        let key = (entry.entity_id, &entry.component_id);
+        if patch_removed.contains(&key) && !selected_removed.contains(&key) {
+            return unmet(
+                format!("Addition of {} to entity {}", entry.component_id, entry.entity_id),
+                "component removal",
+            );
+        }
+    }
+
+    for entry in &selected.components_removed {
+        let key = (entry.entity_id, &entry.component_id);
+        if patch_added.contains(&key) && !selected_added.contains(&key) {
+            return unmet(
+                format!("Removal of {} from entity {}", entry.component_id, entry.entity_id),
+                "component re-addition",
+            );
+        }
+    }
+
+    let lifecycle: HashSet<EntityId> =
+        patch.entities_added.iter().chain(&patch.entities_removed).chain(&patch.tombstones).copied().collect();
+    let touched = selected.changed_entities();
+    for entry in patch.components_added.iter().chain(&patch.components_removed) {
+        let key = (entry.entity_id, &entry.component_id);
+        if lifecycle.contains(&entry.entity_id) && touched.contains(&entry.entity_id) && !selected_added.contains(&key) && !selected_removed.contains(&key) {
+            return unmet(
+                format!("Spawn or despawn of entity {}", entry.entity_id),
+                &format!("{} component", entry.component_id),
+            );
+        }
+    }
+
+    for entry in selected.components_added.iter().chain(&selected.components_removed) {
+        if entry.values.is_none() && patch_blobs.contains(&entry.component_id) && !selected_blobs.contains(&entry.component_id) {
+            return unmet(
+                format!("Blob component {} on entity {}", entry.component_id, entry.entity_id),
+                "blob data change",
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{ComponentEntry, ValueChange};
+    use crate::error::PackError;
+    use crate::format::FieldValue;
+    use tempfile::TempDir;
+
+    fn sample_patch() -> SnapshotDiff {
+        SnapshotDiff {
+            entities_added: vec![7],
+            components_added: vec![ComponentEntry {
+                entity_id: 7,
+                component_id: "Health".to_string(),
+                values: Some(vec![("hp".to_string(), FieldValue::U32(100))]),
+            }],
+            value_changes: vec![ValueChange {
+                entity_id: 1,
+                component_id: "Position".to_string(),
+                field: "x".to_string(),
+                old: FieldValue::F32(1.0),
+                new: FieldValue::F32(1.5),
+            }],
+            ..SnapshotDiff::default()
+        }
+    }
+
+    #[test]
+    fn test_patch_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(format!("update.{}", PATCH_EXTENSION));
+        let patch = sample_patch();
+
+        PatchWriter::new()
+            .with_compression(CompressionCodec::lz4_default())
+            .with_format(PackFormat::MessagePack)
+            .write_to_file(&patch, &path)
+            .unwrap();
+
+        let loaded = PatchReader::new().read_from_file(&path).unwrap();
+        assert_eq!(loaded, patch);
+        assert!(!PatchReader::new().read_patch_from_file(&path).unwrap().has_reverse());
+    }
+
+    #[test]
+    fn test_patch_with_reverse() {
+        use crate::diff::diff;
+        use crate::format::{ComponentArchetype, ComponentData};
+
+        let mut a = PackedSnapshot::new();
+        a.archetypes.push(ComponentArchetype {
+            component_id: "Tag".to_string(),
+            entity_ids: vec![1, 2],
+            data: ComponentData::Blob(vec![1]),
+        });
+        a.resources.insert("clock".to_string(), vec![1]);
+        let mut b = a.clone();
+        b.archetypes[0].entity_ids = vec![2, 3];
+        b.archetypes[0].data = ComponentData::Blob(vec![2]);
+        b.resources.insert("clock".to_string(), vec![2]);
+
+        let bytes = PatchWriter::new().with_reverse(true).write_to_bytes(&diff(&a, &b)).unwrap();
+        let patch = PatchReader::new().read_patch_from_bytes(&bytes).unwrap();
+
+        let forward = patch.apply(&a).unwrap();
+        assert_eq!(forward.content_checksum().unwrap(), b.content_checksum().unwrap());
+        let back = patch.rollback(&forward).unwrap();
+        assert_eq!(back.content_checksum().unwrap(), a.content_checksum().unwrap());
+
+        assert!(Patch::new(diff(&a, &b)).rollback(&b).is_err());
+    }
+
+    #[test]
+    fn test_apply_selected_components() {
+        use crate::diff::diff;
+        use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, StructOfArraysData};
+
+        fn archetype(component: &str, rows: &[(EntityId, u32)]) -> ComponentArchetype {
+            ComponentArchetype {
+                component_id: component.to_string(),
+                entity_ids: rows.iter().map(|(id, _)| *id).collect(),
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["value".to_string()],
+                    field_types: vec![FieldType::U32],
+                    field_data: vec![FieldArray::U32(rows.iter().map(|(_, v)| *v).collect())],
+                }),
+            }
+        }
+
+        let mut base = PackedSnapshot::new();
+        base.archetypes.push(archetype("Inventory", &[(1, 3)]));
+        base.archetypes.push(archetype("Physics", &[(1, 10)]));
+
+        let mut target = PackedSnapshot::new();
+        target.archetypes.push(archetype("Inventory", &[(1, 5), (2, 1)]));
+        target.archetypes.push(archetype("Physics", &[(1, 99), (2, 0)]));
+
+        let patch = diff(&base, &target);
+
+        let inventory_only = DiffFilter::new().with_components(["Inventory"]);
+        assert!(matches!(apply_selected(&base, &patch, &inventory_only), Err(PackError::Delta(_))));
+
+        let hotfix = apply_selected(&base, &patch, &inventory_only.with_entities([1])).unwrap();
+        assert_eq!(hotfix.archetypes[0].row_values(0).unwrap()[0].1, FieldValue::U32(5));
+        assert_eq!(hotfix.archetypes[1].row_values(0).unwrap()[0].1, FieldValue::U32(10));
+        assert!(!hotfix.contains_entity(2));
+
+        let spawn = apply_selected(&base, &patch, &DiffFilter::new().with_entities([2])).unwrap();
+        assert_eq!(spawn.locate(2).len(), 2);
+        assert_eq!(spawn.archetypes[1].row_values(0).unwrap()[0].1, FieldValue::U32(10));
+    }
+
+    #[test]
+    fn test_patch_rejects_corruption_and_snapshots() {
+        let mut bytes = PatchWriter::new().write_to_bytes(&sample_patch()).unwrap();
+        let last = bytes.len() - crate::format::HEADER_SIZE as usize - 1;
+        bytes[last] ^= 0xff;
+        assert!(matches!(PatchReader::new().read_from_bytes(&bytes).map_err(PackError::into_root), Err(PackError::ChecksumMismatch)));
+
+        let snapshot_bytes = SnapshotWriter::new()
+            .write_to_bytes(&crate::format::PackedSnapshot::new())
+            .unwrap();
+        assert!(PatchReader::new().read_from_bytes(&snapshot_bytes).is_err());
+    }
+}
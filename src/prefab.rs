@@ -0,0 +1,223 @@
+use crate::adapter::WorldRestore;
+use crate::capture::CaptureOptions;
+use crate::delta::push_entry;
+use crate::diff::ComponentEntry;
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, PackedSnapshot};
+use crate::registry::ComponentRegistry;
+use crate::remap::EntityIdMap;
+use std::collections::{BTreeSet, HashMap};
+use tx2_link::EntityId;
+
+// Lifts the roots, and every entity linked to them through registered entity
+// fields in either direction, into a pack of its own with ids renumbered from
+// 0. Children that point at their parent come along with it. World resources
+// and the native layout stay behind, as they describe the source world.
+pub fn extract_prefab(
+    snapshot: &PackedSnapshot,
+    roots: &[EntityId],
+    registry: &ComponentRegistry,
+) -> Result<PackedSnapshot> {
+    if let Some(missing) = roots.iter().find(|id| !snapshot.contains_entity(**id)) {
+        return Err(PackError::InvalidFormat(format!("Prefab root {} is not in the snapshot", missing)));
+    }
+
+    let links = entity_links(snapshot, registry);
+    let mut selected: BTreeSet<EntityId> = BTreeSet::new();
+    let mut pending: Vec<EntityId> = roots.to_vec();
+    while let Some(entity_id) = pending.pop() {
+        if selected.insert(entity_id) {
+            pending.extend(links.get(&entity_id).into_iter().flatten().filter(|id| !selected.contains(id)));
+        }
+    }
+
+    let mut prefab = snapshot.clone();
+    prefab.resources.clear();
+    prefab.layout = None;
+    CaptureOptions::new().with_predicate(move |id, _| selected.contains(&id)).apply(&mut prefab);
+
+    let normalized: EntityIdMap = prefab
+        .archetypes
+        .iter()
+        .flat_map(|a| a.entity_ids.iter().copied())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .zip(0..)
+        .collect();
+    prefab.remap_entities(&normalized, registry)?;
+
+    Ok(prefab)
+}
+
+// Spawns a prefab into a live world through its allocate_entity, rewriting
+// the prefab's internal references to the new ids.
+pub fn instantiate_prefab<W>(
+    world: &mut W,
+    prefab: &PackedSnapshot,
+    registry: &ComponentRegistry,
+) -> Result<EntityIdMap>
+where
+    W: WorldRestore + ?Sized,
+{
+    world.restore_remapped(prefab, registry)
+}
+
+// Adds a prefab's entities to a snapshot under ids after its highest one.
+pub fn instantiate_into_snapshot(
+    target: &mut PackedSnapshot,
+    prefab: &PackedSnapshot,
+    registry: &ComponentRegistry,
+) -> Result<EntityIdMap> {
+    let mut next = target
+        .archetypes
+        .iter()
+        .flat_map(|a| a.entity_ids.iter().copied())
+        .chain(target.entity_metadata.keys().copied())
+        .max()
+        .map_or(0, |max| max + 1);
+    let map = crate::adapter::allocate_ids(prefab, |_| {
+        let id = next;
+        next = next
+            .checked_add(1)
+            .ok_or_else(|| PackError::InvalidFormat("Snapshot has no entity ids left".to_string()))?;
+        Ok(id)
+    })?;
+
+    let mut instance = prefab.clone();
+    instance.remap_entities(&map, registry)?;
+
+    for archetype in instance.archetypes {
+        let index = target.archetypes.iter().position(|a| a.component_id == archetype.component_id);
+        let existing = match index {
+            Some(index) => &mut target.archetypes[index],
+            None => {
+                target.archetypes.push(archetype);
+                continue;
+            }
+        };
+
+        match (&existing.data, &archetype.data) {
+            (ComponentData::Blob(_), ComponentData::Blob(_)) => {
+                let mut components = existing.world_components().map_err(|_| opaque_blob(&archetype))?;
+                let added = archetype.world_components().map_err(|_| opaque_blob(&archetype))?;
+                if components.len() != existing.entity_ids.len() || added.len() != archetype.entity_ids.len() {
+                    return Err(opaque_blob(&archetype));
+                }
+                components.extend(added);
+                existing.data = ComponentData::Blob(bincode::serialize(&components)?);
+                existing.entity_ids.extend_from_slice(&archetype.entity_ids);
+            }
+            _ => {
+                for row in 0..archetype.entity_ids.len() {
+                    let entry = ComponentEntry {
+                        entity_id: archetype.entity_ids[row],
+                        component_id: archetype.component_id.clone(),
+                        values: archetype.row_values(row),
+                    };
+                    push_entry(existing, &entry)?;
+                }
+            }
+        }
+    }
+    target.entity_metadata.extend(instance.entity_metadata);
+
+    target.layout = None;
+    target.rebuild_entity_index();
+    target.header.entity_count += map.len() as u64;
+    target.header.component_count = target.archetypes.len() as u64;
+    target.header.archetype_count = target.archetypes.len() as u64;
+
+    Ok(map)
+}
+
+fn opaque_blob(archetype: &ComponentArchetype) -> PackError {
+    PackError::InvalidFormat(format!("Blob component {} cannot be combined with the target's", archetype.component_id))
+}
+
+// Both ends of every registered entity reference, as an undirected graph.
+fn entity_links(snapshot: &PackedSnapshot, registry: &ComponentRegistry) -> HashMap<EntityId, Vec<EntityId>> {
+    let mut links: HashMap<EntityId, Vec<EntityId>> = HashMap::new();
+    for archetype in &snapshot.archetypes {
+        let fields = registry.entity_fields(&archetype.component_id);
+        let soa = match &archetype.data {
+            ComponentData::StructOfArrays(soa) if !fields.is_empty() => soa,
+            _ => continue,
+        };
+
+        for (name, column) in soa.field_names.iter().zip(&soa.field_data) {
+            if !fields.contains(name) {
+                continue;
+            }
+            let targets: Vec<Option<EntityId>> = match column {
+                FieldArray::U32(ids) => ids.iter().map(|id| Some(*id)).collect(),
+                FieldArray::U64(ids) => ids.iter().map(|id| EntityId::try_from(*id).ok()).collect(),
+                _ => continue,
+            };
+            for (entity_id, target) in archetype.entity_ids.iter().zip(targets) {
+                if let Some(target) = target.filter(|target| target != entity_id) {
+                    links.entry(*entity_id).or_default().push(target);
+                    links.entry(target).or_default().push(*entity_id);
+                }
+            }
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{EntityMetadata, FieldType, FieldValue, StructOfArraysData};
+
+    fn column(component_id: &str, field: &str, rows: &[(EntityId, u32)]) -> ComponentArchetype {
+        ComponentArchetype {
+            component_id: component_id.to_string(),
+            entity_ids: rows.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec![field.to_string()],
+                field_types: vec![FieldType::U32],
+                field_data: vec![FieldArray::U32(rows.iter().map(|(_, v)| *v).collect::<Vec<_>>().into())],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_extracted_prefab_is_normalized_and_instantiates_next_to_existing_entities() {
+        // A turret (10) with two barrels (11, 12) pointing at it, aimed at a
+        // target (20) that has its own unrelated child (30).
+        let mut replay = PackedSnapshot::new();
+        replay.archetypes.push(column("ChildOf", "parent", &[(11, 10), (12, 10), (30, 20)]));
+        replay.archetypes.push(column("Aim", "target", &[(10, 20)]));
+        replay.archetypes.push(column("Ammo", "rounds", &[(10, 40), (11, 5), (12, 6), (20, 1), (50, 9)]));
+        replay.entity_metadata.insert(11, EntityMetadata::new());
+        replay.resources.insert("rng".to_string(), vec![1]);
+        replay.rebuild_entity_index();
+
+        let mut registry = ComponentRegistry::new();
+        registry.register_entity_field("ChildOf", "parent");
+        let prefab = extract_prefab(&replay, &[10], &registry).unwrap();
+        assert_eq!(prefab.header.entity_count, 3);
+        assert!(prefab.resources.is_empty());
+        assert_eq!(prefab.archetypes[0].entity_ids, vec![1, 2]);
+        assert_eq!(prefab.archetypes[0].row_values(1).unwrap()[0].1, FieldValue::U32(0));
+        // Aim is not registered, so its target stays outside the prefab.
+        assert_eq!(prefab.archetypes[1].row_values(0).unwrap()[0].1, FieldValue::U32(20));
+        assert!(prefab.entity_metadata.contains_key(&1));
+        assert!(extract_prefab(&replay, &[99], &registry).is_err());
+
+        let mut level = PackedSnapshot::new();
+        level.archetypes.push(column("Ammo", "rounds", &[(7, 3)]));
+        level.rebuild_entity_index();
+        level.header.entity_count = 1;
+        let map = instantiate_into_snapshot(&mut level, &prefab, &registry).unwrap();
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(0, 8), (1, 9), (2, 10)]);
+        assert_eq!(level.header.entity_count, 4);
+        assert_eq!(level.archetypes[0].entity_ids, vec![7, 8, 9, 10]);
+        let child_of = level.archetypes.iter().find(|a| a.component_id == "ChildOf").unwrap();
+        assert_eq!(child_of.row_values(0).unwrap()[0].1, FieldValue::U32(8));
+
+        let again = instantiate_into_snapshot(&mut level, &prefab, &registry).unwrap();
+        assert_eq!(again.map(0), 11);
+        assert_eq!(level.locate(11).len(), 2);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::error::{PackError, Result};
+use crate::format::{self, ComponentArchetype, ComponentData, PackedSnapshot};
+use crate::metadata;
+use prost::Message;
+use std::collections::HashMap;
+
+pub const PROTO_SCHEMA: &str = include_str!(concat!(env!("OUT_DIR"), "/tx2pack.proto"));
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Provenance {
+    #[prost(string, optional, tag = "1")]
+    pub parent_id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub build: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub commit: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub hostname: Option<String>,
+    #[prost(uint64, optional, tag = "5")]
+    pub seed: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SnapshotMetadata {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, optional, tag = "2")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub description: Option<String>,
+    #[prost(int64, tag = "4")]
+    pub created_at: i64,
+    #[prost(double, tag = "5")]
+    pub world_time: f64,
+    #[prost(uint32, tag = "6")]
+    pub schema_version: u32,
+    #[prost(map = "string, string", tag = "7")]
+    pub custom_fields: HashMap<String, String>,
+    #[prost(string, repeated, tag = "8")]
+    pub tags: Vec<String>,
+    #[prost(message, optional, tag = "9")]
+    pub provenance: Option<Provenance>,
+    #[prost(map = "string, string", tag = "10")]
+    pub labels: HashMap<String, String>,
+    #[prost(string, optional, tag = "11")]
+    pub delta_base: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub checksum: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum FieldKind {
+    Unspecified = 0,
+    Bool = 1,
+    I8 = 2,
+    I16 = 3,
+    I32 = 4,
+    I64 = 5,
+    U8 = 6,
+    U16 = 7,
+    U32 = 8,
+    U64 = 9,
+    F32 = 10,
+    F64 = 11,
+    String = 12,
+    Bytes = 13,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FieldDescriptor {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(enumeration = "FieldKind", tag = "2")]
+    pub kind: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Layout {
+    Unspecified = 0,
+    StructOfArrays = 1,
+    Blob = 2,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ArchetypeDescriptor {
+    #[prost(string, tag = "1")]
+    pub component_id: String,
+    #[prost(uint64, tag = "2")]
+    pub entity_count: u64,
+    #[prost(enumeration = "Layout", tag = "3")]
+    pub layout: i32,
+    #[prost(message, repeated, tag = "4")]
+    pub fields: Vec<FieldDescriptor>,
+    #[prost(uint64, tag = "5")]
+    pub blob_size: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SnapshotSummary {
+    #[prost(message, optional, tag = "1")]
+    pub metadata: Option<SnapshotMetadata>,
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+    #[prost(uint64, tag = "3")]
+    pub entity_count: u64,
+    #[prost(message, repeated, tag = "4")]
+    pub archetypes: Vec<ArchetypeDescriptor>,
+}
+
+impl SnapshotSummary {
+    pub fn new(metadata: &metadata::SnapshotMetadata, snapshot: &PackedSnapshot) -> Self {
+        Self {
+            metadata: Some(metadata.into()),
+            timestamp: snapshot.header.timestamp,
+            entity_count: snapshot.header.entity_count,
+            archetypes: snapshot.archetypes.iter().map(ArchetypeDescriptor::from).collect(),
+        }
+    }
+
+    pub fn decode_from(bytes: &[u8]) -> Result<Self> {
+        Self::decode(bytes).map_err(|e| PackError::Deserialization(e.to_string()))
+    }
+}
+
+pub fn encode_metadata(metadata: &metadata::SnapshotMetadata) -> Vec<u8> {
+    SnapshotMetadata::from(metadata).encode_to_vec()
+}
+
+pub fn decode_metadata(bytes: &[u8]) -> Result<metadata::SnapshotMetadata> {
+    SnapshotMetadata::decode(bytes)
+        .map(Into::into)
+        .map_err(|e| PackError::Deserialization(e.to_string()))
+}
+
+impl From<&metadata::Provenance> for Provenance {
+    fn from(provenance: &metadata::Provenance) -> Self {
+        Self {
+            parent_id: provenance.parent_id.clone(),
+            build: provenance.build.clone(),
+            commit: provenance.commit.clone(),
+            hostname: provenance.hostname.clone(),
+            seed: provenance.seed,
+        }
+    }
+}
+
+impl From<Provenance> for metadata::Provenance {
+    fn from(provenance: Provenance) -> Self {
+        Self {
+            parent_id: provenance.parent_id,
+            build: provenance.build,
+            commit: provenance.commit,
+            hostname: provenance.hostname,
+            seed: provenance.seed,
+        }
+    }
+}
+
+impl From<&metadata::SnapshotMetadata> for SnapshotMetadata {
+    fn from(metadata: &metadata::SnapshotMetadata) -> Self {
+        Self {
+            id: metadata.id.clone(),
+            name: metadata.name.clone(),
+            description: metadata.description.clone(),
+            created_at: metadata.created_at,
+            world_time: metadata.world_time,
+            schema_version: metadata.schema_version,
+            custom_fields: metadata.custom_fields.clone(),
+            tags: metadata.tags.clone(),
+            provenance: Some((&metadata.provenance).into()),
+            labels: metadata.labels.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            delta_base: metadata.delta_base.clone(),
+            checksum: metadata.checksum.clone(),
+        }
+    }
+}
+
+impl From<SnapshotMetadata> for metadata::SnapshotMetadata {
+    fn from(message: SnapshotMetadata) -> Self {
+        Self {
+            id: message.id,
+            name: message.name,
+            description: message.description,
+            created_at: message.created_at,
+            world_time: message.world_time,
+            schema_version: message.schema_version,
+            custom_fields: message.custom_fields,
+            tags: message.tags,
+            provenance: message.provenance.map(Into::into).unwrap_or_default(),
+            labels: message.labels.into_iter().collect(),
+            delta_base: message.delta_base,
+            checksum: message.checksum,
+        }
+    }
+}
+
+impl From<format::FieldType> for FieldKind {
+    fn from(field_type: format::FieldType) -> Self {
+        match field_type {
+            format::FieldType::Bool => FieldKind::Bool,
+            format::FieldType::I8 => FieldKind::I8,
+            format::FieldType::I16 => FieldKind::I16,
+            format::FieldType::I32 => FieldKind::I32,
+            format::FieldType::I64 => FieldKind::I64,
+            format::FieldType::U8 => FieldKind::U8,
+            format::FieldType::U16 => FieldKind::U16,
+            format::FieldType::U32 => FieldKind::U32,
+            format::FieldType::U64 => FieldKind::U64,
+            format::FieldType::F32 => FieldKind::F32,
+            format::FieldType::F64 => FieldKind::F64,
+            format::FieldType::String => FieldKind::String,
+            format::FieldType::Bytes => FieldKind::Bytes,
+        }
+    }
+}
+
+impl From<&ComponentArchetype> for ArchetypeDescriptor {
+    fn from(archetype: &ComponentArchetype) -> Self {
+        let (layout, fields, blob_size) = match &archetype.data {
+            ComponentData::StructOfArrays(soa) => (
+                Layout::StructOfArrays,
+                soa.field_names
+                    .iter()
+                    .zip(&soa.field_types)
+                    .map(|(name, field_type)| FieldDescriptor {
+                        name: name.clone(),
+                        kind: FieldKind::from(*field_type) as i32,
+                    })
+                    .collect(),
+                0,
+            ),
+            ComponentData::Blob(data) => (Layout::Blob, Vec::new(), data.len() as u64),
+        };
+
+        Self {
+            component_id: archetype.component_id.clone(),
+            entity_count: archetype.entity_ids.len() as u64,
+            layout: layout as i32,
+            fields,
+            blob_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{FieldArray, FieldType, StructOfArraysData};
+
+    #[test]
+    fn test_protobuf_metadata_and_summary() {
+        let metadata = metadata::SnapshotMetadata::new("level_3".to_string())
+            .with_name("Level 3".to_string())
+            .with_tag("milestone".to_string())
+            .with_label("region".to_string(), "eu".to_string())
+            .with_provenance(metadata::Provenance::new().with_seed(42));
+
+        let decoded = decode_metadata(&encode_metadata(&metadata)).unwrap();
+        assert_eq!(decoded.name.as_deref(), Some("Level 3"));
+        assert_eq!(decoded.labels, metadata.labels);
+        assert_eq!(decoded.provenance, metadata.provenance);
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: vec![1, 2],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string()],
+                field_types: vec![FieldType::F32],
+                field_data: vec![FieldArray::F32(vec![0.0, 1.0].into())],
+            }),
+        });
+
+        let bytes = SnapshotSummary::new(&metadata, &snapshot).encode_to_vec();
+        let summary = SnapshotSummary::decode_from(&bytes).unwrap();
+        assert_eq!(summary.metadata.unwrap().id, "level_3");
+        assert_eq!(summary.archetypes[0].layout(), Layout::StructOfArrays);
+        assert_eq!(summary.archetypes[0].fields[0].kind(), FieldKind::F32);
+        assert_eq!(summary.archetypes[0].entity_count, 2);
+
+        assert!(PROTO_SCHEMA.contains("message SnapshotSummary"));
+        assert!(decode_metadata(&[0xff, 0xff]).is_err());
+    }
+}
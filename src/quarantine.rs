@@ -0,0 +1,199 @@
+use crate::catalog::{write_json_atomic, CatalogChange};
+use crate::error::{PackError, Result};
+use crate::metadata::SnapshotMetadata;
+use crate::storage::{validate_snapshot_id, SnapshotReader, SnapshotStore};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+const REASON_SUFFIX: &str = ".reason.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub id: String,
+    pub files: Vec<String>,
+    pub reason: String,
+    pub quarantined_at: i64,
+}
+
+impl SnapshotStore {
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.root_dir().join(QUARANTINE_DIR)
+    }
+
+    pub fn quarantined(&self) -> Result<Vec<QuarantineEntry>> {
+        let dir = self.quarantine_dir();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.to_string_lossy().ends_with(REASON_SUFFIX) {
+                entries.push(read_entry(&path)?);
+            }
+        }
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(entries)
+    }
+
+    // Moves every file of a snapshot that failed to read out of the store so
+    // listing and loading can carry on without it.
+    pub(crate) fn quarantine(&self, id: &str, error: &PackError) -> Result<()> {
+        let dir = self.quarantine_dir();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut files = Vec::new();
+        for extension in ["tx2pack", "tx2delta", "meta.json"] {
+            let path = self.file_path(id, extension)?;
+            let filename = format!("{}.{}", id, extension);
+            if path.exists() {
+                std::fs::rename(&path, dir.join(&filename))?;
+                files.push(filename);
+            }
+        }
+
+        write_json_atomic(
+            &reason_path(&dir, id),
+            &QuarantineEntry {
+                id: id.to_string(),
+                files,
+                reason: error.to_string(),
+                quarantined_at: chrono::Utc::now().timestamp(),
+            },
+        )?;
+
+        if self.indexes_exist() {
+            self.update_indexes(CatalogChange::Remove(id.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn quarantine_if_corrupt(&self, id: &str, error: PackError) -> PackError {
+        if error.is_corruption() {
+            if let Err(e) = self.quarantine(id, &error) {
+                return e;
+            }
+        }
+        error
+    }
+
+    // Reads the quarantined files again, with a reader that may now have the
+    // right key or looser limits, and restores them if they are readable.
+    // Returns false, with the reason updated, if they are still corrupt.
+    pub fn retry_quarantined(&self, id: &str, reader: &SnapshotReader) -> Result<bool> {
+        validate_snapshot_id(id)?;
+        let dir = self.quarantine_dir();
+        let reason_path = reason_path(&dir, id);
+        if !reason_path.exists() {
+            return Err(PackError::SnapshotNotFound(id.to_string()));
+        }
+        let mut entry = read_entry(&reason_path)?;
+
+        if self.exists(id) {
+            return Err(PackError::InvalidMetadata(format!(
+                "Snapshot {} already exists; delete it before restoring the quarantined copy", id
+            )));
+        }
+
+        match check_files(&dir, &entry.files, reader) {
+            Ok(()) => {}
+            Err(e) if e.is_corruption() => {
+                entry.reason = e.to_string();
+                entry.quarantined_at = chrono::Utc::now().timestamp();
+                write_json_atomic(&reason_path, &entry)?;
+                return Ok(false);
+            }
+            Err(e) => return Err(e),
+        }
+
+        for filename in &entry.files {
+            std::fs::rename(dir.join(filename), self.root_dir().join(filename))?;
+        }
+        std::fs::remove_file(reason_path)?;
+
+        let metadata = self.load_metadata(id)?;
+        self.index_metadata(&metadata, self.file_size(id)?)?;
+
+        Ok(true)
+    }
+}
+
+fn reason_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}{}", id, REASON_SUFFIX))
+}
+
+fn read_entry(path: &Path) -> Result<QuarantineEntry> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+fn check_files(dir: &Path, files: &[String], reader: &SnapshotReader) -> Result<()> {
+    for filename in files {
+        let path = dir.join(filename);
+        if filename.ends_with(".tx2pack") {
+            reader.read_from_file(&path)?;
+        } else if filename.ends_with(".tx2delta") {
+            reader.read_delta_from_file(&path)?;
+        } else {
+            serde_json::from_str::<SnapshotMetadata>(&std::fs::read_to_string(&path)?)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{PackFormat, PackedSnapshot, HEADER_SIZE};
+    use crate::storage::SnapshotWriter;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_corrupt_snapshots_are_quarantined_and_retried() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let writer = SnapshotWriter::new();
+        let reader = SnapshotReader::new();
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Bincode;
+        for id in ["good", "bad_data", "bad_header"] {
+            store.save(&snapshot, &SnapshotMetadata::new(id.to_string()), &writer).unwrap();
+        }
+        assert_eq!(store.catalog().unwrap().len(), 3);
+
+        let bad_data = temp_dir.path().join("bad_data.tx2pack");
+        let original = std::fs::read(&bad_data).unwrap();
+        let mut bytes = original.clone();
+        let last = bytes.len() - HEADER_SIZE as usize - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&bad_data, &bytes).unwrap();
+        std::fs::write(temp_dir.path().join("bad_header.tx2pack"), b"garbage").unwrap();
+
+        // Listing skips the unreadable header; loading catches the bad checksum.
+        let mut ids = store.list().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec!["bad_data".to_string(), "good".to_string()]);
+        assert!(matches!(store.load("bad_data", &reader).map_err(PackError::into_root), Err(PackError::ChecksumMismatch)));
+        assert!(store.load("good", &reader).is_ok());
+        assert_eq!(store.list().unwrap(), vec!["good".to_string()]);
+        assert_eq!(store.catalog().unwrap().len(), 1);
+
+        let quarantined = store.quarantined().unwrap();
+        assert_eq!(quarantined.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), ["bad_data", "bad_header"]);
+        assert_eq!(quarantined[0].files, ["bad_data.tx2pack", "bad_data.meta.json"]);
+        assert!(quarantined[0].reason.contains("Checksum mismatch"));
+
+        assert!(!store.retry_quarantined("bad_data", &reader).unwrap());
+        std::fs::write(store.quarantine_dir().join("bad_data.tx2pack"), &original).unwrap();
+        assert!(store.retry_quarantined("bad_data", &reader).unwrap());
+        assert!(store.load("bad_data", &reader).is_ok());
+        assert_eq!(store.catalog().unwrap().len(), 2);
+        assert_eq!(store.quarantined().unwrap().len(), 1);
+        assert!(matches!(store.retry_quarantined("good", &reader), Err(PackError::SnapshotNotFound(_))));
+    }
+}
@@ -0,0 +1,341 @@
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldValue, PackedSnapshot, StructOfArraysData};
+use std::cmp::Ordering;
+use tx2_link::EntityId;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Eq(FieldValue),
+    Ne(FieldValue),
+    Lt(FieldValue),
+    Le(FieldValue),
+    Gt(FieldValue),
+    Ge(FieldValue),
+    Between(FieldValue, FieldValue),
+}
+
+impl Predicate {
+    pub fn evaluate(&self, value: &FieldValue) -> bool {
+        let cmp = |other: &FieldValue| value.compare(other);
+
+        match self {
+            Predicate::Eq(v) => cmp(v) == Some(Ordering::Equal),
+            Predicate::Ne(v) => cmp(v).is_some_and(|o| o != Ordering::Equal),
+            Predicate::Lt(v) => cmp(v) == Some(Ordering::Less),
+            Predicate::Le(v) => matches!(cmp(v), Some(Ordering::Less | Ordering::Equal)),
+            Predicate::Gt(v) => cmp(v) == Some(Ordering::Greater),
+            Predicate::Ge(v) => matches!(cmp(v), Some(Ordering::Greater | Ordering::Equal)),
+            Predicate::Between(lo, hi) => {
+                matches!(cmp(lo), Some(Ordering::Greater | Ordering::Equal))
+                    && matches!(cmp(hi), Some(Ordering::Less | Ordering::Equal))
+            }
+        }
+    }
+
+    pub fn may_match(&self, stats: &ColumnStats) -> bool {
+        let (Some(min), Some(max)) = (&stats.min, &stats.max) else {
+            return stats.row_count > 0;
+        };
+
+        let ge = |a: &FieldValue, b: &FieldValue| matches!(a.compare(b), Some(Ordering::Greater | Ordering::Equal));
+        let gt = |a: &FieldValue, b: &FieldValue| a.compare(b) == Some(Ordering::Greater);
+
+        match self {
+            Predicate::Eq(v) => ge(v, min) && ge(max, v),
+            Predicate::Ne(v) => !(min.compare(v) == Some(Ordering::Equal) && max.compare(v) == Some(Ordering::Equal)),
+            Predicate::Lt(v) => gt(v, min),
+            Predicate::Le(v) => ge(v, min),
+            Predicate::Gt(v) => gt(max, v),
+            Predicate::Ge(v) => ge(max, v),
+            Predicate::Between(lo, hi) => ge(max, lo) && ge(hi, min),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub row_count: usize,
+    pub min: Option<FieldValue>,
+    pub max: Option<FieldValue>,
+}
+
+impl ColumnStats {
+    pub fn compute(column: &FieldArray, start: usize, end: usize) -> Self {
+        let mut min: Option<FieldValue> = None;
+        let mut max: Option<FieldValue> = None;
+
+        for row in start..end.min(column.len()) {
+            let Some(value) = column.get(row) else { continue };
+
+            if value.as_f64().is_some_and(f64::is_nan) || matches!(value, FieldValue::Bytes(_)) {
+                continue;
+            }
+
+            if min.as_ref().is_none_or(|m| value.compare(m) == Some(Ordering::Less)) {
+                min = Some(value.clone());
+            }
+            if max.as_ref().is_none_or(|m| value.compare(m) == Some(Ordering::Greater)) {
+                max = Some(value);
+            }
+        }
+
+        Self {
+            row_count: end.min(column.len()).saturating_sub(start),
+            min,
+            max,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchetypeStats {
+    pub component_id: String,
+    pub chunk_size: usize,
+    pub columns: Vec<(String, Vec<ColumnStats>)>,
+}
+
+impl ArchetypeStats {
+    pub fn compute(archetype: &ComponentArchetype, chunk_size: usize) -> Result<Self> {
+        let soa = struct_of_arrays(archetype)?;
+        let chunk_size = chunk_size.max(1);
+        let row_count = archetype.entity_ids.len();
+
+        let columns = soa
+            .field_names
+            .iter()
+            .zip(&soa.field_data)
+            .map(|(name, column)| {
+                let chunks = (0..row_count)
+                    .step_by(chunk_size)
+                    .map(|start| ColumnStats::compute(column, start, start + chunk_size))
+                    .collect();
+                (name.clone(), chunks)
+            })
+            .collect();
+
+        Ok(Self {
+            component_id: archetype.component_id.clone(),
+            chunk_size,
+            columns,
+        })
+    }
+
+    pub fn column(&self, field: &str) -> Option<&[ColumnStats]> {
+        self.columns
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, stats)| stats.as_slice())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueryResult {
+    pub entity_ids: Vec<EntityId>,
+    pub columns: Vec<(String, FieldArray)>,
+    pub chunks_scanned: usize,
+    pub chunks_skipped: usize,
+}
+
+impl QueryResult {
+    pub fn column(&self, field: &str) -> Option<&FieldArray> {
+        self.columns
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, column)| column)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entity_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entity_ids.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ColumnQuery {
+    component_id: String,
+    filters: Vec<(String, Predicate)>,
+    projection: Option<Vec<String>>,
+    chunk_size: usize,
+}
+
+impl ColumnQuery {
+    pub fn new(component_id: &str) -> Self {
+        Self {
+            component_id: component_id.to_string(),
+            filters: Vec::new(),
+            projection: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    pub fn filter(mut self, field: &str, predicate: Predicate) -> Self {
+        self.filters.push((field.to_string(), predicate));
+        self
+    }
+
+    pub fn select(mut self, fields: &[&str]) -> Self {
+        self.projection = Some(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn execute(&self, snapshot: &PackedSnapshot) -> Result<QueryResult> {
+        let archetype = self.find_archetype(snapshot)?;
+        let stats = ArchetypeStats::compute(archetype, self.chunk_size)?;
+        self.execute_archetype(archetype, &stats)
+    }
+
+    pub fn execute_with_stats(&self, snapshot: &PackedSnapshot, stats: &ArchetypeStats) -> Result<QueryResult> {
+        let archetype = self.find_archetype(snapshot)?;
+        self.execute_archetype(archetype, stats)
+    }
+
+    fn find_archetype<'a>(&self, snapshot: &'a PackedSnapshot) -> Result<&'a ComponentArchetype> {
+        snapshot
+            .archetypes
+            .iter()
+            .find(|a| a.component_id == self.component_id)
+            .ok_or_else(|| PackError::Query(format!("Component {} not found", self.component_id)))
+    }
+
+    fn execute_archetype(&self, archetype: &ComponentArchetype, stats: &ArchetypeStats) -> Result<QueryResult> {
+        let soa = struct_of_arrays(archetype)?;
+
+        let filters = self
+            .filters
+            .iter()
+            .map(|(field, predicate)| {
+                let column = column_index(soa, &archetype.component_id, field)?;
+                Ok((&soa.field_data[column], stats.column(field), predicate))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let projection: Vec<usize> = match &self.projection {
+            Some(fields) => fields
+                .iter()
+                .map(|field| column_index(soa, &archetype.component_id, field))
+                .collect::<Result<_>>()?,
+            None => (0..soa.field_names.len()).collect(),
+        };
+
+        let row_count = archetype.entity_ids.len();
+        let chunk_size = stats.chunk_size.max(1);
+        let mut result = QueryResult::default();
+        let mut rows = Vec::new();
+
+        for (chunk, start) in (0..row_count).step_by(chunk_size).enumerate() {
+            let skip = filters.iter().any(|(_, chunk_stats, predicate)| {
+                chunk_stats
+                    .and_then(|s| s.get(chunk))
+                    .is_some_and(|s| !predicate.may_match(s))
+            });
+
+            if skip {
+                result.chunks_skipped += 1;
+                continue;
+            }
+
+            result.chunks_scanned += 1;
+
+            for row in start..(start + chunk_size).min(row_count) {
+                let matched = filters.iter().all(|(column, _, predicate)| {
+                    column.get(row).is_some_and(|value| predicate.evaluate(&value))
+                });
+
+                if matched {
+                    rows.push(row);
+                }
+            }
+        }
+
+        result.entity_ids = rows.iter().map(|&row| archetype.entity_ids[row]).collect();
+        result.columns = projection
+            .into_iter()
+            .map(|column| (soa.field_names[column].clone(), soa.field_data[column].gather(&rows)))
+            .collect();
+
+        Ok(result)
+    }
+}
+
+fn struct_of_arrays(archetype: &ComponentArchetype) -> Result<&StructOfArraysData> {
+    match &archetype.data {
+        ComponentData::StructOfArrays(soa) => Ok(soa),
+        ComponentData::Blob(_) => Err(PackError::Query(
+            format!("Component {} is not stored as struct-of-arrays", archetype.component_id)
+        )),
+    }
+}
+
+fn column_index(soa: &StructOfArraysData, component_id: &str, field: &str) -> Result<usize> {
+    soa.field_names
+        .iter()
+        .position(|name| name == field)
+        .ok_or_else(|| PackError::Query(format!("Field {}.{} not found", component_id, field)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::FieldType;
+
+    fn position_snapshot(count: usize) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: (0..count as u32).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["x".to_string(), "y".to_string()],
+                field_types: vec![FieldType::F32, FieldType::F32],
+                field_data: vec![
+                    FieldArray::F32((0..count).map(|i| i as f32).collect()),
+                    FieldArray::F32((0..count).map(|i| (count - i) as f32).collect()),
+                ],
+            }),
+        });
+
+        snapshot
+    }
+
+    #[test]
+    fn test_column_query_filter_and_project() {
+        let snapshot = position_snapshot(5000);
+
+        let result = ColumnQuery::new("Position")
+            .filter("x", Predicate::Gt(FieldValue::F32(4990.0)))
+            .select(&["y"])
+            .execute(&snapshot)
+            .unwrap();
+
+        assert_eq!(result.entity_ids, (4991..5000).collect::<Vec<u32>>());
+        assert_eq!(result.columns.len(), 1);
+        assert_eq!(result.column("y").unwrap().get(0), Some(FieldValue::F32(9.0)));
+        assert_eq!(result.chunks_scanned, 1);
+        assert_eq!(result.chunks_skipped, 4);
+    }
+
+    #[test]
+    fn test_column_query_mixed_types_and_errors() {
+        let snapshot = position_snapshot(100);
+
+        let result = ColumnQuery::new("Position")
+            .filter("x", Predicate::Between(FieldValue::I32(10), FieldValue::U64(12)))
+            .filter("y", Predicate::Ne(FieldValue::F64(89.0)))
+            .execute(&snapshot)
+            .unwrap();
+        assert_eq!(result.entity_ids, vec![10, 12]);
+
+        assert!(ColumnQuery::new("Velocity").execute(&snapshot).is_err());
+        assert!(ColumnQuery::new("Position").select(&["z"]).execute(&snapshot).is_err());
+    }
+}
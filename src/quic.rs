@@ -0,0 +1,220 @@
+use crate::error::{PackError, Result};
+use crate::storage::{read_at, SnapshotStore};
+use crate::transfer::{unexpected_frame, TransferFrame, MAX_CONTROL_FRAME};
+use quinn::{Connection, ReadExactError, RecvStream, SendStream};
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+pub const DEFAULT_QUIC_STREAMS: usize = 8;
+
+// The transfer protocol over QUIC. The manifest and replies travel on one
+// bidirectional stream, while the missing chunks are split into runs that
+// each go out on their own unidirectional stream. A slow or lossy path then
+// stalls one run instead of the whole snapshot, which is what makes long
+// transfers across regions latency-bound over a single TCP connection.
+pub async fn send_snapshot_quic(
+    store: &SnapshotStore,
+    id: &str,
+    chunk_size: u32,
+    streams: usize,
+    connection: &Connection,
+) -> Result<u32> {
+    let manifest = Arc::new(store.transfer_manifest(id, chunk_size)?);
+    let path = store.data_path(id).ok_or_else(|| PackError::SnapshotNotFound(id.to_string()))?;
+
+    let (mut control, mut replies) = connection.open_bi().await.map_err(std::io::Error::from)?;
+    write_frame(&mut control, &TransferFrame::Manifest(Box::new((*manifest).clone()))).await?;
+    let missing = match expect_frame(&mut replies, MAX_CONTROL_FRAME).await? {
+        TransferFrame::Missing { chunks } => chunks,
+        other => return Err(unexpected_frame("Missing", &other)),
+    };
+
+    let run = missing.len().div_ceil(streams.max(1)).max(1);
+    let mut runs = Vec::new();
+    for chunks in missing.chunks(run) {
+        let chunks = chunks.to_vec();
+        let (connection, manifest, path) = (connection.clone(), manifest.clone(), path.clone());
+        runs.push(tokio::spawn(async move {
+            let mut stream = connection.open_uni().await.map_err(std::io::Error::from)?;
+            let mut file = File::open(path)?;
+            for index in chunks {
+                let range = manifest.chunk_range(index).ok_or_else(|| {
+                    PackError::InvalidFormat(format!("Receiver asked for chunk {} past the last", index))
+                })?;
+                // Checked as in read_transfer_chunk, in case the file changed.
+                let data = read_at(&mut file, range.start, range.end - range.start)?;
+                manifest.verify_chunk(index, &data)?;
+                write_frame(&mut stream, &TransferFrame::Chunk { index, data }).await?;
+            }
+            stream.finish().map_err(std::io::Error::from)?;
+            Ok::<_, PackError>(())
+        }));
+    }
+    for run in runs {
+        run.await.map_err(std::io::Error::other)??;
+    }
+
+    match expect_frame(&mut replies, MAX_CONTROL_FRAME).await? {
+        TransferFrame::Complete => {}
+        other => return Err(unexpected_frame("Complete", &other)),
+    }
+    control.finish().map_err(std::io::Error::from)?;
+
+    Ok(missing.len() as u32)
+}
+
+// Chunks are written as they arrive from any stream. Like receive_snapshot,
+// a transfer that breaks off resumes with only the chunks still missing.
+pub async fn receive_snapshot_quic(store: &SnapshotStore, connection: &Connection) -> Result<PathBuf> {
+    let (mut replies, mut control) = connection.accept_bi().await.map_err(std::io::Error::from)?;
+    let manifest = match expect_frame(&mut control, MAX_CONTROL_FRAME).await? {
+        TransferFrame::Manifest(manifest) => *manifest,
+        other => return Err(unexpected_frame("Manifest", &other)),
+    };
+    manifest.validate()?;
+    let max_frame = manifest.max_chunk_frame();
+    let mut receiver = store.receive_transfer(manifest)?;
+    write_frame(&mut replies, &TransferFrame::Missing { chunks: receiver.missing_chunks() }).await?;
+
+    // Streams are drained by their own tasks; writes stay on this one.
+    let (frames, mut incoming) = mpsc::channel(DEFAULT_QUIC_STREAMS * 2);
+    let acceptor = {
+        let connection = connection.clone();
+        tokio::spawn(async move {
+            while let Ok(mut stream) = connection.accept_uni().await {
+                let frames = frames.clone();
+                tokio::spawn(async move {
+                    while let Some(frame) = read_frame(&mut stream, max_frame).await.transpose() {
+                        if frames.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        })
+    };
+
+    let received = async {
+        while !receiver.is_complete() {
+            let frame = incoming.recv().await.ok_or_else(|| std::io::Error::from(ErrorKind::UnexpectedEof))??;
+            match frame {
+                TransferFrame::Chunk { index, data } => receiver.write_chunk(index, &data)?,
+                other => return Err(unexpected_frame("Chunk", &other)),
+            }
+        }
+        Ok(())
+    }
+    .await;
+    acceptor.abort();
+    received?;
+
+    let path = receiver.finish()?;
+    write_frame(&mut replies, &TransferFrame::Complete).await?;
+    replies.finish().map_err(std::io::Error::from)?;
+
+    Ok(path)
+}
+
+async fn write_frame(stream: &mut SendStream, frame: &TransferFrame) -> Result<()> {
+    let encoded = bincode::serialize(frame)?;
+    let len = u32::try_from(encoded.len())
+        .map_err(|_| PackError::InvalidFormat(format!("Frame of {} bytes is too large", encoded.len())))?;
+    stream.write_all(&len.to_le_bytes()).await.map_err(std::io::Error::from)?;
+    stream.write_all(&encoded).await.map_err(std::io::Error::from)?;
+    Ok(())
+}
+
+// None once the peer finishes the stream between two frames.
+async fn read_frame(stream: &mut RecvStream, max_len: usize) -> Result<Option<TransferFrame>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len).await {
+        Ok(()) => {}
+        Err(ReadExactError::FinishedEarly(0)) => return Ok(None),
+        Err(e) => return Err(read_error(e)),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > max_len {
+        return Err(PackError::LimitExceeded { limit: "frame size", max: max_len as u64 });
+    }
+
+    let mut encoded = vec![0u8; len];
+    stream.read_exact(&mut encoded).await.map_err(read_error)?;
+    Ok(Some(bincode::deserialize(&encoded)?))
+}
+
+async fn expect_frame(stream: &mut RecvStream, max_len: usize) -> Result<TransferFrame> {
+    read_frame(stream, max_len).await?.ok_or_else(|| std::io::Error::from(ErrorKind::UnexpectedEof).into())
+}
+
+fn read_error(error: ReadExactError) -> PackError {
+    match error {
+        ReadExactError::FinishedEarly(_) => std::io::Error::from(ErrorKind::UnexpectedEof).into(),
+        ReadExactError::ReadError(error) => std::io::Error::from(error).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot};
+    use crate::metadata::SnapshotMetadata;
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+    use quinn::rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+    use quinn::{ClientConfig, Endpoint, ServerConfig};
+    use tempfile::TempDir;
+
+    async fn connect() -> (Endpoint, Connection, Connection) {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert = CertificateDer::from(certified.cert);
+        let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+        let server_config = ServerConfig::with_single_cert(vec![cert.clone()], key.into()).unwrap();
+        let server = Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut roots = quinn::rustls::RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let mut client = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        client.set_default_client_config(ClientConfig::with_root_certificates(Arc::new(roots)).unwrap());
+
+        let connecting = client.connect(server.local_addr().unwrap(), "localhost").unwrap();
+        let (ours, theirs) = tokio::join!(connecting, async { server.accept().await.unwrap().await });
+        (server, ours.unwrap(), theirs.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_quic_transfer_spreads_missing_chunks_over_streams() {
+        let (source_dir, target_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let source = SnapshotStore::new(source_dir.path()).unwrap();
+        let target = SnapshotStore::new(target_dir.path()).unwrap();
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Trail".to_string(),
+            entity_ids: vec![1],
+            data: ComponentData::Blob((0..8_000u32).flat_map(|v| v.to_le_bytes()).collect()),
+        });
+        let writer = SnapshotWriter::new().with_compression(crate::CompressionCodec::None);
+        source.save(&snapshot, &SnapshotMetadata::new("replay".to_string()), &writer).unwrap();
+
+        // An earlier attempt got two chunks across.
+        let manifest = source.transfer_manifest("replay", 1024).unwrap();
+        let mut receiver = target.receive_transfer(manifest.clone()).unwrap();
+        for index in [0, 5] {
+            receiver.write_chunk(index, &source.read_transfer_chunk(&manifest, index).unwrap()).unwrap();
+        }
+        drop(receiver);
+
+        let (_server, ours, theirs) = connect().await;
+        let (sent, path) = tokio::join!(
+            send_snapshot_quic(&source, "replay", 1024, 4, &ours),
+            receive_snapshot_quic(&target, &theirs)
+        );
+        assert_eq!(sent.unwrap(), manifest.chunk_count() - 2);
+        let original = std::fs::read(source_dir.path().join("replay.tx2pack")).unwrap();
+        assert_eq!(std::fs::read(path.unwrap()).unwrap(), original);
+        let (loaded, _) = target.load("replay", &SnapshotReader::new()).unwrap();
+        assert_eq!(loaded.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+    }
+}
@@ -0,0 +1,305 @@
+use crate::error::{PackError, Result, ResultExt};
+use crate::format::{PackFormat, PackedSnapshot, SnapshotHeader, KNOWN_FLAGS, MAGIC_NUMBER};
+use crate::limits::DecompressionBudget;
+use crate::sectioned;
+use crate::storage::SnapshotReader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use tx2_link::ComponentId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ReadMode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub mode: ReadMode,
+    pub known_components: Option<HashSet<ComponentId>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ReadWarning {
+    UnknownFlags(u32),
+    UnlistedSectionBytes(u64),
+    UnknownComponent(ComponentId),
+    SkippedArchetype { component_id: ComponentId, error: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadResult {
+    pub snapshot: PackedSnapshot,
+    pub warnings: Vec<ReadWarning>,
+}
+
+impl ReadOptions {
+    pub fn strict() -> Self {
+        Self::default()
+    }
+
+    pub fn lenient() -> Self {
+        Self {
+            mode: ReadMode::Lenient,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_known_components<I, S>(mut self, component_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<ComponentId>,
+    {
+        self.known_components = Some(component_ids.into_iter().map(Into::into).collect());
+        self
+    }
+
+    fn is_known(&self, component_id: &ComponentId) -> bool {
+        self.known_components.as_ref().is_none_or(|known| known.contains(component_id))
+    }
+
+    // Strict mode turns what lenient mode would only warn about into an error.
+    fn tolerate(&self, warning: ReadWarning, warnings: &mut Vec<ReadWarning>) -> Result<()> {
+        match self.mode {
+            ReadMode::Lenient => {
+                warnings.push(warning);
+                Ok(())
+            }
+            ReadMode::Strict => Err(match warning {
+                ReadWarning::UnknownFlags(flags) => {
+                    PackError::InvalidFormat(format!("Unknown header flags {:#x}", flags))
+                }
+                ReadWarning::UnlistedSectionBytes(size) => {
+                    PackError::InvalidFormat(format!("{} bytes of sections are missing from the table of contents", size))
+                }
+                ReadWarning::UnknownComponent(component_id) => {
+                    PackError::InvalidFormat(format!("Unknown component {}", component_id))
+                }
+                ReadWarning::SkippedArchetype { component_id, error } => {
+                    PackError::Deserialization(format!("Cannot decode component {}: {}", component_id, error))
+                }
+            }),
+        }
+    }
+}
+
+impl SnapshotReader {
+    pub fn read_from_file_with<P: AsRef<Path>>(&self, path: P, options: &ReadOptions) -> Result<ReadResult> {
+        let data = std::fs::read(path.as_ref())?;
+        self.read_from_bytes_with(&data, options)
+    }
+
+    pub fn read_from_bytes_with(&self, bytes: &[u8], options: &ReadOptions) -> Result<ReadResult> {
+        let (header, data) = self.verified_data(bytes, MAGIC_NUMBER)?;
+        let mut warnings = Vec::new();
+
+        let unknown_flags = header.flags & !KNOWN_FLAGS;
+        if unknown_flags != 0 {
+            options.tolerate(ReadWarning::UnknownFlags(unknown_flags), &mut warnings)?;
+        }
+
+        let budget = self.limits().budget();
+        let mut snapshot = if header.format == PackFormat::Custom {
+            self.read_sections_with(&header, data, &budget, options, &mut warnings)?
+        } else {
+            self.snapshot_from_data(header.clone(), data)?
+        };
+
+        let mut kept = Vec::with_capacity(snapshot.archetypes.len());
+        for archetype in snapshot.archetypes.drain(..) {
+            if options.is_known(&archetype.component_id) {
+                kept.push(archetype);
+            } else {
+                options.tolerate(ReadWarning::UnknownComponent(archetype.component_id), &mut warnings)?;
+            }
+        }
+        snapshot.archetypes = kept;
+
+        self.limits().check_snapshot(&snapshot)?;
+        snapshot.check_consistency()?;
+        snapshot.header = header;
+        snapshot.rebuild_entity_index();
+
+        Ok(ReadResult { snapshot, warnings })
+    }
+
+    fn read_sections_with(
+        &self,
+        header: &SnapshotHeader,
+        data: &[u8],
+        budget: &DecompressionBudget,
+        options: &ReadOptions,
+        warnings: &mut Vec<ReadWarning>,
+    ) -> Result<PackedSnapshot> {
+        let open = |sealed: &[u8]| self.open_with(header, sealed, budget);
+        let toc = sectioned::read_toc(data, open)?;
+        self.limits().check_toc(&toc)?;
+
+        let unlisted = sectioned::unlisted_bytes(data, &toc)?;
+        if unlisted > 0 && header.is_newer_minor() {
+            // Optional sections added by a newer minor version are expected.
+            warnings.push(ReadWarning::UnlistedSectionBytes(unlisted));
+        } else if unlisted > 0 {
+            options.tolerate(ReadWarning::UnlistedSectionBytes(unlisted), warnings)?;
+        }
+
+        // Unknown components are dropped before their sections are even opened.
+        let entries: Vec<_> = toc.archetypes.iter().filter(|entry| options.is_known(&entry.component_id)).collect();
+        for entry in toc.archetypes.iter().filter(|entry| !options.is_known(&entry.component_id)) {
+            options.tolerate(ReadWarning::UnknownComponent(entry.component_id.clone()), warnings)?;
+        }
+
+        let decoded = sectioned::map_sections(&entries, |entry| {
+            Ok(sectioned::open_chunked(sectioned::section_bytes(data, &entry.section)?, open)
+                .and_then(|raw| sectioned::decode_archetype(&raw, header.flags)))
+        })?;
+
+        let mut snapshot = PackedSnapshot::new();
+        for (entry, archetype) in entries.iter().zip(decoded) {
+            match archetype {
+                Ok(archetype) => snapshot.archetypes.push(archetype),
+                // The checksum already matched, so a section that does not decode
+                // was written by a newer version (a new field type, say).
+                Err(e @ PackError::Deserialization(_)) => options.tolerate(
+                    ReadWarning::SkippedArchetype {
+                        component_id: entry.component_id.clone(),
+                        error: e.to_string(),
+                    },
+                    warnings,
+                )?,
+                Err(e) => return Err(e).with_component(&entry.component_id),
+            }
+        }
+        snapshot.entity_metadata = sectioned::decode_section(data, &toc.entity_metadata, open)?;
+        snapshot.resources = sectioned::decode_resources(data, &toc, open)?;
+        snapshot.layout = toc.layout.clone();
+
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionCodec;
+    use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, StructOfArraysData, HEADER_SIZE};
+    use crate::storage::SnapshotWriter;
+
+    fn archetype(component_id: &str, field: &str) -> ComponentArchetype {
+        ComponentArchetype {
+            component_id: component_id.to_string(),
+            entity_ids: vec![1, 2],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec![field.to_string()],
+                field_types: vec![FieldType::U8],
+                field_data: vec![FieldArray::U8(vec![1, 2].into())],
+            }),
+        }
+    }
+
+    fn reseal(bytes: &mut [u8], edit: impl FnOnce(&mut SnapshotHeader)) {
+        let mut header = SnapshotHeader::decode(bytes).unwrap();
+        edit(&mut header);
+        let data_end = (header.data_offset + header.data_size) as usize;
+        header.checksum = header.checksum_algorithm.digest(&bytes[header.data_offset as usize..data_end]);
+        let mut encoded = Vec::new();
+        header.encode_into(&mut encoded).unwrap();
+        bytes[..HEADER_SIZE as usize].copy_from_slice(&encoded);
+        bytes[data_end..].copy_from_slice(&encoded);
+    }
+
+    #[test]
+    fn test_lenient_mode_skips_what_it_does_not_understand() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype("Position", "x"));
+        snapshot.archetypes.push(archetype("Future", "zzzz"));
+        snapshot.archetypes.push(archetype("Debug", "y"));
+
+        let writer = SnapshotWriter::new().with_compression(CompressionCodec::none()).with_chunk_checksums(false);
+        let mut bytes = writer.write_to_bytes(&snapshot).unwrap();
+        let reader = SnapshotReader::new();
+
+        let clean = reader.read_from_bytes_with(&bytes, &ReadOptions::strict()).unwrap();
+        assert!(clean.warnings.is_empty());
+        assert_eq!(clean.snapshot.archetypes.len(), 3);
+
+        // Pretend a newer writer used a field type this version has never seen,
+        // and set a header flag it does not know about.
+        let name = bytes.windows(4).position(|window| window == b"zzzz").unwrap();
+        bytes[name + 4 + 8..name + 4 + 12].copy_from_slice(&99u32.to_le_bytes());
+        reseal(&mut bytes, |header| header.flags |= 1 << 31);
+
+        assert!(reader.read_from_bytes(&bytes).is_err());
+        assert!(reader.read_from_bytes_with(&bytes, &ReadOptions::strict()).is_err());
+
+        let options = ReadOptions::lenient().with_known_components(["Position", "Future"]);
+        let result = reader.read_from_bytes_with(&bytes, &options).unwrap();
+        assert_eq!(result.snapshot.archetypes.len(), 1);
+        assert_eq!(result.snapshot.archetypes[0].component_id, "Position");
+        assert_eq!(result.warnings[0], ReadWarning::UnknownFlags(1 << 31));
+        assert!(result.warnings.contains(&ReadWarning::UnknownComponent("Debug".to_string())));
+        assert!(result.warnings.iter().any(|warning| matches!(
+            warning,
+            ReadWarning::SkippedArchetype { component_id, .. } if component_id == "Future"
+        )));
+    }
+
+    #[test]
+    fn test_unlisted_sections_are_reported() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype("Position", "x"));
+
+        let mut bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+        let header = SnapshotHeader::decode(&bytes).unwrap();
+        let data_end = (header.data_offset + header.data_size) as usize;
+        let toc_size = sectioned::toc_size(&bytes[..data_end]).unwrap() as usize;
+        let footer = data_end - toc_size - 8;
+        bytes.splice(footer..footer, [0xab; 32]);
+        reseal(&mut bytes, |header| header.data_size += 32);
+
+        let reader = SnapshotReader::new();
+        assert!(reader.read_from_bytes_with(&bytes, &ReadOptions::strict()).is_err());
+        let result = reader.read_from_bytes_with(&bytes, &ReadOptions::lenient()).unwrap();
+        assert_eq!(result.warnings, vec![ReadWarning::UnlistedSectionBytes(32)]);
+        assert_eq!(result.snapshot.archetypes.len(), 1);
+    }
+
+    #[test]
+    fn test_newer_minor_versions_are_read() {
+        use crate::format::{format_version, FORMAT_VERSION, FORMAT_VERSION_MAJOR, FORMAT_VERSION_MINOR};
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype("Position", "x"));
+
+        // A newer minor version adds an optional section this reader has never heard of.
+        let mut bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+        let header = SnapshotHeader::decode(&bytes).unwrap();
+        let data_end = (header.data_offset + header.data_size) as usize;
+        let toc_size = sectioned::toc_size(&bytes[..data_end]).unwrap() as usize;
+        let footer = data_end - toc_size - 8;
+        bytes.splice(footer..footer, [0xcd; 32]);
+        reseal(&mut bytes, |header| {
+            header.version = format_version(FORMAT_VERSION_MAJOR, FORMAT_VERSION_MINOR + 1);
+            header.data_size += 32;
+        });
+
+        let reader = SnapshotReader::new();
+        let result = reader.read_from_bytes_with(&bytes, &ReadOptions::strict()).unwrap();
+        assert_eq!(result.warnings, vec![ReadWarning::UnlistedSectionBytes(32)]);
+        assert!(result.snapshot.header.is_newer_minor());
+        assert_eq!(result.snapshot.archetypes.len(), 1);
+
+        let restored = reader.read_from_bytes(&bytes).unwrap();
+        let rewritten = SnapshotWriter::new().write_to_bytes(&restored).unwrap();
+        assert_eq!(SnapshotHeader::decode(&rewritten).unwrap().version, FORMAT_VERSION);
+
+        reseal(&mut bytes, |header| header.version = format_version(FORMAT_VERSION_MAJOR + 1, 0));
+        assert!(matches!(reader.read_from_bytes(&bytes), Err(PackError::VersionMismatch { .. })));
+        assert!(reader.read_from_bytes_with(&bytes, &ReadOptions::lenient()).is_err());
+    }
+}
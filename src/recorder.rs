@@ -0,0 +1,274 @@
+use crate::adapter::ChangeTracking;
+use crate::delta::{DeltaChain, DeltaSnapshot};
+use crate::error::{PackError, Result, ResultExt};
+use crate::incremental::IncrementalCapture;
+use crate::metadata::SnapshotMetadata;
+use crate::storage::{SnapshotReader, SnapshotStore, SnapshotWriter};
+use std::fs::OpenOptions;
+use std::io::Write;
+
+pub const DELTA_LOG_EXTENSION: &str = "deltas";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recorded {
+    Keyframe(String),
+    /// Position in the keyframe's log, from 1.
+    Delta { keyframe: String, index: usize },
+}
+
+/// Saves keyframes as `<prefix>-<tick>` and, on each later call, appends what
+/// `ChangeTracking::changes_since` reports as a delta to the keyframe's log.
+pub struct ChangeRecorder {
+    store: SnapshotStore,
+    writer: SnapshotWriter,
+    prefix: String,
+    keyframe_interval: usize,
+    capture: Option<IncrementalCapture>,
+    keyframe: String,
+    deltas: usize,
+}
+
+impl ChangeRecorder {
+    pub fn new(store: SnapshotStore, prefix: &str) -> Self {
+        Self {
+            store,
+            writer: SnapshotWriter::new(),
+            prefix: prefix.to_string(),
+            keyframe_interval: 100,
+            capture: None,
+            keyframe: String::new(),
+            deltas: 0,
+        }
+    }
+
+    pub fn with_writer(mut self, writer: SnapshotWriter) -> Self {
+        self.writer = writer;
+        self
+    }
+
+    pub fn with_keyframe_interval(mut self, deltas: usize) -> Self {
+        self.keyframe_interval = deltas.max(1);
+        self
+    }
+
+    pub fn store(&self) -> &SnapshotStore {
+        &self.store
+    }
+
+    pub fn current_keyframe(&self) -> Option<&str> {
+        self.capture.as_ref().map(|_| self.keyframe.as_str())
+    }
+
+    /// After an error the next call starts a new keyframe.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn record<W: ChangeTracking + ?Sized>(&mut self, world: &W) -> Result<Recorded> {
+        let recorded = match &mut self.capture {
+            Some(capture) if self.deltas < self.keyframe_interval => capture
+                .capture_delta(world)
+                .and_then(|delta| self.store.append_delta(&self.keyframe, &delta, &self.writer))
+                .map(|()| {
+                    self.deltas += 1;
+                    Recorded::Delta { keyframe: self.keyframe.clone(), index: self.deltas }
+                }),
+            _ => self.record_keyframe(world),
+        };
+
+        // The capture may have moved past what the log holds.
+        if recorded.is_err() {
+            self.capture = None;
+        }
+        recorded
+    }
+
+    /// Runs on the first call, so a restarted recorder never appends to a
+    /// log an interrupted run may have left with half a delta.
+    pub fn record_keyframe<W: ChangeTracking + ?Sized>(&mut self, world: &W) -> Result<Recorded> {
+        let capture = IncrementalCapture::new(world)?;
+        let id = format!("{}-{}", self.prefix, capture.tick());
+
+        self.store.save(capture.snapshot(), &SnapshotMetadata::new(id.clone()), &self.writer)?;
+        let log = self.store.file_path(&id, DELTA_LOG_EXTENSION)?;
+        if log.exists() {
+            std::fs::remove_file(log)?;
+        }
+
+        self.capture = Some(capture);
+        self.keyframe = id.clone();
+        self.deltas = 0;
+        Ok(Recorded::Keyframe(id))
+    }
+}
+
+impl SnapshotStore {
+    /// A failed write is cut back off, so the log never holds half a delta
+    /// that a later one would follow.
+    pub fn append_delta(&self, keyframe: &str, delta: &DeltaSnapshot, writer: &SnapshotWriter) -> Result<()> {
+        let encoded = writer.write_delta_to_bytes(delta).with_snapshot_id(keyframe)?;
+        let len = u32::try_from(encoded.len())
+            .map_err(|_| PackError::LimitExceeded { limit: "delta size", max: u32::MAX as u64 })?;
+
+        let mut record = Vec::with_capacity(4 + encoded.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&encoded);
+
+        let path = self.file_path(keyframe, DELTA_LOG_EXTENSION)?;
+        let mut log = OpenOptions::new().create(true).append(true).open(&path)?;
+        let previous_len = log.metadata()?.len();
+        if let Err(e) = log.write_all(&record) {
+            let _ = log.set_len(previous_len);
+            return Err(PackError::from(e)).with_path(&path);
+        }
+        Ok(())
+    }
+
+    /// A delta cut short by a crash ends the chain.
+    pub fn load_delta_log(&self, keyframe: &str, reader: &SnapshotReader) -> Result<DeltaChain> {
+        let (snapshot, _) = self.load(keyframe, reader)?;
+        let mut chain = DeltaChain::new(snapshot);
+
+        let path = self.file_path(keyframe, DELTA_LOG_EXTENSION)?;
+        let log = match std::fs::read(&path) {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(chain),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut rest = log.as_slice();
+        while let Some((len, after)) = rest.split_first_chunk::<4>() {
+            let Some(delta) = after.get(..u32::from_le_bytes(*len) as usize) else {
+                break;
+            };
+            chain.push(reader.read_delta_from_bytes(delta).with_path(&path)?);
+            rest = &after[delta.len()..];
+        }
+
+        Ok(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::WorldCapture;
+    use crate::changeset::ChangeSet;
+    use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, StructOfArraysData};
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+    use tx2_link::{ComponentId, EntityId};
+
+    #[derive(Default)]
+    struct World {
+        tick: u64,
+        hp: BTreeMap<EntityId, u32>,
+        log: Vec<(u64, EntityId, bool)>,
+    }
+
+    impl World {
+        fn set_hp(&mut self, id: EntityId, hp: u32) {
+            self.tick += 1;
+            let added = self.hp.insert(id, hp).is_none();
+            self.log.push((self.tick, id, added));
+        }
+    }
+
+    impl WorldCapture for World {
+        fn component_ids(&self) -> Result<Vec<ComponentId>> {
+            Ok(vec!["Health".to_string()])
+        }
+
+        fn capture_archetype(&self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+            Ok((!self.hp.is_empty()).then(|| ComponentArchetype {
+                component_id: component_id.clone(),
+                entity_ids: self.hp.keys().copied().collect(),
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["hp".to_string()],
+                    field_types: vec![FieldType::U32],
+                    field_data: vec![FieldArray::U32(self.hp.values().copied().collect::<Vec<_>>().into())],
+                }),
+            }))
+        }
+    }
+
+    impl ChangeTracking for World {
+        fn current_tick(&self) -> u64 {
+            self.tick
+        }
+
+        fn changes_since(&self, tick: u64) -> Result<ChangeSet> {
+            let mut changes = ChangeSet::default();
+            for (_, entity_id, added) in self.log.iter().filter(|(t, ..)| *t > tick) {
+                let health = changes.components.entry("Health".to_string()).or_default();
+                if *added {
+                    health.added.insert(*entity_id);
+                } else {
+                    health.modified.insert(*entity_id);
+                }
+            }
+            Ok(changes)
+        }
+    }
+
+    #[test]
+    fn test_deltas_between_keyframes_replay_to_the_world() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let mut recorder = ChangeRecorder::new(store, "match").with_keyframe_interval(3);
+        let mut world = World::default();
+        world.set_hp(1, 100);
+
+        let mut states = Vec::new();
+        let mut recorded = Vec::new();
+        for tick in 0..5 {
+            world.set_hp(tick % 2 + 1, 90 - tick);
+            recorded.push(recorder.record(&world).unwrap());
+            states.push(world.capture().unwrap().content_checksum().unwrap());
+        }
+        assert_eq!(recorded[0], Recorded::Keyframe("match-2".to_string()));
+        assert_eq!(recorded[3], Recorded::Delta { keyframe: "match-2".to_string(), index: 3 });
+        assert_eq!(recorded[4], Recorded::Keyframe("match-6".to_string()));
+        assert_eq!(recorder.current_keyframe(), Some("match-6"));
+
+        let store = recorder.store();
+        let reader = SnapshotReader::new();
+        let chain = store.load_delta_log("match-2", &reader).unwrap();
+        assert_eq!(chain.len(), 3);
+        for (n, state) in states[..4].iter().enumerate() {
+            assert_eq!(&chain.materialize(n).unwrap().content_checksum().unwrap(), state);
+        }
+        assert!(store.load_delta_log("match-6", &reader).unwrap().is_empty());
+
+        // Half a delta at the end of the log is left out.
+        let log = temp_dir.path().join("match-2.deltas");
+        let mut bytes = std::fs::read(&log).unwrap();
+        bytes.extend_from_slice(&64u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 10]);
+        std::fs::write(&log, &bytes).unwrap();
+        assert_eq!(store.load_delta_log("match-2", &reader).unwrap().len(), 3);
+
+        store.delete("match-2").unwrap();
+        assert!(!log.exists());
+    }
+
+    #[test]
+    fn test_failed_append_starts_a_new_keyframe() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let mut recorder = ChangeRecorder::new(store, "match");
+        let mut world = World::default();
+        world.set_hp(1, 100);
+        assert_eq!(recorder.record(&world).unwrap(), Recorded::Keyframe("match-1".to_string()));
+
+        // A directory where the log belongs makes the append fail.
+        let log = temp_dir.path().join("match-1.deltas");
+        std::fs::create_dir(&log).unwrap();
+        world.set_hp(1, 90);
+        assert!(recorder.record(&world).is_err());
+        assert_eq!(recorder.current_keyframe(), None);
+
+        std::fs::remove_dir(&log).unwrap();
+        world.set_hp(1, 80);
+        assert_eq!(recorder.record(&world).unwrap(), Recorded::Keyframe("match-3".to_string()));
+        let chain = recorder.store().load_delta_log("match-3", &SnapshotReader::new()).unwrap();
+        assert_eq!(chain.materialize(0).unwrap().content_checksum().unwrap(), world.capture().unwrap().content_checksum().unwrap());
+    }
+}
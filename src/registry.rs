@@ -0,0 +1,324 @@
+use crate::error::{PackError, Result, ResultExt};
+use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, PackedSnapshot, StructOfArraysData};
+use crate::packable::Packable;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tx2_link::{ComponentId, EntityId, SerializedComponent};
+
+type PackFn = dyn Fn(&[&[u8]]) -> Result<Vec<FieldArray>> + Send + Sync;
+type UnpackFn = dyn Fn(&[&FieldArray], usize) -> Result<Vec<Vec<u8>>> + Send + Sync;
+
+// How one component's serialized payloads map onto columns. The columns
+// passed to unpack are in field order and already checked against it.
+#[derive(Clone)]
+pub struct ComponentSchema {
+    pub component_id: ComponentId,
+    pub fields: Vec<(String, FieldType)>,
+    pack: Arc<PackFn>,
+    unpack: Arc<UnpackFn>,
+}
+
+impl ComponentSchema {
+    fn pack(&self, entity_ids: Vec<EntityId>, payloads: &[&[u8]]) -> Result<ComponentArchetype> {
+        let field_data = (self.pack)(payloads)?;
+        let archetype = ComponentArchetype {
+            component_id: self.component_id.clone(),
+            entity_ids,
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: self.fields.iter().map(|(name, _)| name.clone()).collect(),
+                field_types: self.fields.iter().map(|(_, field_type)| *field_type).collect(),
+                field_data,
+            }),
+        };
+        archetype.check_consistency()?;
+        Ok(archetype)
+    }
+
+    fn unpack(&self, archetype: &ComponentArchetype) -> Result<Vec<Vec<u8>>> {
+        let soa = match &archetype.data {
+            ComponentData::StructOfArrays(soa) => soa,
+            ComponentData::Blob(_) => {
+                return Err(PackError::InvalidFormat(format!("Component {} is stored as a blob", self.component_id)));
+            }
+        };
+
+        let mut columns = Vec::with_capacity(self.fields.len());
+        for (name, field_type) in &self.fields {
+            let index = soa.field_names.iter().position(|n| n == name).ok_or_else(|| {
+                PackError::InvalidFormat(format!("Component {} has no field '{}'", self.component_id, name))
+            })?;
+            if soa.field_data[index].field_type() != *field_type {
+                return Err(PackError::InvalidFormat(format!(
+                    "Field {}.{} is {:?}, expected {:?}",
+                    self.component_id,
+                    name,
+                    soa.field_data[index].field_type(),
+                    field_type
+                )));
+            }
+            columns.push(&soa.field_data[index]);
+        }
+
+        let payloads = (self.unpack)(&columns, archetype.entity_ids.len())?;
+        if payloads.len() != archetype.entity_ids.len() {
+            return Err(PackError::InvalidFormat(format!(
+                "Component {} unpacked {} rows for {} entities",
+                self.component_id,
+                payloads.len(),
+                archetype.entity_ids.len()
+            )));
+        }
+        Ok(payloads)
+    }
+}
+
+// Maps component ids to their column layout so capture, restore and the
+// reader share one definition instead of each consumer mapping columns by
+// hand. Components that are not registered stay blobs of serialized
+// components, so nothing is dropped.
+#[derive(Clone, Default)]
+pub struct ComponentRegistry {
+    schemas: HashMap<ComponentId, ComponentSchema>,
+    entity_fields: HashMap<ComponentId, Vec<String>>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The component's world payload must be the bincode encoding of T.
+    pub fn register<T>(&mut self) -> &mut Self
+    where
+        T: Packable + Serialize + DeserializeOwned + 'static,
+    {
+        let fields = T::schema().into_iter().map(|(name, field_type)| (name.to_string(), field_type)).collect();
+        self.register_with(
+            T::COMPONENT_ID,
+            fields,
+            |payloads| {
+                let rows = payloads.iter().map(|data| bincode::deserialize::<T>(data)).collect::<bincode::Result<Vec<_>>>()?;
+                Ok(T::pack_columns(&rows.iter().collect::<Vec<_>>()))
+            },
+            |columns, len| {
+                let rows = T::unpack_columns(columns, len).ok_or_else(|| {
+                    PackError::InvalidFormat(format!("Component {} has ragged columns", T::COMPONENT_ID))
+                })?;
+                Ok(rows.iter().map(bincode::serialize).collect::<bincode::Result<Vec<_>>>()?)
+            },
+        )
+    }
+
+    pub fn register_with<P, U>(
+        &mut self,
+        component_id: impl Into<ComponentId>,
+        fields: Vec<(String, FieldType)>,
+        pack: P,
+        unpack: U,
+    ) -> &mut Self
+    where
+        P: Fn(&[&[u8]]) -> Result<Vec<FieldArray>> + Send + Sync + 'static,
+        U: Fn(&[&FieldArray], usize) -> Result<Vec<Vec<u8>>> + Send + Sync + 'static,
+    {
+        let component_id = component_id.into();
+        self.schemas.insert(
+            component_id.clone(),
+            ComponentSchema {
+                component_id,
+                fields,
+                pack: Arc::new(pack),
+                unpack: Arc::new(unpack),
+            },
+        );
+        self
+    }
+
+    pub fn with<T>(mut self) -> Self
+    where
+        T: Packable + Serialize + DeserializeOwned + 'static,
+    {
+        self.register::<T>();
+        self
+    }
+
+    // Marks a column as holding entity ids, so restores that remap entities
+    // rewrite the references too. Blob payloads are opaque and left as is.
+    pub fn register_entity_field(&mut self, component_id: impl Into<ComponentId>, field: impl Into<String>) -> &mut Self {
+        let fields = self.entity_fields.entry(component_id.into()).or_default();
+        let field = field.into();
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+        self
+    }
+
+    pub fn entity_fields(&self, component_id: &ComponentId) -> &[String] {
+        self.entity_fields.get(component_id).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn schema(&self, component_id: &ComponentId) -> Option<&ComponentSchema> {
+        self.schemas.get(component_id)
+    }
+
+    pub fn component_ids(&self) -> Vec<&ComponentId> {
+        let mut ids: Vec<_> = self.schemas.keys().collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn is_registered(&self, component_id: &ComponentId) -> bool {
+        self.schemas.contains_key(component_id)
+    }
+
+    pub fn pack_components(
+        &self,
+        component_id: &ComponentId,
+        entity_ids: Vec<EntityId>,
+        components: &[&SerializedComponent],
+    ) -> Result<ComponentArchetype> {
+        match self.schemas.get(component_id) {
+            Some(schema) => {
+                let payloads: Vec<&[u8]> = components.iter().map(|c| c.data.as_slice()).collect();
+                schema.pack(entity_ids, &payloads).with_component(component_id)
+            }
+            None => Ok(ComponentArchetype {
+                component_id: component_id.clone(),
+                entity_ids,
+                data: ComponentData::Blob(bincode::serialize(components)?),
+            }),
+        }
+    }
+
+    pub fn world_components(&self, archetype: &ComponentArchetype) -> Result<Vec<SerializedComponent>> {
+        match (&archetype.data, self.schemas.get(&archetype.component_id)) {
+            (ComponentData::Blob(_), _) => archetype.world_components(),
+            (ComponentData::StructOfArrays(_), Some(schema)) => Ok(schema
+                .unpack(archetype)
+                .with_component(&archetype.component_id)?
+                .into_iter()
+                .map(|data| SerializedComponent {
+                    id: archetype.component_id.clone(),
+                    data,
+                })
+                .collect()),
+            (ComponentData::StructOfArrays(_), None) => Err(PackError::InvalidFormat(format!(
+                "{} is stored as columns but has no registered schema",
+                archetype.component_id
+            ))),
+        }
+    }
+
+    // Turns a blob of serialized components into columns when the component
+    // is registered; anything else is returned untouched.
+    pub fn interpret(&self, archetype: ComponentArchetype) -> Result<ComponentArchetype> {
+        let schema = match (&archetype.data, self.schemas.get(&archetype.component_id)) {
+            (ComponentData::Blob(_), Some(schema)) => schema,
+            _ => return Ok(archetype),
+        };
+
+        let interpret = || {
+            let components = archetype.world_components()?;
+            if components.len() != archetype.entity_ids.len() {
+                return Err(PackError::InvalidFormat(format!(
+                    "Blob holds {} components for {} entities",
+                    components.len(),
+                    archetype.entity_ids.len()
+                )));
+            }
+            let payloads: Vec<&[u8]> = components.iter().map(|c| c.data.as_slice()).collect();
+            schema.pack(archetype.entity_ids.clone(), &payloads)
+        };
+        interpret().with_component(&archetype.component_id)
+    }
+
+    pub fn interpret_snapshot(&self, snapshot: &mut PackedSnapshot) -> Result<()> {
+        let archetypes = std::mem::take(&mut snapshot.archetypes);
+        snapshot.archetypes = archetypes.into_iter().map(|a| self.interpret(a)).collect::<Result<_>>()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::PackFormat;
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+
+    fn component(hp: u32) -> SerializedComponent {
+        SerializedComponent {
+            id: "Health".to_string(),
+            data: hp.to_le_bytes().to_vec(),
+        }
+    }
+
+    // Payloads here are raw little-endian u32s rather than bincode, which is
+    // what closures are for.
+    fn registry() -> ComponentRegistry {
+        let mut registry = ComponentRegistry::new();
+        registry.register_with(
+            "Health",
+            vec![("hp".to_string(), FieldType::U32)],
+            |payloads| {
+                let hp = payloads
+                    .iter()
+                    .map(|data| {
+                        let bytes: [u8; 4] =
+                            (*data).try_into().map_err(|_| PackError::Deserialization("hp is not 4 bytes".into()))?;
+                        Ok(u32::from_le_bytes(bytes))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(vec![FieldArray::U32(hp.into())])
+            },
+            |columns, _| match columns {
+                [FieldArray::U32(hp)] => Ok(hp.iter().map(|hp| hp.to_le_bytes().to_vec()).collect()),
+                _ => Err(PackError::InvalidFormat("hp is not a u32 column".into())),
+            },
+        );
+        registry
+    }
+
+    #[test]
+    fn test_closures_pack_and_unpack_components() {
+        let registry = registry();
+        let components = [component(10), component(20)];
+        let refs: Vec<_> = components.iter().collect();
+
+        let archetype = registry.pack_components(&"Health".to_string(), vec![1, 2], &refs).unwrap();
+        assert_eq!(archetype.row_values(1).unwrap(), vec![("hp".to_string(), crate::FieldValue::U32(20))]);
+
+        let restored = registry.world_components(&archetype).unwrap();
+        assert_eq!(restored.iter().map(|c| c.data.clone()).collect::<Vec<_>>(), vec![
+            10u32.to_le_bytes().to_vec(),
+            20u32.to_le_bytes().to_vec()
+        ]);
+
+        let bad = [SerializedComponent { id: "Health".to_string(), data: vec![1] }];
+        let err = registry.pack_components(&"Health".to_string(), vec![3], &bad.iter().collect::<Vec<_>>()).unwrap_err();
+        assert_eq!(err.context().and_then(|c| c.component_id.as_deref()), Some("Health"));
+    }
+
+    #[test]
+    fn test_reader_interprets_registered_blobs() {
+        let components = [component(5), component(6)];
+        let refs: Vec<_> = components.iter().collect();
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(ComponentRegistry::new().pack_components(&"Health".to_string(), vec![4, 9], &refs).unwrap());
+        snapshot.rebuild_entity_index();
+        let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+
+        let plain = SnapshotReader::new().read_from_bytes(&bytes).unwrap();
+        assert!(matches!(plain.archetypes[0].data, ComponentData::Blob(_)));
+
+        let reader = SnapshotReader::new().with_registry(Arc::new(registry()));
+        let read = reader.read_from_bytes(&bytes).unwrap();
+        assert_eq!(read.archetypes[0].entity_ids, vec![4, 9]);
+        assert_eq!(read.archetypes[0].row_values(0).unwrap()[0].1, crate::FieldValue::U32(5));
+
+        let lazy = reader.read_lazy_from_bytes(&bytes).unwrap();
+        let archetype = lazy.archetype(&"Health".to_string()).unwrap().unwrap();
+        assert!(matches!(archetype.data, ComponentData::StructOfArrays(_)));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::error::{PackError, Result};
+use crate::format::{ComponentData, FieldArray, PackedSnapshot};
+use crate::registry::ComponentRegistry;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tx2_link::EntityId;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntityIdMap {
+    mapping: BTreeMap<EntityId, EntityId>,
+}
+
+impl EntityIdMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, old_id: EntityId, new_id: EntityId) -> Option<EntityId> {
+        self.mapping.insert(old_id, new_id)
+    }
+
+    pub fn get(&self, old_id: EntityId) -> Option<EntityId> {
+        self.mapping.get(&old_id).copied()
+    }
+
+    pub fn map(&self, old_id: EntityId) -> EntityId {
+        self.get(old_id).unwrap_or(old_id)
+    }
+
+    pub fn contains(&self, old_id: EntityId) -> bool {
+        self.mapping.contains_key(&old_id)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.mapping.iter().all(|(old, new)| old == new)
+    }
+
+    pub fn inverse(&self) -> EntityIdMap {
+        EntityIdMap {
+            mapping: self.mapping.iter().map(|(old, new)| (*new, *old)).collect(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, EntityId)> + '_ {
+        self.mapping.iter().map(|(old, new)| (*old, *new))
+    }
+
+    pub fn len(&self) -> usize {
+        self.mapping.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mapping.is_empty()
+    }
+}
+
+impl FromIterator<(EntityId, EntityId)> for EntityIdMap {
+    fn from_iter<I: IntoIterator<Item = (EntityId, EntityId)>>(iter: I) -> Self {
+        Self {
+            mapping: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl PackedSnapshot {
+    // Rewrites entity ids everywhere they appear: archetype rows, metadata and
+    // the columns the registry marks as entity references. Ids the map does
+    // not cover are kept, so references to entities outside the snapshot
+    // still point where they did.
+    pub fn remap_entities(&mut self, map: &EntityIdMap, registry: &ComponentRegistry) -> Result<()> {
+        if map.is_identity() {
+            return Ok(());
+        }
+
+        for archetype in &mut self.archetypes {
+            for entity_id in &mut archetype.entity_ids {
+                *entity_id = map.map(*entity_id);
+            }
+
+            let fields = registry.entity_fields(&archetype.component_id);
+            let soa = match &mut archetype.data {
+                ComponentData::StructOfArrays(soa) => soa,
+                ComponentData::Blob(_) => continue,
+            };
+            for (name, column) in soa.field_names.iter().zip(&mut soa.field_data) {
+                if !fields.contains(name) {
+                    continue;
+                }
+                match column {
+                    FieldArray::U32(ids) => ids.iter_mut().for_each(|id| *id = map.map(*id)),
+                    FieldArray::U64(ids) => ids.iter_mut().for_each(|id| {
+                        if let Ok(old) = EntityId::try_from(*id) {
+                            *id = map.map(old) as u64;
+                        }
+                    }),
+                    other => {
+                        return Err(PackError::InvalidFormat(format!(
+                            "Entity field {}.{} is {:?}, not an id column",
+                            archetype.component_id,
+                            name,
+                            other.field_type()
+                        )));
+                    }
+                }
+            }
+        }
+
+        self.entity_metadata = std::mem::take(&mut self.entity_metadata)
+            .into_iter()
+            .map(|(entity_id, metadata)| (map.map(entity_id), metadata))
+            .collect();
+        self.rebuild_entity_index();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::WorldRestore;
+    use crate::format::{ComponentArchetype, EntityMetadata, FieldType, StructOfArraysData};
+
+    fn archetype(component_id: &str, rows: &[(EntityId, EntityId)]) -> ComponentArchetype {
+        ComponentArchetype {
+            component_id: component_id.to_string(),
+            entity_ids: rows.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["target".to_string()],
+                field_types: vec![FieldType::U32],
+                field_data: vec![FieldArray::U32(rows.iter().map(|(_, t)| *t).collect::<Vec<_>>().into())],
+            }),
+        }
+    }
+
+    // A live world whose ids 1 and 2 are taken; new entities get the next free id.
+    struct World {
+        next: EntityId,
+        restored: Vec<ComponentArchetype>,
+    }
+
+    impl WorldRestore for World {
+        fn allocate_entity(&mut self, _old_id: EntityId) -> Result<EntityId> {
+            self.next += 1;
+            Ok(self.next)
+        }
+
+        fn restore_archetype(&mut self, archetype: &ComponentArchetype) -> Result<()> {
+            self.restored.push(archetype.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_restore_remaps_ids_and_entity_fields() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(archetype("Follows", &[(1, 2), (2, 99)]));
+        snapshot.archetypes.push(archetype("Score", &[(1, 5)]));
+        snapshot.entity_metadata.insert(2, EntityMetadata::new());
+
+        let mut registry = ComponentRegistry::new();
+        registry.register_entity_field("Follows", "target");
+        let mut world = World { next: 2, restored: Vec::new() };
+
+        let map = world.restore_remapped(&snapshot, &registry).unwrap();
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(1, 3), (2, 4)]);
+
+        let follows = &world.restored[0];
+        assert_eq!(follows.entity_ids, vec![3, 4]);
+        // 99 is not part of the snapshot, so the reference is left alone.
+        assert_eq!(follows.row_values(0).unwrap()[0].1, crate::FieldValue::U32(4));
+        assert_eq!(follows.row_values(1).unwrap()[0].1, crate::FieldValue::U32(99));
+        // Score's column is not an entity field, so its values are untouched.
+        assert_eq!(world.restored[1].row_values(0).unwrap()[0].1, crate::FieldValue::U32(5));
+
+        registry.register_entity_field("Score", "missing");
+        let mut remapped = snapshot.clone();
+        remapped.remap_entities(&map, &registry).unwrap();
+        assert!(remapped.entity_metadata.contains_key(&4));
+
+        let mut bad = ComponentRegistry::new();
+        bad.register_entity_field("Follows", "target");
+        if let ComponentData::StructOfArrays(soa) = &mut snapshot.archetypes[0].data {
+            soa.field_data[0] = FieldArray::String(vec!["a".to_string(), "b".to_string()].into());
+            soa.field_types[0] = FieldType::String;
+        }
+        assert!(snapshot.remap_entities(&map, &bad).is_err());
+    }
+}
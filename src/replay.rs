@@ -1,6 +1,7 @@
 use crate::error::{PackError, Result};
 use crate::format::PackedSnapshot;
 use crate::checkpoint::{Checkpoint, CheckpointManager};
+use crate::storage::{SnapshotReader, SnapshotStore, SnapshotWriter};
 use std::collections::VecDeque;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -9,18 +10,32 @@ pub enum ReplayDirection {
     Backward,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeekMode {
+    #[default]
+    Nearest,
+    Floor,
+    Ceil,
+    Exact,
+}
+
 pub struct ReplayEngine {
     checkpoints: VecDeque<Checkpoint>,
+    // Checkpoint indices ordered by world_time, ties in the order added.
+    by_time: Vec<usize>,
     current_index: usize,
     loop_replay: bool,
+    loop_region: Option<(usize, usize)>,
 }
 
 impl ReplayEngine {
     pub fn new() -> Self {
         Self {
             checkpoints: VecDeque::new(),
+            by_time: Vec::new(),
             current_index: 0,
             loop_replay: false,
+            loop_region: None,
         }
     }
 
@@ -29,17 +44,46 @@ impl ReplayEngine {
         self
     }
 
+    pub fn set_loop_region(&mut self, start: usize, end: usize) -> Result<()> {
+        if start > end || end >= self.checkpoints.len() {
+            return Err(PackError::InvalidCheckpoint(
+                format!("Loop region {}..={} out of bounds", start, end)
+            ));
+        }
+
+        self.loop_region = Some((start, end));
+
+        if self.current_index < start || self.current_index > end {
+            self.current_index = start;
+        }
+
+        Ok(())
+    }
+
+    pub fn clear_loop_region(&mut self) {
+        self.loop_region = None;
+    }
+
+    pub fn loop_region(&self) -> Option<(usize, usize)> {
+        self.loop_region
+    }
+
     pub fn add_checkpoint(&mut self, checkpoint: Checkpoint) {
+        let time = checkpoint.metadata.world_time;
+        let position = self.by_time.partition_point(|&i| {
+            self.checkpoints[i].metadata.world_time.total_cmp(&time).is_le()
+        });
+        self.by_time.insert(position, self.checkpoints.len());
         self.checkpoints.push_back(checkpoint);
     }
 
     pub fn load_from_manager(&mut self, manager: &mut CheckpointManager) -> Result<()> {
-        self.checkpoints.clear();
+        self.clear();
 
         let chain = manager.get_checkpoint_chain().to_vec();
         for id in chain {
             let checkpoint = manager.load_checkpoint(&id)?;
-            self.checkpoints.push_back(checkpoint);
+            self.add_checkpoint(checkpoint);
         }
 
         self.current_index = 0;
@@ -47,11 +91,68 @@ impl ReplayEngine {
         Ok(())
     }
 
+    pub fn load_from_store(
+        &mut self,
+        store: &SnapshotStore,
+        ids: &[String],
+        reader: &SnapshotReader,
+    ) -> Result<()> {
+        self.clear();
+
+        for id in ids {
+            let (snapshot, metadata) = store.load(id, reader)?;
+            self.add_checkpoint(Checkpoint::new(id.clone(), snapshot).with_metadata(metadata));
+        }
+
+        self.current_index = 0;
+
+        Ok(())
+    }
+
+    pub fn export_range(
+        &self,
+        start: usize,
+        end: usize,
+        store: &SnapshotStore,
+        writer: &SnapshotWriter,
+    ) -> Result<Vec<String>> {
+        if start > end || end >= self.checkpoints.len() {
+            return Err(PackError::InvalidCheckpoint(
+                format!("Range {}..={} out of bounds", start, end)
+            ));
+        }
+
+        let mut exported = Vec::with_capacity(end - start + 1);
+
+        // Saved under their metadata ids, replacing whatever the store has
+        // there: the checksum names the version the checkpoint was loaded
+        // from, which says nothing about the target store.
+        for checkpoint in self.checkpoints.range(start..=end) {
+            let mut metadata = checkpoint.metadata.clone();
+            metadata.checksum = None;
+            store.save_versioned(&checkpoint.snapshot, &mut metadata, writer)?;
+            exported.push(metadata.id);
+        }
+
+        Ok(exported)
+    }
+
     pub fn current(&self) -> Option<&Checkpoint> {
         self.checkpoints.get(self.current_index)
     }
 
+    pub fn checkpoints(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.checkpoints.iter()
+    }
+
     pub fn next(&mut self) -> Option<&Checkpoint> {
+        if let Some((start, end)) = self.loop_region {
+            if self.current_index == end {
+                self.current_index = start;
+                return self.current();
+            }
+        }
+
         if self.current_index + 1 < self.checkpoints.len() {
             self.current_index += 1;
             self.current()
@@ -64,6 +165,13 @@ impl ReplayEngine {
     }
 
     pub fn previous(&mut self) -> Option<&Checkpoint> {
+        if let Some((start, end)) = self.loop_region {
+            if self.current_index == start {
+                self.current_index = end;
+                return self.current();
+            }
+        }
+
         if self.current_index > 0 {
             self.current_index -= 1;
             self.current()
@@ -87,6 +195,24 @@ impl ReplayEngine {
             .ok_or_else(|| PackError::InvalidCheckpoint("No checkpoint at index".to_string()))
     }
 
+    pub fn seek_to_time(&mut self, time: f64) -> Option<&Checkpoint> {
+        self.seek_to_time_with_mode(time, SeekMode::Nearest).ok()
+    }
+
+    // Checkpoints keep the order they were added in, which need not follow
+    // world_time, so the search runs over by_time. Ties go to the checkpoint
+    // added first.
+    pub fn seek_to_time_with_mode(&mut self, time: f64, mode: SeekMode) -> Result<&Checkpoint> {
+        let time_of = |i: usize| self.checkpoints[self.by_time[i]].metadata.world_time;
+        let index = find_time_index(self.by_time.len(), time_of, time, mode)
+            .map(|i| self.by_time[i])
+            .ok_or_else(|| PackError::InvalidCheckpoint(
+                format!("No checkpoint at time {} ({:?})", time, mode)
+            ))?;
+
+        self.seek(index)
+    }
+
     pub fn seek_to_start(&mut self) -> Option<&Checkpoint> {
         self.current_index = 0;
         self.current()
@@ -121,7 +247,9 @@ impl ReplayEngine {
 
     pub fn clear(&mut self) {
         self.checkpoints.clear();
+        self.by_time.clear();
         self.current_index = 0;
+        self.loop_region = None;
     }
 }
 
@@ -156,11 +284,22 @@ impl TimeTravel {
         Some(&self.snapshots[index].1)
     }
 
+    pub fn seek_to_time_with_mode(&mut self, target_time: f64, mode: SeekMode) -> Result<&PackedSnapshot> {
+        let index = self.find_snapshot_with_mode(target_time, mode)?;
+        self.current_time = self.snapshots[index].0;
+        Ok(&self.snapshots[index].1)
+    }
+
     pub fn get_snapshot_at_time(&self, time: f64) -> Option<&PackedSnapshot> {
         let index = self.find_snapshot_at_time(time)?;
         Some(&self.snapshots[index].1)
     }
 
+    pub fn get_snapshot_at_time_with_mode(&self, time: f64, mode: SeekMode) -> Result<&PackedSnapshot> {
+        let index = self.find_snapshot_with_mode(time, mode)?;
+        Ok(&self.snapshots[index].1)
+    }
+
     pub fn get_current_snapshot(&self) -> Option<&PackedSnapshot> {
         self.get_snapshot_at_time(self.current_time)
     }
@@ -181,6 +320,39 @@ impl TimeTravel {
         self.get_snapshot_at_time(time).cloned()
     }
 
+    pub fn extract_clip(&self, start_time: f64, end_time: f64) -> TimeTravel {
+        let mut clip = TimeTravel::new();
+
+        if start_time > end_time {
+            return clip;
+        }
+
+        // A clip starting between two snapshots begins with the state in effect at
+        // start_time, re-based onto the clip's first frame.
+        let floor = find_time_index(
+            self.snapshots.len(),
+            |i| self.snapshots[i].0,
+            start_time,
+            SeekMode::Floor,
+        );
+
+        if let Some(index) = floor {
+            if self.snapshots[index].0 < start_time {
+                clip.snapshots.push((start_time, self.snapshots[index].1.clone()));
+            }
+        }
+
+        clip.snapshots.extend(
+            self.snapshots
+                .iter()
+                .filter(|(t, _)| *t >= start_time && *t <= end_time)
+                .cloned()
+        );
+
+        clip.current_time = clip.get_earliest_time().unwrap_or(0.0);
+        clip
+    }
+
     pub fn prune_before(&mut self, time: f64) {
         self.snapshots.retain(|(t, _)| *t >= time);
     }
@@ -202,33 +374,64 @@ impl TimeTravel {
         self.snapshots.is_empty()
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (f64, &PackedSnapshot)> {
+        self.snapshots.iter().map(|(t, snapshot)| (*t, snapshot))
+    }
+
     fn find_snapshot_at_time(&self, target_time: f64) -> Option<usize> {
-        if self.snapshots.is_empty() {
-            return None;
+        find_time_index(self.snapshots.len(), |i| self.snapshots[i].0, target_time, SeekMode::Nearest)
+    }
+
+    fn find_snapshot_with_mode(&self, target_time: f64, mode: SeekMode) -> Result<usize> {
+        find_time_index(self.snapshots.len(), |i| self.snapshots[i].0, target_time, mode)
+            .ok_or_else(|| PackError::SnapshotNotFound(
+                format!("No snapshot at time {} ({:?})", target_time, mode)
+            ))
+    }
+}
+
+fn find_time_index<F>(len: usize, time_at: F, target_time: f64, mode: SeekMode) -> Option<usize>
+where
+    F: Fn(usize) -> f64,
+{
+    if len == 0 {
+        return None;
+    }
+
+    let mut left = 0;
+    let mut right = len;
+
+    while left < right {
+        let mid = (left + right) / 2;
+        if time_at(mid) < target_time {
+            left = mid + 1;
+        } else {
+            right = mid;
         }
+    }
 
-        let mut left = 0;
-        let mut right = self.snapshots.len();
+    let exact = left < len && time_at(left) == target_time;
 
-        while left < right {
-            let mid = (left + right) / 2;
-            if self.snapshots[mid].0 < target_time {
-                left = mid + 1;
+    match mode {
+        SeekMode::Exact => exact.then_some(left),
+        SeekMode::Ceil => (left < len).then_some(left),
+        SeekMode::Floor => {
+            if exact {
+                Some(left)
+            } else if left > 0 {
+                Some(left - 1)
             } else {
-                right = mid;
+                None
             }
         }
-
-        if left > 0 && (left >= self.snapshots.len() ||
-           (self.snapshots[left].0 - target_time).abs() >
-           (target_time - self.snapshots[left - 1].0).abs()) {
-            Some(left - 1)
-        } else if left < self.snapshots.len() {
-            Some(left)
-        } else if left > 0 {
-            Some(left - 1)
-        } else {
-            None
+        SeekMode::Nearest => {
+            if left > 0 && (left >= len ||
+               (time_at(left) - target_time).abs() >
+               (target_time - time_at(left - 1)).abs()) {
+                Some(left - 1)
+            } else {
+                Some(left)
+            }
         }
     }
 }
@@ -292,6 +495,37 @@ mod tests {
         assert_eq!(engine.get_index(), 2);
     }
 
+    #[test]
+    fn test_replay_loop_region() {
+        let mut engine = ReplayEngine::new();
+
+        for i in 0..6 {
+            let checkpoint = Checkpoint::new(format!("cp{}", i), PackedSnapshot::new());
+            engine.add_checkpoint(checkpoint);
+        }
+
+        assert!(engine.set_loop_region(3, 6).is_err());
+        assert!(engine.set_loop_region(4, 2).is_err());
+
+        engine.set_loop_region(2, 4).unwrap();
+        assert_eq!(engine.get_index(), 2);
+
+        engine.next();
+        engine.next();
+        assert_eq!(engine.get_index(), 4);
+
+        engine.next();
+        assert_eq!(engine.get_index(), 2);
+
+        engine.previous();
+        assert_eq!(engine.get_index(), 4);
+
+        engine.clear_loop_region();
+        engine.next();
+        assert_eq!(engine.get_index(), 5);
+        assert!(engine.next().is_none());
+    }
+
     #[test]
     fn test_time_travel() {
         let mut tt = TimeTravel::new();
@@ -318,6 +552,119 @@ mod tests {
         assert_eq!(tt.get_latest_time(), Some(70.0));
     }
 
+    #[test]
+    fn test_time_travel_seek_modes() {
+        let mut tt = TimeTravel::new();
+
+        for i in 0..5 {
+            tt.record(i as f64 * 10.0, PackedSnapshot::new());
+        }
+
+        tt.seek_to_time_with_mode(26.0, SeekMode::Nearest).unwrap();
+        assert_eq!(tt.get_current_time(), 30.0);
+
+        tt.seek_to_time_with_mode(26.0, SeekMode::Floor).unwrap();
+        assert_eq!(tt.get_current_time(), 20.0);
+
+        tt.seek_to_time_with_mode(21.0, SeekMode::Ceil).unwrap();
+        assert_eq!(tt.get_current_time(), 30.0);
+
+        tt.seek_to_time_with_mode(40.0, SeekMode::Exact).unwrap();
+        assert_eq!(tt.get_current_time(), 40.0);
+
+        assert!(tt.seek_to_time_with_mode(41.0, SeekMode::Exact).is_err());
+        assert!(tt.seek_to_time_with_mode(-1.0, SeekMode::Floor).is_err());
+        assert!(tt.seek_to_time_with_mode(41.0, SeekMode::Ceil).is_err());
+        assert_eq!(tt.get_current_time(), 40.0);
+    }
+
+    #[test]
+    fn test_replay_seek_to_time() {
+        let mut engine = ReplayEngine::new();
+
+        for i in 0..4 {
+            let mut metadata = crate::metadata::SnapshotMetadata::new(format!("cp{}", i));
+            metadata.world_time = i as f64 * 5.0;
+            let checkpoint = Checkpoint::new(format!("cp{}", i), PackedSnapshot::new())
+                .with_metadata(metadata);
+            engine.add_checkpoint(checkpoint);
+        }
+
+        assert_eq!(engine.seek_to_time_with_mode(7.0, SeekMode::Floor).unwrap().id, "cp1");
+        assert_eq!(engine.seek_to_time_with_mode(7.0, SeekMode::Ceil).unwrap().id, "cp2");
+        assert_eq!(engine.seek_to_time(6.0).unwrap().id, "cp1");
+        assert!(engine.seek_to_time_with_mode(7.0, SeekMode::Exact).is_err());
+        assert_eq!(engine.get_index(), 1);
+
+        // Added out of time order: seeking still finds them by time.
+        let mut metadata = crate::metadata::SnapshotMetadata::new("early".to_string());
+        metadata.world_time = 2.0;
+        engine.add_checkpoint(Checkpoint::new("early".to_string(), PackedSnapshot::new()).with_metadata(metadata));
+        assert_eq!(engine.seek_to_time_with_mode(3.0, SeekMode::Floor).unwrap().id, "early");
+        assert_eq!(engine.get_index(), 4);
+        assert_eq!(engine.seek_to_time_with_mode(12.0, SeekMode::Floor).unwrap().id, "cp2");
+
+        // A later checkpoint at the same time loses the tie.
+        let mut metadata = crate::metadata::SnapshotMetadata::new("again".to_string());
+        metadata.world_time = 5.0;
+        engine.add_checkpoint(Checkpoint::new("again".to_string(), PackedSnapshot::new()).with_metadata(metadata));
+        assert_eq!(engine.seek_to_time_with_mode(5.0, SeekMode::Exact).unwrap().id, "cp1");
+
+        engine.clear();
+        assert!(engine.seek_to_time(5.0).is_none());
+    }
+
+    #[test]
+    fn test_time_travel_extract_clip() {
+        let mut tt = TimeTravel::new();
+
+        for i in 0..10 {
+            tt.record(i as f64 * 10.0, PackedSnapshot::new());
+        }
+
+        let clip = tt.extract_clip(25.0, 60.0);
+        assert_eq!(clip.len(), 5);
+        assert_eq!(clip.get_earliest_time(), Some(25.0));
+        assert_eq!(clip.get_latest_time(), Some(60.0));
+        assert_eq!(clip.get_current_time(), 25.0);
+
+        let exact = tt.extract_clip(30.0, 40.0);
+        assert_eq!(exact.len(), 2);
+
+        assert!(tt.extract_clip(60.0, 30.0).is_empty());
+    }
+
+    #[test]
+    fn test_replay_export_range() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let mut engine = ReplayEngine::new();
+
+        for i in 0..6 {
+            engine.add_checkpoint(Checkpoint::new(format!("cp{}", i), PackedSnapshot::new()));
+        }
+
+        let ids = engine.export_range(2, 4, &store, &SnapshotWriter::new()).unwrap();
+        assert_eq!(ids, vec!["cp2", "cp3", "cp4"]);
+        assert!(engine.export_range(4, 6, &store, &SnapshotWriter::new()).is_err());
+
+        let mut clip = ReplayEngine::new();
+        clip.load_from_store(&store, &ids, &SnapshotReader::new()).unwrap();
+        assert_eq!(clip.len(), 3);
+        assert_eq!(clip.current().unwrap().id, "cp2");
+
+        // Loaded checkpoints carry a checksum; exporting them again, or under
+        // a renamed metadata id, still works and reports the stored ids.
+        let renamed = Checkpoint::new("cp5".to_string(), PackedSnapshot::new())
+            .with_metadata(crate::metadata::SnapshotMetadata::new("finale".to_string()));
+        clip.add_checkpoint(renamed);
+        let rewritten = crate::metadata::SnapshotMetadata::new("cp3".to_string());
+        store.save(&PackedSnapshot::new(), &rewritten, &SnapshotWriter::new()).unwrap();
+        let ids = clip.export_range(0, 3, &store, &SnapshotWriter::new()).unwrap();
+        assert_eq!(ids, vec!["cp2", "cp3", "cp4", "finale"]);
+        assert!(store.exists("finale"));
+    }
+
     #[test]
     fn test_time_travel_fork() {
         let mut tt = TimeTravel::new();
@@ -330,4 +677,40 @@ mod tests {
         let forked = tt.fork_at_time(20.0);
         assert!(forked.is_some());
     }
+
+    #[test]
+    fn test_time_travel_fork_shares_columns() {
+        use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, FieldValue, StructOfArraysData};
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Health".to_string(),
+            entity_ids: vec![1, 2],
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["hp".to_string()],
+                field_types: vec![FieldType::U32],
+                field_data: vec![FieldArray::U32(vec![100, 80].into())],
+            }),
+        });
+
+        let mut tt = TimeTravel::new();
+        tt.record(0.0, snapshot);
+
+        let column = |snapshot: &PackedSnapshot| match &snapshot.archetypes[0].data {
+            ComponentData::StructOfArrays(soa) => match &soa.field_data[0] {
+                FieldArray::U32(values) => values.clone(),
+                _ => unreachable!(),
+            },
+            ComponentData::Blob(_) => unreachable!(),
+        };
+
+        let mut forked = tt.fork_at_time(0.0).unwrap();
+        assert!(column(&forked).ptr_eq(&column(tt.get_snapshot_at_time(0.0).unwrap())));
+
+        if let ComponentData::StructOfArrays(soa) = &mut forked.archetypes[0].data {
+            soa.field_data[0].set(0, FieldValue::U32(1)).unwrap();
+        }
+        assert_eq!(column(&forked).as_slice(), [1, 80]);
+        assert_eq!(column(tt.get_snapshot_at_time(0.0).unwrap()).as_slice(), [100, 80]);
+    }
 }
@@ -0,0 +1,298 @@
+use crate::delta::DeltaSnapshot;
+use crate::error::{PackError, Result};
+use crate::format::PackedSnapshot;
+use crate::storage::{SnapshotReader, SnapshotWriter};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub type ClientId = u64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ReplicationMessage {
+    Base { sequence: u64, snapshot: Vec<u8> },
+    /// Turns state `base` into state `sequence`.
+    Patch { sequence: u64, base: u64, delta: Vec<u8> },
+}
+
+impl ReplicationMessage {
+    pub fn sequence(&self) -> u64 {
+        match self {
+            ReplicationMessage::Base { sequence, .. } | ReplicationMessage::Patch { sequence, .. } => *sequence,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicationFeedback {
+    Ack { sequence: u64 },
+    Resync,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ClientState {
+    sent: Option<u64>,
+    acked: Option<u64>,
+}
+
+/// Caught-up clients share one patch per pushed state; late joiners and
+/// resyncs get a base of the current state instead.
+pub struct DeltaStream {
+    writer: SnapshotWriter,
+    sequence: u64,
+    current: Option<PackedSnapshot>,
+    base: Option<(u64, Vec<u8>)>,
+    clients: BTreeMap<ClientId, ClientState>,
+}
+
+impl DeltaStream {
+    pub fn new() -> Self {
+        Self {
+            writer: SnapshotWriter::new(),
+            sequence: 0,
+            current: None,
+            base: None,
+            clients: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_writer(mut self, writer: SnapshotWriter) -> Self {
+        self.writer = writer;
+        self
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Sent but not yet acknowledged states.
+    pub fn in_flight(&self, client: ClientId) -> Option<u64> {
+        let state = self.clients.get(&client)?;
+        Some(state.sent.unwrap_or(0).saturating_sub(state.acked.unwrap_or(0)))
+    }
+
+    /// `None` before the first push; the client then gets its base with it.
+    pub fn add_client(&mut self, client: ClientId) -> Result<Option<ReplicationMessage>> {
+        self.clients.insert(client, ClientState::default());
+        self.base_for(client)
+    }
+
+    pub fn remove_client(&mut self, client: ClientId) {
+        self.clients.remove(&client);
+    }
+
+    pub fn push(&mut self, snapshot: PackedSnapshot) -> Result<Vec<(ClientId, ReplicationMessage)>> {
+        let previous = self.sequence;
+        let patch = match &self.current {
+            Some(current) if self.clients.values().any(|state| state.sent == Some(previous)) => {
+                Some(self.writer.write_delta_to_bytes(&DeltaSnapshot::from_snapshots(current, &snapshot)?)?)
+            }
+            _ => None,
+        };
+        self.sequence += 1;
+        self.current = Some(snapshot);
+
+        let clients: Vec<ClientId> = self.clients.keys().copied().collect();
+        let mut messages = Vec::with_capacity(clients.len());
+        for client in clients {
+            let caught_up = self.clients[&client].sent == Some(previous);
+            let message = match &patch {
+                Some(delta) if caught_up => {
+                    self.mark_sent(client);
+                    Some(ReplicationMessage::Patch { sequence: self.sequence, base: previous, delta: delta.clone() })
+                }
+                _ => self.base_for(client)?,
+            };
+            messages.extend(message.map(|message| (client, message)));
+        }
+
+        Ok(messages)
+    }
+
+    pub fn handle(&mut self, client: ClientId, feedback: ReplicationFeedback) -> Result<Option<ReplicationMessage>> {
+        let state = self
+            .clients
+            .get_mut(&client)
+            .ok_or_else(|| PackError::InvalidFormat(format!("Unknown replication client {}", client)))?;
+        match feedback {
+            ReplicationFeedback::Ack { sequence } => {
+                if sequence <= state.sent.unwrap_or(0) {
+                    state.acked = state.acked.max(Some(sequence));
+                }
+                Ok(None)
+            }
+            ReplicationFeedback::Resync => self.base_for(client),
+        }
+    }
+
+    fn mark_sent(&mut self, client: ClientId) {
+        if let Some(state) = self.clients.get_mut(&client) {
+            state.sent = Some(self.sequence);
+        }
+    }
+
+    fn base_for(&mut self, client: ClientId) -> Result<Option<ReplicationMessage>> {
+        let current = match &self.current {
+            Some(current) => current,
+            None => return Ok(None),
+        };
+        if self.base.as_ref().is_none_or(|(sequence, _)| *sequence != self.sequence) {
+            self.base = Some((self.sequence, self.writer.write_to_bytes(current)?));
+        }
+        self.mark_sent(client);
+
+        let (sequence, snapshot) = self.base.clone().expect("base was just encoded");
+        Ok(Some(ReplicationMessage::Base { sequence, snapshot }))
+    }
+}
+
+impl Default for DeltaStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// After a gap it asks for one resync and ignores patches until the base
+/// arrives.
+pub struct DeltaApplier {
+    reader: SnapshotReader,
+    state: Option<PackedSnapshot>,
+    sequence: Option<u64>,
+    resyncing: bool,
+}
+
+impl DeltaApplier {
+    pub fn new() -> Self {
+        Self {
+            reader: SnapshotReader::new(),
+            state: None,
+            sequence: None,
+            resyncing: false,
+        }
+    }
+
+    pub fn with_reader(mut self, reader: SnapshotReader) -> Self {
+        self.reader = reader;
+        self
+    }
+
+    pub fn state(&self) -> Option<&PackedSnapshot> {
+        self.state.as_ref()
+    }
+
+    pub fn sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
+    pub fn is_resyncing(&self) -> bool {
+        self.resyncing
+    }
+
+    pub fn apply(&mut self, message: &ReplicationMessage) -> Result<Option<ReplicationFeedback>> {
+        // Duplicates and stragglers from before the current state.
+        if self.sequence.is_some_and(|current| message.sequence() <= current) {
+            return Ok(None);
+        }
+
+        match message {
+            ReplicationMessage::Base { sequence, snapshot } => {
+                self.state = Some(self.reader.read_from_bytes(snapshot)?);
+                self.sequence = Some(*sequence);
+                self.resyncing = false;
+            }
+            ReplicationMessage::Patch { sequence, base, delta } => {
+                let state = match &self.state {
+                    Some(state) if self.sequence == Some(*base) => state,
+                    _ if self.resyncing => return Ok(None),
+                    _ => {
+                        self.resyncing = true;
+                        return Ok(Some(ReplicationFeedback::Resync));
+                    }
+                };
+                self.state = Some(self.reader.read_delta_from_bytes(delta)?.apply(state)?);
+                self.sequence = Some(*sequence);
+            }
+        }
+
+        Ok(Some(ReplicationFeedback::Ack { sequence: message.sequence() }))
+    }
+}
+
+impl Default for DeltaApplier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData};
+
+    fn state(tick: u8) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Transform".to_string(),
+            entity_ids: vec![1, 2, 3],
+            data: ComponentData::Blob([[0; 24].as_slice(), &[tick; 8]].concat()),
+        });
+        snapshot
+    }
+
+    fn checksum(snapshot: Option<&PackedSnapshot>) -> [u8; 32] {
+        snapshot.unwrap().content_checksum().unwrap()
+    }
+
+    #[test]
+    fn test_clients_follow_patches_and_resync_after_a_gap() {
+        let mut stream = DeltaStream::new();
+        let mut host = DeltaApplier::new();
+        assert_eq!(stream.add_client(1).unwrap(), None);
+
+        for tick in 0..3 {
+            for (client, message) in stream.push(state(tick)).unwrap() {
+                assert_eq!(client, 1);
+                assert_eq!(matches!(message, ReplicationMessage::Patch { .. }), tick > 0);
+                let ack = host.apply(&message).unwrap().unwrap();
+                assert_eq!(stream.handle(1, ack).unwrap(), None);
+            }
+        }
+        assert_eq!(stream.in_flight(1), Some(0));
+
+        // A late joiner starts from a base of the current state, then
+        // shares the patches with everyone else.
+        let mut late = DeltaApplier::new();
+        let base = stream.add_client(2).unwrap().unwrap();
+        let ack = late.apply(&base).unwrap().unwrap();
+        assert_eq!(ack, ReplicationFeedback::Ack { sequence: 3 });
+        stream.handle(2, ack).unwrap();
+        let messages = stream.push(state(3)).unwrap();
+        assert_eq!(messages[0].1, messages[1].1);
+        host.apply(&messages[0].1).unwrap();
+        late.apply(&messages[1].1).unwrap();
+        assert_eq!(checksum(late.state()), checksum(Some(&state(3))));
+        assert_eq!(stream.in_flight(2), Some(1));
+
+        // The late joiner loses frame 5 and catches up through one resync.
+        for (client, message) in stream.push(state(4)).unwrap() {
+            if client == 1 {
+                host.apply(&message).unwrap();
+            }
+        }
+        let messages = stream.push(state(5)).unwrap();
+        host.apply(&messages[0].1).unwrap();
+        assert_eq!(late.apply(&messages[1].1).unwrap(), Some(ReplicationFeedback::Resync));
+        assert_eq!(late.apply(&messages[1].1).unwrap(), None);
+        assert!(late.is_resyncing());
+
+        let base = stream.handle(2, ReplicationFeedback::Resync).unwrap().unwrap();
+        assert!(matches!(base, ReplicationMessage::Base { sequence: 6, .. }));
+        assert_eq!(late.apply(&base).unwrap(), Some(ReplicationFeedback::Ack { sequence: 6 }));
+        assert_eq!(checksum(late.state()), checksum(host.state()));
+        assert_eq!(checksum(host.state()), checksum(Some(&state(5))));
+        assert!(matches!(stream.push(state(6)).unwrap()[1].1, ReplicationMessage::Patch { base: 6, .. }));
+    }
+}
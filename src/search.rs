@@ -0,0 +1,128 @@
+use crate::error::Result;
+use crate::metadata::SnapshotMetadata;
+use crate::catalog::write_json_atomic;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+pub const SEARCH_INDEX_FILE: &str = "search.index.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    postings: BTreeMap<String, BTreeSet<String>>,
+    documents: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_json_atomic(path.as_ref(), self)
+    }
+
+    pub fn index(&mut self, metadata: &SnapshotMetadata) {
+        self.remove(&metadata.id);
+
+        let mut tokens = BTreeSet::new();
+        for text in metadata.name.iter().chain(metadata.description.iter()).chain(metadata.tags.iter()) {
+            tokens.extend(tokenize(text));
+        }
+
+        for token in &tokens {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .insert(metadata.id.clone());
+        }
+
+        self.documents.insert(metadata.id.clone(), tokens);
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        let Some(tokens) = self.documents.remove(id) else {
+            return;
+        };
+
+        for token in tokens {
+            if let Some(ids) = self.postings.get_mut(&token) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+        }
+    }
+
+    pub fn search(&self, query: &str) -> Vec<String> {
+        let mut result: Option<BTreeSet<String>> = None;
+
+        for term in tokenize(query) {
+            let mut matches = BTreeSet::new();
+
+            for (token, ids) in &self.postings {
+                if token.contains(&term) {
+                    matches.extend(ids.iter().cloned());
+                }
+            }
+
+            result = Some(match result {
+                Some(previous) => previous.intersection(&matches).cloned().collect(),
+                None => matches,
+            });
+        }
+
+        result.unwrap_or_default().into_iter().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_index() {
+        let mut index = SearchIndex::new();
+
+        index.index(
+            &SnapshotMetadata::new("a".to_string())
+                .with_name("Desync repro".to_string())
+                .with_tag("netcode".to_string()),
+        );
+        index.index(
+            &SnapshotMetadata::new("b".to_string())
+                .with_description("Server desynced after boss fight".to_string()),
+        );
+        index.index(&SnapshotMetadata::new("c".to_string()).with_name("Boss fight".to_string()));
+
+        assert_eq!(index.search("desync"), vec!["a", "b"]);
+        assert_eq!(index.search("boss FIGHT"), vec!["b", "c"]);
+        assert_eq!(index.search("netcode desync"), vec!["a"]);
+        assert!(index.search("missing").is_empty());
+
+        index.remove("b");
+        assert_eq!(index.search("desync"), vec!["a"]);
+        assert_eq!(index.len(), 2);
+    }
+}
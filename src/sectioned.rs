@@ -0,0 +1,615 @@
+use crate::bounds;
+use crate::error::{PackError, PipelineStage, Result, ResultExt};
+use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot, SnapshotHeader, FLAG_DELTA_ENTITY_IDS};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::layout::NativeLayout;
+use crate::limits::ParseLimits;
+use crate::storage::read_at;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::sync::mpsc;
+use std::thread;
+use tx2_link::{ComponentId, EntityId};
+
+pub(crate) const TOC_LENGTH_SIZE: usize = 8;
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+const CHUNK_LENGTH_SIZE: usize = 8;
+
+const PIPELINE_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Section {
+    pub offset: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchetypeEntry {
+    pub component_id: ComponentId,
+    pub entity_count: u64,
+    pub section: Section,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TableOfContents {
+    pub archetypes: Vec<ArchetypeEntry>,
+    pub entity_metadata: Section,
+    // Added in 2.1 and left empty when a snapshot has no resources. Older
+    // tables of contents end before it, see read_toc.
+    pub resources: Section,
+    // Added in 2.2. Layouts are small enough to travel in the table itself.
+    pub layout: Option<NativeLayout>,
+}
+
+#[derive(Serialize)]
+struct DeltaArchetypeRef<'a> {
+    component_id: &'a ComponentId,
+    entity_ids: Vec<u8>,
+    data: &'a ComponentData,
+}
+
+#[derive(Deserialize)]
+struct DeltaArchetype {
+    component_id: ComponentId,
+    entity_ids: Vec<u8>,
+    data: ComponentData,
+}
+
+pub(crate) fn encode_snapshot<F>(snapshot: &PackedSnapshot, flags: u32, chunk_size: usize, seal: F) -> Result<(Vec<u8>, usize)>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>> + Sync,
+{
+    let mut toc = TableOfContents::default();
+    let mut data = Vec::new();
+    let mut raw_size = 0;
+
+    let sealed = map_sections(&snapshot.archetypes, |archetype| {
+        let raw = encode_archetype(archetype, flags).in_stage(PipelineStage::Encode);
+        raw.and_then(|raw| Ok((raw.len(), seal_chunked(&raw, chunk_size, &seal)?)))
+            .with_component(&archetype.component_id)
+    })?;
+
+    for (archetype, (size, sealed)) in snapshot.archetypes.iter().zip(sealed) {
+        raw_size += size;
+
+        toc.archetypes.push(ArchetypeEntry {
+            component_id: archetype.component_id.clone(),
+            entity_count: archetype.entity_ids.len() as u64,
+            section: append_section(&mut data, &sealed),
+        });
+    }
+
+    let raw = bincode::serialize(&snapshot.entity_metadata).map_err(PackError::from).in_stage(PipelineStage::Encode)?;
+    raw_size += raw.len();
+    toc.entity_metadata = append_section(&mut data, &seal_chunked(&raw, chunk_size, &seal)?);
+
+    if !snapshot.resources.is_empty() {
+        let raw = bincode::serialize(&snapshot.resources).map_err(PackError::from).in_stage(PipelineStage::Encode)?;
+        raw_size += raw.len();
+        toc.resources = append_section(&mut data, &seal_chunked(&raw, chunk_size, &seal)?);
+    }
+
+    toc.layout = snapshot.layout.clone();
+    data.extend_from_slice(&encode_footer(&toc, seal)?);
+
+    Ok((data, raw_size))
+}
+
+pub(crate) fn encode_archetype(archetype: &ComponentArchetype, flags: u32) -> Result<Vec<u8>> {
+    if flags & FLAG_DELTA_ENTITY_IDS == 0 {
+        return Ok(bincode::serialize(archetype)?);
+    }
+
+    Ok(bincode::serialize(&DeltaArchetypeRef {
+        component_id: &archetype.component_id,
+        entity_ids: encode_entity_ids(&archetype.entity_ids),
+        data: &archetype.data,
+    })?)
+}
+
+pub(crate) fn decode_archetype(raw: &[u8], flags: u32) -> Result<ComponentArchetype> {
+    if flags & FLAG_DELTA_ENTITY_IDS == 0 {
+        return bincode::deserialize(raw).map_err(|e| PackError::Deserialization(e.to_string()));
+    }
+
+    let archetype: DeltaArchetype = bincode::deserialize(raw).map_err(|e| PackError::Deserialization(e.to_string()))?;
+    Ok(ComponentArchetype {
+        component_id: archetype.component_id,
+        entity_ids: decode_entity_ids(&archetype.entity_ids)?,
+        data: archetype.data,
+    })
+}
+
+// Ids are stored as zigzag varint deltas in row order, so sorted (or mostly
+// sorted) columns shrink to a byte or two per entity while any order still
+// round-trips exactly.
+pub(crate) fn encode_entity_ids(ids: &[EntityId]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(ids.len() + 4);
+    let mut previous = 0i64;
+
+    for &id in ids {
+        let delta = i64::from(id) - previous;
+        previous = i64::from(id);
+
+        let mut zigzag = ((delta << 1) ^ (delta >> 63)) as u64;
+        while zigzag >= 0x80 {
+            out.push(zigzag as u8 | 0x80);
+            zigzag >>= 7;
+        }
+        out.push(zigzag as u8);
+    }
+
+    out
+}
+
+pub(crate) fn decode_entity_ids(mut bytes: &[u8]) -> Result<Vec<EntityId>> {
+    let mut ids = Vec::with_capacity(bytes.len());
+    let mut previous = 0i64;
+
+    while !bytes.is_empty() {
+        let mut zigzag = 0u64;
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = bytes
+                .split_first()
+                .ok_or_else(|| PackError::Deserialization("Truncated entity id varint".to_string()))?;
+            bytes = rest;
+
+            if shift > 63 {
+                return Err(PackError::Deserialization("Entity id varint is too long".to_string()));
+            }
+            zigzag |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+
+        let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+        let id = previous
+            .checked_add(delta)
+            .and_then(|id| EntityId::try_from(id).ok())
+            .ok_or_else(|| PackError::Deserialization(format!("Entity id delta {} is out of range", delta)))?;
+        ids.push(id);
+        previous = i64::from(id);
+    }
+
+    Ok(ids)
+}
+
+fn append_section(data: &mut Vec<u8>, sealed: &[u8]) -> Section {
+    let section = Section {
+        offset: data.len() as u64,
+        size: sealed.len() as u64,
+    };
+    data.extend_from_slice(sealed);
+    section
+}
+
+pub(crate) fn seal_chunked<F>(raw: &[u8], chunk_size: usize, seal: F) -> Result<Vec<u8>>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let mut sealed = Vec::new();
+    for chunk in raw.chunks(chunk_size.max(1)) {
+        let bytes = seal(chunk)?;
+        sealed.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        sealed.extend_from_slice(&bytes);
+    }
+    Ok(sealed)
+}
+
+pub(crate) fn sealed_chunks(mut sealed: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut chunks = Vec::new();
+    while !sealed.is_empty() {
+        let (chunk, rest) = split_chunk(sealed)?;
+        chunks.push(chunk);
+        sealed = rest;
+    }
+    Ok(chunks)
+}
+
+pub(crate) fn split_chunk(sealed: &[u8]) -> Result<(&[u8], &[u8])> {
+    let (length, rest) = sealed
+        .split_first_chunk::<CHUNK_LENGTH_SIZE>()
+        .ok_or_else(|| PackError::InvalidFormat("Truncated chunk length".to_string()))?;
+    let length = u64::from_le_bytes(*length);
+    let length = usize::try_from(length)
+        .ok()
+        .filter(|length| *length <= rest.len())
+        .ok_or_else(|| {
+            PackError::InvalidFormat(format!("Chunk size {} exceeds remaining {} bytes", length, rest.len()))
+        })?;
+
+    Ok(rest.split_at(length))
+}
+
+pub(crate) fn open_chunked<F>(sealed: &[u8], open: F) -> Result<Vec<u8>>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let chunks = sealed_chunks(sealed)?;
+    if let [chunk] = chunks[..] {
+        return open(chunk);
+    }
+
+    let mut raw = Vec::new();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        raw.extend_from_slice(&open(chunk).map_err(|e| in_chunk(e, index))?);
+    }
+    Ok(raw)
+}
+
+// Chunks are opened one at a time, so only the caller knows which one failed.
+pub(crate) fn in_chunk(mut error: PackError, index: usize) -> PackError {
+    if let PackError::ChunkChecksumMismatch { chunk } = error.root_mut() {
+        *chunk = index;
+    }
+    error
+}
+
+pub(crate) fn encode_footer<F>(toc: &TableOfContents, seal: F) -> Result<Vec<u8>>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let mut footer = seal(&bincode::serialize(toc)?)?;
+    let toc_size = footer.len() as u64;
+    footer.extend_from_slice(&toc_size.to_le_bytes());
+    Ok(footer)
+}
+
+pub(crate) fn toc_size(data: &[u8]) -> Result<u64> {
+    let length_bytes: [u8; TOC_LENGTH_SIZE] = data
+        .len()
+        .checked_sub(TOC_LENGTH_SIZE)
+        .and_then(|start| data[start..].try_into().ok())
+        .ok_or_else(|| PackError::InvalidFormat("Missing table of contents".to_string()))?;
+
+    Ok(u64::from_le_bytes(length_bytes))
+}
+
+pub(crate) fn unlisted_bytes(data: &[u8], toc: &TableOfContents) -> Result<u64> {
+    let footer = toc_size(data)?.saturating_add(TOC_LENGTH_SIZE as u64);
+    let listed = toc
+        .archetypes
+        .iter()
+        .map(|entry| entry.section.size)
+        .chain([toc.entity_metadata.size, toc.resources.size])
+        .fold(0u64, u64::saturating_add);
+
+    Ok((data.len() as u64).saturating_sub(footer).saturating_sub(listed))
+}
+
+pub(crate) fn read_toc<F>(data: &[u8], open: F) -> Result<TableOfContents>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let toc_size = toc_size(data)?;
+    let toc_end = data.len() - TOC_LENGTH_SIZE;
+    let sealed = usize::try_from(toc_size)
+        .ok()
+        .and_then(|size| toc_end.checked_sub(size))
+        .map(|start| &data[start..toc_end])
+        .ok_or_else(|| {
+            PackError::InvalidFormat(format!("Table of contents size {} exceeds data length {}", toc_size, data.len()))
+        })?;
+
+    // Fields are read one at a time because bincode reports a table that ends
+    // early as corrupt rather than defaulting the fields newer minors added.
+    let raw = open(sealed)?;
+    let mut remaining = &raw[..];
+    let mut toc = TableOfContents {
+        archetypes: toc_field(&mut remaining)?,
+        entity_metadata: toc_field(&mut remaining)?,
+        ..TableOfContents::default()
+    };
+    if !remaining.is_empty() {
+        toc.resources = toc_field(&mut remaining)?;
+    }
+    if !remaining.is_empty() {
+        toc.layout = toc_field(&mut remaining)?;
+    }
+    Ok(toc)
+}
+
+fn toc_field<T: DeserializeOwned>(remaining: &mut &[u8]) -> Result<T> {
+    bincode::deserialize_from(remaining).map_err(|e| PackError::Deserialization(e.to_string()))
+}
+
+pub(crate) fn decode_resources<F>(data: &[u8], toc: &TableOfContents, open: F) -> Result<BTreeMap<String, Vec<u8>>>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    if toc.resources.size == 0 {
+        return Ok(BTreeMap::new());
+    }
+    decode_section(data, &toc.resources, open)
+}
+
+pub(crate) fn read_file_toc<F>(file: &mut File, header: &SnapshotHeader, open: F) -> Result<(TableOfContents, u64)>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let data_end = bounds::end(header.data_offset, header.data_size, "Data")?;
+    let footer_size = (TOC_LENGTH_SIZE as u64).min(header.data_size);
+    let toc_size = toc_size(&read_at(file, data_end - footer_size, footer_size)?)?;
+
+    let tail_size = toc_size
+        .checked_add(TOC_LENGTH_SIZE as u64)
+        .filter(|size| *size <= header.data_size)
+        .ok_or_else(|| PackError::InvalidFormat(
+            format!("Table of contents size {} exceeds data size {}", toc_size, header.data_size)
+        ))?;
+
+    let tail = read_at(file, data_end - tail_size, tail_size)?;
+    Ok((read_toc(&tail, open)?, header.data_size - tail_size))
+}
+
+pub(crate) fn section_bytes<'a>(data: &'a [u8], section: &Section) -> Result<&'a [u8]> {
+    bounds::slice(data, section.offset, section.size, "Section")
+}
+
+pub(crate) fn decode_section<T, F>(data: &[u8], section: &Section, open: F) -> Result<T>
+where
+    T: DeserializeOwned,
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    decode_sealed(section_bytes(data, section)?, open)
+}
+
+pub(crate) fn decode_sealed<T, F>(sealed: &[u8], open: F) -> Result<T>
+where
+    T: DeserializeOwned,
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let raw = open_chunked(sealed, open)?;
+    bincode::deserialize(&raw)
+        .map_err(|e| PackError::Deserialization(e.to_string()))
+        .in_stage(PipelineStage::Decode)
+}
+
+pub(crate) fn decode_snapshot<F>(header: SnapshotHeader, data: &[u8], limits: &ParseLimits, open: F) -> Result<PackedSnapshot>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>> + Sync,
+{
+    let toc = read_toc(data, &open)?;
+    limits.check_toc(&toc)?;
+
+    let mut snapshot = PackedSnapshot::new();
+    snapshot.archetypes = map_sections(&toc.archetypes, |entry| {
+        section_bytes(data, &entry.section)
+            .and_then(|sealed| open_chunked(sealed, &open))
+            .and_then(|raw| decode_archetype(&raw, header.flags).in_stage(PipelineStage::Decode))
+            .with_component(&entry.component_id)
+    })?;
+    snapshot.header = header;
+    snapshot.entity_metadata = decode_section(data, &toc.entity_metadata, &open)?;
+    snapshot.resources = decode_resources(data, &toc, &open)?;
+    snapshot.layout = toc.layout.clone();
+    snapshot.rebuild_entity_index();
+
+    Ok(snapshot)
+}
+
+pub(crate) fn decode_snapshot_pipelined<D, U>(
+    header: SnapshotHeader,
+    data: &[u8],
+    limits: &ParseLimits,
+    decrypt: D,
+    decompress: U,
+) -> Result<PackedSnapshot>
+where
+    D: for<'a> Fn(&'a [u8]) -> Result<Cow<'a, [u8]>> + Sync,
+    U: Fn(&[u8]) -> Result<Vec<u8>> + Sync,
+{
+    let open = |sealed: &[u8]| decompress(&decrypt(sealed)?);
+    let toc = read_toc(data, open)?;
+    limits.check_toc(&toc)?;
+    let sections = toc
+        .archetypes
+        .iter()
+        .map(|entry| section_bytes(data, &entry.section))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (decrypt, decompress) = (&decrypt, &decompress);
+    let archetypes = thread::scope(|scope| {
+        let (decrypted_tx, decrypted_rx) = mpsc::sync_channel::<Result<Vec<Cow<[u8]>>>>(PIPELINE_DEPTH);
+        let (raw_tx, raw_rx) = mpsc::sync_channel::<Result<Vec<u8>>>(PIPELINE_DEPTH);
+
+        scope.spawn(move || {
+            for sealed in sections {
+                let decrypted = sealed_chunks(sealed).and_then(|chunks| {
+                    chunks
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, chunk)| decrypt(chunk).map_err(|e| in_chunk(e, index)))
+                        .collect::<Result<Vec<_>>>()
+                });
+                let failed = decrypted.is_err();
+                if decrypted_tx.send(decrypted).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        scope.spawn(move || {
+            for decrypted in decrypted_rx {
+                let raw = decrypted.and_then(|chunks| {
+                    let mut raw = Vec::new();
+                    for chunk in chunks {
+                        raw.extend_from_slice(&decompress(&chunk)?);
+                    }
+                    Ok(raw)
+                });
+                let failed = raw.is_err();
+                if raw_tx.send(raw).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        raw_rx
+            .into_iter()
+            .zip(&toc.archetypes)
+            .map(|(raw, entry)| {
+                raw.and_then(|raw| decode_archetype(&raw, header.flags).in_stage(PipelineStage::Decode))
+                    .with_component(&entry.component_id)
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    if archetypes.len() != toc.archetypes.len() {
+        return Err(PackError::InvalidFormat(format!(
+            "Decoded {} of {} archetype sections", archetypes.len(), toc.archetypes.len()
+        )));
+    }
+
+    let mut snapshot = PackedSnapshot::new();
+    snapshot.header = header;
+    snapshot.archetypes = archetypes;
+    snapshot.entity_metadata = decode_section(data, &toc.entity_metadata, open)?;
+    snapshot.resources = decode_resources(data, &toc, open)?;
+    snapshot.layout = toc.layout.clone();
+    snapshot.rebuild_entity_index();
+
+    Ok(snapshot)
+}
+
+#[cfg(feature = "rayon")]
+pub(crate) fn map_sections<T, U, F>(items: &[T], f: F) -> Result<Vec<U>>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> Result<U> + Sync + Send,
+{
+    use rayon::prelude::*;
+
+    items.par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+pub(crate) fn map_sections<T, U, F>(items: &[T], f: F) -> Result<Vec<U>>
+where
+    F: Fn(&T) -> Result<U>,
+{
+    items.iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData, FieldArray, FieldType, PackFormat, StructOfArraysData};
+    use crate::checksum::strip_crc32c;
+    use crate::compression::decompress;
+    use crate::format::FLAG_CHUNK_CRC32C;
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_sections_preserve_archetype_order() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        for i in 0..32u32 {
+            snapshot.archetypes.push(ComponentArchetype {
+                component_id: format!("Component{}", i),
+                entity_ids: (0..i).collect(),
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["value".to_string()],
+                    field_types: vec![FieldType::U32],
+                    field_data: vec![FieldArray::U32((0..i).map(|v| v * i).collect())],
+                }),
+            });
+        }
+
+        let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+        let restored = SnapshotReader::new().read_from_bytes(&bytes).unwrap();
+
+        let ids: Vec<&String> = restored.archetypes.iter().map(|a| &a.component_id).collect();
+        let expected: Vec<&String> = snapshot.archetypes.iter().map(|a| &a.component_id).collect();
+        assert_eq!(ids, expected);
+        assert_eq!(restored.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+
+        let pipelined = SnapshotReader::new().with_pipelining(true).read_from_bytes(&bytes).unwrap();
+        let ids: Vec<&String> = pipelined.archetypes.iter().map(|a| &a.component_id).collect();
+        assert_eq!(ids, expected);
+        assert_eq!(pipelined.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+
+        let header = SnapshotHeader::decode(&bytes).unwrap();
+        let data = &bytes[header.data_offset as usize..(header.data_offset + header.data_size) as usize];
+        let calls = AtomicUsize::new(0);
+        let result = decode_snapshot_pipelined(header.clone(), data, &ParseLimits::default(), |sealed| Ok(Cow::Borrowed(strip_crc32c(sealed)?)), |sealed| {
+            if calls.fetch_add(1, Ordering::SeqCst) == 8 {
+                return Err(PackError::Decompression("truncated section".to_string()));
+            }
+            decompress(sealed, header.compression)
+        });
+        assert!(matches!(result.map_err(PackError::into_root), Err(PackError::Decompression(_))));
+    }
+
+    #[test]
+    fn test_entity_ids_are_delta_encoded() {
+        let ids: Vec<EntityId> = vec![0, 7, 3, u32::MAX, 1, 1];
+        assert_eq!(decode_entity_ids(&encode_entity_ids(&ids)).unwrap(), ids);
+        assert!(decode_entity_ids(&[0x80]).is_err());
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Tag".to_string(),
+            entity_ids: (1000..11_000).collect(),
+            data: ComponentData::Blob(Vec::new()),
+        });
+
+        let plain = encode_archetype(&snapshot.archetypes[0], 0).unwrap();
+        let delta = encode_archetype(&snapshot.archetypes[0], FLAG_DELTA_ENTITY_IDS).unwrap();
+        assert!(delta.len() * 3 < plain.len());
+        assert_eq!(decode_archetype(&delta, FLAG_DELTA_ENTITY_IDS).unwrap().entity_ids, snapshot.archetypes[0].entity_ids);
+
+        let writer = SnapshotWriter::new();
+        let bytes = writer.write_to_bytes(&snapshot).unwrap();
+        let restored = SnapshotReader::new().read_from_bytes(&bytes).unwrap();
+        assert_ne!(restored.header.flags & FLAG_DELTA_ENTITY_IDS, 0);
+        assert_eq!(restored.archetypes[0].entity_ids, snapshot.archetypes[0].entity_ids);
+
+        let raw = writer.with_delta_entity_ids(false).write_to_bytes(&snapshot).unwrap();
+        assert!(bytes.len() < raw.len());
+        assert_eq!(SnapshotReader::new().read_from_bytes(&raw).unwrap().header.flags, FLAG_CHUNK_CRC32C);
+    }
+
+    #[test]
+    fn test_chunked_sections_round_trip() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Large".to_string(),
+            entity_ids: (0..1000).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["value".to_string()],
+                field_types: vec![FieldType::U64],
+                field_data: vec![FieldArray::U64((0..1000).map(|v| v * 7).collect())],
+            }),
+        });
+
+        let bytes = SnapshotWriter::new().with_section_chunk_size(256).write_to_bytes(&snapshot).unwrap();
+        let header = SnapshotHeader::decode(&bytes).unwrap();
+        let data = &bytes[header.data_offset as usize..(header.data_offset + header.data_size) as usize];
+        let toc = read_toc(data, |sealed| decompress(strip_crc32c(sealed)?, header.compression)).unwrap();
+        let section = section_bytes(data, &toc.archetypes[0].section).unwrap();
+        assert!(sealed_chunks(section).unwrap().len() > 1);
+
+        let expected = snapshot.content_checksum().unwrap();
+        let reader = SnapshotReader::new();
+        assert_eq!(reader.read_from_bytes(&bytes).unwrap().content_checksum().unwrap(), expected);
+        let pipelined = reader.clone().with_pipelining(true).read_from_bytes(&bytes).unwrap();
+        assert_eq!(pipelined.content_checksum().unwrap(), expected);
+        let lazy = reader.read_lazy_from_bytes(&bytes).unwrap();
+        assert_eq!(lazy.into_snapshot().unwrap().content_checksum().unwrap(), expected);
+
+        let mut truncated = section[..section.len() - 1].to_vec();
+        truncated.truncate(CHUNK_LENGTH_SIZE + 3);
+        assert!(sealed_chunks(&truncated).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::error::{PackError, Result};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectorOp {
+    Equals(String),
+    NotEquals(String),
+    In(Vec<String>),
+    NotIn(Vec<String>),
+    Exists,
+    DoesNotExist,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    pub key: String,
+    pub op: SelectorOp,
+}
+
+impl Requirement {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        let value = labels.get(&self.key);
+
+        match &self.op {
+            SelectorOp::Equals(expected) => value == Some(expected),
+            SelectorOp::NotEquals(expected) => value != Some(expected),
+            SelectorOp::In(values) => value.is_some_and(|v| values.contains(v)),
+            SelectorOp::NotIn(values) => value.is_none_or(|v| !values.contains(v)),
+            SelectorOp::Exists => value.is_some(),
+            SelectorOp::DoesNotExist => value.is_none(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelSelector {
+    requirements: Vec<Requirement>,
+}
+
+impl LabelSelector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(selector: &str) -> Result<Self> {
+        let mut requirements = Vec::new();
+
+        for clause in split_clauses(selector)? {
+            let clause = clause.trim();
+            if clause.is_empty() {
+                continue;
+            }
+            requirements.push(parse_requirement(clause)?);
+        }
+
+        Ok(Self { requirements })
+    }
+
+    pub fn with_requirement(mut self, key: &str, op: SelectorOp) -> Self {
+        self.requirements.push(Requirement { key: key.to_string(), op });
+        self
+    }
+
+    pub fn requirements(&self) -> &[Requirement] {
+        &self.requirements
+    }
+
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.requirements.iter().all(|requirement| requirement.matches(labels))
+    }
+}
+
+fn split_clauses(selector: &str) -> Result<Vec<&str>> {
+    let mut clauses = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (i, c) in selector.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth = depth.checked_sub(1).ok_or_else(|| {
+                    PackError::InvalidSelector(format!("Unbalanced ')' in '{}'", selector))
+                })?;
+            }
+            ',' if depth == 0 => {
+                clauses.push(&selector[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if depth != 0 {
+        return Err(PackError::InvalidSelector(format!("Unbalanced '(' in '{}'", selector)));
+    }
+
+    clauses.push(&selector[start..]);
+    Ok(clauses)
+}
+
+fn parse_requirement(clause: &str) -> Result<Requirement> {
+    if let Some(key) = clause.strip_prefix('!') {
+        return Ok(Requirement { key: parse_key(key)?, op: SelectorOp::DoesNotExist });
+    }
+
+    if let Some((key, values)) = split_keyword(clause, "notin") {
+        return Ok(Requirement { key: parse_key(key)?, op: SelectorOp::NotIn(parse_set(values)?) });
+    }
+
+    if let Some((key, values)) = split_keyword(clause, "in") {
+        return Ok(Requirement { key: parse_key(key)?, op: SelectorOp::In(parse_set(values)?) });
+    }
+
+    if let Some((key, value)) = clause.split_once("!=") {
+        return Ok(Requirement { key: parse_key(key)?, op: SelectorOp::NotEquals(parse_value(value)?) });
+    }
+
+    if let Some((key, value)) = clause.split_once("==").or_else(|| clause.split_once('=')) {
+        return Ok(Requirement { key: parse_key(key)?, op: SelectorOp::Equals(parse_value(value)?) });
+    }
+
+    Ok(Requirement { key: parse_key(clause)?, op: SelectorOp::Exists })
+}
+
+fn split_keyword<'a>(clause: &'a str, keyword: &str) -> Option<(&'a str, &'a str)> {
+    let paren = clause.find('(')?;
+    let head = clause[..paren].trim_end();
+    let key = head.strip_suffix(keyword)?;
+
+    if !key.ends_with(char::is_whitespace) {
+        return None;
+    }
+
+    Some((key, &clause[paren..]))
+}
+
+fn parse_set(values: &str) -> Result<Vec<String>> {
+    let inner = values
+        .trim()
+        .strip_prefix('(')
+        .and_then(|v| v.strip_suffix(')'))
+        .ok_or_else(|| PackError::InvalidSelector(format!("Expected '(values)', got '{}'", values)))?;
+
+    inner.split(',').map(parse_value).collect()
+}
+
+fn parse_key(key: &str) -> Result<String> {
+    let key = key.trim();
+
+    if key.is_empty() || !key.chars().all(is_label_char) {
+        return Err(PackError::InvalidSelector(format!("Invalid label key '{}'", key)));
+    }
+
+    Ok(key.to_string())
+}
+
+fn parse_value(value: &str) -> Result<String> {
+    let value = value.trim();
+
+    if !value.chars().all(is_label_char) {
+        return Err(PackError::InvalidSelector(format!("Invalid label value '{}'", value)));
+    }
+
+    Ok(value.to_string())
+}
+
+fn is_label_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_selector() {
+        let selector = LabelSelector::parse("env=prod, tier in (hot, warm), !debug, region, zone notin (eu-1)").unwrap();
+
+        assert_eq!(
+            selector.requirements(),
+            &[
+                Requirement { key: "env".to_string(), op: SelectorOp::Equals("prod".to_string()) },
+                Requirement {
+                    key: "tier".to_string(),
+                    op: SelectorOp::In(vec!["hot".to_string(), "warm".to_string()]),
+                },
+                Requirement { key: "debug".to_string(), op: SelectorOp::DoesNotExist },
+                Requirement { key: "region".to_string(), op: SelectorOp::Exists },
+                Requirement { key: "zone".to_string(), op: SelectorOp::NotIn(vec!["eu-1".to_string()]) },
+            ]
+        );
+
+        assert!(LabelSelector::parse("tier in (hot").is_err());
+        assert!(LabelSelector::parse("=prod").is_err());
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let selector = LabelSelector::parse("env==prod,tier in (hot,warm),owner!=qa").unwrap();
+
+        assert!(selector.matches(&labels(&[("env", "prod"), ("tier", "warm")])));
+        assert!(!selector.matches(&labels(&[("env", "prod"), ("tier", "cold")])));
+        assert!(!selector.matches(&labels(&[("env", "prod"), ("tier", "hot"), ("owner", "qa")])));
+        assert!(!selector.matches(&labels(&[("tier", "hot")])));
+        assert!(LabelSelector::parse("").unwrap().matches(&labels(&[])));
+    }
+}
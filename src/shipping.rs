@@ -0,0 +1,237 @@
+use crate::checkpoint::CheckpointManager;
+use crate::error::{PackError, Result, ResultExt};
+use crate::metadata::SnapshotMetadata;
+use crate::storage::SnapshotStore;
+use crate::store_sync::is_synced;
+use crate::transfer::DEFAULT_TRANSFER_CHUNK_SIZE;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StandbyLag {
+    pub checkpoints: usize,
+    pub bytes: u64,
+    /// Age of the oldest checkpoint the standby is missing.
+    pub seconds: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct StandbyReport {
+    pub shipped: Vec<String>,
+    pub lag: StandbyLag,
+    /// The standby keeps its place and is retried on the next `ship`.
+    pub error: Option<PackError>,
+}
+
+struct Standby {
+    store: SnapshotStore,
+    shipped: Option<String>,
+}
+
+/// Copies new checkpoints to standby stores as stored, deltas after their
+/// base; `CheckpointManager::resume` on a standby carries on from there.
+pub struct CheckpointShipper {
+    standbys: Vec<Standby>,
+    chunk_size: u32,
+    max_lag: Option<usize>,
+}
+
+impl CheckpointShipper {
+    pub fn new() -> Self {
+        Self {
+            standbys: Vec::new(),
+            chunk_size: DEFAULT_TRANSFER_CHUNK_SIZE,
+            max_lag: None,
+        }
+    }
+
+    pub fn with_standby(mut self, store: SnapshotStore) -> Self {
+        self.standbys.push(Standby { store, shipped: None });
+        self
+    }
+
+    pub fn with_chunk_size(mut self, bytes: u32) -> Self {
+        self.chunk_size = bytes;
+        self
+    }
+
+    /// `ship` fails once a standby is still more than this many checkpoints behind.
+    pub fn with_max_lag(mut self, checkpoints: usize) -> Self {
+        self.max_lag = Some(checkpoints);
+        self
+    }
+
+    pub fn standby_count(&self) -> usize {
+        self.standbys.len()
+    }
+
+    pub fn standby(&self, index: usize) -> Option<&SnapshotStore> {
+        self.standbys.get(index).map(|standby| &standby.store)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn ship(&mut self, manager: &CheckpointManager) -> Result<Vec<StandbyReport>> {
+        let (primary, chain) = (manager.store(), manager.get_checkpoint_chain());
+        let mut reports = Vec::with_capacity(self.standbys.len());
+        for standby in &mut self.standbys {
+            let mut report = StandbyReport::default();
+            if let Err(error) = standby.ship(primary, chain, self.chunk_size, &mut report.shipped) {
+                report.error = Some(error);
+            }
+            match standby.lag(primary, chain) {
+                Ok(lag) => report.lag = lag,
+                // A standby whose lag cannot be read counts as missing the
+                // whole chain, so with_max_lag still catches it.
+                Err(error) => {
+                    report.lag.checkpoints = chain.len();
+                    report.error.get_or_insert(error);
+                }
+            }
+            reports.push(report);
+        }
+
+        if let Some(max) = self.max_lag.filter(|max| reports.iter().any(|report| report.lag.checkpoints > *max)) {
+            return Err(PackError::LimitExceeded { limit: "standby lag", max: max as u64 });
+        }
+
+        Ok(reports)
+    }
+
+    pub fn lag(&self, manager: &CheckpointManager) -> Result<Vec<StandbyLag>> {
+        let (primary, chain) = (manager.store(), manager.get_checkpoint_chain());
+        self.standbys.iter().map(|standby| standby.lag(primary, chain)).collect()
+    }
+}
+
+impl Default for CheckpointShipper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Standby {
+    // Checkpoints after the last one shipped, or the whole chain if that one
+    // has been pruned since.
+    fn pending(&self, primary: &SnapshotStore, chain: &[String]) -> Result<Vec<(String, SnapshotMetadata)>> {
+        let start = self
+            .shipped
+            .as_ref()
+            .and_then(|shipped| chain.iter().position(|id| id == shipped))
+            .map_or(0, |position| position + 1);
+
+        let mut pending = Vec::new();
+        for id in &chain[start..] {
+            let metadata = primary.load_metadata(id)?;
+            let checksum = primary.stored_checksum(id, &metadata)?;
+            if !(self.store.exists(id) && is_synced(&self.store.load_metadata(id)?, &checksum)) {
+                pending.push((id.clone(), metadata));
+            }
+        }
+
+        Ok(pending)
+    }
+
+    fn ship(
+        &mut self,
+        primary: &SnapshotStore,
+        chain: &[String],
+        chunk_size: u32,
+        shipped: &mut Vec<String>,
+    ) -> Result<()> {
+        for (id, metadata) in self.pending(primary, chain)? {
+            // Bases come earlier in the chain, unless the standby started
+            // after they were shipped elsewhere.
+            if let Some(base) = metadata.delta_base.filter(|base| !self.store.exists(base)) {
+                self.store.copy_from(primary, &base, chunk_size).with_snapshot_id(&base)?;
+                shipped.push(base);
+            }
+            self.store.copy_from(primary, &id, chunk_size).with_snapshot_id(&id)?;
+            self.shipped = Some(id.clone());
+            shipped.push(id);
+        }
+        self.shipped = chain.last().cloned();
+
+        Ok(())
+    }
+
+    fn lag(&self, primary: &SnapshotStore, chain: &[String]) -> Result<StandbyLag> {
+        let pending = self.pending(primary, chain)?;
+        let mut lag = StandbyLag { checkpoints: pending.len(), ..StandbyLag::default() };
+        for (id, _) in &pending {
+            lag.bytes += primary.file_size(id)?;
+        }
+        if let Some(oldest) = pending.iter().map(|(_, metadata)| metadata.created_at).min() {
+            lag.seconds = (chrono::Utc::now().timestamp() - oldest).max(0);
+        }
+
+        Ok(lag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot};
+    use tempfile::TempDir;
+
+    fn frame(step: u8) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Counter".to_string(),
+            entity_ids: (0..32).collect(),
+            data: ComponentData::Blob([[0; 96].as_slice(), &[step; 32]].concat()),
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_standbys_follow_checkpoints_and_report_lag() {
+        let (primary_dir, standby_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let lost_dir = TempDir::new().unwrap();
+        let mut manager = CheckpointManager::new(primary_dir.path()).unwrap().with_keyframe_interval(2);
+        let mut shipper = CheckpointShipper::new().with_standby(SnapshotStore::new(standby_dir.path()).unwrap());
+        for step in 0..3 {
+            manager.create_checkpoint(format!("cp{}", step), frame(step)).unwrap();
+        }
+
+        let reports = shipper.ship(&manager).unwrap();
+        assert_eq!(reports[0].shipped, vec!["cp0", "cp1", "cp2"]);
+        assert_eq!(reports[0].lag, StandbyLag::default());
+        let standby = shipper.standby(0).unwrap();
+        assert!(standby.is_delta("cp1") && !standby.is_delta("cp2"));
+
+        manager.create_checkpoint("cp3".to_string(), frame(3)).unwrap();
+        let lag = shipper.lag(&manager).unwrap()[0];
+        assert_eq!((lag.checkpoints, lag.bytes), (1, manager.store().file_size("cp3").unwrap()));
+        assert_eq!(shipper.ship(&manager).unwrap()[0].shipped, vec!["cp3"]);
+
+        // Warm failover: the standby carries on from where the primary was.
+        let mut failover = CheckpointManager::resume(standby_dir.path()).unwrap();
+        assert_eq!(failover.get_checkpoint_chain(), manager.get_checkpoint_chain());
+        let loaded = failover.load_checkpoint("cp3").unwrap();
+        assert_eq!(loaded.snapshot.content_checksum().unwrap(), frame(3).content_checksum().unwrap());
+        failover.create_checkpoint("cp4".to_string(), frame(4)).unwrap();
+        assert_eq!(failover.lineage("cp4").unwrap(), vec!["cp4", "cp3", "cp2", "cp1", "cp0"]);
+
+        // A standby that cannot keep up is reported, and trips the bound.
+        let lost = SnapshotStore::new(lost_dir.path()).unwrap();
+        std::fs::remove_dir_all(lost_dir.path()).unwrap();
+        let mut shipper = shipper.with_standby(lost);
+        let reports = shipper.ship(&manager).unwrap();
+        assert!(reports[0].shipped.is_empty() && reports[0].error.is_none());
+        assert!(reports[1].error.is_some());
+        assert_eq!(reports[1].lag.checkpoints, 4);
+
+        // Nor does one whose lag cannot be worked out stop the others.
+        let broken_dir = TempDir::new().unwrap();
+        let broken = SnapshotStore::new(broken_dir.path()).unwrap();
+        std::fs::write(broken_dir.path().join("cp0.tx2pack"), b"junk").unwrap();
+        std::fs::write(broken_dir.path().join("cp0.meta.json"), b"junk").unwrap();
+        let mut shipper = shipper.with_standby(broken);
+        let reports = shipper.ship(&manager).unwrap();
+        assert!(reports[0].error.is_none() && reports[2].error.is_some());
+        assert_eq!(reports[2].lag.checkpoints, 4);
+
+        let mut shipper = shipper.with_max_lag(3);
+        let error = shipper.ship(&manager).unwrap_err();
+        assert!(matches!(error, PackError::LimitExceeded { limit: "standby lag", max: 3 }));
+    }
+}
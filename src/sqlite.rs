@@ -0,0 +1,331 @@
+use crate::backend::StorageBackend;
+use crate::error::{PackError, Result};
+use crate::format::PackedSnapshot;
+use crate::metadata::{MetadataSchema, SnapshotMetadata};
+use crate::storage::{file_checksum, SnapshotReader, SnapshotWriter};
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use std::path::Path;
+
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+
+    CREATE TABLE IF NOT EXISTS snapshots (
+        id TEXT PRIMARY KEY,
+        name TEXT,
+        created_at INTEGER NOT NULL,
+        world_time REAL NOT NULL,
+        size_bytes INTEGER NOT NULL,
+        checksum TEXT NOT NULL,
+        metadata TEXT NOT NULL,
+        payload BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS snapshots_created_at ON snapshots (created_at);
+
+    CREATE TABLE IF NOT EXISTS snapshot_tags (
+        snapshot_id TEXT NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (snapshot_id, tag)
+    );
+    CREATE INDEX IF NOT EXISTS snapshot_tags_tag ON snapshot_tags (tag);
+
+    CREATE TABLE IF NOT EXISTS snapshot_labels (
+        snapshot_id TEXT NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (snapshot_id, key)
+    );
+    CREATE INDEX IF NOT EXISTS snapshot_labels_key_value ON snapshot_labels (key, value);
+";
+
+pub struct SqliteStore {
+    conn: Connection,
+    metadata_schema: Option<MetadataSchema>,
+}
+
+impl SqliteStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(Self {
+            conn,
+            metadata_schema: None,
+        })
+    }
+
+    pub fn with_metadata_schema(mut self, schema: MetadataSchema) -> Self {
+        self.metadata_schema = Some(schema);
+        self
+    }
+
+    pub fn save(&self, snapshot: &PackedSnapshot, metadata: &SnapshotMetadata, writer: &SnapshotWriter) -> Result<()> {
+        if let Some(schema) = &self.metadata_schema {
+            schema.validate(metadata)?;
+        }
+
+        let bytes = writer.write_to_bytes(snapshot)?;
+        let mut metadata = metadata.clone();
+
+        let tx = self.conn.unchecked_transaction()?;
+
+        if let Some(expected) = &metadata.checksum {
+            let stored: Option<String> = tx
+                .query_row("SELECT checksum FROM snapshots WHERE id = ?1", [&metadata.id], |row| row.get(0))
+                .optional()?;
+            if stored.as_ref().is_some_and(|stored| stored != expected) {
+                let theirs = self.load_metadata(&metadata.id)?;
+                return Err(PackError::Conflict {
+                    ours: Box::new(metadata),
+                    theirs: Box::new(theirs),
+                });
+            }
+        }
+
+        metadata.delta_base = None;
+        metadata.checksum = Some(file_checksum(&bytes));
+
+        tx.execute("DELETE FROM snapshots WHERE id = ?1", [&metadata.id])?;
+        tx.execute(
+            "INSERT INTO snapshots (id, name, created_at, world_time, size_bytes, checksum, metadata, payload)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                metadata.id,
+                metadata.name,
+                metadata.created_at,
+                metadata.world_time,
+                bytes.len() as i64,
+                metadata.checksum,
+                serde_json::to_string(&metadata)?,
+                bytes,
+            ],
+        )?;
+        for tag in &metadata.tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO snapshot_tags (snapshot_id, tag) VALUES (?1, ?2)",
+                params![metadata.id, tag],
+            )?;
+        }
+        for (key, value) in &metadata.labels {
+            tx.execute(
+                "INSERT INTO snapshot_labels (snapshot_id, key, value) VALUES (?1, ?2, ?3)",
+                params![metadata.id, key, value],
+            )?;
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn load(&self, id: &str, reader: &SnapshotReader) -> Result<(PackedSnapshot, SnapshotMetadata)> {
+        let (payload, metadata): (Vec<u8>, String) = self
+            .conn
+            .query_row("SELECT payload, metadata FROM snapshots WHERE id = ?1", [id], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?
+            .ok_or_else(|| PackError::SnapshotNotFound(id.to_string()))?;
+
+        Ok((reader.read_from_bytes(&payload)?, serde_json::from_str(&metadata)?))
+    }
+
+    pub fn load_metadata(&self, id: &str) -> Result<SnapshotMetadata> {
+        let metadata: String = self
+            .conn
+            .query_row("SELECT metadata FROM snapshots WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| PackError::SnapshotNotFound(id.to_string()))?;
+
+        Ok(serde_json::from_str(&metadata)?)
+    }
+
+    pub fn size_bytes(&self, id: &str) -> Result<u64> {
+        let size: i64 = self
+            .conn
+            .query_row("SELECT size_bytes FROM snapshots WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| PackError::SnapshotNotFound(id.to_string()))?;
+
+        Ok(size as u64)
+    }
+
+    pub fn exists(&self, id: &str) -> bool {
+        self.conn
+            .query_row("SELECT 1 FROM snapshots WHERE id = ?1", [id], |_| Ok(()))
+            .optional()
+            .is_ok_and(|row| row.is_some())
+    }
+
+    pub fn delete(&self, id: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM snapshots WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT id FROM snapshots ORDER BY id")?;
+        let ids = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(ids)
+    }
+
+    pub fn query(&self) -> SqliteQuery<'_> {
+        SqliteQuery::new(self)
+    }
+}
+
+impl StorageBackend for SqliteStore {
+    fn save(&self, snapshot: &PackedSnapshot, metadata: &SnapshotMetadata, writer: &SnapshotWriter) -> Result<()> {
+        SqliteStore::save(self, snapshot, metadata, writer)
+    }
+
+    fn load(&self, id: &str, reader: &SnapshotReader) -> Result<(PackedSnapshot, SnapshotMetadata)> {
+        SqliteStore::load(self, id, reader)
+    }
+
+    fn load_metadata(&self, id: &str) -> Result<SnapshotMetadata> {
+        SqliteStore::load_metadata(self, id)
+    }
+
+    fn exists(&self, id: &str) -> bool {
+        SqliteStore::exists(self, id)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        SqliteStore::delete(self, id)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        SqliteStore::list(self)
+    }
+}
+
+pub struct SqliteQuery<'a> {
+    store: &'a SqliteStore,
+    clauses: Vec<&'static str>,
+    params: Vec<Box<dyn ToSql>>,
+    limit: Option<usize>,
+}
+
+impl<'a> SqliteQuery<'a> {
+    pub fn new(store: &'a SqliteStore) -> Self {
+        Self {
+            store,
+            clauses: Vec::new(),
+            params: Vec::new(),
+            limit: None,
+        }
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.clauses.push("id IN (SELECT snapshot_id FROM snapshot_tags WHERE tag = ?)");
+        self.params.push(Box::new(tag.to_string()));
+        self
+    }
+
+    pub fn with_label(mut self, key: &str, value: &str) -> Self {
+        self.clauses.push("id IN (SELECT snapshot_id FROM snapshot_labels WHERE key = ? AND value = ?)");
+        self.params.push(Box::new(key.to_string()));
+        self.params.push(Box::new(value.to_string()));
+        self
+    }
+
+    pub fn with_name_containing(mut self, substring: &str) -> Self {
+        self.clauses.push("instr(name, ?) > 0");
+        self.params.push(Box::new(substring.to_string()));
+        self
+    }
+
+    pub fn created_after(mut self, timestamp: i64) -> Self {
+        self.clauses.push("created_at >= ?");
+        self.params.push(Box::new(timestamp));
+        self
+    }
+
+    pub fn created_before(mut self, timestamp: i64) -> Self {
+        self.clauses.push("created_at <= ?");
+        self.params.push(Box::new(timestamp));
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn execute(&self) -> Result<Vec<SnapshotMetadata>> {
+        let mut sql = "SELECT metadata FROM snapshots".to_string();
+        if !self.clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&self.clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY created_at, id");
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let params: Vec<&dyn ToSql> = self.params.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = self.store.conn.prepare(&sql)?;
+        let rows = stmt
+            .query_map(params.as_slice(), |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        rows.iter()
+            .map(|json| serde_json::from_str(json).map_err(PackError::from))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sqlite_store_roundtrip_and_query() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("session.db");
+        let writer = SnapshotWriter::new();
+        let reader = SnapshotReader::new();
+
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            for (i, tag) in ["boss", "checkpoint", "boss"].iter().enumerate() {
+                let metadata = SnapshotMetadata::new(format!("snap_{}", i))
+                    .with_name(format!("Wave {}", i))
+                    .with_tag(tag.to_string())
+                    .with_label("region".to_string(), if i == 2 { "us" } else { "eu" }.to_string());
+                store.save(&PackedSnapshot::new(), &metadata, &writer).unwrap();
+            }
+        }
+
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.list().unwrap(), vec!["snap_0", "snap_1", "snap_2"]);
+
+        let (_, metadata) = store.load("snap_1", &reader).unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("Wave 1"));
+        assert!(metadata.checksum.is_some());
+
+        let bosses: Vec<String> = store.query().with_tag("boss").execute().unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(bosses, vec!["snap_0", "snap_2"]);
+        assert_eq!(store.query().with_tag("boss").with_label("region", "eu").execute().unwrap().len(), 1);
+        assert_eq!(store.query().with_name_containing("Wave 2").execute().unwrap()[0].id, "snap_2");
+
+        let mut stale = metadata.clone();
+        stale.checksum = Some("outdated".to_string());
+        assert!(matches!(
+            store.save(&PackedSnapshot::new(), &stale, &writer),
+            Err(PackError::Conflict { .. })
+        ));
+
+        store.delete("snap_0").unwrap();
+        assert!(!store.exists("snap_0"));
+        assert_eq!(store.query().with_tag("boss").execute().unwrap().len(), 1);
+        assert!(matches!(store.load("snap_0", &reader), Err(PackError::SnapshotNotFound(_))));
+    }
+}
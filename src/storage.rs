@@ -1,27 +1,114 @@
-use crate::error::{PackError, Result};
-use crate::format::{PackedSnapshot, SnapshotHeader, PackFormat};
-use crate::compression::{CompressionCodec, compress, decompress};
-use crate::metadata::SnapshotMetadata;
-use std::path::{Path, PathBuf};
-use std::fs::File;
-use std::io::{Write, Read};
+use crate::bounds;
+use crate::error::{PackError, PipelineStage, Result, ResultExt};
+use crate::format::{ComponentArchetype, PackedSnapshot, PackedSnapshotV2_0, PackedSnapshotV2_1, SnapshotHeader, PackFormat, HEADER_SIZE, MAGIC_NUMBER, DELTA_MAGIC_NUMBER, FORMAT_VERSION, FLAG_CHUNK_CRC32C, FLAG_DELTA_ENTITY_IDS};
+use crate::delta::{DeltaSnapshot, DeltaSnapshotV2_0, DeltaSnapshotV2_1};
+use crate::flatbuffer;
+use crate::legacy;
+use crate::recorder::DELTA_LOG_EXTENSION;
+use crate::sectioned;
+use crate::lazy::LazySnapshot;
+use crate::limits::{DecompressionBudget, ParseLimits};
+use crate::registry::ComponentRegistry;
+use crate::stream_write::StreamingWriter;
+use crate::compression::{CompressionCodec, compress};
+use crate::checksum::{append_crc32c, skip_crc32c, strip_crc32c, ChecksumAlgorithm};
+use crate::metadata::{MetadataSchema, SnapshotMetadata};
+use crate::store_query::{list_page, ListOptions, ListPage, StoreQuery};
+use crate::catalog::{
+    append_catalog_log, read_catalog_log, Catalog, CatalogChange, CatalogEntry, CATALOG_FILE, CATALOG_LOG_FILE,
+};
+use crate::search::{SearchIndex, SEARCH_INDEX_FILE};
+use crate::compare::ComparisonReport;
+use crate::telemetry::Stopwatch;
+use crate::throttle::Throttle;
+use std::borrow::Cow;
+use std::path::{Component, Path, PathBuf};
+use std::fs::{File, OpenOptions};
+use std::io::{Write, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use sha2::{Sha256, Digest};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
 #[cfg(feature = "encryption")]
 use crate::encryption::{EncryptionKey, encrypt_snapshot, decrypt_snapshot};
 
+#[cfg(feature = "rkyv")]
+use crate::archive;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use crate::uring::{UringIo, UringWrite};
+
+#[cfg(feature = "arena")]
+use crate::arena::{self, ArenaSnapshot};
+#[cfg(feature = "arena")]
+use bumpalo::Bump;
+
+const DATA_ALIGNMENT: u64 = 16;
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+const LOCK_RETRY: Duration = Duration::from_millis(5);
+// A lock this old was left by a writer that died and is broken.
+const STALE_LOCK: Duration = Duration::from_secs(5 * 60);
+const CATALOG_LOCK_FILE: &str = "catalog.lock";
+const CATALOG_LOG_COMPACT_BYTES: u64 = 4 * 1024 * 1024;
+
+#[derive(Default)]
+struct ScratchBuffer(Mutex<Vec<u8>>);
+
+impl ScratchBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn put(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        let mut slot = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if buffer.capacity() > slot.capacity() {
+            *slot = buffer;
+        }
+    }
+}
+
+/// Only `FsyncDir` also syncs the directory entry, so a new file survives power loss.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum DurabilityLevel {
+    None,
+    Flush,
+    #[default]
+    Fsync,
+    FsyncDir,
+}
+
 pub struct SnapshotWriter {
     compression: CompressionCodec,
+    checksum: ChecksumAlgorithm,
     #[cfg(feature = "encryption")]
     encryption_key: Option<EncryptionKey>,
+    section_chunk_size: usize,
+    write_rate_limit: Option<u64>,
+    delta_entity_ids: bool,
+    chunk_checksums: bool,
+    structure_checks: bool,
+    durability: DurabilityLevel,
+    scratch: ScratchBuffer,
 }
 
 impl SnapshotWriter {
     pub fn new() -> Self {
         Self {
-            compression: CompressionCodec::zstd_default(),
+            compression: CompressionCodec::default(),
+            checksum: ChecksumAlgorithm::default(),
             #[cfg(feature = "encryption")]
             encryption_key: None,
+            section_chunk_size: sectioned::DEFAULT_CHUNK_SIZE,
+            write_rate_limit: None,
+            delta_entity_ids: true,
+            chunk_checksums: true,
+            structure_checks: true,
+            durability: DurabilityLevel::default(),
+            scratch: ScratchBuffer::default(),
         }
     }
 
@@ -30,74 +117,241 @@ impl SnapshotWriter {
         self
     }
 
+    pub fn with_checksum(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum = algorithm;
+        self
+    }
+
+    pub fn with_section_chunk_size(mut self, bytes: usize) -> Self {
+        self.section_chunk_size = bytes.max(1);
+        self
+    }
+
+    pub fn with_delta_entity_ids(mut self, enabled: bool) -> Self {
+        self.delta_entity_ids = enabled;
+        self
+    }
+
+    pub fn with_chunk_checksums(mut self, enabled: bool) -> Self {
+        self.chunk_checksums = enabled;
+        self
+    }
+
+    pub fn with_structure_checks(mut self, enabled: bool) -> Self {
+        self.structure_checks = enabled;
+        self
+    }
+
+    pub fn with_durability(mut self, level: DurabilityLevel) -> Self {
+        self.durability = level;
+        self
+    }
+
+    pub fn with_write_rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.write_rate_limit = Some(bytes_per_sec.max(1));
+        self
+    }
+
     #[cfg(feature = "encryption")]
     pub fn with_encryption(mut self, key: EncryptionKey) -> Self {
         self.encryption_key = Some(key);
         self
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(path = %path.as_ref().display())))]
     pub fn write_to_file<P: AsRef<Path>>(
         &self,
         snapshot: &PackedSnapshot,
         path: P,
     ) -> Result<()> {
-        let serialized = self.serialize_snapshot(snapshot)?;
+        let path = path.as_ref();
+        self.write_to_bytes(snapshot)
+            .and_then(|bytes| self.write_file(path, &bytes, self.durability))
+            .with_path(path)
+    }
 
-        let compressed = compress(&serialized, self.compression)?;
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(format = ?snapshot.header.format, archetypes = snapshot.archetypes.len())))]
+    pub fn write_to_bytes(&self, snapshot: &PackedSnapshot) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_to_bytes_into(snapshot, &mut bytes)?;
+        Ok(bytes)
+    }
 
-        #[cfg(feature = "encryption")]
-        let final_data = if let Some(key) = &self.encryption_key {
-            encrypt_snapshot(&compressed, key)?
-        } else {
-            compressed
+    pub fn write_to_bytes_into(&self, snapshot: &PackedSnapshot, out: &mut Vec<u8>) -> Result<()> {
+        let stopwatch = Stopwatch::start();
+        let raw_size = self.encode_measured(snapshot, out)?;
+        stopwatch.record_write("snapshot", raw_size, out.len());
+        Ok(())
+    }
+
+    // Returns the serialized size before compression.
+    pub(crate) fn encode_measured(&self, snapshot: &PackedSnapshot, out: &mut Vec<u8>) -> Result<usize> {
+        if self.structure_checks {
+            snapshot.check_consistency()?;
+        }
+        let mut header = snapshot.header.clone();
+        self.set_flags(&mut header);
+
+        let raw_size = match header.format {
+            PackFormat::Custom => {
+                let (data, raw_size) =
+                    sectioned::encode_snapshot(snapshot, header.flags, self.section_chunk_size, |raw| self.seal_chunk(raw))?;
+                self.frame_into(header, &data, out)?;
+                raw_size
+            }
+            PackFormat::FlatBuffers => {
+                let serialized = flatbuffer::encode_snapshot(snapshot);
+                self.encode_into(header, &serialized, out)?;
+                serialized.len()
+            }
+            #[cfg(feature = "rkyv")]
+            PackFormat::Rkyv => {
+                let serialized = archive::encode_snapshot(snapshot)?;
+                self.encode_into(header, &serialized, out)?;
+                serialized.len()
+            }
+            format => self.encode_value_into(header, snapshot, format, out)?,
         };
 
-        #[cfg(not(feature = "encryption"))]
-        let final_data = compressed;
+        Ok(raw_size)
+    }
 
-        let mut header = snapshot.header.clone();
-        header.compression = self.compression.into();
+    pub fn write_delta_to_file<P: AsRef<Path>>(&self, delta: &DeltaSnapshot, path: P) -> Result<()> {
+        let path = path.as_ref();
+        self.write_delta_to_bytes(delta)
+            .and_then(|bytes| self.write_file(path, &bytes, self.durability))
+            .with_path(path)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn write_delta_to_bytes(&self, delta: &DeltaSnapshot) -> Result<Vec<u8>> {
+        let stopwatch = Stopwatch::start();
+        let mut header = SnapshotHeader::new();
+        header.magic = *DELTA_MAGIC_NUMBER;
+        header.format = delta.target_header.format;
+        header.entity_count = delta.target_header.entity_count;
+        header.component_count = delta.target_header.component_count;
+        header.archetype_count = delta.target_header.archetype_count;
+
+        let mut bytes = Vec::new();
+        let format = header.format;
+        let raw_size = self.encode_value_into(header, delta, format, &mut bytes)?;
+        stopwatch.record_write("delta", raw_size, bytes.len());
+        Ok(bytes)
+    }
+
+    pub(crate) fn encode(&self, header: SnapshotHeader, serialized: &[u8]) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.encode_into(header, serialized, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn encode_value_into<T: Serialize>(
+        &self,
+        header: SnapshotHeader,
+        value: &T,
+        format: PackFormat,
+        out: &mut Vec<u8>,
+    ) -> Result<usize> {
+        let mut serialized = self.scratch.take();
+        let result = serialize_value_into(value, format, &mut serialized)
+            .in_stage(PipelineStage::Encode)
+            .and_then(|_| self.encode_into(header, &serialized, out));
+        let raw_size = serialized.len();
+        self.scratch.put(serialized);
+
+        result.map(|_| raw_size)
+    }
+
+    fn encode_into(&self, header: SnapshotHeader, serialized: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        let sealed = self.seal(serialized)?;
+        self.frame_into(header, &sealed, out)
+    }
+
+    pub(crate) fn seal(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let compressed = compress(data, self.compression).in_stage(PipelineStage::Compress)?;
 
         #[cfg(feature = "encryption")]
-        {
-            header.encrypted = self.encryption_key.is_some();
+        if let Some(key) = &self.encryption_key {
+            return encrypt_snapshot(&compressed, key).in_stage(PipelineStage::Encrypt);
         }
 
-        header.checksum = self.compute_checksum(&final_data);
-        header.data_size = final_data.len() as u64;
+        Ok(compressed)
+    }
 
-        let header_bytes = bincode::serialize(&header)?;
-        header.data_offset = header_bytes.len() as u64;
+    pub(crate) fn seal_section(&self, raw: &[u8]) -> Result<Vec<u8>> {
+        sectioned::seal_chunked(raw, self.section_chunk_size, |chunk| self.seal_chunk(chunk))
+    }
 
-        let final_header_bytes = bincode::serialize(&header)?;
+    // Sections are read one chunk at a time, so each sealed chunk carries its
+    // own CRC32C and a bad one is caught without hashing the whole file.
+    pub(crate) fn seal_chunk(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut sealed = self.seal(data)?;
+        if self.chunk_checksums {
+            append_crc32c(&mut sealed);
+        }
+        Ok(sealed)
+    }
 
-        let mut file = File::create(path)?;
+    pub(crate) fn write_file(&self, path: &Path, bytes: &[u8], durability: DurabilityLevel) -> Result<()> {
+        let write = || -> Result<()> {
+            let mut file = File::create(path)?;
 
-        file.write_all(&final_header_bytes)?;
+            self.throttle().write_all(&mut file, bytes)?;
 
-        file.write_all(&final_data)?;
+            match durability {
+                DurabilityLevel::None => {}
+                DurabilityLevel::Flush => file.flush()?,
+                DurabilityLevel::Fsync => file.sync_all()?,
+                DurabilityLevel::FsyncDir => {
+                    file.sync_all()?;
+                    sync_dir(path.parent().unwrap_or(Path::new(".")))?;
+                }
+            }
 
-        file.sync_all()?;
+            Ok(())
+        };
 
-        Ok(())
+        write().in_stage(PipelineStage::Write).with_path(path)
     }
 
-    pub fn write_to_bytes(&self, snapshot: &PackedSnapshot) -> Result<Vec<u8>> {
-        let serialized = self.serialize_snapshot(snapshot)?;
+    #[cfg_attr(not(all(feature = "io-uring", target_os = "linux")), allow(dead_code))]
+    pub(crate) fn is_throttled(&self) -> bool {
+        self.write_rate_limit.is_some()
+    }
 
-        let compressed = compress(&serialized, self.compression)?;
+    pub(crate) fn throttle(&self) -> Throttle {
+        Throttle::new(self.write_rate_limit)
+    }
 
-        #[cfg(feature = "encryption")]
-        let final_data = if let Some(key) = &self.encryption_key {
-            encrypt_snapshot(&compressed, key)?
-        } else {
-            compressed
-        };
+    pub(crate) fn set_flags(&self, header: &mut SnapshotHeader) {
+        header.flags &= !(FLAG_DELTA_ENTITY_IDS | FLAG_CHUNK_CRC32C);
+        if header.format == PackFormat::Custom && self.delta_entity_ids {
+            header.flags |= FLAG_DELTA_ENTITY_IDS;
+        }
+        if header.format == PackFormat::Custom && self.chunk_checksums {
+            header.flags |= FLAG_CHUNK_CRC32C;
+        }
+    }
+
+    pub(crate) fn check_structure(&self, archetype: &ComponentArchetype) -> Result<()> {
+        if self.structure_checks {
+            archetype.check_consistency()?;
+        }
+        Ok(())
+    }
 
-        #[cfg(not(feature = "encryption"))]
-        let final_data = compressed;
+    pub(crate) fn durability(&self) -> DurabilityLevel {
+        self.durability
+    }
 
-        let mut header = snapshot.header.clone();
+    pub(crate) fn checksum_algorithm(&self) -> ChecksumAlgorithm {
+        self.checksum
+    }
+
+    pub(crate) fn finish_header(&self, header: &mut SnapshotHeader, checksum: [u8; 32], data_size: u64) {
+        // A pack read from a newer minor version is rewritten as this one.
+        header.version = FORMAT_VERSION;
         header.compression = self.compression.into();
 
         #[cfg(feature = "encryption")]
@@ -105,41 +359,32 @@ impl SnapshotWriter {
             header.encrypted = self.encryption_key.is_some();
         }
 
-        header.checksum = self.compute_checksum(&final_data);
-        header.data_size = final_data.len() as u64;
+        header.checksum_algorithm = self.checksum;
+        header.checksum = checksum;
+        header.data_size = data_size;
 
-        let header_bytes = bincode::serialize(&header)?;
-        header.data_offset = header_bytes.len() as u64;
-
-        let final_header_bytes = bincode::serialize(&header)?;
+        header.data_offset = HEADER_SIZE;
+        if header.format == PackFormat::Rkyv {
+            header.data_offset = header.data_offset.next_multiple_of(DATA_ALIGNMENT);
+        }
+    }
 
-        let mut result = Vec::with_capacity(final_header_bytes.len() + final_data.len());
-        result.extend_from_slice(&final_header_bytes);
-        result.extend_from_slice(&final_data);
+    fn frame_into(&self, mut header: SnapshotHeader, final_data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        self.finish_header(&mut header, self.checksum.digest(final_data), final_data.len() as u64);
 
-        Ok(result)
-    }
+        out.clear();
+        out.reserve(header.data_offset as usize + final_data.len());
+        header.encode_into(out)?;
+        out.resize(header.data_offset as usize, 0);
+        out.extend_from_slice(final_data);
+        // The trailing copy lets a clobbered leading header be restored.
+        header.encode_into(out)?;
 
-    fn serialize_snapshot(&self, snapshot: &PackedSnapshot) -> Result<Vec<u8>> {
-        match snapshot.header.format {
-            PackFormat::Bincode => {
-                bincode::serialize(snapshot)
-                    .map_err(|e| PackError::Serialization(e.to_string()))
-            }
-            PackFormat::MessagePack => {
-                rmp_serde::to_vec(snapshot)
-                    .map_err(|e| PackError::Serialization(e.to_string()))
-            }
-            PackFormat::Custom => {
-                Err(PackError::Serialization("Custom format not implemented".to_string()))
-            }
-        }
+        Ok(())
     }
 
-    fn compute_checksum(&self, data: &[u8]) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        hasher.finalize().into()
+    pub fn stream_to<W: Write + Seek>(&self, out: W, header: SnapshotHeader) -> Result<StreamingWriter<'_, W>> {
+        StreamingWriter::new(self, out, header)
     }
 }
 
@@ -149,9 +394,23 @@ impl Default for SnapshotWriter {
     }
 }
 
+/// `HeaderOnly` still catches truncated files and torn header updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerifyMode {
+    #[default]
+    Full,
+    HeaderOnly,
+    Skip,
+}
+
+#[derive(Clone)]
 pub struct SnapshotReader {
     #[cfg(feature = "encryption")]
     encryption_key: Option<EncryptionKey>,
+    pipelined: bool,
+    limits: ParseLimits,
+    verify_mode: VerifyMode,
+    registry: Option<Arc<ComponentRegistry>>,
 }
 
 impl SnapshotReader {
@@ -159,6 +418,10 @@ impl SnapshotReader {
         Self {
             #[cfg(feature = "encryption")]
             encryption_key: None,
+            pipelined: false,
+            limits: ParseLimits::default(),
+            verify_mode: VerifyMode::default(),
+            registry: None,
         }
     }
 
@@ -168,112 +431,274 @@ impl SnapshotReader {
         self
     }
 
-    pub fn read_from_file<P: AsRef<Path>>(&self, path: P) -> Result<PackedSnapshot> {
-        let mut file = File::open(path)?;
+    pub fn with_pipelining(mut self, enabled: bool) -> Self {
+        self.pipelined = enabled;
+        self
+    }
 
-        let mut all_data = Vec::new();
-        file.read_to_end(&mut all_data)?;
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 
-        let header: SnapshotHeader = bincode::deserialize(&all_data)?;
-        header.validate()?;
+    pub fn with_verify_mode(mut self, mode: VerifyMode) -> Self {
+        self.verify_mode = mode;
+        self
+    }
 
-        let data_start = header.data_offset as usize;
-        let data_end = data_start + header.data_size as usize;
+    pub fn with_registry(mut self, registry: Arc<ComponentRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
 
-        if data_end > all_data.len() {
-            return Err(PackError::InvalidFormat(
-                format!("Data end {} exceeds file length {}", data_end, all_data.len())
-            ));
+    pub(crate) fn interpret(&self, archetype: ComponentArchetype) -> Result<ComponentArchetype> {
+        match &self.registry {
+            Some(registry) => registry.interpret(archetype),
+            None => Ok(archetype),
         }
+    }
 
-        let data = &all_data[data_start..data_end];
+    pub(crate) fn limits(&self) -> &ParseLimits {
+        &self.limits
+    }
 
-        self.verify_checksum(data, &header.checksum)?;
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(path = %path.as_ref().display())))]
+    pub fn read_from_file<P: AsRef<Path>>(&self, path: P) -> Result<PackedSnapshot> {
+        let stopwatch = Stopwatch::start();
+        let read = |path: &Path| {
+            let mut file = self.open_file(path)?;
+            let header = self.read_header(&mut file, MAGIC_NUMBER)?;
+            let data = self.read_verified_data(&mut file, &header)?;
+            let snapshot = self.snapshot_from_data(header, &data)?;
+            snapshot.check_consistency()?;
+            Ok((snapshot, data.len()))
+        };
+        let (snapshot, size) = read(path.as_ref()).with_path(path.as_ref())?;
+        stopwatch.record_read("snapshot", HEADER_SIZE as usize + size);
+        Ok(snapshot)
+    }
 
-        let decompressed = if header.encrypted {
-            #[cfg(feature = "encryption")]
-            {
-                let key = self.encryption_key.as_ref()
-                    .ok_or_else(|| PackError::Decryption("No encryption key provided".to_string()))?;
-                let decrypted = decrypt_snapshot(data, key)?;
-                decompress(&decrypted, header.compression)?
-            }
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(bytes = bytes.len())))]
+    pub fn read_from_bytes(&self, bytes: &[u8]) -> Result<PackedSnapshot> {
+        let stopwatch = Stopwatch::start();
+        let (header, data) = self.verified_data(bytes, MAGIC_NUMBER)?;
+        let snapshot = self.snapshot_from_data(header, data)?;
+        snapshot.check_consistency()?;
+        stopwatch.record_read("snapshot", bytes.len());
+        Ok(snapshot)
+    }
 
-            #[cfg(not(feature = "encryption"))]
-            {
-                return Err(PackError::Decryption("Snapshot is encrypted but encryption feature is disabled".to_string()));
+    pub fn read_header_from_file<P: AsRef<Path>>(&self, path: P) -> Result<SnapshotHeader> {
+        let path = path.as_ref();
+        self.open_file(path)
+            .and_then(|mut file| self.read_header(&mut file, MAGIC_NUMBER))
+            .with_path(path)
+    }
+
+    pub fn read_lazy_from_file<P: AsRef<Path>>(&self, path: P) -> Result<LazySnapshot> {
+        let read = |path: &Path| {
+            let mut file = self.open_file(path)?;
+            let header = self.read_header(&mut file, MAGIC_NUMBER)?;
+            if header.format == PackFormat::Custom {
+                self.verify_file_trailer(&mut file, &header)?;
+                // With chunk checksums each section is verified as it is read instead.
+                if header.flags & FLAG_CHUNK_CRC32C == 0 && self.verify_mode == VerifyMode::Full {
+                    verify_file_checksum(&mut file, &header).in_stage(PipelineStage::Verify)?;
+                }
+                return LazySnapshot::from_file(header, file, self.clone());
             }
+
+            let data = self.read_verified_data(&mut file, &header)?;
+            let snapshot = self.snapshot_from_data(header, &data)?;
+            snapshot.check_consistency()?;
+            Ok(LazySnapshot::from_snapshot(snapshot))
+        };
+        read(path.as_ref()).with_path(path.as_ref())
+    }
+
+    pub fn read_lazy_from_bytes(&self, bytes: &[u8]) -> Result<LazySnapshot> {
+        let (header, data) = self.verified_data(bytes, MAGIC_NUMBER)?;
+        if header.format == PackFormat::Custom {
+            return LazySnapshot::from_sections(header, data.to_vec(), self.clone());
+        }
+
+        let snapshot = self.deserialize_snapshot(&self.open(&header, data)?, &header)?;
+        snapshot.check_consistency()?;
+        Ok(LazySnapshot::from_snapshot(snapshot))
+    }
+
+    #[cfg(feature = "arena")]
+    pub fn read_arena_from_file<'a, P: AsRef<Path>>(&self, path: P, arena: &'a Bump) -> Result<ArenaSnapshot<'a>> {
+        let read = |path: &Path| {
+            let mut file = self.open_file(path)?;
+            let header = self.read_header(&mut file, MAGIC_NUMBER)?;
+            let data = self.read_verified_data(&mut file, &header)?;
+            let budget = self.limits.budget();
+            arena::decode_snapshot(header.clone(), &data, &self.limits, |sealed| self.open_with(&header, sealed, &budget), arena)
+        };
+        read(path.as_ref()).with_path(path.as_ref())
+    }
+
+    #[cfg(feature = "arena")]
+    pub fn read_arena_from_bytes<'a>(&self, bytes: &[u8], arena: &'a Bump) -> Result<ArenaSnapshot<'a>> {
+        let (header, data) = self.verified_data(bytes, MAGIC_NUMBER)?;
+        let budget = self.limits.budget();
+        arena::decode_snapshot(header.clone(), data, &self.limits, |sealed| self.open_with(&header, sealed, &budget), arena)
+    }
+
+    pub fn read_delta_from_file<P: AsRef<Path>>(&self, path: P) -> Result<DeltaSnapshot> {
+        let stopwatch = Stopwatch::start();
+        let read = |path: &Path| {
+            let mut file = self.open_file(path)?;
+            let header = self.read_header(&mut file, DELTA_MAGIC_NUMBER)?;
+            let data = self.read_verified_data(&mut file, &header)?;
+            let delta = deserialize_versioned::<_, DeltaSnapshotV2_0, DeltaSnapshotV2_1>(&self.open(&header, &data)?, &header)
+                .in_stage(PipelineStage::Decode)?;
+            Ok((delta, data.len()))
+        };
+        let (delta, size) = read(path.as_ref()).with_path(path.as_ref())?;
+        stopwatch.record_read("delta", HEADER_SIZE as usize + size);
+        Ok(delta)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(bytes = bytes.len())))]
+    pub fn read_delta_from_bytes(&self, bytes: &[u8]) -> Result<DeltaSnapshot> {
+        let stopwatch = Stopwatch::start();
+        let (header, decompressed) = self.decode(bytes, DELTA_MAGIC_NUMBER)?;
+        let delta = deserialize_versioned::<_, DeltaSnapshotV2_0, DeltaSnapshotV2_1>(&decompressed, &header)
+            .in_stage(PipelineStage::Decode)?;
+        stopwatch.record_read("delta", bytes.len());
+        Ok(delta)
+    }
+
+    pub(crate) fn snapshot_from_data(&self, header: SnapshotHeader, data: &[u8]) -> Result<PackedSnapshot> {
+        let budget = self.limits.budget();
+
+        let mut snapshot = if header.format == PackFormat::Custom && self.pipelined {
+            sectioned::decode_snapshot_pipelined(
+                header.clone(),
+                data,
+                &self.limits,
+                |sealed| self.decrypt(&header, sealed),
+                |decrypted| budget.decompress(decrypted, header.compression).in_stage(PipelineStage::Decompress),
+            )?
+        } else if header.format == PackFormat::Custom {
+            sectioned::decode_snapshot(header.clone(), data, &self.limits, |sealed| self.open_with(&header, sealed, &budget))?
         } else {
-            decompress(data, header.compression)?
+            self.deserialize_snapshot(&self.open_with(&header, data, &budget)?, &header)?
         };
 
-        self.deserialize_snapshot(&decompressed, header.format)
+        self.limits.check_snapshot(&snapshot)?;
+        if let Some(registry) = &self.registry {
+            registry.interpret_snapshot(&mut snapshot).in_stage(PipelineStage::Decode)?;
+        }
+        Ok(snapshot)
+    }
+
+    fn open_file(&self, path: &Path) -> Result<File> {
+        File::open(path).map_err(PackError::from).in_stage(PipelineStage::Read)
     }
 
-    pub fn read_from_bytes(&self, bytes: &[u8]) -> Result<PackedSnapshot> {
-        let header: SnapshotHeader = bincode::deserialize(bytes)?;
-        header.validate()?;
+    fn read_header(&self, file: &mut File, magic: &[u8; 8]) -> Result<SnapshotHeader> {
+        let header = read_header_from(file, magic).in_stage(PipelineStage::Read)?;
+        self.limits.check_header(&header)?;
+        Ok(header)
+    }
+
+    fn read_verified_data(&self, file: &mut File, header: &SnapshotHeader) -> Result<Vec<u8>> {
+        self.verify_file_trailer(file, header)?;
+        let data = read_at(file, header.data_offset, header.data_size).in_stage(PipelineStage::Read)?;
+        if self.verify_mode == VerifyMode::Full {
+            header.checksum_algorithm.verify(&data, &header.checksum).in_stage(PipelineStage::Verify)?;
+        }
+        Ok(data)
+    }
+
+    fn verify_file_trailer(&self, file: &mut File, header: &SnapshotHeader) -> Result<()> {
+        if self.verify_mode == VerifyMode::Skip || header.is_legacy() {
+            return Ok(());
+        }
+        let data_end = bounds::end(header.data_offset, header.data_size, "Data")?;
+        let trailer = read_at(file, data_end, HEADER_SIZE).in_stage(PipelineStage::Read)?;
+        check_trailer(header, &trailer).in_stage(PipelineStage::Verify)
+    }
+
+    pub(crate) fn decode(&self, bytes: &[u8], magic: &[u8; 8]) -> Result<(SnapshotHeader, Vec<u8>)> {
+        let (header, data) = self.verified_data(bytes, magic)?;
+        let decompressed = self.open(&header, data)?;
+        Ok((header, decompressed))
+    }
 
-        let data_start = header.data_offset as usize;
-        let data_end = data_start + header.data_size as usize;
+    pub(crate) fn verified_data<'a>(&self, bytes: &'a [u8], magic: &[u8; 8]) -> Result<(SnapshotHeader, &'a [u8])> {
+        let header = SnapshotHeader::decode(bytes)?;
+        header.validate_with_magic(magic)?;
+        self.limits.check_header(&header)?;
 
-        if data_end > bytes.len() {
-            return Err(PackError::InvalidFormat(
-                format!("Data end {} exceeds buffer length {}", data_end, bytes.len())
-            ));
+        let data = bounds::slice(bytes, header.data_offset, header.data_size, "Data")?;
+
+        if self.verify_mode != VerifyMode::Skip && !header.is_legacy() {
+            let data_end = header.data_offset + header.data_size;
+            let trailer = bounds::slice(bytes, data_end, HEADER_SIZE, "Trailing header")?;
+            check_trailer(&header, trailer).in_stage(PipelineStage::Verify)?;
+        }
+        if self.verify_mode == VerifyMode::Full {
+            header.checksum_algorithm.verify(data, &header.checksum).in_stage(PipelineStage::Verify)?;
         }
 
-        let data = &bytes[data_start..data_end];
+        Ok((header, data))
+    }
+
+    pub(crate) fn open(&self, header: &SnapshotHeader, data: &[u8]) -> Result<Vec<u8>> {
+        self.open_with(header, data, &self.limits.budget())
+    }
+
+    pub(crate) fn open_with(&self, header: &SnapshotHeader, data: &[u8], budget: &DecompressionBudget) -> Result<Vec<u8>> {
+        let decrypted = self.decrypt(header, data)?;
+        budget.decompress(&decrypted, header.compression).in_stage(PipelineStage::Decompress)
+    }
 
-        self.verify_checksum(data, &header.checksum)?;
+    fn decrypt<'a>(&self, header: &SnapshotHeader, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        let data = if header.flags & FLAG_CHUNK_CRC32C == 0 {
+            data
+        } else if self.verify_mode == VerifyMode::Full {
+            strip_crc32c(data).in_stage(PipelineStage::Verify)?
+        } else {
+            skip_crc32c(data)?
+        };
 
-        let decompressed = if header.encrypted {
+        if header.encrypted {
             #[cfg(feature = "encryption")]
             {
                 let key = self.encryption_key.as_ref()
                     .ok_or_else(|| PackError::Decryption("No encryption key provided".to_string()))?;
-                let decrypted = decrypt_snapshot(data, key)?;
-                decompress(&decrypted, header.compression)?
+                Ok(Cow::Owned(decrypt_snapshot(data, key).in_stage(PipelineStage::Decrypt)?))
             }
 
             #[cfg(not(feature = "encryption"))]
             {
-                return Err(PackError::Decryption("Snapshot is encrypted but encryption feature is disabled".to_string()));
+                Err(PackError::Decryption("Snapshot is encrypted but encryption feature is disabled".to_string()))
             }
         } else {
-            decompress(data, header.compression)?
-        };
-
-        self.deserialize_snapshot(&decompressed, header.format)
-    }
-
-    fn deserialize_snapshot(&self, data: &[u8], format: PackFormat) -> Result<PackedSnapshot> {
-        match format {
-            PackFormat::Bincode => {
-                bincode::deserialize(data)
-                    .map_err(|e| PackError::Deserialization(e.to_string()))
-            }
-            PackFormat::MessagePack => {
-                rmp_serde::from_slice(data)
-                    .map_err(|e| PackError::Deserialization(e.to_string()))
-            }
-            PackFormat::Custom => {
-                Err(PackError::Deserialization("Custom format not implemented".to_string()))
-            }
+            Ok(Cow::Borrowed(data))
         }
     }
 
-    fn verify_checksum(&self, data: &[u8], expected: &[u8; 32]) -> Result<()> {
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let actual: [u8; 32] = hasher.finalize().into();
-
-        if &actual != expected {
-            return Err(PackError::ChecksumMismatch);
-        }
-
-        Ok(())
+    fn deserialize_snapshot(&self, data: &[u8], header: &SnapshotHeader) -> Result<PackedSnapshot> {
+        let decoded: Result<PackedSnapshot> = match header.format {
+            PackFormat::FlatBuffers => flatbuffer::decode_snapshot(data).map(|mut snapshot| {
+                snapshot.header = header.clone();
+                snapshot
+            }),
+            #[cfg(feature = "rkyv")]
+            PackFormat::Rkyv => archive::decode_snapshot(data),
+            _ if header.is_legacy() => legacy::decode_snapshot(data, header),
+            _ => deserialize_versioned::<PackedSnapshot, PackedSnapshotV2_0, PackedSnapshotV2_1>(data, header),
+        };
+        let mut snapshot = decoded.in_stage(PipelineStage::Decode)?;
+        snapshot.ensure_entity_index();
+        Ok(snapshot)
     }
+
 }
 
 impl Default for SnapshotReader {
@@ -282,95 +707,809 @@ impl Default for SnapshotReader {
     }
 }
 
-pub struct SnapshotStore {
-    root_dir: PathBuf,
+pub(crate) fn serialize_value<T: Serialize>(value: &T, format: PackFormat) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    serialize_value_into(value, format, &mut bytes)?;
+    Ok(bytes)
 }
 
-impl SnapshotStore {
-    pub fn new<P: AsRef<Path>>(root_dir: P) -> Result<Self> {
-        let root_dir = root_dir.as_ref().to_path_buf();
-        std::fs::create_dir_all(&root_dir)?;
+fn serialize_value_into<T: Serialize>(value: &T, format: PackFormat, out: &mut Vec<u8>) -> Result<()> {
+    out.clear();
+    match format {
+        PackFormat::Bincode => {
+            bincode::serialize_into(out, value)
+                .map_err(|e| PackError::Serialization(e.to_string()))
+        }
+        PackFormat::MessagePack => {
+            rmp_serde::encode::write(out, value)
+                .map_err(|e| PackError::Serialization(e.to_string()))
+        }
+        PackFormat::Cbor => {
+            ciborium::into_writer(value, out)
+                .map_err(|e| PackError::Serialization(e.to_string()))
+        }
+        PackFormat::Custom => {
+            Err(PackError::Serialization("Custom format only supports snapshots".to_string()))
+        }
+        PackFormat::FlatBuffers => {
+            Err(PackError::Serialization("FlatBuffers format only supports snapshots".to_string()))
+        }
+        PackFormat::Rkyv => {
+            Err(PackError::Serialization("Rkyv format only supports snapshots and requires the rkyv feature".to_string()))
+        }
+    }
+}
 
-        Ok(Self { root_dir })
+// Decodes bincode payloads written before the current minor version
+// through the 2.0 or 2.1 layout; self-describing formats fill the defaults
+// in on their own.
+pub(crate) fn deserialize_versioned<T, L0, L1>(data: &[u8], header: &SnapshotHeader) -> Result<T>
+where
+    T: DeserializeOwned,
+    L0: DeserializeOwned + Into<T>,
+    L1: DeserializeOwned + Into<T>,
+{
+    match (header.format, header.version_minor()) {
+        (PackFormat::Bincode, 0) => deserialize_value::<L0>(data, header.format).map(Into::into),
+        (PackFormat::Bincode, 1) => deserialize_value::<L1>(data, header.format).map(Into::into),
+        _ => deserialize_value(data, header.format),
     }
+}
 
-    pub fn save(
-        &self,
-        snapshot: &PackedSnapshot,
-        metadata: &SnapshotMetadata,
-        writer: &SnapshotWriter,
-    ) -> Result<PathBuf> {
-        let filename = format!("{}.tx2pack", metadata.id);
-        let path = self.root_dir.join(&filename);
+pub(crate) fn deserialize_value<T: DeserializeOwned>(data: &[u8], format: PackFormat) -> Result<T> {
+    match format {
+        PackFormat::Bincode => {
+            bincode::deserialize(data)
+                .map_err(|e| PackError::Deserialization(e.to_string()))
+        }
+        PackFormat::MessagePack => {
+            rmp_serde::from_slice(data)
+                .map_err(|e| PackError::Deserialization(e.to_string()))
+        }
+        PackFormat::Cbor => {
+            ciborium::from_reader(data)
+                .map_err(|e| PackError::Deserialization(e.to_string()))
+        }
+        PackFormat::Custom => {
+            Err(PackError::Deserialization("Custom format only supports snapshots".to_string()))
+        }
+        PackFormat::FlatBuffers => {
+            Err(PackError::Deserialization("FlatBuffers format only supports snapshots".to_string()))
+        }
+        PackFormat::Rkyv => {
+            Err(PackError::Deserialization("Rkyv format only supports snapshots and requires the rkyv feature".to_string()))
+        }
+    }
+}
 
-        writer.write_to_file(snapshot, &path)?;
+pub(crate) fn file_checksum(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-        let metadata_path = self.root_dir.join(format!("{}.meta.json", metadata.id));
-        let metadata_json = serde_json::to_string_pretty(metadata)?;
-        std::fs::write(metadata_path, metadata_json)?;
+pub(crate) fn read_header_from(file: &mut File, magic: &[u8; 8]) -> Result<SnapshotHeader> {
+    // 1.x headers are shorter, so a small 1.x file may end before HEADER_SIZE.
+    let mut encoded = Vec::with_capacity(HEADER_SIZE as usize);
+    file.seek(SeekFrom::Start(0))?;
+    file.take(HEADER_SIZE).read_to_end(&mut encoded)?;
 
-        Ok(path)
-    }
+    let header = SnapshotHeader::decode(&encoded)?;
+    header.validate_with_magic(magic)?;
+    Ok(header)
+}
 
-    pub fn load(&self, id: &str, reader: &SnapshotReader) -> Result<(PackedSnapshot, SnapshotMetadata)> {
-        let filename = format!("{}.tx2pack", id);
-        let path = self.root_dir.join(&filename);
+// Both copies are encoded from the same header, so any difference means one
+// of them was cut short or only half rewritten.
+fn check_trailer(header: &SnapshotHeader, trailer: &[u8]) -> Result<()> {
+    let mut encoded = Vec::with_capacity(HEADER_SIZE as usize);
+    header.encode_into(&mut encoded)?;
+    if encoded != trailer {
+        return Err(PackError::InvalidFormat("Trailing header copy does not match the header".to_string()));
+    }
+    Ok(())
+}
 
-        if !path.exists() {
-            return Err(PackError::SnapshotNotFound(id.to_string()));
-        }
+pub(crate) fn read_at(file: &mut File, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let file_len = file.metadata()?.len();
+    bounds::end_within(offset, len, file_len, "Range")?;
 
-        let snapshot = reader.read_from_file(&path)?;
+    let mut bytes = vec![0u8; bounds::to_usize(len, "Range")?];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
 
-        let metadata_path = self.root_dir.join(format!("{}.meta.json", id));
-        let metadata = if metadata_path.exists() {
-            let metadata_json = std::fs::read_to_string(metadata_path)?;
-            serde_json::from_str(&metadata_json)?
-        } else {
-            SnapshotMetadata::new(id.to_string())
-        };
+pub(crate) fn verify_file_checksum(file: &mut File, header: &SnapshotHeader) -> Result<()> {
+    let mut hasher = header.checksum_algorithm.hasher();
+    let mut chunk = vec![0u8; 64 * 1024];
+    let mut remaining = header.data_size;
+
+    file.seek(SeekFrom::Start(header.data_offset))?;
+    while remaining > 0 {
+        let len = remaining.min(chunk.len() as u64) as usize;
+        file.read_exact(&mut chunk[..len])?;
+        hasher.update(&chunk[..len]);
+        remaining -= len as u64;
+    }
 
-        Ok((snapshot, metadata))
+    if hasher.finalize() != header.checksum {
+        return Err(PackError::ChecksumMismatch);
     }
 
-    pub fn delete(&self, id: &str) -> Result<()> {
-        let filename = format!("{}.tx2pack", id);
-        let path = self.root_dir.join(&filename);
+    Ok(())
+}
 
-        if path.exists() {
-            std::fs::remove_file(path)?;
-        }
+pub(crate) fn sync_dir(dir: &Path) -> Result<()> {
+    // Directories cannot be opened as files on Windows; NTFS journals the
+    // entry with the file itself.
+    #[cfg(unix)]
+    File::open(if dir.as_os_str().is_empty() { Path::new(".") } else { dir })?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
 
-        let metadata_path = self.root_dir.join(format!("{}.meta.json", id));
-        if metadata_path.exists() {
-            std::fs::remove_file(metadata_path)?;
-        }
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path)?;
 
-        Ok(())
-    }
+    let mut all_data = Vec::new();
+    file.read_to_end(&mut all_data)?;
 
-    pub fn list(&self) -> Result<Vec<String>> {
-        let mut snapshots = Vec::new();
+    Ok(all_data)
+}
 
-        for entry in std::fs::read_dir(&self.root_dir)? {
-            let entry = entry?;
-            let path = entry.path();
+pub fn validate_snapshot_id(id: &str) -> Result<()> {
+    let mut components = Path::new(id).components();
+    let single = matches!(components.next(), Some(Component::Normal(name)) if name == id);
+    if !single || components.next().is_some() || id.contains(['/', '\\', '\0']) || id.contains("..") {
+        return Err(PackError::InvalidSnapshotId(id.to_string()));
+    }
+    Ok(())
+}
 
-            if let Some(ext) = path.extension() {
-                if ext == "tx2pack" {
-                    if let Some(stem) = path.file_stem() {
-                        snapshots.push(stem.to_string_lossy().to_string());
-                    }
-                }
-            }
-        }
+pub(crate) struct SnapshotLock {
+    path: PathBuf,
+}
 
-        Ok(snapshots)
+impl Drop for SnapshotLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
     }
 }
 
-#[cfg(test)]
-mod tests {
+fn lock_file(path: PathBuf, what: &str) -> Result<SnapshotLock> {
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(SnapshotLock { path }),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let age = std::fs::metadata(&path)
+            .and_then(|lock| lock.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if age.is_some_and(|age| age > STALE_LOCK) {
+            let _ = std::fs::remove_file(&path);
+            continue;
+        }
+        if Instant::now() >= deadline {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("{} is locked by another writer", what),
+            )
+            .into());
+        }
+        std::thread::sleep(LOCK_RETRY);
+    }
+}
+
+pub struct SnapshotStore {
+    root_dir: PathBuf,
+    metadata_schema: Option<MetadataSchema>,
+    fsync_batch: usize,
+    unsynced: Mutex<Vec<PathBuf>>,
+    unsynced_dir: AtomicBool,
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    uring: Option<UringIo>,
+}
+
+impl SnapshotStore {
+    pub fn new<P: AsRef<Path>>(root_dir: P) -> Result<Self> {
+        let root_dir = root_dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root_dir)?;
+
+        Ok(Self {
+            root_dir,
+            metadata_schema: None,
+            fsync_batch: 1,
+            unsynced: Mutex::new(Vec::new()),
+            unsynced_dir: AtomicBool::new(false),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            uring: None,
+        })
+    }
+
+    pub(crate) fn root_dir(&self) -> &Path {
+        &self.root_dir
+    }
+
+    pub(crate) fn file_path(&self, id: &str, extension: &str) -> Result<PathBuf> {
+        validate_snapshot_id(id)?;
+        Ok(self.root_dir.join(format!("{}.{}", id, extension)))
+    }
+
+    pub fn with_metadata_schema(mut self, schema: MetadataSchema) -> Self {
+        self.metadata_schema = Some(schema);
+        self
+    }
+
+    pub fn with_fsync_batch(mut self, writes: usize) -> Self {
+        self.fsync_batch = writes.max(1);
+        self
+    }
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub fn with_io_uring(mut self, entries: u32) -> Result<Self> {
+        self.uring = Some(UringIo::new(entries)?);
+        Ok(self)
+    }
+
+    pub fn pending_sync(&self) -> usize {
+        self.unsynced.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        let paths = std::mem::take(&mut *self.unsynced.lock().unwrap_or_else(|e| e.into_inner()));
+
+        for path in paths {
+            match File::open(&path) {
+                Ok(file) => file.sync_all()?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if self.unsynced_dir.swap(false, Ordering::AcqRel) {
+            sync_dir(&self.root_dir)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_snapshot_files(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        metadata: &SnapshotMetadata,
+        writer: &SnapshotWriter,
+    ) -> Result<()> {
+        let metadata_path = self.file_path(&metadata.id, "meta.json")?;
+        let metadata_json = serde_json::to_string_pretty(metadata)?;
+
+        let durability = writer.durability();
+        let sync = durability >= DurabilityLevel::Fsync && self.fsync_batch == 1;
+
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(uring) = self.uring.as_ref().filter(|_| !writer.is_throttled()) {
+            uring.write_files(&[
+                UringWrite { path, bytes, sync },
+                UringWrite { path: &metadata_path, bytes: metadata_json.as_bytes(), sync: false },
+            ])?;
+
+            return self.finish_durability(path, durability, sync);
+        }
+
+        let level = if sync { DurabilityLevel::Fsync } else { durability.min(DurabilityLevel::Flush) };
+        writer.write_file(path, bytes, level)?;
+        std::fs::write(metadata_path, metadata_json)?;
+
+        self.finish_durability(path, durability, sync)
+    }
+
+    // The directory is synced once both the data and metadata entries exist.
+    fn finish_durability(&self, path: &Path, durability: DurabilityLevel, synced: bool) -> Result<()> {
+        if durability < DurabilityLevel::Fsync {
+            return Ok(());
+        }
+        if !synced {
+            return self.defer_sync(path, durability);
+        }
+        if durability == DurabilityLevel::FsyncDir {
+            sync_dir(&self.root_dir)?;
+        }
+        Ok(())
+    }
+
+    fn defer_sync(&self, path: &Path, durability: DurabilityLevel) -> Result<()> {
+        let pending = {
+            let mut unsynced = self.unsynced.lock().unwrap_or_else(|e| e.into_inner());
+            unsynced.push(path.to_path_buf());
+            unsynced.len()
+        };
+        if durability == DurabilityLevel::FsyncDir {
+            self.unsynced_dir.store(true, Ordering::Release);
+        }
+
+        if pending >= self.fsync_batch {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Saving metadata with a checksum set again is a `Conflict`; use
+    /// `save_versioned` to keep saving one loaded snapshot.
+    pub fn save(
+        &self,
+        snapshot: &PackedSnapshot,
+        metadata: &SnapshotMetadata,
+        writer: &SnapshotWriter,
+    ) -> Result<PathBuf> {
+        self.save_versioned(snapshot, &mut metadata.clone(), writer)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(id = %metadata.id)))]
+    pub fn save_versioned(
+        &self,
+        snapshot: &PackedSnapshot,
+        metadata: &mut SnapshotMetadata,
+        writer: &SnapshotWriter,
+    ) -> Result<PathBuf> {
+        if let Some(schema) = &self.metadata_schema {
+            schema.validate(metadata)?;
+        }
+
+        let path = self.file_path(&metadata.id, "tx2pack")?;
+        let bytes = writer.write_to_bytes(snapshot).with_snapshot_id(&metadata.id)?;
+
+        let _lock = self.lock_snapshot(&metadata.id)?;
+        self.check_conflict(metadata)?;
+        metadata.delta_base = None;
+        metadata.checksum = Some(file_checksum(&bytes));
+        self.write_snapshot_files(&path, &bytes, metadata, writer).with_snapshot_id(&metadata.id)?;
+
+        let delta_path = self.file_path(&metadata.id, "tx2delta")?;
+        if delta_path.exists() {
+            std::fs::remove_file(delta_path)?;
+        }
+
+        self.index_metadata(metadata, bytes.len() as u64)?;
+
+        Ok(path)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(id = %metadata.id)))]
+    pub fn save_delta(
+        &self,
+        delta: &DeltaSnapshot,
+        metadata: &SnapshotMetadata,
+        writer: &SnapshotWriter,
+    ) -> Result<PathBuf> {
+        let base_id = delta.base_id.clone().ok_or_else(|| {
+            PackError::Delta(format!("Delta {} has no base snapshot id", metadata.id))
+        })?;
+
+        if !self.exists(&base_id) {
+            return Err(PackError::SnapshotNotFound(base_id));
+        }
+
+        let mut metadata = metadata.clone();
+        metadata.delta_base = Some(base_id);
+
+        if let Some(schema) = &self.metadata_schema {
+            schema.validate(&metadata)?;
+        }
+
+        let path = self.file_path(&metadata.id, "tx2delta")?;
+        let bytes = writer.write_delta_to_bytes(delta).with_snapshot_id(&metadata.id)?;
+
+        let _lock = self.lock_snapshot(&metadata.id)?;
+        self.check_conflict(&metadata)?;
+        metadata.checksum = Some(file_checksum(&bytes));
+        self.write_snapshot_files(&path, &bytes, &metadata, writer).with_snapshot_id(&metadata.id)?;
+
+        let full_path = self.file_path(&metadata.id, "tx2pack")?;
+        if full_path.exists() {
+            std::fs::remove_file(full_path)?;
+        }
+
+        self.index_metadata(&metadata, bytes.len() as u64)?;
+
+        Ok(path)
+    }
+
+    // Writers hold this from their conflict check until the new files are in
+    // place, so two that loaded the same version cannot both pass the check.
+    pub(crate) fn lock_snapshot(&self, id: &str) -> Result<SnapshotLock> {
+        lock_file(self.file_path(id, "tx2lock")?, &format!("Snapshot {}", id))
+    }
+
+    pub(crate) fn check_conflict(&self, metadata: &SnapshotMetadata) -> Result<()> {
+        let expected = match &metadata.checksum {
+            Some(expected) => expected,
+            None => return Ok(()),
+        };
+
+        if !self.exists(&metadata.id) {
+            return Ok(());
+        }
+
+        let stored = self.load_metadata(&metadata.id)?;
+        if stored.checksum.as_ref() != Some(expected) {
+            return Err(PackError::Conflict {
+                ours: Box::new(metadata.clone()),
+                theirs: Box::new(stored),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn index_metadata(&self, metadata: &SnapshotMetadata, size_bytes: u64) -> Result<()> {
+        self.update_indexes(CatalogChange::Put(Box::new(CatalogEntry { metadata: metadata.clone(), size_bytes })))
+    }
+
+    fn read_snapshot_file(&self, path: &Path, reader: &SnapshotReader) -> Result<PackedSnapshot> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(uring) = &self.uring {
+            return uring.read_file(path).and_then(|bytes| reader.read_from_bytes(&bytes)).with_path(path);
+        }
+
+        reader.read_from_file(path)
+    }
+
+    fn read_delta_file(&self, path: &Path, reader: &SnapshotReader) -> Result<DeltaSnapshot> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(uring) = &self.uring {
+            return uring.read_file(path).and_then(|bytes| reader.read_delta_from_bytes(&bytes)).with_path(path);
+        }
+
+        reader.read_delta_from_file(path)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(id)))]
+    pub fn load(&self, id: &str, reader: &SnapshotReader) -> Result<(PackedSnapshot, SnapshotMetadata)> {
+        let mut deltas = Vec::new();
+        let mut current = id.to_string();
+
+        let mut snapshot = loop {
+            let path = self.file_path(&current, "tx2pack")?;
+            if path.exists() {
+                break self
+                    .read_snapshot_file(&path, reader)
+                    .map_err(|e| self.quarantine_if_corrupt(&current, e))
+                    .with_snapshot_id(&current)?;
+            }
+
+            let delta_path = self.file_path(&current, "tx2delta")?;
+            if !delta_path.exists() {
+                return Err(PackError::SnapshotNotFound(current));
+            }
+
+            let delta = self
+                .read_delta_file(&delta_path, reader)
+                .map_err(|e| self.quarantine_if_corrupt(&current, e))
+                .with_snapshot_id(&current)?;
+            let base_id = delta.base_id.clone().ok_or_else(|| {
+                PackError::Delta(format!("Delta {} has no base snapshot id", current))
+            })?;
+
+            if base_id == id || deltas.iter().any(|(delta_id, _): &(String, DeltaSnapshot)| *delta_id == base_id) {
+                return Err(PackError::Delta(format!("Delta chain cycle detected at {}", base_id)));
+            }
+
+            deltas.push((current, delta));
+            current = base_id;
+        };
+
+        for (delta_id, delta) in deltas.iter().rev() {
+            snapshot = delta.apply(&snapshot).with_snapshot_id(delta_id)?;
+        }
+
+        let metadata = self.load_metadata(id)?;
+
+        Ok((snapshot, metadata))
+    }
+
+    /// Reads only the header and table of contents of a stored pack; deltas
+    /// have to be applied to their base, so they are loaded in full.
+    pub fn load_lazy(&self, id: &str, reader: &SnapshotReader) -> Result<LazySnapshot> {
+        let path = self.file_path(id, "tx2pack")?;
+        if !path.exists() {
+            let (snapshot, _) = self.load(id, reader)?;
+            return Ok(LazySnapshot::from_snapshot(snapshot));
+        }
+
+        reader
+            .read_lazy_from_file(&path)
+            .map_err(|e| self.quarantine_if_corrupt(id, e))
+            .with_snapshot_id(id)
+    }
+
+    pub fn exists(&self, id: &str) -> bool {
+        self.data_path(id).is_some()
+    }
+
+    pub fn is_delta(&self, id: &str) -> bool {
+        self.file_path(id, "tx2delta").is_ok_and(|path| path.exists())
+    }
+
+    pub fn delta_dependents(&self, id: &str) -> Result<Vec<String>> {
+        Ok(self
+            .catalog()?
+            .entries()
+            .filter(|entry| entry.metadata.delta_base.as_deref() == Some(id))
+            .map(|entry| entry.metadata.id.clone())
+            .collect())
+    }
+
+    pub fn materialize(&self, id: &str, reader: &SnapshotReader, writer: &SnapshotWriter) -> Result<()> {
+        if !self.is_delta(id) {
+            return Ok(());
+        }
+
+        let (snapshot, mut metadata) = self.load(id, reader)?;
+        metadata.delta_base = None;
+
+        self.save(&snapshot, &metadata, writer)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn data_path(&self, id: &str) -> Option<PathBuf> {
+        ["tx2pack", "tx2delta"]
+            .into_iter()
+            .filter_map(|extension| self.file_path(id, extension).ok())
+            .find(|path| path.exists())
+    }
+
+    pub fn load_metadata(&self, id: &str) -> Result<SnapshotMetadata> {
+        let metadata_path = self.file_path(id, "meta.json")?;
+
+        if metadata_path.exists() {
+            let metadata_json = std::fs::read_to_string(metadata_path)?;
+            Ok(serde_json::from_str(&metadata_json)?)
+        } else {
+            Ok(SnapshotMetadata::new(id.to_string()))
+        }
+    }
+
+    pub fn lineage(&self, id: &str) -> Result<Vec<String>> {
+        let mut lineage = vec![id.to_string()];
+        let mut current = self.load_metadata(id)?.provenance.parent_id;
+
+        while let Some(parent_id) = current {
+            if lineage.contains(&parent_id) {
+                return Err(PackError::InvalidCheckpoint(
+                    format!("Lineage cycle detected at {}", parent_id)
+                ));
+            }
+
+            current = self.load_metadata(&parent_id)?.provenance.parent_id;
+            lineage.push(parent_id);
+        }
+
+        Ok(lineage)
+    }
+
+    pub fn children(&self, id: &str) -> Result<Vec<String>> {
+        Ok(self
+            .catalog()?
+            .entries()
+            .filter(|entry| entry.metadata.provenance.parent_id.as_deref() == Some(id))
+            .map(|entry| entry.metadata.id.clone())
+            .collect())
+    }
+
+    pub fn compare(&self, id_a: &str, id_b: &str, reader: &SnapshotReader) -> Result<ComparisonReport> {
+        let (snapshot_a, metadata_a) = self.load(id_a, reader)?;
+        let (snapshot_b, metadata_b) = self.load(id_b, reader)?;
+
+        let mut report = ComparisonReport::compare((&snapshot_a, &metadata_a), (&snapshot_b, &metadata_b))?;
+        report.file_size_a = self.file_size(id_a)?;
+        report.file_size_b = self.file_size(id_b)?;
+
+        Ok(report)
+    }
+
+    pub fn list_page(&self, options: &ListOptions) -> Result<ListPage> {
+        list_page(self, options)
+    }
+
+    pub fn query(&self) -> StoreQuery<'_> {
+        StoreQuery::new(self)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(id)))]
+    pub fn delete(&self, id: &str) -> Result<()> {
+        validate_snapshot_id(id)?;
+        let dependents = self.delta_dependents(id)?;
+        if !dependents.is_empty() {
+            return Err(PackError::Delta(format!(
+                "Cannot delete {}: deltas {} depend on it", id, dependents.join(", ")
+            )));
+        }
+
+        while let Some(path) = self.data_path(id) {
+            std::fs::remove_file(path)?;
+        }
+
+        for extension in ["meta.json", DELTA_LOG_EXTENSION] {
+            let path = self.file_path(id, extension)?;
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        self.update_indexes(CatalogChange::Remove(id.to_string()))?;
+
+        Ok(())
+    }
+
+    pub fn catalog(&self) -> Result<Catalog> {
+        Ok(self.load_indexes()?.0)
+    }
+
+    pub fn search_index(&self) -> Result<SearchIndex> {
+        Ok(self.load_indexes()?.1)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn rebuild_catalog(&self) -> Result<(Catalog, SearchIndex)> {
+        let ids = self.list()?;
+        let mut catalog = Catalog::new();
+        let mut index = SearchIndex::new();
+        let mut corrupt = Vec::new();
+
+        {
+            let _lock = self.lock_catalog()?;
+            for id in ids {
+                let metadata = match self.load_metadata(&id) {
+                    Ok(metadata) => metadata,
+                    Err(e) if e.is_corruption() => {
+                        corrupt.push((id, e));
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let size_bytes = self.file_size(&id)?;
+
+                index.index(&metadata);
+                catalog.insert(CatalogEntry { metadata, size_bytes });
+            }
+            self.write_indexes(&catalog, &index)?;
+        }
+
+        // Quarantining updates the indexes, so it waits for the lock to go.
+        for (id, e) in corrupt {
+            self.quarantine(&id, &e)?;
+        }
+
+        Ok((catalog, index))
+    }
+
+    pub fn search(&self, query: &str) -> Result<Vec<SnapshotMetadata>> {
+        let (catalog, index) = self.load_indexes()?;
+
+        Ok(index
+            .search(query)
+            .iter()
+            .filter_map(|id| catalog.get(id).map(|entry| entry.metadata.clone()))
+            .collect())
+    }
+
+    pub fn read_header(&self, id: &str) -> Result<SnapshotHeader> {
+        let path = self.data_path(id).ok_or_else(|| PackError::SnapshotNotFound(id.to_string()))?;
+        let magic = if self.is_delta(id) { DELTA_MAGIC_NUMBER } else { MAGIC_NUMBER };
+        read_header_from(&mut File::open(path)?, magic)
+    }
+
+    pub fn read_range(&self, id: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let path = self.data_path(id).ok_or_else(|| PackError::SnapshotNotFound(id.to_string()))?;
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut bytes = Vec::new();
+        file.take(len).read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    pub fn file_size(&self, id: &str) -> Result<u64> {
+        let path = self.data_path(id).ok_or_else(|| PackError::SnapshotNotFound(id.to_string()))?;
+        Ok(std::fs::metadata(path)?.len())
+    }
+
+    // The log is folded into the indexes once it passes CATALOG_LOG_COMPACT_BYTES.
+    pub(crate) fn update_indexes(&self, change: CatalogChange) -> Result<()> {
+        if !self.indexes_exist() {
+            // The rebuild reads the change back from disk.
+            return self.rebuild_catalog().map(|_| ());
+        }
+
+        let _lock = self.lock_catalog()?;
+        if append_catalog_log(&self.root_dir.join(CATALOG_LOG_FILE), &change)? > CATALOG_LOG_COMPACT_BYTES {
+            let (catalog, index) = self.read_indexes()?;
+            self.write_indexes(&catalog, &index)?;
+        }
+
+        Ok(())
+    }
+
+    fn load_indexes(&self) -> Result<(Catalog, SearchIndex)> {
+        {
+            let _lock = self.lock_catalog()?;
+            if self.indexes_exist() {
+                return self.read_indexes();
+            }
+        }
+        self.rebuild_catalog()
+    }
+
+    // Callers hold the catalog lock for both of these, so a reader never sees
+    // freshly compacted indexes together with the log they already contain.
+    fn read_indexes(&self) -> Result<(Catalog, SearchIndex)> {
+        let mut catalog = Catalog::load(self.root_dir.join(CATALOG_FILE))?;
+        let mut index = SearchIndex::load(self.root_dir.join(SEARCH_INDEX_FILE))?;
+        for change in read_catalog_log(&self.root_dir.join(CATALOG_LOG_FILE))? {
+            change.apply(&mut catalog, &mut index);
+        }
+
+        Ok((catalog, index))
+    }
+
+    fn write_indexes(&self, catalog: &Catalog, index: &SearchIndex) -> Result<()> {
+        catalog.save(self.root_dir.join(CATALOG_FILE))?;
+        index.save(self.root_dir.join(SEARCH_INDEX_FILE))?;
+        match std::fs::remove_file(self.root_dir.join(CATALOG_LOG_FILE)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn lock_catalog(&self) -> Result<SnapshotLock> {
+        lock_file(self.root_dir.join(CATALOG_LOCK_FILE), "The catalog")
+    }
+
+    pub(crate) fn indexes_exist(&self) -> bool {
+        self.root_dir.join(CATALOG_FILE).exists() && self.root_dir.join(SEARCH_INDEX_FILE).exists()
+    }
+
+    pub fn list(&self) -> Result<Vec<String>> {
+        let mut snapshots = Vec::new();
+
+        for entry in std::fs::read_dir(&self.root_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if let Some(ext) = path.extension() {
+                if ext == "tx2pack" || ext == "tx2delta" {
+                    if let Some(stem) = path.file_stem() {
+                        let id = stem.to_string_lossy().to_string();
+                        // Only the header is read, so listing stays cheap. Files
+                        // from a newer version are still listed.
+                        match self.read_header(&id) {
+                            Err(e) if e.is_corruption() => self.quarantine(&id, &e)?,
+                            _ => snapshots.push(id),
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(snapshots)
+    }
+}
+
+impl Drop for SnapshotStore {
+    fn drop(&mut self) {
+        let _ = self.sync();
+    }
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
     use crate::format::PackedSnapshot;
     use tempfile::TempDir;
@@ -388,6 +1527,177 @@ mod tests {
         assert_eq!(snapshot.header.version, loaded.header.version);
     }
 
+    #[test]
+    fn test_verify_modes_trade_checks_for_speed() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("trusted.tx2pack");
+        let readers = [VerifyMode::Full, VerifyMode::HeaderOnly, VerifyMode::Skip]
+            .map(|mode| SnapshotReader::new().with_verify_mode(mode));
+
+        for format in [PackFormat::Custom, PackFormat::Bincode] {
+            let mut snapshot = PackedSnapshot::new();
+            snapshot.header.format = format;
+            let mut bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+            let trailer = bytes.len() - HEADER_SIZE as usize;
+
+            // A stale digest in both header copies: only Full notices.
+            let mut header = SnapshotHeader::decode(&bytes).unwrap();
+            header.checksum[0] ^= 1;
+            let mut encoded = Vec::new();
+            header.encode_into(&mut encoded).unwrap();
+            bytes[..HEADER_SIZE as usize].copy_from_slice(&encoded);
+            bytes[trailer..].copy_from_slice(&encoded);
+            std::fs::write(&path, &bytes).unwrap();
+            let opened: Vec<bool> = readers.iter().map(|r| r.read_from_bytes(&bytes).is_ok()).collect();
+            assert_eq!(opened, [false, true, true]);
+            let opened: Vec<bool> = readers.iter().map(|r| r.read_from_file(&path).is_ok()).collect();
+            assert_eq!(opened, [false, true, true]);
+
+            // A torn trailing copy: only Skip lets it through.
+            bytes[trailer + 20] ^= 1;
+            std::fs::write(&path, &bytes).unwrap();
+            let opened: Vec<bool> = readers.iter().map(|r| r.read_from_bytes(&bytes).is_ok()).collect();
+            assert_eq!(opened, [false, false, true]);
+            let opened: Vec<bool> = readers.iter().map(|r| r.read_lazy_from_file(&path).is_ok()).collect();
+            assert_eq!(opened, [false, false, true]);
+        }
+    }
+
+    #[test]
+    fn test_header_is_fixed_size() {
+        let mut header = SnapshotHeader::new();
+        header.timestamp = i64::MIN;
+        header.entity_count = u64::MAX;
+        header.format = PackFormat::Rkyv;
+        let mut encoded = Vec::new();
+        header.encode_into(&mut encoded).unwrap();
+        assert_eq!(encoded.len() as u64, HEADER_SIZE);
+
+        let bytes = SnapshotWriter::new().write_to_bytes(&PackedSnapshot::new()).unwrap();
+        let decoded = SnapshotHeader::decode(&bytes).unwrap();
+        assert_eq!(decoded.data_offset, HEADER_SIZE);
+        assert!(SnapshotHeader::decode(&bytes[..HEADER_SIZE as usize - 1]).is_err());
+    }
+
+    #[test]
+    fn test_write_into_reuses_buffer() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.annotate(1, "label", crate::format::FieldValue::String("player".to_string()));
+
+        let writer = SnapshotWriter::new();
+        let mut buffer = Vec::new();
+        writer.write_to_bytes_into(&snapshot, &mut buffer).unwrap();
+        assert_eq!(buffer, writer.write_to_bytes(&snapshot).unwrap());
+
+        let ptr = buffer.as_ptr();
+        writer.write_to_bytes_into(&snapshot, &mut buffer).unwrap();
+        assert_eq!(buffer.as_ptr(), ptr);
+
+        let loaded = SnapshotReader::new().read_from_bytes(&buffer).unwrap();
+        assert!(loaded.annotation(1, "label").is_some());
+    }
+
+    #[test]
+    fn test_entity_annotations_roundtrip() {
+        use crate::format::FieldValue;
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.annotate_many([3, 5], "tracked", FieldValue::Bool(true));
+        snapshot.annotate(5, "suspect", FieldValue::String("desync".to_string()));
+
+        for format in [PackFormat::Bincode, PackFormat::MessagePack, PackFormat::Cbor] {
+            snapshot.header.format = format;
+
+            let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+            let loaded = SnapshotReader::new().read_from_bytes(&bytes).unwrap();
+
+            assert_eq!(loaded.annotations("tracked").len(), 2);
+            assert_eq!(
+                loaded.annotation(5, "suspect"),
+                Some(&FieldValue::String("desync".to_string()))
+            );
+            assert_eq!(loaded.annotation(3, "suspect"), None);
+        }
+
+        snapshot.clear_annotations("tracked");
+        assert!(snapshot.annotations("tracked").is_empty());
+        assert!(snapshot.remove_annotation(5, "suspect").is_some());
+    }
+
+    #[test]
+    fn test_entity_index_roundtrip() {
+        use crate::format::{ComponentArchetype, ComponentData, EntityLocation};
+
+        let mut snapshot = PackedSnapshot::new();
+        for (component, ids) in [("Position", vec![1, 2, 3]), ("Health", vec![3, 4])] {
+            snapshot.archetypes.push(ComponentArchetype {
+                component_id: component.to_string(),
+                entity_ids: ids,
+                data: ComponentData::Blob(Vec::new()),
+            });
+        }
+        snapshot.rebuild_entity_index();
+
+        let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+        let mut loaded = SnapshotReader::new().read_from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            loaded.locate(3),
+            vec![EntityLocation { archetype: 0, row: 2 }, EntityLocation { archetype: 1, row: 0 }]
+        );
+        assert!(!loaded.contains_entity(9));
+
+        loaded.archetypes[0].entity_ids.remove(0);
+        assert_eq!(loaded.locate(3)[0], EntityLocation { archetype: 0, row: 1 });
+    }
+
+    #[test]
+    fn test_resources_roundtrip_and_older_files_read_without_them() {
+        use crate::format::format_version;
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.resources.insert("rng.physics".to_string(), vec![7; 32]);
+        snapshot.resources.insert("schedule.tick".to_string(), 42u64.to_le_bytes().to_vec());
+
+        let formats = [PackFormat::Custom, PackFormat::Bincode, PackFormat::MessagePack, PackFormat::Cbor, PackFormat::FlatBuffers];
+        for format in formats {
+            snapshot.header.format = format;
+            let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+            let loaded = SnapshotReader::new().read_from_bytes(&bytes).unwrap();
+            assert_eq!(loaded.resources, snapshot.resources, "{:?}", format);
+            assert_eq!(loaded.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+        }
+        let bytes = SnapshotWriter::new().write_to_bytes(&snapshot).unwrap();
+        assert_eq!(SnapshotReader::new().read_lazy_from_bytes(&bytes).unwrap().resources().unwrap(), &snapshot.resources);
+
+        let mut target = snapshot.clone();
+        target.resources.insert("schedule.tick".to_string(), 43u64.to_le_bytes().to_vec());
+        target.resources.remove("rng.physics");
+        let delta = DeltaSnapshot::from_snapshots(&snapshot, &target).unwrap();
+        assert_eq!(delta.resource_changes.len(), 2);
+        assert_eq!(delta.apply(&snapshot).unwrap().resources, target.resources);
+
+        // A 2.0 bincode payload ends after the entity index.
+        let old = PackedSnapshot::new();
+        let payload = (&old.header, &old.archetypes, &old.entity_metadata, &old.entity_index);
+        let mut bytes = Vec::new();
+        SnapshotWriter::new().encode_value_into(old.header.clone(), &payload, PackFormat::Bincode, &mut bytes).unwrap();
+        let mut header = SnapshotHeader::decode(&bytes).unwrap();
+        header.version = format_version(2, 0);
+        let mut encoded = Vec::new();
+        header.encode_into(&mut encoded).unwrap();
+        let trailer = bytes.len() - encoded.len();
+        bytes[..encoded.len()].copy_from_slice(&encoded);
+        bytes[trailer..].copy_from_slice(&encoded);
+        assert!(SnapshotReader::new().read_from_bytes(&bytes).unwrap().resources.is_empty());
+
+        // So does a 2.0 table of contents after the entity metadata section.
+        let mut data = bincode::serialize(&(Vec::<sectioned::ArchetypeEntry>::new(), sectioned::Section::default())).unwrap();
+        data.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        let toc = sectioned::read_toc(&data, |sealed| Ok(sealed.to_vec())).unwrap();
+        assert_eq!(toc.resources, sectioned::Section::default());
+    }
+
     #[test]
     fn test_snapshot_store() {
         let temp_dir = TempDir::new().unwrap();
@@ -413,6 +1723,226 @@ mod tests {
         assert!(!snapshots.contains(&"test-snapshot".to_string()));
     }
 
+    #[test]
+    fn test_errors_carry_path_snapshot_component_and_stage() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Position".to_string(),
+            entity_ids: vec![1],
+            data: crate::format::ComponentData::Blob(vec![7; 4096]),
+        });
+        let path = store.save(&snapshot, &SnapshotMetadata::new("snap_ctx".to_string()), &SnapshotWriter::new()).unwrap();
+
+        // The table of contents fits the budget; the archetype section does not.
+        let reader = SnapshotReader::new().with_limits(ParseLimits::default().with_max_decompressed_bytes(1024));
+        let error = store.load("snap_ctx", &reader).unwrap_err();
+        assert!(matches!(error.root(), PackError::LimitExceeded { .. }));
+        let context = error.context().unwrap();
+        assert_eq!(context.path.as_deref(), Some(path.as_path()));
+        assert_eq!(context.snapshot_id.as_deref(), Some("snap_ctx"));
+        assert_eq!(context.component_id.as_deref(), Some("Position"));
+        assert_eq!(context.stage, Some(PipelineStage::Decompress));
+        assert!(error.to_string().contains("component Position, stage decompress"), "{}", error);
+    }
+
+    #[test]
+    fn test_store_batches_fsync_and_throttles_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap().with_fsync_batch(3);
+        let writer = SnapshotWriter::new().with_write_rate_limit(64 * 1024);
+
+        let snapshot = PackedSnapshot::new();
+        for id in ["a", "b"] {
+            store.save(&snapshot, &SnapshotMetadata::new(id.to_string()), &writer).unwrap();
+        }
+        assert_eq!(store.pending_sync(), 2);
+
+        store.save(&snapshot, &SnapshotMetadata::new("c".to_string()), &writer).unwrap();
+        assert_eq!(store.pending_sync(), 0);
+
+        let bytes = vec![7u8; 32 * 1024];
+        let path = temp_dir.path().join("paced.bin");
+        let started = std::time::Instant::now();
+        writer.write_file(&path, &bytes, DurabilityLevel::Fsync).unwrap();
+        assert!(started.elapsed() >= std::time::Duration::from_millis(400));
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_store_honors_writer_durability() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap().with_fsync_batch(2);
+        let snapshot = PackedSnapshot::new();
+
+        // Autosaves that skip fsync never queue anything for the batch.
+        for level in [DurabilityLevel::None, DurabilityLevel::Flush] {
+            let autosave = SnapshotWriter::new().with_durability(level);
+            store.save(&snapshot, &SnapshotMetadata::new(format!("auto_{:?}", level)), &autosave).unwrap();
+            assert_eq!(store.pending_sync(), 0);
+        }
+
+        let milestone = SnapshotWriter::new().with_durability(DurabilityLevel::FsyncDir);
+        store.save(&snapshot, &SnapshotMetadata::new("milestone".to_string()), &milestone).unwrap();
+        assert_eq!(store.pending_sync(), 1);
+        assert!(store.unsynced_dir.load(Ordering::Acquire));
+        store.sync().unwrap();
+        assert!(!store.unsynced_dir.load(Ordering::Acquire));
+
+        let unbatched = SnapshotStore::new(temp_dir.path()).unwrap();
+        unbatched.save(&snapshot, &SnapshotMetadata::new("milestone".to_string()), &milestone).unwrap();
+        assert_eq!(unbatched.pending_sync(), 0);
+
+        let reader = SnapshotReader::new();
+        for id in ["auto_None", "auto_Flush", "milestone"] {
+            assert!(store.load(id, &reader).is_ok());
+        }
+
+        let path = temp_dir.path().join("direct.tx2pack");
+        milestone.write_to_file(&snapshot, &path).unwrap();
+        assert!(reader.read_from_file(&path).is_ok());
+    }
+
+    #[test]
+    fn test_store_rejects_invalid_metadata() {
+        use crate::metadata::MetadataValueType;
+
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path())
+            .unwrap()
+            .with_metadata_schema(
+                MetadataSchema::new().with_required_field("build", MetadataValueType::String),
+            );
+
+        let writer = SnapshotWriter::new();
+        let missing = SnapshotMetadata::new("missing".to_string());
+        let result = store.save(&PackedSnapshot::new(), &missing, &writer);
+
+        assert!(matches!(result, Err(PackError::InvalidMetadata(_))));
+        assert!(store.list().unwrap().is_empty());
+
+        let valid = missing.with_custom_field("build".to_string(), "1.2.0".to_string());
+        store.save(&PackedSnapshot::new(), &valid, &writer).unwrap();
+        assert_eq!(store.list().unwrap(), vec!["missing"]);
+    }
+
+    #[test]
+    fn test_store_detects_concurrent_modification() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let writer = SnapshotWriter::new();
+        let reader = SnapshotReader::new();
+
+        store.save(&PackedSnapshot::new(), &SnapshotMetadata::new("world".to_string()), &writer).unwrap();
+
+        let (_, mut ours) = store.load("world", &reader).unwrap();
+        let (mut snapshot, mut theirs) = store.load("world", &reader).unwrap();
+        assert!(ours.checksum.is_some());
+
+        snapshot.header.timestamp += 1;
+        theirs.name = Some("Theirs".to_string());
+        store.save_versioned(&snapshot, &mut theirs, &writer).unwrap();
+        store.save_versioned(&snapshot, &mut theirs, &writer).unwrap();
+
+        ours.name = Some("Ours".to_string());
+        match store.save(&PackedSnapshot::new(), &ours, &writer) {
+            Err(PackError::Conflict { ours, theirs }) => {
+                assert_eq!(ours.name.as_deref(), Some("Ours"));
+                assert_eq!(theirs.name.as_deref(), Some("Theirs"));
+            }
+            other => panic!("expected conflict, got {:?}", other.map(|_| ())),
+        }
+
+        assert_eq!(store.load_metadata("world").unwrap().name.as_deref(), Some("Theirs"));
+
+        // Writers racing from the same version: exactly one gets through.
+        let (snapshot, loaded) = store.load("world", &reader).unwrap();
+        let saved = std::thread::scope(|scope| {
+            let writers: Vec<_> = (0..4)
+                .map(|i| {
+                    let (mut snapshot, mut metadata) = (snapshot.clone(), loaded.clone());
+                    let (store, writer) = (&store, &writer);
+                    scope.spawn(move || {
+                        snapshot.header.timestamp = 100 + i;
+                        store.save_versioned(&snapshot, &mut metadata, writer)
+                    })
+                })
+                .collect();
+            writers.into_iter().map(|writer| writer.join().unwrap()).filter(Result::is_ok).count()
+        });
+        assert_eq!(saved, 1);
+        assert!(!temp_dir.path().join("world.tx2lock").exists());
+    }
+
+    #[test]
+    fn test_store_search() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let writer = SnapshotWriter::new();
+
+        let desync = SnapshotMetadata::new("s1".to_string())
+            .with_description("Client desync during wave 3".to_string());
+        let other = SnapshotMetadata::new("s2".to_string()).with_name("Autosave".to_string());
+
+        store.save(&PackedSnapshot::new(), &desync, &writer).unwrap();
+        store.save(&PackedSnapshot::new(), &other, &writer).unwrap();
+
+        let found = store.search("desync").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, "s1");
+        assert_eq!(store.catalog().unwrap().len(), 2);
+
+        std::fs::remove_file(temp_dir.path().join(CATALOG_FILE)).unwrap();
+        std::fs::remove_file(temp_dir.path().join(SEARCH_INDEX_FILE)).unwrap();
+        assert_eq!(store.search("AUTOSAVE").unwrap()[0].id, "s2");
+
+        store.delete("s1").unwrap();
+        assert!(store.search("desync").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_catalog_changes_are_journaled() {
+        let temp_dir = TempDir::new().unwrap();
+        SnapshotStore::new(temp_dir.path()).unwrap().catalog().unwrap();
+        let base = std::fs::read(temp_dir.path().join(CATALOG_FILE)).unwrap();
+
+        // Separate stores on one directory stand in for separate processes.
+        let handles: Vec<_> = (0..4)
+            .map(|worker| {
+                let root = temp_dir.path().to_path_buf();
+                std::thread::spawn(move || {
+                    let (store, writer) = (SnapshotStore::new(root).unwrap(), SnapshotWriter::new());
+                    for n in 0..5 {
+                        let metadata = SnapshotMetadata::new(format!("w{}-{}", worker, n)).with_tag("raid".into());
+                        store.save(&PackedSnapshot::new(), &metadata, &writer).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        assert_eq!(std::fs::read(temp_dir.path().join(CATALOG_FILE)).unwrap(), base);
+        assert_eq!(store.catalog().unwrap().len(), 20);
+        assert_eq!(store.search("raid").unwrap().len(), 20);
+
+        // A torn append is skipped, and the next one starts a fresh line.
+        let log_path = temp_dir.path().join(CATALOG_LOG_FILE);
+        std::fs::OpenOptions::new().append(true).open(&log_path).unwrap().write_all(b"{\"Put\":{").unwrap();
+        store.delete("w0-0").unwrap();
+        assert_eq!(store.catalog().unwrap().len(), 19);
+
+        store.rebuild_catalog().unwrap();
+        assert!(!log_path.exists());
+        assert_eq!(store.catalog().unwrap().len(), 19);
+        assert!(!temp_dir.path().join(CATALOG_LOCK_FILE).exists());
+    }
+
     #[cfg(feature = "encryption")]
     #[test]
     fn test_encrypted_snapshot() {
@@ -0,0 +1,544 @@
+use crate::catalog::CatalogEntry;
+use crate::error::{PackError, Result};
+use crate::json::{from_hex, to_hex};
+use crate::metadata::SnapshotMetadata;
+use crate::selector::LabelSelector;
+use crate::storage::SnapshotStore;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    CreatedAt,
+    WorldTime,
+    Id,
+    Name,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+pub struct StoreQuery<'a> {
+    store: &'a SnapshotStore,
+    tags: Vec<String>,
+    created_after: Option<i64>,
+    created_before: Option<i64>,
+    world_time_after: Option<f64>,
+    world_time_before: Option<f64>,
+    name_contains: Option<String>,
+    custom_fields: Vec<(String, String)>,
+    selectors: Vec<LabelSelector>,
+    sort_key: SortKey,
+    sort_order: SortOrder,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl<'a> StoreQuery<'a> {
+    pub fn new(store: &'a SnapshotStore) -> Self {
+        Self {
+            store,
+            tags: Vec::new(),
+            created_after: None,
+            created_before: None,
+            world_time_after: None,
+            world_time_before: None,
+            name_contains: None,
+            custom_fields: Vec::new(),
+            selectors: Vec::new(),
+            sort_key: SortKey::default(),
+            sort_order: SortOrder::default(),
+            limit: None,
+            offset: 0,
+        }
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn created_after(mut self, timestamp: i64) -> Self {
+        self.created_after = Some(timestamp);
+        self
+    }
+
+    pub fn created_before(mut self, timestamp: i64) -> Self {
+        self.created_before = Some(timestamp);
+        self
+    }
+
+    pub fn world_time_after(mut self, time: f64) -> Self {
+        self.world_time_after = Some(time);
+        self
+    }
+
+    pub fn world_time_before(mut self, time: f64) -> Self {
+        self.world_time_before = Some(time);
+        self
+    }
+
+    pub fn with_name_containing(mut self, substring: &str) -> Self {
+        self.name_contains = Some(substring.to_lowercase());
+        self
+    }
+
+    pub fn with_custom_field(mut self, key: &str, value: &str) -> Self {
+        self.custom_fields.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_selector(mut self, selector: LabelSelector) -> Self {
+        self.selectors.push(selector);
+        self
+    }
+
+    pub fn with_label_selector(self, selector: &str) -> Result<Self> {
+        Ok(self.with_selector(LabelSelector::parse(selector)?))
+    }
+
+    pub fn sort_by(mut self, key: SortKey, order: SortOrder) -> Self {
+        self.sort_key = key;
+        self.sort_order = order;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn matches(&self, metadata: &SnapshotMetadata) -> bool {
+        if !self.tags.iter().all(|tag| metadata.tags.contains(tag)) {
+            return false;
+        }
+
+        if self.created_after.is_some_and(|t| metadata.created_at < t)
+            || self.created_before.is_some_and(|t| metadata.created_at > t)
+        {
+            return false;
+        }
+
+        if self.world_time_after.is_some_and(|t| metadata.world_time < t)
+            || self.world_time_before.is_some_and(|t| metadata.world_time > t)
+        {
+            return false;
+        }
+
+        if let Some(substring) = &self.name_contains {
+            let matches_name = metadata
+                .name
+                .as_ref()
+                .is_some_and(|name| name.to_lowercase().contains(substring));
+
+            if !matches_name {
+                return false;
+            }
+        }
+
+        if !self.selectors.iter().all(|selector| selector.matches(&metadata.labels)) {
+            return false;
+        }
+
+        self.custom_fields
+            .iter()
+            .all(|(key, value)| metadata.custom_fields.get(key) == Some(value))
+    }
+
+    pub fn execute(&self) -> Result<Vec<SnapshotMetadata>> {
+        let catalog = self.store.catalog()?;
+
+        let mut results: Vec<&CatalogEntry> = catalog
+            .entries()
+            .filter(|entry| self.matches(&entry.metadata))
+            .collect();
+
+        sort_entries(&mut results, self.sort_key, self.sort_order);
+
+        let page = results
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|entry| entry.metadata.clone())
+            .collect();
+
+        Ok(page)
+    }
+
+    pub fn count(&self) -> Result<usize> {
+        Ok(self
+            .store
+            .catalog()?
+            .entries()
+            .filter(|entry| self.matches(&entry.metadata))
+            .count())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    pub sort_key: SortKey,
+    pub sort_order: SortOrder,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub cursor: Option<String>,
+}
+
+impl ListOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sort_by(mut self, key: SortKey, order: SortOrder) -> Self {
+        self.sort_key = key;
+        self.sort_order = order;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn after(mut self, cursor: String) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListPage {
+    pub entries: Vec<CatalogEntry>,
+    pub total: usize,
+    pub next_cursor: Option<String>,
+}
+
+impl ListPage {
+    pub fn ids(&self) -> Vec<&str> {
+        self.entries.iter().map(|entry| entry.metadata.id.as_str()).collect()
+    }
+}
+
+pub(crate) fn list_page(store: &SnapshotStore, options: &ListOptions) -> Result<ListPage> {
+    let catalog = store.catalog()?;
+    let mut entries: Vec<&CatalogEntry> = catalog.entries().collect();
+    let total = entries.len();
+
+    sort_entries(&mut entries, options.sort_key, options.sort_order);
+
+    let start = match &options.cursor {
+        Some(cursor) => {
+            let cursor = ListCursor::decode(cursor, options)?;
+            entries.partition_point(|entry| cursor.covers(entry))
+        }
+        None => options.offset.min(total),
+    };
+
+    let limit = options.limit.unwrap_or(usize::MAX);
+    let page: Vec<CatalogEntry> = entries
+        .iter()
+        .skip(start)
+        .take(limit)
+        .map(|entry| (*entry).clone())
+        .collect();
+
+    let end = start + page.len();
+    let next_cursor = if end < total {
+        let last = end.checked_sub(1).map(|index| entries[index]);
+        Some(ListCursor::new(options, last).encode()?)
+    } else {
+        None
+    };
+
+    Ok(ListPage {
+        entries: page,
+        total,
+        next_cursor,
+    })
+}
+
+// A keyset cursor: the sort value and id of the last row handed out, so the
+// next page starts at whatever sorts after it now, even if that row is gone.
+#[derive(Debug, Serialize, Deserialize)]
+struct ListCursor {
+    sort_key: SortKey,
+    sort_order: SortOrder,
+    // None before the first row, for a page of no rows at the start.
+    after: Option<(SortValue, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum SortValue {
+    CreatedAt(i64),
+    WorldTime(f64),
+    Id,
+    Name(Option<String>),
+    Size(u64),
+}
+
+impl SortValue {
+    fn of(entry: &CatalogEntry, key: SortKey) -> Self {
+        match key {
+            SortKey::CreatedAt => Self::CreatedAt(entry.metadata.created_at),
+            SortKey::WorldTime => Self::WorldTime(entry.metadata.world_time),
+            SortKey::Id => Self::Id,
+            SortKey::Name => Self::Name(entry.metadata.name.clone()),
+            SortKey::Size => Self::Size(entry.size_bytes),
+        }
+    }
+
+    fn key(&self) -> SortKey {
+        match self {
+            Self::CreatedAt(_) => SortKey::CreatedAt,
+            Self::WorldTime(_) => SortKey::WorldTime,
+            Self::Id => SortKey::Id,
+            Self::Name(_) => SortKey::Name,
+            Self::Size(_) => SortKey::Size,
+        }
+    }
+
+    // Same order as compare_entries.
+    fn compare(&self, entry: &CatalogEntry) -> Ordering {
+        let metadata = &entry.metadata;
+        match self {
+            Self::CreatedAt(created_at) => created_at.cmp(&metadata.created_at),
+            Self::WorldTime(world_time) => world_time.total_cmp(&metadata.world_time),
+            Self::Id => Ordering::Equal,
+            Self::Name(name) => name.cmp(&metadata.name),
+            Self::Size(size) => size.cmp(&entry.size_bytes),
+        }
+    }
+}
+
+impl ListCursor {
+    fn new(options: &ListOptions, last: Option<&CatalogEntry>) -> Self {
+        Self {
+            sort_key: options.sort_key,
+            sort_order: options.sort_order,
+            after: last.map(|entry| (SortValue::of(entry, options.sort_key), entry.metadata.id.clone())),
+        }
+    }
+
+    fn encode(&self) -> Result<String> {
+        Ok(to_hex(&serde_json::to_vec(self)?))
+    }
+
+    fn decode(cursor: &str, options: &ListOptions) -> Result<Self> {
+        let invalid = || PackError::Query(format!("Invalid list cursor {}", cursor));
+        let cursor: Self = from_hex(cursor)
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(invalid)?;
+
+        if cursor.after.as_ref().is_some_and(|(value, _)| value.key() != cursor.sort_key) {
+            return Err(invalid());
+        }
+        if cursor.sort_key != options.sort_key || cursor.sort_order != options.sort_order {
+            return Err(PackError::Query(format!(
+                "List cursor was issued for {:?} {:?}, not {:?} {:?}",
+                cursor.sort_key, cursor.sort_order, options.sort_key, options.sort_order
+            )));
+        }
+        Ok(cursor)
+    }
+
+    // Whether the entry was on this page or an earlier one.
+    fn covers(&self, entry: &CatalogEntry) -> bool {
+        let (value, id) = match &self.after {
+            Some(after) => after,
+            None => return false,
+        };
+        let ordering = value.compare(entry).then_with(|| id.cmp(&entry.metadata.id));
+        match self.sort_order {
+            SortOrder::Ascending => ordering != Ordering::Less,
+            SortOrder::Descending => ordering != Ordering::Greater,
+        }
+    }
+}
+
+fn sort_entries(entries: &mut [&CatalogEntry], key: SortKey, order: SortOrder) {
+    entries.sort_by(|a, b| {
+        let ordering = compare_entries(a, b, key);
+        match order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    });
+}
+
+fn compare_entries(a: &CatalogEntry, b: &CatalogEntry, key: SortKey) -> Ordering {
+    let (meta_a, meta_b) = (&a.metadata, &b.metadata);
+
+    let ordering = match key {
+        SortKey::CreatedAt => meta_a.created_at.cmp(&meta_b.created_at),
+        SortKey::WorldTime => meta_a.world_time.total_cmp(&meta_b.world_time),
+        SortKey::Id => Ordering::Equal,
+        SortKey::Name => meta_a.name.cmp(&meta_b.name),
+        SortKey::Size => a.size_bytes.cmp(&b.size_bytes),
+    };
+
+    ordering.then_with(|| meta_a.id.cmp(&meta_b.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::PackedSnapshot;
+    use crate::storage::SnapshotWriter;
+    use tempfile::TempDir;
+
+    fn populated_store(temp_dir: &TempDir) -> SnapshotStore {
+        let store = SnapshotStore::new(temp_dir.path()).unwrap();
+        let writer = SnapshotWriter::new();
+
+        for i in 0..6 {
+            let mut metadata = SnapshotMetadata::new(format!("save-{}", i))
+                .with_name(format!("Arena Run {}", i))
+                .with_custom_field("map".to_string(), if i % 2 == 0 { "arena" } else { "forest" }.to_string());
+            metadata.created_at = 1_000 + i as i64;
+            metadata.world_time = i as f64 * 10.0;
+
+            if i >= 3 {
+                metadata = metadata.with_tag("milestone".to_string());
+            }
+
+            let tier = ["hot", "warm", "cold"][i % 3];
+            metadata = metadata
+                .with_label("env".to_string(), "prod".to_string())
+                .with_label("tier".to_string(), tier.to_string());
+
+            store.save(&PackedSnapshot::new(), &metadata, &writer).unwrap();
+        }
+
+        store
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = populated_store(&temp_dir);
+
+        let milestones = store.query().with_tag("milestone").execute().unwrap();
+        let ids: Vec<_> = milestones.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["save-3", "save-4", "save-5"]);
+
+        let arena = store
+            .query()
+            .with_custom_field("map", "arena")
+            .world_time_after(15.0)
+            .execute()
+            .unwrap();
+        let ids: Vec<_> = arena.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["save-2", "save-4"]);
+
+        let named = store.query().with_name_containing("run 1").count().unwrap();
+        assert_eq!(named, 1);
+
+        let created = store.query().created_after(1_001).created_before(1_002).count().unwrap();
+        assert_eq!(created, 2);
+    }
+
+    #[test]
+    fn test_query_label_selector() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = populated_store(&temp_dir);
+
+        let selected = store
+            .query()
+            .with_label_selector("env=prod, tier in (hot, warm)")
+            .unwrap()
+            .execute()
+            .unwrap();
+        let ids: Vec<_> = selected.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["save-0", "save-1", "save-3", "save-4"]);
+
+        assert_eq!(store.query().with_label_selector("env!=prod").unwrap().count().unwrap(), 0);
+        assert!(store.query().with_label_selector("tier in hot").is_err());
+    }
+
+    #[test]
+    fn test_list_page_with_cursor() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = populated_store(&temp_dir);
+
+        let options = ListOptions::new()
+            .sort_by(SortKey::CreatedAt, SortOrder::Descending)
+            .limit(4);
+        let first = store.list_page(&options).unwrap();
+        assert_eq!(first.total, 6);
+        assert_eq!(first.ids(), vec!["save-5", "save-4", "save-3", "save-2"]);
+
+        let cursor = first.next_cursor.clone().unwrap();
+        let second = store.list_page(&options.clone().after(cursor)).unwrap();
+        assert_eq!(second.ids(), vec!["save-1", "save-0"]);
+        assert!(second.next_cursor.is_none());
+
+        let by_size = store
+            .list_page(&ListOptions::new().sort_by(SortKey::Size, SortOrder::Ascending).offset(5))
+            .unwrap();
+        assert_eq!(by_size.entries.len(), 1);
+
+        assert!(store.list_page(&ListOptions::new().after("missing".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_list_cursor_survives_deletes_and_checks_the_sort() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = populated_store(&temp_dir);
+
+        let options = ListOptions::new()
+            .sort_by(SortKey::WorldTime, SortOrder::Ascending)
+            .limit(2);
+        let first = store.list_page(&options).unwrap();
+        assert_eq!(first.ids(), vec!["save-0", "save-1"]);
+        let cursor = first.next_cursor.clone().unwrap();
+
+        // The row the cursor names is deleted before the next page is fetched.
+        store.delete("save-1").unwrap();
+        let second = store.list_page(&options.clone().after(cursor.clone())).unwrap();
+        assert_eq!(second.ids(), vec!["save-2", "save-3"]);
+
+        let other_sort = options.clone().sort_by(SortKey::WorldTime, SortOrder::Descending).after(cursor);
+        assert!(store.list_page(&other_sort).is_err());
+
+        // An empty page still hands out a cursor when rows are left.
+        let empty = store.list_page(&options.clone().limit(0)).unwrap();
+        assert!(empty.entries.is_empty());
+        let rest = ListOptions { limit: None, ..options.clone() }.after(empty.next_cursor.unwrap());
+        assert_eq!(store.list_page(&rest).unwrap().ids(), vec!["save-0", "save-2", "save-3", "save-4", "save-5"]);
+    }
+
+    #[test]
+    fn test_query_sort_and_paginate() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = populated_store(&temp_dir);
+
+        let page = store
+            .query()
+            .sort_by(SortKey::WorldTime, SortOrder::Descending)
+            .offset(1)
+            .limit(2)
+            .execute()
+            .unwrap();
+
+        let ids: Vec<_> = page.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["save-4", "save-3"]);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::catalog::{Catalog, CatalogEntry};
+use crate::delta::DeltaSnapshot;
+use crate::error::{PackError, Result, ResultExt};
+use crate::metadata::SnapshotMetadata;
+use crate::storage::{file_checksum, SnapshotReader, SnapshotStore, SnapshotWriter};
+use crate::transfer::DEFAULT_TRANSFER_CHUNK_SIZE;
+use std::collections::{BTreeSet, HashMap};
+
+// Snapshots stored as deltas by a sync get a new file checksum; the remote's
+// is kept here so the next sync still recognises them as up to date.
+pub const SYNC_SOURCE_CHECKSUM: &str = "sync.source_checksum";
+
+pub struct SyncOptions {
+    reader: SnapshotReader,
+    delta_writer: Option<SnapshotWriter>,
+    chunk_size: u32,
+}
+
+impl SyncOptions {
+    pub fn new() -> Self {
+        Self {
+            reader: SnapshotReader::new(),
+            delta_writer: None,
+            chunk_size: DEFAULT_TRANSFER_CHUNK_SIZE,
+        }
+    }
+
+    // Full snapshots whose provenance parent is already here are stored as a
+    // delta against it, written with this writer.
+    pub fn with_deltas(mut self, writer: SnapshotWriter) -> Self {
+        self.delta_writer = Some(writer);
+        self
+    }
+
+    // Reads remote snapshots when they are turned into deltas.
+    pub fn with_reader(mut self, reader: SnapshotReader) -> Self {
+        self.reader = reader;
+        self
+    }
+
+    pub fn with_chunk_size(mut self, bytes: u32) -> Self {
+        self.chunk_size = bytes;
+        self
+    }
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub copied: Vec<String>,
+    pub stored_as_deltas: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub bytes_written: u64,
+}
+
+impl SyncReport {
+    pub fn transferred(&self) -> usize {
+        self.copied.len() + self.stored_as_deltas.len()
+    }
+}
+
+impl SnapshotStore {
+    pub fn sync_from(&self, remote: &SnapshotStore) -> Result<SyncReport> {
+        self.sync_from_with(remote, &SyncOptions::new())
+    }
+
+    // Mirrors every snapshot of the remote store into this one. Snapshots
+    // whose checksum already matches are skipped, the rest are copied chunk
+    // by chunk with the transfer protocol, bases before the deltas on top of
+    // them. The remote wins for ids both stores have; snapshots only this
+    // store has are left alone.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn sync_from_with(&self, remote: &SnapshotStore, options: &SyncOptions) -> Result<SyncReport> {
+        let remote_catalog = remote.catalog()?;
+        let local_catalog = self.catalog()?;
+        let mut report = SyncReport::default();
+
+        let mut pending = Vec::new();
+        for id in sync_order(&remote_catalog)? {
+            let entry = remote_catalog.get(&id).expect("ordered ids come from the catalog");
+            let checksum = remote.stored_checksum(&id, &entry.metadata)?;
+            if local_catalog.get(&id).is_some_and(|local| is_synced(&local.metadata, &checksum)) {
+                report.unchanged.push(id);
+            } else {
+                pending.push((id, checksum));
+            }
+        }
+
+        // Replacing a snapshot would break local deltas on top of it, unless
+        // the remote replaces those too.
+        let replaced: BTreeSet<&str> = pending.iter().map(|(id, _)| id.as_str()).collect();
+        for entry in local_catalog.entries() {
+            let base = entry.metadata.delta_base.as_deref().filter(|base| replaced.contains(base));
+            if let Some(base) = base.filter(|_| !replaced.contains(entry.metadata.id.as_str())) {
+                return Err(PackError::Delta(format!(
+                    "Cannot sync {}: local delta {} depends on it",
+                    base, entry.metadata.id
+                )));
+            }
+        }
+
+        for (id, checksum) in pending {
+            let metadata = &remote_catalog.get(&id).expect("pending ids come from the catalog").metadata;
+            let written = match self.sync_as_delta(remote, metadata, &checksum, options).with_snapshot_id(&id)? {
+                Some(written) => {
+                    report.stored_as_deltas.push(id);
+                    written
+                }
+                None => {
+                    let written = self.copy_from(remote, &id, options.chunk_size).with_snapshot_id(&id)?;
+                    report.copied.push(id);
+                    written
+                }
+            };
+            report.bytes_written += written;
+        }
+
+        Ok(report)
+    }
+
+    pub(crate) fn stored_checksum(&self, id: &str, metadata: &SnapshotMetadata) -> Result<String> {
+        match &metadata.checksum {
+            Some(checksum) => Ok(checksum.clone()),
+            None => Ok(file_checksum(&self.read_range(id, 0, self.file_size(id)?)?)),
+        }
+    }
+
+    pub(crate) fn copy_from(&self, remote: &SnapshotStore, id: &str, chunk_size: u32) -> Result<u64> {
+        let manifest = remote.transfer_manifest(id, chunk_size)?;
+        let total_size = manifest.total_size;
+        let mut receiver = self.open_transfer(manifest)?;
+        for index in receiver.next_chunk()..receiver.manifest().chunk_count() {
+            let data = remote.read_transfer_chunk(receiver.manifest(), index)?;
+            receiver.write_chunk(index, &data)?;
+        }
+        receiver.finish()?;
+
+        Ok(total_size)
+    }
+
+    fn sync_as_delta(
+        &self,
+        remote: &SnapshotStore,
+        metadata: &SnapshotMetadata,
+        checksum: &str,
+        options: &SyncOptions,
+    ) -> Result<Option<u64>> {
+        let writer = match &options.delta_writer {
+            Some(writer) => writer,
+            None => return Ok(None),
+        };
+        let parent_id = match &metadata.provenance.parent_id {
+            Some(parent_id) if metadata.delta_base.is_none() && self.exists(parent_id) => parent_id,
+            _ => return Ok(None),
+        };
+        let parent_in_sync = match remote.load_metadata(parent_id)?.checksum {
+            Some(checksum) if remote.exists(parent_id) => is_synced(&self.load_metadata(parent_id)?, &checksum),
+            _ => false,
+        };
+        if !parent_in_sync {
+            return Ok(None);
+        }
+
+        let (snapshot, _) = remote.load(&metadata.id, &options.reader)?;
+        let (base, _) = self.load(parent_id, &options.reader)?;
+        let delta = DeltaSnapshot::from_snapshots(&base, &snapshot)?.with_base_id(parent_id.clone());
+
+        let mut metadata = metadata.clone();
+        metadata.checksum = None;
+        metadata.custom_fields.insert(SYNC_SOURCE_CHECKSUM.to_string(), checksum.to_string());
+        self.save_delta(&delta, &metadata, writer)?;
+
+        self.file_size(&metadata.id).map(Some)
+    }
+}
+
+pub(crate) fn is_synced(local: &SnapshotMetadata, checksum: &str) -> bool {
+    local.checksum.as_deref() == Some(checksum) || source_checksum(local) == Some(checksum)
+}
+
+// Set on snapshots synced as deltas: the checksum of the full snapshot on the
+// store they came from, which their own checksum no longer matches.
+pub(crate) fn source_checksum(metadata: &SnapshotMetadata) -> Option<&str> {
+    metadata.custom_fields.get(SYNC_SOURCE_CHECKSUM).map(String::as_str)
+}
+
+// Remote ids with every snapshot after the base it is stored against, or
+// else its provenance parent, so parents are in place for delta syncs.
+fn sync_order(catalog: &Catalog) -> Result<Vec<String>> {
+    let entries: HashMap<&str, &CatalogEntry> = catalog.entries().map(|e| (e.metadata.id.as_str(), e)).collect();
+    let mut ordered = Vec::with_capacity(entries.len());
+    let mut visited = BTreeSet::new();
+
+    let mut ids: Vec<&str> = entries.keys().copied().collect();
+    ids.sort_unstable();
+    for id in ids {
+        let mut chain = Vec::new();
+        let mut current = Some(id);
+        while let Some(id) = current.filter(|id| !visited.contains(id)) {
+            if chain.contains(&id) {
+                return Err(PackError::Delta(format!("Snapshot {} is its own ancestor", id)));
+            }
+            chain.push(id);
+            current = entries.get(id).and_then(|entry| {
+                let metadata = &entry.metadata;
+                metadata.delta_base.as_deref().or(metadata.provenance.parent_id.as_deref())
+            });
+        }
+        for id in chain.into_iter().rev() {
+            visited.insert(id);
+            if entries.contains_key(id) {
+                ordered.push(id.to_string());
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot};
+    use crate::metadata::Provenance;
+    use tempfile::TempDir;
+
+    fn snapshot(values: &[u8]) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "State".to_string(),
+            entity_ids: (0..values.len() as u32).collect(),
+            data: ComponentData::Blob(values.to_vec()),
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_sync_copies_only_missing_or_changed_snapshots() {
+        let (central_dir, edge_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let central = SnapshotStore::new(central_dir.path()).unwrap();
+        let edge = SnapshotStore::new(edge_dir.path()).unwrap();
+        let writer = SnapshotWriter::new();
+
+        let base = snapshot(&[1; 64]);
+        central.save(&base, &SnapshotMetadata::new("match-1".to_string()), &writer).unwrap();
+        let next = snapshot(&[[1; 60].as_slice(), &[2; 4]].concat());
+        let delta = DeltaSnapshot::from_snapshots(&base, &next).unwrap().with_base_id("match-1".to_string());
+        central.save_delta(&delta, &SnapshotMetadata::new("match-1b".to_string()), &writer).unwrap();
+
+        let report = edge.sync_from(&central).unwrap();
+        assert_eq!(report.copied, vec!["match-1", "match-1b"]);
+        assert!(edge.is_delta("match-1b"));
+        let loaded = edge.load("match-1b", &SnapshotReader::new()).unwrap().0;
+        assert_eq!(loaded.content_checksum().unwrap(), next.content_checksum().unwrap());
+        assert_eq!(edge.sync_from(&central).unwrap().unchanged.len(), 2);
+
+        // A rewritten snapshot is replaced, a new child lands as a delta.
+        central.delete("match-1b").unwrap();
+        central.save(&snapshot(&[3; 64]), &SnapshotMetadata::new("match-1b".to_string()), &writer).unwrap();
+        let mut child = SnapshotMetadata::new("match-2".to_string());
+        child.provenance = Provenance::new().with_parent("match-1".to_string());
+        let grown = snapshot(&[[1; 64].as_slice(), &[4; 8]].concat());
+        central.save(&grown, &child, &writer).unwrap();
+
+        let report = edge.sync_from_with(&central, &SyncOptions::new().with_deltas(SnapshotWriter::new())).unwrap();
+        assert_eq!(report.copied, vec!["match-1b"]);
+        assert_eq!(report.stored_as_deltas, vec!["match-2"]);
+        assert!(!edge.is_delta("match-1b") && edge.is_delta("match-2"));
+        let loaded = edge.load("match-2", &SnapshotReader::new()).unwrap().0;
+        assert_eq!(loaded.content_checksum().unwrap(), grown.content_checksum().unwrap());
+
+        // Both stores tag it with the source's checksum, so a cached copy of
+        // either validates against the other.
+        assert_eq!(edge.etag("match-2").unwrap(), central.etag("match-2").unwrap());
+        assert_eq!(edge.transfer_manifest("match-2", 64).unwrap().etag(), central.etag("match-2").unwrap());
+
+        let report = edge.sync_from(&central).unwrap();
+        assert_eq!(report.transferred(), 0);
+        assert_eq!(report.unchanged, vec!["match-1", "match-1b", "match-2"]);
+    }
+}
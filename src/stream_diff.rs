@@ -0,0 +1,193 @@
+use crate::adapter::WorldCapture;
+use crate::diff::{diff_component, DiffOptions, SnapshotDiff};
+use crate::error::Result;
+use crate::format::ComponentArchetype;
+use std::collections::BTreeSet;
+use tx2_link::{ComponentId, EntityId};
+
+pub trait ArchetypeSource {
+    fn component_ids(&mut self) -> Result<Vec<ComponentId>>;
+
+    fn read_archetype(&mut self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>>;
+}
+
+impl<W: WorldCapture + ?Sized> ArchetypeSource for &W {
+    fn component_ids(&mut self) -> Result<Vec<ComponentId>> {
+        WorldCapture::component_ids(*self)
+    }
+
+    fn read_archetype(&mut self, component_id: &ComponentId) -> Result<Option<ComponentArchetype>> {
+        self.capture_archetype(component_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffChunk {
+    pub component_id: Option<ComponentId>,
+    pub diff: SnapshotDiff,
+}
+
+pub struct StreamingDiff<A, B> {
+    old: A,
+    new: B,
+    options: DiffOptions,
+    pending: Option<std::vec::IntoIter<ComponentId>>,
+    old_entities: BTreeSet<EntityId>,
+    new_entities: BTreeSet<EntityId>,
+    finished: bool,
+}
+
+impl<A: ArchetypeSource, B: ArchetypeSource> StreamingDiff<A, B> {
+    pub fn new(old: A, new: B) -> Self {
+        Self {
+            old,
+            new,
+            options: DiffOptions::default(),
+            pending: None,
+            old_entities: BTreeSet::new(),
+            new_entities: BTreeSet::new(),
+            finished: false,
+        }
+    }
+
+    pub fn with_options(mut self, options: DiffOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn collect_diff(self) -> Result<SnapshotDiff> {
+        let mut result = SnapshotDiff::default();
+
+        for chunk in self {
+            let chunk = chunk?.diff;
+            result.entities_added.extend(chunk.entities_added);
+            result.entities_removed.extend(chunk.entities_removed);
+            result.components_added.extend(chunk.components_added);
+            result.components_removed.extend(chunk.components_removed);
+            result.value_changes.extend(chunk.value_changes);
+            result.blob_changes.extend(chunk.blob_changes);
+            result.tombstones.extend(chunk.tombstones);
+            result.resource_changes.extend(chunk.resource_changes);
+        }
+
+        Ok(result)
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<DiffChunk>> {
+        if self.pending.is_none() {
+            let component_ids: BTreeSet<ComponentId> = self
+                .old
+                .component_ids()?
+                .into_iter()
+                .chain(self.new.component_ids()?)
+                .collect();
+            self.pending = Some(component_ids.into_iter().collect::<Vec<_>>().into_iter());
+        }
+
+        while let Some(component_id) = self.pending.as_mut().and_then(|pending| pending.next()) {
+            let old = self.old.read_archetype(&component_id)?;
+            let new = self.new.read_archetype(&component_id)?;
+
+            if let Some(archetype) = &old {
+                self.old_entities.extend(archetype.entity_ids.iter().copied());
+            }
+            if let Some(archetype) = &new {
+                self.new_entities.extend(archetype.entity_ids.iter().copied());
+            }
+
+            let mut diff = SnapshotDiff::default();
+            diff_component(old.as_ref(), new.as_ref(), &self.options, &mut diff);
+
+            if !diff.is_empty() {
+                return Ok(Some(DiffChunk {
+                    component_id: Some(component_id),
+                    diff,
+                }));
+            }
+        }
+
+        if self.finished {
+            return Ok(None);
+        }
+        self.finished = true;
+
+        let entities_removed: Vec<EntityId> = self.old_entities.difference(&self.new_entities).copied().collect();
+        let diff = SnapshotDiff {
+            entities_added: self.new_entities.difference(&self.old_entities).copied().collect(),
+            tombstones: entities_removed.clone(),
+            entities_removed,
+            ..SnapshotDiff::default()
+        };
+        self.old_entities.clear();
+        self.new_entities.clear();
+
+        if diff.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(DiffChunk {
+            component_id: None,
+            diff,
+        }))
+    }
+}
+
+impl<A: ArchetypeSource, B: ArchetypeSource> Iterator for StreamingDiff<A, B> {
+    type Item = Result<DiffChunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        match self.next_chunk() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => None,
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::diff;
+    use crate::format::{ComponentData, FieldArray, FieldType, PackedSnapshot, StructOfArraysData};
+
+    fn archetype(component: &str, rows: &[(EntityId, u16)]) -> ComponentArchetype {
+        ComponentArchetype {
+            component_id: component.to_string(),
+            entity_ids: rows.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["v".to_string()],
+                field_types: vec![FieldType::U16],
+                field_data: vec![FieldArray::U16(rows.iter().map(|(_, v)| *v).collect())],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_streaming_diff_matches_diff() {
+        let mut old = PackedSnapshot::new();
+        old.archetypes.push(archetype("A", &[(1, 1), (2, 2)]));
+        old.archetypes.push(archetype("B", &[(1, 5)]));
+        old.archetypes.push(archetype("C", &[(3, 0)]));
+
+        let mut new = PackedSnapshot::new();
+        new.archetypes.push(archetype("A", &[(1, 1), (2, 3)]));
+        new.archetypes.push(archetype("B", &[(1, 5)]));
+        new.archetypes.push(archetype("D", &[(4, 0)]));
+
+        let chunks: Vec<DiffChunk> = StreamingDiff::new(&old, &new).collect::<Result<_>>().unwrap();
+        let components: Vec<Option<&str>> = chunks.iter().map(|c| c.component_id.as_deref()).collect();
+        assert_eq!(components, vec![Some("A"), Some("C"), Some("D"), None]);
+
+        let streamed = StreamingDiff::new(&old, &new).collect_diff().unwrap();
+        assert_eq!(streamed, diff(&old, &new));
+
+        assert_eq!(StreamingDiff::new(&old, &old).count(), 0);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::checksum::ChecksumHasher;
+use crate::error::Result;
+use crate::format::{ComponentArchetype, EntityMetadata, PackFormat, SnapshotHeader, HEADER_SIZE};
+use crate::sectioned::{self, ArchetypeEntry, Section, TableOfContents};
+use crate::storage::SnapshotWriter;
+use crate::telemetry::Stopwatch;
+use crate::throttle::Throttle;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Seek, SeekFrom, Write};
+use tx2_link::EntityId;
+
+pub struct StreamingWriter<'a, W: Write + Seek> {
+    writer: &'a SnapshotWriter,
+    out: W,
+    start: u64,
+    header: SnapshotHeader,
+    toc: TableOfContents,
+    hasher: ChecksumHasher,
+    entities: HashSet<EntityId>,
+    resources: BTreeMap<String, Vec<u8>>,
+    written: u64,
+    raw_size: usize,
+    throttle: Throttle,
+    stopwatch: Stopwatch,
+}
+
+impl<'a, W: Write + Seek> StreamingWriter<'a, W> {
+    pub(crate) fn new(writer: &'a SnapshotWriter, mut out: W, mut header: SnapshotHeader) -> Result<Self> {
+        let stopwatch = Stopwatch::start();
+        let start = out.stream_position()?;
+        out.write_all(&[0u8; HEADER_SIZE as usize])?;
+        header.format = PackFormat::Custom;
+        writer.set_flags(&mut header);
+
+        Ok(Self {
+            writer,
+            out,
+            start,
+            header,
+            toc: TableOfContents::default(),
+            hasher: writer.checksum_algorithm().hasher(),
+            entities: HashSet::new(),
+            resources: BTreeMap::new(),
+            written: 0,
+            raw_size: 0,
+            throttle: writer.throttle(),
+            stopwatch,
+        })
+    }
+
+    pub fn write_archetype(&mut self, archetype: &ComponentArchetype) -> Result<()> {
+        self.writer.check_structure(archetype)?;
+        let section = self.write_section(&sectioned::encode_archetype(archetype, self.header.flags)?)?;
+
+        self.entities.extend(archetype.entity_ids.iter().copied());
+        self.toc.archetypes.push(ArchetypeEntry {
+            component_id: archetype.component_id.clone(),
+            entity_count: archetype.entity_ids.len() as u64,
+            section,
+        });
+
+        Ok(())
+    }
+
+    // Resources are small, so they are held until finish and written as one
+    // section after the entity metadata.
+    pub fn write_resource(&mut self, name: &str, data: &[u8]) {
+        self.resources.insert(name.to_string(), data.to_vec());
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        HEADER_SIZE + self.written
+    }
+
+    pub fn finish(mut self, entity_metadata: &HashMap<EntityId, EntityMetadata>) -> Result<W> {
+        self.toc.entity_metadata = self.write_section(&bincode::serialize(entity_metadata)?)?;
+        if !self.resources.is_empty() {
+            self.toc.resources = self.write_section(&bincode::serialize(&self.resources)?)?;
+        }
+        let footer = sectioned::encode_footer(&self.toc, |raw| self.writer.seal_chunk(raw))?;
+        self.emit(&footer)?;
+
+        let mut header = self.header;
+        header.entity_count = self.entities.len() as u64;
+        header.component_count = self.toc.archetypes.len() as u64;
+        header.archetype_count = self.toc.archetypes.len() as u64;
+        self.writer.finish_header(&mut header, self.hasher.finalize(), self.written);
+
+        let mut encoded = Vec::with_capacity(HEADER_SIZE as usize);
+        header.encode_into(&mut encoded)?;
+        self.out.write_all(&encoded)?;
+        self.out.seek(SeekFrom::Start(self.start))?;
+        self.out.write_all(&encoded)?;
+        self.out.seek(SeekFrom::Start(self.start + 2 * HEADER_SIZE + self.written))?;
+        self.out.flush()?;
+
+        self.stopwatch.record_write("stream", self.raw_size, (2 * HEADER_SIZE + self.written) as usize);
+        Ok(self.out)
+    }
+
+    fn write_section(&mut self, raw: &[u8]) -> Result<Section> {
+        let sealed = self.writer.seal_section(raw)?;
+        let section = Section {
+            offset: self.written,
+            size: sealed.len() as u64,
+        };
+
+        self.raw_size += raw.len();
+        self.emit(&sealed)?;
+        Ok(section)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<()> {
+        self.throttle.write_all(&mut self.out, bytes)?;
+        self.hasher.update(bytes);
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentData, FieldArray, FieldType, FieldValue, PackedSnapshot, StructOfArraysData};
+    use crate::storage::SnapshotReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_streamed_snapshot_matches_buffered() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        for (component_id, count) in [("Position", 3u32), ("Health", 2)] {
+            snapshot.archetypes.push(ComponentArchetype {
+                component_id: component_id.to_string(),
+                entity_ids: (0..count).collect(),
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["value".to_string()],
+                    field_types: vec![FieldType::U32],
+                    field_data: vec![FieldArray::U32((0..count).collect())],
+                }),
+            });
+        }
+        snapshot.entity_metadata.insert(1, EntityMetadata::new());
+        snapshot.rebuild_entity_index();
+        snapshot.header.entity_count = 3;
+        snapshot.header.component_count = 2;
+        snapshot.header.archetype_count = 2;
+
+        let writer = SnapshotWriter::new();
+        let mut out = Cursor::new(vec![0xAA; 4]);
+        out.set_position(4);
+
+        let mut stream = writer.stream_to(out, snapshot.header.clone()).unwrap();
+        for archetype in &snapshot.archetypes {
+            stream.write_archetype(archetype).unwrap();
+        }
+        let bytes = stream.finish(&snapshot.entity_metadata).unwrap().into_inner();
+
+        assert_eq!(bytes, [vec![0xAA; 4], writer.write_to_bytes(&snapshot).unwrap()].concat());
+
+        let loaded = SnapshotReader::new().read_from_bytes(&bytes[4..]).unwrap();
+        assert_eq!(loaded.header.entity_count, 3);
+        assert_eq!(loaded.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+    }
+
+    #[test]
+    #[ignore = "writes and reads back a snapshot larger than 4 GiB"]
+    fn test_stream_snapshot_larger_than_4gib() {
+        use crate::compression::CompressionCodec;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("large.tx2pack");
+        let rows = 128 * 1024 * 1024u32;
+
+        let writer = SnapshotWriter::new().with_compression(CompressionCodec::None);
+        let file = std::fs::File::create(&path).unwrap();
+        let mut stream = writer.stream_to(file, SnapshotHeader::new()).unwrap();
+        for i in 0..3u64 {
+            stream.write_archetype(&ComponentArchetype {
+                component_id: format!("Large{}", i),
+                entity_ids: (0..rows).collect(),
+                data: ComponentData::StructOfArrays(StructOfArraysData {
+                    field_names: vec!["value".to_string()],
+                    field_types: vec![FieldType::U64],
+                    field_data: vec![FieldArray::U64((0..rows as u64).map(|v| v * i).collect())],
+                }),
+            }).unwrap();
+        }
+        stream.finish(&HashMap::new()).unwrap();
+
+        let reader = SnapshotReader::new();
+        let header = reader.read_header_from_file(&path).unwrap();
+        assert_eq!(header.entity_count, rows as u64);
+        assert!(header.data_size > u32::MAX as u64);
+
+        for i in 0..3u64 {
+            let lazy = reader.read_lazy_from_file(&path).unwrap();
+            let archetype = lazy.archetype(&format!("Large{}", i)).unwrap().unwrap();
+            assert_eq!(archetype.entity_ids.len(), rows as usize);
+            assert_eq!(
+                archetype.row_values(rows as usize - 1).unwrap()[0].1,
+                FieldValue::U64((rows as u64 - 1) * i)
+            );
+        }
+    }
+}
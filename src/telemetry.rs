@@ -0,0 +1,56 @@
+#[cfg(any(feature = "tracing", feature = "metrics"))]
+use std::time::Instant;
+
+pub(crate) struct Stopwatch {
+    #[cfg(any(feature = "tracing", feature = "metrics"))]
+    started: Instant,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(any(feature = "tracing", feature = "metrics"))]
+            started: Instant::now(),
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "tracing", feature = "metrics")), allow(unused_variables))]
+    pub(crate) fn record_write(self, operation: &'static str, raw_bytes: usize, written_bytes: usize) {
+        #[cfg(any(feature = "tracing", feature = "metrics"))]
+        {
+            let seconds = self.started.elapsed().as_secs_f64();
+            let compression_ratio = if written_bytes == 0 {
+                1.0
+            } else {
+                raw_bytes as f64 / written_bytes as f64
+            };
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(operation, raw_bytes, written_bytes, compression_ratio, seconds, "snapshot written");
+
+            #[cfg(feature = "metrics")]
+            {
+                metrics::counter!("tx2pack_bytes_written_total", "operation" => operation).increment(written_bytes as u64);
+                metrics::histogram!("tx2pack_compression_ratio", "operation" => operation).record(compression_ratio);
+                metrics::histogram!("tx2pack_write_seconds", "operation" => operation).record(seconds);
+            }
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "tracing", feature = "metrics")), allow(unused_variables))]
+    pub(crate) fn record_read(self, operation: &'static str, read_bytes: usize) {
+        #[cfg(any(feature = "tracing", feature = "metrics"))]
+        {
+            let seconds = self.started.elapsed().as_secs_f64();
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(operation, read_bytes, seconds, "snapshot read");
+
+            #[cfg(feature = "metrics")]
+            {
+                metrics::counter!("tx2pack_bytes_read_total", "operation" => operation).increment(read_bytes as u64);
+                metrics::histogram!("tx2pack_read_seconds", "operation" => operation).record(seconds);
+            }
+        }
+    }
+}
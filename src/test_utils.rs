@@ -0,0 +1,220 @@
+use crate::compression::CompressionCodec;
+use crate::format::{
+    ComponentArchetype, ComponentData, EntityMetadata, FieldArray, FieldType, FieldValue, PackFormat, PackedSnapshot,
+    StructOfArraysData,
+};
+use crate::storage::{SnapshotReader, SnapshotWriter};
+use proptest::collection::{hash_map, hash_set, vec};
+use proptest::prelude::*;
+use tx2_link::EntityId;
+
+// NaN never compares equal to itself and some codecs canonicalize its payload,
+// so generated floats cover every other class, including infinities and -0.0.
+fn f32_value() -> impl Strategy<Value = f32> {
+    use proptest::num::f32::*;
+    POSITIVE | NEGATIVE | NORMAL | SUBNORMAL | ZERO | INFINITE
+}
+
+fn f64_value() -> impl Strategy<Value = f64> {
+    use proptest::num::f64::*;
+    POSITIVE | NEGATIVE | NORMAL | SUBNORMAL | ZERO | INFINITE
+}
+
+pub fn formats() -> Vec<PackFormat> {
+    let mut formats = vec![
+        PackFormat::Custom,
+        PackFormat::Bincode,
+        PackFormat::MessagePack,
+        PackFormat::Cbor,
+        PackFormat::FlatBuffers,
+    ];
+    if cfg!(feature = "rkyv") {
+        formats.push(PackFormat::Rkyv);
+    }
+    formats
+}
+
+pub fn codecs() -> Vec<CompressionCodec> {
+    let mut codecs = vec![CompressionCodec::none()];
+    if cfg!(feature = "compression") {
+        codecs.push(CompressionCodec::zstd_default());
+        codecs.push(CompressionCodec::lz4_default());
+    }
+    codecs
+}
+
+pub fn field_types() -> Vec<FieldType> {
+    vec![
+        FieldType::Bool,
+        FieldType::I8,
+        FieldType::I16,
+        FieldType::I32,
+        FieldType::I64,
+        FieldType::U8,
+        FieldType::U16,
+        FieldType::U32,
+        FieldType::U64,
+        FieldType::F32,
+        FieldType::F64,
+        FieldType::String,
+        FieldType::Bytes,
+    ]
+}
+
+// Empty and single-row archetypes, plus sizes either side of the byte and
+// varint boundaries the encoders care about.
+pub fn row_count() -> impl Strategy<Value = usize> {
+    prop_oneof![
+        2 => Just(0usize),
+        2 => Just(1usize),
+        4 => 2..64usize,
+        1 => Just(127usize),
+        1 => Just(128usize),
+        1 => Just(255usize),
+        1 => Just(256usize),
+        1 => 1000..1100usize,
+    ]
+}
+
+pub fn field_type() -> impl Strategy<Value = FieldType> {
+    proptest::sample::select(field_types())
+}
+
+pub fn field_array(field_type: FieldType, rows: usize) -> BoxedStrategy<FieldArray> {
+    match field_type {
+        FieldType::Bool => vec(any::<bool>(), rows).prop_map(|v| FieldArray::Bool(v.into())).boxed(),
+        FieldType::I8 => vec(any::<i8>(), rows).prop_map(|v| FieldArray::I8(v.into())).boxed(),
+        FieldType::I16 => vec(any::<i16>(), rows).prop_map(|v| FieldArray::I16(v.into())).boxed(),
+        FieldType::I32 => vec(any::<i32>(), rows).prop_map(|v| FieldArray::I32(v.into())).boxed(),
+        FieldType::I64 => vec(any::<i64>(), rows).prop_map(|v| FieldArray::I64(v.into())).boxed(),
+        FieldType::U8 => vec(any::<u8>(), rows).prop_map(|v| FieldArray::U8(v.into())).boxed(),
+        FieldType::U16 => vec(any::<u16>(), rows).prop_map(|v| FieldArray::U16(v.into())).boxed(),
+        FieldType::U32 => vec(any::<u32>(), rows).prop_map(|v| FieldArray::U32(v.into())).boxed(),
+        FieldType::U64 => vec(any::<u64>(), rows).prop_map(|v| FieldArray::U64(v.into())).boxed(),
+        FieldType::F32 => vec(f32_value(), rows).prop_map(|v| FieldArray::F32(v.into())).boxed(),
+        FieldType::F64 => vec(f64_value(), rows).prop_map(|v| FieldArray::F64(v.into())).boxed(),
+        FieldType::String => vec(".{0,24}", rows).prop_map(|v| FieldArray::String(v.into())).boxed(),
+        FieldType::Bytes => vec(vec(any::<u8>(), 0..32), rows).prop_map(|v| FieldArray::Bytes(v.into())).boxed(),
+    }
+}
+
+pub fn field_value() -> impl Strategy<Value = FieldValue> {
+    prop_oneof![
+        any::<bool>().prop_map(FieldValue::Bool),
+        any::<i64>().prop_map(FieldValue::I64),
+        any::<u32>().prop_map(FieldValue::U32),
+        f64_value().prop_map(FieldValue::F64),
+        ".{0,16}".prop_map(FieldValue::String),
+        vec(any::<u8>(), 0..16).prop_map(FieldValue::Bytes),
+    ]
+}
+
+pub fn entity_ids(rows: usize) -> impl Strategy<Value = Vec<EntityId>> {
+    hash_set(any::<EntityId>(), rows).prop_map(|ids| ids.into_iter().collect())
+}
+
+pub fn archetype(component_id: String) -> impl Strategy<Value = ComponentArchetype> {
+    let columns = (row_count(), vec(field_type(), 0..5));
+    let soa = columns.prop_flat_map(|(rows, types)| {
+        let data: Vec<_> = types.iter().map(|field_type| field_array(*field_type, rows)).collect();
+        (entity_ids(rows), Just(types), data)
+    });
+    let soa = soa.prop_map(|(entity_ids, field_types, field_data)| {
+        let field_names = (0..field_types.len()).map(|i| format!("field{}", i)).collect();
+        let data = ComponentData::StructOfArrays(StructOfArraysData {
+            field_names,
+            field_types,
+            field_data,
+        });
+        (entity_ids, data)
+    });
+    let blob = (row_count().prop_flat_map(entity_ids), vec(any::<u8>(), 0..256))
+        .prop_map(|(entity_ids, payload)| (entity_ids, ComponentData::Blob(payload)));
+
+    prop_oneof![4 => soa, 1 => blob].prop_map(move |(entity_ids, data)| ComponentArchetype {
+        component_id: component_id.clone(),
+        entity_ids,
+        data,
+    })
+}
+
+pub fn entity_metadata() -> impl Strategy<Value = EntityMetadata> {
+    (any::<i64>(), any::<i64>(), vec("[a-z]{1,8}", 0..4), hash_map("[a-z]{1,8}", field_value(), 0..4)).prop_map(
+        |(created_at, modified_at, tags, annotations)| EntityMetadata {
+            created_at,
+            modified_at,
+            tags,
+            annotations,
+        },
+    )
+}
+
+pub fn snapshot() -> impl Strategy<Value = PackedSnapshot> {
+    let archetypes = (0..6usize).prop_flat_map(|count| {
+        (0..count).map(|i| archetype(format!("Component{}", i))).collect::<Vec<_>>()
+    });
+    let metadata = hash_map(any::<EntityId>(), entity_metadata(), 0..8);
+
+    (archetypes, metadata).prop_map(|(archetypes, entity_metadata)| {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.archetype_count = archetypes.len() as u64;
+        snapshot.header.entity_count = archetypes.iter().map(|a| a.entity_ids.len() as u64).sum();
+        snapshot.archetypes = archetypes;
+        snapshot.entity_metadata = entity_metadata;
+        snapshot.rebuild_entity_index();
+        snapshot
+    })
+}
+
+// Writes the snapshot in every format with every codec and panics with the
+// combination that failed if anything does not read back identically.
+pub fn assert_roundtrip(snapshot: &PackedSnapshot) {
+    let reader = SnapshotReader::new();
+    for format in formats() {
+        for codec in codecs() {
+            let writer = SnapshotWriter::new().with_compression(codec);
+            let mut snapshot = snapshot.clone();
+            snapshot.header.format = format;
+            assert_roundtrip_with(&snapshot, &writer, &reader);
+        }
+    }
+
+    // Tiny chunks split every Custom section across many sealed frames.
+    let mut custom = snapshot.clone();
+    custom.header.format = PackFormat::Custom;
+    assert_roundtrip_with(&custom, &SnapshotWriter::new().with_section_chunk_size(64), &reader);
+}
+
+pub fn assert_roundtrip_with(snapshot: &PackedSnapshot, writer: &SnapshotWriter, reader: &SnapshotReader) {
+    let format = snapshot.header.format;
+    let expected = snapshot.content_checksum().expect("snapshot cannot be hashed");
+
+    let bytes = writer
+        .write_to_bytes(snapshot)
+        .unwrap_or_else(|e| panic!("{:?} write failed: {}", format, e));
+    let restored = reader
+        .read_from_bytes(&bytes)
+        .unwrap_or_else(|e| panic!("{:?} read failed: {}", format, e));
+    assert_eq!(restored.header.format, format);
+    assert_eq!(restored.content_checksum().unwrap(), expected, "{:?} round trip changed the snapshot", format);
+
+    let lazy = reader
+        .read_lazy_from_bytes(&bytes)
+        .and_then(|lazy| lazy.into_snapshot())
+        .unwrap_or_else(|e| panic!("{:?} lazy read failed: {}", format, e));
+    assert_eq!(lazy.content_checksum().unwrap(), expected, "{:?} lazy round trip changed the snapshot", format);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(24))]
+
+        #[test]
+        fn test_every_format_and_codec_round_trips(snapshot in snapshot()) {
+            assert_roundtrip(&snapshot);
+        }
+    }
+}
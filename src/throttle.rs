@@ -0,0 +1,45 @@
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MIN_THROTTLE_CHUNK: u64 = 4 * 1024;
+const MAX_THROTTLE_CHUNK: u64 = 1024 * 1024;
+
+pub(crate) struct Throttle {
+    bytes_per_sec: Option<u64>,
+    started: Instant,
+    written: u64,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            bytes_per_sec,
+            started: Instant::now(),
+            written: 0,
+        }
+    }
+
+    pub(crate) fn write_all<W: Write>(&mut self, out: &mut W, bytes: &[u8]) -> io::Result<()> {
+        let rate = match self.bytes_per_sec {
+            Some(rate) => rate,
+            None => return out.write_all(bytes),
+        };
+
+        // Roughly ten writes per second keeps the disk queue shallow without
+        // paying a syscall per page.
+        let chunk_size = (rate / 10).clamp(MIN_THROTTLE_CHUNK, MAX_THROTTLE_CHUNK) as usize;
+
+        for chunk in bytes.chunks(chunk_size) {
+            out.write_all(chunk)?;
+            self.written += chunk.len() as u64;
+
+            let due = Duration::from_secs_f64(self.written as f64 / rate as f64);
+            if let Some(wait) = due.checked_sub(self.started.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use crate::checkpoint::CheckpointManager;
+use crate::error::{PackError, Result};
+use crate::format::{ComponentData, FieldValue, PackedSnapshot};
+use crate::replay::TimeTravel;
+use std::collections::{BTreeMap, HashSet};
+use tx2_link::EntityId;
+
+pub type TimeSeries = Vec<(f64, FieldValue)>;
+
+#[derive(Debug, Clone)]
+pub struct SeriesQuery {
+    component_id: String,
+    field: String,
+    entities: Option<HashSet<EntityId>>,
+}
+
+impl SeriesQuery {
+    pub fn new(component_id: &str, field: &str) -> Self {
+        Self {
+            component_id: component_id.to_string(),
+            field: field.to_string(),
+            entities: None,
+        }
+    }
+
+    pub fn for_entities<I: IntoIterator<Item = EntityId>>(mut self, entities: I) -> Self {
+        self.entities = Some(entities.into_iter().collect());
+        self
+    }
+
+    pub fn extract<'a, I>(&self, frames: I) -> Result<BTreeMap<EntityId, TimeSeries>>
+    where
+        I: IntoIterator<Item = (f64, &'a PackedSnapshot)>,
+    {
+        let mut series: BTreeMap<EntityId, TimeSeries> = BTreeMap::new();
+
+        for (time, snapshot) in frames {
+            self.extract_frame(time, snapshot, &mut series)?;
+        }
+
+        Ok(series)
+    }
+
+    pub fn from_time_travel(&self, time_travel: &TimeTravel) -> Result<BTreeMap<EntityId, TimeSeries>> {
+        self.extract(time_travel.iter())
+    }
+
+    pub fn from_checkpoints(&self, manager: &mut CheckpointManager) -> Result<BTreeMap<EntityId, TimeSeries>> {
+        let mut series = BTreeMap::new();
+
+        for id in manager.get_checkpoint_chain().to_vec() {
+            let checkpoint = manager.load_checkpoint(&id)?;
+            self.extract_frame(checkpoint.metadata.world_time, &checkpoint.snapshot, &mut series)?;
+        }
+
+        Ok(series)
+    }
+
+    fn extract_frame(
+        &self,
+        time: f64,
+        snapshot: &PackedSnapshot,
+        series: &mut BTreeMap<EntityId, TimeSeries>,
+    ) -> Result<()> {
+        let Some(archetype) = snapshot
+            .archetypes
+            .iter()
+            .find(|a| a.component_id == self.component_id)
+        else {
+            return Ok(());
+        };
+
+        let ComponentData::StructOfArrays(soa) = &archetype.data else {
+            return Err(PackError::Query(
+                format!("Component {} is not stored as struct-of-arrays", self.component_id)
+            ));
+        };
+
+        let column = soa
+            .field_names
+            .iter()
+            .position(|name| *name == self.field)
+            .map(|index| &soa.field_data[index])
+            .ok_or_else(|| PackError::Query(format!("Field {}.{} not found", self.component_id, self.field)))?;
+
+        for (row, entity_id) in archetype.entity_ids.iter().enumerate() {
+            if self.entities.as_ref().is_some_and(|set| !set.contains(entity_id)) {
+                continue;
+            }
+
+            if let Some(value) = column.get(row) {
+                series.entry(*entity_id).or_default().push((time, value));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, FieldArray, FieldType, StructOfArraysData};
+
+    fn health_snapshot(values: &[(EntityId, i32)]) -> PackedSnapshot {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Health".to_string(),
+            entity_ids: values.iter().map(|(id, _)| *id).collect(),
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["hp".to_string()],
+                field_types: vec![FieldType::I32],
+                field_data: vec![FieldArray::I32(values.iter().map(|(_, hp)| *hp).collect())],
+            }),
+        });
+        snapshot
+    }
+
+    #[test]
+    fn test_series_from_time_travel() {
+        let mut tt = TimeTravel::new();
+        tt.record(0.0, health_snapshot(&[(1, 100), (2, 80)]));
+        tt.record(1.0, health_snapshot(&[(1, 90), (2, 70)]));
+        tt.record(2.0, health_snapshot(&[(1, 75)]));
+
+        let series = SeriesQuery::new("Health", "hp").from_time_travel(&tt).unwrap();
+        assert_eq!(
+            series[&1],
+            vec![(0.0, FieldValue::I32(100)), (1.0, FieldValue::I32(90)), (2.0, FieldValue::I32(75))]
+        );
+        assert_eq!(series[&2].len(), 2);
+
+        let only_two = SeriesQuery::new("Health", "hp").for_entities([2]).from_time_travel(&tt).unwrap();
+        assert_eq!(only_two.keys().copied().collect::<Vec<_>>(), vec![2]);
+
+        assert!(SeriesQuery::new("Health", "armor").from_time_travel(&tt).is_err());
+    }
+
+    #[test]
+    fn test_series_from_checkpoints() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut manager = CheckpointManager::new(temp_dir.path()).unwrap();
+
+        manager.create_checkpoint("cp0".to_string(), health_snapshot(&[(7, 50)])).unwrap();
+        manager.create_checkpoint("cp1".to_string(), health_snapshot(&[(7, 40)])).unwrap();
+
+        let series = SeriesQuery::new("Health", "hp").from_checkpoints(&mut manager).unwrap();
+        let values: Vec<_> = series[&7].iter().map(|(_, v)| v.clone()).collect();
+        assert_eq!(values, vec![FieldValue::I32(50), FieldValue::I32(40)]);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::catalog::write_json_atomic;
+use crate::error::{PackError, Result};
+use crate::metadata::SnapshotMetadata;
+use crate::storage::{validate_snapshot_id, SnapshotStore};
+use crate::store_sync::source_checksum;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+
+pub const DEFAULT_TRANSFER_CHUNK_SIZE: u32 = 4 * 1024 * 1024;
+/// Receivers hold a whole chunk in memory, so larger ones are refused.
+pub const MAX_TRANSFER_CHUNK_SIZE: u32 = 64 * 1024 * 1024;
+
+const FRAME_LENGTH_SIZE: usize = 4;
+const CHUNK_FRAME_OVERHEAD: usize = 64;
+pub(crate) const MAX_CONTROL_FRAME: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferManifest {
+    pub metadata: SnapshotMetadata,
+    pub delta: bool,
+    pub total_size: u64,
+    pub checksum: String,
+    pub chunk_size: u32,
+    pub chunk_hashes: Vec<[u8; 32]>,
+}
+
+impl TransferManifest {
+    pub fn chunk_count(&self) -> u32 {
+        self.chunk_hashes.len() as u32
+    }
+
+    pub fn chunk_range(&self, index: u32) -> Option<Range<u64>> {
+        if index >= self.chunk_count() {
+            return None;
+        }
+        let start = index as u64 * self.chunk_size as u64;
+        Some(start..(start + self.chunk_size as u64).min(self.total_size))
+    }
+
+    pub fn verify_chunk(&self, index: u32, data: &[u8]) -> Result<()> {
+        let range = self.chunk_range(index).ok_or_else(|| {
+            PackError::InvalidFormat(format!("Chunk {} is past the last of {}", index, self.chunk_count()))
+        })?;
+        let intact = data.len() as u64 == range.end - range.start
+            && Sha256::digest(data)[..] == self.chunk_hashes[index as usize];
+        if !intact {
+            return Err(PackError::ChunkChecksumMismatch { chunk: index as usize });
+        }
+        Ok(())
+    }
+
+    /// A resumed transfer only keeps a partial file with the same id.
+    pub fn transfer_id(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.metadata.id.as_bytes());
+        hasher.update([self.delta as u8]);
+        hasher.update(self.total_size.to_le_bytes());
+        hasher.update(self.chunk_size.to_le_bytes());
+        for hash in &self.chunk_hashes {
+            hasher.update(hash);
+        }
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", source_checksum(&self.metadata).unwrap_or(&self.checksum))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        validate_snapshot_id(&self.metadata.id)?;
+        if self.chunk_size == 0 {
+            return Err(PackError::InvalidFormat("Transfer chunk size must not be zero".to_string()));
+        }
+        if self.chunk_size > MAX_TRANSFER_CHUNK_SIZE {
+            return Err(PackError::LimitExceeded {
+                limit: "transfer chunk size",
+                max: MAX_TRANSFER_CHUNK_SIZE as u64,
+            });
+        }
+        let expected = self.total_size.div_ceil(self.chunk_size as u64);
+        if self.chunk_hashes.len() as u64 != expected {
+            return Err(PackError::InvalidFormat(format!(
+                "Manifest for {} lists {} chunk hashes, {} bytes in chunks of {} need {}",
+                self.metadata.id,
+                self.chunk_hashes.len(),
+                self.total_size,
+                self.chunk_size,
+                expected
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn max_chunk_frame(&self) -> usize {
+        self.chunk_size as usize + CHUNK_FRAME_OVERHEAD
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TransferFrame {
+    Manifest(Box<TransferManifest>),
+    Resume { next_chunk: u32 },
+    Missing { chunks: Vec<u32> },
+    Chunk { index: u32, data: Vec<u8> },
+    Complete,
+}
+
+/// A little-endian u32 length followed by the bincode-encoded frame.
+pub fn write_frame<W: Write>(out: &mut W, frame: &TransferFrame) -> Result<()> {
+    write_encoded(out, &bincode::serialize(frame)?)
+}
+
+pub fn read_frame<R: Read>(input: &mut R, max_len: usize) -> Result<TransferFrame> {
+    Ok(bincode::deserialize(&read_encoded(input, max_len)?)?)
+}
+
+pub(crate) fn write_encoded<W: Write>(out: &mut W, encoded: &[u8]) -> Result<()> {
+    let len = u32::try_from(encoded.len())
+        .map_err(|_| PackError::InvalidFormat(format!("Frame of {} bytes is too large", encoded.len())))?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(encoded)?;
+    out.flush()?;
+    Ok(())
+}
+
+pub(crate) fn read_encoded<R: Read>(input: &mut R, max_len: usize) -> Result<Vec<u8>> {
+    let mut len = [0u8; FRAME_LENGTH_SIZE];
+    input.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > max_len {
+        return Err(PackError::LimitExceeded { limit: "frame size", max: max_len as u64 });
+    }
+
+    let mut encoded = vec![0u8; len];
+    input.read_exact(&mut encoded)?;
+    Ok(encoded)
+}
+
+impl SnapshotStore {
+    pub fn transfer_manifest(&self, id: &str, chunk_size: u32) -> Result<TransferManifest> {
+        if chunk_size == 0 {
+            return Err(PackError::InvalidFormat("Transfer chunk size must not be zero".to_string()));
+        }
+        if chunk_size > MAX_TRANSFER_CHUNK_SIZE {
+            return Err(PackError::LimitExceeded { limit: "transfer chunk size", max: MAX_TRANSFER_CHUNK_SIZE as u64 });
+        }
+        let path = self.data_path(id).ok_or_else(|| PackError::SnapshotNotFound(id.to_string()))?;
+        let delta = path.extension().is_some_and(|ext| ext == "tx2delta");
+
+        let mut file = File::open(&path)?;
+        let total_size = file.metadata()?.len();
+        let mut chunk = vec![0u8; chunk_size as usize];
+        let mut chunk_hashes = Vec::new();
+        let mut checksum = Sha256::new();
+        let mut remaining = total_size;
+        while remaining > 0 {
+            let len = remaining.min(chunk_size as u64) as usize;
+            file.read_exact(&mut chunk[..len])?;
+            chunk_hashes.push(Sha256::digest(&chunk[..len]).into());
+            checksum.update(&chunk[..len]);
+            remaining -= len as u64;
+        }
+
+        Ok(TransferManifest {
+            metadata: self.load_metadata(id)?,
+            delta,
+            total_size,
+            checksum: checksum.finalize().iter().map(|b| format!("{:02x}", b)).collect(),
+            chunk_size,
+            chunk_hashes,
+        })
+    }
+
+    /// Taken from the metadata, so copies made by transfer or sync share the original's etag.
+    pub fn etag(&self, id: &str) -> Result<String> {
+        let metadata = self.load_metadata(id)?;
+        match source_checksum(&metadata) {
+            Some(checksum) => Ok(format!("\"{}\"", checksum)),
+            None => Ok(format!("\"{}\"", self.stored_checksum(id, &metadata)?)),
+        }
+    }
+
+    /// Fails if the snapshot changed since the manifest was built.
+    pub fn read_transfer_chunk(&self, manifest: &TransferManifest, index: u32) -> Result<Vec<u8>> {
+        let range = manifest.chunk_range(index).ok_or_else(|| {
+            PackError::InvalidFormat(format!("Chunk {} is past the last of {}", index, manifest.chunk_count()))
+        })?;
+        let data = self.read_range(&manifest.metadata.id, range.start, range.end - range.start)?;
+        manifest.verify_chunk(index, &data)?;
+        Ok(data)
+    }
+
+    /// Picks up the partial file of an earlier attempt at the same transfer.
+    pub fn receive_transfer(&self, manifest: TransferManifest) -> Result<TransferReceiver<'_>> {
+        self.check_conflict(&manifest.metadata)?;
+        let mut receiver = self.open_transfer(manifest)?;
+        receiver.check_conflict = true;
+        Ok(receiver)
+    }
+
+    // Like receive_transfer, but replaces whatever the store has under the id.
+    pub(crate) fn open_transfer(&self, manifest: TransferManifest) -> Result<TransferReceiver<'_>> {
+        manifest.validate()?;
+        let id = &manifest.metadata.id;
+        let extension = if manifest.delta { "tx2delta.partial" } else { "tx2pack.partial" };
+        let partial = self.file_path(id, extension)?;
+        let state = self.file_path(id, "transfer.json")?;
+        let transfer_id = manifest.transfer_id();
+
+        let resumable = std::fs::read(&state)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<String>(&json).ok())
+            .is_some_and(|previous| previous == transfer_id);
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&partial)?;
+
+        let mut received = vec![false; manifest.chunk_count() as usize];
+        if resumable {
+            let written = file.metadata()?.len();
+            let mut chunk = vec![0u8; manifest.chunk_size as usize];
+            for (index, received) in (0..).zip(received.iter_mut()) {
+                let range = manifest.chunk_range(index).expect("index is below the chunk count");
+                if range.end > written {
+                    break;
+                }
+                let chunk = &mut chunk[..(range.end - range.start) as usize];
+                file.seek(SeekFrom::Start(range.start))?;
+                *received = file.read_exact(chunk).is_ok() && manifest.verify_chunk(index, chunk).is_ok();
+            }
+        } else {
+            file.set_len(0)?;
+            write_json_atomic(&state, &transfer_id)?;
+        }
+
+        Ok(TransferReceiver {
+            store: self,
+            remaining: received.iter().filter(|received| !**received).count() as u32,
+            manifest,
+            file,
+            received,
+            partial,
+            state,
+            check_conflict: false,
+        })
+    }
+}
+
+pub struct TransferReceiver<'a> {
+    store: &'a SnapshotStore,
+    manifest: TransferManifest,
+    file: File,
+    received: Vec<bool>,
+    remaining: u32,
+    partial: PathBuf,
+    state: PathBuf,
+    // Checked again in finish, as the stored snapshot may have changed
+    // while the chunks were arriving.
+    check_conflict: bool,
+}
+
+impl TransferReceiver<'_> {
+    pub fn manifest(&self) -> &TransferManifest {
+        &self.manifest
+    }
+
+    pub fn next_chunk(&self) -> u32 {
+        self.received.iter().position(|received| !received).map_or(self.manifest.chunk_count(), |index| index as u32)
+    }
+
+    pub fn missing_chunks(&self) -> Vec<u32> {
+        (0..).zip(&self.received).filter(|(_, received)| !**received).map(|(index, _)| index).collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Chunks that fail their hash are rejected; repeated ones are ignored.
+    pub fn write_chunk(&mut self, index: u32, data: &[u8]) -> Result<()> {
+        self.manifest.verify_chunk(index, data)?;
+        if self.received[index as usize] {
+            return Ok(());
+        }
+        let range = self.manifest.chunk_range(index).expect("verified chunks are in range");
+        self.file.seek(SeekFrom::Start(range.start))?;
+        self.file.write_all(data)?;
+        self.received[index as usize] = true;
+        self.remaining -= 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<PathBuf> {
+        if !self.is_complete() {
+            return Err(PackError::InvalidFormat(format!(
+                "Transfer of {} stopped with {} of {} chunks missing",
+                self.manifest.metadata.id,
+                self.remaining,
+                self.manifest.chunk_count()
+            )));
+        }
+        self.file.sync_all()?;
+        drop(self.file);
+
+        let id = &self.manifest.metadata.id;
+        let (extension, other) = if self.manifest.delta { ("tx2delta", "tx2pack") } else { ("tx2pack", "tx2delta") };
+        let path = self.store.file_path(id, extension)?;
+        let stale = self.store.file_path(id, other)?;
+        let metadata_path = self.store.file_path(id, "meta.json")?;
+        let _lock = self.store.lock_snapshot(id)?;
+        if self.check_conflict {
+            self.store.check_conflict(&self.manifest.metadata)?;
+        }
+        std::fs::rename(&self.partial, &path)?;
+        if stale.exists() {
+            std::fs::remove_file(stale)?;
+        }
+
+        std::fs::write(metadata_path, serde_json::to_string_pretty(&self.manifest.metadata)?)?;
+        self.store.index_metadata(&self.manifest.metadata, self.manifest.total_size)?;
+        std::fs::remove_file(&self.state)?;
+
+        Ok(path)
+    }
+}
+
+/// After a dropped connection, running it again only sends the missing chunks.
+pub fn send_snapshot<S: Read + Write>(store: &SnapshotStore, id: &str, chunk_size: u32, stream: &mut S) -> Result<()> {
+    let manifest = store.transfer_manifest(id, chunk_size)?;
+    write_frame(stream, &TransferFrame::Manifest(Box::new(manifest.clone())))?;
+
+    let next_chunk = match read_frame(stream, MAX_CONTROL_FRAME)? {
+        TransferFrame::Resume { next_chunk } => next_chunk,
+        other => return Err(unexpected_frame("Resume", &other)),
+    };
+    for index in next_chunk..manifest.chunk_count() {
+        let data = store.read_transfer_chunk(&manifest, index)?;
+        write_frame(stream, &TransferFrame::Chunk { index, data })?;
+    }
+
+    match read_frame(stream, MAX_CONTROL_FRAME)? {
+        TransferFrame::Complete => Ok(()),
+        other => Err(unexpected_frame("Complete", &other)),
+    }
+}
+
+pub fn receive_snapshot<S: Read + Write>(store: &SnapshotStore, stream: &mut S) -> Result<PathBuf> {
+    let manifest = match read_frame(stream, MAX_CONTROL_FRAME)? {
+        TransferFrame::Manifest(manifest) => *manifest,
+        other => return Err(unexpected_frame("Manifest", &other)),
+    };
+    manifest.validate()?;
+    let max_frame = manifest.max_chunk_frame();
+    let mut receiver = store.receive_transfer(manifest)?;
+    write_frame(stream, &TransferFrame::Resume { next_chunk: receiver.next_chunk() })?;
+
+    while !receiver.is_complete() {
+        match read_frame(stream, max_frame)? {
+            TransferFrame::Chunk { index, data } => receiver.write_chunk(index, &data)?,
+            other => return Err(unexpected_frame("Chunk", &other)),
+        }
+    }
+    let path = receiver.finish()?;
+    write_frame(stream, &TransferFrame::Complete)?;
+
+    Ok(path)
+}
+
+pub(crate) fn unexpected_frame(expected: &str, frame: &TransferFrame) -> PackError {
+    let got = match frame {
+        TransferFrame::Manifest(_) => "Manifest",
+        TransferFrame::Resume { .. } => "Resume",
+        TransferFrame::Missing { .. } => "Missing",
+        TransferFrame::Chunk { .. } => "Chunk",
+        TransferFrame::Complete => "Complete",
+    };
+    PackError::InvalidFormat(format!("Expected a {} frame, got {}", expected, got))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot};
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+    use std::net::{TcpListener, TcpStream};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_interrupted_transfer_resumes_after_last_verified_chunk() {
+        let (source_dir, target_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let source = SnapshotStore::new(source_dir.path()).unwrap();
+        let target = SnapshotStore::new(target_dir.path()).unwrap();
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.archetypes.push(ComponentArchetype {
+            component_id: "Terrain".to_string(),
+            entity_ids: vec![1],
+            data: ComponentData::Blob((0..20_000u32).flat_map(|v| v.to_le_bytes()).collect()),
+        });
+        let writer = SnapshotWriter::new().with_compression(crate::CompressionCodec::None);
+        source.save(&snapshot, &SnapshotMetadata::new("level".to_string()), &writer).unwrap();
+
+        let manifest = source.transfer_manifest("level", 4096).unwrap();
+        assert!(manifest.chunk_count() > 4);
+
+        // The link drops after three chunks, one of them sent corrupted first.
+        let mut receiver = target.receive_transfer(manifest.clone()).unwrap();
+        receiver.write_chunk(0, &source.read_transfer_chunk(&manifest, 0).unwrap()).unwrap();
+        let mut corrupt = source.read_transfer_chunk(&manifest, 1).unwrap();
+        corrupt[7] ^= 1;
+        assert!(matches!(receiver.write_chunk(1, &corrupt), Err(PackError::ChunkChecksumMismatch { chunk: 1 })));
+        receiver.write_chunk(3, &source.read_transfer_chunk(&manifest, 3).unwrap()).unwrap();
+        receiver.write_chunk(1, &source.read_transfer_chunk(&manifest, 1).unwrap()).unwrap();
+        drop(receiver);
+        assert!(target.list().unwrap().is_empty());
+
+        let receiver = target.receive_transfer(manifest.clone()).unwrap();
+        assert_eq!(receiver.next_chunk(), 2);
+        assert_eq!(receiver.missing_chunks().len(), manifest.chunk_count() as usize - 3);
+        drop(receiver);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut ours = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let receiving = std::thread::spawn(move || {
+            let path = receive_snapshot(&target, &mut listener.accept().unwrap().0).unwrap();
+            (target, path)
+        });
+        send_snapshot(&source, "level", 4096, &mut ours).unwrap();
+        let (target, path) = receiving.join().unwrap();
+
+        assert_eq!(std::fs::read(path).unwrap(), std::fs::read(source_dir.path().join("level.tx2pack")).unwrap());
+        let (loaded, metadata) = target.load("level", &SnapshotReader::new()).unwrap();
+        assert_eq!(loaded.content_checksum().unwrap(), snapshot.content_checksum().unwrap());
+        assert_eq!(metadata.checksum, source.load_metadata("level").unwrap().checksum);
+        assert!(!target_dir.path().join("level.transfer.json").exists());
+    }
+
+    #[test]
+    fn test_receivers_reject_inconsistent_manifests() {
+        let (source_dir, target_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let source = SnapshotStore::new(source_dir.path()).unwrap();
+        let target = SnapshotStore::new(target_dir.path().join("store")).unwrap();
+        let metadata = SnapshotMetadata::new("level".to_string());
+        source.save(&PackedSnapshot::new(), &metadata, &SnapshotWriter::new()).unwrap();
+        let manifest = source.transfer_manifest("level", 16).unwrap();
+        manifest.validate().unwrap();
+
+        let mut escaping = manifest.clone();
+        escaping.metadata.id = "../level".to_string();
+        assert!(matches!(target.receive_transfer(escaping).err(), Some(PackError::InvalidSnapshotId(_))));
+        assert!(!target_dir.path().join("level.tx2pack.partial").exists());
+
+        let mut extra = manifest.clone();
+        extra.chunk_hashes.push([0; 32]);
+        assert!(matches!(target.receive_transfer(extra).err(), Some(PackError::InvalidFormat(_))));
+
+        let mut huge = manifest;
+        huge.chunk_size = u32::MAX;
+        assert!(matches!(huge.validate(), Err(PackError::LimitExceeded { limit: "transfer chunk size", .. })));
+    }
+}
@@ -0,0 +1,333 @@
+use crate::catalog::write_json_atomic;
+use crate::error::{PackError, Result};
+use crate::storage::SnapshotStore;
+use crate::throttle::Throttle;
+use crate::transfer::{send_snapshot, DEFAULT_TRANSFER_CHUNK_SIZE};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const UPLOAD_QUEUE_FILE: &str = "upload_queue.json";
+
+pub struct UploadOptions {
+    bytes_per_sec: Option<u64>,
+    chunk_size: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    queue_file: Option<PathBuf>,
+}
+
+impl UploadOptions {
+    pub fn new() -> Self {
+        Self {
+            bytes_per_sec: None,
+            chunk_size: DEFAULT_TRANSFER_CHUNK_SIZE,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5 * 60),
+            queue_file: None,
+        }
+    }
+
+    pub fn with_bandwidth_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.bytes_per_sec = Some(bytes_per_sec.max(1));
+        self
+    }
+
+    pub fn with_chunk_size(mut self, bytes: u32) -> Self {
+        self.chunk_size = bytes;
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Defaults to `upload_queue.json` in the store's directory.
+    pub fn with_queue_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.queue_file = Some(path.into());
+        self
+    }
+
+    fn backoff(&self, failures: u32) -> Duration {
+        let doublings = failures.saturating_sub(1).min(31);
+        self.initial_backoff.saturating_mul(1 << doublings).min(self.max_backoff)
+    }
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<String>,
+    failures: u32,
+    last_error: Option<String>,
+    stopping: bool,
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    queue_file: PathBuf,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn persist(&self, state: &QueueState) -> Result<()> {
+        write_json_atomic(&self.queue_file, &state.pending)
+    }
+}
+
+/// Uploads snapshots in queue order with `send_snapshot`. The queue is
+/// persisted on every change, so ids queued before a restart survive it.
+pub struct UploadQueue {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl UploadQueue {
+    pub fn start<F, S>(store: Arc<SnapshotStore>, options: UploadOptions, connect: F) -> Result<Self>
+    where
+        F: FnMut() -> io::Result<S> + Send + 'static,
+        S: Read + Write,
+    {
+        let queue_file = options.queue_file.clone().unwrap_or_else(|| store.root_dir().join(UPLOAD_QUEUE_FILE));
+        let pending = match std::fs::read(&queue_file) {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(QueueState { pending, ..QueueState::default() }),
+            changed: Condvar::new(),
+            queue_file,
+        });
+        let worker = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("tx2-pack-upload".to_string())
+                .spawn(move || run(&shared, &store, &options, connect))?
+        };
+
+        Ok(Self { shared, worker: Some(worker) })
+    }
+
+    pub fn enqueue(&self, id: &str) -> Result<()> {
+        let mut state = self.shared.lock();
+        if !state.pending.iter().any(|pending| pending == id) {
+            state.pending.push_back(id.to_string());
+            self.shared.persist(&state)?;
+            self.shared.changed.notify_all();
+        }
+        Ok(())
+    }
+
+    /// Includes the upload in progress.
+    pub fn pending(&self) -> Vec<String> {
+        self.shared.lock().pending.iter().cloned().collect()
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.shared.lock().last_error.clone()
+    }
+
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let state = self.shared.lock();
+        let (state, _) = self
+            .shared
+            .changed
+            .wait_timeout_while(state, timeout, |state| !state.pending.is_empty())
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.pending.is_empty()
+    }
+
+    /// Stops after the upload in progress; the rest stays queued on disk.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.shared.lock().stopping = true;
+        self.shared.changed.notify_all();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for UploadQueue {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run<F, S>(shared: &Shared, store: &SnapshotStore, options: &UploadOptions, mut connect: F)
+where
+    F: FnMut() -> io::Result<S>,
+    S: Read + Write,
+{
+    loop {
+        let id = {
+            let state = shared.lock();
+            let state = shared
+                .changed
+                .wait_while(state, |state| state.pending.is_empty() && !state.stopping)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            match state.pending.front() {
+                Some(id) if !state.stopping => id.clone(),
+                _ => return,
+            }
+        };
+
+        let result = upload(store, &id, options, &mut connect);
+
+        let mut state = shared.lock();
+        match result {
+            Ok(()) => {}
+            // Snapshots deleted while queued are dropped rather than retried.
+            Err(PackError::SnapshotNotFound(_)) if !store.exists(&id) => {}
+            Err(error) => {
+                state.failures += 1;
+                state.last_error = Some(error.to_string());
+                let retry_at = Instant::now() + options.backoff(state.failures);
+                while !state.stopping {
+                    let now = Instant::now();
+                    if now >= retry_at {
+                        break;
+                    }
+                    state = shared.changed.wait_timeout(state, retry_at - now).map_or_else(
+                        |poisoned| poisoned.into_inner().0,
+                        |(state, _)| state,
+                    );
+                }
+                continue;
+            }
+        }
+
+        state.failures = 0;
+        state.last_error = None;
+        if state.pending.front() == Some(&id) {
+            state.pending.pop_front();
+        }
+        if let Err(error) = shared.persist(&state) {
+            state.last_error = Some(error.to_string());
+        }
+        shared.changed.notify_all();
+    }
+}
+
+fn upload<F, S>(store: &SnapshotStore, id: &str, options: &UploadOptions, connect: &mut F) -> Result<()>
+where
+    F: FnMut() -> io::Result<S>,
+    S: Read + Write,
+{
+    if !store.exists(id) {
+        return Err(PackError::SnapshotNotFound(id.to_string()));
+    }
+    let mut stream = ThrottledStream {
+        inner: connect()?,
+        throttle: Throttle::new(options.bytes_per_sec),
+    };
+    send_snapshot(store, id, options.chunk_size, &mut stream)
+}
+
+// Caps what goes out; replies from the receiver are small and read as is.
+struct ThrottledStream<S> {
+    inner: S,
+    throttle: Throttle,
+}
+
+impl<S: Read> Read for ThrottledStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for ThrottledStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.throttle.write_all(&mut self.inner, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentArchetype, ComponentData, PackedSnapshot};
+    use crate::metadata::SnapshotMetadata;
+    use crate::storage::SnapshotWriter;
+    use crate::transfer::receive_snapshot;
+    use crate::CompressionCodec;
+    use std::net::{TcpListener, TcpStream};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_upload_queue_retries_throttles_and_survives_restarts() {
+        let (client_dir, server_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let store = Arc::new(SnapshotStore::new(client_dir.path()).unwrap());
+        let writer = SnapshotWriter::new().with_compression(CompressionCodec::None);
+        for id in ["match-1", "match-2"] {
+            let mut snapshot = PackedSnapshot::new();
+            snapshot.archetypes.push(ComponentArchetype {
+                component_id: "Telemetry".to_string(),
+                entity_ids: vec![1],
+                data: ComponentData::Blob(vec![7; 10_000]),
+            });
+            store.save(&snapshot, &SnapshotMetadata::new(id.to_string()), &writer).unwrap();
+        }
+        let backoff = |options: UploadOptions| options.with_backoff(Duration::from_millis(5), Duration::from_millis(20));
+
+        // Nothing is listening yet: the ids stay queued across the restart.
+        let offline = UploadQueue::start(store.clone(), backoff(UploadOptions::new()), || {
+            Err::<TcpStream, _>(io::Error::from(io::ErrorKind::ConnectionRefused))
+        })
+        .unwrap();
+        offline.enqueue("match-1").unwrap();
+        offline.enqueue("match-2").unwrap();
+        offline.enqueue("match-1").unwrap();
+        assert!(!offline.wait_idle(Duration::from_millis(50)));
+        assert!(offline.last_error().is_some());
+        offline.shutdown();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_path = server_dir.path().to_path_buf();
+        let server = thread::spawn(move || {
+            let remote = SnapshotStore::new(server_path).unwrap();
+            for _ in 0..2 {
+                receive_snapshot(&remote, &mut listener.accept().unwrap().0).unwrap();
+            }
+        });
+
+        let started = Instant::now();
+        let options = backoff(UploadOptions::new()).with_bandwidth_limit(100_000);
+        let queue = UploadQueue::start(store, options, move || TcpStream::connect(addr)).unwrap();
+        assert_eq!(queue.pending(), vec!["match-1", "match-2"]);
+        assert!(queue.wait_idle(Duration::from_secs(10)));
+        server.join().unwrap();
+
+        // Two 10 KB snapshots at 100 KB/s.
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(queue.last_error(), None);
+        let remote = SnapshotStore::new(server_dir.path()).unwrap();
+        assert!(remote.exists("match-1") && remote.exists("match-2"));
+        let queued = std::fs::read(client_dir.path().join(UPLOAD_QUEUE_FILE)).unwrap();
+        assert!(serde_json::from_slice::<Vec<String>>(&queued).unwrap().is_empty());
+    }
+}
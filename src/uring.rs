@@ -0,0 +1,268 @@
+use crate::error::{PackError, Result};
+use io_uring::{opcode, squeue, types, IoUring};
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+
+const MAX_IO_SIZE: usize = 1 << 30;
+const ECANCELED: i32 = 125;
+
+pub struct UringWrite<'a> {
+    pub path: &'a Path,
+    pub bytes: &'a [u8],
+    pub sync: bool,
+}
+
+pub struct UringIo {
+    ring: Mutex<IoUring>,
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Write { file: usize, offset: usize, len: usize },
+    Read { offset: usize, len: usize },
+    Fsync { file: usize },
+}
+
+#[derive(Default)]
+struct Fallback {
+    ranges: Vec<(usize, usize)>,
+    sync: bool,
+}
+
+impl UringIo {
+    pub fn new(entries: u32) -> Result<Self> {
+        Ok(Self {
+            ring: Mutex::new(IoUring::new(entries.max(2))?),
+        })
+    }
+
+    pub fn write_files(&self, writes: &[UringWrite<'_>]) -> Result<()> {
+        let files = writes
+            .iter()
+            .map(|write| File::create(write.path))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let chains = writes
+            .iter()
+            .enumerate()
+            .map(|(file, write)| {
+                let mut chain: Vec<Op> = io_ranges(write.bytes.len())
+                    .map(|(offset, len)| Op::Write { file, offset, len })
+                    .collect();
+                if write.sync {
+                    chain.push(Op::Fsync { file });
+                }
+                chain
+            })
+            .collect();
+
+        let mut fallbacks: Vec<Fallback> = writes.iter().map(|_| Fallback::default()).collect();
+
+        self.run(chains, |op| match *op {
+            Op::Write { file, offset, len } => {
+                let buf = writes[file].bytes[offset..].as_ptr();
+                opcode::Write::new(types::Fd(files[file].as_raw_fd()), buf, len as u32)
+                    .offset(offset as u64)
+                    .build()
+            }
+            Op::Fsync { file } => opcode::Fsync::new(types::Fd(files[file].as_raw_fd())).build(),
+            Op::Read { .. } => unreachable!("write batches contain no reads"),
+        }, |op, result| {
+            match *op {
+                Op::Write { file, offset, len } => match result {
+                    Ok(written) if written >= len => {}
+                    Ok(written) => fallbacks[file].ranges.push((offset + written, len - written)),
+                    Err(ECANCELED) => fallbacks[file].ranges.push((offset, len)),
+                    Err(errno) => return Err(std::io::Error::from_raw_os_error(errno).into()),
+                },
+                Op::Fsync { file } => match result {
+                    Ok(_) => {}
+                    Err(ECANCELED) => fallbacks[file].sync = true,
+                    Err(errno) => return Err(std::io::Error::from_raw_os_error(errno).into()),
+                },
+                Op::Read { .. } => {}
+            }
+            Ok(())
+        })?;
+
+        // Short writes break the link chain, so whatever the ring left
+        // unfinished is completed with plain positioned writes.
+        for ((fallback, file), write) in fallbacks.iter().zip(&files).zip(writes) {
+            for &(offset, len) in &fallback.ranges {
+                file.write_all_at(&write.bytes[offset..offset + len], offset as u64)?;
+            }
+            if fallback.sync || (write.sync && !fallback.ranges.is_empty()) {
+                file.sync_all()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        let file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| PackError::InvalidFormat(format!("{} is too large to read", path.display())))?;
+        let mut buffer = vec![0u8; len];
+        let base = buffer.as_mut_ptr();
+
+        let reads = io_ranges(len)
+            .map(|(offset, len)| vec![Op::Read { offset, len }])
+            .collect();
+        let mut short = Vec::new();
+
+        self.run(reads, |op| match *op {
+            // Each read targets a disjoint range of `buffer`, which outlives the batch.
+            Op::Read { offset, len } => opcode::Read::new(types::Fd(file.as_raw_fd()), unsafe { base.add(offset) }, len as u32)
+                .offset(offset as u64)
+                .build(),
+            _ => unreachable!("read batches contain only reads"),
+        }, |op, result| {
+            if let Op::Read { offset, len } = *op {
+                match result {
+                    Ok(read) if read >= len => {}
+                    Ok(read) => short.push((offset + read, len - read)),
+                    Err(errno) => return Err(std::io::Error::from_raw_os_error(errno).into()),
+                }
+            }
+            Ok(())
+        })?;
+
+        for (offset, len) in short {
+            file.read_exact_at(&mut buffer[offset..offset + len], offset as u64)?;
+        }
+
+        Ok(buffer)
+    }
+
+    fn run<B, C>(&self, chains: Vec<Vec<Op>>, build: B, mut complete: C) -> Result<()>
+    where
+        B: Fn(&Op) -> squeue::Entry,
+        C: FnMut(&Op, std::result::Result<usize, i32>) -> Result<()>,
+    {
+        let mut ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        let capacity = ring.params().sq_entries() as usize;
+        let ops: Vec<Op> = chains.iter().flatten().copied().collect();
+        let mut chains = chains.iter().peekable();
+        let mut next = 0;
+
+        while let Some(first) = chains.peek() {
+            if first.len() > capacity {
+                return Err(PackError::InvalidFormat(format!(
+                    "io_uring queue of {} entries is too small for a {} operation chain", capacity, first.len()
+                )));
+            }
+
+            // Whole chains only: a link must not straddle two submissions.
+            let mut batch = 0;
+            {
+                let mut submission = ring.submission();
+                while let Some(chain) = chains.next_if(|chain| batch + chain.len() <= capacity) {
+                    for (position, op) in chain.iter().enumerate() {
+                        let mut entry = build(op).user_data(next as u64);
+                        if position + 1 < chain.len() {
+                            entry = entry.flags(squeue::Flags::IO_LINK);
+                        }
+                        // Buffers and descriptors referenced by the entry live
+                        // until every completion of this batch is reaped below.
+                        unsafe { submission.push(&entry) }
+                            .map_err(|_| PackError::InvalidFormat("io_uring submission queue is full".to_string()))?;
+                        next += 1;
+                    }
+                    batch += chain.len();
+                }
+            }
+
+            // Every completion is reaped before an error is reported so no
+            // operation is still writing into a buffer the caller frees.
+            let mut reaped = 0;
+            let mut failure = None;
+            while reaped < batch {
+                match ring.submit_and_wait(batch - reaped) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e.into()),
+                }
+                let completed: Vec<_> = ring.completion().map(|cqe| (cqe.user_data(), cqe.result())).collect();
+                for (user_data, result) in completed {
+                    let result = if result < 0 { Err(-result) } else { Ok(result as usize) };
+                    if let Err(e) = complete(&ops[user_data as usize], result) {
+                        failure.get_or_insert(e);
+                    }
+                    reaped += 1;
+                }
+            }
+
+            if let Some(e) = failure {
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn io_ranges(len: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..len.div_ceil(MAX_IO_SIZE).max(1)).map(move |chunk| {
+        let offset = chunk * MAX_IO_SIZE;
+        (offset, (len - offset).min(MAX_IO_SIZE))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batched_writes_and_reads() {
+        // Sandboxes commonly disable io_uring; there is nothing to test then.
+        let uring = match UringIo::new(8) {
+            Ok(uring) => uring,
+            Err(_) => return,
+        };
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let payload: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        let (pack, meta, empty) = (
+            temp_dir.path().join("a.tx2pack"),
+            temp_dir.path().join("a.meta.json"),
+            temp_dir.path().join("empty"),
+        );
+
+        uring
+            .write_files(&[
+                UringWrite { path: &pack, bytes: &payload, sync: true },
+                UringWrite { path: &meta, bytes: b"{}", sync: false },
+                UringWrite { path: &empty, bytes: &[], sync: true },
+            ])
+            .unwrap();
+
+        assert_eq!(uring.read_file(&pack).unwrap(), payload);
+        assert_eq!(std::fs::read(&meta).unwrap(), b"{}");
+        assert!(uring.read_file(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_store_round_trip_over_io_uring() {
+        use crate::format::PackedSnapshot;
+        use crate::metadata::SnapshotMetadata;
+        use crate::storage::{SnapshotReader, SnapshotStore, SnapshotWriter};
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let store = match SnapshotStore::new(temp_dir.path()).unwrap().with_io_uring(16) {
+            Ok(store) => store,
+            Err(_) => return,
+        };
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.timestamp = 42;
+        store.save(&snapshot, &SnapshotMetadata::new("tick-1".to_string()), &SnapshotWriter::new()).unwrap();
+
+        let (loaded, metadata) = store.load("tick-1", &SnapshotReader::new()).unwrap();
+        assert_eq!(loaded.header.timestamp, 42);
+        assert!(metadata.checksum.is_some());
+    }
+}
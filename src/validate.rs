@@ -0,0 +1,415 @@
+use crate::bounds;
+use crate::error::{PackError, Result};
+use crate::format::{
+    ComponentArchetype, ComponentData, EntityMetadata, PackFormat, SnapshotHeader, FORMAT_VERSION, FORMAT_VERSION_MAJOR,
+    HEADER_SIZE, MAGIC_NUMBER,
+};
+use crate::sectioned;
+use crate::storage::{read_at, verify_file_checksum, SnapshotReader};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::path::Path;
+use tx2_link::{ComponentId, EntityId};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ValidationIssue {
+    Truncated { file_len: u64, required: u64 },
+    InvalidHeader(String),
+    InvalidMagic,
+    VersionMismatch { expected: u32, actual: u32 },
+    DataOutOfBounds { offset: u64, size: u64, file_len: u64 },
+    ChecksumMismatch,
+    UnreadableTableOfContents(String),
+    SectionOutOfBounds { section: String, offset: u64, size: u64 },
+    UnreadableSection { section: String, error: String },
+    TableOfContentsMismatch { component_id: ComponentId, message: String },
+    DuplicateComponent(ComponentId),
+    DuplicateEntity { component_id: ComponentId, entity_id: EntityId },
+    ColumnLengthMismatch { component_id: ComponentId, column: String, expected: usize, actual: usize },
+    SchemaMismatch { component_id: ComponentId, message: String },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub header: Option<SnapshotHeader>,
+    pub checksum_verified: bool,
+    pub archetypes_checked: usize,
+    pub entities_checked: u64,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn check_archetype(&mut self, archetype: &ComponentArchetype, components: &mut HashSet<ComponentId>) {
+        self.archetypes_checked += 1;
+        self.entities_checked += archetype.entity_ids.len() as u64;
+        self.issues.extend(archetype_issues(archetype));
+
+        if !components.insert(archetype.component_id.clone()) {
+            self.issues.push(ValidationIssue::DuplicateComponent(archetype.component_id.clone()));
+        }
+    }
+}
+
+pub fn validate<P: AsRef<Path>>(path: P) -> Result<ValidationReport> {
+    SnapshotReader::new().validate_file(path)
+}
+
+impl SnapshotReader {
+    pub fn validate_file<P: AsRef<Path>>(&self, path: P) -> Result<ValidationReport> {
+        let mut file = File::open(path.as_ref())?;
+        let mut report = ValidationReport::default();
+
+        if let Some(header) = check_header(&mut file, &mut report)? {
+            match verify_file_checksum(&mut file, &header) {
+                Ok(()) => report.checksum_verified = true,
+                Err(PackError::ChecksumMismatch) => report.issues.push(ValidationIssue::ChecksumMismatch),
+                Err(e) => return Err(e),
+            }
+
+            if header.format == PackFormat::Custom {
+                self.check_sections(&mut file, &header, &mut report)?;
+            } else {
+                self.check_payload(&mut file, &header, &mut report)?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    // Sections are decoded one at a time and dropped once checked, so memory
+    // stays bounded by the largest archetype rather than the whole snapshot.
+    fn check_sections(&self, file: &mut File, header: &SnapshotHeader, report: &mut ValidationReport) -> Result<()> {
+        let open = |sealed: &[u8]| self.open(header, sealed);
+        let (toc, sections_end) = match sectioned::read_file_toc(file, header, open) {
+            Ok(toc) => toc,
+            Err(e) => {
+                report.issues.push(ValidationIssue::UnreadableTableOfContents(e.to_string()));
+                return Ok(());
+            }
+        };
+
+        let mut components = HashSet::new();
+        for entry in &toc.archetypes {
+            let raw = match read_section(file, header, &entry.section, sections_end, &entry.component_id, report, open)? {
+                Some(raw) => raw,
+                None => continue,
+            };
+
+            let archetype = match sectioned::decode_archetype(&raw, header.flags) {
+                Ok(archetype) => archetype,
+                Err(e) => {
+                    report.issues.push(ValidationIssue::UnreadableSection {
+                        section: entry.component_id.clone(),
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            if archetype.component_id != entry.component_id {
+                report.issues.push(ValidationIssue::TableOfContentsMismatch {
+                    component_id: entry.component_id.clone(),
+                    message: format!("section holds component {}", archetype.component_id),
+                });
+            }
+            if archetype.entity_ids.len() as u64 != entry.entity_count {
+                report.issues.push(ValidationIssue::TableOfContentsMismatch {
+                    component_id: entry.component_id.clone(),
+                    message: format!(
+                        "table of contents lists {} entities, section holds {}",
+                        entry.entity_count,
+                        archetype.entity_ids.len()
+                    ),
+                });
+            }
+
+            report.check_archetype(&archetype, &mut components);
+        }
+
+        let label = "entity_metadata".to_string();
+        if let Some(raw) = read_section(file, header, &toc.entity_metadata, sections_end, &label, report, open)? {
+            if let Err(e) = bincode::deserialize::<HashMap<EntityId, EntityMetadata>>(&raw) {
+                report.issues.push(ValidationIssue::UnreadableSection {
+                    section: label,
+                    error: e.to_string(),
+                });
+            }
+        }
+
+        let label = "resources".to_string();
+        if toc.resources.size > 0 {
+            if let Some(raw) = read_section(file, header, &toc.resources, sections_end, &label, report, open)? {
+                if let Err(e) = bincode::deserialize::<BTreeMap<String, Vec<u8>>>(&raw) {
+                    report.issues.push(ValidationIssue::UnreadableSection {
+                        section: label,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_payload(&self, file: &mut File, header: &SnapshotHeader, report: &mut ValidationReport) -> Result<()> {
+        let data = read_at(file, header.data_offset, header.data_size)?;
+        let snapshot = match self.snapshot_from_data(header.clone(), &data) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                report.issues.push(ValidationIssue::UnreadableSection {
+                    section: format!("{:?} payload", header.format),
+                    error: e.to_string(),
+                });
+                return Ok(());
+            }
+        };
+
+        let mut components = HashSet::new();
+        for archetype in &snapshot.archetypes {
+            report.check_archetype(archetype, &mut components);
+        }
+
+        Ok(())
+    }
+}
+
+fn check_header(file: &mut File, report: &mut ValidationReport) -> Result<Option<SnapshotHeader>> {
+    let file_len = file.metadata()?.len();
+    if file_len < HEADER_SIZE {
+        report.issues.push(ValidationIssue::Truncated { file_len, required: HEADER_SIZE });
+        return Ok(None);
+    }
+
+    let header = match SnapshotHeader::decode(&read_at(file, 0, HEADER_SIZE)?) {
+        Ok(header) => header,
+        Err(e) => {
+            report.issues.push(ValidationIssue::InvalidHeader(e.to_string()));
+            return Ok(None);
+        }
+    };
+    report.header = Some(header.clone());
+
+    if header.magic != *MAGIC_NUMBER {
+        report.issues.push(ValidationIssue::InvalidMagic);
+        return Ok(None);
+    }
+
+    if header.version_major() != FORMAT_VERSION_MAJOR && !header.is_legacy() {
+        report.issues.push(ValidationIssue::VersionMismatch {
+            expected: FORMAT_VERSION,
+            actual: header.version,
+        });
+        return Ok(None);
+    }
+
+    if bounds::end_within(header.data_offset, header.data_size, file_len, "Data").is_err() {
+        report.issues.push(ValidationIssue::DataOutOfBounds {
+            offset: header.data_offset,
+            size: header.data_size,
+            file_len,
+        });
+        return Ok(None);
+    }
+
+    Ok(Some(header))
+}
+
+fn read_section<F>(
+    file: &mut File,
+    header: &SnapshotHeader,
+    section: &sectioned::Section,
+    sections_end: u64,
+    label: &str,
+    report: &mut ValidationReport,
+    open: F,
+) -> Result<Option<Vec<u8>>>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    if bounds::end_within(section.offset, section.size, sections_end, "Section").is_err() {
+        report.issues.push(ValidationIssue::SectionOutOfBounds {
+            section: label.to_string(),
+            offset: section.offset,
+            size: section.size,
+        });
+        return Ok(None);
+    }
+
+    let sealed = read_at(file, bounds::end(header.data_offset, section.offset, "Section")?, section.size)?;
+    match sectioned::open_chunked(&sealed, open) {
+        Ok(raw) => Ok(Some(raw)),
+        Err(e) => {
+            report.issues.push(ValidationIssue::UnreadableSection {
+                section: label.to_string(),
+                error: e.to_string(),
+            });
+            Ok(None)
+        }
+    }
+}
+
+pub fn archetype_issues(archetype: &ComponentArchetype) -> Vec<ValidationIssue> {
+    let component_id = &archetype.component_id;
+    let mut issues = Vec::new();
+
+    let mut seen = HashSet::with_capacity(archetype.entity_ids.len());
+    for &entity_id in &archetype.entity_ids {
+        if !seen.insert(entity_id) {
+            issues.push(ValidationIssue::DuplicateEntity {
+                component_id: component_id.clone(),
+                entity_id,
+            });
+        }
+    }
+
+    let soa = match &archetype.data {
+        ComponentData::StructOfArrays(soa) => soa,
+        ComponentData::Blob(_) => return issues,
+    };
+
+    if soa.field_names.len() != soa.field_types.len() || soa.field_names.len() != soa.field_data.len() {
+        issues.push(ValidationIssue::SchemaMismatch {
+            component_id: component_id.clone(),
+            message: format!(
+                "{} field names, {} field types and {} columns",
+                soa.field_names.len(),
+                soa.field_types.len(),
+                soa.field_data.len()
+            ),
+        });
+    }
+
+    let mut names = HashSet::with_capacity(soa.field_names.len());
+    for name in &soa.field_names {
+        if !names.insert(name) {
+            issues.push(ValidationIssue::SchemaMismatch {
+                component_id: component_id.clone(),
+                message: format!("field {} is declared more than once", name),
+            });
+        }
+    }
+
+    for ((name, field_type), column) in soa.field_names.iter().zip(&soa.field_types).zip(&soa.field_data) {
+        if column.field_type() != *field_type {
+            issues.push(ValidationIssue::SchemaMismatch {
+                component_id: component_id.clone(),
+                message: format!("field {} is declared {:?} but holds {:?}", name, field_type, column.field_type()),
+            });
+        }
+
+        if column.len() != archetype.entity_ids.len() {
+            issues.push(ValidationIssue::ColumnLengthMismatch {
+                component_id: component_id.clone(),
+                column: name.clone(),
+                expected: archetype.entity_ids.len(),
+                actual: column.len(),
+            });
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{FieldArray, FieldType, PackedSnapshot, StructOfArraysData};
+    use crate::storage::SnapshotWriter;
+
+    fn archetype(ids: Vec<EntityId>, hp: Vec<u32>) -> ComponentArchetype {
+        ComponentArchetype {
+            component_id: "Health".to_string(),
+            entity_ids: ids,
+            data: ComponentData::StructOfArrays(StructOfArraysData {
+                field_names: vec!["hp".to_string()],
+                field_types: vec![FieldType::U32],
+                field_data: vec![FieldArray::U32(hp.into())],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_structural_and_byte_damage() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("save.tx2pack");
+
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype(vec![1, 2, 3], vec![10, 20, 30]));
+        SnapshotWriter::new().write_to_file(&snapshot, &path).unwrap();
+
+        let report = validate(&path).unwrap();
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert!(report.checksum_verified);
+        assert_eq!((report.archetypes_checked, report.entities_checked), (1, 3));
+
+        snapshot.archetypes[0] = archetype(vec![1, 2, 2], vec![10, 20]);
+        SnapshotWriter::new().with_structure_checks(false).write_to_file(&snapshot, &path).unwrap();
+        let report = validate(&path).unwrap();
+        assert!(report.issues.contains(&ValidationIssue::DuplicateEntity {
+            component_id: "Health".to_string(),
+            entity_id: 2,
+        }));
+        assert!(report.issues.contains(&ValidationIssue::ColumnLengthMismatch {
+            component_id: "Health".to_string(),
+            column: "hp".to_string(),
+            expected: 3,
+            actual: 2,
+        }));
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - HEADER_SIZE as usize - 20;
+        bytes[last] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+        let report = validate(&path).unwrap();
+        assert!(report.issues.contains(&ValidationIssue::ChecksumMismatch));
+
+        bytes.truncate(HEADER_SIZE as usize + 4);
+        std::fs::write(&path, &bytes).unwrap();
+        let report = validate(&path).unwrap();
+        assert!(matches!(report.issues[..], [ValidationIssue::DataOutOfBounds { .. }]));
+
+        std::fs::write(&path, b"not a pack").unwrap();
+        let report = validate(&path).unwrap();
+        assert!(matches!(report.issues[..], [ValidationIssue::Truncated { .. }]));
+    }
+
+    fn column_of<T>(result: Result<T>) -> (ComponentId, String) {
+        match result.map_err(PackError::into_root) {
+            Err(PackError::InconsistentArchetype { component_id, column, .. }) => (component_id, column),
+            other => panic!("expected an inconsistent archetype, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_misaligned_archetypes_fail_with_the_column() {
+        let mut snapshot = PackedSnapshot::new();
+        snapshot.header.format = PackFormat::Custom;
+        snapshot.archetypes.push(archetype(vec![1, 2, 3], vec![10, 20]));
+
+        let expected = ("Health".to_string(), "hp".to_string());
+        assert_eq!(column_of(SnapshotWriter::new().write_to_bytes(&snapshot)), expected);
+
+        for format in [PackFormat::Custom, PackFormat::Bincode] {
+            snapshot.header.format = format;
+            let bytes = SnapshotWriter::new().with_structure_checks(false).write_to_bytes(&snapshot).unwrap();
+            let reader = SnapshotReader::new();
+            assert_eq!(column_of(reader.read_from_bytes(&bytes)), expected);
+
+            let lazy = reader.read_lazy_from_bytes(&bytes);
+            let lazy = lazy.and_then(|lazy| lazy.archetype(&"Health".to_string()).map(|_| ()));
+            assert_eq!(column_of(lazy), expected);
+        }
+
+        if let ComponentData::StructOfArrays(soa) = &mut snapshot.archetypes[0].data {
+            soa.field_data[0] = FieldArray::U32(vec![10, 20, 30].into());
+            soa.field_names.push("armor".to_string());
+        }
+        assert_eq!(column_of(snapshot.check_consistency()), ("Health".to_string(), "armor".to_string()));
+    }
+}
@@ -0,0 +1,331 @@
+use crate::error::{PackError, Result};
+use crate::format::{ComponentArchetype, PackedSnapshot};
+use crate::adapter::allocate_ids;
+use crate::registry::ComponentRegistry;
+use crate::remap::EntityIdMap;
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tx2_link::{ComponentId, EntityId, SerializedComponent, SerializedEntity, WorldSnapshot};
+
+// Implemented by worlds that can load a tx2-link snapshot, so a packed
+// snapshot can be applied to them directly.
+pub trait WorldSnapshotTarget {
+    // See WorldRestore::allocate_entity.
+    fn allocate_entity(&mut self, old_id: EntityId) -> Result<EntityId> {
+        Ok(old_id)
+    }
+
+    fn restore_from_snapshot(&mut self, snapshot: &WorldSnapshot) -> Result<()>;
+
+    // World snapshots have no place for resources, so they are handed over
+    // one at a time. Returns whether the world took it; the default takes
+    // none and leaves them all in the report.
+    fn restore_resource(&mut self, _name: &str, _data: &[u8]) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+// What a restore left behind. World snapshots carry only entities and their
+// components, so entity metadata is always listed here rather than lost
+// silently, as are resources the target did not take.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub entities_restored: u64,
+    pub components_restored: u64,
+    pub skipped_archetypes: Vec<SkippedArchetype>,
+    pub unrestored_metadata: Vec<EntityId>,
+    #[serde(default)]
+    pub unrestored_resources: Vec<String>,
+    pub id_map: EntityIdMap,
+}
+
+impl RestoreReport {
+    pub fn is_complete(&self) -> bool {
+        self.skipped_archetypes.is_empty()
+            && self.unrestored_metadata.is_empty()
+            && self.unrestored_resources.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedArchetype {
+    pub component_id: ComponentId,
+    pub entity_count: u64,
+    pub reason: String,
+}
+
+impl PackedSnapshot {
+    pub fn from_world_snapshot_with(snapshot: &WorldSnapshot, registry: &ComponentRegistry) -> Result<Self> {
+        let mut packed = Self::new();
+        packed.header.timestamp = snapshot.timestamp as i64;
+
+        let mut slots: AHashMap<&ComponentId, usize> = AHashMap::new();
+        let mut order: Vec<&ComponentId> = Vec::new();
+        let mut counts: Vec<usize> = Vec::new();
+        for entity in &snapshot.entities {
+            for component in &entity.components {
+                let slot = *slots.entry(&component.id).or_insert_with(|| {
+                    order.push(&component.id);
+                    counts.push(0);
+                    counts.len() - 1
+                });
+                counts[slot] += 1;
+            }
+        }
+
+        let mut columns: Vec<(Vec<EntityId>, Vec<&SerializedComponent>)> = counts
+            .iter()
+            .map(|&count| (Vec::with_capacity(count), Vec::with_capacity(count)))
+            .collect();
+        for entity in &snapshot.entities {
+            for component in &entity.components {
+                let (entity_ids, components) = &mut columns[slots[&component.id]];
+                entity_ids.push(entity.id);
+                components.push(component);
+            }
+        }
+
+        packed.archetypes = Vec::with_capacity(order.len());
+        for (component_id, (entity_ids, components)) in order.into_iter().zip(columns) {
+            packed.archetypes.push(registry.pack_components(component_id, entity_ids, &components)?);
+        }
+
+        packed.header.entity_count = snapshot.entities.len() as u64;
+        packed.header.component_count = packed.archetypes.len() as u64;
+        packed.header.archetype_count = packed.archetypes.len() as u64;
+        packed.rebuild_entity_index();
+
+        Ok(packed)
+    }
+
+    // Entities come back in id order, each with its components in archetype
+    // order. The header only keeps whole-second timestamps and no world
+    // version, so those are the caller's to carry if they matter.
+    pub fn to_world_snapshot(&self, registry: &ComponentRegistry) -> Result<WorldSnapshot> {
+        self.convert_to_world(registry, |_, e| Err(e))
+    }
+
+    // Like to_world_snapshot, but archetypes that cannot be converted are left
+    // out and listed in the report instead of failing the whole restore.
+    pub fn to_world_snapshot_lossy(&self, registry: &ComponentRegistry) -> (WorldSnapshot, RestoreReport) {
+        let mut report = RestoreReport::default();
+        let world = self
+            .convert_to_world(registry, |archetype, e| {
+                report.skipped_archetypes.push(SkippedArchetype {
+                    component_id: archetype.component_id.clone(),
+                    entity_count: archetype.entity_ids.len() as u64,
+                    reason: e.to_string(),
+                });
+                Ok(())
+            })
+            .expect("skipped archetypes never fail the conversion");
+
+        report.entities_restored = world.entities.len() as u64;
+        report.components_restored = world.entities.iter().map(|e| e.components.len() as u64).sum();
+        report.unrestored_metadata = self.entity_metadata.keys().copied().collect();
+        report.unrestored_metadata.sort_unstable();
+        report.unrestored_resources = self.resources.keys().cloned().collect();
+
+        (world, report)
+    }
+
+    pub fn apply_to_world<W>(&self, world: &mut W, registry: &ComponentRegistry) -> Result<RestoreReport>
+    where
+        W: WorldSnapshotTarget + ?Sized,
+    {
+        let id_map = allocate_ids(self, |old_id| world.allocate_entity(old_id))?;
+        let (snapshot, mut report) = if id_map.is_identity() {
+            self.to_world_snapshot_lossy(registry)
+        } else {
+            let mut remapped = self.clone();
+            remapped.remap_entities(&id_map, registry)?;
+            remapped.to_world_snapshot_lossy(registry)
+        };
+        world.restore_from_snapshot(&snapshot)?;
+        for (name, data) in &self.resources {
+            if world.restore_resource(name, data)? {
+                report.unrestored_resources.retain(|unrestored| unrestored != name);
+            }
+        }
+        report.id_map = id_map;
+        Ok(report)
+    }
+
+    fn convert_to_world<F>(&self, registry: &ComponentRegistry, mut skip: F) -> Result<WorldSnapshot>
+    where
+        F: FnMut(&ComponentArchetype, PackError) -> Result<()>,
+    {
+        let mut entities: BTreeMap<EntityId, Vec<SerializedComponent>> = BTreeMap::new();
+        for archetype in &self.archetypes {
+            let components = match registry.world_components(archetype) {
+                Ok(components) if components.len() == archetype.entity_ids.len() => components,
+                Ok(components) => {
+                    skip(archetype, PackError::InvalidFormat(format!(
+                        "{} has {} entities but {} components",
+                        archetype.component_id,
+                        archetype.entity_ids.len(),
+                        components.len()
+                    )))?;
+                    continue;
+                }
+                Err(e) => {
+                    skip(archetype, e)?;
+                    continue;
+                }
+            };
+            for (entity_id, component) in archetype.entity_ids.iter().zip(components) {
+                entities.entry(*entity_id).or_default().push(component);
+            }
+        }
+
+        Ok(WorldSnapshot {
+            entities: entities
+                .into_iter()
+                .map(|(id, components)| SerializedEntity { id, components })
+                .collect(),
+            timestamp: self.header.timestamp as f64,
+            version: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{ComponentData, FieldArray, FieldType, PackFormat};
+    use crate::packable::Packable;
+    use crate::storage::{SnapshotReader, SnapshotWriter};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Health {
+        hp: u32,
+        name: String,
+    }
+
+    impl Packable for Health {
+        const COMPONENT_ID: &'static str = "Health";
+
+        fn schema() -> Vec<(&'static str, FieldType)> {
+            vec![("hp", FieldType::U32), ("name", FieldType::String)]
+        }
+
+        fn pack_columns(rows: &[&Self]) -> Vec<FieldArray> {
+            vec![
+                FieldArray::U32(rows.iter().map(|r| r.hp).collect::<Vec<_>>().into()),
+                FieldArray::String(rows.iter().map(|r| r.name.clone()).collect::<Vec<_>>().into()),
+            ]
+        }
+
+        fn unpack_columns(columns: &[&FieldArray], len: usize) -> Option<Vec<Self>> {
+            match columns {
+                [FieldArray::U32(hp), FieldArray::String(name)] if hp.len() == len && name.len() == len => {
+                    Some(hp.iter().zip(name.iter()).map(|(&hp, name)| Health { hp, name: name.clone() }).collect())
+                }
+                _ => None,
+            }
+        }
+    }
+
+    fn component(id: &str, data: Vec<u8>) -> SerializedComponent {
+        SerializedComponent { id: id.to_string(), data }
+    }
+
+    fn world() -> WorldSnapshot {
+        let health = |hp: u32, name: &str| bincode::serialize(&Health { hp, name: name.to_string() }).unwrap();
+        WorldSnapshot {
+            entities: vec![
+                SerializedEntity {
+                    id: 7,
+                    components: vec![component("Health", health(70, "orc")), component("Tag", vec![1, 2])],
+                },
+                SerializedEntity {
+                    id: 3,
+                    components: vec![component("Health", health(30, "elf"))],
+                },
+                SerializedEntity {
+                    id: 5,
+                    components: vec![component("Tag", vec![])],
+                },
+            ],
+            timestamp: 1234.0,
+            version: 0,
+        }
+    }
+
+    fn summary(world: &WorldSnapshot) -> Vec<(EntityId, ComponentId, Vec<u8>)> {
+        let mut rows: Vec<_> = world
+            .entities
+            .iter()
+            .flat_map(|e| e.components.iter().map(|c| (e.id, c.id.clone(), c.data.clone())))
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn test_registered_components_are_packed_as_columns_and_restored() {
+        let registry = ComponentRegistry::new().with::<Health>();
+        let world = world();
+
+        let mut packed = PackedSnapshot::from_world_snapshot_with(&world, &registry).unwrap();
+        assert_eq!(packed.header.entity_count, 3);
+        let health = packed.archetypes.iter().find(|a| a.component_id == "Health").unwrap();
+        assert_eq!(health.entity_ids, vec![7, 3]);
+        assert_eq!(Health::unpack(health).unwrap()[1].1, Health { hp: 30, name: "elf".to_string() });
+        let tag = packed.archetypes.iter().find(|a| a.component_id == "Tag").unwrap();
+        assert!(matches!(tag.data, ComponentData::Blob(_)));
+
+        packed.header.format = PackFormat::Custom;
+        let bytes = SnapshotWriter::new().write_to_bytes(&packed).unwrap();
+        let restored = SnapshotReader::new().read_from_bytes(&bytes).unwrap().to_world_snapshot(&registry).unwrap();
+        assert_eq!(summary(&restored), summary(&world));
+        assert_eq!(restored.timestamp, world.timestamp);
+
+        let err = packed.to_world_snapshot(&ComponentRegistry::new()).unwrap_err();
+        assert!(matches!(err, PackError::InvalidFormat(_)));
+    }
+
+    struct Recorder(Option<WorldSnapshot>);
+
+    impl WorldSnapshotTarget for Recorder {
+        fn restore_from_snapshot(&mut self, snapshot: &WorldSnapshot) -> Result<()> {
+            self.0 = Some(snapshot.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_apply_to_world_reports_what_was_left_behind() {
+        let registry = ComponentRegistry::new().with::<Health>();
+        let mut packed = PackedSnapshot::from_world_snapshot_with(&world(), &registry).unwrap();
+        packed.entity_metadata.insert(5, Default::default());
+
+        let mut target = Recorder(None);
+        let report = packed.apply_to_world(&mut target, &registry).unwrap();
+        assert!(!report.is_complete());
+        assert_eq!((report.entities_restored, report.components_restored), (3, 4));
+        assert!(report.skipped_archetypes.is_empty());
+        assert_eq!(report.unrestored_metadata, vec![5]);
+        assert!(report.id_map.is_identity());
+        assert_eq!(summary(target.0.as_ref().unwrap()), summary(&world()));
+
+        // Without the schema the Health columns cannot become payloads again.
+        let report = packed.apply_to_world(&mut target, &ComponentRegistry::new()).unwrap();
+        assert_eq!(report.skipped_archetypes.len(), 1);
+        assert_eq!(report.skipped_archetypes[0].component_id, "Health");
+        assert_eq!(report.skipped_archetypes[0].entity_count, 2);
+        let restored = target.0.unwrap();
+        assert_eq!(restored.entities.iter().map(|e| e.id).collect::<Vec<_>>(), vec![5, 7]);
+    }
+
+    #[test]
+    fn test_undecodable_payloads_name_the_component() {
+        let registry = ComponentRegistry::new().with::<Health>();
+        let mut world = world();
+        world.entities[1].components[0].data = vec![0xff];
+
+        let err = PackedSnapshot::from_world_snapshot_with(&world, &registry).unwrap_err();
+        assert_eq!(err.context().and_then(|c| c.component_id.as_deref()), Some("Health"));
+    }
+}
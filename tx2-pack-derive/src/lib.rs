@@ -0,0 +1,91 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr, Member};
+
+#[proc_macro_derive(Packable, attributes(pack))]
+pub fn derive_packable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let component_id = pack_attr(&input.attrs, "id")?.unwrap_or_else(|| name.to_string());
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(name, "Packable can only be derived for structs")),
+    };
+
+    let mut members = Vec::new();
+    let mut labels = Vec::new();
+    let mut types = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let (member, default_label) = match &field.ident {
+            Some(ident) => (Member::Named(ident.clone()), ident.to_string().trim_start_matches("r#").to_string()),
+            None => (Member::Unnamed(index.into()), index.to_string()),
+        };
+        labels.push(pack_attr(&field.attrs, "rename")?.unwrap_or(default_label));
+        members.push(member);
+        types.push(&field.ty);
+    }
+
+    let vars: Vec<_> = (0..members.len()).map(|i| format_ident!("__column{}", i)).collect();
+    let construct = match fields {
+        Fields::Named(_) => quote! { Self { #(#members: #vars[_row].clone()),* } },
+        Fields::Unnamed(_) => quote! { Self(#(#vars[_row].clone()),*) },
+        Fields::Unit => quote! { Self },
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::tx2_pack::packable::Packable for #name #ty_generics #where_clause {
+            const COMPONENT_ID: &'static str = #component_id;
+
+            fn schema() -> ::std::vec::Vec<(&'static str, ::tx2_pack::format::FieldType)> {
+                ::std::vec![#((#labels, <#types as ::tx2_pack::packable::PackField>::FIELD_TYPE)),*]
+            }
+
+            fn pack_columns(rows: &[&Self]) -> ::std::vec::Vec<::tx2_pack::format::FieldArray> {
+                ::std::vec![#(
+                    <#types as ::tx2_pack::packable::PackField>::column(
+                        rows.iter().map(|row| row.#members.clone()).collect()
+                    )
+                ),*]
+            }
+
+            fn unpack_columns(
+                columns: &[&::tx2_pack::format::FieldArray],
+                len: usize,
+            ) -> ::std::option::Option<::std::vec::Vec<Self>> {
+                let [#(#vars),*] = columns else {
+                    return ::std::option::Option::None;
+                };
+                #(
+                    let #vars = <#types as ::tx2_pack::packable::PackField>::values(#vars)?;
+                    if #vars.len() != len {
+                        return ::std::option::Option::None;
+                    }
+                )*
+                ::std::option::Option::Some((0..len).map(|_row| #construct).collect())
+            }
+        }
+    })
+}
+
+fn pack_attr(attrs: &[syn::Attribute], key: &str) -> syn::Result<Option<String>> {
+    let mut value = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("pack")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) {
+                value = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported pack attribute"))
+            }
+        })?;
+    }
+    Ok(value)
+}
@@ -0,0 +1,56 @@
+use tx2_pack::format::{ComponentData, FieldType};
+use tx2_pack::Packable;
+
+#[derive(Packable, Debug, Clone, PartialEq)]
+struct Position {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+#[derive(Packable, Debug, Clone, PartialEq)]
+#[pack(id = "game::Label")]
+struct Label {
+    #[pack(rename = "text")]
+    value: String,
+    payload: Vec<u8>,
+}
+
+#[derive(Packable, Debug, Clone, PartialEq)]
+struct Health(u16);
+
+#[derive(Packable, Debug, Clone, PartialEq)]
+struct Frozen;
+
+#[test]
+fn test_derive_packable_roundtrip() {
+    let positions = vec![
+        (1, Position { x: 1.0, y: 2.0, z: 3.0 }),
+        (7, Position { x: -1.0, y: 0.5, z: 0.0 }),
+    ];
+
+    let archetype = Position::pack(positions.iter().map(|(id, p)| (*id, p)));
+    assert_eq!(archetype.component_id, "Position");
+    assert_eq!(archetype.entity_ids, vec![1, 7]);
+    match &archetype.data {
+        ComponentData::StructOfArrays(soa) => {
+            assert_eq!(soa.field_names, vec!["x", "y", "z"]);
+            assert_eq!(soa.field_types, vec![FieldType::F32; 3]);
+        }
+        ComponentData::Blob(_) => panic!("expected columnar data"),
+    }
+    assert_eq!(Position::unpack(&archetype).unwrap(), positions);
+
+    let label = Label { value: "boss".to_string(), payload: vec![1, 2] };
+    let archetype = Label::pack([(3, &label)]);
+    assert_eq!(archetype.component_id, "game::Label");
+    assert_eq!(Label::schema(), vec![("text", FieldType::String), ("payload", FieldType::Bytes)]);
+    assert_eq!(Label::unpack(&archetype).unwrap(), vec![(3, label)]);
+
+    let archetype = Health::pack([(2, &Health(90))]);
+    assert_eq!(Health::unpack(&archetype).unwrap(), vec![(2, Health(90))]);
+    assert!(Position::unpack(&archetype).is_err());
+
+    let archetype = Frozen::pack([(4, &Frozen), (5, &Frozen)]);
+    assert_eq!(Frozen::unpack(&archetype).unwrap().len(), 2);
+}
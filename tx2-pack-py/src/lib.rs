@@ -0,0 +1,242 @@
+use numpy::IntoPyArray;
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+use std::path::PathBuf;
+use tx2_pack::format::{ComponentArchetype, ComponentData, FieldArray, PackedSnapshot};
+use tx2_pack::{CompressionCodec, PackError, SnapshotMetadata, SnapshotReader, SnapshotStore, SnapshotWriter};
+
+fn to_py_err(error: PackError) -> PyErr {
+    match error {
+        PackError::Io(e) => e.into(),
+        PackError::SnapshotNotFound(id) => PyKeyError::new_err(id),
+        other => PyValueError::new_err(other.to_string()),
+    }
+}
+
+fn codec(name: &str) -> PyResult<CompressionCodec> {
+    match name {
+        "none" => Ok(CompressionCodec::none()),
+        "zstd" => Ok(CompressionCodec::zstd_default()),
+        "zstd-fast" => Ok(CompressionCodec::zstd_fast()),
+        "zstd-best" => Ok(CompressionCodec::zstd_best()),
+        "lz4" => Ok(CompressionCodec::lz4_default()),
+        other => Err(PyValueError::new_err(format!("Unknown compression '{}'", other))),
+    }
+}
+
+fn metadata_dict<'py>(py: Python<'py>, metadata: &SnapshotMetadata) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("id", &metadata.id)?;
+    dict.set_item("name", &metadata.name)?;
+    dict.set_item("description", &metadata.description)?;
+    dict.set_item("created_at", metadata.created_at)?;
+    dict.set_item("world_time", metadata.world_time)?;
+    dict.set_item("schema_version", metadata.schema_version)?;
+    dict.set_item("tags", &metadata.tags)?;
+    dict.set_item("labels", &metadata.labels)?;
+    dict.set_item("custom_fields", &metadata.custom_fields)?;
+    dict.set_item("parent_id", &metadata.provenance.parent_id)?;
+    Ok(dict)
+}
+
+#[pyclass(name = "Snapshot", module = "tx2_pack")]
+struct PySnapshot {
+    inner: PackedSnapshot,
+}
+
+impl PySnapshot {
+    fn archetype(&self, component: &str) -> PyResult<&ComponentArchetype> {
+        self.inner
+            .archetypes
+            .iter()
+            .find(|a| a.component_id == component)
+            .ok_or_else(|| PyKeyError::new_err(component.to_string()))
+    }
+}
+
+#[pymethods]
+impl PySnapshot {
+    #[staticmethod]
+    fn read(path: PathBuf) -> PyResult<Self> {
+        let inner = SnapshotReader::new().read_from_file(path).map_err(to_py_err)?;
+        Ok(Self { inner })
+    }
+
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let inner = SnapshotReader::new().read_from_bytes(data).map_err(to_py_err)?;
+        Ok(Self { inner })
+    }
+
+    #[pyo3(signature = (path, compression = "zstd"))]
+    fn write(&self, path: PathBuf, compression: &str) -> PyResult<()> {
+        SnapshotWriter::new()
+            .with_compression(codec(compression)?)
+            .write_to_file(&self.inner, path)
+            .map_err(to_py_err)
+    }
+
+    #[pyo3(signature = (compression = "zstd"))]
+    fn to_bytes<'py>(&self, py: Python<'py>, compression: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = SnapshotWriter::new()
+            .with_compression(codec(compression)?)
+            .write_to_bytes(&self.inner)
+            .map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    #[getter]
+    fn timestamp(&self) -> i64 {
+        self.inner.header.timestamp
+    }
+
+    #[getter]
+    fn entity_count(&self) -> u64 {
+        self.inner.header.entity_count
+    }
+
+    fn components(&self) -> Vec<String> {
+        self.inner.archetypes.iter().map(|a| a.component_id.clone()).collect()
+    }
+
+    fn fields(&self, component: &str) -> PyResult<Vec<(String, String)>> {
+        match &self.archetype(component)?.data {
+            ComponentData::StructOfArrays(soa) => Ok(soa
+                .field_names
+                .iter()
+                .zip(&soa.field_types)
+                .map(|(name, field_type)| (name.clone(), format!("{:?}", field_type).to_lowercase()))
+                .collect()),
+            ComponentData::Blob(_) => Ok(Vec::new()),
+        }
+    }
+
+    fn entity_ids<'py>(&self, py: Python<'py>, component: &str) -> PyResult<Bound<'py, PyAny>> {
+        let ids: Vec<u64> = self.archetype(component)?.entity_ids.iter().map(|id| u64::from(*id)).collect();
+        Ok(ids.into_pyarray(py).into_any())
+    }
+
+    fn column<'py>(&self, py: Python<'py>, component: &str, field: &str) -> PyResult<Bound<'py, PyAny>> {
+        let soa = match &self.archetype(component)?.data {
+            ComponentData::StructOfArrays(soa) => soa,
+            ComponentData::Blob(_) => {
+                return Err(PyValueError::new_err(format!("Component {} is stored as a blob", component)));
+            }
+        };
+
+        let index = soa
+            .field_names
+            .iter()
+            .position(|name| name == field)
+            .ok_or_else(|| PyKeyError::new_err(format!("{}.{}", component, field)))?;
+
+        Ok(match &soa.field_data[index] {
+            FieldArray::Bool(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::I8(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::I16(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::I32(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::I64(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::U8(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::U16(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::U32(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::U64(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::F32(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::F64(v) => v.to_vec().into_pyarray(py).into_any(),
+            FieldArray::String(v) => PyList::new(py, v)?.into_any(),
+            FieldArray::Bytes(v) => PyList::new(py, v.iter().map(|b| PyBytes::new(py, b)))?.into_any(),
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Snapshot(entities={}, components={})",
+            self.inner.header.entity_count,
+            self.inner.archetypes.len()
+        )
+    }
+}
+
+#[pyclass(name = "Store", module = "tx2_pack")]
+struct PyStore {
+    inner: SnapshotStore,
+}
+
+#[pymethods]
+impl PyStore {
+    #[new]
+    fn new(root: PathBuf) -> PyResult<Self> {
+        let inner = SnapshotStore::new(root).map_err(to_py_err)?;
+        Ok(Self { inner })
+    }
+
+    fn list(&self) -> PyResult<Vec<String>> {
+        self.inner.list().map_err(to_py_err)
+    }
+
+    fn load(&self, id: &str) -> PyResult<PySnapshot> {
+        let (inner, _) = self.inner.load(id, &SnapshotReader::new()).map_err(to_py_err)?;
+        Ok(PySnapshot { inner })
+    }
+
+    fn metadata<'py>(&self, py: Python<'py>, id: &str) -> PyResult<Bound<'py, PyDict>> {
+        metadata_dict(py, &self.inner.load_metadata(id).map_err(to_py_err)?)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (tag = None, name_contains = None, selector = None, created_after = None, created_before = None, limit = None))]
+    fn query<'py>(
+        &self,
+        py: Python<'py>,
+        tag: Option<&str>,
+        name_contains: Option<&str>,
+        selector: Option<&str>,
+        created_after: Option<i64>,
+        created_before: Option<i64>,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let mut query = self.inner.query();
+        if let Some(tag) = tag {
+            query = query.with_tag(tag);
+        }
+        if let Some(substring) = name_contains {
+            query = query.with_name_containing(substring);
+        }
+        if let Some(selector) = selector {
+            query = query.with_label_selector(selector).map_err(to_py_err)?;
+        }
+        if let Some(timestamp) = created_after {
+            query = query.created_after(timestamp);
+        }
+        if let Some(timestamp) = created_before {
+            query = query.created_before(timestamp);
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+
+        query
+            .execute()
+            .map_err(to_py_err)?
+            .iter()
+            .map(|metadata| metadata_dict(py, metadata))
+            .collect()
+    }
+
+    fn search<'py>(&self, py: Python<'py>, text: &str) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        self.inner
+            .search(text)
+            .map_err(to_py_err)?
+            .iter()
+            .map(|metadata| metadata_dict(py, metadata))
+            .collect()
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "tx2_pack")]
+fn tx2_pack_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySnapshot>()?;
+    m.add_class::<PyStore>()?;
+    Ok(())
+}